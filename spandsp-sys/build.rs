@@ -13,11 +13,19 @@ fn main() {
     let v32bis = env::var("CARGO_FEATURE_V32BIS").is_ok();
     let v34 = env::var("CARGO_FEATURE_V34").is_ok();
     let ssl_fax = env::var("CARGO_FEATURE_SSL_FAX").is_ok();
+    let conformance = env::var("CARGO_FEATURE_CONFORMANCE").is_ok();
+    let version = vendored_version(&vendor_dir);
 
     // Phase A: Generate headers
-    generate_config_h(&out_dir, fax, v32bis, v34);
+    generate_config_h(&out_dir, fax, v32bis, v34, &version);
     generate_spandsp_h(&out_dir, &vendor_src, fax, v32bis, v34);
-    generate_version_h(&out_dir);
+    let built = generate_version_h(&out_dir);
+
+    // Exposed to the crate as `SPANDSP_VERSION`/`SPANDSP_BUILD_DATETIME`,
+    // and to dependents' build scripts as `DEP_SPANDSP_VERSION`.
+    println!("cargo:rustc-env=SPANDSP_VERSION={version}");
+    println!("cargo:rustc-env=SPANDSP_BUILD_DATETIME={built}");
+    println!("cargo:version={version}");
 
     // Create spandsp subdirectory in OUT_DIR for version.h
     let spandsp_dir = out_dir.join("spandsp");
@@ -30,15 +38,36 @@ fn main() {
 
     // Phase C: Compile C sources
     compile_c_sources(&out_dir, &vendor_src, fax, v32bis, v34, ssl_fax);
+    if conformance {
+        compile_conformance_shim(&out_dir, &vendor_src, &manifest_dir);
+    }
 
     // Phase D: Link system libraries
     link_system_libraries(fax, ssl_fax);
 
     // Phase E: Run bindgen
-    run_bindgen(&out_dir, &vendor_src, &manifest_dir, fax);
+    run_bindgen(&out_dir, &vendor_src, &manifest_dir, fax, conformance);
 }
 
-fn generate_config_h(out_dir: &Path, fax: bool, v32bis: bool, v34: bool) {
+/// Read the package version from `AC_INIT` in the vendored `configure.ac`.
+fn vendored_version(vendor_dir: &Path) -> String {
+    let configure_ac = vendor_dir.join("configure.ac");
+    let text = fs::read_to_string(&configure_ac)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", configure_ac.display()));
+    // AC_INIT([spandsp],[3.0.0])
+    let args = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("AC_INIT("))
+        .unwrap_or_else(|| panic!("No AC_INIT in {}", configure_ac.display()));
+    args.split(',')
+        .nth(1)
+        .map(|v| v.trim_matches(|c: char| c.is_whitespace() || "[])".contains(c)))
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| panic!("No version in AC_INIT in {}", configure_ac.display()))
+        .to_owned()
+}
+
+fn generate_config_h(out_dir: &Path, fax: bool, v32bis: bool, v34: bool, version: &str) {
     let mut config = String::new();
 
     config.push_str("/* Generated by build.rs */\n");
@@ -99,7 +128,7 @@ fn generate_config_h(out_dir: &Path, fax: bool, v32bis: bool, v34: bool) {
 
     // Package info
     config.push_str("#define PACKAGE \"spandsp\"\n");
-    config.push_str("#define VERSION \"3.0.0\"\n");
+    config.push_str(&format!("#define VERSION \"{version}\"\n"));
     config.push('\n');
 
     // Feature flags
@@ -184,7 +213,8 @@ fn generate_spandsp_h(out_dir: &Path, vendor_src: &Path, fax: bool, v32bis: bool
     fs::write(out_dir.join("spandsp.h"), output).unwrap();
 }
 
-fn generate_version_h(out_dir: &Path) {
+/// Write `version.h`, stamped with the build time, and return that time.
+fn generate_version_h(out_dir: &Path) -> String {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap();
@@ -219,6 +249,7 @@ fn generate_version_h(out_dir: &Path) {
     );
 
     fs::write(out_dir.join("version.h"), version_h).unwrap();
+    format!("{date_str} {time_str}")
 }
 
 fn days_to_ymd(days_since_epoch: u64) -> (u64, u64, u64) {
@@ -712,6 +743,21 @@ fn compile_c_sources(
     build.compile("spandsp");
 }
 
+/// Build the hooks into spandsp internals used by the ITU conformance tests.
+fn compile_conformance_shim(out_dir: &Path, vendor_src: &Path, manifest_dir: &Path) {
+    let shim_dir = manifest_dir.join("shim");
+    cc::Build::new()
+        .warnings(false)
+        .std("c99")
+        .define("HAVE_CONFIG_H", None)
+        .define("_GNU_SOURCE", None)
+        .include(out_dir)
+        .include(vendor_src)
+        .include(&shim_dir)
+        .file(shim_dir.join("conformance.c"))
+        .compile("spandsp_conformance");
+}
+
 fn link_system_libraries(fax: bool, ssl_fax: bool) {
     if cfg!(unix) {
         println!("cargo:rustc-link-lib=m");
@@ -736,7 +782,13 @@ fn link_system_libraries(fax: bool, ssl_fax: bool) {
     }
 }
 
-fn run_bindgen(out_dir: &Path, vendor_src: &Path, manifest_dir: &Path, fax: bool) {
+fn run_bindgen(
+    out_dir: &Path,
+    vendor_src: &Path,
+    manifest_dir: &Path,
+    fax: bool,
+    conformance: bool,
+) {
     let wrapper_h = manifest_dir.join("wrapper.h");
 
     let mut builder = bindgen::Builder::default()
@@ -748,6 +800,12 @@ fn run_bindgen(out_dir: &Path, vendor_src: &Path, manifest_dir: &Path, fax: bool
         .clang_arg("-DSPAN_DECLARE(type)=type")
         .clang_arg("-DSPAN_DECLARE_DATA=");
 
+    if conformance {
+        builder = builder
+            .header(manifest_dir.join("shim/conformance.h").to_str().unwrap())
+            .allowlist_function("spandsp_rs_.*");
+    }
+
     // Add include paths for libtiff/libjpeg when fax is enabled
     if fax {
         if let Ok(lib) = pkg_config::probe_library("libtiff-4") {
@@ -771,11 +829,11 @@ fn run_bindgen(out_dir: &Path, vendor_src: &Path, manifest_dir: &Path, fax: bool
         .generate_comments(true)
         .derive_default(true)
         // Allowlist spandsp public API — functions
-        .allowlist_function("(ademco_contactid|adsi|agc_float|alloc|async_|at_interpreter|awgn|bell_r2_mf|bert|bit_operations|bitstream|complex_filters|complex_vector|crc|dds|dtmf|echo_can|fsk|g711|g722|g726|godard|goertzel|gsm0610|hdlc|ima_adpcm|image_translate|logging|span_log|lpc10|math_fixed|modem_echo|modem_connect|noise|oki_adpcm|playout|plc|power_meter|power_surge|queue|schedule|sig_tone|silence_gen|sprt|super_tone|swept_tone|testcpuid|time_scale|timezone|tone_detect|tone_gen|v150_1|v17_|v18_|v22bis|v27ter|v29_|v32bis|v34_|v42_|v42bis|v8_|v80_|fax_|fax_modems|t30_|t31_|t35_|t38_|t4_|t42_|t43_|t81_|t85_|ssl_fax|data_modems|span_set_message_handler|linear_to_ulaw|ulaw_to_linear|linear_to_alaw|alaw_to_linear|alaw_to_ulaw|ulaw_to_alaw|periodogram|make_goertzel_descriptor).*")
+        .allowlist_function("(ademco_contactid|adsi|agc_float|alloc|async_|at_|awgn|bell_mf_|bell_r2_mf|r2_mf_|bert|bit_operations|bitstream|complex_filters|complex_vector|crc|dds|dtmf|echo_can|fsk|g711|g722|g726|godard|goertzel|gsm0610|hdlc|ima_adpcm|image_translate|logging|span_log|lpc10|math_fixed|modem_echo|modem_connect|noise|oki_adpcm|playout|plc|power_meter|power_surge|queue|schedule|sig_tone|silence_gen|sprt|super_tone|swept_tone|testcpuid|time_scale|timezone|tz_|tone_detect|tone_gen|v150_1|v17_|v18_|v22bis|v27ter|v29_|v32bis|v34_|v42_|v42bis|v8_|v80_|fax_|fax_modems|t30_|t31_|t35_|t38_|t4_|t42_|t43_|t81_|t85_|ssl_fax|data_modems|span_set_message_handler|linear_to_ulaw|ulaw_to_linear|linear_to_alaw|alaw_to_linear|alaw_to_ulaw|ulaw_to_alaw|periodogram|make_goertzel_descriptor).*")
         // Allowlist spandsp public API — types
-        .allowlist_type("(ademco_contactid|adsi|agc_float|async_|at_interpreter|awgn|bell_r2_mf|bert|bitstream|complex_filters|complexf_t|crc|dds|dtmf|digits_|echo_can|fsk|g711|g722|g726|godard|goertzel|gsm0610|hdlc|ima_adpcm|image_translate|logging|message_handler|span_|lpc10|math_fixed|modem_echo|modem_connect|noise|oki_adpcm|playout|plc|power_meter|power_surge|queue|schedule|sig_tone|silence_gen|sprt|super_tone|swept_tone|time_scale|timezone|tone_|v150_1|v17_|v18_|v22bis|v27ter|v29_|v32bis|v34_|v42_|v42bis|v8_|v80_|fax_|fax_modems|t30_|t31_|t35_|t38_|t4_|t42_|t43_|t81_|t85_|ssl_fax|data_modems|SAMPLE_RATE).*")
+        .allowlist_type("(ademco_contactid|adsi|agc_float|async_|at_|awgn|bell_mf_|bell_r2_mf|r2_mf_|bert|bitstream|complex_filters|complexf_t|crc|dds|dtmf|digits_|echo_can|fsk|g711|g722|g726|godard|goertzel|gsm0610|hdlc|ima_adpcm|image_translate|logging|message_handler|span_|lpc10|math_fixed|modem_echo|modem_connect|noise|oki_adpcm|playout|plc|power_meter|power_surge|queue|schedule|sig_tone|silence_gen|sprt|super_tone|swept_tone|time_scale|timezone|tone_|tz_|v150_1|v17_|v18_|v22bis|v27ter|v29_|v32bis|v34_|v42_|v42bis|v8_|v80_|fax_|fax_modems|t30_|t31_|t35_|t38_|t4_|t42_|t43_|t81_|t85_|ssl_fax|data_modems|SAMPLE_RATE).*")
         // Allowlist constants from anonymous enums and #defines
-        .allowlist_var("(G711_|G722_|G726_|SPAN_LOG_|ECHO_CAN_|T30_|T38_|HDLC_|MAX_DTMF|SAMPLE_RATE).*")
+        .allowlist_var("(ASYNC_|FSK_|G711_|G722_|G726_|GSM0610_|IMA_ADPCM_|MODEM_CONNECT_TONES_|NOISE_|SIG_STATUS_|SPAN_LOG_|ECHO_CAN_|T30_|T38_|HDLC_|V18_|MAX_DTMF|SAMPLE_RATE|preset_fsk_specs).*")
         // Turn named C enums into proper Rust enums
         .rustified_enum("t30_err_e")
         .rustified_enum("t30_indicator_types_e")
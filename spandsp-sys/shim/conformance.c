@@ -0,0 +1,22 @@
+/* Hooks into spandsp internals needed to run the ITU test sequences. */
+#if defined(HAVE_CONFIG_H)
+#include "config.h"
+#endif
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "spandsp.h"
+#include "spandsp/private/g722.h"
+
+#include "conformance.h"
+
+void spandsp_rs_g722_encode_set_itu_test_mode(g722_encode_state_t *s, int enabled)
+{
+    s->itu_test_mode = (enabled != 0);
+}
+
+void spandsp_rs_g722_decode_set_itu_test_mode(g722_decode_state_t *s, int enabled)
+{
+    s->itu_test_mode = (enabled != 0);
+}
@@ -0,0 +1,13 @@
+/* Hooks into spandsp internals needed to run the ITU test sequences. These
+   are only built with the `conformance` feature. */
+#if !defined(_SPANDSP_RS_CONFORMANCE_H_)
+#define _SPANDSP_RS_CONFORMANCE_H_
+
+#include "spandsp.h"
+
+/* Enable or disable the G.722 ITU test mode, which bypasses the QMF band
+   split so each codeword maps to one low band and one high band sample. */
+void spandsp_rs_g722_encode_set_itu_test_mode(g722_encode_state_t *s, int enabled);
+void spandsp_rs_g722_decode_set_itu_test_mode(g722_decode_state_t *s, int enabled);
+
+#endif
@@ -1,3 +1,26 @@
 #![allow(non_upper_case_globals, non_camel_case_types, non_snake_case)]
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+/// Version of the spandsp library these bindings were built against.
+pub const SPANDSP_VERSION: &str = env!("SPANDSP_VERSION");
+
+/// When the library was built, as `YYYYMMDD HHMMSS` in UTC. The generated
+/// `version.h` stamps the same time as `SPANDSP_RELEASE_DATETIME_STRING`,
+/// since the vendored source carries no release date of its own.
+pub const SPANDSP_BUILD_DATETIME: &str = env!("SPANDSP_BUILD_DATETIME");
+
+/// Whether the T.30/T.38/T.4 fax modules were compiled in.
+pub const HAVE_FAX: bool = cfg!(feature = "fax");
+
+/// Whether the V.32bis modem was compiled in.
+pub const HAVE_V32BIS: bool = cfg!(feature = "v32bis");
+
+/// Whether the V.34 modem was compiled in.
+pub const HAVE_V34: bool = cfg!(feature = "v34");
+
+/// Whether SSL fax support was compiled in.
+pub const HAVE_SSL_FAX: bool = cfg!(feature = "ssl-fax");
+
+/// Whether the ITU test-sequence hooks were compiled in.
+pub const HAVE_CONFORMANCE: bool = cfg!(feature = "conformance");
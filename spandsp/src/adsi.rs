@@ -0,0 +1,1164 @@
+//! ADSI and Type I caller ID.
+//!
+//! [`AdsiTx`] wraps spandsp's ADSI transmitter, which sends caller ID and
+//! other on-hook data messages as FSK (or DTMF) audio. [`CallerIdBuilder`]
+//! puts together the Bellcore (GR-30) messages it sends for Type I caller
+//! ID: the single data message format (SDMF) and the multiple data message
+//! format (MDMF), with the date and time, the number and name or the reason
+//! they are missing, and message waiting indications.
+//!
+//! On the receive side, [`AdsiRx`] demodulates messages and
+//! [`CallerId::parse`] turns them into the caller's details, smoothing over
+//! the differences between the CLASS, ETSI CLIP, JCLIP and DTMF variants.
+//! [`CallerIdDetector`] does both, taking audio and giving back a
+//! [`CallerId`].
+//! [`DtmfCliDecoder`] reads DTMF caller ID from the digits of an existing
+//! DTMF detector instead.
+//!
+//! ```no_run
+//! use spandsp::adsi::{AdsiStandard, AdsiTx, CallerIdBuilder};
+//!
+//! let msg = CallerIdBuilder::new()
+//!     .datetime(3, 14, 15, 9)
+//!     .number("5551234567")
+//!     .name("DOE JOHN")
+//!     .build_mdmf()
+//!     .unwrap();
+//! let mut tx = AdsiTx::new(AdsiStandard::Class).unwrap();
+//! tx.put_message(&msg).unwrap();
+//! let mut amp = [0i16; 160];
+//! while tx.tx(&mut amp) > 0 {
+//!     // play `amp` between the first and second rings
+//! }
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// Message types from GR-30.
+const MSG_SDMF_CALLER_ID: u8 = 0x04;
+const MSG_SDMF_MESSAGE_WAITING: u8 = 0x06;
+const MSG_MDMF_CALLER_ID: u8 = 0x80;
+const MSG_MDMF_MESSAGE_WAITING: u8 = 0x82;
+
+/// MDMF parameter types from GR-30.
+const PARAM_DATETIME: u8 = 0x01;
+const PARAM_NUMBER: u8 = 0x02;
+const PARAM_NUMBER_ABSENT: u8 = 0x04;
+const PARAM_NAME: u8 = 0x07;
+const PARAM_NAME_ABSENT: u8 = 0x08;
+const PARAM_MESSAGE_WAITING: u8 = 0x0B;
+
+/// Longest number and name GR-30 allows.
+const MAX_NUMBER_LEN: usize = 10;
+const MAX_NAME_LEN: usize = 15;
+
+/// The ADSI variants spandsp implements, from its `adsi.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AdsiStandard {
+    /// Bellcore CLASS: Bell 202 FSK, as in North America.
+    Class = 1,
+    /// ETSI/BT caller line identity presentation, V.23 FSK.
+    Clip = 2,
+    /// Analog CLIP as used in some Asian countries.
+    Aclip = 3,
+    /// Japanese CLIP, V.23 FSK with its own framing.
+    Jclip = 4,
+    /// CLIP sent as DTMF digits.
+    ClipDtmf = 5,
+    /// TDD (Baudot) text telephone.
+    Tdd = 6,
+}
+
+// ---------------------------------------------------------------------------
+// Type I caller ID messages
+// ---------------------------------------------------------------------------
+
+/// Why a number or name is not given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Absence {
+    /// The caller withheld it ("P").
+    Private,
+    /// The network does not have it, e.g. the call is from out of area
+    /// ("O").
+    Unavailable,
+}
+
+impl Absence {
+    /// The GR-30 code.
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Private => b'P',
+            Self::Unavailable => b'O',
+        }
+    }
+
+    /// The absence a GR-30 code stands for.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            b'P' => Some(Self::Private),
+            b'O' => Some(Self::Unavailable),
+            _ => None,
+        }
+    }
+}
+
+/// The date and time of a call, as carried by caller ID: no year, no
+/// seconds, local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallDateTime {
+    /// Month, 1-12.
+    pub month: u8,
+    /// Day of the month, 1-31.
+    pub day: u8,
+    /// Hour, 0-23.
+    pub hour: u8,
+    /// Minute, 0-59.
+    pub minute: u8,
+}
+
+impl CallDateTime {
+    /// The eight ASCII digits `MMDDHHMM`, if every field is in range.
+    pub fn to_digits(self) -> Option<[u8; 8]> {
+        let in_range = (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60;
+        if !in_range {
+            return None;
+        }
+        let mut digits = [0u8; 8];
+        for (pair, value) in
+            digits
+                .chunks_exact_mut(2)
+                .zip([self.month, self.day, self.hour, self.minute])
+        {
+            pair[0] = b'0' + value / 10;
+            pair[1] = b'0' + value % 10;
+        }
+        Some(digits)
+    }
+
+    /// Parse the eight ASCII digits `MMDDHHMM`.
+    pub fn from_digits(digits: &[u8]) -> Option<Self> {
+        let &[m1, m2, d1, d2, h1, h2, n1, n2] = digits else {
+            return None;
+        };
+        let pair = |hi: u8, lo: u8| {
+            (hi.is_ascii_digit() && lo.is_ascii_digit()).then(|| (hi - b'0') * 10 + (lo - b'0'))
+        };
+        let datetime = Self {
+            month: pair(m1, m2)?,
+            day: pair(d1, d2)?,
+            hour: pair(h1, h2)?,
+            minute: pair(n1, n2)?,
+        };
+        datetime.to_digits().map(|_| datetime)
+    }
+}
+
+/// A complete caller ID or message waiting message: type, length and body.
+///
+/// The checksum is not part of the message; [`to_bytes`](Self::to_bytes)
+/// appends it for sending over another FSK modem, while [`AdsiTx`] adds its
+/// own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallerIdMessage {
+    bytes: Vec<u8>,
+}
+
+impl CallerIdMessage {
+    fn new(message_type: u8, body: &[u8]) -> Result<Self> {
+        let len = u8::try_from(body.len()).map_err(|_| {
+            SpanDspError::InvalidInput(format!("message body too long: {} bytes", body.len()))
+        })?;
+        let mut bytes = Vec::with_capacity(body.len() + 2);
+        bytes.extend_from_slice(&[message_type, len]);
+        bytes.extend_from_slice(body);
+        Ok(Self { bytes })
+    }
+
+    /// A visual message waiting indication, turning the lamp on or off.
+    pub fn message_waiting(active: bool, mdmf: bool) -> Self {
+        let message = if mdmf {
+            Self::new(
+                MSG_MDMF_MESSAGE_WAITING,
+                &[PARAM_MESSAGE_WAITING, 1, if active { 0xFF } else { 0x00 }],
+            )
+        } else {
+            let code = if active { 0x42 } else { 0x6F };
+            Self::new(MSG_SDMF_MESSAGE_WAITING, &[code; 3])
+        };
+        message.expect("message waiting bodies are short")
+    }
+
+    /// The message type octet.
+    pub fn message_type(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Type, length and body, without the checksum.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The checksum: the two's complement of the sum of every other octet.
+    pub fn checksum(&self) -> u8 {
+        self.bytes
+            .iter()
+            .fold(0u8, |sum, &b| sum.wrapping_add(b))
+            .wrapping_neg()
+    }
+
+    /// The message with its checksum, as it goes on the line.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.bytes.clone();
+        bytes.push(self.checksum());
+        bytes
+    }
+}
+
+/// Puts together a Type I caller ID message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallerIdBuilder {
+    datetime: Option<CallDateTime>,
+    number: Option<std::result::Result<String, Absence>>,
+    name: Option<std::result::Result<String, Absence>>,
+}
+
+impl CallerIdBuilder {
+    /// An empty message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The date and time of the call.
+    pub fn datetime(mut self, month: u8, day: u8, hour: u8, minute: u8) -> Self {
+        self.datetime = Some(CallDateTime {
+            month,
+            day,
+            hour,
+            minute,
+        });
+        self
+    }
+
+    /// The calling number, up to ten digits.
+    pub fn number(mut self, number: &str) -> Self {
+        self.number = Some(Ok(number.to_owned()));
+        self
+    }
+
+    /// Why the calling number is not given.
+    pub fn number_absent(mut self, reason: Absence) -> Self {
+        self.number = Some(Err(reason));
+        self
+    }
+
+    /// The calling name, up to 15 printable ASCII characters. MDMF only.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(Ok(name.to_owned()));
+        self
+    }
+
+    /// Why the calling name is not given. MDMF only.
+    pub fn name_absent(mut self, reason: Absence) -> Self {
+        self.name = Some(Err(reason));
+        self
+    }
+
+    /// Build an MDMF message with whichever parameters were given, in
+    /// GR-30 order.
+    pub fn build_mdmf(&self) -> Result<CallerIdMessage> {
+        let mut body = Vec::new();
+        let mut param = |kind: u8, value: &[u8]| {
+            body.extend_from_slice(&[kind, value.len() as u8]);
+            body.extend_from_slice(value);
+        };
+        if let Some(datetime) = self.datetime {
+            param(PARAM_DATETIME, &datetime_digits(datetime)?);
+        }
+        match &self.number {
+            Some(Ok(number)) => param(PARAM_NUMBER, checked_number(number)?),
+            Some(Err(reason)) => param(PARAM_NUMBER_ABSENT, &[reason.code()]),
+            None => {}
+        }
+        match &self.name {
+            Some(Ok(name)) => param(PARAM_NAME, checked_name(name)?),
+            Some(Err(reason)) => param(PARAM_NAME_ABSENT, &[reason.code()]),
+            None => {}
+        }
+        if body.is_empty() {
+            return Err(SpanDspError::InvalidInput(
+                "caller ID message has no parameters".into(),
+            ));
+        }
+        CallerIdMessage::new(MSG_MDMF_CALLER_ID, &body)
+    }
+
+    /// Build an SDMF message: the date and time, then the number or the
+    /// reason it is missing. SDMF has no room for a name.
+    pub fn build_sdmf(&self) -> Result<CallerIdMessage> {
+        if self.name.is_some() {
+            return Err(SpanDspError::InvalidInput(
+                "SDMF cannot carry a name; use MDMF".into(),
+            ));
+        }
+        let datetime = self
+            .datetime
+            .ok_or_else(|| SpanDspError::InvalidInput("SDMF needs the date and time".into()))?;
+        let mut body = datetime_digits(datetime)?.to_vec();
+        match &self.number {
+            Some(Ok(number)) => body.extend_from_slice(checked_number(number)?),
+            Some(Err(reason)) => body.push(reason.code()),
+            None => {
+                return Err(SpanDspError::InvalidInput(
+                    "SDMF needs a number or a reason for its absence".into(),
+                ));
+            }
+        }
+        CallerIdMessage::new(MSG_SDMF_CALLER_ID, &body)
+    }
+
+    /// Build the message `standard` sends, laid out for
+    /// [`AdsiTx::put_raw`] and as [`CallerId::parse`] reads it back.
+    ///
+    /// CLASS, CLIP and ACLIP get MDMF. JCLIP gets its DLE-framed message,
+    /// less the DLE ETX trailer, parity and CRC that spandsp adds. CLIP DTMF
+    /// gets `A` and the number, or `B00`/`B10`, then `C`. Fields a standard
+    /// has no room for, such as the name in JCLIP and CLIP DTMF, are left
+    /// out.
+    pub fn build(&self, standard: AdsiStandard) -> Result<Vec<u8>> {
+        match standard {
+            AdsiStandard::Class | AdsiStandard::Clip | AdsiStandard::Aclip => {
+                Ok(self.build_mdmf()?.as_bytes().to_vec())
+            }
+            AdsiStandard::Jclip => self.build_jclip(),
+            AdsiStandard::ClipDtmf => self.build_dtmf(),
+            AdsiStandard::Tdd => Err(SpanDspError::InvalidInput(
+                "TDD does not carry caller ID".into(),
+            )),
+        }
+    }
+
+    fn build_jclip(&self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        match &self.number {
+            Some(Ok(number)) => {
+                let number = checked_number(number)?;
+                body.extend_from_slice(&[JCLIP_PARAM_NUMBER, number.len() as u8]);
+                body.extend_from_slice(number);
+            }
+            Some(Err(reason)) => {
+                body.extend_from_slice(&[JCLIP_PARAM_NUMBER_ABSENT, 1, reason.code()])
+            }
+            None => {
+                return Err(SpanDspError::InvalidInput(
+                    "JCLIP needs a number or a reason for its absence".into(),
+                ));
+            }
+        }
+        let mut msg = vec![DLE, 0x01, 0x07, DLE, 0x02];
+        for b in [JCLIP_MSG_CALLER_ID, body.len() as u8]
+            .into_iter()
+            .chain(body)
+        {
+            // DLE is doubled wherever it turns up in the data.
+            if b == DLE {
+                msg.push(DLE);
+            }
+            msg.push(b);
+        }
+        Ok(msg)
+    }
+
+    fn build_dtmf(&self) -> Result<Vec<u8>> {
+        let mut msg = Vec::new();
+        match &self.number {
+            Some(Ok(number)) => {
+                msg.push(b'A');
+                msg.extend_from_slice(checked_number(number)?);
+            }
+            Some(Err(Absence::Private)) => msg.extend_from_slice(b"B10"),
+            Some(Err(Absence::Unavailable)) => msg.extend_from_slice(b"B00"),
+            None => {
+                return Err(SpanDspError::InvalidInput(
+                    "CLIP DTMF needs a number or a reason for its absence".into(),
+                ));
+            }
+        }
+        msg.push(b'C');
+        Ok(msg)
+    }
+}
+
+fn datetime_digits(datetime: CallDateTime) -> Result<[u8; 8]> {
+    datetime
+        .to_digits()
+        .ok_or_else(|| SpanDspError::InvalidInput(format!("date/time out of range: {datetime:?}")))
+}
+
+fn checked_number(number: &str) -> Result<&[u8]> {
+    if number.is_empty()
+        || number.len() > MAX_NUMBER_LEN
+        || !number.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(SpanDspError::InvalidInput(format!(
+            "caller ID number must be 1-{MAX_NUMBER_LEN} digits, got {number:?}"
+        )));
+    }
+    Ok(number.as_bytes())
+}
+
+fn checked_name(name: &str) -> Result<&[u8]> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || !name.bytes().all(|b| b.is_ascii_graphic() || b == b' ')
+    {
+        return Err(SpanDspError::InvalidInput(format!(
+            "caller ID name must be 1-{MAX_NAME_LEN} printable ASCII characters, got {name:?}"
+        )));
+    }
+    Ok(name.as_bytes())
+}
+
+// ---------------------------------------------------------------------------
+// Caller ID parsing
+// ---------------------------------------------------------------------------
+
+/// JCLIP message type and parameters, from spandsp's `adsi.h`.
+const JCLIP_MSG_CALLER_ID: u8 = 0x40;
+const JCLIP_PARAM_NUMBER: u8 = 0x02;
+const JCLIP_PARAM_NUMBER_ABSENT: u8 = 0x04;
+
+/// GR-30's dialable directory number, which CLASS equipment sends in place
+/// of (or besides) the calling number. ETSI uses the code for the called
+/// number instead.
+const PARAM_DIALABLE_NUMBER: u8 = 0x03;
+
+const DLE: u8 = 0x10;
+
+/// Whether the caller's identity may be shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Presentation {
+    /// The number (or at least the name) was sent.
+    Allowed,
+    /// The caller withheld it.
+    Private,
+    /// The network does not have it.
+    Unavailable,
+}
+
+impl From<Absence> for Presentation {
+    fn from(absence: Absence) -> Self {
+        match absence {
+            Absence::Private => Self::Private,
+            Absence::Unavailable => Self::Unavailable,
+        }
+    }
+}
+
+/// The caller details from a received caller ID message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallerId {
+    /// The calling number.
+    pub number: Option<String>,
+    /// The calling name.
+    pub name: Option<String>,
+    /// When the call was made, by the network's clock.
+    pub datetime: Option<CallDateTime>,
+    /// Whether the caller was identified, and if not, why. A message with
+    /// neither a number nor a reason counts as unavailable.
+    pub presentation: Presentation,
+}
+
+impl CallerId {
+    /// Parse a message as delivered by [`AdsiRx`] for `standard`.
+    ///
+    /// FSK messages may carry their checksum or not; one that does must
+    /// match. JCLIP messages have their DLE framing and parity removed, but
+    /// their CRC is left to spandsp. CLIP DTMF messages are the digit string,
+    /// e.g. `A5551234C`, with `B00`/`B10` for an unavailable or withheld
+    /// number.
+    pub fn parse(standard: AdsiStandard, msg: &[u8]) -> Result<Self> {
+        let mut id = CallerIdFields::default();
+        match standard {
+            AdsiStandard::Class | AdsiStandard::Clip | AdsiStandard::Aclip => {
+                id.parse_fsk(standard, msg)?
+            }
+            AdsiStandard::Jclip => id.parse_jclip(msg)?,
+            AdsiStandard::ClipDtmf => id.parse_dtmf(msg)?,
+            AdsiStandard::Tdd => {
+                return Err(SpanDspError::InvalidInput(
+                    "TDD does not carry caller ID".into(),
+                ));
+            }
+        }
+        Ok(id.finish())
+    }
+}
+
+/// A caller ID as the fields turn up.
+#[derive(Default)]
+struct CallerIdFields {
+    number: Option<String>,
+    dialable: Option<String>,
+    name: Option<String>,
+    datetime: Option<CallDateTime>,
+    number_absent: Option<Absence>,
+    name_absent: Option<Absence>,
+}
+
+impl CallerIdFields {
+    fn parse_fsk(&mut self, standard: AdsiStandard, msg: &[u8]) -> Result<()> {
+        let &[message_type, len, ..] = msg else {
+            return Err(truncated(msg));
+        };
+        let end = usize::from(len) + 2;
+        match msg.len().checked_sub(end) {
+            Some(0) => {}
+            Some(1) => {
+                let sum = msg.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+                if sum != 0 {
+                    return Err(SpanDspError::InvalidInput(
+                        "caller ID checksum mismatch".into(),
+                    ));
+                }
+            }
+            _ => return Err(truncated(msg)),
+        }
+        let body = &msg[2..end];
+        match message_type {
+            MSG_SDMF_CALLER_ID => {
+                let (datetime, number) = body.split_at_checked(8).ok_or_else(|| truncated(msg))?;
+                self.datetime = CallDateTime::from_digits(datetime);
+                match number {
+                    &[code] if let Some(absence) = Absence::from_code(code) => {
+                        self.number_absent = Some(absence)
+                    }
+                    _ => self.number = text(number),
+                }
+            }
+            MSG_MDMF_CALLER_ID => {
+                for (kind, value) in params(body)? {
+                    match kind {
+                        PARAM_DATETIME => self.datetime = CallDateTime::from_digits(value),
+                        PARAM_NUMBER => self.number = text(value),
+                        PARAM_DIALABLE_NUMBER if standard == AdsiStandard::Class => {
+                            self.dialable = text(value)
+                        }
+                        PARAM_NUMBER_ABSENT => self.number_absent = absence(value),
+                        PARAM_NAME => self.name = text(value),
+                        PARAM_NAME_ABSENT => self.name_absent = absence(value),
+                        _ => {}
+                    }
+                }
+            }
+            _ => return Err(not_caller_id(message_type)),
+        }
+        Ok(())
+    }
+
+    fn parse_jclip(&mut self, msg: &[u8]) -> Result<()> {
+        // Seven data bits with even parity; drop the parity bit.
+        let msg: Vec<u8> = msg.iter().map(|b| b & 0x7F).collect();
+        let rest = msg
+            .strip_prefix(&[DLE, 0x01, 0x07, DLE, 0x02])
+            .ok_or_else(|| SpanDspError::InvalidInput("JCLIP message lacks its header".into()))?;
+        // Undo DLE doubling up to the DLE ETX trailer.
+        let mut data = Vec::with_capacity(rest.len());
+        let mut bytes = rest.iter();
+        while let Some(&b) = bytes.next() {
+            if b == DLE {
+                match bytes.next() {
+                    Some(&DLE) => data.push(DLE),
+                    Some(0x03) => break,
+                    _ => return Err(truncated(&msg)),
+                }
+            } else {
+                data.push(b);
+            }
+        }
+        let [message_type, len, body @ ..] = data.as_slice() else {
+            return Err(truncated(&msg));
+        };
+        if *message_type != JCLIP_MSG_CALLER_ID {
+            return Err(not_caller_id(*message_type));
+        }
+        let body = body
+            .get(..usize::from(*len))
+            .ok_or_else(|| truncated(&msg))?;
+        for (kind, value) in params(body)? {
+            match kind {
+                JCLIP_PARAM_NUMBER => self.number = text(value),
+                JCLIP_PARAM_NUMBER_ABSENT => {
+                    // Besides O and P, JCLIP has C (public phone) and S
+                    // (service conflict): unavailable either way.
+                    self.number_absent = match value.first() {
+                        Some(b'P') => Some(Absence::Private),
+                        Some(_) => Some(Absence::Unavailable),
+                        None => None,
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_dtmf(&mut self, msg: &[u8]) -> Result<()> {
+        let mut fields = Vec::new();
+        for &b in msg {
+            match b.to_ascii_uppercase() {
+                start @ (b'A' | b'B' | b'D') => fields.push((start, Vec::new())),
+                b'C' | b'#' => {}
+                digit => match fields.last_mut() {
+                    Some((_, value)) => value.push(digit),
+                    None => {
+                        return Err(SpanDspError::InvalidInput(
+                            "CLIP DTMF message lacks a start code".into(),
+                        ));
+                    }
+                },
+            }
+        }
+        for (start, value) in fields {
+            match (start, value.as_slice()) {
+                (b'B', b"10") => self.number_absent = Some(Absence::Private),
+                (b'B', _) => self.number_absent = Some(Absence::Unavailable),
+                (_, number) if self.number.is_none() => self.number = text(number),
+                _ => {}
+            }
+        }
+        if self.number.is_none() && self.number_absent.is_none() {
+            return Err(truncated(msg));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> CallerId {
+        let number = self.number.or(self.dialable);
+        let presentation = match (&number, self.number_absent, &self.name, self.name_absent) {
+            (Some(_), ..) => Presentation::Allowed,
+            (None, Some(absence), ..) => absence.into(),
+            (None, None, Some(_), _) => Presentation::Allowed,
+            (None, None, None, Some(absence)) => absence.into(),
+            (None, None, None, None) => Presentation::Unavailable,
+        };
+        CallerId {
+            number,
+            name: self.name,
+            datetime: self.datetime,
+            presentation,
+        }
+    }
+}
+
+/// Split an MDMF body into its parameters.
+fn params(body: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut params = Vec::new();
+    let mut rest = body;
+    while let [kind, len, tail @ ..] = rest {
+        let len = usize::from(*len);
+        let value = tail.get(..len).ok_or_else(|| truncated(body))?;
+        params.push((*kind, value));
+        rest = &tail[len..];
+    }
+    if !rest.is_empty() {
+        return Err(truncated(body));
+    }
+    Ok(params)
+}
+
+fn text(value: &[u8]) -> Option<String> {
+    let value = String::from_utf8_lossy(value).trim().to_owned();
+    (!value.is_empty()).then_some(value)
+}
+
+fn absence(value: &[u8]) -> Option<Absence> {
+    value.first().copied().and_then(Absence::from_code)
+}
+
+fn truncated(msg: &[u8]) -> SpanDspError {
+    SpanDspError::InvalidInput(format!("malformed caller ID message: {msg:02x?}"))
+}
+
+fn not_caller_id(message_type: u8) -> SpanDspError {
+    SpanDspError::InvalidInput(format!("message type {message_type:#04x} is not caller ID"))
+}
+
+// ---------------------------------------------------------------------------
+// AdsiTx
+// ---------------------------------------------------------------------------
+
+/// The FSK lead-in before a message.
+///
+/// A Bell 202 receiver locks on during the channel seizure, a run of
+/// alternating bits, then waits for a run of marks before the first byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelSeizure {
+    /// Bits of alternating 0 and 1.
+    pub seizure_bits: u16,
+    /// Mark bits after the seizure.
+    pub mark_bits: u16,
+    /// Mark bits after the message.
+    pub postamble_bits: u16,
+    /// Stop bits after each byte.
+    pub stop_bits: u8,
+}
+
+impl Default for ChannelSeizure {
+    /// GR-30: 300 seizure bits, 180 marks, one stop bit.
+    fn default() -> Self {
+        Self {
+            seizure_bits: 300,
+            mark_bits: 180,
+            postamble_bits: 5,
+            stop_bits: 1,
+        }
+    }
+}
+
+/// RAII wrapper around `adsi_tx_state_t`.
+///
+/// Created via `AdsiTx::new()`, which calls `adsi_tx_init(NULL, ...)`.
+/// Freed on drop via `adsi_tx_free`.
+pub struct AdsiTx {
+    ptr: NonNull<spandsp_sys::adsi_tx_state_t>,
+    standard: AdsiStandard,
+}
+
+impl AdsiTx {
+    /// Create a transmitter for `standard`, with its usual lead-in.
+    pub fn new(standard: AdsiStandard) -> Result<Self> {
+        let ptr = unsafe { spandsp_sys::adsi_tx_init(std::ptr::null_mut(), standard as c_int) };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr, standard })
+    }
+
+    /// Create a transmitter with a visual message waiting indication
+    /// queued, turning an analog phone's message lamp on or off.
+    pub fn message_waiting(standard: AdsiStandard, active: bool) -> Result<Self> {
+        let mut tx = Self::new(standard)?;
+        tx.put_message_waiting(active)?;
+        Ok(tx)
+    }
+
+    /// Change the lead-in sent before each message.
+    pub fn set_channel_seizure(&mut self, seizure: ChannelSeizure) {
+        unsafe {
+            spandsp_sys::adsi_tx_set_preamble(
+                self.ptr.as_ptr(),
+                c_int::from(seizure.seizure_bits),
+                c_int::from(seizure.mark_bits),
+                c_int::from(seizure.postamble_bits),
+                c_int::from(seizure.stop_bits),
+            );
+        }
+    }
+
+    /// Queue a message. spandsp adds the checksum and lead-in.
+    pub fn put_message(&mut self, msg: &CallerIdMessage) -> Result<()> {
+        self.put_raw(msg.as_bytes())
+    }
+
+    /// Queue a visual message waiting indication in its MDMF form, which
+    /// CLASS, CLIP and ACLIP phones all read. For a CLASS phone that only
+    /// knows SDMF, queue `CallerIdMessage::message_waiting(active, false)`
+    /// instead.
+    pub fn put_message_waiting(&mut self, active: bool) -> Result<()> {
+        match self.standard {
+            AdsiStandard::Class | AdsiStandard::Clip | AdsiStandard::Aclip => {
+                self.put_message(&CallerIdMessage::message_waiting(active, true))
+            }
+            standard => Err(SpanDspError::InvalidInput(format!(
+                "{standard:?} has no message waiting indication"
+            ))),
+        }
+    }
+
+    /// Queue a caller ID for this transmitter's standard, built with
+    /// [`CallerIdBuilder::build`].
+    pub fn put_caller_id(&mut self, caller: &CallerIdBuilder) -> Result<()> {
+        self.put_raw(&caller.build(self.standard)?)
+    }
+
+    /// Queue a message already laid out for this standard, without its
+    /// checksum.
+    pub fn put_raw(&mut self, msg: &[u8]) -> Result<()> {
+        let len = msg.len().min(c_int::MAX as usize) as c_int;
+        let rc = unsafe { spandsp_sys::adsi_tx_put_message(self.ptr.as_ptr(), msg.as_ptr(), len) };
+        if rc < 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Queue the alert tone that precedes a message on an off-hook line.
+    pub fn send_alert_tone(&mut self) {
+        unsafe {
+            spandsp_sys::adsi_tx_send_alert_tone(self.ptr.as_ptr());
+        }
+    }
+
+    /// Generate audio for the queued message.
+    ///
+    /// Returns the number of samples generated; 0 once it has all been sent.
+    pub fn tx(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::adsi_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), len).max(0) as usize }
+    }
+
+    /// The standard in use.
+    pub fn standard(&self) -> AdsiStandard {
+        self.standard
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::adsi_tx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for AdsiTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdsiTx")
+            .field("standard", &self.standard)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AdsiTx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::adsi_tx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: AdsiTx wraps a SpanDSP adsi_tx_state_t that is only accessed
+// through &self/&mut self methods.
+unsafe impl Send for AdsiTx {}
+
+// ---------------------------------------------------------------------------
+// AdsiRx
+// ---------------------------------------------------------------------------
+
+type AdsiMessageCallback = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Trampoline for received messages.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `AdsiMessageCallback`.
+unsafe extern "C" fn adsi_rx_trampoline(user_data: *mut c_void, msg: *const u8, len: c_int) {
+    unsafe {
+        // Negative lengths report carrier changes, not messages.
+        if user_data.is_null() || msg.is_null() || len <= 0 {
+            return;
+        }
+        let closure = &mut *(user_data as *mut AdsiMessageCallback);
+        closure(std::slice::from_raw_parts(msg, len as usize));
+    }
+}
+
+/// RAII wrapper around `adsi_rx_state_t`.
+///
+/// Created via `AdsiRx::new()` or `AdsiRx::caller_id()`, which call
+/// `adsi_rx_init(NULL, ...)`. Freed on drop via `adsi_rx_free`.
+pub struct AdsiRx {
+    ptr: NonNull<spandsp_sys::adsi_rx_state_t>,
+    standard: AdsiStandard,
+    _callback: Option<Box<AdsiMessageCallback>>,
+}
+
+impl AdsiRx {
+    /// Create a receiver for `standard` that hands each message, as spandsp
+    /// delivers it, to `on_message`.
+    pub fn new<F>(standard: AdsiStandard, on_message: F) -> Result<Self>
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        let boxed: Box<AdsiMessageCallback> = Box::new(Box::new(on_message));
+        let user_data = &*boxed as *const AdsiMessageCallback as *mut c_void;
+        let mut rx = Self::init(standard, Some(adsi_rx_trampoline), user_data)?;
+        rx._callback = Some(boxed);
+        Ok(rx)
+    }
+
+    /// Create a receiver delivering messages to `put_msg`; the caller keeps
+    /// `user_data` alive for as long as the receiver.
+    fn init(
+        standard: AdsiStandard,
+        put_msg: spandsp_sys::put_msg_func_t,
+        user_data: *mut c_void,
+    ) -> Result<Self> {
+        let ptr = unsafe {
+            spandsp_sys::adsi_rx_init(std::ptr::null_mut(), standard as c_int, put_msg, user_data)
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            standard,
+            _callback: None,
+        })
+    }
+
+    /// Create a receiver that parses each message with
+    /// [`CallerId::parse`] and hands over the result.
+    pub fn caller_id<F>(standard: AdsiStandard, mut on_caller_id: F) -> Result<Self>
+    where
+        F: FnMut(Result<CallerId>) + Send + 'static,
+    {
+        Self::new(standard, move |msg| {
+            on_caller_id(CallerId::parse(standard, msg))
+        })
+    }
+
+    /// Feed received audio.
+    ///
+    /// Returns the number of unprocessed samples (normally 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::adsi_rx(self.ptr.as_ptr(), amp.as_ptr(), len).max(0) as usize }
+    }
+
+    /// The standard in use.
+    pub fn standard(&self) -> AdsiStandard {
+        self.standard
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::adsi_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for AdsiRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdsiRx")
+            .field("standard", &self.standard)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AdsiRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::adsi_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: AdsiRx wraps a SpanDSP adsi_rx_state_t that is only accessed
+// through &self/&mut self methods, and its message callback is `Send`.
+unsafe impl Send for AdsiRx {}
+
+// ---------------------------------------------------------------------------
+// CallerIdDetector
+// ---------------------------------------------------------------------------
+
+/// Caller IDs parsed since the detector last handed them over.
+struct Detected {
+    standard: AdsiStandard,
+    results: Vec<Result<CallerId>>,
+}
+
+/// Trampoline parsing each received message into `Detected`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Detected`.
+unsafe extern "C" fn detected_trampoline(user_data: *mut c_void, msg: *const u8, len: c_int) {
+    unsafe {
+        if user_data.is_null() || msg.is_null() || len <= 0 {
+            return;
+        }
+        let detected = &mut *(user_data as *mut Detected);
+        let msg = std::slice::from_raw_parts(msg, len as usize);
+        let result = CallerId::parse(detected.standard, msg);
+        detected.results.push(result);
+    }
+}
+
+/// Decodes on-hook caller ID from the audio between the first and second
+/// rings.
+///
+/// Feed it 8 kHz audio with [`rx`](Self::rx) until a caller ID turns up;
+/// there is no callback to set up and no message to parse.
+///
+/// ```no_run
+/// use spandsp::adsi::{AdsiStandard, CallerIdDetector};
+///
+/// let mut detector = CallerIdDetector::new(AdsiStandard::Class).unwrap();
+/// let between_rings = vec![0i16; 32000];
+/// for frame in between_rings.chunks(160) {
+///     if let Some(Ok(caller)) = detector.rx(frame).pop() {
+///         println!("call from {:?}", caller.number);
+///         break;
+///     }
+/// }
+/// ```
+pub struct CallerIdDetector {
+    // Dropped before `detected`, which spandsp holds a pointer to.
+    rx: AdsiRx,
+    detected: Box<Detected>,
+    caller_id: Option<CallerId>,
+}
+
+impl CallerIdDetector {
+    /// Create a detector for `standard`.
+    pub fn new(standard: AdsiStandard) -> Result<Self> {
+        if standard == AdsiStandard::Tdd {
+            return Err(SpanDspError::InvalidInput(
+                "TDD does not carry caller ID".into(),
+            ));
+        }
+        let mut detected = Box::new(Detected {
+            standard,
+            results: Vec::new(),
+        });
+        let user_data = &mut *detected as *mut Detected as *mut c_void;
+        let rx = AdsiRx::init(standard, Some(detected_trampoline), user_data)?;
+        Ok(Self {
+            rx,
+            detected,
+            caller_id: None,
+        })
+    }
+
+    /// Feed received audio.
+    ///
+    /// Returns the caller ID, or the reason it could not be read, for each
+    /// message that finishes during this block, oldest first. Most blocks
+    /// return none; a long block can return several.
+    pub fn rx(&mut self, amp: &[i16]) -> Vec<Result<CallerId>> {
+        self.rx.rx(amp);
+        let results = std::mem::take(&mut self.detected.results);
+        if let Some(caller_id) = results.iter().rev().find_map(|r| r.as_ref().ok()) {
+            self.caller_id = Some(caller_id.clone());
+        }
+        results
+    }
+
+    /// The most recent caller ID decoded.
+    pub fn caller_id(&self) -> Option<&CallerId> {
+        self.caller_id.as_ref()
+    }
+
+    /// The standard in use.
+    pub fn standard(&self) -> AdsiStandard {
+        self.rx.standard
+    }
+}
+
+impl fmt::Debug for CallerIdDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallerIdDetector")
+            .field("standard", &self.rx.standard)
+            .field("caller_id", &self.caller_id)
+            .finish_non_exhaustive()
+    }
+}
+
+// SAFETY: CallerIdDetector owns its AdsiRx and the results it fills, and
+// both are only accessed through &self/&mut self methods.
+unsafe impl Send for CallerIdDetector {}
+
+// ---------------------------------------------------------------------------
+// DtmfCliDecoder
+// ---------------------------------------------------------------------------
+
+/// Most digits a DTMF caller ID runs to before it is given up on.
+const MAX_DTMF_CLI_DIGITS: usize = 64;
+
+/// Picks DTMF caller ID (ETSI ES 200 778 style CLI) out of the digits a
+/// DTMF detector reports.
+///
+/// A message starts with `A` or `D` and the number, or `B` and a reason
+/// code, and ends with `C` or `#`. Digits outside a message are ignored,
+/// so the decoder can sit on a line's existing [`DtmfRx`](crate::dtmf::DtmfRx)
+/// rather than a dedicated [`AdsiRx`].
+///
+/// ```no_run
+/// use spandsp::adsi::DtmfCliDecoder;
+/// use spandsp::dtmf::DtmfRx;
+///
+/// let mut dtmf = DtmfRx::new().unwrap();
+/// let mut cli = DtmfCliDecoder::new();
+/// let before_ring = vec![0i16; 16000];
+/// for frame in before_ring.chunks(160) {
+///     dtmf.rx(frame);
+///     for caller in cli.push(&dtmf.get(128)).into_iter().flatten() {
+///         println!("call from {:?}", caller.number);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DtmfCliDecoder {
+    digits: String,
+}
+
+impl DtmfCliDecoder {
+    /// Create a decoder waiting for a start digit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed detected digits.
+    ///
+    /// Returns the caller ID, or why it could not be read, for each message
+    /// that ends in them, oldest first.
+    pub fn push(&mut self, digits: &str) -> Vec<Result<CallerId>> {
+        let mut results = Vec::new();
+        for digit in digits.chars().map(|d| d.to_ascii_uppercase()) {
+            match digit {
+                'A' | 'B' | 'D' => self.digits.push(digit),
+                _ if self.digits.is_empty() => {}
+                'C' | '#' => {
+                    self.digits.push(digit);
+                    results.extend(self.finish());
+                }
+                _ if self.digits.len() >= MAX_DTMF_CLI_DIGITS => {
+                    let digits = std::mem::take(&mut self.digits);
+                    results.push(Err(SpanDspError::InvalidInput(format!(
+                        "DTMF caller ID {digits}... never ended"
+                    ))));
+                }
+                _ => self.digits.push(digit),
+            }
+        }
+        results
+    }
+
+    /// End a message whose terminator never came, as when the first ring
+    /// follows straight on from the digits.
+    pub fn finish(&mut self) -> Option<Result<CallerId>> {
+        if self.digits.is_empty() {
+            return None;
+        }
+        let digits = std::mem::take(&mut self.digits);
+        Some(CallerId::parse(AdsiStandard::ClipDtmf, digits.as_bytes()))
+    }
+
+    /// Whether a message has started but not ended.
+    pub fn is_receiving(&self) -> bool {
+        !self.digits.is_empty()
+    }
+
+    /// Drop a message in progress.
+    pub fn reset(&mut self) {
+        self.digits.clear();
+    }
+}
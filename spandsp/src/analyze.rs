@@ -0,0 +1,585 @@
+//! Offline analysis of call recordings.
+//!
+//! [`analyze`] runs a recording through DTMF, call progress, special
+//! information tone, fax tone and modem answer tone detection, and meters
+//! its level, in one pass. The result is a timeline of typed events, for
+//! checking what a recorded call or an IVR under test actually played.
+//!
+//! ```no_run
+//! use spandsp::analyze::{EventKind, analyze_wav};
+//!
+//! let analysis = analyze_wav("call.wav").unwrap();
+//! for event in &analysis.events {
+//!     if let EventKind::Dtmf(digit) = event.kind {
+//!         println!("{digit} at {:.2} s", event.start_secs());
+//!     }
+//! }
+//! ```
+//!
+//! Recordings are 8 kHz mono. Times are in samples from the start.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::dtmf::DtmfRx;
+use crate::error::{Result, SpanDspError};
+use crate::g711::{alaw_to_linear, ulaw_to_linear};
+use crate::modem_connect_tones::{ConnectTone, ConnectToneRx};
+use crate::power_meter::mean_square_dbm0;
+use crate::tone_detect::{GoertzelDescriptor, GoertzelDetector};
+
+/// Samples per second.
+const SAMPLE_RATE: u64 = 8000;
+
+/// Samples fed to the detectors at a time.
+const CHUNK: usize = 80;
+
+/// Samples per call progress analysis block: 50 ms, fine enough to tell
+/// 440 Hz from 480 Hz.
+const CP_BLOCK: usize = 400;
+
+/// Samples per level reading.
+const LEVEL_WINDOW: usize = 800;
+
+/// Floor for level readings, in dBm0.
+const MIN_LEVEL_DBM0: f32 = -99.0;
+
+/// Below this a block is not checked for tones, in dBm0.
+const CP_MIN_LEVEL_DBM0: f32 = -45.0;
+
+/// Share of a block's power a tone (or pair) must hold to count.
+const CP_MIN_SHARE: f32 = 0.75;
+
+/// Precise call progress (350/440/480/620 Hz) and SIT frequencies.
+const CP_FREQS: [f32; 9] = [
+    350.0, 440.0, 480.0, 620.0, 913.8, 985.2, 1370.6, 1428.5, 1776.7,
+];
+
+// ---------------------------------------------------------------------------
+// Events
+// ---------------------------------------------------------------------------
+
+/// North American call progress tones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallProgressTone {
+    /// Continuous 350 + 440 Hz.
+    DialTone,
+    /// 440 + 480 Hz, about 2 s on.
+    Ringback,
+    /// 480 + 620 Hz, 0.5 s on, 0.5 s off.
+    Busy,
+    /// 480 + 620 Hz, 0.25 s on, 0.25 s off (fast busy).
+    Reorder,
+}
+
+/// What a special information tone announces, from the frequencies and
+/// lengths of its first two segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SitCode {
+    /// IC: the number has changed or been disconnected.
+    Intercept,
+    /// NC: all circuits are busy.
+    NoCircuit,
+    /// VC: the number is not in service.
+    VacantCode,
+    /// RO: the call could not be routed.
+    Reorder,
+    /// A pattern outside the common codes.
+    Other,
+}
+
+/// The tones a fax machine sends at the start of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FaxSignal {
+    /// CNG: the calling machine's 1100 Hz cadenced tone.
+    Cng,
+    /// CED: 2100 Hz. A data modem's plain V.25 answer tone is the same
+    /// tone, and shows as this too.
+    Ced,
+    /// V.21 HDLC flags ahead of the first T.30 frame.
+    V21Preamble,
+}
+
+/// Data modem answer and calling tones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModemTone {
+    /// 2100 Hz with phase reversals (V.25 ANS/).
+    AnsPr,
+    /// 2100 Hz amplitude modulated (V.8 ANSam).
+    AnsAm,
+    /// ANSam with phase reversals (ANSam/).
+    AnsAmPr,
+    /// 2225 Hz Bell answer tone.
+    BellAns,
+    /// 1300 Hz V.8 calling tone.
+    CallingTone,
+}
+
+/// What an event is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EventKind {
+    /// A DTMF digit.
+    Dtmf(char),
+    /// A call progress tone; repeats of a cadenced tone make one event.
+    CallProgress(CallProgressTone),
+    /// A special information tone.
+    Sit(SitCode),
+    /// A fax tone, reported when detected.
+    Fax(FaxSignal),
+    /// A modem tone, reported when detected.
+    Modem(ModemTone),
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dtmf(digit) => write!(f, "DTMF {digit}"),
+            Self::CallProgress(tone) => write!(f, "{tone:?}"),
+            Self::Sit(code) => write!(f, "SIT {code:?}"),
+            Self::Fax(signal) => write!(f, "fax {signal:?}"),
+            Self::Modem(tone) => write!(f, "modem {tone:?}"),
+        }
+    }
+}
+
+/// One event on the timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimelineEvent {
+    /// First sample of the event.
+    pub start: u64,
+    /// Sample after the event. Fax and modem tones are reported at the
+    /// point of detection, with `end == start`.
+    pub end: u64,
+    /// What was heard.
+    pub kind: EventKind,
+}
+
+impl TimelineEvent {
+    /// Start time in seconds.
+    pub fn start_secs(&self) -> f64 {
+        self.start as f64 / SAMPLE_RATE as f64
+    }
+
+    /// Length in seconds.
+    pub fn duration_secs(&self) -> f64 {
+        (self.end - self.start) as f64 / SAMPLE_RATE as f64
+    }
+}
+
+/// The result of [`analyze`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Analysis {
+    /// Events in order of start.
+    pub events: Vec<TimelineEvent>,
+    /// Level of each 100 ms of the recording, in dBm0, floored at -99.
+    pub levels: Vec<f32>,
+    /// Largest sample magnitude.
+    pub peak: u16,
+    /// Samples at or beyond full scale.
+    pub clipped: u64,
+    /// Length of the recording in samples.
+    pub samples: u64,
+}
+
+impl Analysis {
+    /// The DTMF digits, in order.
+    pub fn digits(&self) -> String {
+        self.events
+            .iter()
+            .filter_map(|event| match event.kind {
+                EventKind::Dtmf(digit) => Some(digit),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Entry points
+// ---------------------------------------------------------------------------
+
+/// Analyze an 8 kHz mono recording.
+pub fn analyze(samples: &[i16]) -> Result<Analysis> {
+    let mut analyzer = Analyzer::new()?;
+    for chunk in samples.chunks(CHUNK) {
+        analyzer.chunk(chunk);
+    }
+    Ok(analyzer.finish())
+}
+
+/// Analyze an 8 kHz mono WAV file: 16-bit PCM, A-law or u-law.
+pub fn analyze_wav(path: impl AsRef<Path>) -> Result<Analysis> {
+    let path = path.as_ref();
+    let wav = std::fs::read(path).map_err(|err| {
+        SpanDspError::InvalidInput(format!("cannot read {}: {err}", path.display()))
+    })?;
+    analyze(&wav_samples(&wav)?)
+}
+
+/// The samples of an 8 kHz mono WAV file.
+pub(crate) fn wav_samples(wav: &[u8]) -> Result<Vec<i16>> {
+    let invalid = |what: &str| SpanDspError::InvalidInput(format!("unsupported WAV file: {what}"));
+    if wav.len() < 12 || &wav[..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(invalid("no RIFF/WAVE header"));
+    }
+    let mut format = None;
+    let mut rest = &wav[12..];
+    while let [a, b, c, d, l0, l1, l2, l3, body @ ..] = rest {
+        let id = [*a, *b, *c, *d];
+        let len = u32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize;
+        let chunk = body.get(..len).unwrap_or(body);
+        match &id {
+            b"fmt " => {
+                let &[f0, f1, c0, c1, r0, r1, r2, r3, _, _, _, _, _, _, b0, b1, ..] = chunk else {
+                    return Err(invalid("short fmt chunk"));
+                };
+                let tag = u16::from_le_bytes([f0, f1]);
+                let channels = u16::from_le_bytes([c0, c1]);
+                let rate = u32::from_le_bytes([r0, r1, r2, r3]);
+                let bits = u16::from_le_bytes([b0, b1]);
+                if channels != 1 || u64::from(rate) != SAMPLE_RATE {
+                    return Err(invalid("not 8 kHz mono"));
+                }
+                format = Some((tag, bits));
+            }
+            b"data" => {
+                return match format {
+                    Some((1, 16)) => Ok(chunk
+                        .chunks_exact(2)
+                        .map(|s| i16::from_le_bytes([s[0], s[1]]))
+                        .collect()),
+                    Some((6, 8)) => Ok(chunk.iter().map(|&b| alaw_to_linear(b)).collect()),
+                    Some((7, 8)) => Ok(chunk.iter().map(|&b| ulaw_to_linear(b)).collect()),
+                    Some(_) => Err(invalid("not 16-bit PCM, A-law or u-law")),
+                    None => Err(invalid("data before fmt")),
+                };
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length.
+        rest = body.get(len + (len & 1)..).unwrap_or_default();
+    }
+    Err(invalid("no data chunk"))
+}
+
+// ---------------------------------------------------------------------------
+// Analyzer
+// ---------------------------------------------------------------------------
+
+/// The level of a mean square, in dBm0, floored at [`MIN_LEVEL_DBM0`].
+fn floored_dbm0(mean_sq: f64) -> f32 {
+    // log10(0) is -inf, which max() floors too.
+    mean_square_dbm0(mean_sq).max(MIN_LEVEL_DBM0)
+}
+
+/// What a call progress block holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Band {
+    Quiet,
+    Dial,
+    Ringback,
+    Busy,
+    /// SIT first segment; 985.2 Hz rather than 913.8 Hz.
+    SitLow(bool),
+    /// SIT second segment; 1428.5 Hz rather than 1370.6 Hz.
+    SitMid(bool),
+    SitHigh,
+}
+
+/// A run of blocks in one band, in samples.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    band: Band,
+    start: u64,
+    end: u64,
+}
+
+impl Run {
+    fn ms(&self) -> u64 {
+        (self.end - self.start) * 1000 / SAMPLE_RATE
+    }
+}
+
+struct Analyzer {
+    position: u64,
+    dtmf: DtmfRx,
+    digit: Option<(char, u64)>,
+    cng: ConnectToneRx,
+    ced: ConnectToneRx,
+    ans: ConnectToneRx,
+    bell: ConnectToneRx,
+    calling: ConnectToneRx,
+    goertzels: Vec<GoertzelDetector>,
+    cp_energy: f64,
+    cp_count: usize,
+    run: Run,
+    /// The last two SIT-like runs.
+    sit: Vec<Run>,
+    level_energy: f64,
+    level_count: usize,
+    events: Vec<TimelineEvent>,
+    levels: Vec<f32>,
+    peak: u16,
+    clipped: u64,
+}
+
+impl Analyzer {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            position: 0,
+            dtmf: DtmfRx::new()?,
+            digit: None,
+            cng: ConnectToneRx::new(ConnectTone::Cng)?,
+            ced: ConnectToneRx::fax_answer()?,
+            ans: ConnectToneRx::new(ConnectTone::AnsAmPr)?,
+            bell: ConnectToneRx::new(ConnectTone::BellAns)?,
+            calling: ConnectToneRx::new(ConnectTone::CallingTone)?,
+            goertzels: CP_FREQS
+                .iter()
+                .map(|&freq| GoertzelDetector::new(&GoertzelDescriptor::new(freq, CP_BLOCK)))
+                .collect::<Result<_>>()?,
+            cp_energy: 0.0,
+            cp_count: 0,
+            run: Run {
+                band: Band::Quiet,
+                start: 0,
+                end: 0,
+            },
+            sit: Vec::with_capacity(2),
+            level_energy: 0.0,
+            level_count: 0,
+            events: Vec::new(),
+            levels: Vec::new(),
+            peak: 0,
+            clipped: 0,
+        })
+    }
+
+    fn chunk(&mut self, amp: &[i16]) {
+        let end = self.position + amp.len() as u64;
+
+        self.dtmf.rx(amp);
+        self.dtmf.get(128);
+        let tone = self.dtmf.status().filter(|&digit| digit != 'x');
+        if self.digit.map(|(digit, _)| digit) != tone {
+            if let Some((digit, start)) = self.digit.take() {
+                self.push(start, self.position, EventKind::Dtmf(digit));
+            }
+            self.digit = tone.map(|digit| (digit, self.position));
+        }
+
+        let at = self.position;
+        for detector in [
+            &mut self.cng,
+            &mut self.ced,
+            &mut self.ans,
+            &mut self.bell,
+            &mut self.calling,
+        ] {
+            detector.rx(amp);
+        }
+        if self.cng.take_detected() == Some(ConnectTone::Cng) {
+            self.push(at, at, EventKind::Fax(FaxSignal::Cng));
+        }
+        match self.ced.take_detected() {
+            Some(ConnectTone::Ans) => self.push(at, at, EventKind::Fax(FaxSignal::Ced)),
+            Some(ConnectTone::V21Preamble) => {
+                self.push(at, at, EventKind::Fax(FaxSignal::V21Preamble))
+            }
+            _ => {}
+        }
+        // Plain ANS is reported as CED above.
+        let modem = match self.ans.take_detected() {
+            Some(ConnectTone::AnsPr) => Some(ModemTone::AnsPr),
+            Some(ConnectTone::AnsAm) => Some(ModemTone::AnsAm),
+            Some(ConnectTone::AnsAmPr) => Some(ModemTone::AnsAmPr),
+            _ => None,
+        };
+        let bell = self.bell.take_detected().map(|_| ModemTone::BellAns);
+        let calling = self.calling.take_detected().map(|_| ModemTone::CallingTone);
+        for tone in [modem, bell, calling].into_iter().flatten() {
+            self.push(at, at, EventKind::Modem(tone));
+        }
+
+        let mut rest = amp;
+        let mut done = 0;
+        while !rest.is_empty() {
+            let (block, tail) = rest.split_at((CP_BLOCK - self.cp_count).min(rest.len()));
+            for goertzel in &mut self.goertzels {
+                goertzel.update(block);
+            }
+            self.cp_energy += block
+                .iter()
+                .map(|&s| f64::from(s) * f64::from(s))
+                .sum::<f64>();
+            self.cp_count += block.len();
+            done += block.len();
+            if self.cp_count == CP_BLOCK {
+                self.cp_block(self.position + done as u64);
+            }
+            rest = tail;
+        }
+
+        for &sample in amp {
+            let s = f32::from(sample);
+            self.level_energy += f64::from(s * s);
+            self.level_count += 1;
+            if self.level_count == LEVEL_WINDOW {
+                self.flush_level();
+            }
+
+            let magnitude = sample.unsigned_abs();
+            self.peak = self.peak.max(magnitude);
+            if magnitude >= i16::MAX as u16 {
+                self.clipped += 1;
+            }
+        }
+        self.position = end;
+    }
+
+    fn cp_block(&mut self, end: u64) {
+        let n = self.cp_count;
+        let mean_sq = (self.cp_energy / n as f64) as f32;
+        let shares: Vec<f32> = self
+            .goertzels
+            .iter_mut()
+            .map(|g| g.tone_power() / mean_sq.max(1.0))
+            .collect();
+        self.cp_energy = 0.0;
+        self.cp_count = 0;
+
+        let level = floored_dbm0(f64::from(mean_sq));
+        let pair = |a: usize, b: usize| {
+            shares[a] >= 0.2 && shares[b] >= 0.2 && shares[a] + shares[b] >= CP_MIN_SHARE
+        };
+        let single = |a: usize| shares[a] >= CP_MIN_SHARE;
+        let band = if level < CP_MIN_LEVEL_DBM0 {
+            Band::Quiet
+        } else if pair(0, 1) {
+            Band::Dial
+        } else if pair(1, 2) {
+            Band::Ringback
+        } else if pair(2, 3) {
+            Band::Busy
+        } else if single(4) || single(5) {
+            Band::SitLow(single(5))
+        } else if single(6) || single(7) {
+            Band::SitMid(single(7))
+        } else if single(8) {
+            Band::SitHigh
+        } else {
+            Band::Quiet
+        };
+
+        if band == self.run.band {
+            self.run.end = end;
+        } else {
+            let finished = self.run;
+            self.run = Run {
+                band,
+                start: end - n as u64,
+                end,
+            };
+            self.end_run(finished);
+        }
+    }
+
+    fn end_run(&mut self, run: Run) {
+        let ms = run.ms();
+        let (kind, merge_gap_ms) = match run.band {
+            Band::Quiet => return,
+            Band::Dial if ms >= 1000 => (CallProgressTone::DialTone, 500),
+            Band::Ringback if (700..=2600).contains(&ms) => (CallProgressTone::Ringback, 4500),
+            Band::Busy if (350..=700).contains(&ms) => (CallProgressTone::Busy, 1000),
+            Band::Busy if (150..350).contains(&ms) => (CallProgressTone::Reorder, 1000),
+            Band::SitLow(_) | Band::SitMid(_) => {
+                if self.sit.len() == 2 {
+                    self.sit.remove(0);
+                }
+                self.sit.push(run);
+                return;
+            }
+            Band::SitHigh => {
+                self.end_sit(run);
+                return;
+            }
+            _ => return,
+        };
+        let kind = EventKind::CallProgress(kind);
+        let merge_gap = merge_gap_ms * SAMPLE_RATE / 1000;
+        if let Some(last) = self
+            .events
+            .iter_mut()
+            .rev()
+            .find(|event| matches!(event.kind, EventKind::CallProgress(_)))
+            && last.kind == kind
+            && run.start - last.end <= merge_gap
+        {
+            last.end = run.end;
+            return;
+        }
+        self.push(run.start, run.end, kind);
+    }
+
+    fn end_sit(&mut self, high: Run) {
+        let max_gap = 2 * CP_BLOCK as u64;
+        let [low, mid] = self.sit[..] else {
+            return;
+        };
+        self.sit.clear();
+        let (Band::SitLow(first_high), Band::SitMid(second_high)) = (low.band, mid.band) else {
+            return;
+        };
+        if mid.start - low.end > max_gap || high.start - mid.end > max_gap {
+            return;
+        }
+        let first_long = low.ms() >= 330;
+        let second_long = mid.ms() >= 330;
+        let code = match (first_high, first_long, second_high, second_long) {
+            (false, false, false, false) => SitCode::Intercept,
+            (true, true, true, true) | (false, true, false, true) => SitCode::NoCircuit,
+            (true, true, false, false) => SitCode::VacantCode,
+            (false, false, true, true) | (true, false, false, true) => SitCode::Reorder,
+            _ => SitCode::Other,
+        };
+        self.push(low.start, high.end, EventKind::Sit(code));
+    }
+
+    fn flush_level(&mut self) {
+        if self.level_count == 0 {
+            return;
+        }
+        let mean_sq = self.level_energy / self.level_count as f64;
+        self.levels.push(floored_dbm0(mean_sq));
+        self.level_energy = 0.0;
+        self.level_count = 0;
+    }
+
+    fn push(&mut self, start: u64, end: u64, kind: EventKind) {
+        self.events.push(TimelineEvent { start, end, kind });
+    }
+
+    fn finish(mut self) -> Analysis {
+        if let Some((digit, start)) = self.digit.take() {
+            self.push(start, self.position, EventKind::Dtmf(digit));
+        }
+        let last = self.run;
+        self.end_run(last);
+        self.flush_level();
+        self.events.sort_by_key(|event| (event.start, event.end));
+        Analysis {
+            events: self.events,
+            levels: self.levels,
+            peak: self.peak,
+            clipped: self.clipped,
+            samples: self.position,
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Safe wrappers around spandsp's asynchronous serial framing.
+//!
+//! - `AsyncTx` wraps `async_tx_state_t`.
+//! - `AsyncRx` wraps `async_rx_state_t`.
+//!
+//! These put start/stop framing around bytes so a byte stream can ride on
+//! a bit-serial modem: `AsyncTx` is a
+//! [`BitSource`](crate::bit_source::BitSource) that can feed an
+//! [`FskTx`](crate::fsk::FskTx), and `AsyncRx::put_bit` takes the bits an
+//! [`FskRx`](crate::fsk::FskRx) hands out.
+//!
+//! ```no_run
+//! use spandsp::async_serial::{AsyncFormat, AsyncRx, AsyncTx};
+//! use spandsp::fsk::{FskFraming, FskRx, FskSpec, FskTx};
+//!
+//! let mut bytes = b"ATZ\r".iter().copied();
+//! let tx = AsyncTx::new(AsyncFormat::default(), move || bytes.next()).unwrap();
+//! let mut modem = FskTx::from_source(FskSpec::V21Ch1, tx).unwrap();
+//! let mut rx = AsyncRx::new(AsyncFormat::default(), |byte| {
+//!     print!("{}", byte as char);
+//! })
+//! .unwrap();
+//! let mut demod = FskRx::new(FskSpec::V21Ch1, FskFraming::Async, move |bit| {
+//!     rx.put_bit(bit);
+//! })
+//! .unwrap();
+//! let mut amp = [0i16; 160];
+//! let n = modem.generate(&mut amp);
+//! demod.rx(&amp[..n]);
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// The parity bit, if any, sent after the data bits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+impl Parity {
+    fn as_raw(self) -> c_int {
+        (match self {
+            Parity::None => spandsp_sys::ASYNC_PARITY_NONE,
+            Parity::Even => spandsp_sys::ASYNC_PARITY_EVEN,
+            Parity::Odd => spandsp_sys::ASYNC_PARITY_ODD,
+        }) as c_int
+    }
+}
+
+/// The shape of each character: data bits, parity and stop bits.
+///
+/// Defaults to 8N1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AsyncFormat {
+    /// Data bits per character, 5 to 8.
+    pub data_bits: u8,
+    /// The parity bit.
+    pub parity: Parity,
+    /// Stop bits per character, 1 or 2.
+    pub stop_bits: u8,
+    /// Use V.14 rate adaption, dropping and restoring stop bits so a
+    /// slightly fast stream fits a synchronous channel.
+    pub v14: bool,
+}
+
+impl Default for AsyncFormat {
+    fn default() -> Self {
+        Self {
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            v14: false,
+        }
+    }
+}
+
+impl AsyncFormat {
+    /// A format without V.14 rate adaption.
+    pub fn new(data_bits: u8, parity: Parity, stop_bits: u8) -> Self {
+        Self {
+            data_bits,
+            parity,
+            stop_bits,
+            v14: false,
+        }
+    }
+
+    /// Line bits per character, start bit included.
+    pub fn bits_per_char(&self) -> u32 {
+        1 + self.data_bits as u32 + (self.parity != Parity::None) as u32 + self.stop_bits as u32
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(5..=8).contains(&self.data_bits) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "{} data bits; async framing takes 5 to 8",
+                self.data_bits
+            )));
+        }
+        if !(1..=2).contains(&self.stop_bits) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "{} stop bits; async framing takes 1 or 2",
+                self.stop_bits
+            )));
+        }
+        Ok(())
+    }
+
+    fn parity_bits(&self) -> c_int {
+        self.parity.as_raw()
+    }
+}
+
+/// The usual shorthand, e.g. `8N1` or `7E2`.
+impl fmt::Display for AsyncFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        write!(f, "{}{parity}{}", self.data_bits, self.stop_bits)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// AsyncTx
+// ---------------------------------------------------------------------------
+
+type GetByteCallback = Box<dyn FnMut() -> Option<u8> + Send>;
+
+/// Trampoline for `span_get_byte_func_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `GetByteCallback`.
+unsafe extern "C" fn get_byte_trampoline(user_data: *mut c_void) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return spandsp_sys::SIG_STATUS_END_OF_DATA as c_int;
+        }
+        let closure = &mut *(user_data as *mut GetByteCallback);
+        match closure() {
+            Some(byte) => byte as c_int,
+            None => spandsp_sys::SIG_STATUS_END_OF_DATA as c_int,
+        }
+    }
+}
+
+/// RAII wrapper around `async_tx_state_t`.
+///
+/// Created via `AsyncTx::new()`. Freed on drop via `async_tx_free`.
+pub struct AsyncTx {
+    ptr: NonNull<spandsp_sys::async_tx_state_t>,
+    format: AsyncFormat,
+    _callback: Box<GetByteCallback>,
+}
+
+impl AsyncTx {
+    /// Create a transmitter framing each byte `get_byte` returns. The
+    /// bits end once it returns `None`.
+    pub fn new<F>(format: AsyncFormat, get_byte: F) -> Result<Self>
+    where
+        F: FnMut() -> Option<u8> + Send + 'static,
+    {
+        format.validate()?;
+        let boxed: Box<GetByteCallback> = Box::new(Box::new(get_byte));
+        let user_data = &*boxed as *const GetByteCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::async_tx_init(
+                std::ptr::null_mut(),
+                format.data_bits as c_int,
+                format.parity_bits(),
+                format.stop_bits as c_int,
+                format.v14,
+                Some(get_byte_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            format,
+            _callback: boxed,
+        })
+    }
+
+    /// Returns the character format.
+    pub fn format(&self) -> AsyncFormat {
+        self.format
+    }
+
+    /// Get the next bit to send.
+    ///
+    /// Returns 0 or 1, or a negative `SIG_STATUS_*` once the bytes end.
+    pub fn get_bit(&mut self) -> i32 {
+        unsafe { spandsp_sys::async_tx_get_bit(self.ptr.as_ptr() as *mut c_void) as i32 }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::async_tx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for AsyncTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncTx")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AsyncTx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::async_tx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: AsyncTx wraps a SpanDSP async_tx_state_t that is only accessed
+// through &self/&mut self methods, and its byte source is Send.
+unsafe impl Send for AsyncTx {}
+
+// ---------------------------------------------------------------------------
+// AsyncRx
+// ---------------------------------------------------------------------------
+
+type PutByteCallback = Box<dyn FnMut(u8) + Send>;
+
+/// Trampoline for `span_put_byte_func_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `PutByteCallback`.
+unsafe extern "C" fn put_byte_trampoline(user_data: *mut c_void, byte: c_int) {
+    unsafe {
+        // Negative values pass on the modem's status, not data.
+        if user_data.is_null() || byte < 0 {
+            return;
+        }
+        let closure = &mut *(user_data as *mut PutByteCallback);
+        closure(byte as u8);
+    }
+}
+
+/// RAII wrapper around `async_rx_state_t`.
+///
+/// Created via `AsyncRx::new()`. Freed on drop via `async_rx_free`.
+pub struct AsyncRx {
+    ptr: NonNull<spandsp_sys::async_rx_state_t>,
+    format: AsyncFormat,
+    _callback: Box<PutByteCallback>,
+}
+
+impl AsyncRx {
+    /// Create a receiver handing each complete character to `put_byte`.
+    /// Characters with bad parity or a missing stop bit are dropped.
+    pub fn new<F>(format: AsyncFormat, put_byte: F) -> Result<Self>
+    where
+        F: FnMut(u8) + Send + 'static,
+    {
+        format.validate()?;
+        let boxed: Box<PutByteCallback> = Box::new(Box::new(put_byte));
+        let user_data = &*boxed as *const PutByteCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::async_rx_init(
+                std::ptr::null_mut(),
+                format.data_bits as c_int,
+                format.parity_bits(),
+                format.stop_bits as c_int,
+                format.v14,
+                Some(put_byte_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            format,
+            _callback: boxed,
+        })
+    }
+
+    /// Returns the character format.
+    pub fn format(&self) -> AsyncFormat {
+        self.format
+    }
+
+    /// Feed a received bit.
+    pub fn put_bit(&mut self, bit: bool) {
+        unsafe {
+            spandsp_sys::async_rx_put_bit(self.ptr.as_ptr() as *mut c_void, bit as c_int);
+        }
+    }
+
+    /// Feed a run of received bits.
+    pub fn put_bits(&mut self, bits: impl IntoIterator<Item = bool>) {
+        for bit in bits {
+            self.put_bit(bit);
+        }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::async_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for AsyncRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncRx")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AsyncRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::async_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: AsyncRx wraps a SpanDSP async_rx_state_t that is only accessed
+// through &self/&mut self methods, and its byte callback is Send.
+unsafe impl Send for AsyncRx {}
@@ -0,0 +1,491 @@
+//! Safe wrapper around spandsp's Hayes AT command interpreter.
+//!
+//! `AtInterpreter` wraps `at_state_t`: the command side of a modem's DTE
+//! port. It parses `AT` command lines, keeps the S-registers and profile,
+//! and answers with result codes. What the commands mean for the line is
+//! left to three hooks:
+//!
+//! - the response channel, which gets every byte bound for the DTE,
+//! - modem control, which gets dial, answer, hang up and the V.24 circuits,
+//! - the optional class 1 handler, which gets the T.31 `+FTH`, `+FRM` and
+//!   friends.
+//!
+//! Calls the modem side sees are reported back with
+//! [`call_event`](AtInterpreter::call_event), and caller ID for the next
+//! `RING` is set with [`set_call_info`](AtInterpreter::set_call_info).
+//!
+//! ```no_run
+//! use spandsp::at_interpreter::{AtInterpreter, ModemControl};
+//!
+//! let mut at = AtInterpreter::new(
+//!     |bytes| print!("{}", String::from_utf8_lossy(bytes)),
+//!     |op| {
+//!         if let ModemControl::Call(number) = op {
+//!             println!("dialling {number}");
+//!         }
+//!         true
+//!     },
+//! )
+//! .unwrap();
+//! at.interpret(b"ATDT5551234\r");
+//! ```
+
+extern crate spandsp_sys;
+
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// What the interpreter does with the bytes it is given, wrapping
+/// `at_rx_mode_e`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum AtMode {
+    /// Parsing commands, on hook.
+    OnhookCommand = spandsp_sys::at_rx_mode_e_AT_MODE_ONHOOK_COMMAND,
+    /// Parsing commands, off hook.
+    OffhookCommand = spandsp_sys::at_rx_mode_e_AT_MODE_OFFHOOK_COMMAND,
+    /// Online, passing data through.
+    Connected = spandsp_sys::at_rx_mode_e_AT_MODE_CONNECTED,
+    /// Waiting for data to deliver.
+    Delivery = spandsp_sys::at_rx_mode_e_AT_MODE_DELIVERY,
+    /// Sending HDLC frames, as T.31 does.
+    Hdlc = spandsp_sys::at_rx_mode_e_AT_MODE_HDLC,
+    /// Sending DLE-stuffed data, as T.31 does.
+    Stuffed = spandsp_sys::at_rx_mode_e_AT_MODE_STUFFED,
+}
+
+/// A result code sent to the DTE, wrapping `at_response_code_e`. Sent as
+/// text or digits as `ATV` selects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum AtResponseCode {
+    Ok = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_OK,
+    Connect = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_CONNECT,
+    Ring = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_RING,
+    NoCarrier = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_NO_CARRIER,
+    Error = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_ERROR,
+    NoDialtone = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_NO_DIALTONE,
+    Busy = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_BUSY,
+    NoAnswer = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_NO_ANSWER,
+    /// `+FCERROR`: the class 1 modulation did not match.
+    FcError = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_FCERROR,
+    /// `+FRH:3`: V.21 HDLC detected.
+    Frh3 = spandsp_sys::at_response_code_e_AT_RESPONSE_CODE_FRH3,
+}
+
+/// Call progress on the line side, wrapping `at_call_event_e`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum AtCallEvent {
+    /// An incoming call is ringing.
+    Alerting = spandsp_sys::at_call_event_e_AT_CALL_EVENT_ALERTING,
+    /// An outgoing call was answered.
+    Connected = spandsp_sys::at_call_event_e_AT_CALL_EVENT_CONNECTED,
+    /// An incoming call was answered.
+    Answered = spandsp_sys::at_call_event_e_AT_CALL_EVENT_ANSWERED,
+    Busy = spandsp_sys::at_call_event_e_AT_CALL_EVENT_BUSY,
+    NoDialtone = spandsp_sys::at_call_event_e_AT_CALL_EVENT_NO_DIALTONE,
+    NoAnswer = spandsp_sys::at_call_event_e_AT_CALL_EVENT_NO_ANSWER,
+    /// The far end hung up.
+    Hangup = spandsp_sys::at_call_event_e_AT_CALL_EVENT_HANGUP,
+}
+
+/// A modem control request from the interpreter, decoded from
+/// `at_modem_control_operation_e` and its argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModemControl {
+    /// Dial this number (`ATD`).
+    Call(String),
+    /// Answer the ringing call (`ATA`).
+    Answer,
+    /// Hang up (`ATH`).
+    Hangup,
+    OffHook,
+    OnHook,
+    /// Set the DTR circuit.
+    Dtr(bool),
+    /// Set the RTS circuit.
+    Rts(bool),
+    /// Set the CTS circuit.
+    Cts(bool),
+    /// Set the carrier detect circuit.
+    Carrier(bool),
+    /// Set the ring indicator circuit.
+    Ring(bool),
+    /// Set the DSR circuit.
+    Dsr(bool),
+    /// Set the caller ID to send.
+    SetId(String),
+    /// Restart the modem in this mode.
+    Restart(i32),
+    /// Start or stop the timer for a DTE that has gone quiet; the value is
+    /// the timeout, or 0 to stop it.
+    DteTimeout(i32),
+}
+
+impl ModemControl {
+    /// Decode an operation and its argument, which is a string for dialling
+    /// and caller ID and an integer cast to a pointer otherwise.
+    ///
+    /// # Safety
+    ///
+    /// For `CALL` and `SETID`, `num` must be null or a valid C string.
+    unsafe fn from_raw(op: c_int, num: *const c_char) -> Option<Self> {
+        let text = || {
+            if num.is_null() {
+                String::new()
+            } else {
+                unsafe { CStr::from_ptr(num) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        };
+        let value = num as isize as i32;
+        Some(match op as u32 {
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_CALL => {
+                ModemControl::Call(text())
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_ANSWER => {
+                ModemControl::Answer
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_HANGUP => {
+                ModemControl::Hangup
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_OFFHOOK => {
+                ModemControl::OffHook
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_ONHOOK => {
+                ModemControl::OnHook
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_DTR => {
+                ModemControl::Dtr(value != 0)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_RTS => {
+                ModemControl::Rts(value != 0)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_CTS => {
+                ModemControl::Cts(value != 0)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_CAR => {
+                ModemControl::Carrier(value != 0)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_RNG => {
+                ModemControl::Ring(value != 0)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_DSR => {
+                ModemControl::Dsr(value != 0)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_SETID => {
+                ModemControl::SetId(text())
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_RESTART => {
+                ModemControl::Restart(value)
+            }
+            spandsp_sys::at_modem_control_operation_e_AT_MODEM_CONTROL_DTE_TIMEOUT => {
+                ModemControl::DteTimeout(value)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// What a T.31 class 1 command asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Class1Kind {
+    /// `+FTH`/`+FRH`: HDLC frames.
+    Hdlc,
+    /// `+FTM`/`+FRM`: raw modem data.
+    Modem,
+    /// `+FTS`/`+FRS`: silence, `value` in 10 ms units.
+    Silence,
+}
+
+/// A T.31 class 1 command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Class1Command {
+    /// `+FT*` rather than `+FR*`.
+    pub transmit: bool,
+    pub kind: Class1Kind,
+    /// The modulation, or the silence length.
+    pub value: i32,
+}
+
+/// How to answer a class 1 command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class1Reply {
+    /// Send `OK` now.
+    Ok,
+    /// Send `ERROR` now.
+    Error,
+    /// Send nothing yet; the result code follows once the modem has done
+    /// its part, via [`AtInterpreter::put_response_code`].
+    Later,
+}
+
+type ResponseCallback = Box<dyn FnMut(&[u8]) + Send>;
+type ModemControlCallback = Box<dyn FnMut(ModemControl) -> bool + Send>;
+type Class1Callback = Box<dyn FnMut(Class1Command) -> Class1Reply + Send>;
+
+/// The hooks every trampoline reports to. Shared with the T.31 modem,
+/// which takes the same response and modem control hooks.
+pub(crate) struct Handlers {
+    response: ResponseCallback,
+    modem_control: ModemControlCallback,
+    class1: Option<Class1Callback>,
+}
+
+impl Handlers {
+    pub(crate) fn new<R, M>(response: R, modem_control: M) -> Box<Self>
+    where
+        R: FnMut(&[u8]) + Send + 'static,
+        M: FnMut(ModemControl) -> bool + Send + 'static,
+    {
+        Box::new(Self {
+            response: Box::new(response),
+            modem_control: Box::new(modem_control),
+            class1: None,
+        })
+    }
+
+    pub(crate) const TX_TRAMPOLINE: spandsp_sys::at_tx_handler_t = Some(at_tx_trampoline);
+    pub(crate) const MODEM_CONTROL_TRAMPOLINE: spandsp_sys::at_modem_control_handler_t =
+        Some(at_modem_control_trampoline);
+
+    /// The user data to register with the trampolines.
+    pub(crate) fn user_data(self: &mut Box<Self>) -> *mut c_void {
+        &mut **self as *mut Self as *mut c_void
+    }
+}
+
+/// Trampoline for `at_tx_handler_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Handlers`.
+unsafe extern "C" fn at_tx_trampoline(user_data: *mut c_void, buf: *const u8, len: usize) -> c_int {
+    unsafe {
+        if user_data.is_null() || buf.is_null() || len == 0 {
+            return 0;
+        }
+        let handlers = &mut *(user_data as *mut Handlers);
+        (handlers.response)(std::slice::from_raw_parts(buf, len));
+        len.min(c_int::MAX as usize) as c_int
+    }
+}
+
+/// Trampoline for `at_modem_control_handler_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Handlers`.
+unsafe extern "C" fn at_modem_control_trampoline(
+    user_data: *mut c_void,
+    op: c_int,
+    num: *const c_char,
+) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return -1;
+        }
+        let handlers = &mut *(user_data as *mut Handlers);
+        match ModemControl::from_raw(op, num).map(&mut handlers.modem_control) {
+            Some(true) => 0,
+            _ => -1,
+        }
+    }
+}
+
+/// Trampoline for `at_class1_handler_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Handlers`.
+unsafe extern "C" fn at_class1_trampoline(
+    user_data: *mut c_void,
+    direction: c_int,
+    operation: c_int,
+    val: c_int,
+) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return -1;
+        }
+        let handlers = &mut *(user_data as *mut Handlers);
+        let kind = match u8::try_from(operation) {
+            Ok(b'H') => Class1Kind::Hdlc,
+            Ok(b'M') => Class1Kind::Modem,
+            Ok(b'S') => Class1Kind::Silence,
+            _ => return -1,
+        };
+        let Some(class1) = &mut handlers.class1 else {
+            return -1;
+        };
+        let command = Class1Command {
+            transmit: direction != 0,
+            kind,
+            value: val,
+        };
+        // spandsp sends OK for any other value, and holds back the result
+        // code for 0.
+        match class1(command) {
+            Class1Reply::Ok => 1,
+            Class1Reply::Error => -1,
+            Class1Reply::Later => 0,
+        }
+    }
+}
+
+/// RAII wrapper around `at_state_t`.
+///
+/// Created via `AtInterpreter::new()`. Freed on drop via `at_free`.
+pub struct AtInterpreter {
+    ptr: NonNull<spandsp_sys::at_state_t>,
+    handlers: Box<Handlers>,
+}
+
+impl AtInterpreter {
+    /// Create an interpreter, sending everything bound for the DTE to
+    /// `response` and each modem control request to `modem_control`, which
+    /// returns whether it was carried out.
+    pub fn new<R, M>(response: R, modem_control: M) -> Result<Self>
+    where
+        R: FnMut(&[u8]) + Send + 'static,
+        M: FnMut(ModemControl) -> bool + Send + 'static,
+    {
+        let mut handlers = Handlers::new(response, modem_control);
+        let user_data = handlers.user_data();
+        let ptr = unsafe {
+            spandsp_sys::at_init(
+                std::ptr::null_mut(),
+                Handlers::TX_TRAMPOLINE,
+                user_data,
+                Handlers::MODEM_CONTROL_TRAMPOLINE,
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr, handlers })
+    }
+
+    /// Take T.31 class 1 commands. Without a handler they answer `ERROR`.
+    pub fn set_class1_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(Class1Command) -> Class1Reply + Send + 'static,
+    {
+        self.handlers.class1 = Some(Box::new(handler));
+        let user_data = self.handlers.user_data();
+        unsafe {
+            spandsp_sys::at_set_class1_handler(
+                self.ptr.as_ptr(),
+                Some(at_class1_trampoline),
+                user_data,
+            );
+        }
+    }
+
+    /// Feed bytes from the DTE.
+    pub fn interpret(&mut self, cmd: &[u8]) {
+        let len = cmd.len().min(c_int::MAX as usize) as c_int;
+        unsafe {
+            spandsp_sys::at_interpreter(self.ptr.as_ptr(), cmd.as_ptr() as *const c_char, len);
+        }
+    }
+
+    /// Switch what the interpreter does with bytes from the DTE.
+    pub fn set_mode(&mut self, mode: AtMode) {
+        unsafe {
+            spandsp_sys::at_set_at_rx_mode(self.ptr.as_ptr(), mode as c_int);
+        }
+    }
+
+    /// Send a line of text to the DTE, framed as `ATV` selects.
+    pub fn put_response(&mut self, text: &str) -> Result<()> {
+        let c_text = CString::new(text)
+            .map_err(|_| SpanDspError::InvalidInput("response contains NUL byte".into()))?;
+        unsafe {
+            spandsp_sys::at_put_response(self.ptr.as_ptr(), c_text.as_ptr());
+        }
+        Ok(())
+    }
+
+    /// Send a number to the DTE, as for a query.
+    pub fn put_numeric_response(&mut self, value: i32) {
+        unsafe {
+            spandsp_sys::at_put_numeric_response(self.ptr.as_ptr(), value as c_int);
+        }
+    }
+
+    /// Send a result code to the DTE, such as the one a
+    /// [`Class1Reply::Later`] put off.
+    pub fn put_response_code(&mut self, code: AtResponseCode) {
+        unsafe {
+            spandsp_sys::at_put_response_code(self.ptr.as_ptr(), code as c_int);
+        }
+    }
+
+    /// Report call progress, which the interpreter turns into result codes
+    /// and mode changes.
+    pub fn call_event(&mut self, event: AtCallEvent) {
+        unsafe {
+            spandsp_sys::at_call_event(self.ptr.as_ptr(), event as c_int);
+        }
+    }
+
+    /// Set a caller ID field, such as `NMBR` or `NAME`, shown with the next
+    /// `RING` when `+VCID` is on.
+    pub fn set_call_info(&mut self, id: &str, value: &str) -> Result<()> {
+        let c_id = CString::new(id)
+            .map_err(|_| SpanDspError::InvalidInput("call info id contains NUL byte".into()))?;
+        let c_value = CString::new(value)
+            .map_err(|_| SpanDspError::InvalidInput("call info value contains NUL byte".into()))?;
+        unsafe {
+            spandsp_sys::at_set_call_info(self.ptr.as_ptr(), c_id.as_ptr(), c_value.as_ptr());
+        }
+        Ok(())
+    }
+
+    /// Clear the caller ID fields.
+    pub fn reset_call_info(&mut self) {
+        unsafe {
+            spandsp_sys::at_reset_call_info(self.ptr.as_ptr());
+        }
+    }
+
+    /// Send the caller ID fields to the DTE now.
+    pub fn display_call_info(&mut self) {
+        unsafe {
+            spandsp_sys::at_display_call_info(self.ptr.as_ptr());
+        }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::at_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for AtInterpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtInterpreter")
+            .field("has_class1_handler", &self.handlers.class1.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AtInterpreter {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::at_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: AtInterpreter wraps a SpanDSP at_state_t that is only accessed
+// through &self/&mut self methods, and its hooks are Send.
+unsafe impl Send for AtInterpreter {}
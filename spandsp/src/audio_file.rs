@@ -0,0 +1,323 @@
+//! Sun `.au` and headerless G.711 audio files.
+//!
+//! Telephony archives usually hold calls as `.au` files or as raw A-law or
+//! u-law bytes with no header at all. [`AuFile`] reads and writes the
+//! former; [`read_raw`] and [`write_raw`] handle the latter. Companded
+//! audio stays as G.711 codes, so it can go straight to
+//! [`G711State::decode`](crate::g711::G711State::decode) or anything else
+//! that takes A-law or u-law, and codes from
+//! [`G711State::encode`](crate::g711::G711State::encode) can be written
+//! without converting through linear.
+//!
+//! ```no_run
+//! use spandsp::audio_file::AuFile;
+//! use spandsp::g711::G711State;
+//!
+//! let au = AuFile::read("call.au").unwrap();
+//! let mode = au.g711_mode().expect("companded recording");
+//! let mut state = G711State::new(mode).unwrap();
+//! let mut amp = vec![0i16; au.data().len()];
+//! state.decode(&mut amp, au.data());
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+use crate::error::{Result, SpanDspError};
+use crate::g711::{G711Mode, alaw_to_linear, linear_to_alaw, linear_to_ulaw, ulaw_to_linear};
+
+/// The `.au` magic number.
+const AU_MAGIC: &[u8; 4] = b".snd";
+
+/// Fixed header length, before the annotation.
+const AU_HEADER_LEN: usize = 24;
+
+/// Data size meaning "to the end of the file".
+const AU_UNKNOWN_SIZE: u32 = u32::MAX;
+
+/// Sample rate of files created from G.711 codes or linear samples.
+const SAMPLE_RATE: u32 = 8000;
+
+/// Sample encoding of an `.au` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuEncoding {
+    /// 8-bit G.711 u-law (encoding 1).
+    ULaw,
+    /// 16-bit big-endian linear PCM (encoding 3).
+    Linear16,
+    /// 8-bit G.711 A-law (encoding 27).
+    ALaw,
+}
+
+impl AuEncoding {
+    /// The encoding number in the header.
+    pub fn code(self) -> u32 {
+        match self {
+            Self::ULaw => 1,
+            Self::Linear16 => 3,
+            Self::ALaw => 27,
+        }
+    }
+
+    /// The encoding with header number `code`, if supported.
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Self::ULaw),
+            3 => Some(Self::Linear16),
+            27 => Some(Self::ALaw),
+            _ => None,
+        }
+    }
+
+    /// Bytes per sample per channel.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::ULaw | Self::ALaw => 1,
+            Self::Linear16 => 2,
+        }
+    }
+
+    /// The G.711 law, for companded encodings.
+    pub fn g711_mode(self) -> Option<G711Mode> {
+        match self {
+            Self::ULaw => Some(G711Mode::ULaw),
+            Self::ALaw => Some(G711Mode::ALaw),
+            Self::Linear16 => None,
+        }
+    }
+}
+
+impl From<G711Mode> for AuEncoding {
+    fn from(mode: G711Mode) -> Self {
+        match mode {
+            G711Mode::ULaw => Self::ULaw,
+            G711Mode::ALaw => Self::ALaw,
+        }
+    }
+}
+
+impl fmt::Display for AuEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ULaw => f.write_str("u-law"),
+            Self::Linear16 => f.write_str("16-bit linear"),
+            Self::ALaw => f.write_str("A-law"),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// .au files
+// ---------------------------------------------------------------------------
+
+/// A Sun `.au` file held in memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuFile {
+    encoding: AuEncoding,
+    sample_rate: u32,
+    channels: u32,
+    annotation: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl AuFile {
+    /// An 8 kHz mono file of G.711 codes, e.g. from
+    /// [`G711State::encode`](crate::g711::G711State::encode).
+    pub fn from_g711(mode: G711Mode, codes: Vec<u8>) -> Self {
+        Self {
+            encoding: mode.into(),
+            sample_rate: SAMPLE_RATE,
+            channels: 1,
+            annotation: Vec::new(),
+            data: codes,
+        }
+    }
+
+    /// An 8 kHz mono file of `amp` in `encoding`, companding if need be.
+    pub fn from_linear(encoding: AuEncoding, amp: &[i16]) -> Self {
+        let data = match encoding {
+            AuEncoding::ULaw => amp.iter().map(|&s| linear_to_ulaw(s)).collect(),
+            AuEncoding::ALaw => amp.iter().map(|&s| linear_to_alaw(s)).collect(),
+            AuEncoding::Linear16 => amp.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        };
+        Self {
+            encoding,
+            sample_rate: SAMPLE_RATE,
+            channels: 1,
+            annotation: Vec::new(),
+            data,
+        }
+    }
+
+    /// Parse a whole `.au` file.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let invalid = |what: &str| SpanDspError::InvalidInput(format!("invalid .au file: {what}"));
+        if bytes.len() < AU_HEADER_LEN || &bytes[..4] != AU_MAGIC {
+            return Err(invalid("no .snd header"));
+        }
+        let word =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let offset = word(4) as usize;
+        let size = word(8);
+        let code = word(12);
+        let sample_rate = word(16);
+        let channels = word(20);
+        if offset < AU_HEADER_LEN || offset > bytes.len() {
+            return Err(invalid("data offset outside the file"));
+        }
+        let encoding = AuEncoding::from_code(code).ok_or_else(|| {
+            SpanDspError::InvalidInput(format!(
+                "unsupported .au encoding {code}: only u-law, A-law and 16-bit linear"
+            ))
+        })?;
+        if sample_rate == 0 || channels == 0 {
+            return Err(invalid("zero sample rate or channels"));
+        }
+        let body = &bytes[offset..];
+        let data = match size {
+            AU_UNKNOWN_SIZE => body,
+            // Truncated files are common; take what is there.
+            size => body.get(..size as usize).unwrap_or(body),
+        };
+        let frame = encoding.bytes_per_sample() * channels as usize;
+        let data = &data[..data.len() - data.len() % frame];
+        let annotation = &bytes[AU_HEADER_LEN..offset];
+        let text_len = annotation
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(annotation.len());
+        Ok(Self {
+            encoding,
+            sample_rate,
+            channels,
+            annotation: annotation[..text_len].to_vec(),
+            data: data.to_vec(),
+        })
+    }
+
+    /// Read and parse a `.au` file.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|err| {
+            SpanDspError::InvalidInput(format!("cannot read {}: {err}", path.display()))
+        })?;
+        Self::parse(&bytes)
+    }
+
+    /// The file as bytes, with the data size filled in.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // The annotation is NUL terminated and padded to a multiple of 4,
+        // with at least the 4 bytes the format requires.
+        let annotation_len = (self.annotation.len() + 4) & !3;
+        let offset = AU_HEADER_LEN + annotation_len;
+        let size = u32::try_from(self.data.len()).unwrap_or(AU_UNKNOWN_SIZE);
+        let mut bytes = Vec::with_capacity(offset + self.data.len());
+        bytes.extend_from_slice(AU_MAGIC);
+        for word in [
+            offset as u32,
+            size,
+            self.encoding.code(),
+            self.sample_rate,
+            self.channels,
+        ] {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.annotation);
+        bytes.resize(offset, 0);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Write the file to `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()).map_err(|err| {
+            SpanDspError::InvalidInput(format!("cannot write {}: {err}", path.display()))
+        })
+    }
+
+    /// The sample encoding.
+    pub fn encoding(&self) -> AuEncoding {
+        self.encoding
+    }
+
+    /// The G.711 law, if the file is companded.
+    pub fn g711_mode(&self) -> Option<G711Mode> {
+        self.encoding.g711_mode()
+    }
+
+    /// Samples per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Interleaved channels.
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// The annotation, e.g. a description of the recording.
+    pub fn annotation(&self) -> &[u8] {
+        &self.annotation
+    }
+
+    /// Set the annotation. A NUL ends it when read back.
+    pub fn set_annotation(&mut self, annotation: impl Into<Vec<u8>>) {
+        self.annotation = annotation.into();
+    }
+
+    /// The sample data as stored: G.711 codes, or big-endian 16-bit PCM.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The samples as linear PCM, channels interleaved.
+    pub fn samples(&self) -> Vec<i16> {
+        match self.encoding {
+            AuEncoding::ULaw => self.data.iter().map(|&b| ulaw_to_linear(b)).collect(),
+            AuEncoding::ALaw => self.data.iter().map(|&b| alaw_to_linear(b)).collect(),
+            AuEncoding::Linear16 => self
+                .data
+                .chunks_exact(2)
+                .map(|s| i16::from_be_bytes([s[0], s[1]]))
+                .collect(),
+        }
+    }
+
+    /// Duration of the recording, in samples per channel.
+    pub fn len_samples(&self) -> usize {
+        self.data.len() / (self.encoding.bytes_per_sample() * self.channels as usize)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Raw files
+// ---------------------------------------------------------------------------
+
+/// Read a headerless G.711 file of `mode` codes as linear PCM.
+///
+/// The codes themselves are just the file's bytes; use [`std::fs::read`]
+/// to keep them companded.
+pub fn read_raw(path: impl AsRef<Path>, mode: G711Mode) -> Result<Vec<i16>> {
+    let path = path.as_ref();
+    let codes = std::fs::read(path).map_err(|err| {
+        SpanDspError::InvalidInput(format!("cannot read {}: {err}", path.display()))
+    })?;
+    Ok(match mode {
+        G711Mode::ULaw => codes.iter().map(|&b| ulaw_to_linear(b)).collect(),
+        G711Mode::ALaw => codes.iter().map(|&b| alaw_to_linear(b)).collect(),
+    })
+}
+
+/// Write linear PCM to a headerless G.711 file of `mode` codes.
+pub fn write_raw(path: impl AsRef<Path>, mode: G711Mode, amp: &[i16]) -> Result<()> {
+    let path = path.as_ref();
+    let codes: Vec<u8> = match mode {
+        G711Mode::ULaw => amp.iter().map(|&s| linear_to_ulaw(s)).collect(),
+        G711Mode::ALaw => amp.iter().map(|&s| linear_to_alaw(s)).collect(),
+    };
+    std::fs::write(path, codes).map_err(|err| {
+        SpanDspError::InvalidInput(format!("cannot write {}: {err}", path.display()))
+    })
+}
@@ -0,0 +1,240 @@
+//! Lock-free sample rings for exchanging audio with a real-time thread.
+//!
+//! [`sample_ring`] creates a single-producer, single-consumer ring of `i16`
+//! samples. [`AudioStream`] builds on it: the engine side owns a [`Clocked`]
+//! component such as a `FaxState` or `T38Gateway` and runs it from any
+//! thread, while the [`AudioPort`] side moves to the audio thread and only
+//! copies samples in and out, never allocating or calling into spandsp.
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Result, SpanDspError};
+use crate::media_clock::Clocked;
+
+struct Ring {
+    buf: Box<[UnsafeCell<i16>]>,
+    /// Total samples ever written; only the producer stores to it.
+    head: AtomicUsize,
+    /// Total samples ever read; only the consumer stores to it.
+    tail: AtomicUsize,
+}
+
+// The producer only writes slots between `tail` and `head + free`, and the
+// consumer only reads slots between `tail` and `head`, so no slot is
+// accessed from both sides at once.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        head.wrapping_sub(tail)
+    }
+}
+
+/// Create a ring holding up to `capacity` samples.
+pub fn sample_ring(capacity: usize) -> (SampleProducer, SampleConsumer) {
+    let ring = Arc::new(Ring {
+        buf: (0..capacity).map(|_| UnsafeCell::new(0)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        SampleProducer { ring: ring.clone() },
+        SampleConsumer { ring },
+    )
+}
+
+/// Writing end of a [`sample_ring`].
+pub struct SampleProducer {
+    ring: Arc<Ring>,
+}
+
+impl SampleProducer {
+    /// Free space, in samples.
+    pub fn free(&self) -> usize {
+        self.ring.capacity() - self.ring.len()
+    }
+
+    /// Append as many of `samples` as fit. Returns the number written.
+    pub fn push(&mut self, samples: &[i16]) -> usize {
+        let ring = &*self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let n = samples.len().min(self.free());
+        for (i, &sample) in samples[..n].iter().enumerate() {
+            let slot = &ring.buf[head.wrapping_add(i) % ring.capacity()];
+            unsafe { *slot.get() = sample };
+        }
+        ring.head.store(head.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
+
+/// Reading end of a [`sample_ring`].
+pub struct SampleConsumer {
+    ring: Arc<Ring>,
+}
+
+impl SampleConsumer {
+    /// Samples waiting to be read.
+    pub fn available(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Read up to `out.len()` samples. Returns the number read.
+    pub fn pop(&mut self, out: &mut [i16]) -> usize {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let n = out.len().min(self.available());
+        for (i, sample) in out[..n].iter_mut().enumerate() {
+            let slot = &ring.buf[tail.wrapping_add(i) % ring.capacity()];
+            *sample = unsafe { *slot.get() };
+        }
+        ring.tail.store(tail.wrapping_add(n), Ordering::Release);
+        n
+    }
+}
+
+impl fmt::Debug for SampleProducer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SampleProducer")
+            .field("free", &self.free())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for SampleConsumer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SampleConsumer")
+            .field("available", &self.available())
+            .finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// AudioStream
+// ---------------------------------------------------------------------------
+
+/// The audio-thread side of an [`AudioStream`].
+#[derive(Debug)]
+pub struct AudioPort {
+    to_engine: SampleProducer,
+    from_engine: SampleConsumer,
+}
+
+impl AudioPort {
+    /// Queue received (line-side) audio for the engine. Returns the number of
+    /// samples accepted; the rest are dropped because the engine is behind.
+    pub fn write(&mut self, samples: &[i16]) -> usize {
+        self.to_engine.push(samples)
+    }
+
+    /// Take audio the engine has generated for transmission. Returns the
+    /// number of samples copied into `out`.
+    pub fn read(&mut self, out: &mut [i16]) -> usize {
+        self.from_engine.pop(out)
+    }
+
+    /// Samples ready to [`read`](Self::read).
+    pub fn available(&self) -> usize {
+        self.from_engine.available()
+    }
+}
+
+/// Runs a [`Clocked`] engine on audio exchanged through sample rings.
+pub struct AudioStream<C> {
+    engine: C,
+    from_port: SampleConsumer,
+    to_port: SampleProducer,
+    input: Vec<i16>,
+    output: Vec<i16>,
+}
+
+/// An [`AudioStream`] driving a FAX terminal.
+#[cfg(feature = "fax")]
+pub type FaxStream = AudioStream<crate::fax::FaxState>;
+
+/// An [`AudioStream`] driving the audio side of a T.38 gateway.
+#[cfg(feature = "fax")]
+pub type T38GatewayStream = AudioStream<crate::t38_gateway::T38Gateway>;
+
+impl<C: Clocked> AudioStream<C> {
+    /// Wrap `engine`, with rings in each direction holding `frames` frames
+    /// of `frame_samples` samples.
+    pub fn new(engine: C, frame_samples: usize, frames: usize) -> Result<(Self, AudioPort)> {
+        if frame_samples == 0 || frames == 0 {
+            return Err(SpanDspError::InvalidInput(
+                "audio stream needs a non-zero frame size and depth".into(),
+            ));
+        }
+        let capacity = frame_samples
+            .checked_mul(frames)
+            .ok_or_else(|| SpanDspError::InvalidInput("audio ring too large".into()))?;
+        let (to_engine, from_port) = sample_ring(capacity);
+        let (to_port, from_engine) = sample_ring(capacity);
+        let stream = Self {
+            engine,
+            from_port,
+            to_port,
+            input: vec![0; frame_samples],
+            output: vec![0; frame_samples],
+        };
+        let port = AudioPort {
+            to_engine,
+            from_engine,
+        };
+        Ok((stream, port))
+    }
+
+    /// Samples per frame.
+    pub fn frame_samples(&self) -> usize {
+        self.input.len()
+    }
+
+    /// The wrapped engine.
+    pub fn engine(&self) -> &C {
+        &self.engine
+    }
+
+    /// The wrapped engine, e.g. to configure its T.30 session.
+    pub fn engine_mut(&mut self) -> &mut C {
+        &mut self.engine
+    }
+
+    /// Unwrap the engine, dropping the rings.
+    pub fn into_engine(self) -> C {
+        self.engine
+    }
+
+    /// Run the engine over every complete frame received from the port that
+    /// there is room to answer. Returns the number of frames processed.
+    pub fn process(&mut self) -> usize {
+        let frame = self.input.len();
+        let mut frames = 0;
+        while self.from_port.available() >= frame && self.to_port.free() >= frame {
+            self.from_port.pop(&mut self.input);
+            self.output.fill(0);
+            self.engine.advance(&mut self.input, &mut self.output);
+            self.to_port.push(&self.output);
+            frames += 1;
+        }
+        frames
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for AudioStream<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioStream")
+            .field("engine", &self.engine)
+            .field("frame_samples", &self.input.len())
+            .field("pending_input", &self.from_port.available())
+            .finish_non_exhaustive()
+    }
+}
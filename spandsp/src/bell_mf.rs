@@ -0,0 +1,216 @@
+//! Safe wrappers around spandsp's Bell MF tone generation and detection.
+//!
+//! Bell MF (R1) sends address digits as pairs out of six tones from 700 to
+//! 1700 Hz, for North American inter-office signalling; see
+//! [`mf_r1`](crate::mf_r1) for framing them with KP and ST.
+//!
+//! - `BellMfTx` wraps `bell_mf_tx_state_t`.
+//! - `BellMfRx` wraps `bell_mf_rx_state_t`.
+//!
+//! Both follow the shape of the DTMF wrappers: `put` and `generate` to
+//! send, `rx` and either `get` or a digit callback to receive.
+
+extern crate spandsp_sys;
+
+use std::ffi::{CString, c_void};
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// Longest digit string fetched from a Bell MF detector at once.
+const BELL_MF_RX_MAX: usize = 128;
+
+// ---------------------------------------------------------------------------
+// BellMfTx
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `bell_mf_tx_state_t`.
+///
+/// Created via `BellMfTx::new()`, which calls `bell_mf_tx_init(NULL)`.
+/// Freed on drop via `bell_mf_tx_free`.
+pub struct BellMfTx {
+    ptr: NonNull<spandsp_sys::bell_mf_tx_state_t>,
+}
+
+impl BellMfTx {
+    /// Create a new Bell MF transmitter.
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { spandsp_sys::bell_mf_tx_init(std::ptr::null_mut()) };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr })
+    }
+
+    /// Queue digits for transmission.
+    ///
+    /// Valid digits: `0`-`9`, `*` (KP), `#` (ST), and `A`, `B`, `C` (ST',
+    /// ST'', ST'''). Returns the number of digits that did not fit in the
+    /// queue.
+    pub fn put(&mut self, digits: &str) -> Result<usize> {
+        let c_digits = CString::new(digits)
+            .map_err(|_| SpanDspError::InvalidInput("digits contain NUL byte".into()))?;
+        let n = unsafe { spandsp_sys::bell_mf_tx_put(self.ptr.as_ptr(), c_digits.as_ptr(), -1) };
+        if n < 0 {
+            return Err(SpanDspError::ErrorCode(n));
+        }
+        Ok(n as usize)
+    }
+
+    /// Generate audio for the queued digits.
+    ///
+    /// Returns the number of samples generated; fewer than `amp.len()` once
+    /// the queue runs out.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::bell_mf_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), len).max(0) as usize }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::bell_mf_tx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for BellMfTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BellMfTx").finish_non_exhaustive()
+    }
+}
+
+impl Drop for BellMfTx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::bell_mf_tx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: BellMfTx wraps a SpanDSP bell_mf_tx_state_t that is only accessed
+// through &self/&mut self methods.
+unsafe impl Send for BellMfTx {}
+
+// ---------------------------------------------------------------------------
+// BellMfRx
+// ---------------------------------------------------------------------------
+
+type BellMfCallback = Box<dyn FnMut(&str) + Send>;
+
+/// Trampoline for the digit-received callback.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `BellMfCallback`.
+unsafe extern "C" fn bell_mf_rx_callback_trampoline(
+    user_data: *mut c_void,
+    digits: *const c_char,
+    len: c_int,
+) {
+    unsafe {
+        if user_data.is_null() || digits.is_null() || len <= 0 {
+            return;
+        }
+        let closure = &mut *(user_data as *mut BellMfCallback);
+        let slice = std::slice::from_raw_parts(digits as *const u8, len as usize);
+        if let Ok(s) = std::str::from_utf8(slice) {
+            closure(s);
+        }
+    }
+}
+
+/// RAII wrapper around `bell_mf_rx_state_t`.
+///
+/// Created via `BellMfRx::new()` or `BellMfRx::with_callback()`. Freed on
+/// drop via `bell_mf_rx_free`.
+pub struct BellMfRx {
+    ptr: NonNull<spandsp_sys::bell_mf_rx_state_t>,
+    _callback: Option<Box<BellMfCallback>>,
+}
+
+impl BellMfRx {
+    /// Create a new Bell MF receiver. Detected digits are collected for
+    /// [`get`](Self::get).
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe {
+            spandsp_sys::bell_mf_rx_init(std::ptr::null_mut(), None, std::ptr::null_mut())
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            _callback: None,
+        })
+    }
+
+    /// Create a new Bell MF receiver with a callback invoked each time one
+    /// or more digits are detected, instead of collecting them for
+    /// [`get`](Self::get).
+    pub fn with_callback<F>(callback: F) -> Result<Self>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        let boxed: Box<BellMfCallback> = Box::new(Box::new(callback));
+        let user_data = &*boxed as *const BellMfCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::bell_mf_rx_init(
+                std::ptr::null_mut(),
+                Some(bell_mf_rx_callback_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            _callback: Some(boxed),
+        })
+    }
+
+    /// Feed audio to the detector.
+    ///
+    /// Returns the number of unprocessed samples (normally 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::bell_mf_rx(self.ptr.as_ptr(), amp.as_ptr(), len).max(0) as usize }
+    }
+
+    /// Take the digits detected since the last call, KP as `*` and the ST
+    /// codes as `#`, `A`, `B` and `C`.
+    pub fn get(&mut self) -> String {
+        let mut buf = [0 as c_char; BELL_MF_RX_MAX + 1];
+        let n = unsafe {
+            spandsp_sys::bell_mf_rx_get(
+                self.ptr.as_ptr(),
+                buf.as_mut_ptr(),
+                BELL_MF_RX_MAX as c_int,
+            )
+        };
+        buf[..n.min(BELL_MF_RX_MAX)]
+            .iter()
+            .map(|&c| char::from(c as u8))
+            .collect()
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::bell_mf_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for BellMfRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BellMfRx")
+            .field("has_callback", &self._callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for BellMfRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::bell_mf_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: BellMfRx wraps a SpanDSP bell_mf_rx_state_t that is only accessed
+// through &self/&mut self methods, and its callback is Send.
+unsafe impl Send for BellMfRx {}
@@ -0,0 +1,116 @@
+//! Bit-serial transmit sources as iterators.
+//!
+//! The HDLC and async serial transmitters and the T.4/T.6 and T.85 encoders hand
+//! out their output a bit at a time through `get_bit()`, which returns 0
+//! or 1 and a negative status (spandsp's `SIG_STATUS_END_OF_DATA`) once
+//! there is nothing more to send. [`BitSource`] wraps that in
+//! `Option<bool>`, and [`bits`](BitSource::bits) turns it into an iterator
+//! that ends with the data, so a source can be chained, counted or fed into
+//! a receiver without a sentinel check.
+//!
+//! ```no_run
+//! use spandsp::bit_source::BitSource;
+//! use spandsp::hdlc::HdlcTx;
+//!
+//! let mut tx = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+//! tx.frame(b"hello").unwrap();
+//! tx.frame(&[]).unwrap(); // end once the frame has gone
+//! let line: Vec<bool> = tx.bits().collect();
+//! ```
+
+use std::iter::FusedIterator;
+
+/// A transmitter whose output can be taken a bit at a time.
+pub trait BitSource {
+    /// The next bit to send, or `None` at the end of the data.
+    fn next_bit(&mut self) -> Option<bool>;
+
+    /// Iterate over the bits until the end of the data.
+    fn bits(&mut self) -> Bits<'_, Self>
+    where
+        Self: Sized,
+    {
+        Bits {
+            source: self,
+            done: false,
+        }
+    }
+}
+
+/// Map a `get_bit()` return to a bit, or `None` for an end status.
+fn from_status(bit: i32) -> Option<bool> {
+    (bit >= 0).then_some(bit != 0)
+}
+
+/// Iterator over the bits of a [`BitSource`], from
+/// [`BitSource::bits`].
+///
+/// It ends at the first end of data, even if the source would go on to
+/// send more (e.g. an HDLC transmitter given another frame); call
+/// [`bits`](BitSource::bits) again to continue.
+#[derive(Debug)]
+pub struct Bits<'a, S> {
+    source: &'a mut S,
+    done: bool,
+}
+
+impl<S: BitSource> Iterator for Bits<'_, S> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.done {
+            return None;
+        }
+        let bit = self.source.next_bit();
+        self.done = bit.is_none();
+        bit
+    }
+}
+
+impl<S: BitSource> FusedIterator for Bits<'_, S> {}
+
+impl BitSource for crate::hdlc::HdlcTx {
+    /// Flags are sent while idle; the bits end once everything queued
+    /// before an empty [`frame`](crate::hdlc::HdlcTx::frame) has gone.
+    fn next_bit(&mut self) -> Option<bool> {
+        from_status(self.get_bit())
+    }
+}
+
+impl BitSource for crate::async_serial::AsyncTx {
+    /// The bits end with the bytes.
+    fn next_bit(&mut self) -> Option<bool> {
+        from_status(self.get_bit())
+    }
+}
+
+#[cfg(feature = "pure-hdlc")]
+impl BitSource for crate::hdlc_pure::HdlcTx {
+    fn next_bit(&mut self) -> Option<bool> {
+        from_status(self.get_bit())
+    }
+}
+
+#[cfg(feature = "fax")]
+impl BitSource for crate::t4_tx::T4Tx {
+    /// The bits end with the document.
+    fn next_bit(&mut self) -> Option<bool> {
+        from_status(self.get_bit())
+    }
+}
+
+#[cfg(feature = "fax")]
+impl BitSource for crate::t4_tx::T4T6Encoder {
+    /// The bits end with the image.
+    fn next_bit(&mut self) -> Option<bool> {
+        from_status(self.get_bit())
+    }
+}
+
+#[cfg(feature = "fax")]
+impl BitSource for crate::t85::T85Encoder {
+    /// The bits end with the image.
+    fn next_bit(&mut self) -> Option<bool> {
+        from_status(self.get_bit())
+    }
+}
@@ -0,0 +1,250 @@
+//! A per-call media chain.
+//!
+//! Most calls run the same components in the same order: line audio is
+//! DC-restored, echo-cancelled against what was just played to the line,
+//! checked for DTMF, metered, and encoded for the network; network audio
+//! is decoded, metered, and played to the line, where it becomes the echo
+//! reference. [`ChannelEngine`] wires that chain up once, so an
+//! application feeds it a frame each way per tick and reads events and
+//! stats from one place.
+//!
+//! ```no_run
+//! use spandsp::channel::{ChannelConfig, ChannelEngine, ChannelEvent};
+//!
+//! let mut engine = ChannelEngine::new(ChannelConfig::default()).unwrap();
+//! let mut to_line = Vec::new();
+//! let mut to_network = Vec::new();
+//! engine.process_tx(&[0xFF; 160], &mut to_line);
+//! engine.process_rx(&[0i16; 160], &mut to_network);
+//! for event in engine.take_events() {
+//!     let ChannelEvent::Dtmf { digit, .. } = event;
+//!     println!("digit {digit}");
+//! }
+//! println!("{:?}", engine.stats());
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::dtmf::DtmfRx;
+use crate::echo::{EchoCanFlags, EchoCanceller};
+use crate::error::{Result, SpanDspError};
+use crate::g711::G711Mode;
+use crate::power_meter::{DcRestore, LevelDiagnostics, LevelMeter};
+use crate::transcode::{CodecSpec, Decoder, Encoder};
+
+/// Most echo reference held waiting for line audio: one second. Beyond
+/// that the two directions have drifted apart and the oldest is dropped.
+const MAX_REFERENCE: usize = 8000;
+
+/// Most digits taken from the detector per frame.
+const MAX_DIGITS_PER_FRAME: usize = 16;
+
+/// What a [`ChannelEngine`] runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelConfig {
+    /// The codec negotiated for the network side. Must be an 8 kHz codec.
+    pub codec: CodecSpec,
+    /// Echo canceller tail in samples, or `None` for no echo cancellation.
+    pub echo_tail: Option<i32>,
+    /// Echo canceller mode.
+    pub echo_flags: EchoCanFlags,
+    /// Remove DC offset from line audio before anything else sees it.
+    pub dc_restore: bool,
+    /// Detect DTMF in the echo-cancelled line audio.
+    pub dtmf: bool,
+}
+
+impl Default for ChannelConfig {
+    /// G.711 u-law, a 256 sample (32 ms) echo tail with adaption and NLP,
+    /// DC restoration and DTMF detection.
+    fn default() -> Self {
+        Self {
+            codec: CodecSpec::G711(G711Mode::ULaw),
+            echo_tail: Some(256),
+            echo_flags: EchoCanFlags::default(),
+            dc_restore: true,
+            dtmf: true,
+        }
+    }
+}
+
+/// Something a [`ChannelEngine`] noticed in the line audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelEvent {
+    /// A DTMF digit was detected.
+    Dtmf {
+        /// The digit.
+        digit: char,
+        /// Line samples received up to the end of the frame it was
+        /// reported in.
+        sample: u64,
+    },
+}
+
+/// Counters and levels for a [`ChannelEngine`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelStats {
+    /// Line audio in, as received.
+    pub rx: LevelDiagnostics,
+    /// Line audio out, as decoded from the network.
+    pub tx: LevelDiagnostics,
+    /// DTMF digits detected.
+    pub dtmf_digits: u64,
+    /// Echo reference samples dropped because line audio fell behind.
+    pub reference_dropped: u64,
+}
+
+/// Echo cancellation, DC restore, DTMF detection, level metering and a
+/// codec for one call.
+pub struct ChannelEngine {
+    config: ChannelConfig,
+    decoder: Decoder,
+    encoder: Encoder,
+    echo: Option<EchoCanceller>,
+    dc: Option<DcRestore>,
+    dtmf: Option<DtmfRx>,
+    rx_meter: LevelMeter,
+    tx_meter: LevelMeter,
+    /// Samples played to the line and not yet matched with line audio.
+    reference: VecDeque<i16>,
+    reference_dropped: u64,
+    /// Cleaned line audio waiting for a whole encoder unit.
+    pending: Vec<i16>,
+    events: Vec<ChannelEvent>,
+    rx_samples: u64,
+    dtmf_digits: u64,
+}
+
+impl ChannelEngine {
+    /// Build the chain `config` describes.
+    pub fn new(config: ChannelConfig) -> Result<Self> {
+        if config.codec.sample_rate() != 8000 {
+            return Err(SpanDspError::InvalidInput(format!(
+                "{}: the channel engine runs at 8000 Hz",
+                config.codec
+            )));
+        }
+        Ok(Self {
+            config,
+            decoder: Decoder::new(config.codec)?,
+            encoder: Encoder::new(config.codec)?,
+            echo: config
+                .echo_tail
+                .map(|tail| EchoCanceller::new(tail, config.echo_flags))
+                .transpose()?,
+            dc: config.dc_restore.then(DcRestore::new),
+            dtmf: config.dtmf.then(DtmfRx::new).transpose()?,
+            rx_meter: LevelMeter::new()?,
+            tx_meter: LevelMeter::new()?,
+            reference: VecDeque::new(),
+            reference_dropped: 0,
+            pending: Vec::new(),
+            events: Vec::new(),
+            rx_samples: 0,
+            dtmf_digits: 0,
+        })
+    }
+
+    /// The configuration the chain was built from.
+    pub fn config(&self) -> &ChannelConfig {
+        &self.config
+    }
+
+    /// Process a frame of line audio, appending its encoding to `payload`.
+    ///
+    /// Returns the number of bytes appended. Packed codecs hold samples
+    /// back until they make whole bytes.
+    pub fn process_rx(&mut self, amp: &[i16], payload: &mut Vec<u8>) -> usize {
+        self.rx_meter.update(amp);
+        let start = self.pending.len();
+        for &sample in amp {
+            let mut clean = sample;
+            if let Some(dc) = &mut self.dc {
+                clean = dc.restore(clean);
+            }
+            let reference = self.reference.pop_front().unwrap_or(0);
+            if let Some(echo) = &mut self.echo {
+                clean = echo.update(reference, clean);
+            }
+            self.pending.push(clean);
+        }
+        self.rx_samples += amp.len() as u64;
+
+        if let Some(dtmf) = &mut self.dtmf {
+            dtmf.rx(&self.pending[start..]);
+            for digit in dtmf.get(MAX_DIGITS_PER_FRAME).chars() {
+                self.dtmf_digits += 1;
+                self.events.push(ChannelEvent::Dtmf {
+                    digit,
+                    sample: self.rx_samples,
+                });
+            }
+        }
+
+        let unit = self.config.codec.samples_per_unit();
+        let samples = self.pending.len() - self.pending.len() % unit;
+        let before = payload.len();
+        self.encoder.encode(
+            &self.pending[..samples],
+            self.config.codec.bytes_for(samples),
+            payload,
+        );
+        self.pending.drain(..samples);
+        payload.len() - before
+    }
+
+    /// Decode a network payload, appending the audio for the line to `amp`
+    /// and keeping it as the echo reference.
+    ///
+    /// Returns the number of samples appended.
+    pub fn process_tx(&mut self, payload: &[u8], amp: &mut Vec<i16>) -> usize {
+        let start = amp.len();
+        self.decoder.decode(payload, amp);
+        let decoded = &amp[start..];
+        self.tx_meter.update(decoded);
+        if self.echo.is_some() {
+            self.reference.extend(decoded);
+            let excess = self.reference.len().saturating_sub(MAX_REFERENCE);
+            self.reference.drain(..excess);
+            self.reference_dropped += excess as u64;
+        }
+        decoded.len()
+    }
+
+    /// Events since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<ChannelEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Current levels and counters.
+    pub fn stats(&self) -> ChannelStats {
+        ChannelStats {
+            rx: self.rx_meter.diagnostics(),
+            tx: self.tx_meter.diagnostics(),
+            dtmf_digits: self.dtmf_digits,
+            reference_dropped: self.reference_dropped,
+        }
+    }
+
+    /// The echo canceller, if enabled.
+    pub fn echo_canceller_mut(&mut self) -> Option<&mut EchoCanceller> {
+        self.echo.as_mut()
+    }
+
+    /// The DTMF detector, if enabled, e.g. to tune it.
+    pub fn dtmf_rx_mut(&mut self) -> Option<&mut DtmfRx> {
+        self.dtmf.as_mut()
+    }
+}
+
+impl fmt::Debug for ChannelEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelEngine")
+            .field("config", &self.config)
+            .field("stats", &self.stats())
+            .finish_non_exhaustive()
+    }
+}
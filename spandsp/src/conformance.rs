@@ -0,0 +1,460 @@
+//! Conformance checks against the ITU codec test sequences.
+//!
+//! Enabled by the `conformance` feature. The ITU publishes digital test
+//! sequences alongside its codec recommendations; running the linked
+//! spandsp through them confirms the build is bit-exact, which a
+//! miscompiled or patched library may not be. The sequences are not
+//! bundled: point these functions at a directory holding the files as
+//! distributed.
+//!
+//! ```no_run
+//! let report = spandsp::conformance::g722("itu/g722").unwrap();
+//! println!("{report}");
+//! assert!(report.passed());
+//! assert!(spandsp::conformance::g726("itu/g726").unwrap().passed());
+//! ```
+//!
+//! With the `pure-g726` feature, [`g726_pure`] runs the pure-Rust G.726
+//! codec through the same sequences.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SpanDspError};
+use crate::g722::{G722Decoder, G722Encoder, G722Options, G722Rate};
+use crate::g726::{G726Encoding, G726Packing, G726Rate};
+
+/// Most words the ITU parser reads from one line, as in spandsp's tests.
+const WORDS_PER_LINE: usize = 16;
+
+// ---------------------------------------------------------------------------
+// Report
+// ---------------------------------------------------------------------------
+
+/// Outcome of one test sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    /// Which files were run, e.g. `T1C1.XMT -> T2R1.COD`.
+    pub name: String,
+    /// Number of values compared against the reference.
+    pub compared: usize,
+    /// Number of values that differed.
+    pub mismatches: usize,
+    /// Index of the first differing value.
+    pub first_mismatch: Option<usize>,
+}
+
+impl CaseResult {
+    /// Compare output with the reference. Values missing from either side
+    /// count as mismatches.
+    fn compare(name: String, actual: &[u16], expected: &[u16]) -> Self {
+        let compared = actual.len().max(expected.len());
+        let differs = |i: usize| actual.get(i) != expected.get(i);
+        Self {
+            name,
+            compared,
+            mismatches: (0..compared).filter(|&i| differs(i)).count(),
+            first_mismatch: (0..compared).find(|&i| differs(i)),
+        }
+    }
+
+    /// `true` if something was compared and all of it matched.
+    pub fn passed(&self) -> bool {
+        self.compared > 0 && self.mismatches == 0
+    }
+}
+
+impl fmt::Display for CaseResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            write!(f, "PASS {} ({} values)", self.name, self.compared)
+        } else {
+            write!(
+                f,
+                "FAIL {} ({} of {} values differ",
+                self.name, self.mismatches, self.compared
+            )?;
+            if let Some(first) = self.first_mismatch {
+                write!(f, ", first at {first}")?;
+            }
+            f.write_str(")")
+        }
+    }
+}
+
+/// Results of running one codec through its test sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceReport {
+    codec: &'static str,
+    cases: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    /// The codec tested, e.g. `"G.722"`.
+    pub fn codec(&self) -> &'static str {
+        self.codec
+    }
+
+    /// Every case run, in order.
+    pub fn cases(&self) -> &[CaseResult] {
+        &self.cases
+    }
+
+    /// The cases that did not pass.
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases.iter().filter(|c| !c.passed())
+    }
+
+    /// `true` if every case passed.
+    pub fn passed(&self) -> bool {
+        !self.cases.is_empty() && self.cases.iter().all(CaseResult::passed)
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let passed = self.cases.iter().filter(|c| c.passed()).count();
+        writeln!(
+            f,
+            "{}: {passed}/{} cases passed",
+            self.codec,
+            self.cases.len()
+        )?;
+        for case in &self.cases {
+            writeln!(f, "  {case}")?;
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Test sequence files
+// ---------------------------------------------------------------------------
+
+/// Find `name` in `dir`, also trying it in lower case.
+fn vector_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if path.exists() {
+        return path;
+    }
+    let lower = dir.join(name.to_ascii_lowercase());
+    if lower.exists() { lower } else { path }
+}
+
+fn read_vector_file(dir: &Path, name: &str) -> Result<Vec<u8>> {
+    let path = vector_path(dir, name);
+    fs::read(&path).map_err(|e| {
+        SpanDspError::InvalidInput(format!("cannot read test sequence {}: {e}", path.display()))
+    })
+}
+
+/// Parse an ITU hex test sequence: lines of up to 16 four-digit hex words,
+/// with `/*` comment lines.
+fn parse_hex_words(name: &str, text: &str) -> Result<Vec<u16>> {
+    let mut words = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("/*") {
+            continue;
+        }
+        for token in line.split_whitespace().take(WORDS_PER_LINE) {
+            let word = u16::from_str_radix(token, 16).map_err(|_| {
+                SpanDspError::InvalidInput(format!("{name}: bad test sequence word {token:?}"))
+            })?;
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+fn read_hex_words(dir: &Path, name: &str) -> Result<Vec<u16>> {
+    parse_hex_words(
+        name,
+        &String::from_utf8_lossy(&read_vector_file(dir, name)?),
+    )
+}
+
+/// The span of a G.722 sequence between its leading and trailing reset
+/// words, which are marked by a set LSB.
+fn g722_active_span(words: &[u16]) -> (usize, usize) {
+    let start = words.iter().position(|w| w & 1 == 0).unwrap_or(words.len());
+    let end = words[start..]
+        .iter()
+        .position(|w| w & 1 != 0)
+        .map_or(words.len(), |n| start + n);
+    (start, end)
+}
+
+// ---------------------------------------------------------------------------
+// G.722
+// ---------------------------------------------------------------------------
+
+/// Encoder input and the expected 64 kbit/s codewords.
+const G722_ENCODE_CASES: [(&str, &str); 2] = [("T1C1.XMT", "T2R1.COD"), ("T1C2.XMT", "T2R2.COD")];
+
+/// Decoder input, expected low band output for modes 1 to 3, and expected
+/// high band output.
+const G722_DECODE_CASES: [(&str, [&str; 3], &str); 3] = [
+    ("T2R1.COD", ["T3L1.RC1", "T3L1.RC2", "T3L1.RC3"], "T3H1.RC0"),
+    ("T2R2.COD", ["T3L2.RC1", "T3L2.RC2", "T3L2.RC3"], "T3H2.RC0"),
+    ("T1D3.COD", ["T3L3.RC1", "T3L3.RC2", "T3L3.RC3"], "T3H3.RC0"),
+];
+
+/// Run the G.722 encoder and decoder through the ITU test sequences in
+/// `dir`.
+///
+/// The codec runs in the ITU test mode, which bypasses the QMF band split as
+/// the sequences require. Every file listed in G.722 appendix II must be
+/// present; a missing or malformed file is an `InvalidInput` error.
+pub fn g722(dir: impl AsRef<Path>) -> Result<ConformanceReport> {
+    let dir = dir.as_ref();
+    let mut cases = Vec::new();
+
+    for (input_name, ref_name) in G722_ENCODE_CASES {
+        let input = read_hex_words(dir, input_name)?;
+        let reference = read_hex_words(dir, ref_name)?;
+        let (start, end) = g722_active_span(&input);
+        let amp: Vec<i16> = input[start..end].iter().map(|&w| w as i16).collect();
+
+        let mut encoder = G722Encoder::new(G722Rate::Rate64000, G722Options::empty())?;
+        unsafe {
+            spandsp_sys::spandsp_rs_g722_encode_set_itu_test_mode(encoder.as_ptr(), 1);
+        }
+        let mut codes = vec![0u8; amp.len()];
+        let n = encoder.encode(&mut codes, &amp);
+        let actual: Vec<u16> = codes[..n].iter().map(|&c| c as u16).collect();
+        let expected: Vec<u16> = reference
+            .iter()
+            .skip(start)
+            .take(end - start)
+            .map(|&w| (w >> 8) & 0xFF)
+            .collect();
+        cases.push(CaseResult::compare(
+            format!("{input_name} -> {ref_name}"),
+            &actual,
+            &expected,
+        ));
+    }
+
+    for (input_name, low_names, high_name) in G722_DECODE_CASES {
+        let input = read_hex_words(dir, input_name)?;
+        let high = read_hex_words(dir, high_name)?;
+        let (start, end) = g722_active_span(&input);
+
+        let rates = [
+            G722Rate::Rate64000,
+            G722Rate::Rate56000,
+            G722Rate::Rate48000,
+        ];
+        for (mode, (rate, low_name)) in rates.into_iter().zip(low_names).enumerate() {
+            let low = read_hex_words(dir, low_name)?;
+            let shift = 8 + mode;
+            let codes: Vec<u8> = input[start..end]
+                .iter()
+                .map(|&w| (w >> shift) as u8)
+                .collect();
+
+            let mut decoder = G722Decoder::new(rate, G722Options::empty())?;
+            unsafe {
+                spandsp_sys::spandsp_rs_g722_decode_set_itu_test_mode(decoder.as_ptr(), 1);
+            }
+            // Test mode yields a low band and a high band sample per code.
+            let mut out = vec![0i16; 2 * codes.len()];
+            let n = decoder.decode(&mut out, &codes);
+            let actual: Vec<u16> = out[..n].iter().map(|&s| s as u16).collect();
+            let expected: Vec<u16> = low
+                .iter()
+                .zip(&high)
+                .skip(start)
+                .take(end - start)
+                .flat_map(|(&l, &h)| [l, h])
+                .collect();
+            cases.push(CaseResult::compare(
+                format!("{input_name} mode {} -> {low_name} + {high_name}", mode + 1),
+                &actual,
+                &expected,
+            ));
+        }
+    }
+
+    Ok(ConformanceReport {
+        codec: "G.722",
+        cases,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// G.726
+// ---------------------------------------------------------------------------
+
+/// One G.726 sequence: optional encoder input and law, the ADPCM codes,
+/// and the expected decoder output in `output_law`.
+struct G726Case {
+    rate: G726Rate,
+    input: Option<(String, G726Encoding)>,
+    adpcm: String,
+    output: String,
+    output_law: G726Encoding,
+}
+
+fn law_letter(law: G726Encoding) -> char {
+    if law == G726Encoding::ALaw { 'A' } else { 'M' }
+}
+
+fn g726_cases() -> Vec<G726Case> {
+    let laws = [G726Encoding::ULaw, G726Encoding::ALaw];
+    let mut cases = Vec::new();
+    for rate in [
+        G726Rate::Rate16000,
+        G726Rate::Rate24000,
+        G726Rate::Rate32000,
+        G726Rate::Rate40000,
+    ] {
+        let kbps = rate.bps() / 1000;
+        for (kind, input) in [('N', "NRM"), ('V', "OVR")] {
+            for in_law in laws {
+                let law = law_letter(in_law);
+                let stem = format!("DISK1/RESET/{kbps}/R{kind}{kbps}F{law}");
+                for out_law in laws {
+                    // Same-law output is marked M or A, cross-law output C
+                    // (from u-law) or X (from A-law).
+                    let out = match (in_law == out_law, in_law) {
+                        (true, _) => law,
+                        (false, G726Encoding::ULaw) => 'C',
+                        (false, _) => 'X',
+                    };
+                    cases.push(G726Case {
+                        rate,
+                        input: (in_law == out_law)
+                            .then(|| (format!("DISK1/INPUT/{input}.{law}"), in_law)),
+                        adpcm: format!("{stem}.I"),
+                        output: format!("DISK1/RESET/{kbps}/R{kind}{kbps}F{out}.O"),
+                        output_law: out_law,
+                    });
+                }
+            }
+        }
+        for out_law in laws {
+            cases.push(G726Case {
+                rate,
+                input: None,
+                adpcm: format!("DISK2/INPUT/I{kbps}"),
+                output: format!("DISK2/RESET/{kbps}/RI{kbps}F{}.O", law_letter(out_law)),
+                output_law: out_law,
+            });
+        }
+    }
+    cases
+}
+
+/// G.711 bytes laid out as the C codec expects them: one per byte of an
+/// `i16` buffer, `bytes.len()` slots long.
+fn g711_buffer(bytes: &[u8]) -> Vec<i16> {
+    let mut amp = vec![0i16; bytes.len()];
+    for (i, &byte) in bytes.iter().enumerate() {
+        let mut pair = amp[i / 2].to_ne_bytes();
+        pair[i % 2] = byte;
+        amp[i / 2] = i16::from_ne_bytes(pair);
+    }
+    amp
+}
+
+fn g711_bytes(amp: &[i16], len: usize) -> Vec<u16> {
+    amp.iter()
+        .flat_map(|s| s.to_ne_bytes())
+        .take(len)
+        .map(u16::from)
+        .collect()
+}
+
+/// The calls the G.726 sequences make, so the C and pure-Rust codecs can
+/// share one runner.
+trait G726Codec: Sized {
+    fn new(rate: G726Rate, encoding: G726Encoding) -> Result<Self>;
+    fn encode(&mut self, g726_data: &mut [u8], amp: &[i16]) -> usize;
+    fn decode(&mut self, amp: &mut [i16], g726_data: &[u8]) -> usize;
+}
+
+impl G726Codec for crate::g726::G726State {
+    fn new(rate: G726Rate, encoding: G726Encoding) -> Result<Self> {
+        Self::new(rate, encoding, G726Packing::None)
+    }
+
+    fn encode(&mut self, g726_data: &mut [u8], amp: &[i16]) -> usize {
+        self.encode(g726_data, amp)
+    }
+
+    fn decode(&mut self, amp: &mut [i16], g726_data: &[u8]) -> usize {
+        self.decode(amp, g726_data)
+    }
+}
+
+#[cfg(feature = "pure-g726")]
+impl G726Codec for crate::g726_pure::G726State {
+    fn new(rate: G726Rate, encoding: G726Encoding) -> Result<Self> {
+        Self::new(rate, encoding, G726Packing::None)
+    }
+
+    fn encode(&mut self, g726_data: &mut [u8], amp: &[i16]) -> usize {
+        self.encode(g726_data, amp)
+    }
+
+    fn decode(&mut self, amp: &mut [i16], g726_data: &[u8]) -> usize {
+        self.decode(amp, g726_data)
+    }
+}
+
+/// Run the G.726 codec through the ITU reset-mode test sequences in `dir`,
+/// at all four rates with u-law and A-law on both sides.
+///
+/// `dir` holds the sequences as distributed on the two ITU disks:
+/// `DISK1/INPUT/{NRM,OVR}.{M,A}`, `DISK1/RESET/<kbps>/R{N,V}<kbps>F{M,A}.I`
+/// and `.../R{N,V}<kbps>F{M,A,C,X}.O`, `DISK2/INPUT/I<kbps>` and
+/// `DISK2/RESET/<kbps>/RI<kbps>F{M,A}.O`. Names may also be in lower case.
+/// A missing file is an `InvalidInput` error.
+pub fn g726(dir: impl AsRef<Path>) -> Result<ConformanceReport> {
+    run_g726::<crate::g726::G726State>(dir.as_ref(), "G.726")
+}
+
+/// Run the pure-Rust G.726 codec through the sequences [`g726`] uses,
+/// laid out the same way.
+#[cfg(feature = "pure-g726")]
+pub fn g726_pure(dir: impl AsRef<Path>) -> Result<ConformanceReport> {
+    run_g726::<crate::g726_pure::G726State>(dir.as_ref(), "G.726 (pure Rust)")
+}
+
+fn run_g726<C: G726Codec>(dir: &Path, codec: &'static str) -> Result<ConformanceReport> {
+    let mut cases = Vec::new();
+
+    for case in g726_cases() {
+        let adpcm = read_vector_file(dir, &case.adpcm)?;
+
+        if let Some((input_name, law)) = &case.input {
+            let pcm = read_vector_file(dir, input_name)?;
+            let mut encoder = C::new(case.rate, *law)?;
+            let mut codes = vec![0u8; pcm.len()];
+            let n = encoder.encode(&mut codes, &g711_buffer(&pcm));
+            let actual: Vec<u16> = codes[..n].iter().map(|&c| c as u16).collect();
+            let expected: Vec<u16> = adpcm.iter().map(|&c| c as u16).collect();
+            cases.push(CaseResult::compare(
+                format!("{} encode {input_name} -> {}", case.rate, case.adpcm),
+                &actual,
+                &expected,
+            ));
+        }
+
+        let reference = read_vector_file(dir, &case.output)?;
+        let mut decoder = C::new(case.rate, case.output_law)?;
+        let mut out = vec![0i16; adpcm.len()];
+        let n = decoder.decode(&mut out, &adpcm);
+        let expected: Vec<u16> = reference.iter().map(|&b| b as u16).collect();
+        cases.push(CaseResult::compare(
+            format!("{} decode {} -> {}", case.rate, case.adpcm, case.output),
+            &g711_bytes(&out, n),
+            &expected,
+        ));
+    }
+
+    Ok(ConformanceReport { codec, cases })
+}
@@ -0,0 +1,143 @@
+//! Direct digital synthesis oscillators.
+//!
+//! [`Dds`] drives spandsp's `dds_*` functions: a 32-bit phase accumulator
+//! stepped by a fixed rate each sample and looked up in a sine table. The
+//! frequency is exact to a fraction of a millihertz and the phase runs on
+//! unbroken across calls and frequency changes, which suits continuous
+//! test tones and local oscillators. For cadenced tones use
+//! [`ToneGenerator`](crate::tone_generate::ToneGenerator) instead.
+//!
+//! ```no_run
+//! use spandsp::dds::Dds;
+//!
+//! let mut tone = Dds::new(1004.0, -13.0).unwrap();
+//! let mut amp = [0i16; 160];
+//! tone.generate(&mut amp);
+//! // A quadrature local oscillator for mixing a signal down.
+//! let mut lo = Dds::new(-1800.0, 0.0).unwrap();
+//! let (re, im) = lo.next_complex();
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::DBM0_MAX_SINE_POWER;
+
+/// Nyquist at 8 kHz. The accumulator wraps at half a turn per sample, so
+/// only frequencies strictly below this, either way, can be represented.
+const MAX_FREQUENCY: f32 = 4000.0;
+
+/// A phase-accumulator sine oscillator.
+///
+/// Plain state with no C allocation, so it is cheap to copy and keep per
+/// channel.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Dds {
+    phase_acc: u32,
+    phase_rate: i32,
+    scale: i16,
+}
+
+impl Dds {
+    /// An oscillator at `frequency` Hz and `level_dbm0`, starting at zero
+    /// phase. Negative frequencies turn the complex output the other way.
+    pub fn new(frequency: f32, level_dbm0: f32) -> Result<Self> {
+        let mut dds = Self {
+            phase_acc: 0,
+            phase_rate: 0,
+            scale: 0,
+        };
+        dds.set_frequency(frequency)?;
+        dds.set_level_dbm0(level_dbm0)?;
+        Ok(dds)
+    }
+
+    /// Change the frequency, carrying on from the current phase.
+    pub fn set_frequency(&mut self, frequency: f32) -> Result<()> {
+        if !(frequency.is_finite() && frequency.abs() < MAX_FREQUENCY) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "DDS frequency must be less than {MAX_FREQUENCY} Hz from 0, got {frequency}"
+            )));
+        }
+        self.phase_rate = unsafe { spandsp_sys::dds_phase_rate(frequency) };
+        Ok(())
+    }
+
+    /// The frequency actually generated, in Hz, after rounding to the
+    /// accumulator's step.
+    pub fn frequency(&self) -> f32 {
+        unsafe { spandsp_sys::dds_frequency(self.phase_rate) }
+    }
+
+    /// Set the level of the sine wave, in dBm0.
+    pub fn set_level_dbm0(&mut self, level_dbm0: f32) -> Result<()> {
+        if !(level_dbm0.is_finite() && level_dbm0 <= DBM0_MAX_SINE_POWER) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "DDS level must be at most {DBM0_MAX_SINE_POWER} dBm0, got {level_dbm0}"
+            )));
+        }
+        self.scale = unsafe { spandsp_sys::dds_scaling_dbm0(level_dbm0) };
+        Ok(())
+    }
+
+    /// Set the level of the sine wave, in dBov.
+    pub fn set_level_dbov(&mut self, level_dbov: f32) -> Result<()> {
+        if !(level_dbov.is_finite() && level_dbov <= 0.0) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "DDS level must be at most 0 dBov, got {level_dbov}"
+            )));
+        }
+        self.scale = unsafe { spandsp_sys::dds_scaling_dbov(level_dbov) };
+        Ok(())
+    }
+
+    /// The current phase, with the full circle spanning `u32`.
+    pub fn phase(&self) -> u32 {
+        self.phase_acc
+    }
+
+    /// Jump to `phase`, with the full circle spanning `u32`.
+    pub fn set_phase(&mut self, phase: u32) {
+        self.phase_acc = phase;
+    }
+
+    /// Generate one sample.
+    pub fn next_sample(&mut self) -> i16 {
+        unsafe { spandsp_sys::dds_mod(&mut self.phase_acc, self.phase_rate, self.scale, 0) }
+    }
+
+    /// Fill `amp` with the sine wave.
+    pub fn generate(&mut self, amp: &mut [i16]) {
+        for sample in amp {
+            *sample = self.next_sample();
+        }
+    }
+
+    /// Generate one complex sample: cosine and sine of the same phase, as
+    /// (real, imaginary).
+    pub fn next_complex(&mut self) -> (i16, i16) {
+        let c = unsafe {
+            spandsp_sys::dds_complexi_mod(&mut self.phase_acc, self.phase_rate, self.scale, 0)
+        };
+        (c.re as i16, c.im as i16)
+    }
+
+    /// Fill `out` with complex samples.
+    pub fn generate_complex(&mut self, out: &mut [(i16, i16)]) {
+        for sample in out {
+            *sample = self.next_complex();
+        }
+    }
+}
+
+impl fmt::Debug for Dds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dds")
+            .field("frequency", &self.frequency())
+            .field("phase", &self.phase_acc)
+            .field("scale", &self.scale)
+            .finish()
+    }
+}
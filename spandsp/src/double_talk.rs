@@ -0,0 +1,239 @@
+//! Geigel double-talk detection.
+//!
+//! An echo canceller must stop adapting while the near end is talking, or it
+//! trains on the talker instead of the echo path and diverges. The Geigel test
+//! declares double talk when a received sample is louder than the loudest
+//! transmitted sample within the echo tail, scaled by the expected echo return
+//! loss. [`DoubleTalkDetector`] runs that test; [`EchoProcessor`] pairs it with
+//! an [`EchoCanceller`] and freezes adaption for you.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::echo::{EchoCanFlags, EchoCanceller};
+use crate::error::{Result, SpanDspError};
+
+/// Default echo return loss assumed by the detector, in dB.
+const DEFAULT_THRESHOLD_DB: f32 = 6.0;
+
+/// Default time double talk is held after the last detection (30 ms).
+const DEFAULT_HANGOVER_SAMPLES: usize = 240;
+
+// ---------------------------------------------------------------------------
+// DoubleTalkDetector
+// ---------------------------------------------------------------------------
+
+/// Geigel double-talk detector.
+pub struct DoubleTalkDetector {
+    window: usize,
+    ratio: f32,
+    threshold_db: f32,
+    hangover: usize,
+    hang_remaining: usize,
+    double_talk: bool,
+    /// Candidates for the far-end peak over the window, as (sample index,
+    /// magnitude) with magnitudes strictly decreasing.
+    far_peaks: VecDeque<(u64, u16)>,
+    samples: u64,
+}
+
+impl DoubleTalkDetector {
+    /// Create a detector looking back over `window` transmitted samples,
+    /// normally the echo canceller's tail length.
+    pub fn new(window: usize) -> Result<Self> {
+        if window == 0 {
+            return Err(SpanDspError::InvalidInput(
+                "double-talk window must be at least one sample".into(),
+            ));
+        }
+        Ok(Self {
+            window,
+            ratio: db_to_ratio(DEFAULT_THRESHOLD_DB),
+            threshold_db: DEFAULT_THRESHOLD_DB,
+            hangover: DEFAULT_HANGOVER_SAMPLES,
+            hang_remaining: 0,
+            double_talk: false,
+            far_peaks: VecDeque::new(),
+            samples: 0,
+        })
+    }
+
+    /// Set the echo return loss the line is assumed to have, in dB. Received
+    /// audio less than this far below the far-end peak counts as near-end
+    /// speech. The default is 6 dB.
+    pub fn set_threshold_db(&mut self, threshold_db: f32) {
+        self.threshold_db = threshold_db;
+        self.ratio = db_to_ratio(threshold_db);
+    }
+
+    /// The assumed echo return loss, in dB.
+    pub fn threshold_db(&self) -> f32 {
+        self.threshold_db
+    }
+
+    /// Set how many samples double talk is held after the last detection.
+    /// The default is 240 (30 ms).
+    pub fn set_hangover(&mut self, samples: usize) {
+        self.hangover = samples;
+    }
+
+    /// The hangover in samples.
+    pub fn hangover(&self) -> usize {
+        self.hangover
+    }
+
+    /// Feed one transmitted (far-end) and received (near-end) sample pair.
+    ///
+    /// Returns `true` while double talk is in progress.
+    pub fn update(&mut self, tx: i16, rx: i16) -> bool {
+        let far = tx.unsigned_abs();
+        while self.far_peaks.back().is_some_and(|&(_, peak)| peak <= far) {
+            self.far_peaks.pop_back();
+        }
+        self.far_peaks.push_back((self.samples, far));
+        while self
+            .far_peaks
+            .front()
+            .is_some_and(|&(index, _)| index + self.window as u64 <= self.samples)
+        {
+            self.far_peaks.pop_front();
+        }
+        self.samples += 1;
+
+        let far_peak = self.far_peaks.front().map_or(0, |&(_, peak)| peak);
+        if rx.unsigned_abs() as f32 > far_peak as f32 * self.ratio {
+            self.double_talk = true;
+            self.hang_remaining = self.hangover;
+        } else if self.hang_remaining > 0 {
+            self.hang_remaining -= 1;
+        } else {
+            self.double_talk = false;
+        }
+        self.double_talk
+    }
+
+    /// Whether double talk is in progress.
+    pub fn is_double_talk(&self) -> bool {
+        self.double_talk
+    }
+
+    /// Forget the far-end history and any double talk in progress.
+    pub fn reset(&mut self) {
+        self.far_peaks.clear();
+        self.hang_remaining = 0;
+        self.double_talk = false;
+    }
+}
+
+fn db_to_ratio(db: f32) -> f32 {
+    10.0f32.powf(-db / 20.0)
+}
+
+impl fmt::Debug for DoubleTalkDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DoubleTalkDetector")
+            .field("window", &self.window)
+            .field("threshold_db", &self.threshold_db)
+            .field("hangover", &self.hangover)
+            .field("double_talk", &self.is_double_talk())
+            .finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// EchoProcessor
+// ---------------------------------------------------------------------------
+
+/// An [`EchoCanceller`] whose adaption is frozen during double talk.
+pub struct EchoProcessor {
+    canceller: EchoCanceller,
+    detector: DoubleTalkDetector,
+    flags: EchoCanFlags,
+    frozen: bool,
+}
+
+impl EchoProcessor {
+    /// Create an echo canceller with a tail of `len` samples and a detector
+    /// watching the same span.
+    ///
+    /// `flags` is the mode used outside double talk; `ADAPTION` is removed
+    /// from it while the near end is talking.
+    pub fn new(len: i32, flags: EchoCanFlags) -> Result<Self> {
+        let window = usize::try_from(len)
+            .map_err(|_| SpanDspError::InvalidInput(format!("invalid tail length {len}")))?;
+        let detector = DoubleTalkDetector::new(window)?;
+        let canceller = EchoCanceller::new(len, flags)?;
+        Ok(Self {
+            canceller,
+            detector,
+            flags,
+            frozen: false,
+        })
+    }
+
+    /// Process a single sample pair, returning the echo-cancelled receive
+    /// sample. See [`EchoCanceller::update`].
+    pub fn update(&mut self, tx: i16, rx: i16) -> i16 {
+        let double_talk = self.detector.update(tx, rx);
+        if double_talk != self.frozen {
+            self.frozen = double_talk;
+            self.apply_mode();
+        }
+        self.canceller.update(tx, rx)
+    }
+
+    /// Change the mode used outside double talk.
+    pub fn set_adaption_mode(&mut self, flags: EchoCanFlags) {
+        self.flags = flags;
+        self.apply_mode();
+    }
+
+    /// The mode used outside double talk.
+    pub fn flags(&self) -> EchoCanFlags {
+        self.flags
+    }
+
+    /// Whether adaption is currently frozen by double talk.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Flush the canceller and reset the detector.
+    pub fn flush(&mut self) {
+        self.canceller.flush();
+        self.detector.reset();
+        self.frozen = false;
+        self.apply_mode();
+    }
+
+    /// Borrow the echo canceller.
+    pub fn canceller(&self) -> &EchoCanceller {
+        &self.canceller
+    }
+
+    /// Borrow the double-talk detector, e.g. to tune its threshold.
+    pub fn detector_mut(&mut self) -> &mut DoubleTalkDetector {
+        &mut self.detector
+    }
+
+    fn apply_mode(&mut self) {
+        let mode = if self.frozen {
+            self.flags - EchoCanFlags::ADAPTION
+        } else {
+            self.flags
+        };
+        if mode != self.canceller.flags() {
+            self.canceller.set_adaption_mode(mode);
+        }
+    }
+}
+
+impl fmt::Debug for EchoProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EchoProcessor")
+            .field("canceller", &self.canceller)
+            .field("detector", &self.detector)
+            .field("frozen", &self.frozen)
+            .finish_non_exhaustive()
+    }
+}
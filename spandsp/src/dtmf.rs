@@ -2,14 +2,22 @@
 //!
 //! - `DtmfTx` wraps `dtmf_tx_state_t` for generating DTMF tones.
 //! - `DtmfRx` wraps `dtmf_rx_state_t` for detecting DTMF digits.
+//!
+//! spandsp's detector reports digits but not the candidates it turns down,
+//! and its digit and gap timing is fixed. Rejection statistics and tunable
+//! minimum durations are only available from the pure-Rust detector in
+//! `dtmf_pure` (feature `pure-dtmf`), which takes the same
+//! [`DtmfRxConfig`]; this `DtmfRx` refuses a config that sets the timing.
 
 extern crate spandsp_sys;
 
 use std::ffi::CString;
+use std::fmt;
 use std::os::raw::{c_char, c_int, c_void};
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError};
+use crate::gain_ramp::GainRamp;
 
 // ---------------------------------------------------------------------------
 // DtmfTx
@@ -36,6 +44,7 @@ unsafe extern "C" fn dtmf_tx_callback_trampoline(user_data: *mut c_void) {
 pub struct DtmfTx {
     ptr: NonNull<spandsp_sys::dtmf_tx_state_t>,
     _callback: Option<Box<Box<dyn FnMut()>>>,
+    gain: GainRamp,
 }
 
 impl DtmfTx {
@@ -47,6 +56,7 @@ impl DtmfTx {
         Ok(Self {
             ptr,
             _callback: None,
+            gain: GainRamp::unity(),
         })
     }
 
@@ -69,6 +79,7 @@ impl DtmfTx {
         Ok(Self {
             ptr,
             _callback: Some(boxed),
+            gain: GainRamp::unity(),
         })
     }
 
@@ -91,7 +102,11 @@ impl DtmfTx {
     /// `amp.len()` if the digit queue is exhausted).
     pub fn generate(&mut self, amp: &mut [i16]) -> usize {
         let max_samples = amp.len().min(c_int::MAX as usize) as c_int;
-        unsafe { spandsp_sys::dtmf_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), max_samples) as usize }
+        let n = unsafe {
+            spandsp_sys::dtmf_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), max_samples) as usize
+        };
+        self.gain.apply(&mut amp[..n]);
+        n
     }
 
     /// Set the transmit level and twist.
@@ -104,6 +119,20 @@ impl DtmfTx {
         }
     }
 
+    /// Scale the output by `gain_db`, ramping linearly over `ramp_ms`
+    /// milliseconds.
+    ///
+    /// Unlike [`set_level`](Self::set_level), which applies from the next
+    /// digit, this also changes a digit that is already sounding.
+    pub fn set_gain(&mut self, gain_db: f32, ramp_ms: u32) {
+        self.gain.set_target_db(gain_db, ramp_ms);
+    }
+
+    /// The output gain in dB, or the gain being ramped to.
+    pub fn gain(&self) -> f32 {
+        self.gain.target_db()
+    }
+
     /// Set the on and off times for generated DTMF tones.
     ///
     /// Times are in milliseconds.
@@ -119,6 +148,15 @@ impl DtmfTx {
     }
 }
 
+impl fmt::Debug for DtmfTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtmfTx")
+            .field("has_callback", &self._callback.is_some())
+            .field("gain", &self.gain())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for DtmfTx {
     fn drop(&mut self) {
         unsafe {
@@ -133,6 +171,101 @@ impl Drop for DtmfTx {
 
 type DtmfCallback = Box<dyn FnMut(&str)>;
 
+/// Detector defaults, matching `dtmf_rx_init`.
+const DEFAULT_TWIST_DB: f32 = 8.0;
+const DEFAULT_REVERSE_TWIST_DB: f32 = 4.0;
+const DEFAULT_THRESHOLD_DBM0: f32 = -42.0;
+
+/// Detector parameters for [`DtmfRx::configure`].
+///
+/// Fields left as `None` keep their current values.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DtmfRxConfig {
+    /// Notch out 350 Hz and 440 Hz dial tone before detection.
+    pub filter_dialtone: Option<bool>,
+    /// Acceptable normal twist (low group louder than high), in dB.
+    pub twist_db: Option<f32>,
+    /// Acceptable reverse twist (high group louder than low), in dB.
+    pub reverse_twist_db: Option<f32>,
+    /// Minimum level of each tone, in dBm0.
+    pub threshold_dbm0: Option<f32>,
+    /// Shortest tone accepted as a digit, in milliseconds. Only the
+    /// pure-Rust detector's timing is tunable.
+    pub min_on_ms: Option<u32>,
+    /// Shortest gap that ends a digit, in milliseconds. Only the pure-Rust
+    /// detector's timing is tunable.
+    pub min_off_ms: Option<u32>,
+}
+
+impl DtmfRxConfig {
+    /// Check the values that are set, so a rejected config changes nothing.
+    pub(crate) fn validate(&self) -> Result<()> {
+        for (name, twist) in [
+            ("twist", self.twist_db),
+            ("reverse twist", self.reverse_twist_db),
+        ] {
+            if let Some(db) = twist
+                && !(db >= 0.0 && db.is_finite())
+            {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "{name} must be a non-negative number of dB, got {db}"
+                )));
+            }
+        }
+        if let Some(dbm0) = self.threshold_dbm0
+            && !(dbm0 > -99.0 && dbm0.is_finite())
+        {
+            return Err(SpanDspError::InvalidInput(format!(
+                "threshold must be above -99 dBm0, got {dbm0}"
+            )));
+        }
+        for (name, ms) in [("on", self.min_on_ms), ("off", self.min_off_ms)] {
+            if ms == Some(0) {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "minimum {name} time must be at least 1 ms"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the config sets the detector's timing.
+    pub(crate) fn sets_timing(&self) -> bool {
+        self.min_on_ms.is_some() || self.min_off_ms.is_some()
+    }
+
+    /// Values in the form `dtmf_rx_parms` takes, with "unchanged" encoded
+    /// as its out-of-range sentinels.
+    fn to_parms(self) -> (c_int, f32, f32, f32) {
+        (
+            self.filter_dialtone.map_or(-1, c_int::from),
+            self.twist_db.unwrap_or(-1.0),
+            self.reverse_twist_db.unwrap_or(-1.0),
+            self.threshold_dbm0.unwrap_or(-99.0),
+        )
+    }
+
+    /// The config `set_parms` describes. Anything `validate` would refuse,
+    /// i.e. NaN or an infinite level, is taken as "leave unchanged", so
+    /// the result always validates.
+    pub(crate) fn from_parms(
+        filter_dialtone: i32,
+        twist: f32,
+        reverse_twist: f32,
+        threshold: f32,
+    ) -> Self {
+        Self {
+            filter_dialtone: (filter_dialtone >= 0).then_some(filter_dialtone > 0),
+            twist_db: (twist >= 0.0 && twist.is_finite()).then_some(twist),
+            reverse_twist_db: (reverse_twist >= 0.0 && reverse_twist.is_finite())
+                .then_some(reverse_twist),
+            threshold_dbm0: (threshold > -99.0 && threshold.is_finite()).then_some(threshold),
+            min_on_ms: None,
+            min_off_ms: None,
+        }
+    }
+}
+
 /// Trampoline for the digit-received callback on the RX side.
 ///
 /// # Safety
@@ -149,6 +282,8 @@ unsafe extern "C" fn dtmf_rx_callback_trampoline(
         }
         let closure = &mut *(user_data as *mut DtmfCallback);
         let slice = std::slice::from_raw_parts(digits as *const u8, len as usize);
+        #[cfg(feature = "metrics")]
+        crate::metrics::dtmf_digits_detected(slice.len());
         if let Ok(s) = std::str::from_utf8(slice) {
             closure(s);
         }
@@ -161,6 +296,10 @@ unsafe extern "C" fn dtmf_rx_callback_trampoline(
 pub struct DtmfRx {
     ptr: NonNull<spandsp_sys::dtmf_rx_state_t>,
     _callback: Option<Box<DtmfCallback>>,
+    filter_dialtone: bool,
+    twist_db: f32,
+    reverse_twist_db: f32,
+    threshold_dbm0: f32,
 }
 
 impl DtmfRx {
@@ -171,10 +310,7 @@ impl DtmfRx {
         let ptr =
             unsafe { spandsp_sys::dtmf_rx_init(std::ptr::null_mut(), None, std::ptr::null_mut()) };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self {
-            ptr,
-            _callback: None,
-        })
+        Ok(Self::from_ptr(ptr, None))
     }
 
     /// Create a new DTMF receiver with a callback invoked each time one or
@@ -193,10 +329,21 @@ impl DtmfRx {
             )
         };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self {
+        Ok(Self::from_ptr(ptr, Some(boxed)))
+    }
+
+    fn from_ptr(
+        ptr: NonNull<spandsp_sys::dtmf_rx_state_t>,
+        callback: Option<Box<DtmfCallback>>,
+    ) -> Self {
+        Self {
             ptr,
-            _callback: Some(boxed),
-        })
+            _callback: callback,
+            filter_dialtone: false,
+            twist_db: DEFAULT_TWIST_DB,
+            reverse_twist_db: DEFAULT_REVERSE_TWIST_DB,
+            threshold_dbm0: DEFAULT_THRESHOLD_DBM0,
+        }
     }
 
     /// Feed audio samples to the DTMF detector.
@@ -222,6 +369,8 @@ impl DtmfRx {
             )
         };
         buf.truncate(n as usize);
+        #[cfg(feature = "metrics")]
+        crate::metrics::dtmf_digits_detected(buf.len());
         String::from_utf8_lossy(&buf).into_owned()
     }
 
@@ -239,6 +388,36 @@ impl DtmfRx {
         }
     }
 
+    /// Adjust detector parameters. Fields left as `None` are unchanged.
+    ///
+    /// Returns `InvalidInput` for a negative twist, a threshold at or
+    /// below -99 dBm0, or any timing, leaving every parameter unchanged.
+    /// spandsp's detector always wants two 12.75 ms blocks to start or end
+    /// a digit; the pure-Rust detector's timing can be tuned.
+    pub fn configure(&mut self, config: &DtmfRxConfig) -> Result<()> {
+        config.validate()?;
+        if config.sets_timing() {
+            return Err(SpanDspError::InvalidInput(
+                "spandsp's DTMF detector has fixed timing; use the pure-dtmf detector".into(),
+            ));
+        }
+        let (filter_dialtone, twist, reverse_twist, threshold) = config.to_parms();
+        unsafe {
+            spandsp_sys::dtmf_rx_parms(
+                self.ptr.as_ptr(),
+                filter_dialtone,
+                twist,
+                reverse_twist,
+                threshold,
+            );
+        }
+        self.filter_dialtone = config.filter_dialtone.unwrap_or(self.filter_dialtone);
+        self.twist_db = config.twist_db.unwrap_or(self.twist_db);
+        self.reverse_twist_db = config.reverse_twist_db.unwrap_or(self.reverse_twist_db);
+        self.threshold_dbm0 = config.threshold_dbm0.unwrap_or(self.threshold_dbm0);
+        Ok(())
+    }
+
     /// Adjust detector parameters.
     ///
     /// - `filter_dialtone`: positive to enable dial tone filtering, 0 to
@@ -246,6 +425,10 @@ impl DtmfRx {
     /// - `twist`: acceptable twist in dB (< 0.0 to leave unchanged).
     /// - `reverse_twist`: acceptable reverse twist in dB (< 0.0 to leave unchanged).
     /// - `threshold`: minimum tone level in dBm0 (<= -99.0 to leave unchanged).
+    ///
+    /// NaN or infinite values also leave their parameter unchanged, as
+    /// spandsp would otherwise be handed a level it cannot use.
+    #[deprecated(note = "use `configure`")]
     pub fn set_parms(
         &mut self,
         filter_dialtone: i32,
@@ -253,15 +436,32 @@ impl DtmfRx {
         reverse_twist: f32,
         threshold: f32,
     ) {
-        unsafe {
-            spandsp_sys::dtmf_rx_parms(
-                self.ptr.as_ptr(),
-                filter_dialtone as c_int,
-                twist,
-                reverse_twist,
-                threshold,
-            );
-        }
+        let config = DtmfRxConfig::from_parms(filter_dialtone, twist, reverse_twist, threshold);
+        let result = self.configure(&config);
+        debug_assert!(
+            result.is_ok(),
+            "from_parms built an invalid config: {result:?}"
+        );
+    }
+
+    /// Whether dial tone is filtered out before detection.
+    pub fn filter_dialtone(&self) -> bool {
+        self.filter_dialtone
+    }
+
+    /// Acceptable normal twist, in dB.
+    pub fn twist_db(&self) -> f32 {
+        self.twist_db
+    }
+
+    /// Acceptable reverse twist, in dB.
+    pub fn reverse_twist_db(&self) -> f32 {
+        self.reverse_twist_db
+    }
+
+    /// Minimum tone level, in dBm0.
+    pub fn threshold_dbm0(&self) -> f32 {
+        self.threshold_dbm0
     }
 
     /// Return the raw pointer to the underlying state.
@@ -270,6 +470,15 @@ impl DtmfRx {
     }
 }
 
+impl fmt::Debug for DtmfRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtmfRx")
+            .field("status", &self.status())
+            .field("has_callback", &self._callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for DtmfRx {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,562 @@
+//! Pure-Rust DTMF detector.
+//!
+//! Enabled by the `pure-dtmf` feature. [`DtmfRx`] has the same API as
+//! [`crate::dtmf::DtmfRx`] and uses the same detection scheme as spandsp: a
+//! bank of eight Goertzel filters evaluated over 102-sample blocks, with the
+//! Q.24 checks on minimum level, normal and reverse twist, the margin of each
+//! tone over the rest of its group and the share of total energy, and two
+//! agreeing blocks required before a digit starts or ends.
+//!
+//! Unlike spandsp's detector it also counts why candidates were turned down
+//! ([`DtmfRx::stats`]), and takes the minimum digit and gap durations from
+//! [`DtmfRxConfig`], so its behaviour on speech-heavy audio can be measured
+//! and tightened.
+
+use std::f32::consts::PI;
+use std::fmt;
+
+use crate::error::Result;
+use crate::power_meter::dbm0_to_sine_power;
+
+pub use crate::dtmf::DtmfRxConfig;
+
+type DtmfCallback = Box<dyn FnMut(&str)>;
+
+/// Samples per Goertzel block (12.75 ms at 8 kHz).
+const DTMF_SAMPLES_PER_BLOCK: usize = 102;
+
+/// Most digits held for `get()` before further ones are dropped.
+const MAX_DTMF_DIGITS: usize = 128;
+
+const DTMF_ROW_FREQS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+const DTMF_COL_FREQS: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const DTMF_POSITIONS: &[u8; 16] = b"123A456B789C*0#D";
+
+/// Default minimum tone level, in dBm0.
+const DTMF_THRESHOLD_DBM0: f32 = -42.0;
+/// Default normal twist (low group above high group), in dB.
+const DTMF_NORMAL_TWIST_DB: f32 = 8.0;
+/// Default reverse twist (high group above low group), in dB.
+const DTMF_REVERSE_TWIST_DB: f32 = 4.0;
+/// How far the strongest tone in each group must exceed the others, in dB.
+const DTMF_RELATIVE_PEAK_DB: f32 = 8.0;
+/// Share of the block's power the two tones must account for, in dB.
+const DTMF_TO_TOTAL_ENERGY_DB: f32 = -0.85;
+/// Agreeing blocks needed to start a digit, and differing blocks to end one.
+const DTMF_DEFAULT_BLOCKS: u32 = 2;
+
+fn db_to_power_ratio(db: f32) -> f32 {
+    10.0f32.powf(db / 10.0)
+}
+
+// ---------------------------------------------------------------------------
+// Goertzel filter
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+struct Goertzel {
+    fac: f32,
+    v2: f32,
+    v3: f32,
+}
+
+impl Goertzel {
+    fn new(freq: f32) -> Self {
+        Self {
+            fac: 2.0 * (2.0 * PI * freq / 8000.0).cos(),
+            v2: 0.0,
+            v3: 0.0,
+        }
+    }
+
+    #[inline]
+    fn update(&mut self, amp: f32) {
+        let v1 = self.v2;
+        self.v2 = self.v3;
+        self.v3 = self.fac * self.v2 - v1 + amp;
+    }
+
+    /// Mean power of the tone over the block, resetting the filter.
+    fn result(&mut self) -> f32 {
+        let power = self.v3 * self.v3 + self.v2 * self.v2 - self.v2 * self.v3 * self.fac;
+        self.v2 = 0.0;
+        self.v3 = 0.0;
+        let n = DTMF_SAMPLES_PER_BLOCK as f32;
+        2.0 * power / (n * n)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Dial tone notch
+// ---------------------------------------------------------------------------
+
+/// Sharp notches at 350 Hz and 440 Hz, the usual dial tone frequencies.
+#[derive(Debug, Clone, Copy, Default)]
+struct DialToneFilter {
+    z350: [f32; 2],
+    z440: [f32; 2],
+}
+
+impl DialToneFilter {
+    #[inline]
+    fn apply(&mut self, amp: f32) -> f32 {
+        let v1 = 0.98356 * amp + 1.8954426 * self.z350[0] - 0.9691396 * self.z350[1];
+        let famp = v1 - 1.925148 * self.z350[0] + self.z350[1];
+        self.z350[1] = self.z350[0];
+        self.z350[0] = v1;
+
+        let v1 = 0.98456 * famp + 1.8529543 * self.z440[0] - 0.9691396 * self.z440[1];
+        let famp = v1 - 1.8819938 * self.z440[0] + self.z440[1];
+        self.z440[1] = self.z440[0];
+        self.z440[0] = v1;
+        famp
+    }
+}
+
+/// Blocks covering at least `ms` milliseconds.
+fn ms_to_blocks(ms: u32) -> u32 {
+    // In u64, as ms * 8 overflows u32; the block count is smaller than ms.
+    (u64::from(ms) * 8)
+        .div_ceil(DTMF_SAMPLES_PER_BLOCK as u64)
+        .max(1) as u32
+}
+
+/// Duration of `blocks` blocks, in milliseconds.
+fn blocks_to_ms(blocks: u32) -> f32 {
+    blocks as f32 * DTMF_SAMPLES_PER_BLOCK as f32 / 8.0
+}
+
+// ---------------------------------------------------------------------------
+// Statistics
+// ---------------------------------------------------------------------------
+
+/// What [`DtmfRx`] made of its input.
+///
+/// Rejections are counted per 12.75 ms block, and only for blocks at least
+/// as loud as the detection threshold, so silence and line noise do not
+/// count. A block is counted under the first check it failed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DtmfRxStats {
+    /// Blocks analysed.
+    pub blocks: u64,
+    /// Digits reported.
+    pub accepted: u64,
+    /// Blocks with a row or column tone below the threshold.
+    pub rejected_level: u64,
+    /// Blocks whose low group exceeded the high by more than the twist.
+    pub rejected_twist: u64,
+    /// Blocks whose high group exceeded the low by more than the reverse
+    /// twist.
+    pub rejected_reverse_twist: u64,
+    /// Tones that passed every check but stopped before the minimum on
+    /// time.
+    pub rejected_duration: u64,
+    /// Blocks turned down by the talk-off checks: another tone in a group
+    /// too close to the strongest, or too much energy outside the pair, as
+    /// speech and music have.
+    pub talk_off: u64,
+}
+
+impl DtmfRxStats {
+    /// Candidates turned down, for any reason.
+    pub fn rejected(&self) -> u64 {
+        self.rejected_level
+            + self.rejected_twist
+            + self.rejected_reverse_twist
+            + self.rejected_duration
+            + self.talk_off
+    }
+}
+
+impl fmt::Display for DtmfRxStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} digits in {} blocks; rejected: {} level, {} twist, {} reverse twist, \
+             {} duration, {} talk-off",
+            self.accepted,
+            self.blocks,
+            self.rejected_level,
+            self.rejected_twist,
+            self.rejected_reverse_twist,
+            self.rejected_duration,
+            self.talk_off
+        )
+    }
+}
+
+/// Why a block did not hold a digit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reject {
+    Level,
+    Twist,
+    ReverseTwist,
+    TalkOff,
+}
+
+// ---------------------------------------------------------------------------
+// DtmfRx
+// ---------------------------------------------------------------------------
+
+/// Pure-Rust DTMF receiver, a drop-in replacement for
+/// [`crate::dtmf::DtmfRx`].
+pub struct DtmfRx {
+    row_out: [Goertzel; 4],
+    col_out: [Goertzel; 4],
+    dial_tone: Option<DialToneFilter>,
+    threshold: f32,
+    normal_twist: f32,
+    reverse_twist: f32,
+    twist_db: f32,
+    reverse_twist_db: f32,
+    threshold_dbm0: f32,
+    /// Agreeing blocks needed to start a digit.
+    on_blocks: u32,
+    /// Differing blocks needed to end a digit.
+    off_blocks: u32,
+    energy: f32,
+    current_sample: usize,
+    /// The previous block's hit, or 0 after a change that ended without a
+    /// digit, as spandsp keeps it.
+    last_hit: u8,
+    /// Consecutive blocks classified as `last_hit`.
+    hit_blocks: u32,
+    in_digit: u8,
+    /// Consecutive blocks not matching `in_digit` since the last change.
+    change_blocks: u32,
+    /// Whether those blocks held a digit other than `in_digit`.
+    candidate: bool,
+    stats: DtmfRxStats,
+    digits: String,
+    lost_digits: usize,
+    callback: Option<DtmfCallback>,
+}
+
+impl DtmfRx {
+    /// Create a new DTMF receiver with no digit callback.
+    ///
+    /// Detected digits can be retrieved with `get()`.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            row_out: DTMF_ROW_FREQS.map(Goertzel::new),
+            col_out: DTMF_COL_FREQS.map(Goertzel::new),
+            dial_tone: None,
+            threshold: dbm0_to_sine_power(DTMF_THRESHOLD_DBM0),
+            normal_twist: db_to_power_ratio(DTMF_NORMAL_TWIST_DB),
+            reverse_twist: db_to_power_ratio(DTMF_REVERSE_TWIST_DB),
+            twist_db: DTMF_NORMAL_TWIST_DB,
+            reverse_twist_db: DTMF_REVERSE_TWIST_DB,
+            threshold_dbm0: DTMF_THRESHOLD_DBM0,
+            on_blocks: DTMF_DEFAULT_BLOCKS,
+            off_blocks: DTMF_DEFAULT_BLOCKS,
+            energy: 0.0,
+            current_sample: 0,
+            last_hit: 0,
+            hit_blocks: 0,
+            in_digit: 0,
+            change_blocks: 0,
+            candidate: false,
+            stats: DtmfRxStats::default(),
+            digits: String::new(),
+            lost_digits: 0,
+            callback: None,
+        })
+    }
+
+    /// Create a new DTMF receiver with a callback invoked each time one or
+    /// more digits are detected.
+    pub fn with_callback<F>(callback: F) -> Result<Self>
+    where
+        F: FnMut(&str) + 'static,
+    {
+        let mut rx = Self::new()?;
+        rx.callback = Some(Box::new(callback));
+        Ok(rx)
+    }
+
+    /// Feed audio samples to the DTMF detector.
+    ///
+    /// Returns the number of unprocessed samples (always 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        for &sample in amp {
+            let mut xamp = sample as f32;
+            if let Some(filter) = self.dial_tone.as_mut() {
+                xamp = filter.apply(xamp);
+            }
+            self.energy += xamp * xamp;
+            for g in self.row_out.iter_mut().chain(self.col_out.iter_mut()) {
+                g.update(xamp);
+            }
+            self.current_sample += 1;
+            if self.current_sample == DTMF_SAMPLES_PER_BLOCK {
+                self.end_of_block();
+            }
+        }
+        0
+    }
+
+    /// Retrieve detected digits from the internal buffer.
+    ///
+    /// Returns the digits as a `String`. The internal buffer is drained by
+    /// this call.
+    pub fn get(&mut self, max_digits: usize) -> String {
+        let n = max_digits.min(MAX_DTMF_DIGITS).min(self.digits.len());
+        let digits: String = self.digits.drain(..n).collect();
+        #[cfg(feature = "metrics")]
+        crate::metrics::dtmf_digits_detected(digits.len());
+        digits
+    }
+
+    /// Get the current detection status of the last audio chunk.
+    ///
+    /// Returns `Some(digit)` if a digit is being detected, or `None` if
+    /// no detection is active. The special value `'x'` indicates a "maybe"
+    /// condition.
+    pub fn status(&self) -> Option<char> {
+        if self.in_digit != 0 {
+            Some(self.in_digit as char)
+        } else if self.last_hit != 0 {
+            Some('x')
+        } else {
+            None
+        }
+    }
+
+    /// Adjust detector parameters. Fields left as `None` are unchanged.
+    ///
+    /// Returns `InvalidInput` for a negative twist, a threshold at or
+    /// below -99 dBm0 or a zero duration, leaving every parameter
+    /// unchanged. Durations are rounded up to whole 12.75 ms blocks.
+    pub fn configure(&mut self, config: &DtmfRxConfig) -> Result<()> {
+        config.validate()?;
+        if let Some(ms) = config.min_on_ms {
+            self.on_blocks = ms_to_blocks(ms);
+        }
+        if let Some(ms) = config.min_off_ms {
+            self.off_blocks = ms_to_blocks(ms);
+        }
+        if let Some(filter) = config.filter_dialtone {
+            self.dial_tone = filter.then(DialToneFilter::default);
+        }
+        if let Some(db) = config.twist_db {
+            self.twist_db = db;
+            self.normal_twist = db_to_power_ratio(db);
+        }
+        if let Some(db) = config.reverse_twist_db {
+            self.reverse_twist_db = db;
+            self.reverse_twist = db_to_power_ratio(db);
+        }
+        if let Some(dbm0) = config.threshold_dbm0 {
+            self.threshold_dbm0 = dbm0;
+            self.threshold = dbm0_to_sine_power(dbm0);
+        }
+        Ok(())
+    }
+
+    /// Adjust detector parameters.
+    ///
+    /// - `filter_dialtone`: positive to enable dial tone filtering, 0 to
+    ///   disable, negative to leave unchanged.
+    /// - `twist`: acceptable twist in dB (< 0.0 to leave unchanged).
+    /// - `reverse_twist`: acceptable reverse twist in dB (< 0.0 to leave unchanged).
+    /// - `threshold`: minimum tone level in dBm0 (<= -99.0 to leave unchanged).
+    ///
+    /// NaN or infinite values also leave their parameter unchanged.
+    #[deprecated(note = "use `configure`")]
+    pub fn set_parms(
+        &mut self,
+        filter_dialtone: i32,
+        twist: f32,
+        reverse_twist: f32,
+        threshold: f32,
+    ) {
+        let config = DtmfRxConfig::from_parms(filter_dialtone, twist, reverse_twist, threshold);
+        let result = self.configure(&config);
+        debug_assert!(
+            result.is_ok(),
+            "from_parms built an invalid config: {result:?}"
+        );
+    }
+
+    /// Whether dial tone is filtered out before detection.
+    pub fn filter_dialtone(&self) -> bool {
+        self.dial_tone.is_some()
+    }
+
+    /// Acceptable normal twist, in dB.
+    pub fn twist_db(&self) -> f32 {
+        self.twist_db
+    }
+
+    /// Acceptable reverse twist, in dB.
+    pub fn reverse_twist_db(&self) -> f32 {
+        self.reverse_twist_db
+    }
+
+    /// Minimum tone level, in dBm0.
+    pub fn threshold_dbm0(&self) -> f32 {
+        self.threshold_dbm0
+    }
+
+    /// Shortest tone accepted as a digit, in milliseconds.
+    pub fn min_on_ms(&self) -> f32 {
+        blocks_to_ms(self.on_blocks)
+    }
+
+    /// Shortest gap that ends a digit, in milliseconds.
+    pub fn min_off_ms(&self) -> f32 {
+        blocks_to_ms(self.off_blocks)
+    }
+
+    /// Number of digits dropped because the buffer was full.
+    pub fn lost_digits(&self) -> usize {
+        self.lost_digits
+    }
+
+    /// Counts since the detector was created or the stats last taken.
+    pub fn stats(&self) -> DtmfRxStats {
+        self.stats
+    }
+
+    /// Return the counts and start again from zero.
+    pub fn take_stats(&mut self) -> DtmfRxStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    fn end_of_block(&mut self) {
+        let rows = self.row_out.each_mut().map(Goertzel::result);
+        let cols = self.col_out.each_mut().map(Goertzel::result);
+        let mean_power = self.energy / DTMF_SAMPLES_PER_BLOCK as f32;
+        self.energy = 0.0;
+        self.current_sample = 0;
+        self.stats.blocks += 1;
+
+        let hit = match self.classify(&rows, &cols, mean_power) {
+            Ok(hit) => hit,
+            Err(reject) => {
+                if mean_power >= self.threshold {
+                    let counter = match reject {
+                        Reject::Level => &mut self.stats.rejected_level,
+                        Reject::Twist => &mut self.stats.rejected_twist,
+                        Reject::ReverseTwist => &mut self.stats.rejected_reverse_twist,
+                        Reject::TalkOff => &mut self.stats.talk_off,
+                    };
+                    *counter += 1;
+                }
+                0
+            }
+        };
+
+        if hit == self.last_hit {
+            self.hit_blocks = self.hit_blocks.saturating_add(1);
+        } else {
+            self.last_hit = hit;
+            self.hit_blocks = 1;
+        }
+        if hit == self.in_digit {
+            if self.candidate {
+                self.stats.rejected_duration += 1;
+            }
+            self.change_blocks = 0;
+            self.candidate = false;
+            return;
+        }
+        self.candidate |= hit != 0;
+        self.change_blocks = self.change_blocks.saturating_add(1);
+        let needed = if self.in_digit == 0 {
+            self.on_blocks
+        } else {
+            self.off_blocks
+        };
+        if self.change_blocks < needed {
+            return;
+        }
+
+        // Enough blocks say something changed, as spandsp's two in a row do
+        // with the default durations. The new digit is `hit` if the latest
+        // blocks agree on it; otherwise the tone is off, and like spandsp
+        // `last_hit` becomes 0 so a new digit starts counting afresh.
+        let digit = if hit != 0 && self.hit_blocks >= self.on_blocks {
+            hit
+        } else {
+            0
+        };
+        if digit != 0 {
+            self.stats.accepted += 1;
+            self.report_digit(digit as char);
+        } else {
+            if self.candidate {
+                self.stats.rejected_duration += 1;
+            }
+            self.last_hit = 0;
+            self.hit_blocks = 0;
+        }
+        self.in_digit = digit;
+        self.change_blocks = 0;
+        self.candidate = false;
+    }
+
+    fn classify(
+        &self,
+        rows: &[f32; 4],
+        cols: &[f32; 4],
+        mean_power: f32,
+    ) -> std::result::Result<u8, Reject> {
+        let best_row = strongest(rows);
+        let best_col = strongest(cols);
+        let row = rows[best_row];
+        let col = cols[best_col];
+        if row < self.threshold || col < self.threshold {
+            return Err(Reject::Level);
+        }
+        if col * self.normal_twist <= row {
+            return Err(Reject::Twist);
+        }
+        if col >= row * self.reverse_twist {
+            return Err(Reject::ReverseTwist);
+        }
+        let relative_peak = db_to_power_ratio(DTMF_RELATIVE_PEAK_DB);
+        let peaks_clear = (0..4).all(|i| {
+            (i == best_row || rows[i] * relative_peak <= row)
+                && (i == best_col || cols[i] * relative_peak <= col)
+        });
+        if !peaks_clear || row + col <= db_to_power_ratio(DTMF_TO_TOTAL_ENERGY_DB) * mean_power {
+            return Err(Reject::TalkOff);
+        }
+        Ok(DTMF_POSITIONS[best_row * 4 + best_col])
+    }
+
+    fn report_digit(&mut self, digit: char) {
+        if self.digits.len() >= MAX_DTMF_DIGITS {
+            self.lost_digits += 1;
+            return;
+        }
+        self.digits.push(digit);
+        if let Some(callback) = self.callback.as_mut() {
+            #[cfg(feature = "metrics")]
+            crate::metrics::dtmf_digits_detected(self.digits.len());
+            callback(&self.digits);
+            self.digits.clear();
+        }
+    }
+}
+
+fn strongest(energies: &[f32; 4]) -> usize {
+    (1..4).fold(0, |best, i| {
+        if energies[i] > energies[best] {
+            i
+        } else {
+            best
+        }
+    })
+}
+
+impl fmt::Debug for DtmfRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtmfRx")
+            .field("status", &self.status())
+            .field("has_callback", &self.callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,283 @@
+//! In-band DTMF to RFC 4733 telephone-event relay.
+//!
+//! [`DtmfRelay`] is the DTMF interworking function of a media gateway: it
+//! detects digits in the audio, squelches the tones with a
+//! [`DtmfSquelch`], and produces the RFC 4733 packets that carry them
+//! instead. Each event covers exactly the stretch of audio the squelch
+//! blanked, so event and audio timestamps share one clock: the number of
+//! samples from the start of the stream.
+//!
+//! ```no_run
+//! use spandsp::dtmf_relay::{DtmfRelay, DtmfRelayConfig};
+//!
+//! let mut relay = DtmfRelay::new(DtmfRelayConfig::default()).unwrap();
+//! let rtp_base: u32 = 0x1000;
+//! let mut frame = [0i16; 160];
+//! for packet in relay.process(&mut frame) {
+//!     let timestamp = rtp_base.wrapping_add(packet.timestamp);
+//!     let payload = packet.event.to_payload();
+//!     // send `payload` with `timestamp` and `packet.marker`
+//! #   let _ = (timestamp, payload);
+//! }
+//! // send `frame` as audio
+//! ```
+
+use std::fmt;
+
+use crate::dtmf_squelch::{DtmfSquelch, DtmfSquelchConfig};
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::DBM0_MAX_SINE_POWER;
+
+/// Event codes 0-15 in order (RFC 4733 section 3.2).
+const DTMF_EVENTS: &[u8; 16] = b"0123456789*#ABCD";
+
+/// An RFC 4733 telephone-event payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TelephoneEvent {
+    /// Event code; 0-15 are the DTMF digits.
+    pub event: u8,
+    /// The E bit: this is (a repeat of) the event's last packet.
+    pub end: bool,
+    /// Tone power in -dBm0, 0..=63.
+    pub volume: u8,
+    /// Samples since the event started.
+    pub duration: u16,
+}
+
+impl TelephoneEvent {
+    /// The event code for a DTMF digit.
+    pub fn code_for_digit(digit: char) -> Option<u8> {
+        let digit = u8::try_from(digit.to_ascii_uppercase()).ok()?;
+        DTMF_EVENTS
+            .iter()
+            .position(|&d| d == digit)
+            .map(|code| code as u8)
+    }
+
+    /// The DTMF digit this event carries, if it is one.
+    pub fn digit(&self) -> Option<char> {
+        DTMF_EVENTS
+            .get(usize::from(self.event))
+            .map(|&d| char::from(d))
+    }
+
+    /// The 4-byte wire form.
+    pub fn to_payload(self) -> [u8; 4] {
+        let [hi, lo] = self.duration.to_be_bytes();
+        [
+            self.event,
+            (u8::from(self.end) << 7) | (self.volume & 0x3F),
+            hi,
+            lo,
+        ]
+    }
+
+    /// Parse the first event of a payload.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        let &[event, flags, hi, lo, ..] = payload else {
+            return None;
+        };
+        Some(Self {
+            event,
+            end: flags & 0x80 != 0,
+            volume: flags & 0x3F,
+            duration: u16::from_be_bytes([hi, lo]),
+        })
+    }
+}
+
+/// One telephone-event packet to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelayPacket {
+    /// Start of the event, in samples from the start of the stream. Every
+    /// packet of an event has the same timestamp.
+    pub timestamp: u32,
+    /// The RTP marker bit: set on the first packet of an event.
+    pub marker: bool,
+    /// The payload.
+    pub event: TelephoneEvent,
+}
+
+/// Tuning for [`DtmfRelay`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DtmfRelayConfig {
+    /// Detection delay and squelch fill.
+    pub squelch: DtmfSquelchConfig,
+    /// Samples between packets while an event lasts.
+    pub packet_interval: u32,
+    /// Times the final packet of an event is sent, for loss resilience.
+    pub end_repeats: u8,
+}
+
+impl Default for DtmfRelayConfig {
+    /// The default squelch, a packet every 50 ms and three end packets.
+    fn default() -> Self {
+        Self {
+            squelch: DtmfSquelchConfig::default(),
+            packet_interval: 400,
+            end_repeats: 3,
+        }
+    }
+}
+
+/// The event being relayed.
+#[derive(Debug, Clone, Copy)]
+struct ActiveEvent {
+    code: u8,
+    /// Stream position the event (or its current segment) started at.
+    start: u64,
+    /// Duration in the last packet sent; `None` before the first.
+    sent: Option<u64>,
+    /// Whether this segment continues an event too long for one duration
+    /// field, and so carries no marker.
+    continued: bool,
+    /// Sum of squares and count of the tone samples heard so far.
+    energy: f64,
+    samples: u64,
+}
+
+impl ActiveEvent {
+    fn new(code: u8, start: u64) -> Self {
+        Self {
+            code,
+            start,
+            sent: None,
+            continued: false,
+            energy: 0.0,
+            samples: 0,
+        }
+    }
+
+    /// The tone's level so far, as a volume field.
+    fn volume(&self) -> u8 {
+        if self.energy == 0.0 {
+            return 63;
+        }
+        let mean_sq = self.energy / self.samples as f64;
+        let full_scale_sine = 32767.0f64 * 32767.0 / 2.0;
+        let dbm0 = 10.0 * (mean_sq / full_scale_sine).log10() + f64::from(DBM0_MAX_SINE_POWER);
+        (-dbm0).round().clamp(0.0, 63.0) as u8
+    }
+
+    /// A packet for this event at stream position `now`, noting it as sent.
+    fn packet(&mut self, now: u64, end: bool) -> RelayPacket {
+        let duration = now - self.start;
+        let packet = RelayPacket {
+            timestamp: self.start as u32,
+            marker: self.sent.is_none() && !self.continued,
+            event: TelephoneEvent {
+                event: self.code,
+                end,
+                volume: self.volume(),
+                duration: duration.min(u64::from(u16::MAX)) as u16,
+            },
+        };
+        self.sent = Some(duration);
+        packet
+    }
+}
+
+/// DTMF detection, squelch and RFC 4733 event generation in one.
+pub struct DtmfRelay {
+    squelch: DtmfSquelch,
+    config: DtmfRelayConfig,
+    /// Stream position of the next output sample handed over by the squelch.
+    position: u64,
+    active: Option<ActiveEvent>,
+}
+
+impl DtmfRelay {
+    /// Create a relay with its own detector.
+    pub fn new(config: DtmfRelayConfig) -> Result<Self> {
+        if config.packet_interval == 0 || config.end_repeats == 0 {
+            return Err(SpanDspError::InvalidInput(
+                "packet_interval and end_repeats must be non-zero".into(),
+            ));
+        }
+        Ok(Self {
+            squelch: DtmfSquelch::new(config.squelch)?,
+            config,
+            // The squelch hands over nothing until its delay line fills.
+            position: config.squelch.lookahead as u64,
+            active: None,
+        })
+    }
+
+    /// Process one frame of received audio in place, squelching tones, and
+    /// return the telephone-event packets due by the end of the frame.
+    ///
+    /// An event's first packet goes out with the frame its tone starts in,
+    /// then one every [`packet_interval`](DtmfRelayConfig::packet_interval)
+    /// samples, and its end packets with the frame where the squelch stops.
+    pub fn process(&mut self, amp: &mut [i16]) -> Vec<RelayPacket> {
+        let mut packets = Vec::new();
+        let repeats = usize::from(self.config.end_repeats);
+        let Self {
+            squelch,
+            position,
+            active,
+            ..
+        } = self;
+        squelch.process_marked(amp, |original, digit| {
+            let code = digit.and_then(TelephoneEvent::code_for_digit);
+            if let Some(mut event) = active.take_if(|event| code != Some(event.code)) {
+                let end = event.packet(*position, true);
+                packets.push(end);
+                let repeat = RelayPacket {
+                    marker: false,
+                    ..end
+                };
+                packets.extend(std::iter::repeat_n(repeat, repeats - 1));
+            }
+            if let Some(code) = code {
+                let event = active.get_or_insert_with(|| ActiveEvent::new(code, *position));
+                if *position - event.start == u64::from(u16::MAX) {
+                    // Too long for one duration field: close this segment
+                    // without the E bit and carry on in a new one.
+                    packets.push(event.packet(*position, false));
+                    event.start = *position;
+                    event.sent = Some(0);
+                    event.continued = true;
+                }
+                event.energy += f64::from(original) * f64::from(original);
+                event.samples += 1;
+            }
+            *position += 1;
+        });
+        if let Some(event) = &mut self.active {
+            let due = event.sent.is_none_or(|sent| {
+                self.position - event.start - sent >= u64::from(self.config.packet_interval)
+            });
+            if due {
+                packets.push(event.packet(self.position, false));
+            }
+        }
+        packets
+    }
+
+    /// The digits detected since the last call.
+    pub fn take_digits(&mut self) -> String {
+        self.squelch.take_digits()
+    }
+
+    /// The squelch, e.g. to configure its detector.
+    pub fn squelch_mut(&mut self) -> &mut DtmfSquelch {
+        &mut self.squelch
+    }
+
+    /// The tuning in use.
+    pub fn config(&self) -> DtmfRelayConfig {
+        self.config
+    }
+}
+
+impl fmt::Debug for DtmfRelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtmfRelay")
+            .field("config", &self.config)
+            .field("position", &self.position)
+            .field("active", &self.active.map(|event| event.code))
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,214 @@
+//! Removing DTMF from an audio stream.
+//!
+//! A gateway that turns in-band DTMF into events should not also pass the
+//! tones through: a recording or the far end would still hear a dialled PIN.
+//! [`DtmfSquelch`] runs a [`DtmfRx`] on the audio and writes it back out
+//! with each tone replaced by silence or comfort noise, collecting the
+//! digits as it goes.
+//!
+//! The detector only reports a digit some tens of milliseconds into the
+//! tone, so the output is delayed by [`DtmfSquelchConfig::lookahead`]
+//! samples; that is what lets the start of the tone be blanked as well.
+//!
+//! ```no_run
+//! use spandsp::dtmf_squelch::{DtmfSquelch, DtmfSquelchConfig};
+//!
+//! let mut squelch = DtmfSquelch::new(DtmfSquelchConfig::default()).unwrap();
+//! let mut frame = [0i16; 160];
+//! squelch.process(&mut frame); // `frame` now holds delayed, cleaned audio
+//! let digits = squelch.take_digits();
+//! # let _ = digits;
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::dtmf::DtmfRx;
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::DBM0_MAX_SINE_POWER;
+
+/// Samples fed to the detector at a time, so its status is checked often.
+const DETECT_CHUNK: usize = 40;
+
+/// What replaces a squelched tone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SquelchFill {
+    /// Digital silence.
+    Silence,
+    /// White noise at this level, in dBm0.
+    ComfortNoise {
+        /// Noise level in dBm0.
+        level_dbm0: f32,
+    },
+}
+
+/// Tuning for [`DtmfSquelch`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DtmfSquelchConfig {
+    /// How far the output lags the input, in samples. Audio up to this far
+    /// before the detector fires is squelched too.
+    pub lookahead: usize,
+    /// Samples to keep squelching after the detector lets go, to cover the
+    /// tone's tail.
+    pub hangover: usize,
+    /// What to put in place of the tone.
+    pub fill: SquelchFill,
+}
+
+impl Default for DtmfSquelchConfig {
+    /// 60 ms lookahead and 40 ms hangover at 8 kHz, filled with silence.
+    fn default() -> Self {
+        Self {
+            lookahead: 480,
+            hangover: 320,
+            fill: SquelchFill::Silence,
+        }
+    }
+}
+
+/// A DTMF detector that also blanks the tones out of the audio.
+pub struct DtmfSquelch {
+    rx: DtmfRx,
+    config: DtmfSquelchConfig,
+    /// Delayed samples, each marked with the digit it is squelched for.
+    delay: VecDeque<(i16, Option<char>)>,
+    /// The digit last detected, and input samples still to squelch for it
+    /// after the detector let go.
+    hangover: (Option<char>, usize),
+    noise_amplitude: f32,
+    noise_seed: u32,
+    squelched: u64,
+}
+
+impl DtmfSquelch {
+    /// Create a squelch with its own detector.
+    pub fn new(config: DtmfSquelchConfig) -> Result<Self> {
+        Self::with_detector(DtmfRx::new()?, config)
+    }
+
+    /// Squelch with an existing detector, e.g. one already configured or
+    /// with a digit callback.
+    pub fn with_detector(rx: DtmfRx, config: DtmfSquelchConfig) -> Result<Self> {
+        let noise_amplitude = match config.fill {
+            SquelchFill::Silence => 0.0,
+            SquelchFill::ComfortNoise { level_dbm0 } => {
+                if !(level_dbm0.is_finite() && level_dbm0 <= DBM0_MAX_SINE_POWER) {
+                    return Err(SpanDspError::InvalidInput(format!(
+                        "comfort noise level must be at most {DBM0_MAX_SINE_POWER} dBm0, got {level_dbm0}"
+                    )));
+                }
+                // Uniform noise over +/-a has an RMS of a/sqrt(3).
+                let rms =
+                    32767.0 / 2f32.sqrt() * 10f32.powf((level_dbm0 - DBM0_MAX_SINE_POWER) / 20.0);
+                (rms * 3f32.sqrt()).min(32767.0)
+            }
+        };
+        Ok(Self {
+            rx,
+            config,
+            delay: VecDeque::with_capacity(config.lookahead + DETECT_CHUNK),
+            hangover: (None, 0),
+            noise_amplitude,
+            noise_seed: 0x1234_5678,
+            squelched: 0,
+        })
+    }
+
+    /// Run `amp` through the detector and replace it with the output, which
+    /// lags by [`lookahead`](DtmfSquelchConfig::lookahead) samples; the
+    /// first output is silence while the delay fills.
+    pub fn process(&mut self, amp: &mut [i16]) {
+        self.process_marked(amp, |_, _| {});
+    }
+
+    /// [`process`](Self::process), calling `on_output` for each output
+    /// sample with the audio it replaced and the digit it was squelched for.
+    pub(crate) fn process_marked<F>(&mut self, amp: &mut [i16], mut on_output: F)
+    where
+        F: FnMut(i16, Option<char>),
+    {
+        for chunk in amp.chunks_mut(DETECT_CHUNK) {
+            self.rx.rx(chunk);
+            let tone = self.rx.status().filter(|&digit| digit != 'x');
+            if let Some(digit) = tone {
+                // Reach back over the delay line to catch the tone's start.
+                for (_, squelch) in self.delay.iter_mut() {
+                    squelch.get_or_insert(digit);
+                }
+                self.hangover = (tone, self.config.hangover);
+            }
+            for sample in chunk.iter_mut() {
+                let squelch = match tone {
+                    Some(_) => tone,
+                    None if self.hangover.1 > 0 => {
+                        self.hangover.1 -= 1;
+                        self.hangover.0
+                    }
+                    None => None,
+                };
+                self.delay.push_back((*sample, squelch));
+                if self.delay.len() <= self.config.lookahead {
+                    *sample = 0;
+                    continue;
+                }
+                let (delayed, squelch) = self.delay.pop_front().unwrap_or_default();
+                on_output(delayed, squelch);
+                *sample = match squelch {
+                    Some(_) => {
+                        self.squelched += 1;
+                        self.fill()
+                    }
+                    None => delayed,
+                };
+            }
+        }
+    }
+
+    /// The digits detected since the last call.
+    pub fn take_digits(&mut self) -> String {
+        self.rx.get(128)
+    }
+
+    /// Output samples squelched so far.
+    pub fn squelched_samples(&self) -> u64 {
+        self.squelched
+    }
+
+    /// The detector, e.g. to read its status.
+    pub fn detector(&self) -> &DtmfRx {
+        &self.rx
+    }
+
+    /// The detector, e.g. to [`configure`](DtmfRx::configure) it.
+    pub fn detector_mut(&mut self) -> &mut DtmfRx {
+        &mut self.rx
+    }
+
+    /// The tuning in use.
+    pub fn config(&self) -> DtmfSquelchConfig {
+        self.config
+    }
+
+    fn fill(&mut self) -> i16 {
+        if self.noise_amplitude == 0.0 {
+            return 0;
+        }
+        // xorshift32
+        let mut x = self.noise_seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_seed = x;
+        let unit = x as f32 / u32::MAX as f32 * 2.0 - 1.0;
+        (unit * self.noise_amplitude) as i16
+    }
+}
+
+impl fmt::Debug for DtmfSquelch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DtmfSquelch")
+            .field("config", &self.config)
+            .field("squelched", &self.squelched)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,176 @@
+//! Discontinuous transmission (DTX) driven by a simple energy VAD.
+//!
+//! [`Dtx`] classifies each frame of linear audio as speech or silence. Speech
+//! frames are encoded as usual; during silence the encoder emits an
+//! occasional [`ComfortNoise`] (RFC 3389 SID) frame and otherwise nothing,
+//! so the far end can generate matching background noise.
+//!
+//! The codec wrappers expose this through `encode_dtx`, e.g.
+//! [`G711State::encode_dtx`](crate::g711::G711State::encode_dtx).
+
+use crate::power_meter::{DBM0_MAX_SINE_POWER, mean_square_dbm0};
+
+/// Lowest level representable in an RFC 3389 noise level byte, in -dBov.
+const CN_MIN_LEVEL: u8 = 127;
+
+/// Level of a full-scale square wave, in dBm0. RFC 3389 §3.1 references dBov
+/// to this, which is 3.01 dB above a full-scale sine.
+const DBM0_MAX_SQUARE_POWER: f32 = DBM0_MAX_SINE_POWER + 3.01;
+
+/// Tuning for the voice activity detector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DtxConfig {
+    /// Frames at or above this level (dBm0) count as speech.
+    pub threshold_dbm0: f32,
+    /// Number of frames to keep sending after speech stops, so word endings
+    /// are not clipped.
+    pub hangover_frames: u32,
+    /// Re-send a SID frame after this many silent frames even if the noise
+    /// level is unchanged. Zero sends SID only on entry to silence and when
+    /// the level moves.
+    pub sid_interval_frames: u32,
+    /// Change in noise level (dB) that triggers a fresh SID frame.
+    pub sid_level_delta_db: u8,
+}
+
+impl Default for DtxConfig {
+    fn default() -> Self {
+        Self {
+            threshold_dbm0: -45.0,
+            hangover_frames: 5,
+            sid_interval_frames: 0,
+            sid_level_delta_db: 3,
+        }
+    }
+}
+
+/// RFC 3389 comfort noise parameters.
+///
+/// Only the noise level is produced; the spectral reflection coefficients
+/// are omitted (model order 0), which the RFC permits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ComfortNoise {
+    /// Noise level in -dBov, 0..=127, where 0 dBov is a full-scale square
+    /// wave.
+    pub level: u8,
+}
+
+impl ComfortNoise {
+    /// The RFC 3389 payload for this frame.
+    pub fn to_payload(self) -> [u8; 1] {
+        [self.level]
+    }
+
+    /// Parse an RFC 3389 payload, ignoring any spectral coefficients.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        payload.first().map(|&level| Self {
+            level: level.min(CN_MIN_LEVEL),
+        })
+    }
+}
+
+/// What a DTX encode call produced for one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DtxFrame {
+    /// An encoded speech frame of this many bytes.
+    Voice(usize),
+    /// A silence descriptor; nothing was written to the output buffer.
+    Sid(ComfortNoise),
+    /// Silence with nothing to send.
+    NoFrame,
+}
+
+/// Voice activity detector and DTX state machine.
+#[derive(Debug, Clone)]
+pub struct Dtx {
+    config: DtxConfig,
+    hangover: u32,
+    in_silence: bool,
+    frames_since_sid: u32,
+    last_sid: Option<ComfortNoise>,
+}
+
+impl Dtx {
+    /// Create a DTX state with the given tuning.
+    pub fn new(config: DtxConfig) -> Self {
+        Self {
+            config,
+            hangover: 0,
+            in_silence: false,
+            frames_since_sid: 0,
+            last_sid: None,
+        }
+    }
+
+    /// Returns the tuning in use.
+    pub fn config(&self) -> DtxConfig {
+        self.config
+    }
+
+    /// Returns `true` if the last frame was treated as silence.
+    pub fn in_silence(&self) -> bool {
+        self.in_silence
+    }
+
+    /// Forget any speech/silence history, e.g. at the start of a new stream.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// Classify `amp` and either encode it with `encode` or produce a DTX
+    /// decision. `encode` must return the number of bytes it wrote.
+    pub fn process<F>(&mut self, amp: &[i16], encode: F) -> DtxFrame
+    where
+        F: FnOnce(&[i16]) -> usize,
+    {
+        let level = frame_level_dbm0(amp);
+        if level >= self.config.threshold_dbm0 {
+            self.hangover = self.config.hangover_frames;
+            self.in_silence = false;
+            return DtxFrame::Voice(encode(amp));
+        }
+        if self.hangover > 0 {
+            self.hangover -= 1;
+            return DtxFrame::Voice(encode(amp));
+        }
+
+        let cn = ComfortNoise {
+            level: dbm0_to_cn_level(level),
+        };
+        let entering = !self.in_silence;
+        self.in_silence = true;
+        self.frames_since_sid += 1;
+        let moved = self
+            .last_sid
+            .is_none_or(|last| last.level.abs_diff(cn.level) >= self.config.sid_level_delta_db);
+        let due = self.config.sid_interval_frames != 0
+            && self.frames_since_sid >= self.config.sid_interval_frames;
+        if entering || moved || due {
+            self.frames_since_sid = 0;
+            self.last_sid = Some(cn);
+            DtxFrame::Sid(cn)
+        } else {
+            DtxFrame::NoFrame
+        }
+    }
+}
+
+impl Default for Dtx {
+    fn default() -> Self {
+        Self::new(DtxConfig::default())
+    }
+}
+
+/// Mean power of a frame in dBm0 (a full-scale sine is +3.14 dBm0).
+pub fn frame_level_dbm0(amp: &[i16]) -> f32 {
+    if amp.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let sum_sq: f64 = amp.iter().map(|&s| f64::from(s) * f64::from(s)).sum();
+    mean_square_dbm0(sum_sq / amp.len() as f64)
+}
+
+fn dbm0_to_cn_level(dbm0: f32) -> u8 {
+    let dbov = dbm0 - DBM0_MAX_SQUARE_POWER;
+    (-dbov).round().clamp(0.0, f32::from(CN_MIN_LEVEL)) as u8
+}
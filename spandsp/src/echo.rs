@@ -53,6 +53,10 @@ impl fmt::Display for EchoCanFlags {
 /// internally and returns a pointer (or NULL on failure).
 pub struct EchoCanceller {
     ptr: NonNull<spandsp_sys::echo_can_state_t>,
+    len: i32,
+    flags: EchoCanFlags,
+    #[cfg(feature = "metrics")]
+    erle: crate::metrics::ErleWindow,
 }
 
 impl EchoCanceller {
@@ -63,7 +67,23 @@ impl EchoCanceller {
     pub fn new(len: i32, flags: EchoCanFlags) -> Result<Self> {
         let ptr = unsafe { spandsp_sys::echo_can_init(len as c_int, flags.bits() as c_int) };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            len,
+            flags,
+            #[cfg(feature = "metrics")]
+            erle: Default::default(),
+        })
+    }
+
+    /// Returns the tail length in samples.
+    pub fn tail_len(&self) -> i32 {
+        self.len
+    }
+
+    /// Returns the current adaption mode flags.
+    pub fn flags(&self) -> EchoCanFlags {
+        self.flags
     }
 
     /// Process a single sample pair through the echo canceller.
@@ -73,7 +93,10 @@ impl EchoCanceller {
     ///
     /// Returns the cleaned (echo-cancelled) receive sample.
     pub fn update(&mut self, tx: i16, rx: i16) -> i16 {
-        unsafe { spandsp_sys::echo_can_update(self.ptr.as_ptr(), tx, rx) }
+        let clean = unsafe { spandsp_sys::echo_can_update(self.ptr.as_ptr(), tx, rx) };
+        #[cfg(feature = "metrics")]
+        self.erle.push(rx, clean);
+        clean
     }
 
     /// Flush (reinitialise) the echo canceller, resetting the adaptive filter.
@@ -81,6 +104,8 @@ impl EchoCanceller {
         unsafe {
             spandsp_sys::echo_can_flush(self.ptr.as_ptr());
         }
+        #[cfg(feature = "metrics")]
+        self.erle.reset();
     }
 
     /// Change the adaption mode of the echo canceller.
@@ -88,6 +113,7 @@ impl EchoCanceller {
         unsafe {
             spandsp_sys::echo_can_adaption_mode(self.ptr.as_ptr(), flags.bits() as c_int);
         }
+        self.flags = flags;
     }
 
     /// Apply a high-pass filter to a transmit sample.
@@ -108,6 +134,15 @@ impl EchoCanceller {
     }
 }
 
+impl fmt::Debug for EchoCanceller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EchoCanceller")
+            .field("len", &self.len)
+            .field("flags", &self.flags)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for EchoCanceller {
     fn drop(&mut self) {
         unsafe {
@@ -3,17 +3,28 @@
 //! `FaxState` combines the T.30 protocol engine with FAX modems for
 //! analog line FAX operation.
 
+use std::fmt;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError};
-use crate::t30::T30State;
+use crate::fax_tones::{FaxTone, FaxToneWatch};
+use crate::t30::{
+    EcmEvent, FaxQualityReport, IdentDecision, IdentValidator, ReceivedPage, RemoteIdent,
+    RetrainPolicy, SessionHooks, T30InterruptSignal, T30ModemSupport, T30Snapshot, T30State,
+    TxDocument, TxDocumentQueue, install_ident_validator,
+};
 
 /// High-level analog FAX state wrapping `fax_state_t`.
 ///
 /// Created via `FaxState::new()`, freed on drop.
 pub struct FaxState {
     inner: NonNull<spandsp_sys::fax_state_t>,
+    calling_party: bool,
+    documents: Option<Box<TxDocumentQueue>>,
+    hooks: Box<SessionHooks>,
+    ident_validator: Option<Box<IdentValidator>>,
+    tone_watch: Option<FaxToneWatch>,
 }
 
 impl FaxState {
@@ -23,7 +34,15 @@ impl FaxState {
     pub fn new(calling_party: bool) -> Result<Self> {
         let ptr = unsafe { spandsp_sys::fax_init(std::ptr::null_mut(), calling_party) };
         let inner = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { inner })
+        let t30 = unsafe { spandsp_sys::fax_get_t30_state(ptr) };
+        Ok(Self {
+            inner,
+            calling_party,
+            documents: None,
+            hooks: unsafe { SessionHooks::install(t30) },
+            ident_validator: None,
+            tone_watch: None,
+        })
     }
 
     /// Get the raw pointer.
@@ -32,15 +51,222 @@ impl FaxState {
     }
 
     /// Get a (non-owned) handle to the T.30 protocol engine inside this FAX context.
-    pub fn get_t30_state(&self) -> Result<T30State> {
-        let ptr = unsafe { spandsp_sys::fax_get_t30_state(self.inner.as_ptr()) };
-        unsafe { T30State::from_raw(ptr, false) }
+    ///
+    /// The handle borrows the context mutably, since it drives the same
+    /// engine; see the crate-level notes on mutability.
+    pub fn get_t30_state(&mut self) -> Result<T30State<'_>> {
+        unsafe { T30State::from_raw(self.t30_ptr(), false) }
+    }
+
+    fn t30_ptr(&self) -> *mut spandsp_sys::t30_state_t {
+        unsafe { spandsp_sys::fax_get_t30_state(self.inner.as_ptr()) }
+    }
+
+    /// A T.30 handle for read-only queries from `&self`.
+    fn peek_t30(&self) -> Result<T30State<'_>> {
+        // SAFETY: the handle only lives for one query and is never handed
+        // out, so nothing can drive the engine through it.
+        unsafe { T30State::from_raw(self.t30_ptr(), false) }
+    }
+
+    /// Queue several documents to be sent back-to-back in one call.
+    ///
+    /// The first document replaces any file set with
+    /// [`T30State::set_tx_file`]. After its last page the engine signals EOM
+    /// rather than EOP, renegotiates, and continues with the next document;
+    /// EOP follows the final one.
+    pub fn set_tx_documents(&mut self, docs: impl IntoIterator<Item = TxDocument>) -> Result<()> {
+        let t30 = self.t30_ptr();
+        self.documents = Some(unsafe { TxDocumentQueue::install(t30, docs)? });
+        Ok(())
+    }
+
+    /// Number of queued documents that have not been started yet.
+    pub fn pending_tx_documents(&self) -> usize {
+        self.documents.as_ref().map_or(0, |q| q.pending())
+    }
+
+    /// Capture the resumable state of this call.
+    ///
+    /// Includes the T.30 snapshot, which side placed the call and any
+    /// documents from [`set_tx_documents`](Self::set_tx_documents) that were
+    /// not confirmed as sent.
+    pub fn snapshot(&self) -> Result<T30Snapshot> {
+        let mut snap = self.peek_t30()?.snapshot();
+        snap.calling_party = Some(self.calling_party);
+        if let Some(queue) = &self.documents {
+            snap.remaining_documents = queue.remaining(snap.pages_tx);
+        }
+        Ok(snap)
+    }
+
+    /// Prepare this context to carry on from `snapshot`.
+    ///
+    /// Restores the local identity and queues the remaining documents.
+    /// Negotiated parameters are renegotiated by the new call.
+    pub fn resume(&mut self, snapshot: &T30Snapshot) -> Result<()> {
+        if let Some(ident) = &snapshot.local_ident {
+            self.get_t30_state()?.set_tx_ident(ident)?;
+        }
+        if !snapshot.remaining_documents.is_empty() {
+            self.set_tx_documents(snapshot.remaining_documents.iter().cloned())?;
+        }
+        Ok(())
+    }
+
+    /// Call `handler` whenever a PIP/PIN or PRI-Q signal is exchanged.
+    ///
+    /// This occupies the T.30 phase D handler, replacing anything set with
+    /// `T30State::set_phase_d_handler_raw`. Pair it with
+    /// `T30State::request_local_interrupt` and
+    /// `T30State::set_remote_interrupts_allowed` to switch a call to voice.
+    pub fn set_interrupt_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(T30InterruptSignal) + Send + 'static,
+    {
+        self.hooks.set_interrupt_handler(Box::new(handler));
+    }
+
+    /// Call `handler` for each ECM control frame exchanged: partial page
+    /// signals, partial page requests, RNR and EOR.
+    ///
+    /// The same frames feed the ECM counts in
+    /// [`quality_report`](Self::quality_report), whether or not a handler
+    /// is set. This uses the T.30 real-time frame handler.
+    pub fn set_ecm_event_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(EcmEvent) + Send + 'static,
+    {
+        self.hooks.set_ecm_handler(Box::new(handler));
+    }
+
+    /// Receive into `rx_file`, as `T30State::set_rx_file` does, and call
+    /// `handler` with each page as soon as it has been received.
+    ///
+    /// At each page boundary (T.30 phase D), before the call ends, only the
+    /// bytes spandsp has just appended to the file are read back and
+    /// decoded to a bitmap, e.g. for progressive display or OCR, so the
+    /// cost per page does not grow with the document. The handler runs on
+    /// the thread driving the session and should hand heavy work off. A
+    /// page that cannot be read back is passed on as an error; the file
+    /// itself is unaffected.
+    pub fn set_received_page_handler<F>(
+        &mut self,
+        rx_file: &str,
+        stop_page: i32,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Result<ReceivedPage>) + Send + 'static,
+    {
+        self.hooks
+            .set_received_page_handler(rx_file, stop_page, Box::new(handler))
+    }
+
+    /// Screen the far end before any page is exchanged.
+    ///
+    /// `validator` sees the identity, sub-address and password the far end
+    /// sent in phase B; anything other than [`IdentDecision::Accept`] ends
+    /// the call with the matching completion code, e.g.
+    /// `T30_ERR_IDENT_UNACCEPTABLE`. This occupies the T.30 phase B handler.
+    pub fn set_ident_validator<F>(&mut self, validator: F)
+    where
+        F: FnMut(&RemoteIdent) -> IdentDecision + Send + 'static,
+    {
+        let t30 = self.t30_ptr();
+        self.ident_validator = Some(unsafe { install_ident_validator(t30, validator) });
+    }
+
+    /// Line quality of the pages exchanged so far.
+    ///
+    /// Pages are recorded at each page boundary through the T.30 phase D
+    /// handler; the report can be read during or after the call.
+    pub fn quality_report(&self) -> FaxQualityReport {
+        self.hooks.quality_report()
+    }
+
+    /// Set the modems the session may offer, returning the modems actually
+    /// offered once any [`set_max_bit_rate`](Self::set_max_bit_rate) cap is
+    /// applied.
+    ///
+    /// Use this rather than `T30State::set_supported_modems`, which goes
+    /// straight to the engine: the cap would be lost, and the next call to
+    /// `set_max_bit_rate` would put back the default modems.
+    pub fn set_supported_modems(&mut self, modems: T30ModemSupport) -> Result<T30ModemSupport> {
+        self.hooks.set_supported_modems(modems)
+    }
+
+    /// Cap the bit rate the session negotiates, or lift the cap with `None`.
+    ///
+    /// Modems from [`set_supported_modems`](Self::set_supported_modems)
+    /// whose top rate is above the cap are withdrawn (see
+    /// [`T30ModemSupport::capped_at`]) and the modems still offered are
+    /// returned. A cap that would withdraw all of them is refused. spandsp
+    /// offers V.27ter as a single modem, so a cap below its 4800 top rate
+    /// is refused too, even though the engine's own fallback can take
+    /// V.27ter down to 2400.
+    ///
+    /// The engine chooses a modem only when it negotiates: at the start of
+    /// the call and again whenever DIS is exchanged, e.g. between
+    /// documents. A cap set mid-call therefore applies from the next
+    /// negotiation, and the engine's own fallbacks stay under it from then
+    /// on.
+    pub fn set_max_bit_rate(&mut self, max_bit_rate: Option<u32>) -> Result<T30ModemSupport> {
+        self.hooks.set_max_bit_rate(max_bit_rate)
+    }
+
+    /// The bit rate cap set by [`set_max_bit_rate`](Self::set_max_bit_rate),
+    /// [`request_fallback`](Self::request_fallback) or the retrain policy.
+    pub fn max_bit_rate(&self) -> Option<u32> {
+        self.hooks.max_bit_rate()
+    }
+
+    /// Ask for a fallback when the line is worse than the engine thinks.
+    ///
+    /// Caps the bit rate at the top rate of the next slower modem than the
+    /// one in use, e.g. 9600 after a page at 14400, and returns the new
+    /// cap, or `None` when already on V.27ter.
+    ///
+    /// This does not force a retrain: spandsp has no call for one. The
+    /// cap applies from the engine's next negotiation, as for
+    /// [`set_max_bit_rate`](Self::set_max_bit_rate), so a call that
+    /// carries on with the same document keeps its current rate until the
+    /// engine retrains on its own, e.g. after RTN.
+    pub fn request_fallback(&mut self) -> Result<Option<u32>> {
+        self.hooks.request_fallback(0)
+    }
+
+    /// Fall back automatically after each page whose line quality crosses
+    /// `policy`, or leave it to the engine with `None`.
+    ///
+    /// Pages are judged by the same figures as
+    /// [`quality_report`](Self::quality_report).
+    pub fn set_retrain_policy(&mut self, policy: Option<RetrainPolicy>) {
+        self.hooks.set_retrain_policy(policy);
+    }
+
+    /// Call `handler` the first time CNG, CED or a V.21 preamble is heard
+    /// in the received audio, once per call.
+    ///
+    /// The engine finds these tones too but keeps them to itself; this runs
+    /// a separate [`FaxToneDetector`](crate::fax_tones::FaxToneDetector) on
+    /// the audio passed to [`rx`](Self::rx) until the first tone.
+    /// [`restart`](Self::restart) listens again.
+    pub fn set_fax_tone_handler<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: FnMut(FaxTone) + Send + 'static,
+    {
+        self.tone_watch = Some(FaxToneWatch::new(Box::new(handler))?);
+        Ok(())
     }
 
     /// Process received audio samples through the FAX engine.
     ///
     /// Returns the number of unprocessed samples (non-zero means end of call).
-    pub fn rx(&self, samples: &mut [i16]) -> usize {
+    pub fn rx(&mut self, samples: &mut [i16]) -> usize {
+        if let Some(watch) = &mut self.tone_watch {
+            watch.rx(samples);
+        }
         unsafe {
             spandsp_sys::fax_rx(
                 self.inner.as_ptr(),
@@ -53,27 +279,50 @@ impl FaxState {
     /// Generate transmit audio samples.
     ///
     /// Returns the number of samples generated (0 when nothing to send).
-    pub fn tx(&self, buf: &mut [i16]) -> usize {
+    pub fn tx(&mut self, buf: &mut [i16]) -> usize {
         unsafe {
             spandsp_sys::fax_tx(self.inner.as_ptr(), buf.as_mut_ptr(), buf.len() as c_int) as usize
         }
     }
 
     /// Select whether silent audio is sent when FAX transmit is idle.
-    pub fn set_transmit_on_idle(&self, on: bool) {
+    pub fn set_transmit_on_idle(&mut self, on: bool) {
         unsafe {
             spandsp_sys::fax_set_transmit_on_idle(self.inner.as_ptr(), on as c_int);
         }
     }
 
     /// Restart the FAX context.
-    pub fn restart(&self, calling_party: bool) -> Result<()> {
+    pub fn restart(&mut self, calling_party: bool) -> Result<()> {
         let rc = unsafe { spandsp_sys::fax_restart(self.inner.as_ptr(), calling_party) };
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
         }
+        self.calling_party = calling_party;
+        self.hooks.clear_quality();
+        if let Some(watch) = &mut self.tone_watch {
+            watch.rearm()?;
+        }
         Ok(())
     }
+
+    /// Returns `true` if this context was set up as the calling party.
+    pub fn calling_party(&self) -> bool {
+        self.calling_party
+    }
+}
+
+impl fmt::Debug for FaxState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaxState")
+            .field("calling_party", &self.calling_party)
+            .field("pending_tx_documents", &self.pending_tx_documents())
+            .field(
+                "call_active",
+                &self.peek_t30().map(|t30| t30.call_active()).ok(),
+            )
+            .finish_non_exhaustive()
+    }
 }
 
 // SAFETY: FaxState wraps a SpanDSP fax_state_t that is only accessed through
@@ -6,6 +6,7 @@
 //! significant effort and is left for future work.
 
 /// Placeholder for FAX modem state.
+#[derive(Debug)]
 pub struct FaxModemsState {
     _private: (),
 }
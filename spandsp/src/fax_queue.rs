@@ -0,0 +1,481 @@
+//! Outbound FAX job queue.
+//!
+//! [`FaxJobQueue`] is the bookkeeping a fax server wraps around the session
+//! API: jobs are submitted with a destination, documents, a
+//! [`RetryPolicy`] and an optional start time, and [`FaxJobQueue::run_due`]
+//! hands each job that is due to a caller-provided [`FaxCarrier`], which
+//! dials, runs a `FaxState` or `T38Terminal` and reports how the call went.
+//! Failed calls are rescheduled with backoff; a session that failed part way
+//! through resumes from the pages it had not yet sent.
+//!
+//! Jobs are plain data. With the `serde` feature they can be written out on
+//! every state change (see [`FaxJobQueue::set_transition_handler`]) and
+//! restored with [`FaxJobQueue::from_jobs`] after a restart.
+//!
+//! ```no_run
+//! use std::time::SystemTime;
+//! use spandsp::fax_queue::{CallOutcome, FaxJob, FaxJobQueue};
+//! use spandsp::t30::TxDocument;
+//!
+//! let mut queue = FaxJobQueue::new();
+//! queue
+//!     .submit(FaxJob::new("+15551234", vec![TxDocument::new("invoice.tif")]))
+//!     .unwrap();
+//! queue.run_due(SystemTime::now(), &mut |job: &FaxJob| {
+//!     // Dial job.destination(), send job.documents() ...
+//!     CallOutcome::Busy
+//! });
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::error::{Result, SpanDspError, T30Error};
+use crate::t30::{T30Snapshot, TxDocument};
+
+// ---------------------------------------------------------------------------
+// Jobs
+// ---------------------------------------------------------------------------
+
+/// Identifies a job within its queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JobId(pub u64);
+
+impl fmt::Display for JobId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job {}", self.0)
+    }
+}
+
+/// How often, and how far apart, a failed job is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetryPolicy {
+    /// Calls to place before giving up, counting the first.
+    pub max_attempts: u32,
+    /// Wait after the first failed call.
+    pub initial_delay: Duration,
+    /// Factor the wait grows by after each further failure.
+    pub backoff: u32,
+    /// Longest wait between calls. A job whose next call would fall
+    /// beyond what `SystemTime` can hold fails instead.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, five minutes apart and doubling, at most an hour.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_secs(5 * 60),
+            backoff: 2,
+            max_delay: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn once() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The wait after `failures` failed calls (1 for the first).
+    pub fn delay_after(&self, failures: u32) -> Duration {
+        let factor = self
+            .backoff
+            .max(1)
+            .saturating_pow(failures.saturating_sub(1));
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+/// Where a job stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JobState {
+    /// Waiting for its next attempt.
+    Scheduled,
+    /// A call is being placed; `attempt` counts from 1.
+    InProgress {
+        /// Which attempt this is.
+        attempt: u32,
+    },
+    /// Every document was delivered.
+    Completed,
+    /// Gave up, after a permanent error or the last allowed attempt.
+    Failed,
+    /// Cancelled before it finished.
+    Cancelled,
+}
+
+impl JobState {
+    /// Whether the job will not be tried again.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// How a call attempt went, as reported by the [`FaxCarrier`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallOutcome {
+    /// The line was busy.
+    Busy,
+    /// Nobody answered, or no FAX answered.
+    NoAnswer,
+    /// The call could not be placed.
+    ConnectFailed(String),
+    /// A FAX session ran; the snapshot taken when it ended (see
+    /// `FaxState::snapshot`) gives its status and what was left unsent.
+    Session(T30Snapshot),
+}
+
+impl CallOutcome {
+    /// Whether every document went through.
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Session(s) if s.completion().is_some_and(T30Error::is_ok))
+    }
+
+    /// Whether trying again cannot help: the documents are unreadable, or
+    /// the far end cannot or will not take them.
+    pub fn is_permanent_failure(&self) -> bool {
+        use spandsp_sys::t30_err_e::*;
+        let Self::Session(snapshot) = self else {
+            return false;
+        };
+        snapshot.completion().is_some_and(|err| {
+            matches!(
+                err.raw(),
+                T30_ERR_INCOMPATIBLE
+                    | T30_ERR_RX_INCAPABLE
+                    | T30_ERR_NORESSUPPORT
+                    | T30_ERR_NOSIZESUPPORT
+                    | T30_ERR_FILEERROR
+                    | T30_ERR_NOPAGE
+                    | T30_ERR_BADTIFF
+                    | T30_ERR_BADPAGE
+                    | T30_ERR_BADTAG
+                    | T30_ERR_BADTIFFHDR
+                    | T30_ERR_IDENT_UNACCEPTABLE
+                    | T30_ERR_SUB_UNACCEPTABLE
+                    | T30_ERR_SEP_UNACCEPTABLE
+                    | T30_ERR_PSA_UNACCEPTABLE
+                    | T30_ERR_SID_UNACCEPTABLE
+                    | T30_ERR_PWD_UNACCEPTABLE
+                    | T30_ERR_TSA_UNACCEPTABLE
+                    | T30_ERR_IRA_UNACCEPTABLE
+                    | T30_ERR_CIA_UNACCEPTABLE
+                    | T30_ERR_ISP_UNACCEPTABLE
+                    | T30_ERR_CSA_UNACCEPTABLE
+            )
+        })
+    }
+}
+
+/// One call placed for a job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attempt {
+    /// When the call was placed.
+    pub started: SystemTime,
+    /// How it went.
+    pub outcome: CallOutcome,
+}
+
+/// An outbound FAX and its history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaxJob {
+    id: JobId,
+    destination: String,
+    documents: Vec<TxDocument>,
+    retry: RetryPolicy,
+    not_before: Option<SystemTime>,
+    state: JobState,
+    attempts: Vec<Attempt>,
+}
+
+impl FaxJob {
+    /// A job sending `documents` to `destination` as soon as possible, with
+    /// the default retry policy.
+    pub fn new(destination: impl Into<String>, documents: Vec<TxDocument>) -> Self {
+        Self {
+            id: JobId(0),
+            destination: destination.into(),
+            documents,
+            retry: RetryPolicy::default(),
+            not_before: None,
+            state: JobState::Scheduled,
+            attempts: Vec::new(),
+        }
+    }
+
+    /// Use `retry` instead of the default policy.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Hold the first attempt until `time`.
+    pub fn not_before(mut self, time: SystemTime) -> Self {
+        self.not_before = Some(time);
+        self
+    }
+
+    /// The job's ID, assigned on submission.
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    /// Number or address to call; its meaning is up to the carrier.
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Documents still to send. After a session that failed part way
+    /// through, only the unsent remainder.
+    pub fn documents(&self) -> &[TxDocument] {
+        &self.documents
+    }
+
+    /// The retry policy.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// Where the job stands.
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    /// When the next attempt is due. `None` means straight away for a
+    /// scheduled job, and never for any other.
+    pub fn next_attempt(&self) -> Option<SystemTime> {
+        self.not_before
+            .filter(|_| self.state == JobState::Scheduled)
+    }
+
+    /// Every call placed so far, oldest first.
+    pub fn attempts(&self) -> &[Attempt] {
+        &self.attempts
+    }
+
+    fn is_due(&self, now: SystemTime) -> bool {
+        self.state == JobState::Scheduled && self.not_before.is_none_or(|t| t <= now)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Queue
+// ---------------------------------------------------------------------------
+
+/// Places the calls for a [`FaxJobQueue`].
+///
+/// An implementation dials [`FaxJob::destination`], sends
+/// [`FaxJob::documents`] (e.g. with `FaxState::set_tx_documents`) and
+/// returns once the call has ended. Closures taking `&FaxJob` implement it.
+pub trait FaxCarrier {
+    /// Place one call for `job` and report how it went.
+    fn place_call(&mut self, job: &FaxJob) -> CallOutcome;
+}
+
+impl<F> FaxCarrier for F
+where
+    F: FnMut(&FaxJob) -> CallOutcome,
+{
+    fn place_call(&mut self, job: &FaxJob) -> CallOutcome {
+        self(job)
+    }
+}
+
+type TransitionCallback = Box<dyn FnMut(&FaxJob, JobState) + Send>;
+
+/// Outbound FAX jobs, scheduled and retried.
+#[derive(Default)]
+pub struct FaxJobQueue {
+    jobs: BTreeMap<JobId, FaxJob>,
+    next_id: u64,
+    on_transition: Option<TransitionCallback>,
+}
+
+impl FaxJobQueue {
+    /// An empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuild a queue from saved jobs. Jobs that were mid-call when saved
+    /// are scheduled again straight away.
+    pub fn from_jobs(jobs: impl IntoIterator<Item = FaxJob>) -> Self {
+        let mut queue = Self::new();
+        for mut job in jobs {
+            if let JobState::InProgress { .. } = job.state {
+                job.state = JobState::Scheduled;
+                job.not_before = None;
+            }
+            queue.next_id = queue.next_id.max(job.id.0 + 1);
+            queue.jobs.insert(job.id, job);
+        }
+        queue
+    }
+
+    /// Call `handler` after every state change with the job as it now is
+    /// and the state it left, e.g. to persist it.
+    pub fn set_transition_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&FaxJob, JobState) + Send + 'static,
+    {
+        self.on_transition = Some(Box::new(handler));
+    }
+
+    /// Add a job, returning its ID.
+    ///
+    /// Returns `InvalidInput` for a job with no destination or documents,
+    /// or a retry policy allowing no attempts.
+    pub fn submit(&mut self, mut job: FaxJob) -> Result<JobId> {
+        if job.destination.is_empty() || job.documents.is_empty() {
+            return Err(SpanDspError::InvalidInput(
+                "a FAX job needs a destination and at least one document".into(),
+            ));
+        }
+        if job.retry.max_attempts == 0 {
+            return Err(SpanDspError::InvalidInput(
+                "retry policy must allow at least one attempt".into(),
+            ));
+        }
+        let id = JobId(self.next_id);
+        self.next_id += 1;
+        job.id = id;
+        job.state = JobState::Scheduled;
+        job.attempts.clear();
+        self.jobs.insert(id, job);
+        Ok(id)
+    }
+
+    /// Cancel a job that has not finished.
+    pub fn cancel(&mut self, id: JobId) -> Result<()> {
+        let job = self
+            .jobs
+            .get_mut(&id)
+            .ok_or_else(|| SpanDspError::InvalidInput(format!("no {id}")))?;
+        if job.state.is_finished() {
+            return Err(SpanDspError::InvalidInput(format!("{id} has finished")));
+        }
+        transition(job, JobState::Cancelled, &mut self.on_transition);
+        Ok(())
+    }
+
+    /// Look up a job.
+    pub fn job(&self, id: JobId) -> Option<&FaxJob> {
+        self.jobs.get(&id)
+    }
+
+    /// Every job, in submission order.
+    pub fn jobs(&self) -> impl Iterator<Item = &FaxJob> {
+        self.jobs.values()
+    }
+
+    /// Number of jobs not yet finished.
+    pub fn pending(&self) -> usize {
+        self.jobs
+            .values()
+            .filter(|j| !j.state.is_finished())
+            .count()
+    }
+
+    /// When the earliest scheduled job is due, or `None` if nothing is
+    /// scheduled. A job due straight away reports `UNIX_EPOCH`.
+    pub fn next_due(&self) -> Option<SystemTime> {
+        self.jobs
+            .values()
+            .filter(|j| j.state == JobState::Scheduled)
+            .map(|j| j.not_before.unwrap_or(SystemTime::UNIX_EPOCH))
+            .min()
+    }
+
+    /// Place a call for every job due at `now`, earliest first, returning
+    /// the number of calls placed.
+    pub fn run_due(&mut self, now: SystemTime, carrier: &mut impl FaxCarrier) -> usize {
+        let mut due: Vec<_> = self
+            .jobs
+            .values()
+            .filter(|j| j.is_due(now))
+            .map(|j| (j.not_before, j.id))
+            .collect();
+        due.sort();
+        for &(_, id) in &due {
+            let Some(job) = self.jobs.get_mut(&id) else {
+                continue;
+            };
+            let attempt = job.attempts.len() as u32 + 1;
+            transition(
+                job,
+                JobState::InProgress { attempt },
+                &mut self.on_transition,
+            );
+            let outcome = carrier.place_call(job);
+            // A retry time past what SystemTime can hold is never reached,
+            // so give up rather than overflow.
+            let retry_at = now.checked_add(job.retry.delay_after(attempt));
+            let next = if outcome.is_success() {
+                JobState::Completed
+            } else if outcome.is_permanent_failure() || attempt >= job.retry.max_attempts {
+                JobState::Failed
+            } else if let Some(retry_at) = retry_at {
+                job.not_before = Some(retry_at);
+                JobState::Scheduled
+            } else {
+                JobState::Failed
+            };
+            if let CallOutcome::Session(snapshot) = &outcome
+                && !snapshot.remaining_documents.is_empty()
+            {
+                job.documents = snapshot.remaining_documents.clone();
+            }
+            job.attempts.push(Attempt {
+                started: now,
+                outcome,
+            });
+            transition(job, next, &mut self.on_transition);
+        }
+        due.len()
+    }
+
+    /// Remove and return the finished jobs, e.g. once they are archived.
+    pub fn take_finished(&mut self) -> Vec<FaxJob> {
+        let finished: Vec<JobId> = self
+            .jobs
+            .values()
+            .filter(|j| j.state.is_finished())
+            .map(|j| j.id)
+            .collect();
+        finished
+            .into_iter()
+            .filter_map(|id| self.jobs.remove(&id))
+            .collect()
+    }
+}
+
+fn transition(job: &mut FaxJob, state: JobState, hook: &mut Option<TransitionCallback>) {
+    let previous = std::mem::replace(&mut job.state, state);
+    if let Some(hook) = hook {
+        hook(job, previous);
+    }
+}
+
+impl fmt::Debug for FaxJobQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaxJobQueue")
+            .field("jobs", &self.jobs.len())
+            .field("pending", &self.pending())
+            .finish_non_exhaustive()
+    }
+}
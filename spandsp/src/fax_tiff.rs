@@ -0,0 +1,436 @@
+//! Just enough TIFF to read and write bilevel fax pages.
+//!
+//! Handles the files spandsp and common fax software write: one image per
+//! IFD, CCITT-compressed (Modified Huffman, T.4 or T.6) in one or more
+//! strips. The strips are returned as they are, MSB first, without decoding.
+//! Written files are little-endian with one strip per page, in the layout
+//! RFC 3949 asks for.
+
+use std::collections::BTreeMap;
+
+use crate::error::{Result, SpanDspError};
+
+pub(crate) const TAG_NEW_SUBFILE_TYPE: u16 = 254;
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+pub(crate) const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+pub(crate) const TAG_PHOTOMETRIC: u16 = 262;
+pub(crate) const TAG_FILL_ORDER: u16 = 266;
+const TAG_STRIP_OFFSETS: u16 = 273;
+pub(crate) const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+pub(crate) const TAG_X_RESOLUTION: u16 = 282;
+pub(crate) const TAG_Y_RESOLUTION: u16 = 283;
+pub(crate) const TAG_T4_OPTIONS: u16 = 292;
+const TAG_T6_OPTIONS: u16 = 293;
+pub(crate) const TAG_RESOLUTION_UNIT: u16 = 296;
+pub(crate) const TAG_PAGE_NUMBER: u16 = 297;
+const TAG_BAD_FAX_LINES: u16 = 326;
+const TAG_CLEAN_FAX_DATA: u16 = 327;
+const TAG_CONSECUTIVE_BAD_FAX_LINES: u16 = 328;
+
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+/// T4Options bit: rows may be coded two-dimensionally.
+const T4_OPTION_2D: u32 = 0x01;
+/// T4Options bit: fill bits byte-align each EOL.
+const T4_OPTION_FILL_BITS: u32 = 0x04;
+
+/// How a page's image data is coded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum CcittCoding {
+    /// TIFF compression 2: Modified Huffman, each row byte-aligned, no EOLs.
+    ModifiedHuffman,
+    /// T.4, with or without 2-D rows and byte-aligned EOLs.
+    T4 { two_d: bool, byte_aligned: bool },
+    /// T.6 (MMR).
+    T6,
+}
+
+/// One page of a fax TIFF.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TiffPage {
+    pub(crate) width: u32,
+    pub(crate) length: u32,
+    pub(crate) coding: CcittCoding,
+    /// White is 1 (photometric BlackIsZero) rather than the usual 0.
+    pub(crate) inverted: bool,
+    pub(crate) x_dpi: f32,
+    pub(crate) y_dpi: f32,
+    /// The strips concatenated, MSB first.
+    pub(crate) data: Vec<u8>,
+    /// Rows received damaged, from the BadFaxLines tag.
+    pub(crate) bad_rows: u32,
+    /// Longest run of damaged rows, from ConsecutiveBadFaxLines.
+    pub(crate) longest_bad_row_run: u32,
+}
+
+/// The tags of one IFD, as integers. Rationals give numerator and
+/// denominator in turn; tags of other types have no values.
+pub(crate) type Tags = BTreeMap<u16, Vec<u32>>;
+
+/// Read every page of a fax TIFF.
+pub(crate) fn read_pages(file: &[u8]) -> Result<Vec<TiffPage>> {
+    read_ifds(file)?
+        .iter()
+        .map(|tags| page(file, tags))
+        .collect()
+}
+
+/// Read the tags of every IFD in a TIFF.
+pub(crate) fn read_ifds(file: &[u8]) -> Result<Vec<Tags>> {
+    let reader = Reader::new(file)?;
+    let mut ifds = Vec::new();
+    let mut offset = reader.u32_at(4)?;
+    while offset != 0 {
+        if ifds.len() > 10_000 {
+            return Err(malformed("IFD chain does not end"));
+        }
+        let (tags, next) = reader.ifd(offset as usize)?;
+        ifds.push(tags);
+        offset = next;
+    }
+    if ifds.is_empty() {
+        return Err(malformed("no pages"));
+    }
+    Ok(ifds)
+}
+
+/// The first value of `tag`, if present.
+pub(crate) fn first(tags: &Tags, tag: u16) -> Option<u32> {
+    tags.get(&tag).and_then(|values| values.first().copied())
+}
+
+/// Build the page an IFD describes, taking its strips from `file`.
+pub(crate) fn page(file: &[u8], tags: &Tags) -> Result<TiffPage> {
+    let reader = Reader {
+        file,
+        base: 0,
+        big_endian: false,
+    };
+    page_from(&reader, tags)
+}
+
+/// Where [`read_appended_page`] got to in a TIFF that is still being
+/// written.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AppendCursor {
+    big_endian: bool,
+    /// Offset of the pointer to the next IFD.
+    link: u64,
+    /// Bytes of the file read so far.
+    end: u64,
+}
+
+/// Read the next page of a fax TIFF that a writer appends to page by page,
+/// as libtiff does.
+///
+/// Each page's strips and IFD come after everything written before them,
+/// so only the bytes added since the previous page are read. `cursor`
+/// starts as `None` and is kept between calls.
+pub(crate) fn read_appended_page(
+    file: &mut std::fs::File,
+    cursor: &mut Option<AppendCursor>,
+) -> Result<TiffPage> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let io = |err: std::io::Error| SpanDspError::InvalidInput(format!("reading fax TIFF: {err}"));
+    let mut at = match *cursor {
+        Some(at) => at,
+        None => {
+            let mut header = [0u8; 8];
+            file.seek(SeekFrom::Start(0)).map_err(io)?;
+            file.read_exact(&mut header).map_err(io)?;
+            let reader = Reader::new(&header)?;
+            AppendCursor {
+                big_endian: reader.big_endian,
+                link: 4,
+                end: header.len() as u64,
+            }
+        }
+    };
+    let mut link = [0u8; 4];
+    file.seek(SeekFrom::Start(at.link)).map_err(io)?;
+    file.read_exact(&mut link).map_err(io)?;
+    let ifd = match at.big_endian {
+        true => u32::from_be_bytes(link),
+        false => u32::from_le_bytes(link),
+    };
+    if ifd == 0 {
+        return Err(malformed("page not written yet"));
+    }
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(at.end)).map_err(io)?;
+    file.read_to_end(&mut tail).map_err(io)?;
+    let reader = Reader {
+        file: &tail,
+        base: at.end as usize,
+        big_endian: at.big_endian,
+    };
+    let (tags, _) = reader.ifd(ifd as usize)?;
+    let page = page_from(&reader, &tags)?;
+    let entries = reader.u16_at(ifd as usize)?;
+    at.link = u64::from(ifd) + 2 + u64::from(entries) * 12;
+    at.end += tail.len() as u64;
+    *cursor = Some(at);
+    Ok(page)
+}
+
+fn page_from(reader: &Reader<'_>, tags: &Tags) -> Result<TiffPage> {
+    let rational = |tag| match tags.get(&tag).map(Vec::as_slice) {
+        Some(&[num, den, ..]) if den != 0 => Some(num as f32 / den as f32),
+        _ => None,
+    };
+    let width = first(tags, TAG_IMAGE_WIDTH)
+        .filter(|&w| w > 0)
+        .ok_or_else(|| malformed("no width"))?;
+    let length = first(tags, TAG_IMAGE_LENGTH)
+        .filter(|&l| l > 0)
+        .ok_or_else(|| malformed("no length"))?;
+    let t4_options = first(tags, TAG_T4_OPTIONS).unwrap_or(0);
+    let coding = match first(tags, TAG_COMPRESSION).unwrap_or(1) {
+        2 => CcittCoding::ModifiedHuffman,
+        3 => CcittCoding::T4 {
+            two_d: t4_options & T4_OPTION_2D != 0,
+            byte_aligned: t4_options & T4_OPTION_FILL_BITS != 0,
+        },
+        4 => CcittCoding::T6,
+        other => {
+            return Err(SpanDspError::InvalidInput(format!(
+                "fax TIFF page uses compression {other}, not CCITT"
+            )));
+        }
+    };
+    let offsets = tags.get(&TAG_STRIP_OFFSETS).map_or(&[][..], Vec::as_slice);
+    let counts = tags
+        .get(&TAG_STRIP_BYTE_COUNTS)
+        .map_or(&[][..], Vec::as_slice);
+    if offsets.is_empty() || offsets.len() != counts.len() {
+        return Err(malformed("strip tables missing or mismatched"));
+    }
+    let mut data = Vec::new();
+    for (&offset, &count) in offsets.iter().zip(counts) {
+        data.extend_from_slice(reader.bytes(offset as usize, count as usize)?);
+    }
+    if first(tags, TAG_FILL_ORDER) == Some(2) {
+        data.iter_mut().for_each(|b| *b = b.reverse_bits());
+    }
+    // Fax resolutions default to standard: 204 x 98 dpi.
+    let per_inch = if first(tags, TAG_RESOLUTION_UNIT) == Some(3) {
+        2.54
+    } else {
+        1.0
+    };
+    Ok(TiffPage {
+        width,
+        length,
+        coding,
+        inverted: first(tags, TAG_PHOTOMETRIC) == Some(1),
+        x_dpi: rational(TAG_X_RESOLUTION).map_or(204.0, |r| r * per_inch),
+        y_dpi: rational(TAG_Y_RESOLUTION).map_or(98.0, |r| r * per_inch),
+        data,
+        bad_rows: first(tags, TAG_BAD_FAX_LINES).unwrap_or(0),
+        longest_bad_row_run: first(tags, TAG_CONSECUTIVE_BAD_FAX_LINES).unwrap_or(0),
+    })
+}
+
+/// Write `pages` as a multi-page fax TIFF. With `line_quality`, each page
+/// also records its bad rows in the TIFF Class F tags.
+pub(crate) fn write_pages(pages: &[TiffPage], line_quality: bool) -> Vec<u8> {
+    let mut out = b"II*\0\0\0\0\0".to_vec();
+    let mut link = 4;
+    for (number, page) in pages.iter().enumerate() {
+        let strip = out.len() as u32;
+        out.extend_from_slice(&page.data);
+        align(&mut out);
+        let x_res = out.len() as u32;
+        push_rational(&mut out, page.x_dpi);
+        let y_res = out.len() as u32;
+        push_rational(&mut out, page.y_dpi);
+
+        let (compression, options) = match page.coding {
+            CcittCoding::ModifiedHuffman => (2, None),
+            CcittCoding::T4 {
+                two_d,
+                byte_aligned,
+            } => {
+                let mut options = 0;
+                if two_d {
+                    options |= T4_OPTION_2D;
+                }
+                if byte_aligned {
+                    options |= T4_OPTION_FILL_BITS;
+                }
+                (3, Some((TAG_T4_OPTIONS, options)))
+            }
+            CcittCoding::T6 => (4, Some((TAG_T6_OPTIONS, 0))),
+        };
+        let page_number = number as u32 | ((pages.len() as u32) << 16);
+        // (tag, type, count, value or offset), in ascending tag order.
+        let mut entries: Vec<(u16, u16, u32, u32)> = vec![
+            (TAG_NEW_SUBFILE_TYPE, TYPE_LONG, 1, 2),
+            (TAG_IMAGE_WIDTH, TYPE_LONG, 1, page.width),
+            (TAG_IMAGE_LENGTH, TYPE_LONG, 1, page.length),
+            (TAG_BITS_PER_SAMPLE, TYPE_SHORT, 1, 1),
+            (TAG_COMPRESSION, TYPE_SHORT, 1, compression),
+            (TAG_PHOTOMETRIC, TYPE_SHORT, 1, u32::from(page.inverted)),
+            (TAG_FILL_ORDER, TYPE_SHORT, 1, 1),
+            (TAG_STRIP_OFFSETS, TYPE_LONG, 1, strip),
+            (TAG_SAMPLES_PER_PIXEL, TYPE_SHORT, 1, 1),
+            (TAG_ROWS_PER_STRIP, TYPE_LONG, 1, page.length),
+            (TAG_STRIP_BYTE_COUNTS, TYPE_LONG, 1, page.data.len() as u32),
+            (TAG_X_RESOLUTION, TYPE_RATIONAL, 1, x_res),
+            (TAG_Y_RESOLUTION, TYPE_RATIONAL, 1, y_res),
+        ];
+        if let Some((tag, value)) = options {
+            entries.push((tag, TYPE_LONG, 1, value));
+        }
+        entries.push((TAG_RESOLUTION_UNIT, TYPE_SHORT, 1, 2));
+        entries.push((TAG_PAGE_NUMBER, TYPE_SHORT, 2, page_number));
+        if line_quality {
+            // CleanFaxData: 0 clean, 1 damaged rows regenerated.
+            let clean = u32::from(page.bad_rows > 0);
+            entries.push((TAG_BAD_FAX_LINES, TYPE_LONG, 1, page.bad_rows));
+            entries.push((TAG_CLEAN_FAX_DATA, TYPE_SHORT, 1, clean));
+            entries.push((
+                TAG_CONSECUTIVE_BAD_FAX_LINES,
+                TYPE_LONG,
+                1,
+                page.longest_bad_row_run,
+            ));
+        }
+
+        let ifd = out.len() as u32;
+        out[link..link + 4].copy_from_slice(&ifd.to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in entries {
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&kind.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+            // Little-endian, so short values land left-justified as required.
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        link = out.len();
+        out.extend_from_slice(&0u32.to_le_bytes());
+    }
+    out
+}
+
+/// Pad to the word boundary TIFF offsets need.
+fn align(out: &mut Vec<u8>) {
+    if out.len() % 2 != 0 {
+        out.push(0);
+    }
+}
+
+fn push_rational(out: &mut Vec<u8>, value: f32) {
+    let hundredths = (value * 100.0).round() as u32;
+    let (num, den) = if hundredths % 100 == 0 {
+        (hundredths / 100, 1u32)
+    } else {
+        (hundredths, 100)
+    };
+    out.extend_from_slice(&num.to_le_bytes());
+    out.extend_from_slice(&den.to_le_bytes());
+}
+
+fn malformed(what: &str) -> SpanDspError {
+    SpanDspError::InvalidInput(format!("malformed fax TIFF: {what}"))
+}
+
+struct Reader<'a> {
+    file: &'a [u8],
+    /// File offset of `file[0]`, when only the end of the file is held.
+    base: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(file: &'a [u8]) -> Result<Self> {
+        let big_endian = match file.get(..2) {
+            Some(b"II") => false,
+            Some(b"MM") => true,
+            _ => return Err(malformed("bad byte-order mark")),
+        };
+        let reader = Self {
+            file,
+            base: 0,
+            big_endian,
+        };
+        if reader.u16_at(2)? != 42 {
+            return Err(malformed("bad magic number"));
+        }
+        Ok(reader)
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        let start = offset
+            .checked_sub(self.base)
+            .ok_or_else(|| malformed("offset out of range"))?;
+        start
+            .checked_add(len)
+            .and_then(|end| self.file.get(start..end))
+            .ok_or_else(|| malformed("offset out of range"))
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16> {
+        let b: [u8; 2] = self.bytes(offset, 2)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32> {
+        let b: [u8; 4] = self.bytes(offset, 4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    /// The values of the IFD entry at `entry`, as integers. Rationals give
+    /// numerator and denominator in turn.
+    fn values(&self, entry: usize) -> Result<Vec<u32>> {
+        let kind = self.u16_at(entry + 2)?;
+        let count = self.u32_at(entry + 4)? as usize;
+        let (items, size) = match kind {
+            TYPE_SHORT => (count, 2),
+            TYPE_LONG => (count, 4),
+            TYPE_RATIONAL => (count.saturating_mul(2), 4),
+            _ => return Ok(Vec::new()),
+        };
+        let total = items
+            .checked_mul(size)
+            .ok_or_else(|| malformed("oversized tag"))?;
+        let start = if total <= 4 {
+            entry + 8
+        } else {
+            self.u32_at(entry + 8)? as usize
+        };
+        self.bytes(start, total)?;
+        (0..items)
+            .map(|i| match kind {
+                TYPE_SHORT => self.u16_at(start + i * 2).map(u32::from),
+                _ => self.u32_at(start + i * 4),
+            })
+            .collect()
+    }
+
+    fn ifd(&self, ifd: usize) -> Result<(Tags, u32)> {
+        let entries = self.u16_at(ifd)? as usize;
+        let mut tags = Tags::new();
+        for i in 0..entries {
+            let entry = ifd + 2 + i * 12;
+            tags.insert(self.u16_at(entry)?, self.values(entry)?);
+        }
+        let next = self.u32_at(ifd + 2 + entries * 12)?;
+        Ok((tags, next))
+    }
+}
@@ -0,0 +1,126 @@
+//! Detection of the tones that open a FAX call.
+//!
+//! A media gateway carrying a call as voice has to notice when it turns
+//! into a FAX call, and switch to T.38 (e.g. with a SIP re-INVITE) before
+//! the machines start training. The signs are the calling machine's CNG,
+//! the answering machine's CED, and the V.21 preamble that precedes the
+//! first T.30 frame when CED is missing. [`FaxToneDetector`] watches audio
+//! for all three; `FaxState` and `T38Gateway` run one on their received
+//! audio once a fax tone handler is set.
+
+use std::fmt;
+
+use crate::error::Result;
+use crate::modem_connect_tones::{ConnectTone, ConnectToneRx};
+
+/// A tone that identifies a FAX call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FaxTone {
+    /// CNG: the calling machine's 1100 Hz cadenced tone.
+    Cng,
+    /// CED: the answering machine's 2100 Hz tone.
+    Ced,
+    /// V.21 HDLC flags ahead of the first T.30 frame.
+    V21Preamble,
+}
+
+impl fmt::Display for FaxTone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cng => "CNG",
+            Self::Ced => "CED",
+            Self::V21Preamble => "V.21 preamble",
+        })
+    }
+}
+
+/// Watches audio for the first [`FaxTone`].
+///
+/// Runs spandsp's CNG and CED-or-preamble connect tone detectors side by
+/// side. Once a tone is found the detectors are no longer fed, so a
+/// detector left on a call costs nothing after the switch to FAX.
+pub struct FaxToneDetector {
+    cng: ConnectToneRx,
+    ced: ConnectToneRx,
+    detected: Option<FaxTone>,
+}
+
+impl FaxToneDetector {
+    /// Create a detector that has not heard anything yet.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            cng: ConnectToneRx::new(ConnectTone::Cng)?,
+            ced: ConnectToneRx::fax_answer()?,
+            detected: None,
+        })
+    }
+
+    /// Feed received audio, returning the first fax tone once it has been
+    /// heard (in this block or an earlier one).
+    pub fn rx(&mut self, samples: &[i16]) -> Option<FaxTone> {
+        if self.detected.is_some() || samples.is_empty() {
+            return self.detected;
+        }
+        self.cng.rx(samples);
+        self.ced.rx(samples);
+        self.detected = match (self.cng.take_detected(), self.ced.take_detected()) {
+            (Some(ConnectTone::Cng), _) => Some(FaxTone::Cng),
+            (_, Some(ConnectTone::Ans)) => Some(FaxTone::Ced),
+            (_, Some(ConnectTone::V21Preamble)) => Some(FaxTone::V21Preamble),
+            _ => None,
+        };
+        self.detected
+    }
+
+    /// The first fax tone heard, if any.
+    pub fn detected(&self) -> Option<FaxTone> {
+        self.detected
+    }
+
+    /// Forget what was heard and listen afresh, e.g. for a new call.
+    pub fn reset(&mut self) -> Result<()> {
+        *self = Self::new()?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FaxToneDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaxToneDetector")
+            .field("detected", &self.detected)
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) type FaxToneCallback = Box<dyn FnMut(FaxTone) + Send>;
+
+/// A detector on an engine's receive audio and the handler it reports to,
+/// called once per call.
+pub(crate) struct FaxToneWatch {
+    detector: FaxToneDetector,
+    handler: FaxToneCallback,
+}
+
+impl FaxToneWatch {
+    pub(crate) fn new(handler: FaxToneCallback) -> Result<Self> {
+        Ok(Self {
+            detector: FaxToneDetector::new()?,
+            handler,
+        })
+    }
+
+    pub(crate) fn rx(&mut self, samples: &[i16]) {
+        if self.detector.detected().is_some() {
+            return;
+        }
+        if let Some(tone) = self.detector.rx(samples) {
+            (self.handler)(tone);
+        }
+    }
+
+    /// Listen again, so the handler fires for the next call too.
+    pub(crate) fn rearm(&mut self) -> Result<()> {
+        self.detector.reset()
+    }
+}
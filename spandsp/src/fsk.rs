@@ -0,0 +1,555 @@
+//! Safe wrappers around spandsp's FSK modems.
+//!
+//! - `FskTx` wraps `fsk_tx_state_t`.
+//! - `FskRx` wraps `fsk_rx_state_t`.
+//! - `FskSpec` picks one of spandsp's `preset_fsk_specs`.
+//! - `V21HdlcReceiver` pairs a V.21 `FskRx` with an `HdlcRx` to recover
+//!   T.30 control frames from fax audio.
+//!
+//! The modems carry bare bits: the transmitter asks a closure (or any
+//! [`BitSource`]) for each bit it sends, and the receiver hands each bit it
+//! demodulates to a closure. Framing the bits into bytes or HDLC frames is
+//! up to the caller, as ADSI, V.18 and the V.21 fax control channel each do
+//! it differently; `V21HdlcReceiver` does it for the last of these.
+//!
+//! ```no_run
+//! use spandsp::fsk::{FskFraming, FskRx, FskSpec, FskTx};
+//!
+//! let mut bits = [true, false, true, true].into_iter();
+//! let mut tx = FskTx::new(FskSpec::Bell202, move || bits.next()).unwrap();
+//! let mut rx = FskRx::new(FskSpec::Bell202, FskFraming::Async, |bit| {
+//!     println!("{}", bit as u8);
+//! })
+//! .unwrap();
+//! let mut amp = [0i16; 160];
+//! let n = tx.generate(&mut amp);
+//! rx.rx(&amp[..n]);
+//! ```
+
+extern crate spandsp_sys;
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use crate::bit_source::BitSource;
+use crate::error::{Result, SpanDspError};
+use crate::hdlc::HdlcRx;
+
+/// One of spandsp's preset FSK modems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FskSpec {
+    /// V.21 channel 1 (calling), 300 baud.
+    V21Ch1,
+    /// V.21 channel 2 (answering), 300 baud; the T.30 fax control channel.
+    V21Ch2,
+    /// V.23 forward channel, 1200 baud; ETSI caller ID.
+    V23Ch1,
+    /// V.23 backward channel, 75 baud.
+    V23Ch2,
+    /// Bell 103 originate, 300 baud.
+    Bell103Ch1,
+    /// Bell 103 answer, 300 baud.
+    Bell103Ch2,
+    /// Bell 202, 1200 baud; CLASS caller ID.
+    Bell202,
+    /// Weitbrecht (Baudot TDD) at 45.45 baud.
+    Weitbrecht4545,
+    /// Weitbrecht at 50 baud.
+    Weitbrecht50,
+    /// Weitbrecht at 47.6 baud, as V.18 uses it.
+    Weitbrecht476,
+    /// V.21 channel 1 at 110 baud, as V.18 uses it.
+    V21Ch1At110,
+}
+
+impl FskSpec {
+    fn as_raw(self) -> usize {
+        (match self {
+            FskSpec::V21Ch1 => spandsp_sys::FSK_V21CH1,
+            FskSpec::V21Ch2 => spandsp_sys::FSK_V21CH2,
+            FskSpec::V23Ch1 => spandsp_sys::FSK_V23CH1,
+            FskSpec::V23Ch2 => spandsp_sys::FSK_V23CH2,
+            FskSpec::Bell103Ch1 => spandsp_sys::FSK_BELL103CH1,
+            FskSpec::Bell103Ch2 => spandsp_sys::FSK_BELL103CH2,
+            FskSpec::Bell202 => spandsp_sys::FSK_BELL202,
+            FskSpec::Weitbrecht4545 => spandsp_sys::FSK_WEITBRECHT_4545,
+            FskSpec::Weitbrecht50 => spandsp_sys::FSK_WEITBRECHT_50,
+            FskSpec::Weitbrecht476 => spandsp_sys::FSK_WEITBRECHT_476,
+            FskSpec::V21Ch1At110 => spandsp_sys::FSK_V21CH1_110,
+        }) as usize
+    }
+
+    /// spandsp's entry for this modem.
+    fn spec(self) -> &'static spandsp_sys::fsk_spec_t {
+        // SAFETY: preset_fsk_specs is a constant table with an entry for
+        // every FSK_* index.
+        unsafe {
+            &*std::ptr::addr_of!(spandsp_sys::preset_fsk_specs)
+                .cast::<spandsp_sys::fsk_spec_t>()
+                .add(self.as_raw())
+        }
+    }
+
+    /// Frequency sent for a 0 (space), in Hz.
+    pub fn freq_zero(self) -> i32 {
+        self.spec().freq_zero
+    }
+
+    /// Frequency sent for a 1 (mark), in Hz.
+    pub fn freq_one(self) -> i32 {
+        self.spec().freq_one
+    }
+
+    /// Symbol rate in baud.
+    pub fn baud_rate(self) -> f32 {
+        // spandsp keeps it in hundredths, for Weitbrecht's 45.45.
+        self.spec().baud_rate as f32 / 100.0
+    }
+
+    /// Transmit level, in dBm0.
+    pub fn tx_level(self) -> i32 {
+        self.spec().tx_level
+    }
+
+    /// Weakest signal the receiver accepts, in dBm0.
+    pub fn min_level(self) -> i32 {
+        self.spec().min_level
+    }
+}
+
+impl fmt::Display for FskSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.spec().name;
+        if name.is_null() {
+            return write!(f, "{self:?}");
+        }
+        f.write_str(&unsafe { CStr::from_ptr(name) }.to_string_lossy())
+    }
+}
+
+/// How the receiver times its bits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FskFraming {
+    /// Resynchronise on every transition, for start-stop characters such
+    /// as caller ID and TDD.
+    #[default]
+    Async,
+    /// Recover a continuous bit clock, for synchronous data such as HDLC.
+    Sync,
+}
+
+impl FskFraming {
+    fn as_raw(self) -> c_int {
+        match self {
+            FskFraming::Async => spandsp_sys::FSK_FRAME_MODE_ASYNC as c_int,
+            FskFraming::Sync => spandsp_sys::FSK_FRAME_MODE_SYNC as c_int,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FskTx
+// ---------------------------------------------------------------------------
+
+type GetBitCallback = Box<dyn FnMut() -> Option<bool> + Send>;
+
+/// Trampoline for `get_bit_func_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `GetBitCallback`.
+unsafe extern "C" fn get_bit_trampoline(user_data: *mut c_void) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return spandsp_sys::SIG_STATUS_END_OF_DATA as c_int;
+        }
+        let closure = &mut *(user_data as *mut GetBitCallback);
+        match closure() {
+            Some(bit) => bit as c_int,
+            None => spandsp_sys::SIG_STATUS_END_OF_DATA as c_int,
+        }
+    }
+}
+
+/// RAII wrapper around `fsk_tx_state_t`.
+///
+/// Created via `FskTx::new()` or `FskTx::from_source()`. Freed on drop via
+/// `fsk_tx_free`.
+pub struct FskTx {
+    ptr: NonNull<spandsp_sys::fsk_tx_state_t>,
+    spec: FskSpec,
+    _get_bit: Box<GetBitCallback>,
+}
+
+impl FskTx {
+    /// Create a transmitter for `spec` sending the bits `get_bit` returns.
+    /// The transmitter stops when `get_bit` returns `None`.
+    pub fn new<F>(spec: FskSpec, get_bit: F) -> Result<Self>
+    where
+        F: FnMut() -> Option<bool> + Send + 'static,
+    {
+        let boxed: Box<GetBitCallback> = Box::new(Box::new(get_bit));
+        let user_data = &*boxed as *const GetBitCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::fsk_tx_init(
+                std::ptr::null_mut(),
+                spec.spec(),
+                Some(get_bit_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            spec,
+            _get_bit: boxed,
+        })
+    }
+
+    /// Create a transmitter for `spec` sending the bits of `source`, e.g.
+    /// an HDLC transmitter.
+    pub fn from_source<S>(spec: FskSpec, mut source: S) -> Result<Self>
+    where
+        S: BitSource + Send + 'static,
+    {
+        Self::new(spec, move || source.next_bit())
+    }
+
+    /// Switch to another modem, keeping the bit source.
+    pub fn restart(&mut self, spec: FskSpec) -> Result<()> {
+        let rc = unsafe { spandsp_sys::fsk_tx_restart(self.ptr.as_ptr(), spec.spec()) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        self.spec = spec;
+        Ok(())
+    }
+
+    /// Returns the modem in use.
+    pub fn spec(&self) -> FskSpec {
+        self.spec
+    }
+
+    /// Set the transmit level, in dBm0.
+    pub fn set_power(&mut self, dbm0: f32) {
+        unsafe {
+            spandsp_sys::fsk_tx_power(self.ptr.as_ptr(), dbm0);
+        }
+    }
+
+    /// Generate audio.
+    ///
+    /// Returns the number of samples generated; fewer than `amp.len()` once
+    /// the bit source has run dry.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::fsk_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), len).max(0) as usize }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::fsk_tx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for FskTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FskTx")
+            .field("spec", &self.spec)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for FskTx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::fsk_tx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: FskTx wraps a SpanDSP fsk_tx_state_t that is only accessed through
+// &self/&mut self methods, and its bit source is Send.
+unsafe impl Send for FskTx {}
+
+// ---------------------------------------------------------------------------
+// FskRx
+// ---------------------------------------------------------------------------
+
+/// What the receive trampoline reports to.
+struct Received {
+    /// Takes each bit, or a `SIG_STATUS_*` code, as spandsp passes it.
+    put_bit: Box<dyn FnMut(c_int) + Send>,
+    carrier: bool,
+}
+
+/// Trampoline for `put_bit_func_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Received`.
+unsafe extern "C" fn put_bit_trampoline(user_data: *mut c_void, bit: c_int) {
+    unsafe {
+        if user_data.is_null() {
+            return;
+        }
+        let received = &mut *(user_data as *mut Received);
+        match bit {
+            spandsp_sys::SIG_STATUS_CARRIER_UP => received.carrier = true,
+            spandsp_sys::SIG_STATUS_CARRIER_DOWN => received.carrier = false,
+            _ => {}
+        }
+        (received.put_bit)(bit);
+    }
+}
+
+/// RAII wrapper around `fsk_rx_state_t`.
+///
+/// Created via `FskRx::new()`. Freed on drop via `fsk_rx_free`.
+pub struct FskRx {
+    ptr: NonNull<spandsp_sys::fsk_rx_state_t>,
+    spec: FskSpec,
+    framing: FskFraming,
+    received: Box<Received>,
+}
+
+impl FskRx {
+    /// Create a receiver for `spec`, handing each demodulated bit to
+    /// `put_bit`.
+    pub fn new<F>(spec: FskSpec, framing: FskFraming, mut put_bit: F) -> Result<Self>
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        Self::with_status(spec, framing, move |bit| {
+            if let 0 | 1 = bit {
+                put_bit(bit != 0);
+            }
+        })
+    }
+
+    /// Like [`new`](Self::new), but `put_bit` also gets the `SIG_STATUS_*`
+    /// codes spandsp reports between bits, such as carrier up and down.
+    pub(crate) fn with_status<F>(spec: FskSpec, framing: FskFraming, put_bit: F) -> Result<Self>
+    where
+        F: FnMut(c_int) + Send + 'static,
+    {
+        let mut received = Box::new(Received {
+            put_bit: Box::new(put_bit),
+            carrier: false,
+        });
+        let user_data = &mut *received as *mut Received as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::fsk_rx_init(
+                std::ptr::null_mut(),
+                spec.spec(),
+                framing.as_raw(),
+                Some(put_bit_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            spec,
+            framing,
+            received,
+        })
+    }
+
+    /// Switch to another modem or framing, keeping the bit callback.
+    pub fn restart(&mut self, spec: FskSpec, framing: FskFraming) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::fsk_rx_restart(self.ptr.as_ptr(), spec.spec(), framing.as_raw())
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        self.spec = spec;
+        self.framing = framing;
+        self.received.carrier = false;
+        Ok(())
+    }
+
+    /// Returns the modem in use.
+    pub fn spec(&self) -> FskSpec {
+        self.spec
+    }
+
+    /// Returns the framing in use.
+    pub fn framing(&self) -> FskFraming {
+        self.framing
+    }
+
+    /// Whether a carrier is being received.
+    pub fn carrier(&self) -> bool {
+        self.received.carrier
+    }
+
+    /// Set the weakest signal taken as carrier, in dBm0.
+    pub fn set_signal_cutoff(&mut self, dbm0: f32) {
+        unsafe {
+            spandsp_sys::fsk_rx_signal_cutoff(self.ptr.as_ptr(), dbm0);
+        }
+    }
+
+    /// The received signal level, in dBm0.
+    pub fn signal_power(&self) -> f32 {
+        unsafe { spandsp_sys::fsk_rx_signal_power(self.ptr.as_ptr()) }
+    }
+
+    /// Feed received audio.
+    ///
+    /// Returns the number of unprocessed samples (normally 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::fsk_rx(self.ptr.as_ptr(), amp.as_ptr(), len).max(0) as usize }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::fsk_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for FskRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FskRx")
+            .field("spec", &self.spec)
+            .field("framing", &self.framing)
+            .field("carrier", &self.received.carrier)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for FskRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::fsk_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: FskRx wraps a SpanDSP fsk_rx_state_t that is only accessed through
+// &self/&mut self methods, and its bit callback is Send.
+unsafe impl Send for FskRx {}
+
+// ---------------------------------------------------------------------------
+// V21HdlcReceiver
+// ---------------------------------------------------------------------------
+
+/// Consecutive flags needed before the V.21 channel is taken as framed, as
+/// spandsp's own fax modems use.
+const V21_FRAMING_OK_THRESHOLD: i32 = 5;
+
+/// An HDLC frame heard on the V.21 fax control channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct V21Frame {
+    /// The frame from its address octet on, with the FCS stripped.
+    pub data: Vec<u8>,
+    /// Whether the FCS checked out.
+    pub crc_ok: bool,
+}
+
+impl V21Frame {
+    /// The T.30 facsimile control field, if the frame is long enough to
+    /// have one. The low bit says which end sent it.
+    pub fn fcf(&self) -> Option<u8> {
+        self.data.get(2).copied()
+    }
+
+    /// Whether the control octet marks this as the last frame of its
+    /// command or response.
+    pub fn is_final(&self) -> bool {
+        self.data.get(1).is_some_and(|&control| control & 0x10 != 0)
+    }
+}
+
+/// A V.21 channel 2 receiver feeding an [`HdlcRx`], turning the audio of a
+/// fax call into its T.30 control frames.
+///
+/// Bits demodulated by each [`rx`](Self::rx) call are deframed before it
+/// returns, so frames come back from the block that completed them.
+pub struct V21HdlcReceiver {
+    fsk: FskRx,
+    hdlc: HdlcRx,
+    /// Bits and status codes from the demodulator, in order.
+    bits: Arc<Mutex<Vec<c_int>>>,
+    frames: Rc<RefCell<Vec<V21Frame>>>,
+}
+
+impl V21HdlcReceiver {
+    /// Create a receiver. With `report_bad_frames`, frames failing their
+    /// FCS are returned too, marked by [`V21Frame::crc_ok`].
+    pub fn new(report_bad_frames: bool) -> Result<Self> {
+        let bits = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&bits);
+        let fsk = FskRx::with_status(FskSpec::V21Ch2, FskFraming::Sync, move |bit| {
+            sink.lock().unwrap().push(bit)
+        })?;
+        let frames = Rc::new(RefCell::new(Vec::new()));
+        let out = Rc::clone(&frames);
+        let hdlc = HdlcRx::new(
+            false,
+            report_bad_frames,
+            V21_FRAMING_OK_THRESHOLD,
+            move |data: &[u8], crc_ok| {
+                // Empty frames report framing status, not data.
+                if !data.is_empty() {
+                    out.borrow_mut().push(V21Frame {
+                        data: data.to_vec(),
+                        crc_ok,
+                    });
+                }
+            },
+        )?;
+        Ok(Self {
+            fsk,
+            hdlc,
+            bits,
+            frames,
+        })
+    }
+
+    /// Feed received audio, returning the frames it completed.
+    pub fn rx(&mut self, amp: &[i16]) -> Vec<V21Frame> {
+        self.fsk.rx(amp);
+        let bits = std::mem::take(&mut *self.bits.lock().unwrap());
+        // Statuses go through too, as in spandsp's fax modems: carrier
+        // down ends the frame in progress and resets the flag count.
+        for bit in bits {
+            self.hdlc.put_bit_or_status(bit);
+        }
+        std::mem::take(&mut *self.frames.borrow_mut())
+    }
+
+    /// Whether a V.21 carrier is being received.
+    pub fn carrier(&self) -> bool {
+        self.fsk.carrier()
+    }
+
+    /// Returns the FSK receiver.
+    pub fn fsk(&mut self) -> &mut FskRx {
+        &mut self.fsk
+    }
+
+    /// Returns the HDLC receiver.
+    pub fn hdlc(&mut self) -> &mut HdlcRx {
+        &mut self.hdlc
+    }
+}
+
+impl fmt::Debug for V21HdlcReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V21HdlcReceiver")
+            .field("carrier", &self.fsk.carrier())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,506 @@
+//! G.168 test signals and a compliance harness for the echo canceller.
+//!
+//! ITU-T G.168 tests a line echo canceller by playing a composite source
+//! signal (CSS) into the receive path, returning it through a model hybrid,
+//! and measuring how much echo gets past the canceller. [`CompositeSource`]
+//! and [`EchoPath`] generate those signals; [`G168Harness`] runs tests 2A,
+//! 2B, 3A and 3B against an [`EchoCanceller`] configuration and reports each
+//! as pass or fail with the residual echo level it measured.
+//!
+//! The signals follow the structure G.168 gives them but are synthesised
+//! rather than copied from its tables, so a pass is a regression check on
+//! canceller settings, not a certification.
+//!
+//! ```no_run
+//! use spandsp::echo::EchoCanFlags;
+//! use spandsp::g168::G168Harness;
+//!
+//! let report = G168Harness::new(256, EchoCanFlags::default()).run_all().unwrap();
+//! println!("{report}");
+//! assert!(report.passed());
+//! ```
+
+use std::fmt;
+
+use crate::echo::{EchoCanFlags, EchoCanceller};
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::{DBM0_MAX_SINE_POWER, mean_square_dbm0};
+
+/// CSS segment lengths at 8 kHz: 48.62 ms voiced, 200 ms pseudo-noise,
+/// 101.38 ms pause.
+const VOICED_SAMPLES: usize = 389;
+const NOISE_SAMPLES: usize = 1600;
+const PAUSE_SAMPLES: usize = 811;
+const CSS_PERIOD: usize = VOICED_SAMPLES + NOISE_SAMPLES + PAUSE_SAMPLES;
+
+/// Fundamental of the voiced segment, and the harmonics summed above it.
+const VOICED_F0: f64 = 125.0;
+const VOICED_HARMONICS: usize = 24;
+
+/// Impulse response of the model hybrid after its pure delay, before
+/// scaling to unit energy.
+const DISPERSION: [f32; 8] = [0.2, 0.8, 0.5, -0.3, -0.25, 0.1, 0.05, -0.02];
+
+/// Harness defaults: receive level, echo path delay and echo return loss.
+const DEFAULT_RIN_DBM0: f32 = -10.0;
+const DEFAULT_DELAY: usize = 64;
+const DEFAULT_ERL_DB: f32 = 6.0;
+
+/// One second at 8 kHz.
+const SECOND: usize = 8000;
+
+// ---------------------------------------------------------------------------
+// Test signals
+// ---------------------------------------------------------------------------
+
+/// The G.168 composite source signal, repeated indefinitely.
+///
+/// Each 350 ms period is a voiced burst, a burst of pseudo-noise and a
+/// pause; every other period is inverted. Both bursts are at the requested
+/// level, so the signal averaged over a period sits about 1.5 dB lower.
+#[derive(Debug, Clone)]
+pub struct CompositeSource {
+    level_dbm0: f32,
+    cycle: Vec<i16>,
+    pos: usize,
+}
+
+impl CompositeSource {
+    /// A source at `level_dbm0`, whose pseudo-noise is drawn from `seed`.
+    /// Sources with different seeds are uncorrelated enough to stand in for
+    /// the two ends of a conversation.
+    pub fn new(level_dbm0: f32, seed: u32) -> Result<Self> {
+        if !(level_dbm0.is_finite() && level_dbm0 <= DBM0_MAX_SINE_POWER - 6.0) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "CSS level must be at most {} dBm0, got {level_dbm0}",
+                DBM0_MAX_SINE_POWER - 6.0
+            )));
+        }
+        let rms = dbm0_to_rms(level_dbm0);
+
+        let voiced: Vec<f64> = (0..VOICED_SAMPLES)
+            .map(|i| {
+                let t = i as f64 / SECOND as f64;
+                (1..=VOICED_HARMONICS)
+                    .map(|k| {
+                        let k = k as f64;
+                        (2.0 * std::f64::consts::PI * k * VOICED_F0 * t + k).sin() / k
+                    })
+                    .sum()
+            })
+            .collect();
+        // 32-bit Galois LFSR, never allowed to stick at zero.
+        let mut lfsr = seed.max(1);
+        let noise: Vec<f64> = (0..NOISE_SAMPLES)
+            .map(|_| {
+                lfsr = (lfsr >> 1) ^ (0u32.wrapping_sub(lfsr & 1) & 0xA300_0000);
+                f64::from(lfsr) / f64::from(u32::MAX) * 2.0 - 1.0
+            })
+            .collect();
+
+        let mut cycle = Vec::with_capacity(2 * CSS_PERIOD);
+        for polarity in [1.0, -1.0] {
+            for segment in [&voiced, &noise] {
+                let scale = polarity * rms / segment_rms(segment);
+                cycle.extend(segment.iter().map(|&v| to_sample(v * scale)));
+            }
+            cycle.resize(cycle.len() + PAUSE_SAMPLES, 0);
+        }
+        Ok(Self {
+            level_dbm0,
+            cycle,
+            pos: 0,
+        })
+    }
+
+    /// The level of the voiced and noise bursts, in dBm0.
+    pub fn level_dbm0(&self) -> f32 {
+        self.level_dbm0
+    }
+
+    /// The next sample.
+    pub fn next_sample(&mut self) -> i16 {
+        let sample = self.cycle[self.pos];
+        self.pos = (self.pos + 1) % self.cycle.len();
+        sample
+    }
+
+    /// Fill `amp` with the next samples.
+    pub fn fill(&mut self, amp: &mut [i16]) {
+        for sample in amp {
+            *sample = self.next_sample();
+        }
+    }
+
+    /// Move `samples` further through the signal without generating it.
+    pub fn skip(&mut self, samples: usize) {
+        self.pos = (self.pos + samples) % self.cycle.len();
+    }
+}
+
+/// A model hybrid: a pure delay, a short dispersive response and an echo
+/// return loss.
+#[derive(Debug, Clone)]
+pub struct EchoPath {
+    delay: usize,
+    erl_db: f32,
+    taps: Vec<f32>,
+    history: Vec<i16>,
+    pos: usize,
+}
+
+impl EchoPath {
+    /// An echo path `delay` samples long before its dispersion, returning
+    /// white noise `erl_db` below its receive level. Speech-like signals
+    /// such as the CSS come back about 1 dB louder.
+    pub fn new(delay: usize, erl_db: f32) -> Result<Self> {
+        if !(erl_db.is_finite() && erl_db >= 0.0) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "echo return loss must be at least 0 dB, got {erl_db}"
+            )));
+        }
+        let energy: f32 = DISPERSION.iter().map(|t| t * t).sum();
+        let gain = 10f32.powf(-erl_db / 20.0) / energy.sqrt();
+        Ok(Self {
+            delay,
+            erl_db,
+            taps: DISPERSION.iter().map(|t| t * gain).collect(),
+            history: vec![0; delay + DISPERSION.len()],
+            pos: 0,
+        })
+    }
+
+    /// The pure delay, in samples.
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    /// The echo return loss, in dB.
+    pub fn erl_db(&self) -> f32 {
+        self.erl_db
+    }
+
+    /// Samples from a receive sample to the end of its echo. The canceller's
+    /// tail must be at least this long.
+    pub fn span(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Feed one sample going out to the line and return the echo arriving
+    /// back with it.
+    pub fn echo(&mut self, rin: i16) -> i16 {
+        let len = self.history.len();
+        self.history[self.pos] = rin;
+        let mut echo = 0.0;
+        for (i, tap) in self.taps.iter().enumerate() {
+            // The sample delay + i before the one just written.
+            let back = (self.pos + len - (self.delay + i) % len) % len;
+            echo += tap * f32::from(self.history[back]);
+        }
+        self.pos = (self.pos + 1) % len;
+        to_sample(f64::from(echo))
+    }
+
+    /// Forget everything sent so far.
+    pub fn reset(&mut self) {
+        self.history.fill(0);
+        self.pos = 0;
+    }
+}
+
+fn dbm0_to_rms(level_dbm0: f32) -> f64 {
+    32767.0 / 2f64.sqrt() * 10f64.powf(f64::from(level_dbm0 - DBM0_MAX_SINE_POWER) / 20.0)
+}
+
+/// The level of a mean square, in dBm0, floored at -99.
+fn floored_dbm0(mean_sq: f64) -> f32 {
+    // log10(0) is -inf, which max() floors too.
+    mean_square_dbm0(mean_sq).max(-99.0)
+}
+
+fn segment_rms(segment: &[f64]) -> f64 {
+    (segment.iter().map(|v| v * v).sum::<f64>() / segment.len() as f64).sqrt()
+}
+
+fn to_sample(v: f64) -> i16 {
+    v.round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+}
+
+// ---------------------------------------------------------------------------
+// Harness
+// ---------------------------------------------------------------------------
+
+/// A G.168 test run by [`G168Harness`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum G168Test {
+    /// Convergence with NLP enabled: the residual after one second must be
+    /// at most -65 dBm0.
+    Test2A,
+    /// Convergence with NLP disabled: after one second the echo must be
+    /// 20 dB below the receive level, echo return loss included.
+    Test2B,
+    /// Convergence while a near-end talker 15 dB below the receive level is
+    /// present, NLP disabled: the same 20 dB as 2B.
+    Test3A,
+    /// Double talk after convergence, NLP disabled: the canceller must not
+    /// diverge, keeping the echo 10 dB below the receive level throughout.
+    Test3B,
+}
+
+impl G168Test {
+    /// Every test, in the order G.168 lists them.
+    pub const ALL: [Self; 4] = [Self::Test2A, Self::Test2B, Self::Test3A, Self::Test3B];
+
+    /// A short description of the test.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Test2A => "convergence, NLP enabled",
+            Self::Test2B => "convergence, NLP disabled",
+            Self::Test3A => "convergence with low-level double talk",
+            Self::Test3B => "double talk after convergence",
+        }
+    }
+}
+
+impl fmt::Display for G168Test {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Test2A => "2A",
+            Self::Test2B => "2B",
+            Self::Test3A => "3A",
+            Self::Test3B => "3B",
+        })
+    }
+}
+
+/// Outcome of one test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct G168Result {
+    /// The test run.
+    pub test: G168Test,
+    /// Echo left in the canceller's output over the measurement second,
+    /// with any near-end signal taken out, in dBm0.
+    pub residual_dbm0: f32,
+    /// The most the test allows, in dBm0.
+    pub limit_dbm0: f32,
+}
+
+impl G168Result {
+    /// `true` if the residual was within the limit.
+    pub fn passed(&self) -> bool {
+        self.residual_dbm0 <= self.limit_dbm0
+    }
+}
+
+impl fmt::Display for G168Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}): residual {:.1} dBm0, limit {:.1} dBm0",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.test,
+            self.test.description(),
+            self.residual_dbm0,
+            self.limit_dbm0
+        )
+    }
+}
+
+/// Results of running a canceller configuration through the tests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct G168Report {
+    tail_len: i32,
+    flags: EchoCanFlags,
+    results: Vec<G168Result>,
+}
+
+impl G168Report {
+    /// The tail length tested.
+    pub fn tail_len(&self) -> i32 {
+        self.tail_len
+    }
+
+    /// The adaption mode tested, before each test set NLP its own way.
+    pub fn flags(&self) -> EchoCanFlags {
+        self.flags
+    }
+
+    /// Every test run, in order.
+    pub fn results(&self) -> &[G168Result] {
+        &self.results
+    }
+
+    /// The tests that did not pass.
+    pub fn failures(&self) -> impl Iterator<Item = &G168Result> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// `true` if every test passed.
+    pub fn passed(&self) -> bool {
+        !self.results.is_empty() && self.results.iter().all(G168Result::passed)
+    }
+}
+
+impl fmt::Display for G168Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let passed = self.results.iter().filter(|r| r.passed()).count();
+        writeln!(
+            f,
+            "G.168, tail {} samples, {}: {passed}/{} tests passed",
+            self.tail_len,
+            self.flags,
+            self.results.len()
+        )?;
+        for result in &self.results {
+            writeln!(f, "  {result}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs G.168 tests against an echo canceller configuration.
+///
+/// Every test gets a fresh [`EchoCanceller`] built from the configuration,
+/// with NLP (and CNG, which only acts through it) switched on for 2A and
+/// off for the rest, as G.168 specifies.
+#[derive(Debug, Clone, Copy)]
+pub struct G168Harness {
+    tail_len: i32,
+    flags: EchoCanFlags,
+    rin_dbm0: f32,
+    delay: usize,
+    erl_db: f32,
+}
+
+impl G168Harness {
+    /// A harness for cancellers with a tail of `tail_len` samples in mode
+    /// `flags`, driven at -10 dBm0 through a 64 sample, 6 dB echo path.
+    pub fn new(tail_len: i32, flags: EchoCanFlags) -> Self {
+        Self {
+            tail_len,
+            flags,
+            rin_dbm0: DEFAULT_RIN_DBM0,
+            delay: DEFAULT_DELAY,
+            erl_db: DEFAULT_ERL_DB,
+        }
+    }
+
+    /// Set the level of the CSS played into the receive path. The default
+    /// is -10 dBm0.
+    pub fn set_rin_level(&mut self, dbm0: f32) -> Result<()> {
+        CompositeSource::new(dbm0, 1)?;
+        self.rin_dbm0 = dbm0;
+        Ok(())
+    }
+
+    /// Set the pure delay and echo return loss of the model hybrid. The
+    /// defaults are 64 samples and 6 dB.
+    pub fn set_echo_path(&mut self, delay: usize, erl_db: f32) -> Result<()> {
+        EchoPath::new(delay, erl_db)?;
+        self.delay = delay;
+        self.erl_db = erl_db;
+        Ok(())
+    }
+
+    /// Run one test.
+    pub fn run(&self, test: G168Test) -> Result<G168Result> {
+        let flags = match test {
+            G168Test::Test2A => self.flags | EchoCanFlags::NLP,
+            _ => self.flags - EchoCanFlags::NLP - EchoCanFlags::CNG,
+        };
+        let mut line = Line::new(self, flags)?;
+        let (residual_dbm0, limit_dbm0) = match test {
+            G168Test::Test2A => {
+                line.run(SECOND, None)?;
+                (line.run(SECOND, None)?, -65.0)
+            }
+            G168Test::Test2B => {
+                line.run(SECOND, None)?;
+                (line.run(SECOND, None)?, self.rin_dbm0 - 20.0)
+            }
+            G168Test::Test3A => {
+                let low = Some(self.rin_dbm0 - 15.0);
+                line.run(SECOND, low)?;
+                (line.run(SECOND, low)?, self.rin_dbm0 - 20.0)
+            }
+            G168Test::Test3B => {
+                line.run(SECOND, None)?;
+                let level = Some(self.rin_dbm0);
+                line.run(SECOND, level)?;
+                (line.run(SECOND, level)?, self.rin_dbm0 - 10.0)
+            }
+        };
+        Ok(G168Result {
+            test,
+            residual_dbm0,
+            limit_dbm0,
+        })
+    }
+
+    /// Run every test in [`G168Test::ALL`].
+    pub fn run_all(&self) -> Result<G168Report> {
+        let results = G168Test::ALL
+            .into_iter()
+            .map(|test| self.run(test))
+            .collect::<Result<_>>()?;
+        Ok(G168Report {
+            tail_len: self.tail_len,
+            flags: self.flags,
+            results,
+        })
+    }
+}
+
+/// A canceller wired to a model hybrid, with a far-end and near-end talker.
+struct Line {
+    canceller: EchoCanceller,
+    path: EchoPath,
+    far: CompositeSource,
+    near_seed: u32,
+}
+
+impl Line {
+    fn new(harness: &G168Harness, flags: EchoCanFlags) -> Result<Self> {
+        let path = EchoPath::new(harness.delay, harness.erl_db)?;
+        if !usize::try_from(harness.tail_len).is_ok_and(|tail| tail >= path.span()) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "echo path spans {} samples, longer than the {} sample tail",
+                path.span(),
+                harness.tail_len
+            )));
+        }
+        Ok(Self {
+            canceller: EchoCanceller::new(harness.tail_len, flags)?,
+            path,
+            far: CompositeSource::new(harness.rin_dbm0, 1)?,
+            near_seed: 0x5A5A_5A5A,
+        })
+    }
+
+    /// Run `samples` of far-end CSS, with near-end CSS at `near_dbm0` if
+    /// given, and return the level of the output less the near-end signal.
+    fn run(&mut self, samples: usize, near_dbm0: Option<f32>) -> Result<f32> {
+        let mut near = match near_dbm0 {
+            Some(level) => {
+                let mut near = CompositeSource::new(level, self.near_seed)?;
+                // Half a period out, so its voiced bursts do not line up
+                // with the far end's.
+                near.skip(CSS_PERIOD / 2);
+                Some(near)
+            }
+            None => None,
+        };
+        self.near_seed = self.near_seed.wrapping_add(1);
+        let mut sum_sq = 0.0f64;
+        for _ in 0..samples {
+            let rin = self.far.next_sample();
+            let near = near.as_mut().map_or(0, CompositeSource::next_sample);
+            let sin = to_sample(f64::from(self.path.echo(rin)) + f64::from(near));
+            let residual = f64::from(self.canceller.update(rin, sin)) - f64::from(near);
+            sum_sq += residual * residual;
+        }
+        Ok(floored_dbm0(sum_sq / samples as f64))
+    }
+}
@@ -8,8 +8,12 @@ extern crate spandsp_sys;
 use std::fmt;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
+#[cfg(debug_assertions)]
+use std::sync::Once;
 
+use crate::dtx::{Dtx, DtxFrame};
 use crate::error::{Result, SpanDspError};
+use crate::plc::{ConcealingDecoder, FrameDecoder};
 
 /// G.711 encoding mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -27,6 +31,20 @@ impl G711Mode {
             G711Mode::ULaw => spandsp_sys::G711_ULAW as c_int,
         }
     }
+
+    fn decode_sample(self, code: u8) -> i16 {
+        match self {
+            G711Mode::ALaw => alaw_to_linear(code),
+            G711Mode::ULaw => ulaw_to_linear(code),
+        }
+    }
+
+    fn encode_sample(self, amp: i16) -> u8 {
+        match self {
+            G711Mode::ALaw => linear_to_alaw(amp),
+            G711Mode::ULaw => linear_to_ulaw(amp),
+        }
+    }
 }
 
 impl fmt::Display for G711Mode {
@@ -49,7 +67,12 @@ pub struct G711State {
 
 impl G711State {
     /// Create a new G.711 encoder/decoder state for the specified mode.
+    ///
+    /// In debug builds the first call also runs [`verify_against_ffi`],
+    /// panicking if the Rust conversions disagree with the linked library.
     pub fn new(mode: G711Mode) -> Result<Self> {
+        #[cfg(debug_assertions)]
+        verify_once();
         let ptr = unsafe { spandsp_sys::g711_init(std::ptr::null_mut(), mode.as_raw()) };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
         Ok(Self { ptr, mode })
@@ -71,6 +94,14 @@ impl G711State {
         }
     }
 
+    /// Encode a frame with discontinuous transmission.
+    ///
+    /// Speech is encoded into `g711_data` as with [`encode`](Self::encode);
+    /// silence yields a SID or no frame, as decided by `dtx`.
+    pub fn encode_dtx(&mut self, dtx: &mut Dtx, g711_data: &mut [u8], amp: &[i16]) -> DtxFrame {
+        dtx.process(amp, |amp| self.encode(g711_data, amp))
+    }
+
     /// Decode G.711 data to linear PCM samples.
     ///
     /// Returns the number of linear samples produced.
@@ -102,12 +133,46 @@ impl G711State {
         }
     }
 
+    /// Encode a fixed-size frame, e.g. `encode_frame::<160>` for 20 ms.
+    ///
+    /// The frame length is checked at compile time, and the conversion runs
+    /// in Rust without touching the C state, so it can be fully unrolled.
+    pub fn encode_frame<const N: usize>(&self, amp: &[i16; N]) -> [u8; N] {
+        match self.mode {
+            G711Mode::ALaw => amp.map(linear_to_alaw),
+            G711Mode::ULaw => amp.map(linear_to_ulaw),
+        }
+    }
+
+    /// Decode a fixed-size frame; the counterpart of
+    /// [`encode_frame`](Self::encode_frame).
+    pub fn decode_frame<const N: usize>(&self, g711_data: &[u8; N]) -> [i16; N] {
+        match self.mode {
+            G711Mode::ALaw => g711_data.map(alaw_to_linear),
+            G711Mode::ULaw => g711_data.map(ulaw_to_linear),
+        }
+    }
+
+    /// Put packet loss concealment in front of this decoder, for frames of
+    /// `frame_samples` samples (e.g. 160 for 20 ms).
+    pub fn with_plc(self, frame_samples: usize) -> Result<ConcealingDecoder<Self>> {
+        ConcealingDecoder::new(self, frame_samples)
+    }
+
     /// Return the raw pointer to the underlying state.
     pub fn as_ptr(&self) -> *mut spandsp_sys::g711_state_t {
         self.ptr.as_ptr()
     }
 }
 
+impl FrameDecoder for G711State {
+    fn decode_into(&mut self, amp: &mut [i16], frame: &[u8]) -> usize {
+        // g711_decode does not bound its output by `amp`.
+        let len = frame.len().min(amp.len());
+        self.decode(amp, &frame[..len])
+    }
+}
+
 impl fmt::Debug for G711State {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("G711State")
@@ -231,3 +296,156 @@ pub fn alaw_to_ulaw(alaw: u8) -> u8 {
 pub fn ulaw_to_alaw(ulaw: u8) -> u8 {
     unsafe { spandsp_sys::ulaw_to_alaw(ulaw) }
 }
+
+// ---------------------------------------------------------------------------
+// Known-answer tables and verification
+// ---------------------------------------------------------------------------
+
+/// Known answers: the 16-bit linear value of every u-law code.
+pub const ULAW_TO_LINEAR: [i16; 256] = [
+    -32124, -31100, -30076, -29052, -28028, -27004, -25980, -24956, -23932, -22908, -21884, -20860,
+    -19836, -18812, -17788, -16764, -15996, -15484, -14972, -14460, -13948, -13436, -12924, -12412,
+    -11900, -11388, -10876, -10364, -9852, -9340, -8828, -8316, -7932, -7676, -7420, -7164, -6908,
+    -6652, -6396, -6140, -5884, -5628, -5372, -5116, -4860, -4604, -4348, -4092, -3900, -3772,
+    -3644, -3516, -3388, -3260, -3132, -3004, -2876, -2748, -2620, -2492, -2364, -2236, -2108,
+    -1980, -1884, -1820, -1756, -1692, -1628, -1564, -1500, -1436, -1372, -1308, -1244, -1180,
+    -1116, -1052, -988, -924, -876, -844, -812, -780, -748, -716, -684, -652, -620, -588, -556,
+    -524, -492, -460, -428, -396, -372, -356, -340, -324, -308, -292, -276, -260, -244, -228, -212,
+    -196, -180, -164, -148, -132, -120, -112, -104, -96, -88, -80, -72, -64, -56, -48, -40, -32,
+    -24, -16, -8, 0, 32124, 31100, 30076, 29052, 28028, 27004, 25980, 24956, 23932, 22908, 21884,
+    20860, 19836, 18812, 17788, 16764, 15996, 15484, 14972, 14460, 13948, 13436, 12924, 12412,
+    11900, 11388, 10876, 10364, 9852, 9340, 8828, 8316, 7932, 7676, 7420, 7164, 6908, 6652, 6396,
+    6140, 5884, 5628, 5372, 5116, 4860, 4604, 4348, 4092, 3900, 3772, 3644, 3516, 3388, 3260, 3132,
+    3004, 2876, 2748, 2620, 2492, 2364, 2236, 2108, 1980, 1884, 1820, 1756, 1692, 1628, 1564, 1500,
+    1436, 1372, 1308, 1244, 1180, 1116, 1052, 988, 924, 876, 844, 812, 780, 748, 716, 684, 652,
+    620, 588, 556, 524, 492, 460, 428, 396, 372, 356, 340, 324, 308, 292, 276, 260, 244, 228, 212,
+    196, 180, 164, 148, 132, 120, 112, 104, 96, 88, 80, 72, 64, 56, 48, 40, 32, 24, 16, 8, 0,
+];
+
+/// Known answers: the 16-bit linear value of every A-law code.
+pub const ALAW_TO_LINEAR: [i16; 256] = [
+    -5504, -5248, -6016, -5760, -4480, -4224, -4992, -4736, -7552, -7296, -8064, -7808, -6528,
+    -6272, -7040, -6784, -2752, -2624, -3008, -2880, -2240, -2112, -2496, -2368, -3776, -3648,
+    -4032, -3904, -3264, -3136, -3520, -3392, -22016, -20992, -24064, -23040, -17920, -16896,
+    -19968, -18944, -30208, -29184, -32256, -31232, -26112, -25088, -28160, -27136, -11008, -10496,
+    -12032, -11520, -8960, -8448, -9984, -9472, -15104, -14592, -16128, -15616, -13056, -12544,
+    -14080, -13568, -344, -328, -376, -360, -280, -264, -312, -296, -472, -456, -504, -488, -408,
+    -392, -440, -424, -88, -72, -120, -104, -24, -8, -56, -40, -216, -200, -248, -232, -152, -136,
+    -184, -168, -1376, -1312, -1504, -1440, -1120, -1056, -1248, -1184, -1888, -1824, -2016, -1952,
+    -1632, -1568, -1760, -1696, -688, -656, -752, -720, -560, -528, -624, -592, -944, -912, -1008,
+    -976, -816, -784, -880, -848, 5504, 5248, 6016, 5760, 4480, 4224, 4992, 4736, 7552, 7296, 8064,
+    7808, 6528, 6272, 7040, 6784, 2752, 2624, 3008, 2880, 2240, 2112, 2496, 2368, 3776, 3648, 4032,
+    3904, 3264, 3136, 3520, 3392, 22016, 20992, 24064, 23040, 17920, 16896, 19968, 18944, 30208,
+    29184, 32256, 31232, 26112, 25088, 28160, 27136, 11008, 10496, 12032, 11520, 8960, 8448, 9984,
+    9472, 15104, 14592, 16128, 15616, 13056, 12544, 14080, 13568, 344, 328, 376, 360, 280, 264,
+    312, 296, 472, 456, 504, 488, 408, 392, 440, 424, 88, 72, 120, 104, 24, 8, 56, 40, 216, 200,
+    248, 232, 152, 136, 184, 168, 1376, 1312, 1504, 1440, 1120, 1056, 1248, 1184, 1888, 1824, 2016,
+    1952, 1632, 1568, 1760, 1696, 688, 656, 752, 720, 560, 528, 624, 592, 944, 912, 1008, 976, 816,
+    784, 880, 848,
+];
+
+/// Which conversion disagreed in [`verify_against_ffi`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum G711Check {
+    /// The Rust decoder against the known-answer table.
+    DecodeTable,
+    /// The C decoder against the known-answer table.
+    DecodeFfi,
+    /// The Rust encoder against the C encoder.
+    Encode,
+}
+
+/// A divergence found by [`verify_against_ffi`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, thiserror::Error)]
+#[error("G.711 {mode} {check:?} mismatch for input {input}: expected {expected}, got {actual}")]
+pub struct G711Mismatch {
+    /// The law being checked.
+    pub mode: G711Mode,
+    /// The conversion that disagreed.
+    pub check: G711Check,
+    /// The code or linear sample converted.
+    pub input: i32,
+    /// The reference result.
+    pub expected: i32,
+    /// The result under test.
+    pub actual: i32,
+}
+
+/// Cross-check the pure-Rust conversions against the known-answer tables
+/// and the linked C library, for every code and every linear sample.
+///
+/// Returns the first mismatch found.
+pub fn verify_against_ffi() -> std::result::Result<(), G711Mismatch> {
+    let codes: Vec<u8> = (0..=255).collect();
+    let linear: Vec<i16> = (i16::MIN..=i16::MAX).collect();
+    for mode in [G711Mode::ULaw, G711Mode::ALaw] {
+        let table = match mode {
+            G711Mode::ULaw => &ULAW_TO_LINEAR,
+            G711Mode::ALaw => &ALAW_TO_LINEAR,
+        };
+        let mismatch = |check, input: i32, expected: i32, actual: i32| {
+            (expected != actual).then_some(G711Mismatch {
+                mode,
+                check,
+                input,
+                expected,
+                actual,
+            })
+        };
+        // The state is only used to reach the C codec, so build it directly
+        // rather than through `new`, which would recurse into this check.
+        let ptr = unsafe { spandsp_sys::g711_init(std::ptr::null_mut(), mode.as_raw()) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            // Out of memory; there is nothing to compare against.
+            continue;
+        };
+        let mut ffi = G711State { ptr, mode };
+
+        let mut ffi_decoded = [0i16; 256];
+        ffi.decode(&mut ffi_decoded, &codes);
+        let mut ffi_encoded = vec![0u8; linear.len()];
+        ffi.encode(&mut ffi_encoded, &linear);
+
+        for (code, &expected) in table.iter().enumerate() {
+            let input = code as i32;
+            let found = mismatch(
+                G711Check::DecodeTable,
+                input,
+                expected as i32,
+                mode.decode_sample(code as u8) as i32,
+            )
+            .or_else(|| {
+                mismatch(
+                    G711Check::DecodeFfi,
+                    input,
+                    expected as i32,
+                    ffi_decoded[code] as i32,
+                )
+            });
+            if let Some(found) = found {
+                return Err(found);
+            }
+        }
+        for (&sample, &expected) in linear.iter().zip(&ffi_encoded) {
+            if let Some(found) = mismatch(
+                G711Check::Encode,
+                sample as i32,
+                expected as i32,
+                mode.encode_sample(sample) as i32,
+            ) {
+                return Err(found);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(debug_assertions)]
+fn verify_once() {
+    static VERIFIED: Once = Once::new();
+    VERIFIED.call_once(|| {
+        if let Err(mismatch) = verify_against_ffi() {
+            panic!("{mismatch}");
+        }
+    });
+}
@@ -9,7 +9,9 @@ use std::fmt;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
 
+use crate::dtx::{Dtx, DtxFrame};
 use crate::error::{Result, SpanDspError};
+use crate::g726::codes_to_byte_boundary;
 
 bitflags::bitflags! {
     /// G.722 codec option flags.
@@ -58,6 +60,15 @@ impl G722Rate {
     pub fn bps(self) -> u32 {
         self.as_raw() as u32
     }
+
+    /// Returns the number of bits per codeword.
+    pub fn bits_per_code(self) -> u8 {
+        match self {
+            G722Rate::Rate64000 => 8,
+            G722Rate::Rate56000 => 7,
+            G722Rate::Rate48000 => 6,
+        }
+    }
 }
 
 impl fmt::Display for G722Rate {
@@ -94,6 +105,9 @@ impl TryFrom<u32> for G722Rate {
 /// Created via `G722Encoder::new()`. Freed on drop via `g722_encode_free`.
 pub struct G722Encoder {
     ptr: NonNull<spandsp_sys::g722_encode_state_t>,
+    rate: G722Rate,
+    options: G722Options,
+    pending_bits: u32,
 }
 
 impl G722Encoder {
@@ -107,7 +121,22 @@ impl G722Encoder {
             )
         };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            rate,
+            options,
+            pending_bits: 0,
+        })
+    }
+
+    /// Returns the bit rate this encoder was initialized with.
+    pub fn rate(&self) -> G722Rate {
+        self.rate
+    }
+
+    /// Returns the option flags this encoder was initialized with.
+    pub fn options(&self) -> G722Options {
+        self.options
     }
 
     /// Encode linear PCM audio to G.722.
@@ -115,18 +144,83 @@ impl G722Encoder {
     /// Returns the number of G.722 bytes produced.
     pub fn encode(&mut self, g722_data: &mut [u8], amp: &[i16]) -> usize {
         let len = amp.len().min(c_int::MAX as usize) as c_int;
+        if self.options.contains(G722Options::PACKED) {
+            let bits = self.codes_for(len as usize) as u64 * self.rate.bits_per_code() as u64;
+            self.pending_bits = ((self.pending_bits as u64 + bits) % 8) as u32;
+        }
         unsafe {
             spandsp_sys::g722_encode(self.ptr.as_ptr(), g722_data.as_mut_ptr(), amp.as_ptr(), len)
                 as usize
         }
     }
 
+    /// Codewords produced from `samples` input samples: one per sample at
+    /// 8 kHz, one per pair at 16 kHz.
+    fn codes_for(&self, samples: usize) -> usize {
+        if self.options.contains(G722Options::SAMPLE_RATE_8000) {
+            samples
+        } else {
+            samples.div_ceil(2)
+        }
+    }
+
+    /// Bits of encoded audio held back because they do not fill a byte.
+    /// Always 0 unless [`G722Options::PACKED`] is set at 56 or 48 kbit/s.
+    pub fn pending_bits(&self) -> u32 {
+        self.pending_bits
+    }
+
+    /// Complete the last packed byte at the end of a recording, so its
+    /// final samples are not lost.
+    ///
+    /// Encodes just enough silence to fill the byte (at most 7 codewords)
+    /// and returns the number of bytes written, 0 if nothing was pending.
+    /// Returns `InvalidInput` if `g722_data` cannot hold them.
+    pub fn flush(&mut self, g722_data: &mut [u8]) -> Result<usize> {
+        let bits = self.rate.bits_per_code() as u32;
+        let codes = codes_to_byte_boundary(self.pending_bits, bits);
+        let bytes = (self.pending_bits as usize + codes * bits as usize) / 8;
+        if g722_data.len() < bytes {
+            return Err(SpanDspError::InvalidInput(format!(
+                "flushing G.722 needs {bytes} bytes, buffer holds {}",
+                g722_data.len()
+            )));
+        }
+        if codes == 0 {
+            return Ok(0);
+        }
+        let samples = if self.options.contains(G722Options::SAMPLE_RATE_8000) {
+            codes
+        } else {
+            2 * codes
+        };
+        Ok(self.encode(g722_data, &vec![0; samples]))
+    }
+
+    /// Encode a frame with discontinuous transmission, as decided by `dtx`.
+    ///
+    /// `amp` must be at the encoder's input rate (16 kHz unless
+    /// [`G722Options::SAMPLE_RATE_8000`] is set); the VAD threshold is in
+    /// dBm0 either way.
+    pub fn encode_dtx(&mut self, dtx: &mut Dtx, g722_data: &mut [u8], amp: &[i16]) -> DtxFrame {
+        dtx.process(amp, |amp| self.encode(g722_data, amp))
+    }
+
     /// Return the raw pointer.
     pub fn as_ptr(&self) -> *mut spandsp_sys::g722_encode_state_t {
         self.ptr.as_ptr()
     }
 }
 
+impl fmt::Debug for G722Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("G722Encoder")
+            .field("rate", &self.rate)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for G722Encoder {
     fn drop(&mut self) {
         unsafe {
@@ -144,6 +238,8 @@ impl Drop for G722Encoder {
 /// Created via `G722Decoder::new()`. Freed on drop via `g722_decode_free`.
 pub struct G722Decoder {
     ptr: NonNull<spandsp_sys::g722_decode_state_t>,
+    rate: G722Rate,
+    options: G722Options,
 }
 
 impl G722Decoder {
@@ -157,7 +253,17 @@ impl G722Decoder {
             )
         };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self { ptr, rate, options })
+    }
+
+    /// Returns the bit rate this decoder was initialized with.
+    pub fn rate(&self) -> G722Rate {
+        self.rate
+    }
+
+    /// Returns the option flags this decoder was initialized with.
+    pub fn options(&self) -> G722Options {
+        self.options
     }
 
     /// Decode G.722 data to linear PCM.
@@ -177,6 +283,15 @@ impl G722Decoder {
     }
 }
 
+impl fmt::Debug for G722Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("G722Decoder")
+            .field("rate", &self.rate)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for G722Decoder {
     fn drop(&mut self) {
         unsafe {
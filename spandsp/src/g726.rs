@@ -8,6 +8,7 @@ use std::fmt;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
 
+use crate::dtx::{Dtx, DtxFrame};
 use crate::error::{Result, SpanDspError};
 
 /// External coding type for G.726 interworking.
@@ -139,6 +140,25 @@ impl TryFrom<u32> for G726Rate {
     }
 }
 
+/// Number of `bits`-wide codes that bring `pending_bits` up to a whole
+/// number of bytes.
+pub(crate) fn codes_to_byte_boundary(pending_bits: u32, bits: u32) -> usize {
+    (0..8)
+        .find(|k| (pending_bits + k * bits) % 8 == 0)
+        .unwrap_or(0) as usize
+}
+
+/// An input buffer of `len` silent samples in `encoding`, laid out as
+/// [`G726State::encode`] takes it.
+pub(crate) fn silence(encoding: G726Encoding, len: usize) -> Vec<i16> {
+    let word = match encoding {
+        G726Encoding::Linear => 0,
+        G726Encoding::ULaw => i16::from_ne_bytes([0xFF; 2]),
+        G726Encoding::ALaw => i16::from_ne_bytes([0xD5; 2]),
+    };
+    vec![word; len]
+}
+
 /// RAII wrapper around `g726_state_t`.
 ///
 /// A single state handles both encoding and decoding, depending on which
@@ -146,6 +166,10 @@ impl TryFrom<u32> for G726Rate {
 /// `g726_free`.
 pub struct G726State {
     ptr: NonNull<spandsp_sys::g726_state_t>,
+    rate: G726Rate,
+    encoding: G726Encoding,
+    packing: G726Packing,
+    pending_bits: u32,
 }
 
 impl G726State {
@@ -160,7 +184,28 @@ impl G726State {
             )
         };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            rate,
+            encoding,
+            packing,
+            pending_bits: 0,
+        })
+    }
+
+    /// Returns the bit rate this state was initialized with.
+    pub fn rate(&self) -> G726Rate {
+        self.rate
+    }
+
+    /// Returns the external coding this state was initialized with.
+    pub fn encoding(&self) -> G726Encoding {
+        self.encoding
+    }
+
+    /// Returns the bit packing this state was initialized with.
+    pub fn packing(&self) -> G726Packing {
+        self.packing
     }
 
     /// Encode linear PCM (or A-law/u-law per init) to G.726.
@@ -168,12 +213,55 @@ impl G726State {
     /// Returns the number of G.726 bytes produced.
     pub fn encode(&mut self, g726_data: &mut [u8], amp: &[i16]) -> usize {
         let len = amp.len().min(c_int::MAX as usize) as c_int;
+        if self.packing != G726Packing::None {
+            let bits = len as u64 * self.rate.bits_per_sample() as u64;
+            self.pending_bits = ((self.pending_bits as u64 + bits) % 8) as u32;
+        }
         unsafe {
             spandsp_sys::g726_encode(self.ptr.as_ptr(), g726_data.as_mut_ptr(), amp.as_ptr(), len)
                 as usize
         }
     }
 
+    /// Bits of encoded audio held back because they do not fill a byte.
+    /// Always 0 without packing.
+    pub fn pending_bits(&self) -> u32 {
+        self.pending_bits
+    }
+
+    /// Complete the last packed byte at the end of a recording, so its
+    /// final samples are not lost.
+    ///
+    /// Encodes just enough silence to fill the byte (at most 7 samples) and
+    /// returns the number of bytes written, 0 if nothing was pending. Returns
+    /// `InvalidInput` if `g726_data` cannot hold them.
+    pub fn flush(&mut self, g726_data: &mut [u8]) -> Result<usize> {
+        let bits = self.rate.bits_per_sample() as u32;
+        let codes = codes_to_byte_boundary(self.pending_bits, bits);
+        let bytes = (self.pending_bits as usize + codes * bits as usize) / 8;
+        if g726_data.len() < bytes {
+            return Err(SpanDspError::InvalidInput(format!(
+                "flushing G.726 needs {bytes} bytes, buffer holds {}",
+                g726_data.len()
+            )));
+        }
+        if codes == 0 {
+            return Ok(0);
+        }
+        Ok(self.encode(g726_data, &silence(self.encoding, codes)))
+    }
+
+    /// Encode a frame with discontinuous transmission, as decided by `dtx`.
+    ///
+    /// Voice activity can only be judged on linear input, so with A-law or
+    /// u-law input every frame is encoded.
+    pub fn encode_dtx(&mut self, dtx: &mut Dtx, g726_data: &mut [u8], amp: &[i16]) -> DtxFrame {
+        if self.encoding != G726Encoding::Linear {
+            return DtxFrame::Voice(self.encode(g726_data, amp));
+        }
+        dtx.process(amp, |amp| self.encode(g726_data, amp))
+    }
+
     /// Decode G.726 data to linear PCM (or A-law/u-law per init).
     ///
     /// Returns the number of samples produced.
@@ -195,6 +283,16 @@ impl G726State {
     }
 }
 
+impl fmt::Debug for G726State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("G726State")
+            .field("rate", &self.rate)
+            .field("encoding", &self.encoding)
+            .field("packing", &self.packing)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for G726State {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,690 @@
+//! Pure-Rust G.726 ADPCM codec.
+//!
+//! Enabled by the `pure-g726` feature. [`G726State`] has the same API as
+//! [`crate::g726::G726State`] and produces bit-identical output, but runs
+//! entirely in Rust, so switching between the two is a matter of changing an
+//! import. The rest of the crate still links libspandsp, so the feature does
+//! not remove the need to build it. The algorithm follows the ITU G.726 reference as implemented in
+//! spandsp's `g726.c`, including the synchronous tandem adjustment applied
+//! when decoding to A-law or u-law.
+
+use std::fmt;
+
+use crate::dtx::{Dtx, DtxFrame};
+use crate::error::{Result, SpanDspError};
+use crate::g711::{alaw_to_linear, linear_to_alaw, linear_to_ulaw, ulaw_to_linear};
+pub use crate::g726::{G726Encoding, G726Packing, G726Rate};
+use crate::g726::{codes_to_byte_boundary, silence};
+
+// ---------------------------------------------------------------------------
+// Per-rate quantizer tables
+// ---------------------------------------------------------------------------
+
+/// Quantizer decision levels, log2 of the reconstructed magnitude, scale
+/// factor multipliers and speed control values for one bit rate.
+struct RateTables {
+    qtab: &'static [i32],
+    quantizer_states: i32,
+    dqlntab: &'static [i32],
+    witab: &'static [i32],
+    fitab: &'static [i32],
+}
+
+static RATE_16: RateTables = RateTables {
+    qtab: &[261],
+    quantizer_states: 4,
+    dqlntab: &[116, 365, 365, 116],
+    witab: &[-704, 14048, 14048, -704],
+    fitab: &[0x000, 0xE00, 0xE00, 0x000],
+};
+
+static RATE_24: RateTables = RateTables {
+    qtab: &[8, 218, 331],
+    quantizer_states: 7,
+    dqlntab: &[-2048, 135, 273, 373, 373, 273, 135, -2048],
+    witab: &[-128, 960, 4384, 18624, 18624, 4384, 960, -128],
+    fitab: &[0x000, 0x200, 0x400, 0xE00, 0xE00, 0x400, 0x200, 0x000],
+};
+
+static RATE_32: RateTables = RateTables {
+    qtab: &[-124, 80, 178, 246, 300, 349, 400],
+    quantizer_states: 15,
+    dqlntab: &[
+        -2048, 4, 135, 213, 273, 323, 373, 425, 425, 373, 323, 273, 213, 135, 4, -2048,
+    ],
+    witab: &[
+        -384, 576, 1312, 2048, 3584, 6336, 11360, 35904, 35904, 11360, 6336, 3584, 2048, 1312, 576,
+        -384,
+    ],
+    fitab: &[
+        0x000, 0x000, 0x000, 0x200, 0x200, 0x200, 0x600, 0xE00, 0xE00, 0x600, 0x200, 0x200, 0x200,
+        0x000, 0x000, 0x000,
+    ],
+};
+
+static RATE_40: RateTables = RateTables {
+    qtab: &[
+        -122, -16, 68, 139, 198, 250, 298, 339, 378, 413, 445, 475, 502, 528, 553,
+    ],
+    quantizer_states: 31,
+    dqlntab: &[
+        -2048, -66, 28, 104, 169, 224, 274, 318, 358, 395, 429, 459, 488, 514, 539, 566, 566, 539,
+        514, 488, 459, 429, 395, 358, 318, 274, 224, 169, 104, 28, -66, -2048,
+    ],
+    witab: &[
+        448, 448, 768, 1248, 1280, 1312, 1856, 3200, 4512, 5728, 7008, 8960, 11456, 14080, 16928,
+        22272, 22272, 16928, 14080, 11456, 8960, 7008, 5728, 4512, 3200, 1856, 1312, 1280, 1248,
+        768, 448, 448,
+    ],
+    fitab: &[
+        0x000, 0x000, 0x000, 0x000, 0x000, 0x200, 0x200, 0x200, 0x200, 0x200, 0x400, 0x600, 0x800,
+        0xA00, 0xC00, 0xC00, 0xC00, 0xC00, 0xA00, 0x800, 0x600, 0x400, 0x200, 0x200, 0x200, 0x200,
+        0x200, 0x000, 0x000, 0x000, 0x000, 0x000,
+    ],
+};
+
+fn rate_tables(rate: G726Rate) -> &'static RateTables {
+    match rate {
+        G726Rate::Rate16000 => &RATE_16,
+        G726Rate::Rate24000 => &RATE_24,
+        G726Rate::Rate32000 => &RATE_32,
+        G726Rate::Rate40000 => &RATE_40,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Fixed-point helpers
+// ---------------------------------------------------------------------------
+
+/// Number of significant bits in `val` (0 for 0), i.e. the index of the
+/// first power of two greater than `val`.
+#[inline]
+fn bit_len(val: i32) -> i32 {
+    32 - (val as u32).leading_zeros() as i32
+}
+
+/// Index of the first entry in `table` greater than `val`.
+#[inline]
+fn quan(val: i32, table: &[i32]) -> i32 {
+    table.iter().position(|&t| val < t).unwrap_or(table.len()) as i32
+}
+
+/// Multiply a predictor coefficient by a value in the codec's 4-bit
+/// exponent, 6-bit mantissa floating point format.
+fn fmult(an: i32, srn: i32) -> i32 {
+    let anmag = if an > 0 { an } else { (-an) & 0x1FFF };
+    let anexp = bit_len(anmag) - 6;
+    let anmant = if anmag == 0 {
+        32
+    } else if anexp >= 0 {
+        anmag >> anexp
+    } else {
+        anmag << -anexp
+    };
+    let wanexp = anexp + ((srn >> 6) & 0xF) - 13;
+    let wanmant = (anmant * (srn & 0x3F) + 0x30) >> 4;
+    let retval = if wanexp >= 0 {
+        (wanmant << wanexp) & 0x7FFF
+    } else {
+        wanmant >> -wanexp
+    };
+    if (an ^ srn) < 0 { -retval } else { retval }
+}
+
+/// Quantize the difference signal `d` with scale factor `y`.
+fn quantize(d: i32, y: i32, table: &[i32], quantizer_states: i32) -> i32 {
+    let dqm = d.abs();
+    let exp = bit_len(dqm >> 1);
+    let mant = ((dqm << 7) >> exp) & 0x7F;
+    let dl = (exp << 7) + mant;
+    let dln = dl - (y >> 2);
+    let size = (quantizer_states - 1) >> 1;
+    let i = quan(dln, &table[..size as usize]);
+    if d < 0 {
+        (size << 1) + 1 - i
+    } else if i == 0 && (quantizer_states & 1) != 0 {
+        // Zero is only a valid code with an even number of states.
+        quantizer_states
+    } else {
+        i
+    }
+}
+
+/// Reconstruct the quantized difference signal from its log magnitude.
+fn reconstruct(sign: bool, dqln: i32, y: i32) -> i32 {
+    let dql = dqln + (y >> 2);
+    if dql < 0 {
+        return if sign { -0x8000 } else { 0 };
+    }
+    let dex = (dql >> 7) & 15;
+    let dqt = 128 + (dql & 127);
+    let dq = (dqt << 7) >> (14 - dex);
+    if sign { dq - 0x8000 } else { dq }
+}
+
+/// Convert a magnitude and sign to the 4-bit exponent, 6-bit mantissa form
+/// held in the predictor delay lines.
+#[inline]
+fn to_float(mag: i32, negative: bool) -> i16 {
+    let exp = bit_len(mag);
+    let v = (exp << 6) + ((mag << 6) >> exp);
+    (if negative { v - 0x400 } else { v }) as i16
+}
+
+#[inline]
+fn saturate(amp: i32) -> i16 {
+    amp.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+fn tandem_adjust_alaw(sr: i32, se: i32, y: i32, i: i32, sign: i32, tables: &RateTables) -> u8 {
+    let sr = if sr <= -32768 { -1 } else { sr };
+    let sp = linear_to_alaw(saturate((sr >> 1) << 3));
+    let dx = (alaw_to_linear(sp) as i32 >> 2) - se;
+    let id = quantize(dx, y, tables.qtab, tables.quantizer_states);
+    if id == i {
+        return sp;
+    }
+    // Codes run 8, 9, ... F, 0, 1, ... 7; compare them as biased unsigned.
+    let im = i ^ sign;
+    let imx = id ^ sign;
+    let sp = sp as i32;
+    let sd = if imx > im {
+        // Adjust to the next lower value.
+        if sp & 0x80 != 0 {
+            if sp == 0xD5 {
+                0x55
+            } else {
+                ((sp ^ 0x55) - 1) ^ 0x55
+            }
+        } else if sp == 0x2A {
+            0x2A
+        } else {
+            ((sp ^ 0x55) + 1) ^ 0x55
+        }
+    } else if sp & 0x80 != 0 {
+        // Adjust to the next higher value.
+        if sp == 0xAA {
+            0xAA
+        } else {
+            ((sp ^ 0x55) + 1) ^ 0x55
+        }
+    } else if sp == 0x55 {
+        0xD5
+    } else {
+        ((sp ^ 0x55) - 1) ^ 0x55
+    };
+    sd as u8
+}
+
+fn tandem_adjust_ulaw(sr: i32, se: i32, y: i32, i: i32, sign: i32, tables: &RateTables) -> u8 {
+    let sr = if sr <= -32768 { 0 } else { sr };
+    let sp = linear_to_ulaw(saturate(sr << 2));
+    let dx = (ulaw_to_linear(sp) as i32 >> 2) - se;
+    let id = quantize(dx, y, tables.qtab, tables.quantizer_states);
+    if id == i {
+        return sp;
+    }
+    let im = i ^ sign;
+    let imx = id ^ sign;
+    let sp = sp as i32;
+    let sd = if imx > im {
+        if sp & 0x80 != 0 {
+            if sp == 0xFF { 0x7E } else { sp + 1 }
+        } else if sp == 0 {
+            0
+        } else {
+            sp - 1
+        }
+    } else if sp & 0x80 != 0 {
+        if sp == 0x80 { 0x80 } else { sp - 1 }
+    } else if sp == 0x7F {
+        0xFE
+    } else {
+        sp + 1
+    };
+    sd as u8
+}
+
+// ---------------------------------------------------------------------------
+// Codec state
+// ---------------------------------------------------------------------------
+
+/// Adaptive predictor and quantizer state shared by encoder and decoder.
+#[derive(Clone)]
+struct Adpcm {
+    yl: i32,
+    yu: i16,
+    dms: i16,
+    dml: i16,
+    ap: i16,
+    a: [i16; 2],
+    b: [i16; 6],
+    pk: [i16; 2],
+    dq: [i16; 6],
+    sr: [i16; 2],
+    td: bool,
+}
+
+impl Adpcm {
+    fn new() -> Self {
+        Self {
+            yl: 34816,
+            yu: 544,
+            dms: 0,
+            dml: 0,
+            ap: 0,
+            a: [0; 2],
+            b: [0; 6],
+            pk: [0; 2],
+            dq: [32; 6],
+            sr: [32; 2],
+            td: false,
+        }
+    }
+
+    fn predictor_zero(&self) -> i32 {
+        self.b
+            .iter()
+            .zip(&self.dq)
+            .map(|(&b, &dq)| fmult(b as i32 >> 2, dq as i32))
+            .sum()
+    }
+
+    fn predictor_pole(&self) -> i32 {
+        fmult(self.a[1] as i32 >> 2, self.sr[1] as i32)
+            + fmult(self.a[0] as i32 >> 2, self.sr[0] as i32)
+    }
+
+    fn step_size(&self) -> i32 {
+        if self.ap >= 256 {
+            return self.yu as i32;
+        }
+        let mut y = self.yl >> 6;
+        let dif = self.yu as i32 - y;
+        let al = self.ap as i32 >> 2;
+        if dif > 0 {
+            y += (dif * al) >> 6;
+        } else if dif < 0 {
+            y += (dif * al + 0x3F) >> 6;
+        }
+        y
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update(&mut self, bits: u8, y: i32, wi: i32, fi: i32, dq: i32, sr: i32, dqsez: i32) {
+        let pk0: i16 = (dqsez < 0).into();
+        let mag = dq & 0x7FFF;
+
+        // Transition detect
+        let ylint = self.yl >> 15;
+        let ylfrac = (self.yl >> 10) & 0x1F;
+        let thr1 = (32 + ylfrac) << ylint;
+        let thr2 = if ylint > 9 { 31 << 10 } else { thr1 };
+        let dqthr = (thr2 + (thr2 >> 1)) >> 1;
+        let tr = self.td && mag > dqthr;
+
+        // Quantizer scale factor adaptation
+        let yu = (y + ((wi - y) >> 5)).clamp(544, 5120);
+        self.yu = yu as i16;
+        self.yl += yu + ((-self.yl) >> 6);
+
+        // Adaptive predictor coefficients
+        let mut a2p = 0;
+        if tr {
+            self.a = [0; 2];
+            self.b = [0; 6];
+        } else {
+            let pks1 = pk0 ^ self.pk[0];
+            let a1 = self.a[0] as i32;
+            a2p = self.a[1] as i32 - (self.a[1] as i32 >> 7);
+            if dqsez != 0 {
+                let fa1 = if pks1 != 0 { a1 } else { -a1 };
+                if fa1 < -8191 {
+                    a2p -= 0x100;
+                } else if fa1 > 8191 {
+                    a2p += 0xFF;
+                } else {
+                    a2p += fa1 >> 5;
+                }
+                if pk0 ^ self.pk[1] != 0 {
+                    if a2p <= -12160 {
+                        a2p = -12288;
+                    } else if a2p >= 12416 {
+                        a2p = 12288;
+                    } else {
+                        a2p -= 0x80;
+                    }
+                } else if a2p <= -12416 {
+                    a2p = -12288;
+                } else if a2p >= 12160 {
+                    a2p = 12288;
+                } else {
+                    a2p += 0x80;
+                }
+            }
+            self.a[1] = a2p as i16;
+
+            let mut a1 = a1 - (a1 >> 8);
+            if dqsez != 0 {
+                if pks1 == 0 {
+                    a1 += 192;
+                } else {
+                    a1 -= 192;
+                }
+            }
+            let a1ul = 15360 - a2p;
+            self.a[0] = a1.clamp(-a1ul, a1ul) as i16;
+
+            let leak = if bits == 5 { 9 } else { 8 };
+            for (b, &dqn) in self.b.iter_mut().zip(&self.dq) {
+                let mut bn = *b as i32;
+                bn -= bn >> leak;
+                if mag != 0 {
+                    if (dq ^ dqn as i32) >= 0 {
+                        bn += 128;
+                    } else {
+                        bn -= 128;
+                    }
+                }
+                *b = bn as i16;
+            }
+        }
+
+        self.dq.copy_within(0..5, 1);
+        self.dq[0] = if mag == 0 {
+            if dq >= 0 { 0x20 } else { 0xFC20u16 as i16 }
+        } else {
+            to_float(mag, dq < 0)
+        };
+
+        self.sr[1] = self.sr[0];
+        self.sr[0] = if sr == 0 {
+            0x20
+        } else if sr > 0 {
+            to_float(sr, false)
+        } else if sr > -32768 {
+            to_float(-sr, true)
+        } else {
+            0xFC20u16 as i16
+        };
+
+        self.pk[1] = self.pk[0];
+        self.pk[0] = pk0;
+
+        // Tone detect
+        self.td = !tr && a2p < -11776;
+
+        // Adaptation speed control
+        self.dms += ((fi - self.dms as i32) >> 5) as i16;
+        self.dml += (((fi << 2) - self.dml as i32) >> 7) as i16;
+        let ap = self.ap as i32;
+        self.ap = if tr {
+            256
+        } else if y < 1536
+            || self.td
+            || (((self.dms as i32) << 2) - self.dml as i32).abs() >= (self.dml as i32 >> 3)
+        {
+            (ap + ((0x200 - ap) >> 4)) as i16
+        } else {
+            (ap + ((-ap) >> 4)) as i16
+        };
+    }
+
+    /// Encode one 14-bit linear sample, returning the ADPCM code.
+    fn encode(&mut self, tables: &RateTables, bits: u8, sl: i32) -> u8 {
+        let sezi = self.predictor_zero();
+        let sei = sezi + self.predictor_pole();
+        let se = sei >> 1;
+        let d = sl - se;
+        let y = self.step_size();
+        let i = quantize(d, y, tables.qtab, tables.quantizer_states);
+        let sign = 1 << (bits - 1);
+        let dq = reconstruct(i & sign != 0, tables.dqlntab[i as usize], y);
+        let sr = if dq < 0 { se - (dq & 0x3FFF) } else { se + dq };
+        let dqsez = sr + (sezi >> 1) - se;
+        let idx = i as usize;
+        self.update(bits, y, tables.witab[idx], tables.fitab[idx], dq, sr, dqsez);
+        i as u8
+    }
+
+    /// Decode one ADPCM code to a linear sample, or a G.711 byte when
+    /// interworking with A-law or u-law.
+    fn decode(&mut self, tables: &RateTables, bits: u8, encoding: G726Encoding, code: u8) -> i16 {
+        let i = (code & ((1 << bits) - 1)) as i32;
+        let sezi = self.predictor_zero();
+        let sei = sezi + self.predictor_pole();
+        let y = self.step_size();
+        let sign = 1 << (bits - 1);
+        let dq = reconstruct(i & sign != 0, tables.dqlntab[i as usize], y);
+        let se = sei >> 1;
+        let sr = if dq < 0 { se - (dq & 0x3FFF) } else { se + dq };
+        let dqsez = sr + (sezi >> 1) - se;
+        let idx = i as usize;
+        self.update(bits, y, tables.witab[idx], tables.fitab[idx], dq, sr, dqsez);
+        match encoding {
+            G726Encoding::Linear => (sr << 2) as i16,
+            G726Encoding::ALaw => tandem_adjust_alaw(sr, se, y, i, sign, tables) as i16,
+            G726Encoding::ULaw => tandem_adjust_ulaw(sr, se, y, i, sign, tables) as i16,
+        }
+    }
+}
+
+/// Pure-Rust G.726 state, a drop-in replacement for
+/// [`crate::g726::G726State`].
+///
+/// As with the C codec, A-law and u-law samples are carried one per byte in
+/// the `i16` buffers (in native byte order), and `len` counts bytes.
+#[derive(Clone)]
+pub struct G726State {
+    rate: G726Rate,
+    encoding: G726Encoding,
+    packing: G726Packing,
+    tables: &'static RateTables,
+    bits: u8,
+    adpcm: Adpcm,
+    in_buffer: u32,
+    in_bits: u32,
+    out_buffer: u32,
+    out_bits: u32,
+}
+
+impl G726State {
+    /// Create a new G.726 state.
+    pub fn new(rate: G726Rate, encoding: G726Encoding, packing: G726Packing) -> Result<Self> {
+        Ok(Self {
+            rate,
+            encoding,
+            packing,
+            tables: rate_tables(rate),
+            bits: rate.bits_per_sample(),
+            adpcm: Adpcm::new(),
+            in_buffer: 0,
+            in_bits: 0,
+            out_buffer: 0,
+            out_bits: 0,
+        })
+    }
+
+    /// Returns the bit rate this state was initialized with.
+    pub fn rate(&self) -> G726Rate {
+        self.rate
+    }
+
+    /// Returns the external coding this state was initialized with.
+    pub fn encoding(&self) -> G726Encoding {
+        self.encoding
+    }
+
+    /// Returns the bit packing this state was initialized with.
+    pub fn packing(&self) -> G726Packing {
+        self.packing
+    }
+
+    /// Encode linear PCM (or A-law/u-law per init) to G.726.
+    ///
+    /// Returns the number of G.726 bytes produced. Encoding stops early if
+    /// `g726_data` fills up.
+    pub fn encode(&mut self, g726_data: &mut [u8], amp: &[i16]) -> usize {
+        let mut g726_bytes = 0;
+        for n in 0..amp.len() {
+            if g726_bytes >= g726_data.len() {
+                break;
+            }
+            let sl = match self.encoding {
+                G726Encoding::Linear => amp[n] as i32 >> 2,
+                G726Encoding::ALaw => alaw_to_linear(g711_byte(amp, n)) as i32 >> 2,
+                G726Encoding::ULaw => ulaw_to_linear(g711_byte(amp, n)) as i32 >> 2,
+            };
+            let code = self.adpcm.encode(self.tables, self.bits, sl) as u32;
+            let bits = self.bits as u32;
+            match self.packing {
+                G726Packing::None => {
+                    g726_data[g726_bytes] = code as u8;
+                    g726_bytes += 1;
+                }
+                G726Packing::Right => {
+                    self.out_buffer |= code << self.out_bits;
+                    self.out_bits += bits;
+                    if self.out_bits >= 8 {
+                        g726_data[g726_bytes] = self.out_buffer as u8;
+                        g726_bytes += 1;
+                        self.out_bits -= 8;
+                        self.out_buffer >>= 8;
+                    }
+                }
+                G726Packing::Left => {
+                    self.out_buffer = (self.out_buffer << bits) | code;
+                    self.out_bits += bits;
+                    if self.out_bits >= 8 {
+                        g726_data[g726_bytes] = (self.out_buffer >> (self.out_bits - 8)) as u8;
+                        g726_bytes += 1;
+                        self.out_bits -= 8;
+                    }
+                }
+            }
+        }
+        g726_bytes
+    }
+
+    /// Bits of encoded audio held back because they do not fill a byte.
+    /// Always 0 without packing.
+    pub fn pending_bits(&self) -> u32 {
+        self.out_bits
+    }
+
+    /// Complete the last packed byte at the end of a recording, so its
+    /// final samples are not lost.
+    ///
+    /// Encodes just enough silence to fill the byte (at most 7 samples) and
+    /// returns the number of bytes written, 0 if nothing was pending. Returns
+    /// `InvalidInput` if `g726_data` cannot hold them.
+    pub fn flush(&mut self, g726_data: &mut [u8]) -> Result<usize> {
+        let bits = self.bits as u32;
+        let codes = codes_to_byte_boundary(self.out_bits, bits);
+        let bytes = (self.out_bits as usize + codes * bits as usize) / 8;
+        if g726_data.len() < bytes {
+            return Err(SpanDspError::InvalidInput(format!(
+                "flushing G.726 needs {bytes} bytes, buffer holds {}",
+                g726_data.len()
+            )));
+        }
+        if codes == 0 {
+            return Ok(0);
+        }
+        Ok(self.encode(g726_data, &silence(self.encoding, codes)))
+    }
+
+    /// Encode a frame with discontinuous transmission, as decided by `dtx`.
+    ///
+    /// Voice activity can only be judged on linear input, so with A-law or
+    /// u-law input every frame is encoded.
+    pub fn encode_dtx(&mut self, dtx: &mut Dtx, g726_data: &mut [u8], amp: &[i16]) -> DtxFrame {
+        if self.encoding != G726Encoding::Linear {
+            return DtxFrame::Voice(self.encode(g726_data, amp));
+        }
+        dtx.process(amp, |amp| self.encode(g726_data, amp))
+    }
+
+    /// Decode G.726 data to linear PCM (or A-law/u-law per init).
+    ///
+    /// Returns the number of samples produced. Decoding stops early if `amp`
+    /// fills up.
+    pub fn decode(&mut self, amp: &mut [i16], g726_data: &[u8]) -> usize {
+        let capacity = match self.encoding {
+            G726Encoding::Linear => amp.len(),
+            _ => amp.len() * 2,
+        };
+        let bits = self.bits as u32;
+        let mask = (1u32 << bits) - 1;
+        let mut samples = 0;
+        let mut i = 0;
+        while samples < capacity {
+            let code = match self.packing {
+                G726Packing::None => {
+                    let Some(&code) = g726_data.get(i) else {
+                        break;
+                    };
+                    i += 1;
+                    code
+                }
+                G726Packing::Right => {
+                    if self.in_bits < bits {
+                        let Some(&byte) = g726_data.get(i) else {
+                            break;
+                        };
+                        i += 1;
+                        self.in_buffer |= (byte as u32) << self.in_bits;
+                        self.in_bits += 8;
+                    }
+                    let code = (self.in_buffer & mask) as u8;
+                    self.in_buffer >>= bits;
+                    self.in_bits -= bits;
+                    code
+                }
+                G726Packing::Left => {
+                    if self.in_bits < bits {
+                        let Some(&byte) = g726_data.get(i) else {
+                            break;
+                        };
+                        i += 1;
+                        self.in_buffer = (self.in_buffer << 8) | byte as u32;
+                        self.in_bits += 8;
+                    }
+                    let code = ((self.in_buffer >> (self.in_bits - bits)) & mask) as u8;
+                    self.in_bits -= bits;
+                    code
+                }
+            };
+            let sl = self
+                .adpcm
+                .decode(self.tables, self.bits, self.encoding, code);
+            match self.encoding {
+                G726Encoding::Linear => amp[samples] = sl,
+                _ => set_g711_byte(amp, samples, sl as u8),
+            }
+            samples += 1;
+        }
+        samples
+    }
+}
+
+/// Byte `n` of `amp`, viewed as a native-endian byte buffer.
+fn g711_byte(amp: &[i16], n: usize) -> u8 {
+    amp[n / 2].to_ne_bytes()[n % 2]
+}
+
+fn set_g711_byte(amp: &mut [i16], n: usize, byte: u8) {
+    let mut bytes = amp[n / 2].to_ne_bytes();
+    bytes[n % 2] = byte;
+    amp[n / 2] = i16::from_ne_bytes(bytes);
+}
+
+impl fmt::Debug for G726State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("G726State")
+            .field("rate", &self.rate)
+            .field("encoding", &self.encoding)
+            .field("packing", &self.packing)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,72 @@
+//! Output gain stage shared by the tone generators.
+//!
+//! A gain change is spread linearly over a number of samples rather than
+//! applied as a step, which would be heard as a click.
+
+/// Samples per millisecond at 8 kHz.
+const SAMPLES_PER_MS: usize = 8;
+
+/// A linear gain that moves towards its target one sample at a time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct GainRamp {
+    gain: f32,
+    target: f32,
+    target_db: f32,
+    step: f32,
+    remaining: usize,
+}
+
+impl GainRamp {
+    /// Unity gain, not ramping.
+    pub(crate) const fn unity() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            target_db: 0.0,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    /// The gain being ramped to, in dB. Silence is `f32::NEG_INFINITY`.
+    pub(crate) fn target_db(&self) -> f32 {
+        self.target_db
+    }
+
+    /// Samples left until the target is reached.
+    pub(crate) fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Start ramping from the current gain to `gain_db` over `ramp_ms`.
+    pub(crate) fn set_target_db(&mut self, gain_db: f32, ramp_ms: u32) {
+        self.target_db = gain_db;
+        self.target = 10.0f32.powf(gain_db / 20.0);
+        self.remaining = ramp_ms as usize * SAMPLES_PER_MS;
+        if self.remaining == 0 {
+            self.gain = self.target;
+            self.step = 0.0;
+        } else {
+            self.step = (self.target - self.gain) / self.remaining as f32;
+        }
+    }
+
+    /// Scale `amp` in place, advancing the ramp by `amp.len()` samples.
+    pub(crate) fn apply(&mut self, amp: &mut [i16]) {
+        if self.remaining == 0 && self.gain == 1.0 {
+            return;
+        }
+        for sample in amp {
+            if self.remaining > 0 {
+                self.remaining -= 1;
+                self.gain = if self.remaining == 0 {
+                    self.target
+                } else {
+                    self.gain + self.step
+                };
+            }
+            let scaled = (*sample as f32 * self.gain).round();
+            *sample = scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Safe wrappers around spandsp's GSM 06.10 full-rate codec.
+//!
+//! - `Gsm0610Encoder` wraps a `gsm0610_state_t` used for encoding.
+//! - `Gsm0610Decoder` wraps a `gsm0610_state_t` used for decoding.
+//!
+//! GSM-FR codes 20 ms frames of 160 samples at 8 kHz. spandsp only codes
+//! whole frames (whole frame pairs with [`Gsm0610Packing::Wav49`]), so
+//! [`Gsm0610Encoder::encode`] and [`Gsm0610Decoder::decode`] take as many
+//! whole frames as fit both buffers and leave the rest for the next call.
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::plc::{ConcealingDecoder, FrameDecoder};
+
+/// Samples in one GSM 06.10 frame (20 ms at 8 kHz).
+pub const GSM0610_FRAME_SAMPLES: usize = 160;
+
+/// How coded frames are laid out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gsm0610Packing {
+    /// One byte per codec parameter, 76 bytes a frame.
+    None,
+    /// Microsoft WAV49, two frames in 65 bytes, as in GSM `.wav` files.
+    Wav49,
+    /// RFC 3551 RTP payload, 33 bytes a frame.
+    #[default]
+    Voip,
+}
+
+impl Gsm0610Packing {
+    fn as_raw(self) -> c_int {
+        match self {
+            Gsm0610Packing::None => spandsp_sys::GSM0610_PACKING_NONE as c_int,
+            Gsm0610Packing::Wav49 => spandsp_sys::GSM0610_PACKING_WAV49 as c_int,
+            Gsm0610Packing::Voip => spandsp_sys::GSM0610_PACKING_VOIP as c_int,
+        }
+    }
+
+    /// Frames spandsp codes together: two for WAV49, otherwise one.
+    pub fn frames_per_unit(self) -> usize {
+        match self {
+            Gsm0610Packing::Wav49 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Samples coded together, [`frames_per_unit`](Self::frames_per_unit)
+    /// frames' worth.
+    pub fn samples_per_unit(self) -> usize {
+        self.frames_per_unit() * GSM0610_FRAME_SAMPLES
+    }
+
+    /// Bytes those samples code to.
+    pub fn bytes_per_unit(self) -> usize {
+        match self {
+            Gsm0610Packing::None => 76,
+            Gsm0610Packing::Wav49 => 65,
+            Gsm0610Packing::Voip => 33,
+        }
+    }
+}
+
+impl fmt::Display for Gsm0610Packing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gsm0610Packing::None => f.write_str("none"),
+            Gsm0610Packing::Wav49 => f.write_str("WAV49"),
+            Gsm0610Packing::Voip => f.write_str("VoIP"),
+        }
+    }
+}
+
+/// Create a `gsm0610_state_t` for `packing`.
+fn init(packing: Gsm0610Packing) -> Result<NonNull<spandsp_sys::gsm0610_state_t>> {
+    let ptr = unsafe { spandsp_sys::gsm0610_init(std::ptr::null_mut(), packing.as_raw()) };
+    NonNull::new(ptr).ok_or(SpanDspError::InitFailed)
+}
+
+/// Switch an existing state to `packing`.
+fn set_packing(ptr: NonNull<spandsp_sys::gsm0610_state_t>, packing: Gsm0610Packing) -> Result<()> {
+    let rc = unsafe { spandsp_sys::gsm0610_set_packing(ptr.as_ptr(), packing.as_raw()) };
+    if rc != 0 {
+        return Err(SpanDspError::ErrorCode(rc));
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Encoder
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around a `gsm0610_state_t` used for encoding.
+///
+/// Created via `Gsm0610Encoder::new()`. Freed on drop via `gsm0610_free`.
+pub struct Gsm0610Encoder {
+    ptr: NonNull<spandsp_sys::gsm0610_state_t>,
+    packing: Gsm0610Packing,
+}
+
+impl Gsm0610Encoder {
+    /// Create a new GSM 06.10 encoder.
+    pub fn new(packing: Gsm0610Packing) -> Result<Self> {
+        Ok(Self {
+            ptr: init(packing)?,
+            packing,
+        })
+    }
+
+    /// Returns the packing in use.
+    pub fn packing(&self) -> Gsm0610Packing {
+        self.packing
+    }
+
+    /// Change the packing, e.g. between a WAV49 file and an RTP stream.
+    /// Takes effect from the next frame.
+    pub fn set_packing(&mut self, packing: Gsm0610Packing) -> Result<()> {
+        set_packing(self.ptr, packing)?;
+        self.packing = packing;
+        Ok(())
+    }
+
+    /// Encode linear PCM audio to GSM 06.10.
+    ///
+    /// Encodes as many whole frames (frame pairs for WAV49) as both `amp`
+    /// and `gsm_data` hold; samples beyond them are not consumed. Returns
+    /// the number of GSM bytes produced.
+    pub fn encode(&mut self, gsm_data: &mut [u8], amp: &[i16]) -> usize {
+        let units = (amp.len() / self.packing.samples_per_unit())
+            .min(gsm_data.len() / self.packing.bytes_per_unit())
+            .min(c_int::MAX as usize / self.packing.samples_per_unit());
+        let len = (units * self.packing.samples_per_unit()) as c_int;
+        unsafe {
+            spandsp_sys::gsm0610_encode(self.ptr.as_ptr(), gsm_data.as_mut_ptr(), amp.as_ptr(), len)
+                .max(0) as usize
+        }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::gsm0610_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for Gsm0610Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gsm0610Encoder")
+            .field("packing", &self.packing)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Gsm0610Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::gsm0610_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Decoder
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around a `gsm0610_state_t` used for decoding.
+///
+/// Created via `Gsm0610Decoder::new()`. Freed on drop via `gsm0610_free`.
+pub struct Gsm0610Decoder {
+    ptr: NonNull<spandsp_sys::gsm0610_state_t>,
+    packing: Gsm0610Packing,
+}
+
+impl Gsm0610Decoder {
+    /// Create a new GSM 06.10 decoder.
+    pub fn new(packing: Gsm0610Packing) -> Result<Self> {
+        Ok(Self {
+            ptr: init(packing)?,
+            packing,
+        })
+    }
+
+    /// Returns the packing in use.
+    pub fn packing(&self) -> Gsm0610Packing {
+        self.packing
+    }
+
+    /// Change the packing, e.g. between a WAV49 file and an RTP stream.
+    /// Takes effect from the next frame.
+    pub fn set_packing(&mut self, packing: Gsm0610Packing) -> Result<()> {
+        set_packing(self.ptr, packing)?;
+        self.packing = packing;
+        Ok(())
+    }
+
+    /// Decode GSM 06.10 data to linear PCM.
+    ///
+    /// Decodes as many whole frames (frame pairs for WAV49) as `gsm_data`
+    /// holds and `amp` has room for; bytes beyond them are not consumed.
+    /// Returns the number of PCM samples produced, 0 if a VoIP frame is
+    /// missing its signature nibble.
+    pub fn decode(&mut self, amp: &mut [i16], gsm_data: &[u8]) -> usize {
+        let units = (gsm_data.len() / self.packing.bytes_per_unit())
+            .min(amp.len() / self.packing.samples_per_unit())
+            .min(c_int::MAX as usize / self.packing.bytes_per_unit());
+        let len = (units * self.packing.bytes_per_unit()) as c_int;
+        unsafe {
+            spandsp_sys::gsm0610_decode(self.ptr.as_ptr(), amp.as_mut_ptr(), gsm_data.as_ptr(), len)
+                .max(0) as usize
+        }
+    }
+
+    /// Put packet loss concealment in front of this decoder, for packets
+    /// of one frame (one frame pair for WAV49) each.
+    pub fn with_plc(self) -> Result<ConcealingDecoder<Self>> {
+        let frame_samples = self.packing.samples_per_unit();
+        ConcealingDecoder::new(self, frame_samples)
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::gsm0610_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl FrameDecoder for Gsm0610Decoder {
+    fn decode_into(&mut self, amp: &mut [i16], frame: &[u8]) -> usize {
+        self.decode(amp, frame)
+    }
+}
+
+impl fmt::Debug for Gsm0610Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gsm0610Decoder")
+            .field("packing", &self.packing)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Gsm0610Decoder {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::gsm0610_free(self.ptr.as_ptr());
+        }
+    }
+}
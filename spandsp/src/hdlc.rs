@@ -2,9 +2,13 @@
 //!
 //! - `HdlcTx` wraps `hdlc_tx_state_t` for HDLC transmit (bit-stuffing, CRC).
 //! - `HdlcRx` wraps `hdlc_rx_state_t` for HDLC receive (destuffing, CRC check).
+//!
+//! spandsp's engine always appends and checks an FCS. Relaying frames
+//! without one takes the `pure-hdlc` engine's `FcsMode::Raw`.
 
 extern crate spandsp_sys;
 
+use std::fmt;
 use std::os::raw::{c_int, c_void};
 use std::ptr::NonNull;
 
@@ -47,6 +51,7 @@ unsafe extern "C" fn hdlc_rx_frame_trampoline(
 /// Created via `HdlcRx::new()`. Freed on drop via `hdlc_rx_free`.
 pub struct HdlcRx {
     ptr: NonNull<spandsp_sys::hdlc_rx_state_t>,
+    crc32: bool,
     _callback: Option<Box<HdlcRxCallback>>,
 }
 
@@ -83,6 +88,7 @@ impl HdlcRx {
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
         Ok(Self {
             ptr,
+            crc32,
             _callback: Some(boxed),
         })
     }
@@ -102,6 +108,14 @@ impl HdlcRx {
         }
     }
 
+    /// Feed a bit, or a `SIG_STATUS_*` code from the demodulator, the way
+    /// spandsp's modems call `hdlc_rx_put_bit`.
+    pub(crate) fn put_bit_or_status(&mut self, bit: c_int) {
+        unsafe {
+            spandsp_sys::hdlc_rx_put_bit(self.ptr.as_ptr(), bit);
+        }
+    }
+
     /// Feed a single byte to the HDLC receiver.
     pub fn put_byte(&mut self, byte: u8) {
         unsafe {
@@ -129,6 +143,14 @@ impl HdlcRx {
     }
 }
 
+impl fmt::Debug for HdlcRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdlcRx")
+            .field("crc32", &self.crc32)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for HdlcRx {
     fn drop(&mut self) {
         unsafe {
@@ -161,6 +183,7 @@ unsafe extern "C" fn hdlc_tx_underflow_trampoline(user_data: *mut c_void) {
 /// Created via `HdlcTx::new()`. Freed on drop via `hdlc_tx_free`.
 pub struct HdlcTx {
     ptr: NonNull<spandsp_sys::hdlc_tx_state_t>,
+    crc32: bool,
     _callback: Option<Box<HdlcTxCallback>>,
 }
 
@@ -211,6 +234,7 @@ impl HdlcTx {
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
         Ok(Self {
             ptr,
+            crc32,
             _callback: boxed,
         })
     }
@@ -270,6 +294,15 @@ impl HdlcTx {
     }
 }
 
+impl fmt::Debug for HdlcTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdlcTx")
+            .field("crc32", &self.crc32)
+            .field("has_underflow_handler", &self._callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for HdlcTx {
     fn drop(&mut self) {
         unsafe {
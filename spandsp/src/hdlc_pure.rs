@@ -0,0 +1,580 @@
+//! Pure-Rust HDLC framing and deframing.
+//!
+//! Enabled by the `pure-hdlc` feature. [`HdlcTx`] and [`HdlcRx`] have the
+//! same API as their [`crate::hdlc`] counterparts and follow spandsp's
+//! `hdlc.c` bit for bit: flag and abort handling, zero-bit stuffing, the
+//! ITU CRC-16/CRC-32 frame check sequences and the framing-OK preamble
+//! threshold. They can be used where the C library is not available, or to
+//! cross-check it.
+//!
+//! Unlike spandsp's engine, which has no CRC-less mode, they also support
+//! [`FcsMode::Raw`], sending and receiving frames with no CRC added,
+//! checked or stripped.
+
+use std::fmt;
+
+use crate::error::{Result, SpanDspError};
+
+/// What the HDLC engines do with the frame check sequence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FcsMode {
+    /// The transmitter appends a CRC; the receiver checks and strips it.
+    /// spandsp's engine always works this way.
+    #[default]
+    Crc,
+    /// Frames pass through as they are: the transmitter sends exactly the
+    /// bytes queued, so the caller supplies any FCS, and the receiver hands
+    /// over everything between the flags unchecked. For relaying frames
+    /// whose CRC was computed or checked elsewhere.
+    Raw,
+}
+
+type HdlcRxCallback = Box<dyn FnMut(&[u8], bool)>;
+type HdlcTxCallback = Box<dyn FnMut()>;
+
+/// Largest frame body spandsp's HDLC engine accepts.
+const HDLC_MAXFRAME_LEN: usize = 400;
+
+/// Returned by the transmitter once it has drained after a zero-length frame
+/// (spandsp's `SIG_STATUS_END_OF_DATA`).
+const SIG_STATUS_END_OF_DATA: i32 = -7;
+
+// ---------------------------------------------------------------------------
+// CRCs
+// ---------------------------------------------------------------------------
+
+/// Residue of a good CRC-16 frame check sequence.
+const CRC_ITU16_GOOD: u16 = 0xF0B8;
+
+/// Residue of a good CRC-32 frame check sequence.
+const CRC_ITU32_GOOD: u32 = 0xDEBB_20E3;
+
+fn crc_itu16_calc(buf: &[u8], mut crc: u16) -> u16 {
+    for &byte in buf {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc_itu32_calc(buf: &[u8], mut crc: u32) -> u32 {
+    for &byte in buf {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+// ---------------------------------------------------------------------------
+// HdlcRx
+// ---------------------------------------------------------------------------
+
+/// Pure-Rust HDLC receiver, a drop-in replacement for
+/// [`crate::hdlc::HdlcRx`].
+pub struct HdlcRx {
+    crc32: bool,
+    crc_bytes: usize,
+    fcs_mode: FcsMode,
+    report_bad_frames: bool,
+    framing_ok_threshold: i32,
+    framing_ok_announced: bool,
+    flags_seen: i32,
+    raw_bit_stream: u32,
+    byte_in_progress: u32,
+    num_bits: u32,
+    max_frame_len: usize,
+    len: usize,
+    buffer: [u8; HDLC_MAXFRAME_LEN + 4],
+    handler: HdlcRxCallback,
+}
+
+impl HdlcRx {
+    /// Create a new HDLC receiver.
+    ///
+    /// - `crc32`: `true` for ITU CRC-32, `false` for ITU CRC-16.
+    /// - `report_bad_frames`: `true` to deliver frames that fail CRC.
+    /// - `framing_ok_threshold`: number of consecutive flags required before
+    ///   framing is considered OK.
+    /// - `handler`: closure called for each received frame. Arguments are
+    ///   `(frame_data, crc_ok)`.
+    pub fn new<F>(
+        crc32: bool,
+        report_bad_frames: bool,
+        framing_ok_threshold: i32,
+        handler: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(&[u8], bool) + 'static,
+    {
+        let buffer = [0; HDLC_MAXFRAME_LEN + 4];
+        Ok(Self {
+            crc32,
+            crc_bytes: if crc32 { 4 } else { 2 },
+            fcs_mode: FcsMode::Crc,
+            report_bad_frames,
+            framing_ok_threshold: framing_ok_threshold.max(1),
+            framing_ok_announced: false,
+            flags_seen: 0,
+            raw_bit_stream: 0,
+            byte_in_progress: 0,
+            num_bits: 0,
+            max_frame_len: buffer.len(),
+            len: 0,
+            buffer,
+            handler: Box::new(handler),
+        })
+    }
+
+    /// Feed a block of bytes to the HDLC receiver for deframing.
+    pub fn put(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            self.put_byte(byte);
+        }
+    }
+
+    /// Feed a single bit to the HDLC receiver.
+    pub fn put_bit(&mut self, bit: bool) {
+        self.raw_bit_stream = (self.raw_bit_stream << 1) | ((bit as u32) << 8);
+        self.put_bit_core();
+    }
+
+    /// Feed a single byte to the HDLC receiver.
+    pub fn put_byte(&mut self, byte: u8) {
+        self.raw_bit_stream |= byte as u32;
+        for _ in 0..8 {
+            self.raw_bit_stream <<= 1;
+            self.put_bit_core();
+        }
+    }
+
+    /// Restart the HDLC receiver (does not reset statistics).
+    pub fn restart(&mut self) {
+        self.framing_ok_announced = false;
+        self.flags_seen = 0;
+        self.raw_bit_stream = 0;
+        self.byte_in_progress = 0;
+        self.num_bits = 0;
+        self.len = 0;
+    }
+
+    /// Set the maximum acceptable frame length.
+    pub fn set_max_frame_len(&mut self, max_len: usize) {
+        self.max_frame_len = max_len
+            .saturating_add(self.crc_bytes)
+            .min(self.buffer.len());
+    }
+
+    /// Choose whether frames are checked against their FCS.
+    ///
+    /// In [`FcsMode::Raw`] every whole frame is delivered with its last
+    /// bytes intact and `crc_ok` set; only misaligned or overlength frames
+    /// are bad.
+    pub fn set_fcs_mode(&mut self, mode: FcsMode) -> Result<()> {
+        self.fcs_mode = mode;
+        Ok(())
+    }
+
+    /// How the frame check sequence is handled.
+    pub fn fcs_mode(&self) -> FcsMode {
+        self.fcs_mode
+    }
+
+    fn report_status(&mut self) {
+        (self.handler)(&[], true);
+    }
+
+    fn put_bit_core(&mut self) {
+        if self.raw_bit_stream & 0x3E00 == 0x3E00 {
+            // At least five ones in a row: a stuffed zero, a flag, an abort,
+            // or bit errors.
+            if self.raw_bit_stream & 0x4100 == 0 {
+                return;
+            }
+            if self.raw_bit_stream & 0xFE00 == 0x7E00 {
+                self.flag_or_abort();
+                return;
+            }
+        }
+        self.num_bits += 1;
+        if self.flags_seen < self.framing_ok_threshold {
+            return;
+        }
+        self.byte_in_progress = (self.byte_in_progress | (self.raw_bit_stream & 0x100)) >> 1;
+        if self.num_bits == 8 {
+            if self.len < self.max_frame_len {
+                self.buffer[self.len] = self.byte_in_progress as u8;
+                self.len += 1;
+            } else {
+                // Overlength: abandon the frame and wait for the next flag.
+                self.len = self.buffer.len() + 1;
+                self.flags_seen = self.framing_ok_threshold - 1;
+            }
+            self.num_bits = 0;
+        }
+    }
+
+    fn flag_or_abort(&mut self) {
+        if self.raw_bit_stream & 0x8000 != 0 {
+            self.report_status();
+            // Back off so another flag is needed before collecting octets.
+            if self.flags_seen < self.framing_ok_threshold - 1 {
+                self.flags_seen = 0;
+            } else {
+                self.flags_seen = self.framing_ok_threshold - 1;
+            }
+        } else if self.flags_seen >= self.framing_ok_threshold {
+            if self.len > 0 {
+                self.end_of_frame();
+            }
+        } else {
+            // Preamble flags must be back to back.
+            if self.flags_seen > 0 && self.num_bits != 7 {
+                self.flags_seen = 0;
+            }
+            self.flags_seen += 1;
+            if self.flags_seen >= self.framing_ok_threshold && !self.framing_ok_announced {
+                self.report_status();
+                self.framing_ok_announced = true;
+            }
+        }
+        self.len = 0;
+        self.num_bits = 0;
+    }
+
+    fn end_of_frame(&mut self) {
+        let len = self.len;
+        if self.fcs_mode == FcsMode::Raw {
+            if self.num_bits == 7 && len <= self.max_frame_len {
+                (self.handler)(&self.buffer[..len], true);
+            } else if self.report_bad_frames {
+                let len = len.min(self.buffer.len());
+                (self.handler)(&self.buffer[..len], false);
+            }
+            return;
+        }
+        if self.num_bits == 7 && len >= self.crc_bytes && len <= self.max_frame_len {
+            let frame = &self.buffer[..len];
+            let crc_ok = if self.crc32 {
+                crc_itu32_calc(frame, 0xFFFF_FFFF) == CRC_ITU32_GOOD
+            } else {
+                crc_itu16_calc(frame, 0xFFFF) == CRC_ITU16_GOOD
+            };
+            if crc_ok || self.report_bad_frames {
+                (self.handler)(&self.buffer[..len - self.crc_bytes], crc_ok);
+            }
+        } else if self.report_bad_frames {
+            // Too short, too long, or the flag is misaligned with its octets.
+            let len = len.saturating_sub(self.crc_bytes).min(self.buffer.len());
+            (self.handler)(&self.buffer[..len], false);
+        }
+    }
+}
+
+impl fmt::Debug for HdlcRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdlcRx")
+            .field("crc32", &self.crc32)
+            .field("fcs_mode", &self.fcs_mode)
+            .finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HdlcTx
+// ---------------------------------------------------------------------------
+
+/// Pure-Rust HDLC transmitter, a drop-in replacement for
+/// [`crate::hdlc::HdlcTx`].
+pub struct HdlcTx {
+    crc32: bool,
+    crc_bytes: usize,
+    crc: u32,
+    fcs_mode: FcsMode,
+    inter_frame_flags: i32,
+    progressive: bool,
+    max_frame_len: usize,
+    octets_in_progress: u32,
+    num_bits: u32,
+    idle_octet: u8,
+    flag_octets: i32,
+    abort_octets: i32,
+    report_flag_underflow: bool,
+    len: usize,
+    pos: usize,
+    buffer: [u8; HDLC_MAXFRAME_LEN + 4],
+    byte: i32,
+    bits: u32,
+    tx_end: bool,
+    underflow_handler: Option<HdlcTxCallback>,
+}
+
+impl HdlcTx {
+    /// Create a new HDLC transmitter.
+    ///
+    /// - `crc32`: `true` for ITU CRC-32, `false` for ITU CRC-16.
+    /// - `inter_frame_flags`: minimum flag octets between frames (typically 1).
+    /// - `progressive`: `true` to allow progressive frame construction.
+    /// - `underflow_handler`: optional closure called when the transmitter needs more data.
+    pub fn new<F>(
+        crc32: bool,
+        inter_frame_flags: i32,
+        progressive: bool,
+        underflow_handler: Option<F>,
+    ) -> Result<Self>
+    where
+        F: FnMut() + 'static,
+    {
+        let mut tx = Self {
+            crc32,
+            crc_bytes: if crc32 { 4 } else { 2 },
+            crc: 0,
+            fcs_mode: FcsMode::Crc,
+            inter_frame_flags: inter_frame_flags.max(1),
+            progressive,
+            max_frame_len: HDLC_MAXFRAME_LEN,
+            octets_in_progress: 0,
+            num_bits: 0,
+            idle_octet: 0x7E,
+            flag_octets: 0,
+            abort_octets: 0,
+            report_flag_underflow: false,
+            len: 0,
+            pos: 0,
+            buffer: [0; HDLC_MAXFRAME_LEN + 4],
+            byte: 0,
+            bits: 0,
+            tx_end: false,
+            underflow_handler: underflow_handler.map(|h| Box::new(h) as HdlcTxCallback),
+        };
+        tx.reset_crc();
+        Ok(tx)
+    }
+
+    /// Queue a frame for transmission.
+    ///
+    /// An empty frame asks for transmission to end once everything queued
+    /// has gone.
+    pub fn frame(&mut self, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            self.tx_end = true;
+            return Ok(());
+        }
+        if self.len + data.len() > self.max_frame_len {
+            return Err(SpanDspError::ErrorCode(-1));
+        }
+        if self.progressive {
+            // Only lock out once the CRC is being sent.
+            if self.pos >= HDLC_MAXFRAME_LEN {
+                return Err(SpanDspError::ErrorCode(-1));
+            }
+        } else if self.len > 0 {
+            return Err(SpanDspError::ErrorCode(-1));
+        }
+        self.buffer[self.len..self.len + data.len()].copy_from_slice(data);
+        self.crc = if self.crc32 {
+            crc_itu32_calc(data, self.crc)
+        } else {
+            crc_itu16_calc(data, self.crc as u16) as u32
+        };
+        if self.progressive {
+            self.len += data.len();
+        } else {
+            self.len = data.len();
+        }
+        self.tx_end = false;
+        Ok(())
+    }
+
+    /// Queue flag octets (preamble).
+    ///
+    /// If `len` is 0, requests that transmission terminate when buffers drain.
+    pub fn flags(&mut self, len: i32) -> Result<()> {
+        if self.pos != 0 {
+            return Err(SpanDspError::ErrorCode(-1));
+        }
+        if len < 0 {
+            self.flag_octets += -len;
+        } else {
+            self.flag_octets = len;
+        }
+        self.report_flag_underflow = true;
+        self.tx_end = false;
+        Ok(())
+    }
+
+    /// Send an abort sequence.
+    ///
+    /// The C engine always reports this as an error; here it succeeds.
+    pub fn abort(&mut self) -> Result<()> {
+        self.flag_octets += 1;
+        self.abort_octets += 1;
+        Ok(())
+    }
+
+    /// Get the next block of bytes for transmission.
+    ///
+    /// Returns the number of bytes actually written to `buf`.
+    pub fn get(&mut self, buf: &mut [u8]) -> usize {
+        for (i, out) in buf.iter_mut().enumerate() {
+            let byte = self.get_byte();
+            if byte == SIG_STATUS_END_OF_DATA {
+                return i;
+            }
+            *out = byte as u8;
+        }
+        buf.len()
+    }
+
+    /// Get the next bit for transmission.
+    pub fn get_bit(&mut self) -> i32 {
+        if self.bits == 0 {
+            self.byte = self.get_byte();
+            if self.byte < 0 {
+                return self.byte;
+            }
+            self.bits = 8;
+        }
+        self.bits -= 1;
+        (self.byte >> self.bits) & 0x01
+    }
+
+    /// Restart the HDLC transmitter.
+    pub fn restart(&mut self) {
+        self.octets_in_progress = 0;
+        self.num_bits = 0;
+        self.idle_octet = 0x7E;
+        self.flag_octets = 0;
+        self.abort_octets = 0;
+        self.report_flag_underflow = false;
+        self.len = 0;
+        self.pos = 0;
+        self.reset_crc();
+        self.byte = 0;
+        self.bits = 0;
+        self.tx_end = false;
+    }
+
+    /// Choose whether an FCS is appended to each frame.
+    ///
+    /// In [`FcsMode::Raw`] frames are sent exactly as queued, so a frame
+    /// relayed with its FCS goes out with that FCS. Change it between
+    /// frames.
+    pub fn set_fcs_mode(&mut self, mode: FcsMode) -> Result<()> {
+        self.fcs_mode = mode;
+        Ok(())
+    }
+
+    /// How the frame check sequence is handled.
+    pub fn fcs_mode(&self) -> FcsMode {
+        self.fcs_mode
+    }
+
+    fn reset_crc(&mut self) {
+        self.crc = if self.crc32 { 0xFFFF_FFFF } else { 0xFFFF };
+    }
+
+    fn underflow(&mut self) {
+        if let Some(handler) = self.underflow_handler.as_mut() {
+            handler();
+        }
+    }
+
+    fn get_byte(&mut self) -> i32 {
+        if self.flag_octets > 0 {
+            // A timed flag section: preamble, inter-frame gap, etc.
+            self.flag_octets -= 1;
+            if self.flag_octets <= 0 && self.report_flag_underflow {
+                self.report_flag_underflow = false;
+                if self.len == 0 {
+                    self.underflow();
+                }
+            }
+            if self.abort_octets != 0 {
+                self.abort_octets = 0;
+                return 0x7F;
+            }
+            return self.idle_octet as i32;
+        }
+        if self.len == 0 {
+            // Untimed idling on flags.
+            if self.tx_end {
+                self.tx_end = false;
+                return SIG_STATUS_END_OF_DATA;
+            }
+            return self.idle_octet as i32;
+        }
+        if self.num_bits >= 8 {
+            self.num_bits -= 8;
+            return ((self.octets_in_progress >> self.num_bits) & 0xFF) as i32;
+        }
+        if self.pos >= self.len {
+            if self.pos == self.len && self.fcs_mode == FcsMode::Raw {
+                return self.end_frame();
+            } else if self.pos == self.len {
+                let crc = self.crc ^ 0xFFFF_FFFF;
+                let fcs = crc.to_le_bytes();
+                self.buffer[HDLC_MAXFRAME_LEN..HDLC_MAXFRAME_LEN + self.crc_bytes]
+                    .copy_from_slice(&fcs[..self.crc_bytes]);
+                self.pos = HDLC_MAXFRAME_LEN;
+            } else if self.pos == HDLC_MAXFRAME_LEN + self.crc_bytes {
+                return self.end_frame();
+            }
+        }
+        let mut byte_in_progress = self.buffer[self.pos];
+        self.pos += 1;
+        for _ in 0..8 {
+            self.octets_in_progress =
+                (self.octets_in_progress << 1) | (byte_in_progress & 0x01) as u32;
+            byte_in_progress >>= 1;
+            if self.octets_in_progress & 0x1F == 0x1F {
+                // Five ones in a row: stuff a zero.
+                self.octets_in_progress <<= 1;
+                self.num_bits += 1;
+            }
+        }
+        ((self.octets_in_progress >> self.num_bits) & 0xFF) as i32
+    }
+
+    /// Close the current frame with a flag, leaving the bit alignment of the
+    /// idle flags rotated to follow on from it.
+    fn end_frame(&mut self) -> i32 {
+        let num_bits = self.num_bits;
+        let txbyte = ((self.octets_in_progress << (8 - num_bits)) | (0x7E >> num_bits)) as u8;
+        self.idle_octet = ((0x7E7E >> num_bits) & 0xFF) as u8;
+        self.octets_in_progress = (self.idle_octet as u32) >> (8 - num_bits);
+        self.flag_octets = self.inter_frame_flags - 1;
+        self.len = 0;
+        self.pos = 0;
+        self.reset_crc();
+        self.report_flag_underflow = false;
+        self.underflow();
+        // Finish with at least one whole flag if nothing new was queued.
+        if self.len == 0 && self.flag_octets < 2 {
+            self.flag_octets = 2;
+        }
+        txbyte as i32
+    }
+}
+
+impl fmt::Debug for HdlcTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HdlcTx")
+            .field("crc32", &self.crc32)
+            .field("fcs_mode", &self.fcs_mode)
+            .field("has_underflow_handler", &self.underflow_handler.is_some())
+            .finish_non_exhaustive()
+    }
+}
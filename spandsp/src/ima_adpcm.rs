@@ -0,0 +1,215 @@
+//! Safe wrapper around spandsp's IMA/DVI ADPCM codec.
+//!
+//! Wraps `ima_adpcm_state_t` for both encoding and decoding, in the three
+//! bitstream variants spandsp implements: IMA ADPCM as found in WAV files,
+//! DVI4 as carried in RTP (RFC 3551), and the variable-length VDVI.
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::plc::{ConcealingDecoder, FrameDecoder};
+
+/// Bytes of predictor state heading each chunk: the last sample, the step
+/// index and a reserved byte.
+const CHUNK_HEADER_BYTES: usize = 4;
+
+/// Which IMA ADPCM bitstream to produce and accept.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImaAdpcmVariant {
+    /// Original IMA ADPCM, as in WAV files: low nibble first, little-endian
+    /// chunk headers.
+    Ima4,
+    /// DVI4, as in RTP: high nibble first, big-endian chunk headers.
+    #[default]
+    Dvi4,
+    /// Variable-length DVI4 (VDVI), 2 to 8 bits per sample.
+    Vdvi,
+}
+
+impl ImaAdpcmVariant {
+    fn as_raw(self) -> c_int {
+        match self {
+            ImaAdpcmVariant::Ima4 => spandsp_sys::IMA_ADPCM_IMA4 as c_int,
+            ImaAdpcmVariant::Dvi4 => spandsp_sys::IMA_ADPCM_DVI4 as c_int,
+            ImaAdpcmVariant::Vdvi => spandsp_sys::IMA_ADPCM_VDVI as c_int,
+        }
+    }
+
+    /// Most bits one sample codes to.
+    fn max_bits_per_sample(self) -> usize {
+        match self {
+            ImaAdpcmVariant::Ima4 | ImaAdpcmVariant::Dvi4 => 4,
+            ImaAdpcmVariant::Vdvi => 8,
+        }
+    }
+
+    /// Fewest bits one sample codes to.
+    fn min_bits_per_sample(self) -> usize {
+        match self {
+            ImaAdpcmVariant::Ima4 | ImaAdpcmVariant::Dvi4 => 4,
+            ImaAdpcmVariant::Vdvi => 2,
+        }
+    }
+}
+
+impl fmt::Display for ImaAdpcmVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImaAdpcmVariant::Ima4 => f.write_str("IMA"),
+            ImaAdpcmVariant::Dvi4 => f.write_str("DVI4"),
+            ImaAdpcmVariant::Vdvi => f.write_str("VDVI"),
+        }
+    }
+}
+
+/// RAII wrapper around `ima_adpcm_state_t`.
+///
+/// A single state handles both encoding and decoding, depending on which
+/// method is called. Created via `ImaAdpcmState::new()`. Freed on drop via
+/// `ima_adpcm_free`.
+pub struct ImaAdpcmState {
+    ptr: NonNull<spandsp_sys::ima_adpcm_state_t>,
+    variant: ImaAdpcmVariant,
+    chunk_size: usize,
+}
+
+impl ImaAdpcmState {
+    /// Create a new IMA ADPCM state.
+    ///
+    /// `chunk_size` is the number of samples in each chunk (WAV block)
+    /// coded after a header carrying the predictor state. 0 treats each
+    /// `encode` or `decode` call as one chunk, as DVI4 RTP packets are.
+    pub fn new(variant: ImaAdpcmVariant, chunk_size: usize) -> Result<Self> {
+        let raw_chunk = c_int::try_from(chunk_size).map_err(|_| {
+            SpanDspError::InvalidInput(format!("IMA ADPCM chunk of {chunk_size} samples"))
+        })?;
+        let ptr = unsafe {
+            spandsp_sys::ima_adpcm_init(std::ptr::null_mut(), variant.as_raw(), raw_chunk)
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            variant,
+            chunk_size,
+        })
+    }
+
+    /// Returns the variant this state was initialized with.
+    pub fn variant(&self) -> ImaAdpcmVariant {
+        self.variant
+    }
+
+    /// Returns the chunk size, in samples, this state was initialized with.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Largest number of bytes encoding `samples` samples can produce,
+    /// chunk headers included.
+    pub fn max_encoded_len(&self, samples: usize) -> usize {
+        let headers = match self.chunk_size {
+            0 => 1,
+            // A call may start and end part way through a chunk.
+            chunk => samples / chunk + 2,
+        };
+        headers * CHUNK_HEADER_BYTES + (samples * self.variant.max_bits_per_sample()).div_ceil(8)
+    }
+
+    /// Largest number of samples decoding `bytes` bytes can produce.
+    pub fn max_decoded_len(&self, bytes: usize) -> usize {
+        bytes * 8 / self.variant.min_bits_per_sample()
+    }
+
+    /// Most samples certain to encode into `bytes` bytes: the inverse of
+    /// [`max_encoded_len`](Self::max_encoded_len).
+    fn encodable_samples(&self, bytes: usize) -> usize {
+        let bits = self.variant.max_bits_per_sample();
+        match self.chunk_size {
+            0 => bytes.saturating_sub(CHUNK_HEADER_BYTES) * 8 / bits,
+            chunk => {
+                bytes.saturating_sub(2 * CHUNK_HEADER_BYTES) * 8 * chunk
+                    / (bits * chunk + 8 * CHUNK_HEADER_BYTES)
+            }
+        }
+    }
+
+    /// Encode linear PCM audio to IMA ADPCM.
+    ///
+    /// Size `ima_data` with [`max_encoded_len`](Self::max_encoded_len); a
+    /// shorter buffer only takes the samples certain to fit. Returns the
+    /// number of bytes produced.
+    pub fn encode(&mut self, ima_data: &mut [u8], amp: &[i16]) -> usize {
+        let room = self.encodable_samples(ima_data.len());
+        let len = amp.len().min(room).min(c_int::MAX as usize) as c_int;
+        if len == 0 {
+            // A chunk header would still be written.
+            return 0;
+        }
+        unsafe {
+            spandsp_sys::ima_adpcm_encode(
+                self.ptr.as_ptr(),
+                ima_data.as_mut_ptr(),
+                amp.as_ptr(),
+                len,
+            )
+            .max(0) as usize
+        }
+    }
+
+    /// Decode IMA ADPCM data to linear PCM.
+    ///
+    /// Size `amp` with [`max_decoded_len`](Self::max_decoded_len); a
+    /// shorter buffer only takes the bytes certain to fit. Returns the
+    /// number of samples produced.
+    pub fn decode(&mut self, amp: &mut [i16], ima_data: &[u8]) -> usize {
+        let room = amp.len() * self.variant.min_bits_per_sample() / 8;
+        let len = ima_data.len().min(room).min(c_int::MAX as usize) as c_int;
+        unsafe {
+            spandsp_sys::ima_adpcm_decode(
+                self.ptr.as_ptr(),
+                amp.as_mut_ptr(),
+                ima_data.as_ptr(),
+                len,
+            )
+            .max(0) as usize
+        }
+    }
+
+    /// Put packet loss concealment in front of this decoder, for packets
+    /// of `frame_samples` samples (e.g. 160 for 20 ms of DVI4).
+    pub fn with_plc(self, frame_samples: usize) -> Result<ConcealingDecoder<Self>> {
+        ConcealingDecoder::new(self, frame_samples)
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::ima_adpcm_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl FrameDecoder for ImaAdpcmState {
+    fn decode_into(&mut self, amp: &mut [i16], frame: &[u8]) -> usize {
+        self.decode(amp, frame)
+    }
+}
+
+impl fmt::Debug for ImaAdpcmState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImaAdpcmState")
+            .field("variant", &self.variant)
+            .field("chunk_size", &self.chunk_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ImaAdpcmState {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::ima_adpcm_free(self.ptr.as_ptr());
+        }
+    }
+}
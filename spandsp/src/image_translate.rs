@@ -0,0 +1,179 @@
+//! Image format conversion for fax pages.
+//!
+//! [`ImageTranslate`] wraps `image_translate_state_t`. It pulls grey-scale
+//! or colour rows from a callback and hands out rows in another
+//! [`T4ImageType`], rescaling to a new width on the way. Converting to
+//! bi-level dithers, so photographs survive the trip to a T.4/T.6 page.
+//!
+//! The translator has the same row-read shape as the encoders, so it can
+//! sit straight in front of one:
+//!
+//! ```no_run
+//! use spandsp::image_translate::ImageTranslate;
+//! use spandsp::t4::{T4Compression, T4ImageType};
+//! use spandsp::t4_tx::T4T6Encoder;
+//!
+//! let photo = vec![0x80u8; 640 * 480];
+//! let mut offset = 0;
+//! let mut translate = ImageTranslate::new(
+//!     T4ImageType::Gray8,
+//!     640,
+//!     480,
+//!     T4ImageType::Bilevel,
+//!     Some(1728),
+//!     move |buf: &mut [u8]| match photo.get(offset..offset + 640) {
+//!         Some(row) => {
+//!             offset += row.len();
+//!             buf[..row.len()].copy_from_slice(row);
+//!             row.len()
+//!         }
+//!         None => 0,
+//!     },
+//! )
+//! .unwrap();
+//! let (width, length) = (translate.output_width(), translate.output_length());
+//! let mut encoder = T4T6Encoder::new(T4Compression::T6, width, length, move |buf| {
+//!     translate.row(buf)
+//! })
+//! .unwrap();
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::t4::T4ImageType;
+use crate::t4_tx::{RowReadCallback, row_read_trampoline};
+
+/// RAII wrapper around `image_translate_state_t`.
+///
+/// Created via [`ImageTranslate::new()`]. Freed on drop via
+/// `image_translate_free`.
+pub struct ImageTranslate {
+    ptr: NonNull<spandsp_sys::image_translate_state_t>,
+    input_type: T4ImageType,
+    output_type: T4ImageType,
+    _callback: Box<RowReadCallback>,
+}
+
+impl ImageTranslate {
+    /// Create a new translator.
+    ///
+    /// - `input_type`, `input_width`, `input_length`: the rows `handler`
+    ///   supplies.
+    /// - `output_type`: the rows [`row`](Self::row) hands out.
+    /// - `output_width`: the width to scale to, or `None` to keep the input
+    ///   width. The output length follows, keeping the aspect ratio.
+    /// - `handler`: closure called to read each input row. Receives a
+    ///   mutable buffer `&mut [u8]` to fill with row data. Return the number
+    ///   of bytes filled, or `0` to signal end of image.
+    pub fn new<F>(
+        input_type: T4ImageType,
+        input_width: u32,
+        input_length: u32,
+        output_type: T4ImageType,
+        output_width: Option<u32>,
+        handler: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(&mut [u8]) -> usize + 'static,
+    {
+        let boxed: Box<RowReadCallback> = Box::new(Box::new(handler));
+        let user_data = &*boxed as *const RowReadCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::image_translate_init(
+                std::ptr::null_mut(),
+                output_type as c_int,
+                output_width.map_or(-1, |w| w as c_int),
+                -1,
+                input_type as c_int,
+                input_width as c_int,
+                input_length as c_int,
+                Some(row_read_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            input_type,
+            output_type,
+            _callback: boxed,
+        })
+    }
+
+    /// Get the next output row.
+    ///
+    /// `buf` should hold at least [`output_row_bytes`](Self::output_row_bytes).
+    /// Returns the number of bytes written, or `0` once the image has ended.
+    pub fn row(&mut self, buf: &mut [u8]) -> usize {
+        let rc = unsafe {
+            spandsp_sys::image_translate_row(self.ptr.as_ptr(), buf.as_mut_ptr(), buf.len())
+        };
+        rc.max(0) as usize
+    }
+
+    /// Restart for a new input image of the same type and width.
+    pub fn restart(&mut self, input_length: u32) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::image_translate_restart(self.ptr.as_ptr(), input_length as c_int)
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// The type of the rows read from the callback.
+    pub fn input_type(&self) -> T4ImageType {
+        self.input_type
+    }
+
+    /// The type of the rows handed out.
+    pub fn output_type(&self) -> T4ImageType {
+        self.output_type
+    }
+
+    /// Get the output width in pixels.
+    pub fn output_width(&self) -> i32 {
+        unsafe { spandsp_sys::image_translate_get_output_width(self.ptr.as_ptr()) }
+    }
+
+    /// Get the output length in pixels.
+    pub fn output_length(&self) -> i32 {
+        unsafe { spandsp_sys::image_translate_get_output_length(self.ptr.as_ptr()) }
+    }
+
+    /// Bytes in each output row.
+    pub fn output_row_bytes(&self) -> usize {
+        self.output_type
+            .row_bytes(self.output_width().max(0) as u32)
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::image_translate_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for ImageTranslate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageTranslate")
+            .field("input_type", &self.input_type)
+            .field("output_type", &self.output_type)
+            .field("output_width", &self.output_width())
+            .field("output_length", &self.output_length())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ImageTranslate {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::image_translate_free(self.ptr.as_ptr());
+        }
+    }
+}
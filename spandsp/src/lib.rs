@@ -1,33 +1,121 @@
 //! Safe, idiomatic Rust wrappers for the [spandsp](https://github.com/freeswitch/spandsp)
 //! telephony DSP library.
 //!
-//! Provides RAII-managed types for codecs (G.711, G.722, G.726), DTMF
-//! generation/detection, HDLC framing, tone generation, Goertzel detection,
-//! echo cancellation, power metering, and (with the `fax` feature) full
-//! T.30/T.38/T.4 fax support.
+//! Provides RAII-managed types for codecs (G.711, G.722, G.726, GSM 06.10,
+//! IMA ADPCM), DTMF generation/detection, HDLC framing, tone generation,
+//! Goertzel detection, echo cancellation, power metering, and (with the
+//! `fax` feature) full T.30/T.38/T.4 fax support. The `metrics` feature
+//! publishes session and detector statistics through the `metrics` facade;
+//! see [`metrics`].
+//!
+//! # Mutability
+//!
+//! Methods that change the underlying C state take `&mut self`; read-only
+//! queries take `&self`. The sub-engine accessors such as
+//! `FaxState::get_t30_state()` and `T38Terminal::get_t38_core_state()` take
+//! `&mut self` too: the handles they return drive the parent's engine, so
+//! they carry a lifetime that keeps the parent mutably borrowed until the
+//! handle is dropped. A handle cannot outlive its parent or be used
+//! alongside it.
+//!
+//! Releases up to 0.1.5 took `&self` on the fax/T.38 drive and setter
+//! methods and on the sub-engine accessors. Existing callers need to bind
+//! the objects (and any T.30 or T.38 core handles) with `let mut`, and drop
+//! a handle before using its parent again. Code that stored a handle next
+//! to its parent should store the parent and call the accessor when needed.
 
 pub use spandsp_sys;
 
 pub mod error;
+mod gain_ramp;
 pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 
+pub mod adsi;
+pub mod analyze;
+pub mod async_serial;
+pub mod at_interpreter;
+pub mod audio_file;
+pub mod audio_ring;
+pub mod bell_mf;
+pub mod bit_source;
+pub mod channel;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod dds;
+pub mod double_talk;
 pub mod dtmf;
+#[cfg(feature = "pure-dtmf")]
+pub mod dtmf_pure;
+pub mod dtmf_relay;
+pub mod dtmf_squelch;
+pub mod dtx;
 pub mod echo;
+pub mod fsk;
+pub mod g168;
 pub mod g711;
 pub mod g722;
 pub mod g726;
+#[cfg(feature = "pure-g726")]
+pub mod g726_pure;
+pub mod gsm0610;
 pub mod hdlc;
+#[cfg(feature = "pure-hdlc")]
+pub mod hdlc_pure;
+pub mod ima_adpcm;
+pub mod media_clock;
+pub mod mf_r1;
+pub mod modem_connect_tones;
+pub mod modem_echo;
+pub mod noise;
+pub mod playout;
+pub mod plc;
 pub mod power_meter;
+pub mod quality;
+pub mod r2_mf;
+pub mod r2_mfc;
+pub mod self_test;
+pub mod sprt;
+pub mod super_tone;
+#[cfg(feature = "talk-off")]
+pub mod talk_off;
+pub mod time_scale;
 pub mod tone_detect;
 pub mod tone_generate;
+pub mod tone_mixer;
+pub mod tone_plan;
+pub mod transcode;
+pub mod type2_cid;
+pub mod v150_1_sse;
+pub mod v18;
+pub mod version;
 
 #[cfg(feature = "fax")]
 pub mod fax;
 #[cfg(feature = "fax")]
 pub mod fax_modems;
 #[cfg(feature = "fax")]
+pub mod fax_queue;
+#[cfg(feature = "fax")]
+mod fax_tiff;
+#[cfg(feature = "fax")]
+pub mod fax_tones;
+#[cfg(feature = "fax")]
+pub mod image_translate;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "fax")]
+pub mod sync_session;
+#[cfg(feature = "fax")]
 pub mod t30;
 #[cfg(feature = "fax")]
+pub mod t31;
+#[cfg(feature = "fax")]
+pub mod t35;
+#[cfg(feature = "fax")]
+pub mod t37;
+#[cfg(feature = "fax")]
 pub mod t38_core;
 #[cfg(feature = "fax")]
 pub mod t38_gateway;
@@ -36,6 +124,18 @@ pub mod t38_terminal;
 #[cfg(feature = "fax")]
 pub mod t4;
 #[cfg(feature = "fax")]
+pub mod t42;
+#[cfg(feature = "fax")]
+pub mod t43;
+#[cfg(feature = "fax")]
 pub mod t4_rx;
 #[cfg(feature = "fax")]
 pub mod t4_tx;
+#[cfg(feature = "fax")]
+pub mod t85;
+#[cfg(feature = "fax")]
+pub mod test_chart;
+#[cfg(feature = "fax")]
+pub mod tiff_fx;
+#[cfg(feature = "fax")]
+pub mod tz;
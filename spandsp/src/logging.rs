@@ -259,6 +259,14 @@ impl LoggingState {
     }
 }
 
+impl fmt::Debug for LoggingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoggingState")
+            .field("has_handler", &self._handler.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for LoggingState {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,309 @@
+//! A frame clock that drives tick-based components together.
+//!
+//! Most spandsp engines are advanced by handing them a block of samples:
+//! generators fill a buffer, the FAX engine consumes and produces audio, and
+//! the T.38 terminal counts elapsed samples. [`MediaClock`] owns a set of such
+//! components, advances them all by one frame per [`tick`](MediaClock::tick)
+//! and mixes their output, and runs one-shot timers at frame boundaries.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use spandsp::dtmf::DtmfTx;
+//! use spandsp::media_clock::MediaClock;
+//!
+//! let mut clock = MediaClock::new(Duration::from_millis(20)).unwrap();
+//! let dtmf = clock.add(DtmfTx::new().unwrap());
+//! clock.schedule(Duration::from_millis(500), move |clock| {
+//!     if let Some(tx) = clock.component_mut::<DtmfTx>(dtmf) {
+//!         let _ = tx.put("123");
+//!     }
+//! });
+//! let silence = [0i16; 160];
+//! for _ in 0..100 {
+//!     let out = clock.tick(&silence);
+//!     // write `out` to the audio device
+//! #   let _ = out;
+//! }
+//! ```
+
+use std::any::Any;
+use std::fmt;
+use std::time::Duration;
+
+use crate::dtmf::DtmfTx;
+use crate::error::{Result, SpanDspError};
+use crate::super_tone::SuperToneTx;
+use crate::tone_generate::ToneGenerator;
+
+/// Sample rate the clock runs at.
+pub const SAMPLE_RATE: u32 = 8000;
+
+/// A component advanced by [`MediaClock`] once per frame.
+pub trait Clocked: Any {
+    /// Advance by one frame. `input` holds a private copy of the frame's
+    /// received audio and `output` is zeroed; audio written to `output` is
+    /// mixed into the clock's output.
+    fn advance(&mut self, input: &mut [i16], output: &mut [i16]);
+}
+
+impl Clocked for DtmfTx {
+    fn advance(&mut self, _input: &mut [i16], output: &mut [i16]) {
+        self.generate(output);
+    }
+}
+
+impl Clocked for ToneGenerator {
+    fn advance(&mut self, _input: &mut [i16], output: &mut [i16]) {
+        self.generate(output);
+    }
+}
+
+impl Clocked for SuperToneTx {
+    fn advance(&mut self, _input: &mut [i16], output: &mut [i16]) {
+        self.generate(output);
+    }
+}
+
+#[cfg(feature = "fax")]
+impl Clocked for crate::fax::FaxState {
+    fn advance(&mut self, input: &mut [i16], output: &mut [i16]) {
+        self.rx(input);
+        self.tx(output);
+    }
+}
+
+#[cfg(feature = "fax")]
+impl Clocked for crate::t38_gateway::T38Gateway {
+    fn advance(&mut self, input: &mut [i16], output: &mut [i16]) {
+        self.rx(input);
+        self.tx(output);
+    }
+}
+
+#[cfg(feature = "fax")]
+impl Clocked for crate::t38_terminal::T38Terminal {
+    fn advance(&mut self, input: &mut [i16], _output: &mut [i16]) {
+        self.send_timeout(input.len() as i32);
+    }
+}
+
+/// Adapts a closure into a [`Clocked`] component.
+pub struct ClockFn<F>(pub F);
+
+impl<F> Clocked for ClockFn<F>
+where
+    F: FnMut(&mut [i16], &mut [i16]) + 'static,
+{
+    fn advance(&mut self, input: &mut [i16], output: &mut [i16]) {
+        (self.0)(input, output)
+    }
+}
+
+impl<F> fmt::Debug for ClockFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClockFn").finish_non_exhaustive()
+    }
+}
+
+/// Lets a boxed component be downcast back to its concrete type.
+trait Component: Clocked {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clocked> Component for T {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// Handle to a component registered with a [`MediaClock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ComponentId(u64);
+
+/// Handle to a timer scheduled on a [`MediaClock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+type TimerCallback = Box<dyn FnOnce(&mut MediaClock)>;
+
+struct Timer {
+    id: TimerId,
+    due: u64,
+    callback: TimerCallback,
+}
+
+/// Drives a set of [`Clocked`] components one frame at a time.
+pub struct MediaClock {
+    frame_samples: usize,
+    samples_elapsed: u64,
+    next_id: u64,
+    components: Vec<(ComponentId, Box<dyn Component>)>,
+    timers: Vec<Timer>,
+    input: Vec<i16>,
+    scratch_in: Vec<i16>,
+    scratch_out: Vec<i16>,
+    mixed: Vec<i16>,
+}
+
+impl MediaClock {
+    /// Create a clock with the given frame duration, typically 10 or 20 ms.
+    ///
+    /// The duration must be non-zero and a whole number of samples.
+    pub fn new(frame: Duration) -> Result<Self> {
+        let nanos = frame.as_nanos();
+        let per_sample = 1_000_000_000 / SAMPLE_RATE as u128;
+        if nanos == 0 || nanos % per_sample != 0 {
+            return Err(SpanDspError::InvalidInput(format!(
+                "frame duration {frame:?} is not a whole number of samples"
+            )));
+        }
+        Ok(Self::with_frame_samples((nanos / per_sample) as usize))
+    }
+
+    fn with_frame_samples(frame_samples: usize) -> Self {
+        Self {
+            frame_samples,
+            samples_elapsed: 0,
+            next_id: 0,
+            components: Vec::new(),
+            timers: Vec::new(),
+            input: vec![0; frame_samples],
+            scratch_in: vec![0; frame_samples],
+            scratch_out: vec![0; frame_samples],
+            mixed: vec![0; frame_samples],
+        }
+    }
+
+    /// Samples per frame.
+    pub fn frame_samples(&self) -> usize {
+        self.frame_samples
+    }
+
+    /// Duration of one frame.
+    pub fn frame_duration(&self) -> Duration {
+        samples_to_duration(self.frame_samples as u64)
+    }
+
+    /// Time advanced so far.
+    pub fn elapsed(&self) -> Duration {
+        samples_to_duration(self.samples_elapsed)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Register a component; it is advanced from the next tick on, in
+    /// registration order.
+    pub fn add<C: Clocked>(&mut self, component: C) -> ComponentId {
+        let id = ComponentId(self.next_id());
+        self.components.push((id, Box::new(component)));
+        id
+    }
+
+    /// Unregister a component, returning it if it was of type `C`.
+    ///
+    /// A component of another type is dropped.
+    pub fn remove<C: Clocked>(&mut self, id: ComponentId) -> Option<C> {
+        let index = self.components.iter().position(|(cid, _)| *cid == id)?;
+        let (_, component) = self.components.remove(index);
+        component.into_any().downcast::<C>().ok().map(|c| *c)
+    }
+
+    /// Borrow a registered component as its concrete type.
+    pub fn component_mut<C: Clocked>(&mut self, id: ComponentId) -> Option<&mut C> {
+        self.components
+            .iter_mut()
+            .find(|(cid, _)| *cid == id)
+            .and_then(|(_, c)| c.as_any_mut().downcast_mut::<C>())
+    }
+
+    /// Number of registered components.
+    pub fn component_count(&self) -> usize {
+        self.components.len()
+    }
+
+    /// Run `callback` at the start of the first tick at least `delay` from
+    /// now.
+    pub fn schedule<F>(&mut self, delay: Duration, callback: F) -> TimerId
+    where
+        F: FnOnce(&mut MediaClock) + 'static,
+    {
+        let id = TimerId(self.next_id());
+        let delay_samples = delay.as_nanos() * SAMPLE_RATE as u128 / 1_000_000_000;
+        self.timers.push(Timer {
+            id,
+            due: self.samples_elapsed + delay_samples as u64,
+            callback: Box::new(callback),
+        });
+        id
+    }
+
+    /// Cancel a pending timer. Returns `false` if it has already fired.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        let before = self.timers.len();
+        self.timers.retain(|t| t.id != id);
+        self.timers.len() != before
+    }
+
+    /// Advance every component by one frame and return the mixed output.
+    ///
+    /// `input` is the received audio for the frame; it is zero-padded or
+    /// truncated to [`frame_samples`](Self::frame_samples).
+    pub fn tick(&mut self, input: &[i16]) -> &[i16] {
+        self.run_due_timers();
+
+        let n = input.len().min(self.frame_samples);
+        self.input[..n].copy_from_slice(&input[..n]);
+        self.input[n..].fill(0);
+        self.mixed.fill(0);
+        for (_, component) in &mut self.components {
+            self.scratch_in.copy_from_slice(&self.input);
+            self.scratch_out.fill(0);
+            component.advance(&mut self.scratch_in, &mut self.scratch_out);
+            for (mix, &sample) in self.mixed.iter_mut().zip(&self.scratch_out) {
+                *mix = mix.saturating_add(sample);
+            }
+        }
+        self.samples_elapsed += self.frame_samples as u64;
+        &self.mixed
+    }
+
+    fn run_due_timers(&mut self) {
+        let now = self.samples_elapsed;
+        let mut due = Vec::new();
+        let mut i = 0;
+        while i < self.timers.len() {
+            if self.timers[i].due <= now {
+                due.push(self.timers.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        due.sort_by_key(|t| t.due);
+        for timer in due {
+            (timer.callback)(self);
+        }
+    }
+}
+
+fn samples_to_duration(samples: u64) -> Duration {
+    Duration::from_nanos(samples * (1_000_000_000 / SAMPLE_RATE as u64))
+}
+
+impl fmt::Debug for MediaClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MediaClock")
+            .field("frame_samples", &self.frame_samples)
+            .field("elapsed", &self.elapsed())
+            .field("components", &self.components.len())
+            .field("pending_timers", &self.timers.len())
+            .finish_non_exhaustive()
+    }
+}
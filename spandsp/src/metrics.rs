@@ -0,0 +1,105 @@
+//! Operational metrics emitted through the [`metrics`](https://docs.rs/metrics)
+//! facade.
+//!
+//! Enabled by the `metrics` feature. The wrappers record counters and gauges
+//! as they run; install any `metrics` recorder (for example
+//! `metrics-exporter-prometheus`) to collect them. Without a recorder the
+//! calls are no-ops.
+//!
+//! | Name | Kind | Emitted by |
+//! |------|------|------------|
+//! | `spandsp_dtmf_digits_detected_total` | counter | `DtmfRx` |
+//! | `spandsp_echo_erle_db` | gauge | `EchoCanceller` |
+//! | `spandsp_t38_packets_sent_total` | counter | `T38Core`, `T38Terminal`, `T38Gateway` |
+//! | `spandsp_t38_packets_received_total` | counter | `T38Core` |
+//! | `spandsp_fax_pages_sent_total` | counter | `FaxState`, `T38Terminal` |
+//! | `spandsp_fax_pages_received_total` | counter | `FaxState`, `T38Terminal` |
+//! | `spandsp_fax_failures_total` | counter, labelled by `error` | `FaxState`, `T38Terminal` |
+//! | `spandsp_fax_ecm_partial_page_requests_total` | counter | `FaxState`, `T38Terminal` |
+//! | `spandsp_fax_ecm_frames_resent_total` | counter | `FaxState`, `T38Terminal` |
+
+/// Counter of DTMF digits reported by `DtmfRx`.
+pub const DTMF_DIGITS_DETECTED: &str = "spandsp_dtmf_digits_detected_total";
+
+/// Gauge of the echo return loss enhancement, in dB, over the last second of
+/// audio passed through `EchoCanceller::update`.
+pub const ECHO_ERLE_DB: &str = "spandsp_echo_erle_db";
+
+/// Counter of IFP packets passed to the transmit handler of a `T38Core`,
+/// `T38Terminal` or `T38Gateway`, whether the caller or the engine sent them.
+#[cfg(feature = "fax")]
+pub const T38_PACKETS_SENT: &str = "spandsp_t38_packets_sent_total";
+
+/// Counter of IFP packets fed to `T38Core::rx_ifp_packet` or
+/// `T38Core::rx_ifp_stream`.
+#[cfg(feature = "fax")]
+pub const T38_PACKETS_RECEIVED: &str = "spandsp_t38_packets_received_total";
+
+/// Counter of pages sent, as reported at the end of a T.30 session.
+#[cfg(feature = "fax")]
+pub const FAX_PAGES_SENT: &str = "spandsp_fax_pages_sent_total";
+
+/// Counter of pages received, as reported at the end of a T.30 session.
+#[cfg(feature = "fax")]
+pub const FAX_PAGES_RECEIVED: &str = "spandsp_fax_pages_received_total";
+
+/// Counter of failed T.30 sessions, labelled with the `T30Error` description.
+#[cfg(feature = "fax")]
+pub const FAX_FAILURES: &str = "spandsp_fax_failures_total";
+
+/// Counter of ECM partial page requests (PPR), sent or received.
+#[cfg(feature = "fax")]
+pub const FAX_ECM_PARTIAL_PAGE_REQUESTS: &str = "spandsp_fax_ecm_partial_page_requests_total";
+
+/// Counter of ECM frames asked for again in partial page requests.
+#[cfg(feature = "fax")]
+pub const FAX_ECM_FRAMES_RESENT: &str = "spandsp_fax_ecm_frames_resent_total";
+
+pub(crate) fn dtmf_digits_detected(count: usize) {
+    if count > 0 {
+        ::metrics::counter!(DTMF_DIGITS_DETECTED).increment(count as u64);
+    }
+}
+
+#[cfg(feature = "fax")]
+pub(crate) fn t38_packet_sent() {
+    ::metrics::counter!(T38_PACKETS_SENT).increment(1);
+}
+
+#[cfg(feature = "fax")]
+pub(crate) fn t38_packet_received() {
+    ::metrics::counter!(T38_PACKETS_RECEIVED).increment(1);
+}
+
+/// Number of samples (one second at 8 kHz) over which ERLE is averaged.
+const ERLE_WINDOW: u32 = 8000;
+
+/// Running energy totals used to derive the ERLE gauge.
+#[derive(Debug, Default)]
+pub(crate) struct ErleWindow {
+    rx_energy: f64,
+    residual_energy: f64,
+    samples: u32,
+}
+
+impl ErleWindow {
+    /// Account for one sample pair, publishing the gauge once per window.
+    pub(crate) fn push(&mut self, rx: i16, clean: i16) {
+        self.rx_energy += f64::from(rx) * f64::from(rx);
+        self.residual_energy += f64::from(clean) * f64::from(clean);
+        self.samples += 1;
+        if self.samples < ERLE_WINDOW {
+            return;
+        }
+        // Skip silent windows rather than reporting a meaningless ratio.
+        if self.rx_energy > 0.0 {
+            let erle = 10.0 * (self.rx_energy / self.residual_energy.max(1.0)).log10();
+            ::metrics::gauge!(ECHO_ERLE_DB).set(erle);
+        }
+        *self = Self::default();
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,265 @@
+//! MF R1 address framing.
+//!
+//! R1 register signalling sends each address as one burst of Bell MF
+//! digits: KP, the digits, then one of the four ST codes, whose choice
+//! tells the far end what kind of address it is (e.g. which operator
+//! service a Feature Group D call wants). [`R1Address`] frames and parses
+//! those bursts, [`R1Decoder`] pulls complete addresses out of a stream of
+//! received digits, and [`R1Sender`]/[`R1Receiver`] do both over audio on
+//! the [`BellMfTx`]/[`BellMfRx`] wrappers.
+//!
+//! ```no_run
+//! use spandsp::mf_r1::{R1Address, R1Sender, StCode};
+//!
+//! let mut sender = R1Sender::new().unwrap();
+//! sender.send(&R1Address::new("5551234", StCode::St).unwrap()).unwrap();
+//! let mut amp = [0i16; 160];
+//! while sender.tx(&mut amp) > 0 {
+//!     // send `amp` on the trunk
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::bell_mf::{BellMfRx, BellMfTx};
+use crate::error::{Result, SpanDspError};
+
+/// Bell MF codes for KP and the ST family, from spandsp's `bell_r2_mf.h`.
+const KP: char = '*';
+const ST: char = '#';
+const STP: char = 'A';
+const ST2P: char = 'B';
+const ST3P: char = 'C';
+
+/// The ST signal that ends an R1 address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StCode {
+    /// ST: an ordinary address.
+    St,
+    /// ST' (STP).
+    Stp,
+    /// ST'' (ST2P).
+    St2p,
+    /// ST''' (ST3P).
+    St3p,
+}
+
+impl StCode {
+    /// The Bell MF digit that carries this code.
+    pub fn code(self) -> char {
+        match self {
+            Self::St => ST,
+            Self::Stp => STP,
+            Self::St2p => ST2P,
+            Self::St3p => ST3P,
+        }
+    }
+
+    /// The ST code a Bell MF digit carries, if any.
+    pub fn from_code(code: char) -> Option<Self> {
+        match code {
+            ST => Some(Self::St),
+            STP => Some(Self::Stp),
+            ST2P => Some(Self::St2p),
+            ST3P => Some(Self::St3p),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::St => "ST",
+            Self::Stp => "ST'",
+            Self::St2p => "ST''",
+            Self::St3p => "ST'''",
+        })
+    }
+}
+
+/// An address as sent between KP and ST.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct R1Address {
+    digits: String,
+    st: StCode,
+}
+
+impl R1Address {
+    /// An address of `digits`, 0-9 only, ended with `st`.
+    pub fn new(digits: &str, st: StCode) -> Result<Self> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "R1 address must be one or more digits 0-9, got {digits:?}"
+            )));
+        }
+        Ok(Self {
+            digits: digits.to_owned(),
+            st,
+        })
+    }
+
+    /// Parse a whole burst as [`BellMfRx::get`] reports it, e.g.
+    /// `*5551234#`.
+    pub fn parse(burst: &str) -> Result<Self> {
+        let malformed = || SpanDspError::InvalidInput(format!("{burst:?} is not KP, digits, ST"));
+        let rest = burst.strip_prefix(KP).ok_or_else(malformed)?;
+        let mut chars = rest.chars();
+        let st = chars
+            .next_back()
+            .and_then(StCode::from_code)
+            .ok_or_else(malformed)?;
+        Self::new(chars.as_str(), st)
+    }
+
+    /// The digits.
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+
+    /// The ST code ending the address.
+    pub fn st(&self) -> StCode {
+        self.st
+    }
+
+    /// The burst to hand to [`BellMfTx::put`]: KP, the digits, ST.
+    pub fn framed(&self) -> String {
+        format!("{KP}{}{}", self.digits, self.st.code())
+    }
+}
+
+impl fmt::Display for R1Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KP {} {}", self.digits, self.st)
+    }
+}
+
+/// Pulls complete addresses out of received Bell MF digits.
+///
+/// Digits outside a KP...ST burst are discarded and counted, as is a burst
+/// cut short by another KP.
+#[derive(Debug, Clone, Default)]
+pub struct R1Decoder {
+    /// Digits since the last KP, or `None` outside a burst.
+    partial: Option<String>,
+    discarded: u64,
+}
+
+impl R1Decoder {
+    /// A decoder waiting for KP.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed received digits and return the addresses they complete.
+    pub fn feed(&mut self, received: &str) -> Vec<R1Address> {
+        let mut addresses = Vec::new();
+        for c in received.chars() {
+            if c == KP {
+                if let Some(cut) = self.partial.replace(String::new()) {
+                    self.discarded += cut.len() as u64 + 1;
+                }
+                continue;
+            }
+            let Some(partial) = &mut self.partial else {
+                self.discarded += 1;
+                continue;
+            };
+            if c.is_ascii_digit() {
+                partial.push(c);
+            } else if let Some(st) = StCode::from_code(c) {
+                let digits = std::mem::take(partial);
+                self.partial = None;
+                match R1Address::new(&digits, st) {
+                    Ok(address) => addresses.push(address),
+                    Err(_) => self.discarded += digits.len() as u64 + 2,
+                }
+            } else {
+                self.discarded += 1;
+            }
+        }
+        addresses
+    }
+
+    /// Whether a burst has started but not ended.
+    pub fn in_burst(&self) -> bool {
+        self.partial.is_some()
+    }
+
+    /// Received digits thrown away so far.
+    pub fn discarded(&self) -> u64 {
+        self.discarded
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Sender and receiver
+// ---------------------------------------------------------------------------
+
+/// Sends R1 addresses as Bell MF audio.
+#[derive(Debug)]
+pub struct R1Sender {
+    tx: BellMfTx,
+}
+
+impl R1Sender {
+    /// Create a sender with its own transmitter.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            tx: BellMfTx::new()?,
+        })
+    }
+
+    /// Queue an address, framed with KP and its ST code.
+    pub fn send(&mut self, address: &R1Address) -> Result<()> {
+        match self.tx.put(&address.framed())? {
+            0 => Ok(()),
+            left => Err(SpanDspError::InvalidInput(format!(
+                "transmit queue full: {left} digits of {address} not queued"
+            ))),
+        }
+    }
+
+    /// Generate audio for the queued addresses.
+    ///
+    /// Returns the number of samples generated; 0 once everything is sent.
+    pub fn tx(&mut self, amp: &mut [i16]) -> usize {
+        self.tx.generate(amp)
+    }
+
+    /// The transmitter.
+    pub fn transmitter_mut(&mut self) -> &mut BellMfTx {
+        &mut self.tx
+    }
+}
+
+/// Receives R1 addresses from Bell MF audio.
+#[derive(Debug)]
+pub struct R1Receiver {
+    rx: BellMfRx,
+    decoder: R1Decoder,
+}
+
+impl R1Receiver {
+    /// Create a receiver with its own detector.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            rx: BellMfRx::new()?,
+            decoder: R1Decoder::new(),
+        })
+    }
+
+    /// Feed audio and return the addresses completed in it.
+    pub fn rx(&mut self, amp: &[i16]) -> Vec<R1Address> {
+        self.rx.rx(amp);
+        let digits = self.rx.get();
+        self.decoder.feed(&digits)
+    }
+
+    /// The decoder, e.g. to check [`discarded`](R1Decoder::discarded).
+    pub fn decoder(&self) -> &R1Decoder {
+        &self.decoder
+    }
+}
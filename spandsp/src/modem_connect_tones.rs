@@ -0,0 +1,291 @@
+//! Safe wrappers around spandsp's modem connect tone generation and
+//! detection.
+//!
+//! - `ConnectToneTx` wraps `modem_connect_tones_tx_state_t`.
+//! - `ConnectToneRx` wraps `modem_connect_tones_rx_state_t`.
+//!
+//! These are the tones that say what is on the other end of a call: a fax
+//! machine's CNG and CED, a data modem's V.25 and V.8 answer tones, the
+//! Bell 2225 Hz answer tone and the V.8 calling tone. For the narrower job
+//! of spotting a fax call and switching it to T.38, see `fax_tones` (with
+//! the `fax` feature).
+
+extern crate spandsp_sys;
+
+use std::ffi::c_void;
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// A modem connect tone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectTone {
+    /// CNG: the calling fax machine's 1100 Hz, 0.5 s on, 3 s off.
+    Cng,
+    /// ANS: plain 2100 Hz, V.25. A fax machine's CED is the same tone.
+    Ans,
+    /// ANS/: 2100 Hz with phase reversals every 450 ms.
+    AnsPr,
+    /// ANSam: 2100 Hz amplitude modulated at 15 Hz, V.8.
+    AnsAm,
+    /// ANSam/: ANSam with phase reversals.
+    AnsAmPr,
+    /// V.21 HDLC flags ahead of the first T.30 frame. Detected only.
+    V21Preamble,
+    /// The Bell 103 2225 Hz answer tone.
+    BellAns,
+    /// The V.8 1300 Hz calling tone.
+    CallingTone,
+}
+
+impl ConnectTone {
+    fn as_raw(self) -> c_int {
+        match self {
+            ConnectTone::Cng => spandsp_sys::MODEM_CONNECT_TONES_FAX_CNG as c_int,
+            ConnectTone::Ans => spandsp_sys::MODEM_CONNECT_TONES_ANS as c_int,
+            ConnectTone::AnsPr => spandsp_sys::MODEM_CONNECT_TONES_ANS_PR as c_int,
+            ConnectTone::AnsAm => spandsp_sys::MODEM_CONNECT_TONES_ANSAM as c_int,
+            ConnectTone::AnsAmPr => spandsp_sys::MODEM_CONNECT_TONES_ANSAM_PR as c_int,
+            ConnectTone::V21Preamble => spandsp_sys::MODEM_CONNECT_TONES_FAX_PREAMBLE as c_int,
+            ConnectTone::BellAns => spandsp_sys::MODEM_CONNECT_TONES_BELL_ANS as c_int,
+            ConnectTone::CallingTone => spandsp_sys::MODEM_CONNECT_TONES_CALLING_TONE as c_int,
+        }
+    }
+
+    fn from_raw(code: c_int) -> Option<Self> {
+        [
+            ConnectTone::Cng,
+            ConnectTone::Ans,
+            ConnectTone::AnsPr,
+            ConnectTone::AnsAm,
+            ConnectTone::AnsAmPr,
+            ConnectTone::V21Preamble,
+            ConnectTone::BellAns,
+            ConnectTone::CallingTone,
+        ]
+        .into_iter()
+        .find(|tone| tone.as_raw() == code)
+    }
+}
+
+impl fmt::Display for ConnectTone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConnectTone::Cng => "CNG",
+            ConnectTone::Ans => "ANS",
+            ConnectTone::AnsPr => "ANS/",
+            ConnectTone::AnsAm => "ANSam",
+            ConnectTone::AnsAmPr => "ANSam/",
+            ConnectTone::V21Preamble => "V.21 preamble",
+            ConnectTone::BellAns => "Bell ANS",
+            ConnectTone::CallingTone => "calling tone",
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ConnectToneTx
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `modem_connect_tones_tx_state_t`.
+///
+/// Created via `ConnectToneTx::new()`. Freed on drop via
+/// `modem_connect_tones_tx_free`.
+pub struct ConnectToneTx {
+    ptr: NonNull<spandsp_sys::modem_connect_tones_tx_state_t>,
+    tone: ConnectTone,
+}
+
+impl ConnectToneTx {
+    /// Create a generator for `tone`. Every tone but
+    /// [`V21Preamble`](ConnectTone::V21Preamble) can be generated.
+    pub fn new(tone: ConnectTone) -> Result<Self> {
+        if tone == ConnectTone::V21Preamble {
+            return Err(SpanDspError::InvalidInput(
+                "the V.21 preamble comes from a V.21 modem, not a tone generator".into(),
+            ));
+        }
+        let ptr = unsafe {
+            spandsp_sys::modem_connect_tones_tx_init(std::ptr::null_mut(), tone.as_raw())
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr, tone })
+    }
+
+    /// Returns the tone being generated.
+    pub fn tone(&self) -> ConnectTone {
+        self.tone
+    }
+
+    /// Generate audio.
+    ///
+    /// Returns the number of samples generated; fewer than `amp.len()` once
+    /// a tone with a fixed length, such as ANSam, has finished.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe {
+            spandsp_sys::modem_connect_tones_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), len).max(0)
+                as usize
+        }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::modem_connect_tones_tx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for ConnectToneTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectToneTx")
+            .field("tone", &self.tone)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ConnectToneTx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::modem_connect_tones_tx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: ConnectToneTx wraps a SpanDSP modem_connect_tones_tx_state_t that
+// is only accessed through &self/&mut self methods.
+unsafe impl Send for ConnectToneTx {}
+
+// ---------------------------------------------------------------------------
+// ConnectToneRx
+// ---------------------------------------------------------------------------
+
+type ConnectToneCallback = Box<dyn FnMut(Option<ConnectTone>, i32) + Send>;
+
+/// Trampoline for `tone_report_func_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `ConnectToneCallback`.
+unsafe extern "C" fn connect_tone_callback_trampoline(
+    user_data: *mut c_void,
+    code: c_int,
+    level: c_int,
+    _delay: c_int,
+) {
+    unsafe {
+        if user_data.is_null() {
+            return;
+        }
+        let closure = &mut *(user_data as *mut ConnectToneCallback);
+        closure(ConnectTone::from_raw(code), level);
+    }
+}
+
+/// RAII wrapper around `modem_connect_tones_rx_state_t`.
+///
+/// Created via `ConnectToneRx::new()`, `ConnectToneRx::fax_answer()` or
+/// `ConnectToneRx::with_callback()`. Freed on drop via
+/// `modem_connect_tones_rx_free`.
+pub struct ConnectToneRx {
+    ptr: NonNull<spandsp_sys::modem_connect_tones_rx_state_t>,
+    tone_type: c_int,
+    _callback: Option<Box<ConnectToneCallback>>,
+}
+
+impl ConnectToneRx {
+    /// Create a detector listening for `tone`. Listening for any of the
+    /// 2100 Hz tones hears them all, and reports which one it is.
+    pub fn new(tone: ConnectTone) -> Result<Self> {
+        Self::init(tone.as_raw(), None)
+    }
+
+    /// Create a detector for whatever a fax machine answers with: CED, or
+    /// the V.21 preamble if it skips CED.
+    pub fn fax_answer() -> Result<Self> {
+        Self::init(
+            spandsp_sys::MODEM_CONNECT_TONES_FAX_CED_OR_PREAMBLE as c_int,
+            None,
+        )
+    }
+
+    /// Create a detector listening for `tone`, calling `callback` with
+    /// each tone detected and its level in dBm0, and with `None` when the
+    /// tone stops.
+    pub fn with_callback<F>(tone: ConnectTone, callback: F) -> Result<Self>
+    where
+        F: FnMut(Option<ConnectTone>, i32) + Send + 'static,
+    {
+        Self::init(tone.as_raw(), Some(Box::new(Box::new(callback))))
+    }
+
+    fn init(tone_type: c_int, callback: Option<Box<ConnectToneCallback>>) -> Result<Self> {
+        let (trampoline, user_data): (spandsp_sys::tone_report_func_t, _) = match &callback {
+            Some(boxed) => (
+                Some(connect_tone_callback_trampoline),
+                &**boxed as *const ConnectToneCallback as *mut c_void,
+            ),
+            None => (None, std::ptr::null_mut()),
+        };
+        let ptr = unsafe {
+            spandsp_sys::modem_connect_tones_rx_init(
+                std::ptr::null_mut(),
+                tone_type,
+                trampoline,
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            tone_type,
+            _callback: callback,
+        })
+    }
+
+    /// Feed audio to the detector.
+    ///
+    /// Returns the number of unprocessed samples (normally 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe {
+            spandsp_sys::modem_connect_tones_rx(self.ptr.as_ptr(), amp.as_ptr(), len).max(0)
+                as usize
+        }
+    }
+
+    /// The tone heard since the last call, if any.
+    ///
+    /// spandsp latches a detection until it is read, so this clears it.
+    pub fn take_detected(&mut self) -> Option<ConnectTone> {
+        ConnectTone::from_raw(unsafe { spandsp_sys::modem_connect_tones_rx_get(self.ptr.as_ptr()) })
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::modem_connect_tones_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for ConnectToneRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectToneRx")
+            .field("tone_type", &self.tone_type)
+            .field("has_callback", &self._callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ConnectToneRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::modem_connect_tones_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: ConnectToneRx wraps a SpanDSP modem_connect_tones_rx_state_t that
+// is only accessed through &self/&mut self methods, and its callback is Send.
+unsafe impl Send for ConnectToneRx {}
@@ -0,0 +1,106 @@
+//! Safe wrapper around spandsp's modem echo canceller.
+//!
+//! Wraps `modem_echo_can_state_t`, a plain adaptive line echo canceller for
+//! data and fax paths. Unlike [`EchoCanceller`](crate::echo::EchoCanceller)
+//! it has no non-linear processor, comfort noise or double-talk handling,
+//! which would distort a modem signal; adaption is instead switched on while
+//! the modem trains and off once it has converged.
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// RAII wrapper around `modem_echo_can_state_t`.
+///
+/// Like `echo_can_init`, `modem_echo_can_init` always allocates internally.
+/// Freed on drop via `modem_echo_can_free`.
+pub struct ModemEchoCanceller {
+    ptr: NonNull<spandsp_sys::modem_echo_can_state_t>,
+    len: usize,
+    adapting: bool,
+}
+
+impl ModemEchoCanceller {
+    /// Create a new modem echo canceller with adaption on.
+    ///
+    /// `len` is the tail length in samples, and must be a power of two.
+    pub fn new(len: usize) -> Result<Self> {
+        if !len.is_power_of_two() || len > c_int::MAX as usize {
+            return Err(SpanDspError::InvalidInput(format!(
+                "modem echo tail of {len} samples is not a power of two"
+            )));
+        }
+        let ptr = unsafe { spandsp_sys::modem_echo_can_init(len as c_int) };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        let mut canceller = Self {
+            ptr,
+            len,
+            adapting: false,
+        };
+        // spandsp starts with adaption off.
+        canceller.set_adaption(true);
+        Ok(canceller)
+    }
+
+    /// Returns the tail length in samples.
+    pub fn tail_len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the filter is adapting.
+    pub fn is_adapting(&self) -> bool {
+        self.adapting
+    }
+
+    /// Turn adaption of the filter coefficients on or off. Turn it off once
+    /// the modem has trained, so the filter holds through the data phase.
+    pub fn set_adaption(&mut self, adapt: bool) {
+        unsafe {
+            spandsp_sys::modem_echo_can_adaption_mode(self.ptr.as_ptr(), adapt as c_int);
+        }
+        self.adapting = adapt;
+    }
+
+    /// Process a single sample pair through the echo canceller.
+    ///
+    /// - `tx`: the transmitted (far-end) sample.
+    /// - `rx`: the received (near-end) sample, which may contain echo.
+    ///
+    /// Returns the cleaned (echo-cancelled) receive sample.
+    pub fn update(&mut self, tx: i16, rx: i16) -> i16 {
+        unsafe { spandsp_sys::modem_echo_can_update(self.ptr.as_ptr(), tx, rx) }
+    }
+
+    /// Flush (reinitialise) the echo canceller, resetting the adaptive filter.
+    pub fn flush(&mut self) {
+        unsafe {
+            spandsp_sys::modem_echo_can_flush(self.ptr.as_ptr());
+        }
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::modem_echo_can_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for ModemEchoCanceller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModemEchoCanceller")
+            .field("len", &self.len)
+            .field("adapting", &self.adapting)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ModemEchoCanceller {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::modem_echo_can_free(self.ptr.as_ptr());
+        }
+    }
+}
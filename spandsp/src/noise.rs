@@ -0,0 +1,146 @@
+//! Safe wrapper around spandsp's noise generators.
+//!
+//! `NoiseGenerator` wraps `noise_state_t` for white and Hoth noise, and
+//! `awgn_state_t` for true Gaussian noise, behind one type. Use it for
+//! comfort noise, or with the tone generator to build test signals.
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::DBM0_MAX_SINE_POWER;
+
+/// Uniform values `noise_state_t` sums per sample to approximate a
+/// Gaussian; spandsp's own tests use 7.
+const NOISE_QUALITY: c_int = 7;
+
+/// What kind of noise to generate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoiseClass {
+    /// Flat-spectrum noise, approximately Gaussian. Cheap, and right for
+    /// comfort noise.
+    #[default]
+    White,
+    /// Hoth noise, shaped like the background of a typical office, falling
+    /// off with frequency (ANSI/IEEE Std 269).
+    Hoth,
+    /// Additive white Gaussian noise with an exact Gaussian distribution,
+    /// for measurements where the amplitude distribution matters.
+    Awgn,
+}
+
+impl fmt::Display for NoiseClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoiseClass::White => f.write_str("white"),
+            NoiseClass::Hoth => f.write_str("Hoth"),
+            NoiseClass::Awgn => f.write_str("AWGN"),
+        }
+    }
+}
+
+/// The spandsp state behind a [`NoiseGenerator`].
+enum State {
+    Noise(NonNull<spandsp_sys::noise_state_t>),
+    Awgn(NonNull<spandsp_sys::awgn_state_t>),
+}
+
+/// RAII wrapper around spandsp's noise generator state.
+///
+/// Created via `NoiseGenerator::new()`. Freed on drop via `noise_free` or
+/// `awgn_free`.
+pub struct NoiseGenerator {
+    state: State,
+    class: NoiseClass,
+    level_dbm0: f32,
+}
+
+impl NoiseGenerator {
+    /// Create a generator of `class` noise at `level_dbm0`. The same `seed`
+    /// always gives the same noise.
+    pub fn new(class: NoiseClass, level_dbm0: f32, seed: i32) -> Result<Self> {
+        if !(level_dbm0.is_finite() && level_dbm0 <= DBM0_MAX_SINE_POWER) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "noise level must be at most {DBM0_MAX_SINE_POWER} dBm0, got {level_dbm0}"
+            )));
+        }
+        let state = match class {
+            NoiseClass::White | NoiseClass::Hoth => {
+                let raw_class = match class {
+                    NoiseClass::Hoth => spandsp_sys::NOISE_CLASS_HOTH as c_int,
+                    _ => spandsp_sys::NOISE_CLASS_AWGN as c_int,
+                };
+                let ptr = unsafe {
+                    spandsp_sys::noise_init_dbm0(
+                        std::ptr::null_mut(),
+                        seed,
+                        level_dbm0,
+                        raw_class,
+                        NOISE_QUALITY,
+                    )
+                };
+                State::Noise(NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?)
+            }
+            NoiseClass::Awgn => {
+                let ptr =
+                    unsafe { spandsp_sys::awgn_init_dbm0(std::ptr::null_mut(), seed, level_dbm0) };
+                State::Awgn(NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?)
+            }
+        };
+        Ok(Self {
+            state,
+            class,
+            level_dbm0,
+        })
+    }
+
+    /// Returns the class of noise generated.
+    pub fn class(&self) -> NoiseClass {
+        self.class
+    }
+
+    /// Returns the level, in dBm0.
+    pub fn level_dbm0(&self) -> f32 {
+        self.level_dbm0
+    }
+
+    /// Generate one sample.
+    pub fn next_sample(&mut self) -> i16 {
+        match self.state {
+            State::Noise(ptr) => unsafe { spandsp_sys::noise(ptr.as_ptr()) },
+            State::Awgn(ptr) => unsafe { spandsp_sys::awgn(ptr.as_ptr()) },
+        }
+    }
+
+    /// Fill `amp` with noise.
+    pub fn generate(&mut self, amp: &mut [i16]) {
+        for sample in amp {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+impl fmt::Debug for NoiseGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoiseGenerator")
+            .field("class", &self.class)
+            .field("level_dbm0", &self.level_dbm0)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for NoiseGenerator {
+    fn drop(&mut self) {
+        match self.state {
+            State::Noise(ptr) => unsafe {
+                spandsp_sys::noise_free(ptr.as_ptr());
+            },
+            State::Awgn(ptr) => unsafe {
+                spandsp_sys::awgn_free(ptr.as_ptr());
+            },
+        }
+    }
+}
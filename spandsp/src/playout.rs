@@ -0,0 +1,247 @@
+//! Adaptive playout of received media frames.
+//!
+//! [`PlayoutPipeline`] sits between the network and the audio device: frames
+//! go in as they arrive, stamped with their RTP-style sample timestamps, and
+//! one fixed-size frame of PCM comes out per clock tick. In between it holds
+//! a jitter buffer, decodes frames in timestamp order, and conceals frames
+//! that are missing or arrive too late to play through a
+//! [`ConcealingDecoder`].
+//!
+//! The buffer starts at [`PlayoutConfig::min_delay`] frames. Each underrun
+//! (no frame queued at all when one is due) adds a frame of delay, up to
+//! [`PlayoutConfig::max_delay`]; after a quiet spell it gives a frame back.
+//!
+//! ```no_run
+//! use spandsp::g711::{G711Mode, G711State};
+//! use spandsp::playout::{PlayoutConfig, PlayoutPipeline};
+//!
+//! let decoder = G711State::new(G711Mode::ALaw).unwrap().with_plc(160).unwrap();
+//! let mut playout = PlayoutPipeline::new(decoder, PlayoutConfig::default()).unwrap();
+//! playout.put(0, &[0xD5; 160]);
+//! playout.put(160, &[0xD5; 160]);
+//! let pcm = playout.tick(); // every 20 ms
+//! # let _ = pcm;
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::{Result, SpanDspError};
+use crate::plc::{ConcealingDecoder, FrameDecoder};
+
+/// Jitter buffer tuning, in frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayoutConfig {
+    /// Frames to buffer before playout starts, and the least the delay
+    /// adapts down to.
+    pub min_delay: usize,
+    /// Most frames the delay adapts up to. A frame stamped further ahead
+    /// than this flushes the buffer, as after a sender restart.
+    pub max_delay: usize,
+    /// Ticks without an underrun before the delay shrinks by one frame.
+    pub shrink_after: u32,
+}
+
+impl Default for PlayoutConfig {
+    /// 2 to 10 frames, shrinking after 250 clean ticks (5 s of 20 ms
+    /// frames).
+    fn default() -> Self {
+        Self {
+            min_delay: 2,
+            max_delay: 10,
+            shrink_after: 250,
+        }
+    }
+}
+
+/// Counters kept by a [`PlayoutPipeline`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlayoutStats {
+    /// Frames accepted into the buffer.
+    pub received: u64,
+    /// Frames decoded and played.
+    pub played: u64,
+    /// Frames concealed, because they were lost or not there in time.
+    pub concealed: u64,
+    /// Frames that arrived after their playout time.
+    pub late: u64,
+    /// Frames whose timestamp was already queued.
+    pub duplicates: u64,
+    /// Frames thrown away to bring the delay down.
+    pub dropped: u64,
+    /// Times the buffer was flushed by a timestamp jump.
+    pub resyncs: u64,
+}
+
+/// A jitter buffer, decoder and concealment producing one PCM frame per
+/// tick.
+pub struct PlayoutPipeline<D> {
+    decoder: ConcealingDecoder<D>,
+    config: PlayoutConfig,
+    /// Queued frames by extended timestamp.
+    frames: BTreeMap<i64, Vec<u8>>,
+    /// Extended timestamp of the next frame to play; `None` while filling.
+    next: Option<i64>,
+    /// A recent timestamp, for extending 32-bit timestamps across wraps.
+    reference: Option<i64>,
+    target_delay: usize,
+    clean_ticks: u32,
+    output: Vec<i16>,
+    stats: PlayoutStats,
+}
+
+impl<D: FrameDecoder> PlayoutPipeline<D> {
+    /// Play out frames through `decoder`; frames are
+    /// [`frame_samples`](ConcealingDecoder::frame_samples) long.
+    pub fn new(decoder: ConcealingDecoder<D>, config: PlayoutConfig) -> Result<Self> {
+        if config.min_delay == 0 || config.max_delay < config.min_delay {
+            return Err(SpanDspError::InvalidInput(format!(
+                "playout delay must satisfy 0 < min_delay <= max_delay, got {}..={}",
+                config.min_delay, config.max_delay
+            )));
+        }
+        let frame_samples = decoder.frame_samples();
+        Ok(Self {
+            decoder,
+            config,
+            frames: BTreeMap::new(),
+            next: None,
+            reference: None,
+            target_delay: config.min_delay,
+            clean_ticks: 0,
+            output: vec![0; frame_samples],
+            stats: PlayoutStats::default(),
+        })
+    }
+
+    /// Queue a received frame. `timestamp` counts samples, wrapping like an
+    /// RTP timestamp.
+    ///
+    /// Returns `false` if the frame was discarded as late or a duplicate.
+    pub fn put(&mut self, timestamp: u32, frame: &[u8]) -> bool {
+        let ts = self.extend(timestamp);
+        if let Some(next) = self.next {
+            if ts < next {
+                self.stats.late += 1;
+                return false;
+            }
+            let horizon = next + (self.config.max_delay * self.frame_samples()) as i64;
+            if ts >= horizon {
+                self.stats.resyncs += 1;
+                self.frames.clear();
+                self.next = None;
+                self.target_delay = self.config.min_delay;
+            }
+        }
+        if self.frames.contains_key(&ts) {
+            self.stats.duplicates += 1;
+            return false;
+        }
+        self.frames.insert(ts, frame.to_vec());
+        self.stats.received += 1;
+        true
+    }
+
+    /// Produce the next frame of audio: decoded, concealed, or silence
+    /// while the buffer fills.
+    pub fn tick(&mut self) -> &[i16] {
+        self.output.fill(0);
+        let next = match self.next {
+            Some(next) => next,
+            None => match self.frames.first_key_value() {
+                Some((&first, _)) if self.frames.len() >= self.target_delay => first,
+                _ => return &self.output,
+            },
+        };
+        let step = self.frame_samples() as i64;
+        match self.frames.remove(&next) {
+            Some(frame) => {
+                self.decoder.decode_frame(Some(&frame), &mut self.output);
+                self.stats.played += 1;
+                self.next = Some(next + step);
+            }
+            None => {
+                self.decoder.decode_frame(None, &mut self.output);
+                self.stats.concealed += 1;
+                if self.frames.is_empty() && self.target_delay < self.config.max_delay {
+                    // Underrun: hold the playout point, adding a frame of delay.
+                    self.target_delay += 1;
+                    self.clean_ticks = 0;
+                    self.next = Some(next);
+                    return &self.output;
+                }
+                self.next = Some(next + step);
+            }
+        }
+        self.adapt();
+        &self.output
+    }
+
+    /// Shrink the delay after a quiet spell, dropping a frame if the
+    /// buffer runs deeper than the delay allows.
+    fn adapt(&mut self) {
+        self.clean_ticks += 1;
+        if self.clean_ticks >= self.config.shrink_after && self.target_delay > self.config.min_delay
+        {
+            self.target_delay -= 1;
+            self.clean_ticks = 0;
+        }
+        if self.frames.len() > self.target_delay
+            && let Some(next) = self.next
+        {
+            if self.frames.remove(&next).is_some() {
+                self.stats.dropped += 1;
+            }
+            self.next = Some(next + self.frame_samples() as i64);
+        }
+    }
+
+    /// Extend a 32-bit timestamp to 64 bits, relative to the last one seen.
+    fn extend(&mut self, timestamp: u32) -> i64 {
+        let ts = match self.reference {
+            Some(reference) => {
+                reference + i64::from(timestamp.wrapping_sub(reference as u32) as i32)
+            }
+            None => i64::from(timestamp),
+        };
+        self.reference = Some(ts);
+        ts
+    }
+
+    /// Samples in each output frame.
+    pub fn frame_samples(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Frames queued.
+    pub fn buffered(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The delay the buffer is currently aiming for, in frames.
+    pub fn target_delay(&self) -> usize {
+        self.target_delay
+    }
+
+    /// Counters since creation.
+    pub fn stats(&self) -> PlayoutStats {
+        self.stats
+    }
+
+    /// The decoder and its concealment state.
+    pub fn decoder(&self) -> &ConcealingDecoder<D> {
+        &self.decoder
+    }
+}
+
+impl<D: fmt::Debug> fmt::Debug for PlayoutPipeline<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlayoutPipeline")
+            .field("decoder", &self.decoder)
+            .field("buffered", &self.frames.len())
+            .field("target_delay", &self.target_delay)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
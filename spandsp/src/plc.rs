@@ -0,0 +1,170 @@
+//! Packet loss concealment.
+//!
+//! [`Plc`] wraps spandsp's pitch-repeating concealment. It has to see every
+//! received frame as well as fill in the lost ones: the history it repeats
+//! from, and the fade back to real audio after a gap, both come from the
+//! frames passed to [`Plc::rx`]. [`ConcealingDecoder`] keeps the two in
+//! step, so a jitter buffer can hand it `None` for a missing packet.
+//!
+//! ```no_run
+//! use spandsp::g711::{G711Mode, G711State};
+//!
+//! let mut decoder = G711State::new(G711Mode::ULaw)
+//!     .unwrap()
+//!     .with_plc(160)
+//!     .unwrap();
+//! let mut amp = [0i16; 160];
+//! decoder.decode_frame(Some(&[0xFF; 160]), &mut amp);
+//! decoder.decode_frame(None, &mut amp); // lost: concealed
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// RAII wrapper around `plc_state_t`.
+///
+/// Created via `Plc::new()`, which calls `plc_init(NULL)`. Freed on drop
+/// via `plc_free`.
+pub struct Plc {
+    ptr: NonNull<spandsp_sys::plc_state_t>,
+}
+
+impl Plc {
+    /// Create a concealment state with no history.
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { spandsp_sys::plc_init(std::ptr::null_mut()) };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr })
+    }
+
+    /// Pass received audio through, recording it as history and smoothing
+    /// the join if it follows concealed audio.
+    ///
+    /// Returns the number of samples processed.
+    pub fn rx(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::plc_rx(self.ptr.as_ptr(), amp.as_mut_ptr(), len) as usize }
+    }
+
+    /// Fill `amp` with synthetic audio in place of a lost frame.
+    ///
+    /// Returns the number of samples generated.
+    pub fn fillin(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::plc_fillin(self.ptr.as_ptr(), amp.as_mut_ptr(), len) as usize }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::plc_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for Plc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plc").finish_non_exhaustive()
+    }
+}
+
+impl Drop for Plc {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::plc_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+/// A decoder that [`ConcealingDecoder`] can put concealment behind.
+pub trait FrameDecoder {
+    /// Decode one frame into `amp`, returning the number of samples
+    /// written.
+    fn decode_into(&mut self, amp: &mut [i16], frame: &[u8]) -> usize;
+}
+
+/// A decoder with packet loss concealment in front of its output.
+pub struct ConcealingDecoder<D> {
+    decoder: D,
+    plc: Plc,
+    frame_samples: usize,
+    lost_frames: u64,
+    lost_run: u32,
+}
+
+impl<D: FrameDecoder> ConcealingDecoder<D> {
+    /// Conceal lost frames of `frame_samples` samples for `decoder`.
+    pub fn new(decoder: D, frame_samples: usize) -> Result<Self> {
+        if frame_samples == 0 {
+            return Err(SpanDspError::InvalidInput(
+                "frame_samples must be non-zero".into(),
+            ));
+        }
+        Ok(Self {
+            decoder,
+            plc: Plc::new()?,
+            frame_samples,
+            lost_frames: 0,
+            lost_run: 0,
+        })
+    }
+
+    /// Decode the next frame of the stream into `amp`, or conceal it when
+    /// it was lost (`None`).
+    ///
+    /// A concealed frame is `frame_samples` long, or shorter if `amp` is.
+    /// Returns the number of samples written.
+    pub fn decode_frame(&mut self, frame: Option<&[u8]>, amp: &mut [i16]) -> usize {
+        match frame {
+            Some(frame) => {
+                let samples = self.decoder.decode_into(amp, frame);
+                self.lost_run = 0;
+                self.plc.rx(&mut amp[..samples])
+            }
+            None => {
+                let samples = self.frame_samples.min(amp.len());
+                self.lost_frames += 1;
+                self.lost_run += 1;
+                self.plc.fillin(&mut amp[..samples])
+            }
+        }
+    }
+
+    /// Samples in one frame.
+    pub fn frame_samples(&self) -> usize {
+        self.frame_samples
+    }
+
+    /// Frames concealed since creation.
+    pub fn lost_frames(&self) -> u64 {
+        self.lost_frames
+    }
+
+    /// Frames concealed since the last one received.
+    pub fn consecutive_lost_frames(&self) -> u32 {
+        self.lost_run
+    }
+
+    /// The wrapped decoder.
+    pub fn decoder(&self) -> &D {
+        &self.decoder
+    }
+
+    /// Unwrap the decoder, dropping the concealment history.
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+}
+
+impl<D: fmt::Debug> fmt::Debug for ConcealingDecoder<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcealingDecoder")
+            .field("decoder", &self.decoder)
+            .field("frame_samples", &self.frame_samples)
+            .field("lost_frames", &self.lost_frames)
+            .finish_non_exhaustive()
+    }
+}
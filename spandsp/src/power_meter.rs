@@ -1,20 +1,39 @@
 //! Safe wrapper around spandsp's power meter.
 //!
-//! Wraps `power_meter_t` for measuring the power level of an audio signal.
+//! Wraps `power_meter_t` for measuring the power level of an audio signal,
+//! and builds a [`LevelMeter`] on it for call-health diagnostics.
 
 extern crate spandsp_sys;
 
+use std::fmt;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError};
 
+/// Level of a full-scale sine wave, in dBm0 (spandsp's `DBM0_MAX_SINE_POWER`).
+#[allow(clippy::approx_constant)]
+pub(crate) const DBM0_MAX_SINE_POWER: f32 = 3.14;
+
+/// Mean square of a sine wave at `dbm0`, in squared linear units.
+pub(crate) fn dbm0_to_sine_power(dbm0: f32) -> f32 {
+    let amp = 32767.0 * 10.0f32.powf((dbm0 - DBM0_MAX_SINE_POWER) / 20.0);
+    amp * amp / 2.0
+}
+
+/// The level of a mean square, in dBm0; `-inf` for silence.
+pub(crate) fn mean_square_dbm0(mean_sq: f64) -> f32 {
+    let full_scale_sine = 32767.0f64 * 32767.0 / 2.0;
+    (10.0 * (mean_sq / full_scale_sine).log10()) as f32 + DBM0_MAX_SINE_POWER
+}
+
 /// RAII wrapper around `power_meter_t`.
 ///
 /// Created via `PowerMeter::new()`, which calls `power_meter_init(NULL, shift)`.
 /// Freed on drop via `power_meter_free`.
 pub struct PowerMeter {
     ptr: NonNull<spandsp_sys::power_meter_t>,
+    shift: i32,
 }
 
 impl PowerMeter {
@@ -25,7 +44,7 @@ impl PowerMeter {
     pub fn new(shift: i32) -> Result<Self> {
         let ptr = unsafe { spandsp_sys::power_meter_init(std::ptr::null_mut(), shift as c_int) };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self { ptr, shift })
     }
 
     /// Update the power meter with a single audio sample.
@@ -55,6 +74,7 @@ impl PowerMeter {
         unsafe {
             spandsp_sys::power_meter_damping(self.ptr.as_ptr(), shift as c_int);
         }
+        self.shift = shift;
     }
 
     /// Return the raw pointer.
@@ -63,6 +83,15 @@ impl PowerMeter {
     }
 }
 
+impl fmt::Debug for PowerMeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PowerMeter")
+            .field("shift", &self.shift)
+            .field("current_dbm0", &self.current_dbm0())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for PowerMeter {
     fn drop(&mut self) {
         unsafe {
@@ -80,3 +109,153 @@ pub fn level_dbm0(level: f32) -> i32 {
 pub fn level_dbov(level: f32) -> i32 {
     unsafe { spandsp_sys::power_meter_level_dbov(level) }
 }
+
+// ---------------------------------------------------------------------------
+// DC restoration
+// ---------------------------------------------------------------------------
+
+/// Removes DC offset from a signal.
+///
+/// A port of spandsp's `dc_restore`, which is inline in its header and so
+/// has no symbol to bind: a slow single-pole estimate of the DC level,
+/// subtracted from each sample.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DcRestore {
+    state: i32,
+}
+
+impl DcRestore {
+    /// A restorer with no offset estimated yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the estimate with `amp` and return it with the offset removed.
+    pub fn restore(&mut self, amp: i16) -> i16 {
+        self.state += ((i32::from(amp) << 15) - self.state) >> 14;
+        (i32::from(amp) - (self.state >> 15)) as i16
+    }
+
+    /// The current DC offset estimate.
+    pub fn estimate(&self) -> i16 {
+        (self.state >> 15) as i16
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Level meter
+// ---------------------------------------------------------------------------
+
+/// Damping of the short-term meter: a time constant of 2^7 samples (16 ms).
+const SHORT_TERM_SHIFT: i32 = 7;
+
+/// Damping of the long-term meter: a time constant of 2^13 samples (1 s).
+const LONG_TERM_SHIFT: i32 = 13;
+
+/// A snapshot of a [`LevelMeter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelDiagnostics {
+    /// Level over roughly the last 16 ms, in dBm0.
+    pub short_term_dbm0: f32,
+    /// Level over roughly the last second, in dBm0.
+    pub long_term_dbm0: f32,
+    /// Largest sample magnitude since the counters were reset.
+    pub peak: u16,
+    /// Samples at full scale since the counters were reset.
+    pub clipped: u64,
+    /// The DC offset currently estimated.
+    pub dc_offset: i16,
+    /// Samples metered since the counters were reset.
+    pub samples: u64,
+}
+
+impl fmt::Display for LevelDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "short {:.1} dBm0, long {:.1} dBm0, peak {}, clipped {}/{}, dc {}",
+            self.short_term_dbm0,
+            self.long_term_dbm0,
+            self.peak,
+            self.clipped,
+            self.samples,
+            self.dc_offset
+        )
+    }
+}
+
+/// Level, peak, clipping and DC offset of an audio stream.
+///
+/// The short- and long-term levels come from two [`PowerMeter`]s fed with
+/// the signal after [`DcRestore`], so an offset shows up in
+/// [`dc_offset`](LevelDiagnostics::dc_offset) rather than inflating the
+/// level. Peak and clipping are counted on the signal as received.
+pub struct LevelMeter {
+    short_term: PowerMeter,
+    long_term: PowerMeter,
+    dc: DcRestore,
+    peak: u16,
+    clipped: u64,
+    samples: u64,
+}
+
+impl LevelMeter {
+    /// Create a meter with nothing measured.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            short_term: PowerMeter::new(SHORT_TERM_SHIFT)?,
+            long_term: PowerMeter::new(LONG_TERM_SHIFT)?,
+            dc: DcRestore::new(),
+            peak: 0,
+            clipped: 0,
+            samples: 0,
+        })
+    }
+
+    /// Meter a block of audio.
+    pub fn update(&mut self, amp: &[i16]) {
+        for &sample in amp {
+            let magnitude = sample.unsigned_abs();
+            self.peak = self.peak.max(magnitude);
+            if magnitude >= i16::MAX as u16 {
+                self.clipped += 1;
+            }
+            let restored = self.dc.restore(sample);
+            self.short_term.update(restored);
+            self.long_term.update(restored);
+        }
+        self.samples += amp.len() as u64;
+    }
+
+    /// The current readings.
+    pub fn diagnostics(&self) -> LevelDiagnostics {
+        LevelDiagnostics {
+            short_term_dbm0: self.short_term.current_dbm0(),
+            long_term_dbm0: self.long_term.current_dbm0(),
+            peak: self.peak,
+            clipped: self.clipped,
+            dc_offset: self.dc.estimate(),
+            samples: self.samples,
+        }
+    }
+
+    /// The current readings, then reset the peak, clipping and sample
+    /// counts, e.g. once per dashboard reporting interval. The levels and
+    /// DC estimate carry on.
+    pub fn take_diagnostics(&mut self) -> LevelDiagnostics {
+        let diagnostics = self.diagnostics();
+        self.peak = 0;
+        self.clipped = 0;
+        self.samples = 0;
+        diagnostics
+    }
+}
+
+impl fmt::Debug for LevelMeter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LevelMeter")
+            .field("diagnostics", &self.diagnostics())
+            .finish_non_exhaustive()
+    }
+}
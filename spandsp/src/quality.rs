@@ -0,0 +1,191 @@
+//! Signal quality measurements.
+//!
+//! Checking a codec, an echo canceller or a whole media path comes down to
+//! comparing what went in with what came out. These are the measurements
+//! this crate's own tests use: level, correlation (with a search for the
+//! path's delay), plain and segmental SNR, and total harmonic distortion
+//! of a tone.
+//!
+//! ```
+//! use spandsp::quality::{best_correlation, rms, snr_db};
+//!
+//! let tone: Vec<i16> = (0..800)
+//!     .map(|i| (8000.0 * (i as f64 * 0.25).sin()) as i16)
+//!     .collect();
+//! // The same tone, three samples late and slightly quieter.
+//! let mut delayed = vec![0i16; 3];
+//! delayed.extend(tone.iter().map(|&s| s - s / 10));
+//!
+//! let found = best_correlation(&tone[..400], &delayed, 10).unwrap();
+//! assert_eq!(found.lag, 3);
+//! assert!(found.correlation > 0.99);
+//! assert!(rms(&delayed) < rms(&tone));
+//! assert!(snr_db(&tone, &delayed[3..]) > 19.0);
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::error::{Result, SpanDspError};
+use crate::tone_detect::{GoertzelDescriptor, GoertzelDetector};
+
+/// Lowest per-segment SNR counted by [`segmental_snr_db`], in dB.
+const SEGMENT_SNR_FLOOR_DB: f64 = -10.0;
+
+/// Highest per-segment SNR counted by [`segmental_snr_db`], in dB.
+const SEGMENT_SNR_CEILING_DB: f64 = 35.0;
+
+/// Root mean square of `amp`, in linear units; 0 for no samples.
+pub fn rms(amp: &[i16]) -> f64 {
+    if amp.is_empty() {
+        return 0.0;
+    }
+    let sum_sq: f64 = amp.iter().map(|&s| f64::from(s).powi(2)).sum();
+    (sum_sq / amp.len() as f64).sqrt()
+}
+
+/// Pearson correlation of `a` and `b` over their common length.
+///
+/// 1 means `b` is a scaled copy of `a`. Returns 0 if either is constant.
+pub fn correlation(a: &[i16], b: &[i16]) -> f64 {
+    let n = a.len().min(b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    let (a, b) = (&a[..n], &b[..n]);
+    let mean = |x: &[i16]| x.iter().map(|&s| f64::from(s)).sum::<f64>() / n as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut cov = 0.0;
+    let mut var_a = 0.0;
+    let mut var_b = 0.0;
+    for (&x, &y) in a.iter().zip(b) {
+        let da = f64::from(x) - mean_a;
+        let db = f64::from(y) - mean_b;
+        cov += da * db;
+        var_a += da * da;
+        var_b += db * db;
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return 0.0;
+    }
+    cov / (var_a.sqrt() * var_b.sqrt())
+}
+
+/// Where [`best_correlation`] found the closest match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LagMatch {
+    /// Samples by which the test signal lags the reference.
+    pub lag: usize,
+    /// [`correlation`] at that lag.
+    pub correlation: f64,
+}
+
+/// Find the delay through a path: correlate `reference` with each
+/// `reference.len()`-sample window of `test` starting up to `max_lag`
+/// samples in, and return the best.
+///
+/// Returns `None` if `test` is too short for even the first window.
+pub fn best_correlation(reference: &[i16], test: &[i16], max_lag: usize) -> Option<LagMatch> {
+    (0..=max_lag)
+        .map_while(|lag| {
+            let window = test.get(lag..lag + reference.len())?;
+            Some(LagMatch {
+                lag,
+                correlation: correlation(reference, window),
+            })
+        })
+        .max_by(|a, b| a.correlation.total_cmp(&b.correlation))
+}
+
+/// Signal to noise ratio of `test` against `reference` over their common
+/// length, in dB, counting every difference as noise.
+///
+/// Infinite if the two are identical.
+pub fn snr_db(reference: &[i16], test: &[i16]) -> f64 {
+    let (signal, noise) = signal_and_noise(reference, test);
+    10.0 * (signal / noise).log10()
+}
+
+/// Segmental SNR of `test` against `reference`, in dB: the mean of the SNR
+/// of each `segment_len`-sample segment, each limited to -10..35 dB.
+///
+/// Unlike [`snr_db`], quiet passages count as much as loud ones, which
+/// tracks perceived codec quality more closely. Silent reference segments
+/// are skipped; with none left the result is 0.
+pub fn segmental_snr_db(reference: &[i16], test: &[i16], segment_len: usize) -> f64 {
+    let n = reference.len().min(test.len());
+    let segment_len = segment_len.max(1);
+    let snrs: Vec<f64> = reference[..n]
+        .chunks(segment_len)
+        .zip(test[..n].chunks(segment_len))
+        .filter_map(|(r, t)| {
+            let (signal, noise) = signal_and_noise(r, t);
+            (signal > 0.0).then(|| {
+                (10.0 * (signal / noise).log10())
+                    .clamp(SEGMENT_SNR_FLOOR_DB, SEGMENT_SNR_CEILING_DB)
+            })
+        })
+        .collect();
+    if snrs.is_empty() {
+        0.0
+    } else {
+        snrs.iter().sum::<f64>() / snrs.len() as f64
+    }
+}
+
+fn signal_and_noise(reference: &[i16], test: &[i16]) -> (f64, f64) {
+    reference
+        .iter()
+        .zip(test)
+        .fold((0.0, 0.0), |(signal, noise), (&r, &t)| {
+            let r = f64::from(r);
+            (signal + r * r, noise + (r - f64::from(t)).powi(2))
+        })
+}
+
+/// Total harmonic distortion of a tone at `fundamental_hz`: the RMS of its
+/// harmonics below the Nyquist frequency relative to the fundamental, as a
+/// fraction (0.01 is 1%).
+///
+/// A Hann window keeps the fundamental's own energy out of the harmonics;
+/// give it a few hundred cycles of the tone for a reading below 0.1%.
+/// Each frequency is measured with spandsp's Goertzel filter
+/// ([`GoertzelDetector`]). Returns `InvalidInput` for no samples, more than
+/// `i32::MAX` of them, or a fundamental outside 0..Nyquist, and an infinite
+/// THD if the fundamental is absent.
+pub fn thd(amp: &[i16], sample_rate: u32, fundamental_hz: f64) -> Result<f64> {
+    let nyquist = f64::from(sample_rate) / 2.0;
+    if amp.is_empty()
+        || amp.len() > i32::MAX as usize
+        || !(fundamental_hz > 0.0 && fundamental_hz < nyquist)
+    {
+        return Err(SpanDspError::InvalidInput(format!(
+            "THD needs samples and a fundamental between 0 and {nyquist} Hz, got {} samples at {fundamental_hz} Hz",
+            amp.len()
+        )));
+    }
+    let last = (amp.len().max(2) - 1) as f64;
+    let windowed: Vec<i16> = amp
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| {
+            (f64::from(s) * (0.5 - 0.5 * (2.0 * PI * i as f64 / last).cos())).round() as i16
+        })
+        .collect();
+    // spandsp's filters assume 8 kHz, so scale each frequency to match.
+    let power = |hz: f64| -> Result<f64> {
+        let scaled = (hz * 8000.0 / f64::from(sample_rate)) as f32;
+        let mut goertzel = GoertzelDetector::new(&GoertzelDescriptor::new(scaled, amp.len()))?;
+        goertzel.update(&windowed);
+        Ok(f64::from(goertzel.result()))
+    };
+    let mut harmonics = 0.0;
+    for k in 2.. {
+        let hz = k as f64 * fundamental_hz;
+        if hz >= nyquist {
+            break;
+        }
+        harmonics += power(hz)?;
+    }
+    Ok((harmonics / power(fundamental_hz)?).sqrt())
+}
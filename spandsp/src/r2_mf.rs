@@ -0,0 +1,307 @@
+//! Safe wrappers around spandsp's R2 MF tone generation and detection.
+//!
+//! R2 register signalling passes each signal as a pair out of six tones.
+//! The forward direction (from the calling exchange) uses 1380-1980 Hz and
+//! the backward direction 540-1140 Hz, so both can be on the line at once;
+//! see [`r2_mfc`](crate::r2_mfc) for the compelled exchange built on them.
+//!
+//! - `R2MfTx` wraps `r2_mf_tx_state_t`.
+//! - `R2MfRx` wraps `r2_mf_rx_state_t`.
+
+extern crate spandsp_sys;
+
+use std::ffi::c_void;
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// Tone codes for signals 1-15, from spandsp's `bell_r2_mf.c`.
+const R2_MF_TONE_CODES: &[u8; 15] = b"1234567890BCDEF";
+
+/// One of the 15 R2 MF signals.
+///
+/// What a signal means depends on its direction and group: forward I-1 to
+/// I-10 are the digits 1-9 and 0, while backward A-1 asks for the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct R2Signal(u8);
+
+impl R2Signal {
+    /// Signal `number`, 1-15.
+    pub fn new(number: u8) -> Option<Self> {
+        (1..=15).contains(&number).then_some(Self(number))
+    }
+
+    /// The signal number, 1-15.
+    pub fn number(self) -> u8 {
+        self.0
+    }
+
+    /// The Group I signal for an address digit.
+    pub fn from_digit(digit: char) -> Option<Self> {
+        match digit.to_digit(10)? {
+            0 => Some(Self(10)),
+            d => Some(Self(d as u8)),
+        }
+    }
+
+    /// The address digit this signal carries in Group I, if any.
+    pub fn to_digit(self) -> Option<char> {
+        match self.0 {
+            10 => Some('0'),
+            n @ 1..=9 => Some(char::from(b'0' + n)),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> c_char {
+        let index = usize::from(self.0).wrapping_sub(1);
+        R2_MF_TONE_CODES.get(index).map_or(0, |&c| c as c_char)
+    }
+
+    fn from_code(code: c_int) -> Option<Self> {
+        let code = u8::try_from(code).ok()?;
+        R2_MF_TONE_CODES
+            .iter()
+            .position(|&c| c == code)
+            .map(|i| Self(i as u8 + 1))
+    }
+}
+
+impl fmt::Display for R2Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// R2MfTx
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `r2_mf_tx_state_t`.
+///
+/// Created via `R2MfTx::new()`, which calls `r2_mf_tx_init(NULL, ...)`.
+/// Freed on drop via `r2_mf_tx_free`.
+pub struct R2MfTx {
+    ptr: NonNull<spandsp_sys::r2_mf_tx_state_t>,
+    forward: bool,
+    signal: Option<R2Signal>,
+}
+
+impl R2MfTx {
+    /// Create a generator for the forward or backward tone set.
+    pub fn new(forward: bool) -> Result<Self> {
+        let ptr = unsafe { spandsp_sys::r2_mf_tx_init(std::ptr::null_mut(), forward) };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            forward,
+            signal: None,
+        })
+    }
+
+    /// Create a generator for the forward tone set, as the calling
+    /// exchange sends.
+    pub fn forward() -> Result<Self> {
+        Self::new(true)
+    }
+
+    /// Create a generator for the backward tone set, as the called
+    /// exchange sends.
+    pub fn backward() -> Result<Self> {
+        Self::new(false)
+    }
+
+    /// Start sending `signal`, or stop with `None`. The tone continues
+    /// until changed: R2 signals last as long as the exchange needs them.
+    pub fn put(&mut self, signal: Option<R2Signal>) -> Result<()> {
+        let code = signal.map_or(0, R2Signal::code);
+        let rc = unsafe { spandsp_sys::r2_mf_tx_put(self.ptr.as_ptr(), code) };
+        if rc < 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        self.signal = signal;
+        Ok(())
+    }
+
+    /// Generate audio, silence while no signal is on.
+    ///
+    /// Returns the number of samples generated.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        let n = unsafe { spandsp_sys::r2_mf_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), len) };
+        let n = (n.max(0) as usize).min(amp.len());
+        amp[n..].fill(0);
+        n
+    }
+
+    /// The signal being sent.
+    pub fn signal(&self) -> Option<R2Signal> {
+        self.signal
+    }
+
+    /// Whether this generator uses the forward tone set.
+    pub fn is_forward(&self) -> bool {
+        self.forward
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::r2_mf_tx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for R2MfTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("R2MfTx")
+            .field("forward", &self.forward)
+            .field("signal", &self.signal)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for R2MfTx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::r2_mf_tx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: R2MfTx wraps a SpanDSP r2_mf_tx_state_t that is only accessed
+// through &self/&mut self methods.
+unsafe impl Send for R2MfTx {}
+
+// ---------------------------------------------------------------------------
+// R2MfRx
+// ---------------------------------------------------------------------------
+
+type R2MfCallback = Box<dyn FnMut(Option<R2Signal>) + Send>;
+
+/// Trampoline for `r2_mf_rx_callback_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `R2MfCallback`.
+unsafe extern "C" fn r2_mf_rx_callback_trampoline(
+    user_data: *mut c_void,
+    code: c_int,
+    _level: c_int,
+    _delay: c_int,
+) {
+    unsafe {
+        if user_data.is_null() {
+            return;
+        }
+        let closure = &mut *(user_data as *mut R2MfCallback);
+        closure(R2Signal::from_code(code));
+    }
+}
+
+/// RAII wrapper around `r2_mf_rx_state_t`.
+///
+/// Created via `R2MfRx::new()` or `R2MfRx::with_callback()`. Freed on drop
+/// via `r2_mf_rx_free`.
+pub struct R2MfRx {
+    ptr: NonNull<spandsp_sys::r2_mf_rx_state_t>,
+    forward: bool,
+    _callback: Option<Box<R2MfCallback>>,
+}
+
+impl R2MfRx {
+    /// Create a detector for the forward or backward tone set.
+    pub fn new(forward: bool) -> Result<Self> {
+        let ptr = unsafe {
+            spandsp_sys::r2_mf_rx_init(std::ptr::null_mut(), forward, None, std::ptr::null_mut())
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            forward,
+            _callback: None,
+        })
+    }
+
+    /// Create a detector for the forward tone set, as the called exchange
+    /// hears.
+    pub fn forward() -> Result<Self> {
+        Self::new(true)
+    }
+
+    /// Create a detector for the backward tone set, as the calling exchange
+    /// hears.
+    pub fn backward() -> Result<Self> {
+        Self::new(false)
+    }
+
+    /// Create a detector calling `callback` each time the signal on the
+    /// line changes, with `None` when it stops.
+    pub fn with_callback<F>(forward: bool, callback: F) -> Result<Self>
+    where
+        F: FnMut(Option<R2Signal>) + Send + 'static,
+    {
+        let boxed: Box<R2MfCallback> = Box::new(Box::new(callback));
+        let user_data = &*boxed as *const R2MfCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::r2_mf_rx_init(
+                std::ptr::null_mut(),
+                forward,
+                Some(r2_mf_rx_callback_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            forward,
+            _callback: Some(boxed),
+        })
+    }
+
+    /// Feed audio to the detector.
+    ///
+    /// Returns the number of unprocessed samples (normally 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::r2_mf_rx(self.ptr.as_ptr(), amp.as_ptr(), len).max(0) as usize }
+    }
+
+    /// The signal currently on the line, if any.
+    pub fn signal(&self) -> Option<R2Signal> {
+        R2Signal::from_code(unsafe { spandsp_sys::r2_mf_rx_get(self.ptr.as_ptr()) })
+    }
+
+    /// Whether this detector listens for the forward tone set.
+    pub fn is_forward(&self) -> bool {
+        self.forward
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::r2_mf_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for R2MfRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("R2MfRx")
+            .field("forward", &self.forward)
+            .field("signal", &self.signal())
+            .field("has_callback", &self._callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for R2MfRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::r2_mf_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: R2MfRx wraps a SpanDSP r2_mf_rx_state_t that is only accessed
+// through &self/&mut self methods, and its callback is Send.
+unsafe impl Send for R2MfRx {}
@@ -0,0 +1,730 @@
+//! R2 MFC compelled register signalling.
+//!
+//! On an E1 CAS trunk, once the line signalling has seized a channel, the
+//! two registers pass the called number, the caller's category and
+//! optionally the calling number as compelled R2 MF signals: each forward
+//! signal stays on until the backward side answers it, and each backward
+//! signal until the forward tone stops. [`R2Caller`] runs the outgoing
+//! (forward) side of that exchange and [`R2Callee`] the incoming (backward)
+//! side, each on a [`R2MfTx`]/[`R2MfRx`] pair, one audio frame at a time.
+//!
+//! Both sides time out when the other stops answering, and report each
+//! step of the exchange as an [`R2Event`] through `take_events`.
+//!
+//! The meaning of the backward signals beyond the ITU-T Q.441 core varies
+//! by country; [`R2Variant`] holds the choices.
+//!
+//! ```no_run
+//! use spandsp::r2_mfc::{R2Caller, R2Variant};
+//!
+//! let mut caller = R2Caller::dial(R2Variant::itu(), "4321", None).unwrap();
+//! let mut rx = [0i16; 160];
+//! let mut tx = [0i16; 160];
+//! while caller.outcome().is_none() {
+//!     // receive `rx` from the channel
+//!     caller.process(&rx, &mut tx).unwrap();
+//!     // send `tx` on the channel
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::error::{Result, SpanDspError};
+use crate::r2_mf::{R2MfRx, R2MfTx, R2Signal};
+
+/// Samples per millisecond at 8 kHz.
+const SAMPLES_PER_MS: u32 = 8;
+
+/// Group A backward signals (Q.441).
+const A_NEXT_DIGIT: u8 = 1;
+const A_LAST_BUT_ONE: u8 = 2;
+const A_ADDRESS_COMPLETE: u8 = 3;
+const A_CONGESTION: u8 = 4;
+const A_SEND_CATEGORY: u8 = 5;
+const A_COMPLETE_CHARGE: u8 = 6;
+const A_LAST_BUT_TWO: u8 = 7;
+const A_LAST_BUT_THREE: u8 = 8;
+
+/// Forward I-15: no more digits.
+const I_END_OF_DIGITS: u8 = 15;
+
+fn signal(number: u8) -> R2Signal {
+    R2Signal::new(number).expect("R2 signal numbers are 1-15")
+}
+
+/// The country-specific parts of R2 MFC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct R2Variant {
+    /// Whether the caller closes the called number with I-15. Without it,
+    /// the callee decides the number is complete after
+    /// [`digit_timeout_ms`](Self::digit_timeout_ms) and says so with a
+    /// pulsed A-3.
+    pub end_of_digits: bool,
+    /// Whether the callee asks for the category and calling number (with
+    /// A-5) after the first digit.
+    pub request_ani: bool,
+    /// The caller's category, a Group II signal.
+    pub category: R2Signal,
+    /// Group B: subscriber free, charge.
+    pub accept_with_charge: R2Signal,
+    /// Group B: subscriber free, no charge.
+    pub accept_without_charge: R2Signal,
+    /// Group B: subscriber busy.
+    pub busy: R2Signal,
+    /// Group B: congestion.
+    pub congestion: R2Signal,
+    /// Group B: unallocated number.
+    pub unallocated: R2Signal,
+    /// Group B: subscriber line out of order.
+    pub out_of_order: R2Signal,
+    /// How long either side waits for the other at any step.
+    pub signal_timeout_ms: u32,
+    /// How long the callee waits for another digit without I-15.
+    pub digit_timeout_ms: u32,
+    /// Length of a pulsed backward signal.
+    pub pulse_ms: u32,
+}
+
+impl R2Variant {
+    /// ITU-T Q.441: I-15 ends the number, no calling number, B-6/B-7 to
+    /// answer.
+    pub fn itu() -> Self {
+        Self {
+            end_of_digits: true,
+            request_ani: false,
+            category: signal(1),
+            accept_with_charge: signal(6),
+            accept_without_charge: signal(7),
+            busy: signal(3),
+            congestion: signal(4),
+            unallocated: signal(5),
+            out_of_order: signal(8),
+            signal_timeout_ms: 15_000,
+            digit_timeout_ms: 4_000,
+            pulse_ms: 150,
+        }
+    }
+
+    /// Brazil: the calling number is requested, the called number is not
+    /// closed with I-15, and the Group B signals are renumbered.
+    pub fn brazil() -> Self {
+        Self {
+            end_of_digits: false,
+            request_ani: true,
+            accept_with_charge: signal(1),
+            busy: signal(2),
+            accept_without_charge: signal(5),
+            unallocated: signal(7),
+            ..Self::itu()
+        }
+    }
+
+    fn rejection(&self, b: R2Signal) -> Option<R2Reject> {
+        [
+            (self.busy, R2Reject::Busy),
+            (self.congestion, R2Reject::Congestion),
+            (self.unallocated, R2Reject::Unallocated),
+            (self.out_of_order, R2Reject::OutOfOrder),
+        ]
+        .into_iter()
+        .find_map(|(s, reject)| (s == b).then_some(reject))
+    }
+
+    fn rejection_signal(&self, reject: R2Reject) -> R2Signal {
+        match reject {
+            R2Reject::Busy => self.busy,
+            R2Reject::Congestion => self.congestion,
+            R2Reject::Unallocated => self.unallocated,
+            R2Reject::OutOfOrder => self.out_of_order,
+        }
+    }
+
+    fn samples(ms: u32) -> u32 {
+        ms.saturating_mul(SAMPLES_PER_MS)
+    }
+}
+
+impl Default for R2Variant {
+    /// [`R2Variant::itu`].
+    fn default() -> Self {
+        Self::itu()
+    }
+}
+
+/// Why the callee turned a call away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum R2Reject {
+    /// The called line is busy.
+    Busy,
+    /// No route or register available.
+    Congestion,
+    /// The number does not exist.
+    Unallocated,
+    /// The called line is out of order.
+    OutOfOrder,
+}
+
+/// How a register exchange ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum R2Outcome {
+    /// The call goes ahead.
+    Accepted {
+        /// Whether the call is charged.
+        charge: bool,
+    },
+    /// The callee turned the call away.
+    Rejected(R2Reject),
+    /// The caller got a backward signal it has no use for.
+    Unexpected(R2Signal),
+    /// The other side stopped answering.
+    Timeout,
+}
+
+/// A step of a register exchange, for logging a call or acting on it
+/// before the exchange ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum R2Event {
+    /// The callee received a digit of the called number.
+    DnisDigit(char),
+    /// The callee received a digit of the calling number.
+    AniDigit(char),
+    /// The callee received the caller's category.
+    Category(R2Signal),
+    /// The callee asked the caller for its category and calling number.
+    AniRequested,
+    /// The callee has the whole called number and moves on to Group B.
+    AddressComplete,
+    /// The exchange is over; for an accepted call, the line is answered.
+    Finished(R2Outcome),
+}
+
+/// What the callee has been told about a call, for deciding on it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct R2Offer {
+    /// The called number.
+    pub dnis: String,
+    /// The calling number, if it was requested.
+    pub ani: Option<String>,
+    /// The caller's category, a Group II signal.
+    pub category: R2Signal,
+}
+
+/// Keys the signal to send from frame to frame.
+struct Line {
+    tx: R2MfTx,
+    rx: R2MfRx,
+}
+
+impl Line {
+    fn new(forward: bool) -> Result<Self> {
+        Ok(Self {
+            tx: R2MfTx::new(forward)?,
+            rx: R2MfRx::new(!forward)?,
+        })
+    }
+
+    /// Feed `rx` to the detector, let `step` choose what to send given the
+    /// signal heard, and generate `tx`.
+    fn process(
+        &mut self,
+        rx: &[i16],
+        tx: &mut [i16],
+        step: impl FnOnce(Option<R2Signal>, u32) -> Option<R2Signal>,
+    ) -> Result<()> {
+        self.rx.rx(rx);
+        let samples = rx.len().min(u32::MAX as usize) as u32;
+        let send = step(self.rx.signal(), samples);
+        if send != self.tx.signal() {
+            self.tx.put(send)?;
+        }
+        self.tx.generate(tx);
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// R2Caller
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CallerState {
+    /// A forward signal is on, awaiting the answer.
+    Send(R2Signal),
+    /// The answer was heard and the forward tone stopped; waiting for the
+    /// backward tone to stop before acting on it.
+    Release(R2Signal),
+    /// Nothing left to send; waiting for a pulsed backward signal.
+    Idle,
+    Done(R2Outcome),
+}
+
+/// The forward register's decisions, apart from the audio.
+#[derive(Debug)]
+struct CallerLogic {
+    variant: R2Variant,
+    dnis: Vec<R2Signal>,
+    ani: Vec<R2Signal>,
+    /// Index of the next called digit to send.
+    next: usize,
+    /// Index of the next calling digit, once the category has been sent.
+    ani_next: Option<usize>,
+    group_b: bool,
+    state: CallerState,
+    waited: u32,
+    events: Vec<R2Event>,
+}
+
+impl CallerLogic {
+    fn step(&mut self, heard: Option<R2Signal>, samples: u32) -> Option<R2Signal> {
+        let before = self.state;
+        match (self.state, heard) {
+            (CallerState::Send(_) | CallerState::Idle, Some(b)) => {
+                self.state = CallerState::Release(b)
+            }
+            (CallerState::Release(b), None) => self.state = self.act(b),
+            _ => {}
+        }
+        if self.state != before {
+            self.waited = 0;
+        } else if !matches!(self.state, CallerState::Done(_)) {
+            self.waited = self.waited.saturating_add(samples);
+            if self.waited >= R2Variant::samples(self.variant.signal_timeout_ms) {
+                self.state = CallerState::Done(R2Outcome::Timeout);
+            }
+        }
+        if let CallerState::Done(outcome) = self.state
+            && self.state != before
+        {
+            self.events.push(R2Event::Finished(outcome));
+        }
+        match self.state {
+            CallerState::Send(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn act(&mut self, b: R2Signal) -> CallerState {
+        if self.group_b {
+            let outcome = if b == self.variant.accept_with_charge {
+                R2Outcome::Accepted { charge: true }
+            } else if b == self.variant.accept_without_charge {
+                R2Outcome::Accepted { charge: false }
+            } else {
+                self.variant
+                    .rejection(b)
+                    .map_or(R2Outcome::Unexpected(b), R2Outcome::Rejected)
+            };
+            return CallerState::Done(outcome);
+        }
+        match b.number() {
+            A_NEXT_DIGIT => self.next_digit(),
+            A_LAST_BUT_ONE => self.repeat(1),
+            A_LAST_BUT_TWO => self.repeat(2),
+            A_LAST_BUT_THREE => self.repeat(3),
+            A_ADDRESS_COMPLETE => {
+                self.group_b = true;
+                self.events.push(R2Event::AddressComplete);
+                CallerState::Send(self.variant.category)
+            }
+            A_SEND_CATEGORY => match self.ani_next {
+                None => {
+                    self.ani_next = Some(0);
+                    self.events.push(R2Event::AniRequested);
+                    CallerState::Send(self.variant.category)
+                }
+                Some(i) => {
+                    self.ani_next = Some(i + 1);
+                    let end = signal(I_END_OF_DIGITS);
+                    CallerState::Send(self.ani.get(i).copied().unwrap_or(end))
+                }
+            },
+            A_CONGESTION => CallerState::Done(R2Outcome::Rejected(R2Reject::Congestion)),
+            A_COMPLETE_CHARGE => CallerState::Done(R2Outcome::Accepted { charge: true }),
+            _ => CallerState::Done(R2Outcome::Unexpected(b)),
+        }
+    }
+
+    fn next_digit(&mut self) -> CallerState {
+        match self.dnis.get(self.next) {
+            Some(&digit) => {
+                self.next += 1;
+                CallerState::Send(digit)
+            }
+            None if self.variant.end_of_digits => CallerState::Send(signal(I_END_OF_DIGITS)),
+            None => CallerState::Idle,
+        }
+    }
+
+    /// Go back `back` digits before the last one sent and send from there.
+    fn repeat(&mut self, back: usize) -> CallerState {
+        self.next = self.next.saturating_sub(back + 1);
+        self.next_digit()
+    }
+}
+
+/// The outgoing side of an R2 MFC register exchange.
+pub struct R2Caller {
+    line: Line,
+    logic: CallerLogic,
+}
+
+impl R2Caller {
+    /// Start sending `number`, offering `ani` as the calling number if the
+    /// callee asks for it.
+    pub fn dial(variant: R2Variant, number: &str, ani: Option<&str>) -> Result<Self> {
+        let dnis = address(number)?;
+        if dnis.is_empty() {
+            return Err(SpanDspError::InvalidInput(
+                "the called number is empty".into(),
+            ));
+        }
+        let ani = ani.map(address).transpose()?.unwrap_or_default();
+        let first = dnis[0];
+        Ok(Self {
+            line: Line::new(true)?,
+            logic: CallerLogic {
+                variant,
+                dnis,
+                ani,
+                next: 1,
+                ani_next: None,
+                group_b: false,
+                state: CallerState::Send(first),
+                waited: 0,
+                events: Vec::new(),
+            },
+        })
+    }
+
+    /// Process one frame: `rx` from the channel, `tx` to send on it.
+    ///
+    /// Returns the outcome once the exchange is over; after that `tx` is
+    /// silence.
+    pub fn process(&mut self, rx: &[i16], tx: &mut [i16]) -> Result<Option<R2Outcome>> {
+        let logic = &mut self.logic;
+        self.line
+            .process(rx, tx, |heard, samples| logic.step(heard, samples))?;
+        Ok(self.outcome())
+    }
+
+    /// How the exchange ended, once it has.
+    pub fn outcome(&self) -> Option<R2Outcome> {
+        match self.logic.state {
+            CallerState::Done(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    /// Steps of the exchange since the last call, oldest first. The caller
+    /// reports [`AniRequested`](R2Event::AniRequested),
+    /// [`AddressComplete`](R2Event::AddressComplete) and
+    /// [`Finished`](R2Event::Finished).
+    pub fn take_events(&mut self) -> Vec<R2Event> {
+        std::mem::take(&mut self.logic.events)
+    }
+
+    /// Called digits sent so far, counting any repeats only once.
+    pub fn digits_sent(&self) -> usize {
+        self.logic.next
+    }
+
+    /// The variant in use.
+    pub fn variant(&self) -> &R2Variant {
+        &self.logic.variant
+    }
+}
+
+impl fmt::Debug for R2Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("R2Caller")
+            .field("state", &self.logic.state)
+            .field("digits_sent", &self.logic.next)
+            .finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// R2Callee
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Digit,
+    Category,
+    AniDigit,
+    FinalCategory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CalleeState {
+    /// Waiting for a forward signal.
+    Listen,
+    /// A backward signal is on, awaiting the forward tone to stop; then
+    /// done with the outcome, if any.
+    Respond(R2Signal, Option<R2Outcome>),
+    /// A pulsed backward signal is on.
+    Pulse(R2Signal),
+    /// The category is in and the forward tone held, awaiting the
+    /// application's decision.
+    Offered,
+    Done(R2Outcome),
+}
+
+/// The backward register's decisions, apart from the audio.
+#[derive(Debug)]
+struct CalleeLogic {
+    variant: R2Variant,
+    expected_digits: Option<usize>,
+    dnis: String,
+    ani: Option<String>,
+    category: Option<R2Signal>,
+    expect: Expect,
+    address_complete: bool,
+    state: CalleeState,
+    waited: u32,
+    events: Vec<R2Event>,
+}
+
+impl CalleeLogic {
+    fn step(&mut self, heard: Option<R2Signal>, samples: u32) -> Option<R2Signal> {
+        let before = self.state;
+        match (self.state, heard) {
+            (CalleeState::Listen, Some(f)) => self.state = self.hear(f),
+            (CalleeState::Respond(_, then), None) => {
+                self.state = then.map_or(CalleeState::Listen, CalleeState::Done)
+            }
+            (CalleeState::Offered, None) => self.state = CalleeState::Done(R2Outcome::Timeout),
+            _ => {}
+        }
+        if self.state != before {
+            self.waited = 0;
+        } else {
+            self.waited = self.waited.saturating_add(samples);
+            match self.state {
+                CalleeState::Pulse(_)
+                    if self.waited >= R2Variant::samples(self.variant.pulse_ms) =>
+                {
+                    self.state = CalleeState::Listen;
+                    self.waited = 0;
+                }
+                CalleeState::Listen
+                    if self.expect == Expect::Digit
+                        && !self.variant.end_of_digits
+                        && !self.dnis.is_empty()
+                        && self.waited >= R2Variant::samples(self.variant.digit_timeout_ms) =>
+                {
+                    self.expect = Expect::FinalCategory;
+                    self.state = CalleeState::Pulse(signal(A_ADDRESS_COMPLETE));
+                    self.waited = 0;
+                    self.events.push(R2Event::AddressComplete);
+                }
+                CalleeState::Done(_) | CalleeState::Pulse(_) => {}
+                _ if self.waited >= R2Variant::samples(self.variant.signal_timeout_ms) => {
+                    self.state = CalleeState::Done(R2Outcome::Timeout);
+                }
+                _ => {}
+            }
+        }
+        if let CalleeState::Done(outcome) = self.state
+            && self.state != before
+        {
+            self.events.push(R2Event::Finished(outcome));
+        }
+        match self.state {
+            CalleeState::Respond(b, _) | CalleeState::Pulse(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Take in a forward signal and choose the answer.
+    fn hear(&mut self, f: R2Signal) -> CalleeState {
+        let answer = match self.expect {
+            Expect::Digit => {
+                match f.to_digit() {
+                    Some(digit) => {
+                        self.dnis.push(digit);
+                        self.events.push(R2Event::DnisDigit(digit));
+                    }
+                    None if f.number() == I_END_OF_DIGITS => self.address_complete = true,
+                    None => {}
+                }
+                if self.expected_digits.is_some_and(|n| self.dnis.len() >= n) {
+                    self.address_complete = true;
+                }
+                if self.variant.request_ani && self.ani.is_none() {
+                    self.ani = Some(String::new());
+                    self.expect = Expect::Category;
+                    self.events.push(R2Event::AniRequested);
+                    A_SEND_CATEGORY
+                } else {
+                    self.after_address()
+                }
+            }
+            Expect::Category => {
+                self.category = Some(f);
+                self.events.push(R2Event::Category(f));
+                self.expect = Expect::AniDigit;
+                A_SEND_CATEGORY
+            }
+            Expect::AniDigit => match (f.to_digit(), &mut self.ani) {
+                (Some(digit), Some(ani)) => {
+                    ani.push(digit);
+                    self.events.push(R2Event::AniDigit(digit));
+                    A_SEND_CATEGORY
+                }
+                _ => self.after_address(),
+            },
+            Expect::FinalCategory => {
+                self.category = Some(f);
+                self.events.push(R2Event::Category(f));
+                return CalleeState::Offered;
+            }
+        };
+        CalleeState::Respond(signal(answer), None)
+    }
+
+    /// Ask for the next digit, or move on to Group B once there are no more.
+    fn after_address(&mut self) -> u8 {
+        if self.address_complete {
+            self.expect = Expect::FinalCategory;
+            self.events.push(R2Event::AddressComplete);
+            A_ADDRESS_COMPLETE
+        } else {
+            self.expect = Expect::Digit;
+            A_NEXT_DIGIT
+        }
+    }
+
+    fn decide(&mut self, b: R2Signal, outcome: R2Outcome) -> Result<()> {
+        if self.state != CalleeState::Offered {
+            return Err(SpanDspError::InvalidInput(
+                "no call is waiting for a decision".into(),
+            ));
+        }
+        self.state = CalleeState::Respond(b, Some(outcome));
+        self.waited = 0;
+        Ok(())
+    }
+}
+
+/// The incoming side of an R2 MFC register exchange.
+pub struct R2Callee {
+    line: Line,
+    logic: CalleeLogic,
+}
+
+impl R2Callee {
+    /// Wait for the first digit of a call whose channel the line signalling
+    /// has just seized. With `expected_digits`, the number is complete once
+    /// that many digits are in; otherwise the caller's I-15 or the digit
+    /// timeout ends it.
+    pub fn answer(variant: R2Variant, expected_digits: Option<usize>) -> Result<Self> {
+        if expected_digits == Some(0) {
+            return Err(SpanDspError::InvalidInput(
+                "expected_digits must be at least 1".into(),
+            ));
+        }
+        Ok(Self {
+            line: Line::new(false)?,
+            logic: CalleeLogic {
+                variant,
+                expected_digits,
+                dnis: String::new(),
+                ani: None,
+                category: None,
+                expect: Expect::Digit,
+                address_complete: false,
+                state: CalleeState::Listen,
+                waited: 0,
+                events: Vec::new(),
+            },
+        })
+    }
+
+    /// Process one frame: `rx` from the channel, `tx` to send on it.
+    ///
+    /// Returns the offer once the call is waiting for
+    /// [`accept`](Self::accept) or [`reject`](Self::reject).
+    pub fn process(&mut self, rx: &[i16], tx: &mut [i16]) -> Result<Option<R2Offer>> {
+        let logic = &mut self.logic;
+        self.line
+            .process(rx, tx, |heard, samples| logic.step(heard, samples))?;
+        Ok(self.offer())
+    }
+
+    /// The call, while it is waiting for a decision.
+    pub fn offer(&self) -> Option<R2Offer> {
+        let logic = &self.logic;
+        (logic.state == CalleeState::Offered).then(|| R2Offer {
+            dnis: logic.dnis.clone(),
+            ani: logic.ani.clone(),
+            category: logic.category.unwrap_or(logic.variant.category),
+        })
+    }
+
+    /// Let the call through.
+    pub fn accept(&mut self, charge: bool) -> Result<()> {
+        let variant = &self.logic.variant;
+        let b = if charge {
+            variant.accept_with_charge
+        } else {
+            variant.accept_without_charge
+        };
+        self.logic.decide(b, R2Outcome::Accepted { charge })
+    }
+
+    /// Turn the call away.
+    pub fn reject(&mut self, reason: R2Reject) -> Result<()> {
+        let b = self.logic.variant.rejection_signal(reason);
+        self.logic.decide(b, R2Outcome::Rejected(reason))
+    }
+
+    /// How the exchange ended, once the caller has heard the decision.
+    pub fn outcome(&self) -> Option<R2Outcome> {
+        match self.logic.state {
+            CalleeState::Done(outcome) => Some(outcome),
+            _ => None,
+        }
+    }
+
+    /// Steps of the exchange since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<R2Event> {
+        std::mem::take(&mut self.logic.events)
+    }
+
+    /// The called digits received so far.
+    pub fn dnis(&self) -> &str {
+        &self.logic.dnis
+    }
+
+    /// The variant in use.
+    pub fn variant(&self) -> &R2Variant {
+        &self.logic.variant
+    }
+}
+
+impl fmt::Debug for R2Callee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("R2Callee")
+            .field("state", &self.logic.state)
+            .field("dnis", &self.logic.dnis)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The Group I signals for a string of digits.
+fn address(digits: &str) -> Result<Vec<R2Signal>> {
+    digits
+        .chars()
+        .map(|d| {
+            R2Signal::from_digit(d).ok_or_else(|| {
+                SpanDspError::InvalidInput(format!("{digits:?} is not a string of digits"))
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,359 @@
+//! Start-up self tests of the linked spandsp and these bindings.
+//!
+//! A mislinked, miscompiled or mismatched libspandsp usually still loads;
+//! it shows up later as garbled audio or missed digits in the middle of a
+//! call. Each function here runs one subsystem through a short known-answer
+//! exercise (codecs round-trip a tone, DTMF sends and detects every digit,
+//! HDLC loops frames back, the echo canceller converges on a model hybrid)
+//! and reports what it measured, so a deployment can refuse to start
+//! instead. [`all`] runs the lot in well under a second.
+//!
+//! ```no_run
+//! let report = spandsp::self_test::all();
+//! if !report.passed() {
+//!     eprintln!("{report}");
+//!     std::process::exit(1);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::dtmf::{DtmfRx, DtmfTx};
+use crate::echo::EchoCanFlags;
+use crate::error::Result;
+use crate::g168::{G168Harness, G168Test};
+use crate::g711::{G711Mode, G711State};
+use crate::g722::{G722Decoder, G722Encoder, G722Options, G722Rate};
+use crate::g726::{G726Encoding, G726Packing, G726Rate, G726State};
+use crate::hdlc::{HdlcRx, HdlcTx};
+use crate::quality::{best_correlation, snr_db};
+
+/// Digits the DTMF check sends: the whole keypad.
+const DTMF_DIGITS: &str = "0123456789*#ABCD";
+
+/// Frame the HDLC checks loop back.
+const HDLC_FRAME: &[u8] = b"\x7e\x7d spandsp self test \xff\x00";
+
+/// Lowest SNR a G.711 round trip of a loud tone may show, in dB.
+const G711_MIN_SNR_DB: f64 = 30.0;
+
+/// Lowest correlation an ADPCM round trip of a tone may show.
+const ADPCM_MIN_CORRELATION: f64 = 0.9;
+
+/// Least improvement in residual echo adaption must bring, in dB.
+const ECHO_MIN_GAIN_DB: f32 = 6.0;
+
+// ---------------------------------------------------------------------------
+// Report
+// ---------------------------------------------------------------------------
+
+/// Part of the library a check exercises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Subsystem {
+    /// G.711, G.722 and G.726.
+    Codecs,
+    /// DTMF generation and detection.
+    Dtmf,
+    /// HDLC framing and deframing.
+    Hdlc,
+    /// Line echo cancellation.
+    Echo,
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Codecs => "codecs",
+            Self::Dtmf => "DTMF",
+            Self::Hdlc => "HDLC",
+            Self::Echo => "echo",
+        })
+    }
+}
+
+/// Outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Check {
+    /// The subsystem checked.
+    pub subsystem: Subsystem,
+    /// What was checked, e.g. `"G.722 64 kbit/s round trip"`.
+    pub name: String,
+    /// Whether the result was within limits.
+    pub passed: bool,
+    /// What was measured, or the error that stopped the check.
+    pub detail: String,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {}",
+            if self.passed { "PASS" } else { "FAIL" },
+            self.name,
+            self.detail
+        )
+    }
+}
+
+/// Results of a set of checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelfTestReport {
+    checks: Vec<Check>,
+}
+
+impl SelfTestReport {
+    /// Every check run, in order.
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// The checks that did not pass.
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|c| !c.passed)
+    }
+
+    /// `true` if every check passed.
+    pub fn passed(&self) -> bool {
+        !self.checks.is_empty() && self.checks.iter().all(|c| c.passed)
+    }
+
+    /// Run `check`, recording an error from it as a failure.
+    fn run(&mut self, subsystem: Subsystem, name: &str, check: impl FnOnce() -> Result<Outcome>) {
+        let (passed, detail) = match check() {
+            Ok(outcome) => (outcome.passed, outcome.detail),
+            Err(e) => (false, e.to_string()),
+        };
+        self.checks.push(Check {
+            subsystem,
+            name: name.to_string(),
+            passed,
+            detail,
+        });
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let passed = self.checks.iter().filter(|c| c.passed).count();
+        writeln!(
+            f,
+            "spandsp self test: {passed}/{} checks passed",
+            self.checks.len()
+        )?;
+        for check in &self.checks {
+            writeln!(f, "  [{}] {check}", check.subsystem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Extend<Check> for SelfTestReport {
+    fn extend<I: IntoIterator<Item = Check>>(&mut self, iter: I) {
+        self.checks.extend(iter);
+    }
+}
+
+/// What a check measured.
+struct Outcome {
+    passed: bool,
+    detail: String,
+}
+
+impl Outcome {
+    fn new(passed: bool, detail: impl Into<String>) -> Self {
+        Self {
+            passed,
+            detail: detail.into(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Checks
+// ---------------------------------------------------------------------------
+
+/// A tone at `freq_hz` and amplitude `amplitude`.
+fn tone(freq_hz: f64, sample_rate: f64, len: usize, amplitude: f64) -> Vec<i16> {
+    (0..len)
+        .map(|i| {
+            let phase = 2.0 * std::f64::consts::PI * freq_hz * i as f64 / sample_rate;
+            (amplitude * phase.sin()) as i16
+        })
+        .collect()
+}
+
+/// Correlation of a codec's output with its input, allowing for the
+/// codec's delay.
+fn delayed_match(original: &[i16], decoded: &[i16]) -> Outcome {
+    // Skip the codec's start-up and allow for up to 400 samples of delay.
+    let skip = original.len() / 8;
+    let window = original.len() / 4;
+    let found = decoded
+        .get(skip..)
+        .and_then(|decoded| best_correlation(&original[skip..skip + window], decoded, 400));
+    match found {
+        Some(found) => Outcome::new(
+            found.correlation > ADPCM_MIN_CORRELATION,
+            format!(
+                "correlation {:.3} at a delay of {} samples",
+                found.correlation, found.lag
+            ),
+        ),
+        None => Outcome::new(
+            false,
+            format!("decoded {} of {} samples", decoded.len(), original.len()),
+        ),
+    }
+}
+
+/// Round-trip tones through G.711, G.722 and G.726, and cross-check the
+/// G.711 tables against the C library.
+pub fn codecs() -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    report.run(Subsystem::Codecs, "G.711 conversion tables", || {
+        Ok(match crate::g711::verify_against_ffi() {
+            Ok(()) => Outcome::new(true, "every code and sample matches"),
+            Err(mismatch) => Outcome::new(false, mismatch.to_string()),
+        })
+    });
+    for mode in [G711Mode::ALaw, G711Mode::ULaw] {
+        report.run(
+            Subsystem::Codecs,
+            &format!("G.711 {mode} round trip"),
+            || {
+                let original = tone(1000.0, 8000.0, 800, 16000.0);
+                let mut encoded = vec![0u8; original.len()];
+                let n = G711State::new(mode)?.encode(&mut encoded, &original);
+                let mut decoded = vec![0i16; n];
+                G711State::new(mode)?.decode(&mut decoded, &encoded[..n]);
+                let snr = snr_db(&original, &decoded);
+                Ok(Outcome::new(
+                    n == original.len() && snr > G711_MIN_SNR_DB,
+                    format!("SNR {snr:.1} dB over {n} samples"),
+                ))
+            },
+        );
+    }
+    report.run(Subsystem::Codecs, "G.722 64 kbit/s round trip", || {
+        let original = tone(1000.0, 16000.0, 3200, 10000.0);
+        let mut encoded = vec![0u8; original.len()];
+        let n = G722Encoder::new(G722Rate::Rate64000, G722Options::empty())?
+            .encode(&mut encoded, &original);
+        let mut decoded = vec![0i16; original.len()];
+        let n = G722Decoder::new(G722Rate::Rate64000, G722Options::empty())?
+            .decode(&mut decoded, &encoded[..n]);
+        Ok(delayed_match(&original, &decoded[..n]))
+    });
+    report.run(Subsystem::Codecs, "G.726 32 kbit/s round trip", || {
+        let original = tone(1000.0, 8000.0, 3200, 10000.0);
+        let new = || G726State::new(G726Rate::Rate32000, G726Encoding::Linear, G726Packing::None);
+        let mut encoded = vec![0u8; original.len()];
+        let n = new()?.encode(&mut encoded, &original);
+        let mut decoded = vec![0i16; original.len()];
+        let n = new()?.decode(&mut decoded, &encoded[..n]);
+        Ok(delayed_match(&original, &decoded[..n]))
+    });
+    report
+}
+
+/// Send every DTMF digit and detect them again.
+pub fn dtmf() -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    report.run(Subsystem::Dtmf, "DTMF round trip", || {
+        let mut tx = DtmfTx::new()?;
+        let mut rx = DtmfRx::new()?;
+        tx.put(DTMF_DIGITS)?;
+        let mut amp = [0i16; 160];
+        loop {
+            let n = tx.generate(&mut amp);
+            if n == 0 {
+                break;
+            }
+            rx.rx(&amp[..n]);
+        }
+        // Trailing silence, so the last digit is seen to end.
+        rx.rx(&[0; 800]);
+        let detected = rx.get(DTMF_DIGITS.len() * 2);
+        Ok(Outcome::new(
+            detected == DTMF_DIGITS,
+            format!("sent {DTMF_DIGITS:?}, detected {detected:?}"),
+        ))
+    });
+    report
+}
+
+/// Loop a frame through an HDLC transmitter and receiver, with each CRC.
+pub fn hdlc() -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    for crc32 in [false, true] {
+        let name = format!("HDLC CRC-{} loopback", if crc32 { 32 } else { 16 });
+        report.run(Subsystem::Hdlc, &name, || {
+            let received = Rc::new(RefCell::new(Vec::new()));
+            let sink = received.clone();
+            let mut rx = HdlcRx::new(crc32, true, 2, move |data: &[u8], ok: bool| {
+                if !data.is_empty() {
+                    sink.borrow_mut().push((data.to_vec(), ok));
+                }
+            })?;
+            let mut tx = HdlcTx::new(crc32, 2, false, None::<fn()>)?;
+            let mut line = [0u8; 16];
+            tx.get(&mut line);
+            rx.put(&line);
+            tx.frame(HDLC_FRAME)?;
+            let mut line = vec![0u8; 2 * HDLC_FRAME.len() + 16];
+            let n = tx.get(&mut line);
+            rx.put(&line[..n]);
+            let received = received.take();
+            Ok(match received.as_slice() {
+                [(data, true)] if data == HDLC_FRAME => {
+                    Outcome::new(true, format!("{}-byte frame intact", data.len()))
+                }
+                frames => Outcome::new(
+                    false,
+                    format!(
+                        "sent one {}-byte frame, received {:?}",
+                        HDLC_FRAME.len(),
+                        frames
+                            .iter()
+                            .map(|(data, ok)| (data.len(), ok))
+                            .collect::<Vec<_>>()
+                    ),
+                ),
+            })
+        });
+    }
+    report
+}
+
+/// Check the echo canceller converges: G.168 test 2B with adaption must
+/// leave well under the echo a frozen canceller does.
+pub fn echo() -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    report.run(Subsystem::Echo, "echo canceller convergence", || {
+        let frozen = G168Harness::new(256, EchoCanFlags::empty()).run(G168Test::Test2B)?;
+        let adapting = G168Harness::new(256, EchoCanFlags::ADAPTION).run(G168Test::Test2B)?;
+        Ok(Outcome::new(
+            adapting.residual_dbm0 < frozen.residual_dbm0 - ECHO_MIN_GAIN_DB,
+            format!(
+                "residual echo {:.1} dBm0 adapting, {:.1} dBm0 frozen",
+                adapting.residual_dbm0, frozen.residual_dbm0
+            ),
+        ))
+    });
+    report
+}
+
+/// Run every subsystem's checks.
+pub fn all() -> SelfTestReport {
+    let mut report = codecs();
+    for more in [dtmf(), hdlc(), echo()] {
+        report.extend(more.checks);
+    }
+    report
+}
@@ -0,0 +1,397 @@
+//! SPRT, the Simple Packet Relay Transport of V.150.1.
+//!
+//! Once [SSE](crate::v150_1_sse) has moved a call to modem relay, the
+//! gateways terminate the modems locally and carry the data between them
+//! over SPRT: a thin transport on top of RTP-style packets with four
+//! transport channels, two of them reliable (retransmitted and windowed)
+//! and two not. [`Sprt`] wraps spandsp's implementation; the caller moves
+//! the packets and supplies a clock for the retransmission timers.
+//!
+//! ```no_run
+//! use spandsp::sprt::{Sprt, SprtChannel};
+//!
+//! let mut sprt = Sprt::new(
+//!     0,
+//!     120,
+//!     120,
+//!     |pkt: &[u8]| {
+//!         // send `pkt` to the peer gateway
+//! #       let _ = pkt;
+//!     },
+//!     |channel: SprtChannel, seq_no: u16, msg: &[u8]| {
+//!         // hand `msg` to the local modem's data pump
+//! #       let _ = (channel, seq_no, msg);
+//!     },
+//! )
+//! .unwrap();
+//! sprt.send(SprtChannel::ReliableSequenced, b"V.42 frame").unwrap();
+//! sprt.poll(10_000); // from a microsecond clock, so retransmissions go out
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// An SPRT transport channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SprtChannel {
+    /// TC0: unreliable, unsequenced; for control messages.
+    UnreliableUnsequenced,
+    /// TC1: reliable, sequenced; for the modem data.
+    ReliableSequenced,
+    /// TC2: expedited reliable, sequenced; for urgent control.
+    ExpeditedReliableSequenced,
+    /// TC3: unreliable, sequenced; for loss-tolerant data.
+    UnreliableSequenced,
+}
+
+impl SprtChannel {
+    /// All channels, by number.
+    pub const ALL: [Self; 4] = [
+        Self::UnreliableUnsequenced,
+        Self::ReliableSequenced,
+        Self::ExpeditedReliableSequenced,
+        Self::UnreliableSequenced,
+    ];
+
+    /// The transport channel ID.
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /// The channel with transport channel ID `id`.
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(id)).copied()
+    }
+
+    /// Whether messages on this channel are acknowledged and retransmitted.
+    pub fn is_reliable(self) -> bool {
+        matches!(
+            self,
+            Self::ReliableSequenced | Self::ExpeditedReliableSequenced
+        )
+    }
+}
+
+/// Local settings for one transport channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SprtChannelConfig {
+    /// Largest message payload, in bytes.
+    pub payload_bytes: u16,
+    /// Messages in flight before waiting for acknowledgement. Reliable
+    /// channels only.
+    pub window_size: u16,
+    /// Transmissions of a message before the link is given up on. Reliable
+    /// channels only.
+    pub max_tries: u16,
+}
+
+impl SprtChannelConfig {
+    /// The V.150.1 defaults for `channel`: 140 payload bytes on the
+    /// unreliable channels, 132 on the reliable ones with windows of 32
+    /// (TC1) and 8 (TC2), and 10 tries.
+    pub fn default_for(channel: SprtChannel) -> Self {
+        let (payload_bytes, window_size) = match channel {
+            SprtChannel::UnreliableUnsequenced | SprtChannel::UnreliableSequenced => (140, 0),
+            SprtChannel::ReliableSequenced => (132, 32),
+            SprtChannel::ExpeditedReliableSequenced => (132, 8),
+        };
+        Self {
+            payload_bytes,
+            window_size,
+            max_tries: 10,
+        }
+    }
+}
+
+type SprtTxCallback = Box<dyn FnMut(&[u8]) + Send>;
+type SprtRxCallback = Box<dyn FnMut(SprtChannel, u16, &[u8]) + Send>;
+
+/// What the C callbacks reach through their `user_data`.
+struct SprtShared {
+    tx: SprtTxCallback,
+    rx: SprtRxCallback,
+    /// The caller's clock, as of the last [`Sprt::poll`].
+    now: u64,
+    /// When spandsp next wants `sprt_timer_expired` called.
+    deadline: Option<u64>,
+}
+
+/// Trampoline for `sprt_tx_packet_handler_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SprtShared`.
+unsafe extern "C" fn sprt_tx_trampoline(
+    user_data: *mut c_void,
+    pkt: *const u8,
+    len: c_int,
+) -> c_int {
+    unsafe {
+        if user_data.is_null() || pkt.is_null() || len <= 0 {
+            return -1;
+        }
+        let shared = &mut *(user_data as *mut SprtShared);
+        (shared.tx)(std::slice::from_raw_parts(pkt, len as usize));
+        0
+    }
+}
+
+/// Trampoline for `sprt_rx_delivery_handler_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SprtShared`.
+unsafe extern "C" fn sprt_rx_trampoline(
+    user_data: *mut c_void,
+    channel: c_int,
+    seq_no: c_int,
+    msg: *const u8,
+    len: c_int,
+) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return -1;
+        }
+        let Some(channel) = u8::try_from(channel).ok().and_then(SprtChannel::from_id) else {
+            return -1;
+        };
+        let shared = &mut *(user_data as *mut SprtShared);
+        let msg = if msg.is_null() || len <= 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(msg, len as usize)
+        };
+        (shared.rx)(channel, seq_no as u16, msg);
+        0
+    }
+}
+
+/// Trampoline for `sprt_timer_handler_t`: records the requested deadline
+/// (zero cancels it) and answers with the current time.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SprtShared`.
+unsafe extern "C" fn sprt_timer_trampoline(
+    user_data: *mut c_void,
+    timeout: spandsp_sys::span_timestamp_t,
+) -> spandsp_sys::span_timestamp_t {
+    unsafe {
+        if user_data.is_null() {
+            return 0;
+        }
+        let shared = &mut *(user_data as *mut SprtShared);
+        shared.deadline = (timeout > 0).then_some(timeout as u64);
+        shared.now as spandsp_sys::span_timestamp_t
+    }
+}
+
+/// Trampoline for `sprt_status_handler_t`; status changes are not
+/// reported, so this only acknowledges.
+unsafe extern "C" fn sprt_status_trampoline(_user_data: *mut c_void, _status: c_int) -> c_int {
+    0
+}
+
+/// RAII wrapper around `sprt_state_t`.
+///
+/// Created via `Sprt::new()`, which calls `sprt_init(NULL, ...)` with the
+/// default channel parameters. Freed on drop via `sprt_free`.
+///
+/// Times are microseconds on the caller's monotonic clock, handed in through
+/// [`poll`](Self::poll).
+pub struct Sprt {
+    ptr: NonNull<spandsp_sys::sprt_state_t>,
+    shared: Box<SprtShared>,
+}
+
+impl Sprt {
+    /// Create an SPRT endpoint for the subsession `subsession_id`, sending
+    /// and expecting the given RTP payload types (dynamic, below 128).
+    ///
+    /// `tx_packet` is called with each packet to send; `deliver` with each
+    /// message received, its channel and its sequence number, in order on
+    /// the sequenced channels.
+    pub fn new<T, R>(
+        subsession_id: u8,
+        rx_payload_type: u8,
+        tx_payload_type: u8,
+        tx_packet: T,
+        deliver: R,
+    ) -> Result<Self>
+    where
+        T: FnMut(&[u8]) + Send + 'static,
+        R: FnMut(SprtChannel, u16, &[u8]) + Send + 'static,
+    {
+        if rx_payload_type > 127 || tx_payload_type > 127 {
+            return Err(SpanDspError::InvalidInput(format!(
+                "RTP payload types must be below 128, got {rx_payload_type}/{tx_payload_type}"
+            )));
+        }
+        let mut shared = Box::new(SprtShared {
+            tx: Box::new(tx_packet),
+            rx: Box::new(deliver),
+            now: 0,
+            deadline: None,
+        });
+        let user_data = &mut *shared as *mut SprtShared as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::sprt_init(
+                std::ptr::null_mut(),
+                subsession_id,
+                rx_payload_type,
+                tx_payload_type,
+                std::ptr::null_mut(),
+                Some(sprt_tx_trampoline),
+                user_data,
+                Some(sprt_rx_trampoline),
+                user_data,
+                Some(sprt_timer_trampoline),
+                user_data,
+                Some(sprt_status_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr, shared })
+    }
+
+    /// Apply local settings to `channel`. The window and try count are
+    /// only applied to the reliable channels.
+    pub fn configure_channel(
+        &mut self,
+        channel: SprtChannel,
+        config: SprtChannelConfig,
+    ) -> Result<()> {
+        let s = self.ptr.as_ptr();
+        let id = c_int::from(channel.id());
+        check(unsafe {
+            spandsp_sys::sprt_set_local_tc_payload_bytes(s, id, c_int::from(config.payload_bytes))
+        })?;
+        if channel.is_reliable() {
+            check(unsafe {
+                spandsp_sys::sprt_set_local_tc_windows_size(s, id, c_int::from(config.window_size))
+            })?;
+            check(unsafe {
+                spandsp_sys::sprt_set_local_tc_max_tries(s, id, c_int::from(config.max_tries))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The local settings of `channel`; the window and try count read as
+    /// zero on the unreliable channels.
+    pub fn channel_config(&self, channel: SprtChannel) -> SprtChannelConfig {
+        let s = self.ptr.as_ptr();
+        let id = c_int::from(channel.id());
+        let get = |value: c_int| u16::try_from(value).unwrap_or(0);
+        unsafe {
+            SprtChannelConfig {
+                payload_bytes: get(spandsp_sys::sprt_get_local_tc_payload_bytes(s, id)),
+                window_size: match channel.is_reliable() {
+                    true => get(spandsp_sys::sprt_get_local_tc_windows_size(s, id)),
+                    false => 0,
+                },
+                max_tries: match channel.is_reliable() {
+                    true => get(spandsp_sys::sprt_get_local_tc_max_tries(s, id)),
+                    false => 0,
+                },
+            }
+        }
+    }
+
+    /// Send a message on `channel`. It must fit the channel's
+    /// [`payload_bytes`](SprtChannelConfig::payload_bytes).
+    pub fn send(&mut self, channel: SprtChannel, msg: &[u8]) -> Result<()> {
+        let len = msg.len().min(c_int::MAX as usize) as c_int;
+        check(unsafe {
+            spandsp_sys::sprt_tx(
+                self.ptr.as_ptr(),
+                c_int::from(channel.id()),
+                msg.as_ptr(),
+                len,
+            )
+        })
+    }
+
+    /// Handle a packet received from the peer, delivering any messages it
+    /// completes and sending any acknowledgements it calls for.
+    pub fn rx_packet(&mut self, pkt: &[u8]) -> Result<()> {
+        let len = pkt.len().min(c_int::MAX as usize) as c_int;
+        check(unsafe { spandsp_sys::sprt_rx_packet(self.ptr.as_ptr(), pkt.as_ptr(), len) })
+    }
+
+    /// Advance the clock to `now`, running any retransmission or
+    /// acknowledgement timers that have come due.
+    pub fn poll(&mut self, now: u64) {
+        self.shared.now = now;
+        if self.shared.deadline.is_some_and(|deadline| deadline <= now) {
+            self.shared.deadline = None;
+            unsafe {
+                spandsp_sys::sprt_timer_expired(
+                    self.ptr.as_ptr(),
+                    now as spandsp_sys::span_timestamp_t,
+                );
+            }
+        }
+    }
+
+    /// When [`poll`](Self::poll) next has work to do, if ever.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.shared.deadline
+    }
+
+    /// Tell the peer to hold off sending on a reliable `channel`, e.g.
+    /// while the local modem's buffers are full.
+    pub fn set_local_busy(&mut self, channel: SprtChannel, busy: bool) {
+        unsafe {
+            spandsp_sys::sprt_set_local_busy(self.ptr.as_ptr(), c_int::from(channel.id()), busy);
+        }
+    }
+
+    /// Whether the peer has asked us to hold off on `channel`.
+    pub fn far_busy(&self, channel: SprtChannel) -> bool {
+        unsafe {
+            spandsp_sys::sprt_get_far_busy_status(self.ptr.as_ptr(), c_int::from(channel.id()))
+        }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::sprt_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+fn check(rc: c_int) -> Result<()> {
+    if rc < 0 {
+        Err(SpanDspError::ErrorCode(rc))
+    } else {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Sprt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sprt")
+            .field("deadline", &self.shared.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Sprt {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::sprt_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: Sprt wraps a SpanDSP sprt_state_t that is only accessed through
+// &self/&mut self methods, and its callbacks are `Send`.
+unsafe impl Send for Sprt {}
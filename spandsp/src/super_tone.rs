@@ -0,0 +1,611 @@
+//! Supervisory tones with arbitrary cadences.
+//!
+//! [`ToneGenDescriptor`](crate::tone_generate::ToneGenDescriptor) covers
+//! tones with at most two on/off pairs. Many national tone plans need more:
+//! a burst of three short beeps then a long pause, a tone that changes
+//! frequency partway through its cadence, or a pattern played a few times
+//! before settling into another.
+//!
+//! - [`SuperToneTx`] plays a tree of [`SuperToneStep`]s: tone or silence
+//!   steps, and repeats of nested steps. spandsp builds these trees by
+//!   linking its private step structures, so the tree is walked here and
+//!   each step played with a [`ToneGenerator`].
+//! - [`SuperToneRx`] wraps spandsp's `super_tone_rx_state_t`, which matches
+//!   incoming audio against a set of cadences described by
+//!   [`ToneElement`]s and reports which one, if any, is present, either
+//!   when polled or as [`SuperToneEvent`]s to a closure.
+//!
+//! ```no_run
+//! use spandsp::super_tone::{SuperToneStep, SuperToneTx};
+//! use spandsp::tone_generate::ToneFreq;
+//!
+//! // UK call waiting: 400 Hz, 100 ms on, 2.5 s off, then 100 on, 100 off,
+//! // 100 on and 2.5 s off.
+//! let beep = |ms| SuperToneStep::tone(ToneFreq::new(400, -20), ToneFreq::NONE, ms);
+//! let mut tx = SuperToneTx::new(vec![
+//!     beep(100),
+//!     SuperToneStep::silence(2500),
+//!     SuperToneStep::repeat(0, vec![
+//!         beep(100),
+//!         SuperToneStep::silence(100),
+//!         beep(100),
+//!         SuperToneStep::silence(2500),
+//!     ]),
+//! ])
+//! .unwrap();
+//! let mut amp = [0i16; 160];
+//! tx.generate(&mut amp);
+//! ```
+
+use std::ffi::c_void;
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::tone_generate::{ToneCadence, ToneFreq, ToneGenDescriptor, ToneGenerator};
+
+/// Samples per millisecond at 8 kHz.
+const SAMPLES_PER_MS: usize = 8;
+
+// ---------------------------------------------------------------------------
+// Generation
+// ---------------------------------------------------------------------------
+
+/// One step of a supervisory tone.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SuperToneStep {
+    /// Up to two tones, or silence if both are [`ToneFreq::NONE`].
+    Tone {
+        /// First tone component.
+        tone1: ToneFreq,
+        /// Second tone component.
+        tone2: ToneFreq,
+        /// Duration in milliseconds, or 0 to play forever.
+        duration_ms: u32,
+    },
+    /// Nested steps played in turn.
+    Repeat {
+        /// Times to play `steps`, or 0 to repeat forever.
+        cycles: u32,
+        /// The steps to play.
+        steps: Vec<SuperToneStep>,
+    },
+}
+
+impl SuperToneStep {
+    /// A step of `tone1` and `tone2`.
+    pub fn tone(tone1: ToneFreq, tone2: ToneFreq, duration_ms: u32) -> Self {
+        Self::Tone {
+            tone1,
+            tone2,
+            duration_ms,
+        }
+    }
+
+    /// A step of silence.
+    pub fn silence(duration_ms: u32) -> Self {
+        Self::tone(ToneFreq::NONE, ToneFreq::NONE, duration_ms)
+    }
+
+    /// `steps` played `cycles` times, or forever if `cycles` is 0.
+    pub fn repeat(cycles: u32, steps: Vec<SuperToneStep>) -> Self {
+        Self::Repeat { cycles, steps }
+    }
+
+    /// Whether playing this step produces any samples, tone or silence.
+    fn plays(&self) -> bool {
+        match self {
+            Self::Tone { .. } => true,
+            Self::Repeat { steps, .. } => steps.iter().any(Self::plays),
+        }
+    }
+
+    /// Check that every repeat has something to play, so the walk always
+    /// makes progress.
+    fn validate(steps: &[Self]) -> Result<()> {
+        if !steps.iter().any(Self::plays) {
+            return Err(SpanDspError::InvalidInput(
+                "super tone has no tone or silence steps".into(),
+            ));
+        }
+        for step in steps {
+            if let Self::Repeat { steps, .. } = step {
+                Self::validate(steps)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Position in one level of the step tree.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// The next step to play at this level.
+    index: usize,
+    /// Passes through this level still to play, including the current
+    /// one, or `None` for forever.
+    cycles_left: Option<u32>,
+}
+
+/// The tone or silence step being played.
+struct Playing {
+    /// `None` for silence.
+    generator: Option<ToneGenerator>,
+    /// Samples left, or `None` for forever.
+    remaining: Option<usize>,
+}
+
+/// Plays a tree of [`SuperToneStep`]s.
+pub struct SuperToneTx {
+    steps: Vec<SuperToneStep>,
+    /// One frame per nesting level; the last is the innermost.
+    stack: Vec<Frame>,
+    playing: Option<Playing>,
+}
+
+impl SuperToneTx {
+    /// Play `steps` once, in order.
+    pub fn new(steps: Vec<SuperToneStep>) -> Result<Self> {
+        SuperToneStep::validate(&steps)?;
+        let mut tx = Self {
+            steps,
+            stack: Vec::new(),
+            playing: None,
+        };
+        tx.restart();
+        Ok(tx)
+    }
+
+    /// The steps being played.
+    pub fn steps(&self) -> &[SuperToneStep] {
+        &self.steps
+    }
+
+    /// Start again from the first step.
+    pub fn restart(&mut self) {
+        self.stack.clear();
+        self.stack.push(Frame {
+            index: 0,
+            cycles_left: Some(1),
+        });
+        self.playing = None;
+    }
+
+    /// Whether every step has been played.
+    pub fn is_finished(&self) -> bool {
+        self.stack.is_empty() && self.playing.is_none()
+    }
+
+    /// Generate samples into `amp`.
+    ///
+    /// Returns the number of samples generated. Fewer than `amp.len()`
+    /// means the tone has finished.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let mut done = 0;
+        while done < amp.len() {
+            if self.playing.is_none() {
+                match self.next_tone() {
+                    Some(playing) => self.playing = Some(playing),
+                    None => break,
+                }
+            }
+            let Some(playing) = self.playing.as_mut() else {
+                break;
+            };
+            let n = playing
+                .remaining
+                .map_or(amp.len() - done, |left| left.min(amp.len() - done));
+            let out = &mut amp[done..done + n];
+            match &mut playing.generator {
+                Some(generator) => {
+                    generator.generate(out);
+                }
+                None => out.fill(0),
+            }
+            done += n;
+            if let Some(left) = &mut playing.remaining {
+                *left -= n;
+                if *left == 0 {
+                    self.playing = None;
+                }
+            }
+        }
+        done
+    }
+
+    /// The steps at nesting level `depth`.
+    fn level(&self, depth: usize) -> &[SuperToneStep] {
+        let mut steps = &self.steps[..];
+        for frame in &self.stack[..depth] {
+            match &steps[frame.index] {
+                SuperToneStep::Repeat { steps: nested, .. } => steps = nested,
+                SuperToneStep::Tone { .. } => unreachable!("only repeats nest"),
+            }
+        }
+        steps
+    }
+
+    /// Walk to the next tone or silence step and start it.
+    fn next_tone(&mut self) -> Option<Playing> {
+        loop {
+            let depth = self.stack.len().checked_sub(1)?;
+            let index = self.stack[depth].index;
+            match self.level(depth).get(index) {
+                Some(&SuperToneStep::Tone {
+                    tone1,
+                    tone2,
+                    duration_ms,
+                }) => {
+                    self.stack[depth].index += 1;
+                    return Some(Playing {
+                        generator: continuous(tone1, tone2),
+                        remaining: (duration_ms > 0).then(|| duration_ms as usize * SAMPLES_PER_MS),
+                    });
+                }
+                Some(&SuperToneStep::Repeat { cycles, .. }) => {
+                    // Validation guarantees something inside plays.
+                    self.stack.push(Frame {
+                        index: 0,
+                        cycles_left: (cycles > 0).then_some(cycles),
+                    });
+                }
+                None => {
+                    // The end of this level: go round again or climb out.
+                    let frame = &mut self.stack[depth];
+                    match frame.cycles_left {
+                        Some(0 | 1) => {
+                            self.stack.pop();
+                            if let Some(parent) = self.stack.last_mut() {
+                                parent.index += 1;
+                            }
+                        }
+                        Some(n) => {
+                            frame.cycles_left = Some(n - 1);
+                            frame.index = 0;
+                        }
+                        None => frame.index = 0,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A generator playing `tone1` and `tone2` until stopped, or `None` for
+/// silence. A generator spandsp cannot allocate plays as silence too.
+fn continuous(tone1: ToneFreq, tone2: ToneFreq) -> Option<ToneGenerator> {
+    if tone1.frequency == 0 && tone2.frequency == 0 {
+        return None;
+    }
+    let descriptor =
+        ToneGenDescriptor::new(tone1, tone2, ToneCadence::continuous(1000), true).ok()?;
+    ToneGenerator::new(&descriptor).ok()
+}
+
+impl fmt::Debug for SuperToneTx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SuperToneTx")
+            .field("steps", &self.steps.len())
+            .field("depth", &self.stack.len())
+            .field("finished", &self.is_finished())
+            .finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Detection
+// ---------------------------------------------------------------------------
+
+/// One segment of a cadence to detect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ToneElement {
+    /// First frequency in Hz; 0 with `tone2_hz` 0 for silence.
+    pub tone1_hz: i32,
+    /// Second frequency in Hz, or 0 for a single tone.
+    pub tone2_hz: i32,
+    /// Shortest duration that matches, in milliseconds.
+    pub min_ms: u32,
+    /// Longest duration that matches, in milliseconds, or 0 for no limit.
+    pub max_ms: u32,
+}
+
+impl ToneElement {
+    /// A segment of `tone1_hz` and `tone2_hz` lasting `min_ms` to `max_ms`.
+    pub const fn new(tone1_hz: i32, tone2_hz: i32, min_ms: u32, max_ms: u32) -> Self {
+        Self {
+            tone1_hz,
+            tone2_hz,
+            min_ms,
+            max_ms,
+        }
+    }
+
+    /// A segment of silence lasting `min_ms` to `max_ms`.
+    pub const fn silence(min_ms: u32, max_ms: u32) -> Self {
+        Self::new(0, 0, min_ms, max_ms)
+    }
+}
+
+/// The set of cadences a [`SuperToneRx`] listens for.
+///
+/// Wraps `super_tone_rx_descriptor_t`.
+pub struct SuperToneRxDescriptor {
+    ptr: NonNull<spandsp_sys::super_tone_rx_descriptor_t>,
+    tones: usize,
+}
+
+impl SuperToneRxDescriptor {
+    /// An empty set of cadences.
+    pub fn new() -> Result<Self> {
+        let ptr = unsafe { spandsp_sys::super_tone_rx_make_descriptor(std::ptr::null_mut()) };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self { ptr, tones: 0 })
+    }
+
+    /// Add a cadence made of `elements` in order, returning the index
+    /// [`SuperToneRx`] reports it by.
+    pub fn add_tone(&mut self, elements: &[ToneElement]) -> Result<usize> {
+        if elements.is_empty() {
+            return Err(SpanDspError::InvalidInput(
+                "a detected tone needs at least one element".into(),
+            ));
+        }
+        for element in elements {
+            if element.max_ms != 0 && element.max_ms < element.min_ms {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "tone element of {} ms at most is shorter than its {} ms minimum",
+                    element.max_ms, element.min_ms
+                )));
+            }
+        }
+        let tone = unsafe { spandsp_sys::super_tone_rx_add_tone(self.ptr.as_ptr()) };
+        if tone < 0 {
+            return Err(SpanDspError::ErrorCode(tone));
+        }
+        for element in elements {
+            let rc = unsafe {
+                spandsp_sys::super_tone_rx_add_element(
+                    self.ptr.as_ptr(),
+                    tone,
+                    element.tone1_hz as c_int,
+                    element.tone2_hz as c_int,
+                    element.min_ms.min(c_int::MAX as u32) as c_int,
+                    element.max_ms.min(c_int::MAX as u32) as c_int,
+                )
+            };
+            if rc < 0 {
+                return Err(SpanDspError::ErrorCode(rc));
+            }
+        }
+        self.tones += 1;
+        Ok(tone as usize)
+    }
+
+    /// Number of cadences added.
+    pub fn tones(&self) -> usize {
+        self.tones
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::super_tone_rx_descriptor_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for SuperToneRxDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SuperToneRxDescriptor")
+            .field("tones", &self.tones)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SuperToneRxDescriptor {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::super_tone_rx_free_descriptor(self.ptr.as_ptr());
+        }
+    }
+}
+
+/// A change in the cadence a [`SuperToneRx`] hears.
+///
+/// `at` is the number of samples the detector had been given when spandsp
+/// reported the change, to within a millisecond. spandsp only recognises a
+/// cadence once its elements have been heard for their minimum durations,
+/// so a tone starts some way before its `Started` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SuperToneEvent {
+    /// The cadence with index `tone` was recognised.
+    Started {
+        /// Index returned by [`SuperToneRxDescriptor::add_tone`].
+        tone: usize,
+        /// Sample position of the report.
+        at: u64,
+    },
+    /// The cadence with index `tone` is no longer heard.
+    Ended {
+        /// Index returned by [`SuperToneRxDescriptor::add_tone`].
+        tone: usize,
+        /// Sample position of the report.
+        at: u64,
+    },
+}
+
+impl SuperToneEvent {
+    /// The cadence this event is about.
+    pub fn tone(&self) -> usize {
+        match *self {
+            SuperToneEvent::Started { tone, .. } | SuperToneEvent::Ended { tone, .. } => tone,
+        }
+    }
+
+    /// Sample position of the report.
+    pub fn at(&self) -> u64 {
+        match *self {
+            SuperToneEvent::Started { at, .. } | SuperToneEvent::Ended { at, .. } => at,
+        }
+    }
+}
+
+type SuperToneCallback = Box<dyn FnMut(SuperToneEvent)>;
+
+/// Detections reported by spandsp, written by the callback.
+#[derive(Default)]
+struct Reports {
+    current: Option<usize>,
+    changes: Vec<Option<usize>>,
+    /// Samples given to spandsp, up to the end of the slice being processed.
+    position: u64,
+    callback: Option<SuperToneCallback>,
+}
+
+impl Reports {
+    fn change(&mut self, tone: Option<usize>) {
+        let previous = std::mem::replace(&mut self.current, tone);
+        let Some(callback) = self.callback.as_mut() else {
+            self.changes.push(tone);
+            return;
+        };
+        let at = self.position;
+        if let Some(tone) = previous {
+            callback(SuperToneEvent::Ended { tone, at });
+        }
+        if let Some(tone) = tone {
+            callback(SuperToneEvent::Started { tone, at });
+        }
+    }
+}
+
+/// Trampoline for `tone_report_func_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Reports`.
+unsafe extern "C" fn report_trampoline(
+    user_data: *mut c_void,
+    code: c_int,
+    _level: c_int,
+    _delay: c_int,
+) {
+    unsafe {
+        if user_data.is_null() {
+            return;
+        }
+        let reports = &mut *(user_data as *mut Reports);
+        let tone = usize::try_from(code).ok();
+        if tone != reports.current {
+            reports.change(tone);
+        }
+    }
+}
+
+/// Supervisory tone detector wrapping `super_tone_rx_state_t`.
+pub struct SuperToneRx {
+    ptr: NonNull<spandsp_sys::super_tone_rx_state_t>,
+    /// The state points at the descriptor, so it lives as long.
+    descriptor: SuperToneRxDescriptor,
+    reports: Box<Reports>,
+}
+
+impl SuperToneRx {
+    /// A detector for the cadences in `descriptor`.
+    pub fn new(descriptor: SuperToneRxDescriptor) -> Result<Self> {
+        Self::with_reports(descriptor, Box::default())
+    }
+
+    /// A detector for the cadences in `descriptor`, calling `callback` as
+    /// each one starts and ends. A change straight from one cadence to
+    /// another gives an `Ended` then a `Started` event. Changes are not
+    /// also queued for [`take_changes`](Self::take_changes).
+    pub fn with_callback<F>(descriptor: SuperToneRxDescriptor, callback: F) -> Result<Self>
+    where
+        F: FnMut(SuperToneEvent) + 'static,
+    {
+        let reports = Box::new(Reports {
+            callback: Some(Box::new(callback)),
+            ..Default::default()
+        });
+        Self::with_reports(descriptor, reports)
+    }
+
+    fn with_reports(descriptor: SuperToneRxDescriptor, mut reports: Box<Reports>) -> Result<Self> {
+        let ptr = unsafe {
+            spandsp_sys::super_tone_rx_init(
+                std::ptr::null_mut(),
+                descriptor.as_ptr(),
+                Some(report_trampoline),
+                &mut *reports as *mut Reports as *mut c_void,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            descriptor,
+            reports,
+        })
+    }
+
+    /// Process received audio. Returns the number of samples processed.
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        // Fed a millisecond at a time, so reports carry their position.
+        let mut processed = 0;
+        for slice in amp.chunks(SAMPLES_PER_MS) {
+            self.reports.position += slice.len() as u64;
+            let n = unsafe {
+                spandsp_sys::super_tone_rx(self.ptr.as_ptr(), slice.as_ptr(), slice.len() as c_int)
+            };
+            processed += n.max(0) as usize;
+        }
+        processed
+    }
+
+    /// Samples processed since the detector was created.
+    pub fn position(&self) -> u64 {
+        self.reports.position
+    }
+
+    /// The index of the cadence being heard, if any.
+    pub fn current(&self) -> Option<usize> {
+        self.reports.current
+    }
+
+    /// Changes in [`current`](Self::current) since the last call, oldest
+    /// first; `None` marks a tone ending. Always empty for a detector made
+    /// with [`with_callback`](Self::with_callback).
+    pub fn take_changes(&mut self) -> Vec<Option<usize>> {
+        std::mem::take(&mut self.reports.changes)
+    }
+
+    /// The cadences being listened for.
+    pub fn descriptor(&self) -> &SuperToneRxDescriptor {
+        &self.descriptor
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::super_tone_rx_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for SuperToneRx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SuperToneRx")
+            .field("tones", &self.descriptor.tones())
+            .field("current", &self.reports.current)
+            .field("has_callback", &self.reports.callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SuperToneRx {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::super_tone_rx_free(self.ptr.as_ptr());
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! FAX and T.38 sessions that can be shared between threads.
+//!
+//! [`FaxState`] and [`T38Terminal`] are `Send` but not `Sync`: spandsp's
+//! state is not thread-safe, so every call into a session has to be
+//! serialised. In a SIP server the media thread drives the session every
+//! 20 ms while signalling threads configure it, snapshot it or read its
+//! quality report, and each application ends up rebuilding the same locking.
+//! [`SyncFaxSession`] and [`SyncT38Session`] do it once.
+//!
+//! Each wraps its session in a mutex and exposes two kinds of call:
+//!
+//! - The media path ([`SyncFaxSession::process_audio`],
+//!   [`SyncT38Session::tick`], [`SyncT38Session::rx_packet`]) takes the lock
+//!   once per frame or packet, for exactly one pass through spandsp. The
+//!   `try_` variants never block, so a real-time thread can skip a frame
+//!   rather than wait behind a control call.
+//! - Control calls ([`with`](SyncFaxSession::with),
+//!   [`with_t30`](SyncFaxSession::with_t30) and the shortcuts built on them)
+//!   hold the lock for one closure. T.30 and T.38 core handles borrow the
+//!   session they came from, so they cannot be returned from the closure
+//!   and outlive the lock:
+//!
+//! ```compile_fail
+//! # use spandsp::sync_session::SyncT38Session;
+//! # fn escape(session: &SyncT38Session) {
+//! let core = session.with(|terminal| terminal.get_t38_core_state().unwrap());
+//! # }
+//! ```
+//!
+//! Session handlers (received pages, ECM events, interrupts, tone
+//! detection, T.38 packet transmission) run on the media path with the lock
+//! held. They must not call back into the same session, or they deadlock.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use spandsp::fax::FaxState;
+//! use spandsp::sync_session::SyncFaxSession;
+//!
+//! let session = Arc::new(SyncFaxSession::new(FaxState::new(true).unwrap()));
+//!
+//! let media = Arc::clone(&session);
+//! std::thread::spawn(move || {
+//!     let mut rx = [0i16; 160];
+//!     let mut tx = [0i16; 160];
+//!     media.process_audio(&mut rx, &mut tx);
+//! });
+//!
+//! session
+//!     .with_t30(|t30| t30.set_tx_ident("+1 555 0100"))
+//!     .unwrap()
+//!     .unwrap();
+//! println!("{:?}", session.quality_report());
+//! ```
+
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use crate::error::Result;
+use crate::fax::FaxState;
+use crate::t30::{FaxQualityReport, T30Snapshot, T30State};
+use crate::t38_core::T38Core;
+use crate::t38_terminal::{T38Pacing, T38Terminal};
+
+/// Lock `mutex`, ignoring poisoning.
+///
+/// spandsp's state is only touched inside single calls into C, which cannot
+/// unwind, so a closure that panicked between calls left it consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Lock `mutex` if nobody holds it, ignoring poisoning like [`lock`].
+fn try_lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FAX
+// ---------------------------------------------------------------------------
+
+/// A [`FaxState`] shared between a media thread and control threads.
+pub struct SyncFaxSession {
+    fax: Mutex<FaxState>,
+}
+
+impl SyncFaxSession {
+    /// Share `fax`, e.g. through an `Arc`.
+    pub fn new(fax: FaxState) -> Self {
+        Self {
+            fax: Mutex::new(fax),
+        }
+    }
+
+    /// Feed a frame of line audio and generate the frame to send back,
+    /// under one lock.
+    ///
+    /// Returns the number of samples generated; the rest of `tx` is left as
+    /// it was. See [`FaxState::rx`] and [`FaxState::tx`].
+    pub fn process_audio(&self, rx: &mut [i16], tx: &mut [i16]) -> usize {
+        Self::audio(&mut lock(&self.fax), rx, tx)
+    }
+
+    /// Like [`process_audio`](Self::process_audio), but returns `None`
+    /// instead of waiting if a control call holds the lock.
+    pub fn try_process_audio(&self, rx: &mut [i16], tx: &mut [i16]) -> Option<usize> {
+        try_lock(&self.fax).map(|mut fax| Self::audio(&mut fax, rx, tx))
+    }
+
+    fn audio(fax: &mut FaxState, rx: &mut [i16], tx: &mut [i16]) -> usize {
+        fax.rx(rx);
+        fax.tx(tx)
+    }
+
+    /// Run `f` on the session with the lock held.
+    pub fn with<R>(&self, f: impl FnOnce(&mut FaxState) -> R) -> R {
+        f(&mut lock(&self.fax))
+    }
+
+    /// Run `f` on the session's T.30 engine with the lock held.
+    pub fn with_t30<R>(&self, f: impl FnOnce(&mut T30State<'_>) -> R) -> Result<R> {
+        self.with(|fax| Ok(f(&mut fax.get_t30_state()?)))
+    }
+
+    /// See [`FaxState::snapshot`].
+    pub fn snapshot(&self) -> Result<T30Snapshot> {
+        self.with(|fax| fax.snapshot())
+    }
+
+    /// See [`FaxState::quality_report`].
+    pub fn quality_report(&self) -> FaxQualityReport {
+        self.with(|fax| fax.quality_report())
+    }
+
+    /// Whether a T.30 call is in progress.
+    pub fn call_active(&self) -> bool {
+        self.with_t30(|t30| t30.call_active()).unwrap_or(false)
+    }
+
+    /// Take the session back, e.g. once the call has ended.
+    pub fn into_inner(self) -> FaxState {
+        self.fax
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl From<FaxState> for SyncFaxSession {
+    fn from(fax: FaxState) -> Self {
+        Self::new(fax)
+    }
+}
+
+impl fmt::Debug for SyncFaxSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SyncFaxSession");
+        match try_lock(&self.fax) {
+            Some(fax) => d.field("fax", &*fax),
+            None => d.field("fax", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// T.38
+// ---------------------------------------------------------------------------
+
+/// A [`T38Terminal`] shared between a packet thread and control threads.
+pub struct SyncT38Session {
+    terminal: Mutex<T38Terminal>,
+}
+
+impl SyncT38Session {
+    /// Share `terminal`, e.g. through an `Arc`.
+    ///
+    /// The terminal's transmit handler is called from whichever thread
+    /// calls [`tick`](Self::tick) or [`rx_packet`](Self::rx_packet).
+    pub fn new(terminal: T38Terminal) -> Self {
+        Self {
+            terminal: Mutex::new(terminal),
+        }
+    }
+
+    /// Drive the terminal's timer under one lock. See [`T38Terminal::tick`].
+    pub fn tick(&self, samples: i32) -> T38Pacing {
+        lock(&self.terminal).tick(samples)
+    }
+
+    /// Like [`tick`](Self::tick), but returns `None` instead of waiting if
+    /// a control call holds the lock.
+    pub fn try_tick(&self, samples: i32) -> Option<T38Pacing> {
+        try_lock(&self.terminal).map(|mut terminal| terminal.tick(samples))
+    }
+
+    /// Feed one received IFP packet under one lock. See
+    /// [`T38Core::rx_ifp_packet`].
+    pub fn rx_packet(&self, buf: &[u8], seq_no: u16) -> Result<()> {
+        self.with_t38_core(|core| core.rx_ifp_packet(buf, seq_no))?
+    }
+
+    /// Run `f` on the terminal with the lock held.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T38Terminal) -> R) -> R {
+        f(&mut lock(&self.terminal))
+    }
+
+    /// Run `f` on the terminal's T.30 engine with the lock held.
+    pub fn with_t30<R>(&self, f: impl FnOnce(&mut T30State<'_>) -> R) -> Result<R> {
+        self.with(|terminal| Ok(f(&mut terminal.get_t30_state()?)))
+    }
+
+    /// Run `f` on the terminal's T.38 core with the lock held.
+    pub fn with_t38_core<R>(&self, f: impl FnOnce(&mut T38Core<'_>) -> R) -> Result<R> {
+        self.with(|terminal| Ok(f(&mut terminal.get_t38_core_state()?)))
+    }
+
+    /// See [`T38Terminal::snapshot`].
+    pub fn snapshot(&self) -> Result<T30Snapshot> {
+        self.with(|terminal| terminal.snapshot())
+    }
+
+    /// See [`T38Terminal::quality_report`].
+    pub fn quality_report(&self) -> FaxQualityReport {
+        self.with(|terminal| terminal.quality_report())
+    }
+
+    /// Whether a T.30 call is in progress.
+    pub fn call_active(&self) -> bool {
+        self.with_t30(|t30| t30.call_active()).unwrap_or(false)
+    }
+
+    /// Take the terminal back, e.g. once the call has ended.
+    pub fn into_inner(self) -> T38Terminal {
+        self.terminal
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl From<T38Terminal> for SyncT38Session {
+    fn from(terminal: T38Terminal) -> Self {
+        Self::new(terminal)
+    }
+}
+
+impl fmt::Debug for SyncT38Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SyncT38Session");
+        match try_lock(&self.terminal) {
+            Some(terminal) => d.field("terminal", &*terminal),
+            None => d.field("terminal", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
@@ -1,10 +1,16 @@
 //! Safe wrapper around the T.30 FAX protocol engine.
 
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::fmt;
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::PathBuf;
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError, T30Error};
+use crate::fax_tiff::AppendCursor;
+use crate::t4::{PageHeader, T4Stats};
+use crate::t37::FaxPage;
 
 bitflags::bitflags! {
     /// Supported modem types for T.30 negotiation.
@@ -36,23 +42,84 @@ impl fmt::Display for T30ModemSupport {
     }
 }
 
+impl T30ModemSupport {
+    /// The modems the linked spandsp can run. V.34 is only there when it
+    /// was built with it; see [`capabilities`](crate::version::capabilities).
+    pub fn available() -> Self {
+        let modems = Self::V27TER | Self::V29 | Self::V17 | Self::IAF;
+        if crate::version::capabilities().v34 {
+            modems | Self::V34HDX
+        } else {
+            modems
+        }
+    }
+
+    /// Fail if any of these modems are missing from the linked spandsp.
+    pub(crate) fn check_available(self) -> Result<()> {
+        let missing = self - Self::available();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(SpanDspError::InvalidInput(format!(
+                "{missing} not compiled into the linked spandsp"
+            )))
+        }
+    }
+
+    /// Top rate of each modem family, fastest first.
+    const TOP_RATES: [(Self, u32); 4] = [
+        (Self::V34HDX, 33_600),
+        (Self::V17, 14_400),
+        (Self::V29, 9_600),
+        (Self::V27TER, 4_800),
+    ];
+
+    /// Highest bit rate these modems can reach, in bits/s; 0 if none.
+    pub fn max_bit_rate(self) -> u32 {
+        Self::TOP_RATES
+            .iter()
+            .find(|(modem, _)| self.contains(*modem))
+            .map_or(0, |&(_, rate)| rate)
+    }
+
+    /// These modems without the ones whose top rate is above `bit_rate`.
+    ///
+    /// A modem cannot be told to stop short of its own top rate, so a cap
+    /// between two top rates lands on the slower one: capping at 12000
+    /// drops V.17 and leaves V.29 at 9600. IAF is kept.
+    pub fn capped_at(self, bit_rate: u32) -> Self {
+        Self::TOP_RATES
+            .iter()
+            .filter(|&&(_, rate)| rate > bit_rate)
+            .fold(self, |modems, &(modem, _)| modems - modem)
+    }
+}
+
 /// T.30 FAX protocol state machine.
 ///
 /// This is typically obtained via `FaxState::get_t30_state()` or
-/// `T38Terminal::get_t30_state()` rather than created directly.
-pub struct T30State {
+/// `T38Terminal::get_t30_state()` rather than created directly. `'a` is the
+/// mutable borrow of that owner, as for [`T38Core`](crate::t38_core::T38Core).
+pub struct T30State<'a> {
     inner: NonNull<spandsp_sys::t30_state_t>,
     owned: bool,
+    _owner: PhantomData<&'a mut ()>,
 }
 
-impl T30State {
+impl<'a> T30State<'a> {
     /// Wrap an existing pointer obtained from another spandsp object.
     ///
     /// # Safety
-    /// The pointer must be valid. `owned` controls whether `t30_free` is called on drop.
+    /// The pointer must be valid for `'a`, and nothing else may drive the
+    /// engine while the result is in use. `owned` controls whether
+    /// `t30_free` is called on drop.
     pub unsafe fn from_raw(ptr: *mut spandsp_sys::t30_state_t, owned: bool) -> Result<Self> {
         let inner = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { inner, owned })
+        Ok(Self {
+            inner,
+            owned,
+            _owner: PhantomData,
+        })
     }
 
     /// Get the raw pointer.
@@ -61,7 +128,7 @@ impl T30State {
     }
 
     /// Set the file to transmit.
-    pub fn set_tx_file(&self, file: &str, start_page: i32, stop_page: i32) -> Result<()> {
+    pub fn set_tx_file(&mut self, file: &str, start_page: i32, stop_page: i32) -> Result<()> {
         let c_file = CString::new(file)
             .map_err(|_| SpanDspError::InvalidInput("file path contains NUL".into()))?;
         unsafe {
@@ -76,7 +143,7 @@ impl T30State {
     }
 
     /// Set the file to receive into.
-    pub fn set_rx_file(&self, file: &str, stop_page: i32) -> Result<()> {
+    pub fn set_rx_file(&mut self, file: &str, stop_page: i32) -> Result<()> {
         let c_file = CString::new(file)
             .map_err(|_| SpanDspError::InvalidInput("file path contains NUL".into()))?;
         unsafe {
@@ -86,7 +153,12 @@ impl T30State {
     }
 
     /// Set supported modems for T.30 negotiation.
-    pub fn set_supported_modems(&self, modems: T30ModemSupport) -> Result<()> {
+    ///
+    /// Fails if any are not in [`T30ModemSupport::available`]. On a
+    /// session with a bit rate cap, use `FaxState::set_supported_modems`
+    /// or `T38Terminal::set_supported_modems` instead, which keep the cap.
+    pub fn set_supported_modems(&mut self, modems: T30ModemSupport) -> Result<()> {
+        modems.check_available()?;
         let rc =
             unsafe { spandsp_sys::t30_set_supported_modems(self.inner.as_ptr(), modems.bits()) };
         if rc != 0 {
@@ -96,7 +168,7 @@ impl T30State {
     }
 
     /// Enable or disable ECM.
-    pub fn set_ecm_capability(&self, enabled: bool) -> Result<()> {
+    pub fn set_ecm_capability(&mut self, enabled: bool) -> Result<()> {
         let rc = unsafe { spandsp_sys::t30_set_ecm_capability(self.inner.as_ptr(), enabled) };
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
@@ -104,6 +176,109 @@ impl T30State {
         Ok(())
     }
 
+    /// Set the local identity (TSI/CSI) sent to the far end.
+    pub fn set_tx_ident(&mut self, ident: &str) -> Result<()> {
+        let c_ident = CString::new(ident)
+            .map_err(|_| SpanDspError::InvalidInput("ident contains NUL".into()))?;
+        let rc = unsafe { spandsp_sys::t30_set_tx_ident(self.inner.as_ptr(), c_ident.as_ptr()) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Configure the header line stamped on transmitted pages in one step.
+    ///
+    /// The header is validated first (see [`PageHeader::validate`]). T.30
+    /// copies the strings and parses the time zone itself, so nothing needs
+    /// to be kept alive afterwards. A header without a time zone keeps any
+    /// zone applied earlier, as spandsp has no way to clear it.
+    pub fn set_page_header(&mut self, header: &PageHeader) -> Result<()> {
+        header.validate()?;
+        let to_c = |s: &str| {
+            CString::new(s).map_err(|_| SpanDspError::InvalidInput("header contains NUL".into()))
+        };
+        let ident = to_c(header.local_ident.as_deref().unwrap_or(""))?;
+        let info = to_c(header.info.as_deref().unwrap_or(""))?;
+        let p = self.inner.as_ptr();
+        unsafe {
+            let rc = spandsp_sys::t30_set_tx_ident(p, ident.as_ptr());
+            if rc != 0 {
+                return Err(SpanDspError::ErrorCode(rc));
+            }
+            let rc = spandsp_sys::t30_set_tx_page_header_info(p, info.as_ptr());
+            if rc != 0 {
+                return Err(SpanDspError::ErrorCode(rc));
+            }
+            spandsp_sys::t30_set_tx_page_header_overlays_image(p, header.overlays_image);
+            if let Some(tz) = &header.timezone {
+                let c_tz = to_c(tz)?;
+                if spandsp_sys::t30_set_tx_page_header_tz(p, c_tz.as_ptr()) != 0 {
+                    return Err(SpanDspError::InvalidInput(format!(
+                        "invalid time zone: {tz}"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The local identity, if one has been set.
+    pub fn tx_ident(&self) -> Option<String> {
+        unsafe { owned_c_str(spandsp_sys::t30_get_tx_ident(self.inner.as_ptr())) }
+    }
+
+    /// The identity received from the far end, if any.
+    pub fn rx_ident(&self) -> Option<String> {
+        unsafe { owned_c_str(spandsp_sys::t30_get_rx_ident(self.inner.as_ptr())) }
+    }
+
+    /// The sub-address received from the far end, if any.
+    pub fn rx_sub_address(&self) -> Option<String> {
+        unsafe { owned_c_str(spandsp_sys::t30_get_rx_sub_address(self.inner.as_ptr())) }
+    }
+
+    /// The password received from the far end, if any.
+    pub fn rx_password(&self) -> Option<String> {
+        unsafe { owned_c_str(spandsp_sys::t30_get_rx_password(self.inner.as_ptr())) }
+    }
+
+    /// The far end's vendor, as decoded from its NSF/NSC/NSS, if known.
+    pub fn rx_vendor(&self) -> Option<String> {
+        unsafe { owned_c_str(spandsp_sys::t30_get_rx_vendor(self.inner.as_ptr())) }
+    }
+
+    /// The far end's model, as decoded from its NSF/NSC/NSS, if known.
+    pub fn rx_model(&self) -> Option<String> {
+        unsafe { owned_c_str(spandsp_sys::t30_get_rx_model(self.inner.as_ptr())) }
+    }
+
+    /// Capture the resumable parts of this session.
+    ///
+    /// Session types add what only they know (calling side, queued
+    /// documents); see `FaxState::snapshot`.
+    pub fn snapshot(&self) -> T30Snapshot {
+        let stats = self.get_transfer_statistics();
+        T30Snapshot {
+            calling_party: None,
+            local_ident: self.tx_ident(),
+            far_ident: self.rx_ident(),
+            far_sub_address: self.rx_sub_address(),
+            far_vendor: self.rx_vendor(),
+            far_model: self.rx_model(),
+            bit_rate: stats.bit_rate,
+            ecm: stats.error_correcting_mode != 0,
+            pages_tx: stats.pages_tx,
+            pages_rx: stats.pages_rx,
+            x_resolution: stats.x_resolution,
+            y_resolution: stats.y_resolution,
+            image_width: stats.width,
+            compression: stats.compression,
+            status: stats.current_status,
+            remaining_documents: Vec::new(),
+        }
+    }
+
     /// Get the current transfer statistics.
     pub fn get_transfer_statistics(&self) -> spandsp_sys::t30_stats_t {
         let mut stats = unsafe { std::mem::zeroed::<spandsp_sys::t30_stats_t>() };
@@ -115,10 +290,13 @@ impl T30State {
 
     /// Set the T.30 phase B handler (called at start of document exchange).
     ///
+    /// The session types' `set_ident_validator` uses this handler; replacing
+    /// it stops ident screening.
+    ///
     /// # Safety
     /// The callback and user_data must remain valid for the lifetime of this state.
     pub unsafe fn set_phase_b_handler_raw(
-        &self,
+        &mut self,
         handler: spandsp_sys::t30_phase_b_handler_t,
         user_data: *mut std::ffi::c_void,
     ) {
@@ -129,10 +307,13 @@ impl T30State {
 
     /// Set the T.30 phase D handler (called at end of each page).
     ///
+    /// The session types use this handler for interrupt signals and the
+    /// quality report; replacing it stops both.
+    ///
     /// # Safety
     /// The callback and user_data must remain valid for the lifetime of this state.
     pub unsafe fn set_phase_d_handler_raw(
-        &self,
+        &mut self,
         handler: spandsp_sys::t30_phase_d_handler_t,
         user_data: *mut std::ffi::c_void,
     ) {
@@ -143,10 +324,13 @@ impl T30State {
 
     /// Set the T.30 phase E handler (called at completion of fax session).
     ///
+    /// With the `metrics` feature the session types use this handler to
+    /// publish the page and failure counters; replacing it stops them.
+    ///
     /// # Safety
     /// The callback and user_data must remain valid for the lifetime of this state.
     pub unsafe fn set_phase_e_handler_raw(
-        &self,
+        &mut self,
         handler: spandsp_sys::t30_phase_e_handler_t,
         user_data: *mut std::ffi::c_void,
     ) {
@@ -159,7 +343,7 @@ impl T30State {
     ///
     /// The `compressions` parameter is a bitmask of `T4_COMPRESSION_*` constants
     /// from `spandsp_sys` (e.g., `T4_COMPRESSION_T4_1D | T4_COMPRESSION_T4_2D | T4_COMPRESSION_T6`).
-    pub fn set_supported_compressions(&self, compressions: i32) -> Result<()> {
+    pub fn set_supported_compressions(&mut self, compressions: i32) -> Result<()> {
         let rc = unsafe {
             spandsp_sys::t30_set_supported_compressions(self.inner.as_ptr(), compressions)
         };
@@ -173,7 +357,7 @@ impl T30State {
     ///
     /// The `sizes` parameter is a bitmask of `T4_SUPPORT_WIDTH_*` and related
     /// constants from `spandsp_sys`.
-    pub fn set_supported_image_sizes(&self, sizes: i32) -> Result<()> {
+    pub fn set_supported_image_sizes(&mut self, sizes: i32) -> Result<()> {
         let rc = unsafe { spandsp_sys::t30_set_supported_image_sizes(self.inner.as_ptr(), sizes) };
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
@@ -186,6 +370,27 @@ impl T30State {
         unsafe { spandsp_sys::t30_call_active(self.inner.as_ptr()) != 0 }
     }
 
+    /// Request (or withdraw a request for) operator intervention.
+    ///
+    /// While set, the engine sends PRI-Q post-page messages when
+    /// transmitting and PIP responses when receiving, asking the far end to
+    /// go to voice at the next page boundary.
+    pub fn request_local_interrupt(&mut self, requested: bool) {
+        unsafe {
+            spandsp_sys::t30_local_interrupt_request(self.inner.as_ptr(), requested as c_int);
+        }
+    }
+
+    /// Allow or refuse interrupt requests from the far end.
+    ///
+    /// When refused, a received PRI-Q is treated like its plain
+    /// counterpart and the call continues as fax.
+    pub fn set_remote_interrupts_allowed(&mut self, allowed: bool) {
+        unsafe {
+            spandsp_sys::t30_remote_interrupts_allowed(self.inner.as_ptr(), allowed as c_int);
+        }
+    }
+
     /// Convert a T.30 completion code to a `T30Error`.
     ///
     /// Returns `None` if the code does not correspond to a known `t30_err_e`
@@ -261,7 +466,16 @@ impl T30State {
     }
 }
 
-impl Drop for T30State {
+impl fmt::Debug for T30State<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T30State")
+            .field("owned", &self.owned)
+            .field("call_active", &self.call_active())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for T30State<'_> {
     fn drop(&mut self) {
         if self.owned {
             unsafe {
@@ -270,3 +484,1042 @@ impl Drop for T30State {
         }
     }
 }
+
+// ---------------------------------------------------------------------------
+// Multi-document transmit
+// ---------------------------------------------------------------------------
+
+/// One document in a multi-document transmission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxDocument {
+    /// Path of the TIFF file to send.
+    pub path: String,
+    /// First page to send, or -1 for the start of the file.
+    pub start_page: i32,
+    /// Last page to send, or -1 for the end of the file.
+    pub stop_page: i32,
+}
+
+impl TxDocument {
+    /// A document that sends every page of `path`.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            start_page: -1,
+            stop_page: -1,
+        }
+    }
+
+    /// Restrict the document to pages `start_page..=stop_page`.
+    pub fn with_pages(mut self, start_page: i32, stop_page: i32) -> Self {
+        self.start_page = start_page;
+        self.stop_page = stop_page;
+        self
+    }
+
+    fn c_path(&self) -> Result<CString> {
+        CString::new(self.path.as_str())
+            .map_err(|_| SpanDspError::InvalidInput("file path contains NUL".into()))
+    }
+}
+
+/// Documents still waiting to be sent, plus the T.30 engine that sends them.
+///
+/// Owned (boxed) by the session type so that its address stays stable while
+/// it is registered as the T.30 document handler's user data.
+pub(crate) struct TxDocumentQueue {
+    t30: *mut spandsp_sys::t30_state_t,
+    current: TxDocument,
+    /// Session page count when `current` was started.
+    pages_before_current: i32,
+    pending: std::collections::VecDeque<(TxDocument, CString)>,
+}
+
+impl TxDocumentQueue {
+    /// Validate `docs`, load the first one into `t30` and register the
+    /// document handler that feeds it the rest.
+    ///
+    /// # Safety
+    /// `t30` must be valid, and the returned queue must outlive every call
+    /// into the engine (or be replaced by another `install`).
+    pub(crate) unsafe fn install(
+        t30: *mut spandsp_sys::t30_state_t,
+        docs: impl IntoIterator<Item = TxDocument>,
+    ) -> Result<Box<Self>> {
+        let mut pending = docs
+            .into_iter()
+            .map(|doc| doc.c_path().map(|c_path| (doc, c_path)))
+            .collect::<Result<std::collections::VecDeque<_>>>()?;
+        let (first, c_path) = pending
+            .pop_front()
+            .ok_or_else(|| SpanDspError::InvalidInput("no documents to send".into()))?;
+        let mut queue = Box::new(Self {
+            t30,
+            current: first.clone(),
+            pages_before_current: 0,
+            pending,
+        });
+        unsafe {
+            spandsp_sys::t30_set_tx_file(t30, c_path.as_ptr(), first.start_page, first.stop_page);
+            spandsp_sys::t30_set_document_handler(
+                t30,
+                Some(document_handler_trampoline),
+                &mut *queue as *mut Self as *mut std::ffi::c_void,
+            );
+        }
+        Ok(queue)
+    }
+
+    /// Number of documents not yet handed to the T.30 engine.
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The unsent remainder of the queue, given the session's page count.
+    ///
+    /// The document in progress is trimmed to start after its last
+    /// confirmed page.
+    pub(crate) fn remaining(&self, pages_tx: i32) -> Vec<TxDocument> {
+        let done = (pages_tx - self.pages_before_current).max(0);
+        let mut current = self.current.clone();
+        current.start_page = current.start_page.max(0) + done;
+        let finished = current.stop_page >= 0 && current.start_page > current.stop_page;
+        let mut docs = Vec::with_capacity(self.pending.len() + 1);
+        if !finished {
+            docs.push(current);
+        }
+        docs.extend(self.pending.iter().map(|(doc, _)| doc.clone()));
+        docs
+    }
+}
+
+/// Trampoline for the T.30 document handler.
+///
+/// spandsp calls this after the last page of each document. Returning
+/// non-zero makes the engine signal EOM and start the next document, whose
+/// file has just been set with `t30_set_tx_file`; returning zero ends the
+/// call with EOP.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `TxDocumentQueue`.
+unsafe extern "C" fn document_handler_trampoline(
+    user_data: *mut std::ffi::c_void,
+    _status: std::os::raw::c_int,
+) -> std::os::raw::c_int {
+    unsafe {
+        if user_data.is_null() {
+            return 0;
+        }
+        let queue = &mut *(user_data as *mut TxDocumentQueue);
+        match queue.pending.pop_front() {
+            Some((doc, c_path)) => {
+                spandsp_sys::t30_set_tx_file(
+                    queue.t30,
+                    c_path.as_ptr(),
+                    doc.start_page,
+                    doc.stop_page,
+                );
+                let mut stats = std::mem::zeroed::<spandsp_sys::t30_stats_t>();
+                spandsp_sys::t30_get_transfer_statistics(queue.t30, &mut stats);
+                queue.pages_before_current = stats.pages_tx;
+                queue.current = doc;
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Ident screening
+// ---------------------------------------------------------------------------
+
+/// What the far end announced about itself in phase B.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteIdent {
+    /// Identity from CSI (when calling) or TSI (when answering).
+    pub ident: Option<String>,
+    /// Sub-address (SUB), for routing within the receiving station.
+    pub sub_address: Option<String>,
+    /// Password (PWD).
+    pub password: Option<String>,
+}
+
+/// Verdict of an ident validator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IdentDecision {
+    /// Carry on with the call.
+    Accept,
+    /// Hang up with `T30_ERR_IDENT_UNACCEPTABLE`.
+    RejectIdent,
+    /// Hang up with `T30_ERR_SUB_UNACCEPTABLE`.
+    RejectSubAddress,
+    /// Hang up with `T30_ERR_PWD_UNACCEPTABLE`.
+    RejectPassword,
+}
+
+impl IdentDecision {
+    /// The completion code the call ends with.
+    pub fn completion(self) -> T30Error {
+        use spandsp_sys::t30_err_e::*;
+        T30Error::from(match self {
+            Self::Accept => T30_ERR_OK,
+            Self::RejectIdent => T30_ERR_IDENT_UNACCEPTABLE,
+            Self::RejectSubAddress => T30_ERR_SUB_UNACCEPTABLE,
+            Self::RejectPassword => T30_ERR_PWD_UNACCEPTABLE,
+        })
+    }
+}
+
+pub(crate) type IdentCallback = Box<dyn FnMut(&RemoteIdent) -> IdentDecision + Send>;
+
+/// An ident validator bound to the engine whose phase B it screens.
+pub(crate) struct IdentValidator {
+    t30: *mut spandsp_sys::t30_state_t,
+    callback: IdentCallback,
+}
+
+/// Phase B trampoline that shows the far end's identity to the user closure
+/// and returns its verdict as a completion code.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `IdentValidator`.
+unsafe extern "C" fn ident_phase_b_trampoline(user_data: *mut c_void, _result: c_int) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return spandsp_sys::t30_err_e::T30_ERR_OK as c_int;
+        }
+        let validator = &mut *(user_data as *mut IdentValidator);
+        let remote = RemoteIdent {
+            ident: owned_c_str(spandsp_sys::t30_get_rx_ident(validator.t30)),
+            sub_address: owned_c_str(spandsp_sys::t30_get_rx_sub_address(validator.t30)),
+            password: owned_c_str(spandsp_sys::t30_get_rx_password(validator.t30)),
+        };
+        (validator.callback)(&remote).completion().raw() as c_int
+    }
+}
+
+/// Register `handler` as the phase B handler of `t30`, returning the box
+/// that must be kept alive while the engine runs.
+///
+/// # Safety
+/// `t30` must be valid.
+pub(crate) unsafe fn install_ident_validator<F>(
+    t30: *mut spandsp_sys::t30_state_t,
+    handler: F,
+) -> Box<IdentValidator>
+where
+    F: FnMut(&RemoteIdent) -> IdentDecision + Send + 'static,
+{
+    let mut boxed = Box::new(IdentValidator {
+        t30,
+        callback: Box::new(handler),
+    });
+    let user_data = &mut *boxed as *mut IdentValidator as *mut c_void;
+    unsafe {
+        spandsp_sys::t30_set_phase_b_handler(t30, Some(ident_phase_b_trampoline), user_data);
+    }
+    boxed
+}
+
+// ---------------------------------------------------------------------------
+// Operator interrupts
+// ---------------------------------------------------------------------------
+
+/// A procedure-interrupt signal seen at a page boundary.
+///
+/// Delivered to the interrupt handler installed with
+/// `FaxState::set_interrupt_handler` or `T38Terminal::set_interrupt_handler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum T30InterruptSignal {
+    /// Procedure interrupt positive: page received OK, the far end wants to talk.
+    Pip,
+    /// Procedure interrupt negative: page bad, the far end wants to talk.
+    Pin,
+    /// PRI-EOM: end of document, interrupt requested.
+    PriEom,
+    /// PRI-MPS: more pages follow, interrupt requested.
+    PriMps,
+    /// PRI-EOP: end of procedure, interrupt requested.
+    PriEop,
+}
+
+impl T30InterruptSignal {
+    /// Map a T.30 facsimile control field (as spandsp reports it, with the
+    /// X bit cleared) to an interrupt signal.
+    pub fn from_fcf(fcf: u8) -> Option<Self> {
+        match fcf & 0xFE {
+            0xAC => Some(Self::Pip),
+            0x2C => Some(Self::Pin),
+            0x9E => Some(Self::PriEom),
+            0x5E => Some(Self::PriMps),
+            0x3E => Some(Self::PriEop),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for T30InterruptSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pip => f.write_str("PIP"),
+            Self::Pin => f.write_str("PIN"),
+            Self::PriEom => f.write_str("PRI-EOM"),
+            Self::PriMps => f.write_str("PRI-MPS"),
+            Self::PriEop => f.write_str("PRI-EOP"),
+        }
+    }
+}
+
+pub(crate) type InterruptCallback = Box<dyn FnMut(T30InterruptSignal) + Send>;
+
+pub(crate) type EcmCallback = Box<dyn FnMut(EcmEvent) + Send>;
+
+/// Everything a session hangs off the T.30 phase D and real-time frame
+/// handlers: the optional interrupt, ECM and received page callbacks, the
+/// line quality record and the bit rate cap it can lower.
+pub(crate) struct SessionHooks {
+    t30: *mut spandsp_sys::t30_state_t,
+    interrupt: Option<InterruptCallback>,
+    ecm: Option<EcmCallback>,
+    quality: QualityRecorder,
+    speed: SpeedControl,
+    received: Option<ReceivedPageWatch>,
+}
+
+impl SessionHooks {
+    /// Register a fresh set of hooks as the phase D and real-time frame
+    /// handlers of `t30`, returning the box that must be kept alive while
+    /// the engine runs.
+    ///
+    /// # Safety
+    /// `t30` must be valid for as long as the returned box is.
+    pub(crate) unsafe fn install(t30: *mut spandsp_sys::t30_state_t) -> Box<Self> {
+        let mut hooks = Box::new(Self {
+            t30,
+            interrupt: None,
+            ecm: None,
+            quality: QualityRecorder::default(),
+            speed: SpeedControl::default(),
+            received: None,
+        });
+        let user_data = &mut *hooks as *mut Self as *mut c_void;
+        unsafe {
+            spandsp_sys::t30_set_phase_d_handler(t30, Some(phase_d_trampoline), user_data);
+            spandsp_sys::t30_set_real_time_frame_handler(t30, Some(frame_trampoline), user_data);
+            #[cfg(feature = "metrics")]
+            spandsp_sys::t30_set_phase_e_handler(t30, Some(phase_e_trampoline), user_data);
+        }
+        hooks
+    }
+
+    /// Forward interrupt signals to `handler`, taking the phase D handler
+    /// back if it was replaced with `T30State::set_phase_d_handler_raw`.
+    pub(crate) fn set_interrupt_handler(&mut self, handler: InterruptCallback) {
+        self.interrupt = Some(handler);
+        let user_data = self as *mut Self as *mut c_void;
+        unsafe {
+            spandsp_sys::t30_set_phase_d_handler(self.t30, Some(phase_d_trampoline), user_data);
+        }
+    }
+
+    /// Forward ECM events to `handler`.
+    pub(crate) fn set_ecm_handler(&mut self, handler: EcmCallback) {
+        self.ecm = Some(handler);
+    }
+
+    /// Receive into `rx_file`, handing each page to `handler` as it ends.
+    pub(crate) fn set_received_page_handler(
+        &mut self,
+        rx_file: &str,
+        stop_page: i32,
+        handler: ReceivedPageCallback,
+    ) -> Result<()> {
+        let c_file = CString::new(rx_file)
+            .map_err(|_| SpanDspError::InvalidInput("file path contains NUL".into()))?;
+        unsafe {
+            spandsp_sys::t30_set_rx_file(self.t30, c_file.as_ptr(), stop_page);
+        }
+        self.received = Some(ReceivedPageWatch::new(rx_file, handler));
+        Ok(())
+    }
+
+    /// The pages recorded so far, with the session's current status.
+    pub(crate) fn quality_report(&self) -> FaxQualityReport {
+        FaxQualityReport {
+            pages: self.quality.pages.clone(),
+            status: self.stats().current_status,
+        }
+    }
+
+    /// Offer `modems`, less any above the current cap, returning the
+    /// modems now offered.
+    pub(crate) fn set_supported_modems(
+        &mut self,
+        modems: T30ModemSupport,
+    ) -> Result<T30ModemSupport> {
+        modems.check_available()?;
+        let offered = self.offer(modems, self.speed.max_bit_rate)?;
+        self.speed.modems = modems;
+        Ok(offered)
+    }
+
+    /// Offer only the supported modems up to `max_bit_rate`, or all of them
+    /// for `None`, returning the modems now offered.
+    pub(crate) fn set_max_bit_rate(
+        &mut self,
+        max_bit_rate: Option<u32>,
+    ) -> Result<T30ModemSupport> {
+        let offered = self.offer(self.speed.modems, max_bit_rate)?;
+        self.speed.max_bit_rate = max_bit_rate;
+        Ok(offered)
+    }
+
+    /// Hand the engine `modems` capped at `max_bit_rate`, unless that
+    /// would leave no modem to train with.
+    fn offer(
+        &mut self,
+        modems: T30ModemSupport,
+        max_bit_rate: Option<u32>,
+    ) -> Result<T30ModemSupport> {
+        let offered = modems.capped_at(max_bit_rate.unwrap_or(u32::MAX));
+        if offered.max_bit_rate() == 0 && modems.max_bit_rate() > 0 {
+            return Err(SpanDspError::InvalidInput(format!(
+                "none of {modems} runs at or below {} bit/s",
+                max_bit_rate.unwrap_or(0)
+            )));
+        }
+        let rc = unsafe { spandsp_sys::t30_set_supported_modems(self.t30, offered.bits()) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(offered)
+    }
+
+    /// The current bit rate cap, if any.
+    pub(crate) fn max_bit_rate(&self) -> Option<u32> {
+        self.speed.max_bit_rate
+    }
+
+    /// Cap the bit rate at the top rate of the next modem down from the
+    /// one in use, unless that would go below `floor`. Returns the new cap.
+    pub(crate) fn request_fallback(&mut self, floor: u32) -> Result<Option<u32>> {
+        let offered = self
+            .speed
+            .modems
+            .capped_at(self.speed.max_bit_rate.unwrap_or(u32::MAX));
+        // Before training there is no rate in use; step down from the cap.
+        let current = match self.stats().bit_rate {
+            rate if rate > 0 => (rate as u32).min(offered.max_bit_rate()),
+            _ => offered.max_bit_rate(),
+        };
+        let next = offered.capped_at(current.saturating_sub(1)).max_bit_rate();
+        if next == 0 || next < floor {
+            return Ok(None);
+        }
+        self.set_max_bit_rate(Some(next)).map(|_| Some(next))
+    }
+
+    /// Fall back after each page that crosses `policy`, or never for `None`.
+    pub(crate) fn set_retrain_policy(&mut self, policy: Option<RetrainPolicy>) {
+        self.speed.policy = policy;
+    }
+
+    /// Apply the retrain policy to the page just recorded, once per page.
+    fn check_page_quality(&mut self) {
+        let Some(policy) = self.speed.policy else {
+            return;
+        };
+        let Some(page) = self.quality.pages.last() else {
+            return;
+        };
+        if page.page == self.speed.fell_back_at || !policy.is_degraded(page) {
+            return;
+        }
+        self.speed.fell_back_at = page.page;
+        // Nothing to do if the line is already on the slowest modem allowed.
+        let _ = self.request_fallback(policy.min_bit_rate);
+    }
+
+    fn stats(&self) -> spandsp_sys::t30_stats_t {
+        let mut stats = unsafe { std::mem::zeroed::<spandsp_sys::t30_stats_t>() };
+        unsafe {
+            spandsp_sys::t30_get_transfer_statistics(self.t30, &mut stats);
+        }
+        stats
+    }
+
+    /// Forget the pages recorded so far, e.g. for a new call.
+    pub(crate) fn clear_quality(&mut self) {
+        self.quality = QualityRecorder::default();
+        self.speed.fell_back_at = 0;
+        if let Some(watch) = &mut self.received {
+            watch.reset();
+        }
+    }
+}
+
+/// Phase D trampoline that records page quality, applies the retrain
+/// policy, delivers received pages and forwards interrupt signals to the
+/// user closure.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SessionHooks`.
+unsafe extern "C" fn phase_d_trampoline(user_data: *mut c_void, result: c_int) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return 0;
+        }
+        let hooks = &mut *(user_data as *mut SessionHooks);
+        let mut stats = std::mem::zeroed::<spandsp_sys::t30_stats_t>();
+        spandsp_sys::t30_get_transfer_statistics(hooks.t30, &mut stats);
+        hooks.quality.record(&stats);
+        hooks.check_page_quality();
+        if let Some(watch) = &mut hooks.received {
+            watch.check(hooks.t30, &stats);
+        }
+        if let Some(signal) = T30InterruptSignal::from_fcf(result as u8)
+            && let Some(closure) = &mut hooks.interrupt
+        {
+            closure(signal);
+        }
+        0
+    }
+}
+
+/// Phase E trampoline that publishes the page counts of the finished
+/// session and, for a non-zero completion code, a failure labelled with its
+/// description.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SessionHooks`.
+#[cfg(feature = "metrics")]
+unsafe extern "C" fn phase_e_trampoline(user_data: *mut c_void, result: c_int) {
+    use crate::metrics::{FAX_FAILURES, FAX_PAGES_RECEIVED, FAX_PAGES_SENT};
+
+    unsafe {
+        if user_data.is_null() {
+            return;
+        }
+        let hooks = &*(user_data as *const SessionHooks);
+        let stats = hooks.stats();
+        ::metrics::counter!(FAX_PAGES_SENT).increment(stats.pages_tx.max(0) as u64);
+        ::metrics::counter!(FAX_PAGES_RECEIVED).increment(stats.pages_rx.max(0) as u64);
+        if result != 0 {
+            let error = match T30State::completion_code(result) {
+                Some(e) => e.to_string(),
+                None => format!("code {result}"),
+            };
+            ::metrics::counter!(FAX_FAILURES, "error" => error).increment(1);
+        }
+    }
+}
+
+/// Real-time frame trampoline that picks the ECM control frames out of the
+/// T.30 exchange.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SessionHooks`; `msg` must be valid for
+/// `len` bytes.
+unsafe extern "C" fn frame_trampoline(
+    user_data: *mut c_void,
+    incoming: bool,
+    msg: *const u8,
+    len: c_int,
+) {
+    unsafe {
+        if user_data.is_null() || msg.is_null() || len <= 0 {
+            return;
+        }
+        let hooks = &mut *(user_data as *mut SessionHooks);
+        let frame = std::slice::from_raw_parts(msg, len as usize);
+        let Some(kind) = EcmEventKind::from_frame(frame) else {
+            return;
+        };
+        let event = EcmEvent {
+            received: incoming,
+            kind,
+        };
+        hooks.quality.record_ecm(&event);
+        #[cfg(feature = "metrics")]
+        if let EcmEventKind::PartialPageRequest { frames } = kind {
+            use crate::metrics::{FAX_ECM_FRAMES_RESENT, FAX_ECM_PARTIAL_PAGE_REQUESTS};
+            ::metrics::counter!(FAX_ECM_PARTIAL_PAGE_REQUESTS).increment(1);
+            ::metrics::counter!(FAX_ECM_FRAMES_RESENT).increment(u64::from(frames));
+        }
+        if let Some(closure) = &mut hooks.ecm {
+            closure(event);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ECM events
+// ---------------------------------------------------------------------------
+
+/// T.30 FCF of a partial page signal (PPS), X bit clear.
+const FCF_PPS: u8 = 0xBE;
+/// T.30 FCF of a partial page request (PPR).
+const FCF_PPR: u8 = 0xBC;
+/// T.30 FCF of receive not ready (RNR).
+const FCF_RNR: u8 = 0xEC;
+/// T.30 FCF of end of retransmission (EOR), X bit clear.
+const FCF_EOR: u8 = 0xCE;
+
+/// An error correction mode control frame seen during a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EcmEvent {
+    /// Whether the far end sent the frame.
+    pub received: bool,
+    /// What the frame says.
+    pub kind: EcmEventKind,
+}
+
+/// The ECM control frames of T.30 Annex A.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EcmEventKind {
+    /// PPS: a block of a page has been sent.
+    PartialPage {
+        /// Page counter, modulo 256.
+        page: u8,
+        /// Block counter within the page, modulo 256.
+        block: u8,
+        /// Frames in the block.
+        frames: u16,
+    },
+    /// PPR: the receiver asked for frames of the last block again.
+    PartialPageRequest {
+        /// Frames asked for.
+        frames: u16,
+    },
+    /// RNR: the receiver is not ready for the next block.
+    ReceiverNotReady,
+    /// EOR: the sender gave up retransmitting a block.
+    EndOfRetransmission,
+}
+
+impl EcmEventKind {
+    /// Decode a T.30 HDLC frame (address, control, FCF, FIF), as passed to
+    /// a real-time frame handler. Returns `None` for frames that are not
+    /// ECM control frames.
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        let (&fcf, fif) = frame.get(2..)?.split_first()?;
+        match fcf & 0xFE {
+            FCF_PPS => match *fif {
+                [_, page, block, frames, ..] => Some(Self::PartialPage {
+                    page,
+                    block,
+                    frames: u16::from(frames) + 1,
+                }),
+                _ => None,
+            },
+            FCF_PPR => {
+                let map = fif.get(..32)?;
+                let frames = map.iter().map(|b| b.count_ones() as u16).sum();
+                Some(Self::PartialPageRequest { frames })
+            }
+            FCF_RNR => Some(Self::ReceiverNotReady),
+            FCF_EOR => Some(Self::EndOfRetransmission),
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Speed control
+// ---------------------------------------------------------------------------
+
+/// When to lower the bit rate cap without waiting for the engine.
+///
+/// The engine falls back on its own only when training fails or a page is
+/// answered with RTN. With a policy set, a page whose line quality crosses
+/// either threshold also steps the cap down one modem, as if
+/// `request_fallback` had been called at the page boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetrainPolicy {
+    /// Bad rows on a page above which to fall back.
+    pub max_bad_rows: i32,
+    /// Partial page requests on a page above which to fall back.
+    pub max_partial_page_requests: u32,
+    /// Never cap the bit rate below this, in bits/s.
+    pub min_bit_rate: u32,
+}
+
+impl Default for RetrainPolicy {
+    /// Fall back after more than 10 bad rows or 2 partial page requests on
+    /// a page, as far as V.27ter.
+    fn default() -> Self {
+        Self {
+            max_bad_rows: 10,
+            max_partial_page_requests: 2,
+            min_bit_rate: 0,
+        }
+    }
+}
+
+impl RetrainPolicy {
+    /// Whether `page` is bad enough to fall back.
+    pub fn is_degraded(&self, page: &PageQuality) -> bool {
+        page.bad_rows > self.max_bad_rows
+            || page.partial_page_requests > self.max_partial_page_requests
+    }
+}
+
+/// The bit rate cap of a session and the policy that lowers it.
+#[derive(Debug, Default)]
+struct SpeedControl {
+    /// The modems to offer before the cap is applied.
+    modems: T30ModemSupport,
+    max_bit_rate: Option<u32>,
+    policy: Option<RetrainPolicy>,
+    /// The last page the policy fell back for; 0 for none.
+    fell_back_at: u32,
+}
+
+// ---------------------------------------------------------------------------
+// Line quality
+// ---------------------------------------------------------------------------
+
+/// Line quality of one page, as seen at the page boundary.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageQuality {
+    /// Page number within the call, from 1. A page that was retried after
+    /// a retrain keeps its number.
+    pub page: u32,
+    /// Bit rate the page was sent at, in bits/s.
+    pub bit_rate: i32,
+    /// Whether the page was sent with ECM.
+    pub ecm: bool,
+    /// Pixel rows the receiver could not decode (receive side only).
+    pub bad_rows: i32,
+    /// Longest run of consecutive bad rows (receive side only).
+    pub longest_bad_row_run: i32,
+    /// Retrains (RTP/RTN) around this page.
+    pub retrains: u32,
+    /// Whether the bit rate dropped since the previous page.
+    pub rate_fallback: bool,
+    /// ECM partial-page retransmissions of this page.
+    pub ecm_retransmissions: u32,
+    /// ECM blocks (partial pages) sent for this page, retransmissions
+    /// included.
+    pub ecm_blocks: u32,
+    /// Partial page requests (PPR) for this page.
+    pub partial_page_requests: u32,
+    /// ECM frames asked for again over all partial page requests.
+    pub ecm_frames_resent: u32,
+}
+
+/// Per-page line quality of a FAX call.
+///
+/// Collected at each page boundary and read with `FaxState::quality_report`
+/// or `T38Terminal::quality_report`. Bad rows, retrains and rate fallbacks
+/// point at the line; a failed [`completion`](Self::completion) on a clean
+/// line points at the protocol or the far end.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FaxQualityReport {
+    /// One entry per page, in order.
+    pub pages: Vec<PageQuality>,
+    /// Session status when the report was taken (a `t30_err_e` value).
+    pub status: i32,
+}
+
+impl FaxQualityReport {
+    /// The session status as a `T30Error`, if the code is known.
+    pub fn completion(&self) -> Option<T30Error> {
+        T30State::completion_code(self.status)
+    }
+
+    /// Bad rows over all pages.
+    pub fn bad_rows(&self) -> i32 {
+        self.pages.iter().map(|p| p.bad_rows).sum()
+    }
+
+    /// Retrains over all pages.
+    pub fn retrains(&self) -> u32 {
+        self.pages.iter().map(|p| p.retrains).sum()
+    }
+
+    /// Pages sent at a lower rate than the one before.
+    pub fn rate_fallbacks(&self) -> usize {
+        self.pages.iter().filter(|p| p.rate_fallback).count()
+    }
+
+    /// ECM retransmissions over all pages.
+    pub fn ecm_retransmissions(&self) -> u32 {
+        self.pages.iter().map(|p| p.ecm_retransmissions).sum()
+    }
+
+    /// Partial page requests over all pages.
+    pub fn partial_page_requests(&self) -> u32 {
+        self.pages.iter().map(|p| p.partial_page_requests).sum()
+    }
+
+    /// ECM frames asked for again over all pages.
+    pub fn ecm_frames_resent(&self) -> u32 {
+        self.pages.iter().map(|p| p.ecm_frames_resent).sum()
+    }
+
+    /// Whether any page shows signs of a poor line, including errors ECM
+    /// corrected without losing rows.
+    pub fn has_line_problems(&self) -> bool {
+        self.pages.iter().any(|p| {
+            p.bad_rows > 0
+                || p.retrains > 0
+                || p.rate_fallback
+                || p.ecm_retransmissions > 0
+                || p.partial_page_requests > 0
+        })
+    }
+}
+
+/// Turns the cumulative T.30 counters into per-page deltas.
+#[derive(Debug, Default)]
+struct QualityRecorder {
+    pages: Vec<PageQuality>,
+    /// `pages_tx + pages_rx` at the last boundary.
+    last_page_count: Option<i32>,
+    retrain_events: i32,
+    ecm_retries: i32,
+    /// ECM activity since the last boundary: blocks, PPRs, frames asked for.
+    ecm_pending: (u32, u32, u32),
+}
+
+impl QualityRecorder {
+    fn record_ecm(&mut self, event: &EcmEvent) {
+        match event.kind {
+            EcmEventKind::PartialPage { .. } => self.ecm_pending.0 += 1,
+            EcmEventKind::PartialPageRequest { frames } => {
+                self.ecm_pending.1 += 1;
+                self.ecm_pending.2 += u32::from(frames);
+            }
+            EcmEventKind::ReceiverNotReady | EcmEventKind::EndOfRetransmission => {}
+        }
+    }
+
+    fn record(&mut self, stats: &spandsp_sys::t30_stats_t) {
+        let retrain_events = stats.rtp_events + stats.rtn_events;
+        let retrains = (retrain_events - self.retrain_events).max(0) as u32;
+        self.retrain_events = retrain_events;
+        // The retry counter restarts with each page.
+        let ecm_retries = stats.error_correcting_mode_retries;
+        let retransmissions = if ecm_retries >= self.ecm_retries {
+            ecm_retries - self.ecm_retries
+        } else {
+            ecm_retries
+        } as u32;
+        self.ecm_retries = ecm_retries;
+        let (blocks, requests, frames) = std::mem::take(&mut self.ecm_pending);
+
+        let page_count = stats.pages_tx + stats.pages_rx;
+        let same_page = self.last_page_count == Some(page_count);
+        self.last_page_count = Some(page_count);
+        if same_page && let Some(page) = self.pages.last_mut() {
+            page.retrains += retrains;
+            page.ecm_retransmissions += retransmissions;
+            page.ecm_blocks += blocks;
+            page.partial_page_requests += requests;
+            page.ecm_frames_resent += frames;
+            page.rate_fallback |= stats.bit_rate < page.bit_rate;
+            page.bit_rate = stats.bit_rate;
+            page.bad_rows = stats.bad_rows;
+            page.longest_bad_row_run = stats.longest_bad_row_run;
+            return;
+        }
+        let rate_fallback = self
+            .pages
+            .last()
+            .is_some_and(|prev| stats.bit_rate < prev.bit_rate);
+        self.pages.push(PageQuality {
+            page: self.pages.len() as u32 + 1,
+            bit_rate: stats.bit_rate,
+            ecm: stats.error_correcting_mode != 0,
+            bad_rows: stats.bad_rows,
+            longest_bad_row_run: stats.longest_bad_row_run,
+            retrains,
+            rate_fallback,
+            ecm_retransmissions: retransmissions,
+            ecm_blocks: blocks,
+            partial_page_requests: requests,
+            ecm_frames_resent: frames,
+        });
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Received pages
+// ---------------------------------------------------------------------------
+
+/// A page as soon as it has been received, decoded to a bitmap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedPage {
+    /// Page number in the call, from 1.
+    pub number: u32,
+    /// Width in pixels.
+    pub width: u32,
+    /// The rows, each `width` pixels packed MSB first with 1 for black.
+    pub rows: Vec<Vec<u8>>,
+    /// The receiver's statistics for the page.
+    pub stats: T4Stats,
+}
+
+pub(crate) type ReceivedPageCallback = Box<dyn FnMut(Result<ReceivedPage>) + Send>;
+
+/// Hands each page of the receive file to a callback as it completes.
+struct ReceivedPageWatch {
+    rx_file: PathBuf,
+    handler: ReceivedPageCallback,
+    /// Pages handed over so far this call.
+    delivered: i32,
+    /// The receive file, open from its first page on.
+    file: Option<std::fs::File>,
+    /// How far the receive file has been read.
+    cursor: Option<AppendCursor>,
+}
+
+impl ReceivedPageWatch {
+    fn new(rx_file: &str, handler: ReceivedPageCallback) -> Self {
+        Self {
+            rx_file: PathBuf::from(rx_file),
+            handler,
+            delivered: 0,
+            file: None,
+            cursor: None,
+        }
+    }
+
+    /// Start again for a new call, which rewrites the receive file.
+    fn reset(&mut self) {
+        self.delivered = 0;
+        self.file = None;
+        self.cursor = None;
+    }
+
+    /// At a page boundary, deliver the page that just ended.
+    ///
+    /// Phase D is reported once per page, so normally there is exactly one.
+    /// If the count jumped, the pages in between are read past and passed
+    /// on as errors, since their statistics are gone.
+    fn check(&mut self, t30: *mut spandsp_sys::t30_state_t, stats: &spandsp_sys::t30_stats_t) {
+        if self.delivered >= stats.pages_rx {
+            return;
+        }
+        // The receiver still holds the statistics of the page just ended.
+        let page_stats = unsafe {
+            let t4 = spandsp_sys::t30_get_t4_rx_state(t30);
+            let mut stats = std::mem::MaybeUninit::<spandsp_sys::t4_stats_t>::zeroed();
+            if !t4.is_null() {
+                spandsp_sys::t4_rx_get_transfer_statistics(t4, stats.as_mut_ptr());
+            }
+            T4Stats::from(stats.assume_init())
+        };
+        while self.delivered < stats.pages_rx {
+            self.delivered += 1;
+            let number = self.delivered;
+            let page = self.read_next().and_then(|page| {
+                if number < stats.pages_rx {
+                    return Err(SpanDspError::InvalidInput(format!(
+                        "page {number} ended without a page boundary report"
+                    )));
+                }
+                Ok(ReceivedPage {
+                    number: number as u32,
+                    width: page.width(),
+                    rows: page.rows()?,
+                    stats: page_stats.clone(),
+                })
+            });
+            (self.handler)(page);
+        }
+    }
+
+    /// Read the next page from the receive file. spandsp writes each page
+    /// out, IFD and all, when it ends, before phase D is reported; only the
+    /// bytes it added are read.
+    fn read_next(&mut self) -> Result<FaxPage> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            file => file.insert(std::fs::File::open(&self.rx_file).map_err(|err| {
+                SpanDspError::InvalidInput(format!("opening {}: {err}", self.rx_file.display()))
+            })?),
+        };
+        let page = crate::fax_tiff::read_appended_page(file, &mut self.cursor)?;
+        Ok(FaxPage { image: page })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Session snapshots
+// ---------------------------------------------------------------------------
+
+/// Copy a C string owned by spandsp, mapping NULL and "" to `None`.
+///
+/// # Safety
+/// `ptr` must be NULL or point to a NUL-terminated string.
+pub(crate) unsafe fn owned_c_str(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let s = unsafe { CStr::from_ptr(ptr) }.to_string_lossy();
+    (!s.is_empty()).then(|| s.into_owned())
+}
+
+/// Resumable context of a T.30 session.
+///
+/// Holds the negotiated parameters, page progress and identities of a call,
+/// but not modem or mid-page state: a resumed session starts a new call and
+/// carries on from the last confirmed page. With the `serde` feature the
+/// snapshot can be persisted across a transfer or re-INVITE.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct T30Snapshot {
+    /// Whether this side originated the call, if known.
+    pub calling_party: Option<bool>,
+    /// Local identity (TSI/CSI).
+    pub local_ident: Option<String>,
+    /// Identity received from the far end.
+    pub far_ident: Option<String>,
+    /// Sub-address received from the far end.
+    pub far_sub_address: Option<String>,
+    /// Far end vendor, if it could be identified.
+    pub far_vendor: Option<String>,
+    /// Far end model, if it could be identified.
+    pub far_model: Option<String>,
+    /// Negotiated bit rate in bits/s.
+    pub bit_rate: i32,
+    /// Whether ECM was negotiated.
+    pub ecm: bool,
+    /// Pages sent so far.
+    pub pages_tx: i32,
+    /// Pages received so far.
+    pub pages_rx: i32,
+    /// Negotiated horizontal resolution (pixels per metre).
+    pub x_resolution: i32,
+    /// Negotiated vertical resolution (pixels per metre).
+    pub y_resolution: i32,
+    /// Negotiated image width in pixels.
+    pub image_width: i32,
+    /// Negotiated compression (a `T4_COMPRESSION_*` value).
+    pub compression: i32,
+    /// Current completion status (a `t30_err_e` value).
+    pub status: i32,
+    /// Documents (or the unsent part of them) still to be transmitted.
+    pub remaining_documents: Vec<TxDocument>,
+}
+
+impl T30Snapshot {
+    /// The session status as a `T30Error`, if the code is known.
+    pub fn completion(&self) -> Option<T30Error> {
+        T30State::completion_code(self.status)
+    }
+}
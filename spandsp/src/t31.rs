@@ -0,0 +1,371 @@
+//! Safe wrapper around the T.31 class 1 fax modem.
+//!
+//! `T31` wraps `t31_state_t`: a fax modem as seen from its DTE port. An
+//! external fax stack (HylaFAX, efax, a softphone's fax client) drives it
+//! with `AT+FCLASS=1` commands, and the modem carries the result either as
+//! audio ([`rx`](T31::rx)/[`tx`](T31::tx)) or as T.38 packets once
+//! [`set_t38_mode`](T31::set_t38_mode) is on.
+//!
+//! The DTE side uses the same hooks as
+//! [`AtInterpreter`](crate::at_interpreter::AtInterpreter): a closure for
+//! the bytes bound for the DTE and one for modem control. The class 1
+//! commands are handled by T.31 itself. [`Class1Modem`] turns those hooks
+//! into a queue of events for a software DTE to poll.
+
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+
+use crate::at_interpreter::{AtCallEvent, Handlers, ModemControl};
+use crate::error::{Result, SpanDspError};
+use crate::t38_core::{IfpTap, T38Core};
+
+/// T.31 modem state wrapping `t31_state_t`.
+pub struct T31 {
+    inner: NonNull<spandsp_sys::t31_state_t>,
+    tap: NonNull<IfpTap>,
+    _handlers: Box<Handlers>,
+    t38_mode: bool,
+}
+
+impl T31 {
+    /// Create an audio-only T.31 modem, sending everything bound for the
+    /// DTE to `response` and each modem control request to
+    /// `modem_control`, which returns whether it was carried out.
+    pub fn new<R, M>(response: R, modem_control: M) -> Result<Self>
+    where
+        R: FnMut(&[u8]) + Send + 'static,
+        M: FnMut(ModemControl) -> bool + Send + 'static,
+    {
+        unsafe { Self::new_raw(response, modem_control, None, std::ptr::null_mut()) }
+    }
+
+    /// Create a T.31 modem that can also run over T.38, sending IFP
+    /// packets through `tx_packet_handler`.
+    ///
+    /// # Safety
+    /// `tx_packet_handler` and `tx_packet_user_data` must remain valid for
+    /// the lifetime of this object.
+    pub unsafe fn new_raw<R, M>(
+        response: R,
+        modem_control: M,
+        tx_packet_handler: spandsp_sys::t38_tx_packet_handler_t,
+        tx_packet_user_data: *mut c_void,
+    ) -> Result<Self>
+    where
+        R: FnMut(&[u8]) + Send + 'static,
+        M: FnMut(ModemControl) -> bool + Send + 'static,
+    {
+        let mut handlers = Handlers::new(response, modem_control);
+        let user_data = handlers.user_data();
+        let tap = IfpTap::new(tx_packet_handler, tx_packet_user_data);
+        unsafe {
+            let ptr = spandsp_sys::t31_init(
+                std::ptr::null_mut(),
+                Handlers::TX_TRAMPOLINE,
+                user_data,
+                Handlers::MODEM_CONTROL_TRAMPOLINE,
+                user_data,
+                IfpTap::TRAMPOLINE,
+                tap.as_ptr() as *mut c_void,
+            );
+            let Some(inner) = NonNull::new(ptr) else {
+                IfpTap::free(tap);
+                return Err(SpanDspError::InitFailed);
+            };
+            Ok(Self {
+                inner,
+                tap,
+                _handlers: handlers,
+                t38_mode: false,
+            })
+        }
+    }
+
+    /// Get the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::t31_state_t {
+        self.inner.as_ptr()
+    }
+
+    /// Get a (non-owned) handle to the T.38 core IFP engine, to feed it
+    /// received packets.
+    ///
+    /// The handle borrows the modem mutably, since it drives the same
+    /// engine.
+    pub fn get_t38_core_state(&mut self) -> Result<T38Core<'_>> {
+        let ptr = unsafe { spandsp_sys::t31_get_t38_core_state(self.inner.as_ptr()) };
+        unsafe { T38Core::from_raw_tapped(ptr, self.tap) }
+    }
+
+    /// Feed bytes from the DTE: commands, or DLE-stuffed data while
+    /// sending.
+    pub fn at_rx(&mut self, bytes: &[u8]) {
+        unsafe {
+            spandsp_sys::t31_at_rx(
+                self.inner.as_ptr(),
+                bytes.as_ptr() as *const c_char,
+                bytes.len().min(c_int::MAX as usize) as c_int,
+            );
+        }
+    }
+
+    /// Report call progress on the line side.
+    pub fn call_event(&mut self, event: AtCallEvent) {
+        unsafe {
+            spandsp_sys::t31_call_event(self.inner.as_ptr(), event as c_int);
+        }
+    }
+
+    /// Process received audio samples.
+    ///
+    /// Returns the number of unprocessed samples.
+    pub fn rx(&mut self, samples: &mut [i16]) -> usize {
+        unsafe {
+            spandsp_sys::t31_rx(
+                self.inner.as_ptr(),
+                samples.as_mut_ptr(),
+                samples.len().min(c_int::MAX as usize) as c_int,
+            )
+            .max(0) as usize
+        }
+    }
+
+    /// Account for `len` samples of received audio that were lost.
+    pub fn rx_fillin(&mut self, len: usize) {
+        unsafe {
+            spandsp_sys::t31_rx_fillin(self.inner.as_ptr(), len.min(c_int::MAX as usize) as c_int);
+        }
+    }
+
+    /// Generate transmit audio samples.
+    ///
+    /// Returns the number of samples generated.
+    pub fn tx(&mut self, buf: &mut [i16]) -> usize {
+        unsafe {
+            spandsp_sys::t31_tx(
+                self.inner.as_ptr(),
+                buf.as_mut_ptr(),
+                buf.len().min(c_int::MAX as usize) as c_int,
+            )
+            .max(0) as usize
+        }
+    }
+
+    /// Drive the T.38 timer. Call periodically with the number of
+    /// audio-equivalent samples elapsed while in T.38 mode.
+    pub fn send_timeout(&mut self, samples: i32) -> i32 {
+        unsafe { spandsp_sys::t31_t38_send_timeout(self.inner.as_ptr(), samples) }
+    }
+
+    /// Switch between audio and T.38.
+    pub fn set_t38_mode(&mut self, t38: bool) {
+        unsafe {
+            spandsp_sys::t31_set_mode(self.inner.as_ptr(), t38 as c_int);
+        }
+        self.t38_mode = t38;
+    }
+
+    /// Returns whether the modem runs over T.38.
+    pub fn t38_mode(&self) -> bool {
+        self.t38_mode
+    }
+
+    /// Set whether T.38 packets go out as fast as they are made, for
+    /// transports such as TCP that need no pacing.
+    pub fn set_t38_config(&mut self, without_pacing: bool) {
+        unsafe {
+            spandsp_sys::t31_set_t38_config(self.inner.as_ptr(), without_pacing);
+        }
+    }
+
+    /// Set whether to send silent audio when idle.
+    pub fn set_transmit_on_idle(&mut self, on: bool) {
+        unsafe {
+            spandsp_sys::t31_set_transmit_on_idle(self.inner.as_ptr(), on);
+        }
+    }
+
+    /// Set TEP mode.
+    pub fn set_tep_mode(&mut self, use_tep: bool) {
+        unsafe {
+            spandsp_sys::t31_set_tep_mode(self.inner.as_ptr(), use_tep);
+        }
+    }
+}
+
+// SAFETY: T31 wraps a SpanDSP t31_state_t that is only accessed through
+// &self/&mut self methods, and its hooks are Send.
+unsafe impl Send for T31 {}
+
+impl fmt::Debug for T31 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T31")
+            .field("t38_mode", &self.t38_mode)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for T31 {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::t31_free(self.inner.as_ptr());
+            IfpTap::free(self.tap);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Class1Modem
+// ---------------------------------------------------------------------------
+
+/// Something the DTE or the line side needs to hear about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Class1Event {
+    /// Bytes for the DTE. Bytes sent together are coalesced until taken.
+    ToDte(Vec<u8>),
+    /// A modem control request for the line side, such as dialling.
+    Control(ModemControl),
+}
+
+/// Where the hooks queue events.
+#[derive(Default)]
+struct EventQueue {
+    events: VecDeque<Class1Event>,
+    off_hook: bool,
+}
+
+impl EventQueue {
+    fn push_dte(&mut self, bytes: &[u8]) {
+        match self.events.back_mut() {
+            Some(Class1Event::ToDte(pending)) => pending.extend_from_slice(bytes),
+            _ => self.events.push_back(Class1Event::ToDte(bytes.to_vec())),
+        }
+    }
+
+    fn control(&mut self, control: ModemControl) {
+        match control {
+            ModemControl::Call(_) | ModemControl::Answer | ModemControl::OffHook => {
+                self.off_hook = true
+            }
+            ModemControl::Hangup | ModemControl::OnHook => self.off_hook = false,
+            _ => {}
+        }
+        self.events.push_back(Class1Event::Control(control));
+    }
+}
+
+/// A [`T31`] modem with its hooks turned into a queue of
+/// [`Class1Event`]s, so a software DTE can be attached by moving bytes and
+/// audio in and events out.
+///
+/// ```no_run
+/// use spandsp::t31::{Class1Event, Class1Modem};
+///
+/// let mut modem = Class1Modem::new().unwrap();
+/// modem.process_at_bytes(b"AT+FCLASS=1\r");
+/// let mut audio = [0i16; 160];
+/// modem.tx_audio(&mut audio);
+/// while let Some(event) = modem.poll_event() {
+///     if let Class1Event::ToDte(bytes) = event {
+///         print!("{}", String::from_utf8_lossy(&bytes));
+///     }
+/// }
+/// ```
+pub struct Class1Modem {
+    t31: T31,
+    queue: Arc<Mutex<EventQueue>>,
+}
+
+impl Class1Modem {
+    /// Create an audio-only modem.
+    pub fn new() -> Result<Self> {
+        unsafe { Self::new_raw(None, std::ptr::null_mut()) }
+    }
+
+    /// Create a modem that can also run over T.38, sending IFP packets
+    /// through `tx_packet_handler`.
+    ///
+    /// # Safety
+    /// `tx_packet_handler` and `tx_packet_user_data` must remain valid for
+    /// the lifetime of this object.
+    pub unsafe fn new_raw(
+        tx_packet_handler: spandsp_sys::t38_tx_packet_handler_t,
+        tx_packet_user_data: *mut c_void,
+    ) -> Result<Self> {
+        let queue = Arc::new(Mutex::new(EventQueue::default()));
+        let (dte, line) = (Arc::clone(&queue), Arc::clone(&queue));
+        let t31 = unsafe {
+            T31::new_raw(
+                move |bytes| dte.lock().unwrap().push_dte(bytes),
+                move |control| {
+                    line.lock().unwrap().control(control);
+                    true
+                },
+                tx_packet_handler,
+                tx_packet_user_data,
+            )?
+        };
+        Ok(Self { t31, queue })
+    }
+
+    /// Feed bytes from the DTE.
+    pub fn process_at_bytes(&mut self, bytes: &[u8]) {
+        self.t31.at_rx(bytes);
+    }
+
+    /// Process received audio samples.
+    ///
+    /// Returns the number of unprocessed samples.
+    pub fn rx_audio(&mut self, samples: &mut [i16]) -> usize {
+        self.t31.rx(samples)
+    }
+
+    /// Generate transmit audio samples.
+    ///
+    /// Returns the number of samples generated.
+    pub fn tx_audio(&mut self, buf: &mut [i16]) -> usize {
+        self.t31.tx(buf)
+    }
+
+    /// Report call progress on the line side, e.g.
+    /// [`AtCallEvent::Alerting`] for an incoming call or
+    /// [`AtCallEvent::Connected`] once a dialled call is answered.
+    pub fn call_event(&mut self, event: AtCallEvent) {
+        self.t31.call_event(event);
+    }
+
+    /// Take the next event, oldest first.
+    pub fn poll_event(&mut self) -> Option<Class1Event> {
+        self.queue.lock().unwrap().events.pop_front()
+    }
+
+    /// Take every pending event, oldest first.
+    pub fn drain_events(&mut self) -> Vec<Class1Event> {
+        self.queue.lock().unwrap().events.drain(..).collect()
+    }
+
+    /// Whether the DTE last asked for the line to be off hook, by
+    /// dialling, answering or going off hook.
+    pub fn is_off_hook(&self) -> bool {
+        self.queue.lock().unwrap().off_hook
+    }
+
+    /// Returns the T.31 modem, for T.38 and other settings.
+    pub fn t31(&mut self) -> &mut T31 {
+        &mut self.t31
+    }
+}
+
+impl fmt::Debug for Class1Modem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let queue = self.queue.lock().unwrap();
+        f.debug_struct("Class1Modem")
+            .field("t31", &self.t31)
+            .field("off_hook", &queue.off_hook)
+            .field("pending_events", &queue.events.len())
+            .finish()
+    }
+}
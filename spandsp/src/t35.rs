@@ -0,0 +1,129 @@
+//! T.35 country and vendor codes.
+//!
+//! The non-standard facilities frames of T.30 (NSF, NSC and NSS) open with
+//! a T.35 country code and a provider code, then carry whatever the vendor
+//! likes. spandsp keeps tables of the codes and of the NSF layouts of
+//! common machines; these functions turn a frame into the country, vendor
+//! and, where known, model behind it.
+//!
+//! ```no_run
+//! use spandsp::t35::T35Info;
+//!
+//! // A V.21 frame as heard: address, control, FCF, then the NSF body.
+//! let frame = [0xFF, 0x03, 0x20, 0x00, 0x00, 0x0E, 0x00];
+//! if let Some(info) = T35Info::from_frame(&frame) {
+//!     println!("{info}");
+//! }
+//! ```
+
+use std::fmt;
+use std::os::raw::c_int;
+
+use crate::t30::owned_c_str;
+
+/// T.30 facsimile control fields carrying T.35 data, ignoring the X bit.
+const FCF_NSF: u8 = 0x20;
+const FCF_NSC: u8 = 0xA0;
+const FCF_NSS: u8 = 0x22;
+
+/// The country code that says an extension octet follows.
+const COUNTRY_EXTENSION: u8 = 0xFF;
+
+/// What a non-standard facilities frame says about the machine that sent it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct T35Info {
+    /// The country, if the code is known.
+    pub country: Option<String>,
+    /// The vendor, if the provider code is known.
+    pub vendor: Option<String>,
+    /// The model, if the vendor's layout is known.
+    pub model: Option<String>,
+}
+
+impl T35Info {
+    /// Decode the body of an NSF, NSC or NSS frame, from its country code
+    /// on.
+    pub fn decode(body: &[u8]) -> Self {
+        let mut country = std::ptr::null();
+        let mut vendor = std::ptr::null();
+        let mut model = std::ptr::null();
+        let len = body.len().min(c_int::MAX as usize) as c_int;
+        unsafe {
+            spandsp_sys::t35_decode(body.as_ptr(), len, &mut country, &mut vendor, &mut model);
+            Self {
+                country: owned_c_str(country),
+                vendor: owned_c_str(vendor),
+                model: owned_c_str(model),
+            }
+        }
+    }
+
+    /// Decode a whole HDLC frame as received, from its address octet on,
+    /// if it is an NSF, NSC or NSS.
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        let (&fcf, body) = frame.get(2..)?.split_first()?;
+        match fcf & 0xFE {
+            FCF_NSF | FCF_NSC | FCF_NSS if !body.is_empty() => Some(Self::decode(body)),
+            _ => None,
+        }
+    }
+
+    /// Whether anything was recognised.
+    pub fn is_known(&self) -> bool {
+        self.country.is_some() || self.vendor.is_some()
+    }
+}
+
+impl fmt::Display for T35Info {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<&str> = [&self.vendor, &self.model, &self.country]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        if parts.is_empty() {
+            f.write_str("unknown")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// The name of a T.35 country. `extension` is only used when `code` is
+/// 0xFF. Codes sent bit-reversed need [`real_country_code`] first.
+pub fn country_name(code: u8, extension: u8) -> Option<String> {
+    unsafe {
+        owned_c_str(spandsp_sys::t35_country_code_to_str(
+            code as c_int,
+            extension as c_int,
+        ))
+    }
+}
+
+/// The country code as T.35 defines it, undoing the bit reversal many
+/// machines apply, or `None` if neither order is a known country.
+pub fn real_country_code(code: u8, extension: u8) -> Option<u8> {
+    let real = unsafe { spandsp_sys::t35_real_country_code(code as c_int, extension as c_int) };
+    u8::try_from(real).ok()
+}
+
+/// The vendor named by the start of an NSF, NSC or NSS body.
+pub fn vendor_name(body: &[u8]) -> Option<String> {
+    let len = body.len().min(c_int::MAX as usize) as c_int;
+    unsafe { owned_c_str(spandsp_sys::t35_vendor_to_str(body.as_ptr(), len)) }
+}
+
+/// Split the body of an NSF, NSC or NSS into its country code, extension
+/// octet (0 when there is none), provider code and the vendor's own data.
+pub fn split(body: &[u8]) -> Option<(u8, u8, [u8; 2], &[u8])> {
+    let (&code, rest) = body.split_first()?;
+    let (extension, rest) = if code == COUNTRY_EXTENSION {
+        let (&extension, rest) = rest.split_first()?;
+        (extension, rest)
+    } else {
+        (0, rest)
+    };
+    let provider = rest.get(..2)?;
+    Some((code, extension, [provider[0], provider[1]], &rest[2..]))
+}
@@ -0,0 +1,407 @@
+//! T.37 store-and-forward fax: TIFF attachments for Internet fax by e-mail.
+//!
+//! T.37 carries each fax as a TIFF attachment in the profiles of RFC 3949.
+//! [`TiffProfile::S`] is the minimum every recipient accepts: Modified
+//! Huffman pages 1728 pixels wide at 204 x 98 or 204 x 196 dpi.
+//! [`TiffProfile::F`] adds 2-D and T.6 coding, B4/A3 widths and the higher
+//! resolutions. An onramp reads the TIFF [`T4Rx`] wrote, conforms its pages
+//! to the profile it sends and attaches the result; an offramp checks an
+//! incoming attachment and rewrites it in the one-strip layout [`T4Tx`]
+//! reads.
+//!
+//! ```no_run
+//! use spandsp::t37::{self, FaxPage, TiffProfile};
+//!
+//! // Onramp: a page received over T.30 becomes a Profile S attachment.
+//! let received = std::fs::read("received.tif").unwrap();
+//! let pages = FaxPage::from_tiff(&received)
+//!     .unwrap()
+//!     .into_iter()
+//!     .map(|page| TiffProfile::S.conform(&page))
+//!     .collect::<Result<Vec<_>, _>>()
+//!     .unwrap();
+//! let attachment = t37::write_tiff(&pages, TiffProfile::S).unwrap();
+//!
+//! // Offramp: an attachment becomes a file for `T4Tx`.
+//! let pages = t37::read_tiff(&attachment, TiffProfile::F).unwrap();
+//! std::fs::write("outgoing.tif", t37::write_tiff(&pages, TiffProfile::F).unwrap()).unwrap();
+//! ```
+//!
+//! [`T4Rx`]: crate::t4_rx::T4Rx
+//! [`T4Tx`]: crate::t4_tx::T4Tx
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::{Result, SpanDspError};
+use crate::fax_tiff::{self, CcittCoding, TiffPage};
+use crate::t4::{T4Compression, T4DecodeStatus};
+use crate::t4_rx::T4T6Decoder;
+use crate::t4_tx::T4T6Encoder;
+
+/// MIME content type of a T.37 attachment, for either profile.
+pub const CONTENT_TYPE: &str = "image/tiff; application=faxbw";
+
+// ---------------------------------------------------------------------------
+// FaxPage
+// ---------------------------------------------------------------------------
+
+/// One bilevel fax page, CCITT-coded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaxPage {
+    pub(crate) image: TiffPage,
+}
+
+impl FaxPage {
+    /// Code a bitmap as T.6.
+    ///
+    /// Each row is `width` pixels packed MSB first with 1 for black, as
+    /// produced by [`T4T6Decoder`](crate::t4_rx::T4T6Decoder). Fax
+    /// resolutions are 204 dpi across by 98 (standard) or 196 (fine) down.
+    pub fn from_rows<I, R>(width: u32, rows: I, x_dpi: f32, y_dpi: f32) -> Result<Self>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[u8]>,
+    {
+        Self::encode(T4Compression::T6, width, rows, x_dpi, y_dpi)
+    }
+
+    /// Code a bitmap as T.4 1-D, T.4 2-D or T.6. See [`from_rows`](Self::from_rows)
+    /// for the row format.
+    pub fn encode<I, R>(
+        compression: T4Compression,
+        width: u32,
+        rows: I,
+        x_dpi: f32,
+        y_dpi: f32,
+    ) -> Result<Self>
+    where
+        I: IntoIterator<Item = R>,
+        R: AsRef<[u8]>,
+    {
+        let coding = if compression == T4Compression::T4_1D {
+            CcittCoding::T4 {
+                two_d: false,
+                byte_aligned: false,
+            }
+        } else if compression == T4Compression::T4_2D {
+            CcittCoding::T4 {
+                two_d: true,
+                byte_aligned: false,
+            }
+        } else if compression == T4Compression::T6 {
+            CcittCoding::T6
+        } else {
+            return Err(SpanDspError::InvalidInput(format!(
+                "fax pages are coded T4_1D, T4_2D or T6, not {compression}"
+            )));
+        };
+        if !(x_dpi > 0.0 && y_dpi > 0.0) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "resolution must be positive, got {x_dpi}x{y_dpi} dpi"
+            )));
+        }
+        let row_bytes = width.div_ceil(8) as usize;
+        let mut rows: Vec<Vec<u8>> = rows
+            .into_iter()
+            .map(|row| {
+                let mut row = row.as_ref().to_vec();
+                row.resize(row_bytes, 0);
+                row
+            })
+            .collect();
+        if width == 0 || rows.is_empty() {
+            return Err(SpanDspError::InvalidInput(
+                "fax page must not be empty".into(),
+            ));
+        }
+        let length = rows.len() as u32;
+        rows.reverse();
+        let mut encoder = T4T6Encoder::new(
+            compression,
+            width as i32,
+            length as i32,
+            move |buf: &mut [u8]| match rows.pop() {
+                Some(row) => {
+                    let n = buf.len().min(row.len());
+                    buf[..n].copy_from_slice(&row[..n]);
+                    n
+                }
+                None => 0,
+            },
+        )?;
+        let mut data = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = encoder.get(&mut chunk);
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&chunk[..n]);
+        }
+        // spandsp packs coded bits LSB first; TIFF and PDF readers expect MSB
+        // first unless told otherwise.
+        data.iter_mut().for_each(|b| *b = b.reverse_bits());
+        Ok(Self {
+            image: TiffPage {
+                width,
+                length,
+                coding,
+                inverted: false,
+                x_dpi,
+                y_dpi,
+                data,
+                bad_rows: 0,
+                longest_bad_row_run: 0,
+            },
+        })
+    }
+
+    /// Read the pages of a fax TIFF, keeping their coded data.
+    ///
+    /// Pages must be CCITT-compressed (TIFF compression 2, 3 or 4), as
+    /// written by `T4Rx`.
+    pub fn from_tiff(file: &[u8]) -> Result<Vec<Self>> {
+        Ok(fax_tiff::read_pages(file)?
+            .into_iter()
+            .map(|image| Self { image })
+            .collect())
+    }
+
+    /// Decode the page to rows packed MSB first with 1 for black.
+    ///
+    /// Rows the coded data stops short of come back white. Pages stored as
+    /// TIFF compression 2 cannot be decoded here.
+    pub fn rows(&self) -> Result<Vec<Vec<u8>>> {
+        let compression = match self.image.coding {
+            CcittCoding::ModifiedHuffman => {
+                return Err(SpanDspError::InvalidInput(
+                    "TIFF compression 2 pages cannot be decoded".into(),
+                ));
+            }
+            CcittCoding::T4 { two_d: false, .. } => T4Compression::T4_1D,
+            CcittCoding::T4 { two_d: true, .. } => T4Compression::T4_2D,
+            CcittCoding::T6 => T4Compression::T6,
+        };
+        let decoded = Rc::new(RefCell::new(Vec::new()));
+        let sink = decoded.clone();
+        let mut decoder =
+            T4T6Decoder::new(compression, self.image.width as i32, move |row: &[u8]| {
+                // An empty row marks the end of the image.
+                if !row.is_empty() {
+                    sink.borrow_mut().push(row.to_vec());
+                }
+                true
+            })?;
+        let data: Vec<u8> = self.image.data.iter().map(|b| b.reverse_bits()).collect();
+        if decoder.put(&data) == T4DecodeStatus::InvalidData {
+            return Err(SpanDspError::InvalidInput(
+                "fax page image data is corrupt".into(),
+            ));
+        }
+        drop(decoder);
+        let mut rows = Rc::try_unwrap(decoded)
+            .map(RefCell::into_inner)
+            .unwrap_or_default();
+        let row_bytes = self.image.width.div_ceil(8) as usize;
+        rows.resize(self.image.length as usize, vec![0; row_bytes]);
+        if self.image.inverted {
+            rows.iter_mut().flatten().for_each(|b| *b = !*b);
+        }
+        Ok(rows)
+    }
+
+    /// Decode the page and code it again with `compression`, keeping its
+    /// resolution and line quality.
+    pub fn recode(&self, compression: T4Compression) -> Result<Self> {
+        let mut page = Self::encode(
+            compression,
+            self.image.width,
+            self.rows()?,
+            self.image.x_dpi,
+            self.image.y_dpi,
+        )?;
+        page.set_bad_rows(self.image.bad_rows, self.image.longest_bad_row_run);
+        Ok(page)
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> u32 {
+        self.image.width
+    }
+
+    /// Length in rows.
+    pub fn length(&self) -> u32 {
+        self.image.length
+    }
+
+    /// Horizontal resolution in dots per inch.
+    pub fn x_dpi(&self) -> f32 {
+        self.image.x_dpi
+    }
+
+    /// Vertical resolution in dots per inch.
+    pub fn y_dpi(&self) -> f32 {
+        self.image.y_dpi
+    }
+
+    /// Size of the coded image in bytes.
+    pub fn coded_size(&self) -> usize {
+        self.image.data.len()
+    }
+
+    /// Record how many rows arrived damaged and the longest run of them,
+    /// e.g. from [`T4Stats`](crate::t4::T4Stats). Profile F attachments
+    /// carry these in their TIFF tags.
+    pub fn set_bad_rows(&mut self, bad_rows: u32, longest_run: u32) {
+        self.image.bad_rows = bad_rows;
+        self.image.longest_bad_row_run = longest_run;
+    }
+
+    /// Rows that arrived damaged.
+    pub fn bad_rows(&self) -> u32 {
+        self.image.bad_rows
+    }
+
+    /// Longest run of damaged rows.
+    pub fn longest_bad_row_run(&self) -> u32 {
+        self.image.longest_bad_row_run
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TiffProfile
+// ---------------------------------------------------------------------------
+
+/// A horizontal resolution in dpi, the page widths allowed at it and the
+/// vertical resolutions that go with it.
+type Format = (f32, &'static [u32], &'static [f32]);
+
+const PROFILE_S_FORMATS: [Format; 1] = [(204.0, &[1728], &[98.0, 196.0])];
+
+const PROFILE_F_FORMATS: [Format; 5] = [
+    (204.0, &[1728, 2048, 2432], &[98.0, 196.0, 391.0]),
+    (200.0, &[1728, 2048, 2432], &[100.0, 200.0]),
+    (300.0, &[2592, 3072, 3648], &[300.0]),
+    (408.0, &[3456, 4096, 4864], &[391.0]),
+    (400.0, &[3456, 4096, 4864], &[400.0]),
+];
+
+/// A TIFF profile for Internet fax (RFC 3949).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TiffProfile {
+    /// Minimal black-and-white: T.4 1-D, A4 width, standard or fine.
+    S,
+    /// Extended black-and-white: T.4 1-D/2-D or T.6, A4/B4/A3 widths,
+    /// resolutions up to 400 dpi.
+    F,
+}
+
+impl TiffProfile {
+    /// Check that `page` fits the profile.
+    pub fn check(self, page: &FaxPage) -> Result<()> {
+        match self.problem(page) {
+            None => Ok(()),
+            Some(why) => Err(SpanDspError::InvalidInput(format!(
+                "page is not TIFF Profile {self}: {why}"
+            ))),
+        }
+    }
+
+    /// What stops `page` fitting the profile, if anything.
+    pub(crate) fn problem(self, page: &FaxPage) -> Option<String> {
+        let image = &page.image;
+        if image.inverted {
+            return Some("photometric must be WhiteIsZero".into());
+        }
+        let coding_ok = match (self, image.coding) {
+            (TiffProfile::S, CcittCoding::T4 { two_d, .. }) => !two_d,
+            (TiffProfile::F, CcittCoding::T4 { .. } | CcittCoding::T6) => true,
+            _ => false,
+        };
+        if !coding_ok {
+            return Some(format!("{:?} coding is not allowed", image.coding));
+        }
+        let formats: &[Format] = match self {
+            TiffProfile::S => &PROFILE_S_FORMATS,
+            TiffProfile::F => &PROFILE_F_FORMATS,
+        };
+        let Some((_, widths, y_dpis)) = formats.iter().find(|(x, ..)| near(*x, image.x_dpi)) else {
+            return Some(format!("{} dpi across is not allowed", image.x_dpi));
+        };
+        if !widths.contains(&image.width) {
+            return Some(format!(
+                "{} pixels wide at {} dpi is not allowed",
+                image.width, image.x_dpi
+            ));
+        }
+        if !y_dpis.iter().any(|&y| near(y, image.y_dpi)) {
+            return Some(format!(
+                "{} x {} dpi is not allowed",
+                image.x_dpi, image.y_dpi
+            ));
+        }
+        None
+    }
+
+    /// Return `page` in a form that fits the profile, recoding it as T.4
+    /// 1-D if its coding is the only problem.
+    pub fn conform(self, page: &FaxPage) -> Result<FaxPage> {
+        if self.check(page).is_ok() {
+            return Ok(page.clone());
+        }
+        let recoded = page.recode(T4Compression::T4_1D)?;
+        self.check(&recoded)?;
+        Ok(recoded)
+    }
+}
+
+impl fmt::Display for TiffProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TiffProfile::S => "S",
+            TiffProfile::F => "F",
+        })
+    }
+}
+
+/// Resolutions match to within a dot per inch, so metric 8 pels/mm
+/// (203.2 dpi) counts as 204.
+fn near(a: f32, b: f32) -> bool {
+    (a - b).abs() <= 1.0
+}
+
+// ---------------------------------------------------------------------------
+// Attachments
+// ---------------------------------------------------------------------------
+
+/// Write `pages` as a T.37 attachment in `profile`.
+///
+/// Every page must pass [`TiffProfile::check`]; use
+/// [`TiffProfile::conform`] first if it may not. Profile F files also
+/// record each page's bad rows.
+pub fn write_tiff(pages: &[FaxPage], profile: TiffProfile) -> Result<Vec<u8>> {
+    if pages.is_empty() {
+        return Err(SpanDspError::InvalidInput(
+            "attachment needs at least one page".into(),
+        ));
+    }
+    for page in pages {
+        profile.check(page)?;
+    }
+    let images: Vec<TiffPage> = pages.iter().map(|page| page.image.clone()).collect();
+    Ok(fax_tiff::write_pages(&images, profile == TiffProfile::F))
+}
+
+/// Read a T.37 attachment, checking every page against `profile`.
+///
+/// Offramps should read with [`TiffProfile::F`], which accepts Profile S
+/// files too.
+pub fn read_tiff(file: &[u8], profile: TiffProfile) -> Result<Vec<FaxPage>> {
+    let pages = FaxPage::from_tiff(file)?;
+    for page in &pages {
+        profile.check(page)?;
+    }
+    Ok(pages)
+}
@@ -2,10 +2,15 @@
 //!
 //! T.38 is the ITU standard for real-time FAX over IP. The core module
 //! handles IFP packet encoding/decoding and sequence number management.
+//! [`IfpMessage::parse`] and [`IfpMessage::to_bytes`] work on IFP packets
+//! without an engine, for capture analysis and middle-boxes.
 
+use std::collections::VecDeque;
 use std::fmt;
-use std::os::raw::c_int;
+use std::marker::PhantomData;
+use std::os::raw::{c_int, c_void};
 use std::ptr::NonNull;
+use std::time::SystemTime;
 
 use crate::error::{Result, SpanDspError};
 
@@ -22,6 +27,40 @@ impl T38Indicator {
     pub const CED: Self = Self(spandsp_sys::t30_indicator_types_e::T38_IND_CED);
     /// V.21 preamble flags detected.
     pub const V21_PREAMBLE: Self = Self(spandsp_sys::t30_indicator_types_e::T38_IND_V21_PREAMBLE);
+
+    /// Look up an indicator by its `t30_indicator_types_e` value.
+    pub fn from_code(code: i32) -> Option<Self> {
+        use spandsp_sys::t30_indicator_types_e::*;
+        const ALL: [spandsp_sys::t30_indicator_types_e; 23] = [
+            T38_IND_NO_SIGNAL,
+            T38_IND_CNG,
+            T38_IND_CED,
+            T38_IND_V21_PREAMBLE,
+            T38_IND_V27TER_2400_TRAINING,
+            T38_IND_V27TER_4800_TRAINING,
+            T38_IND_V29_7200_TRAINING,
+            T38_IND_V29_9600_TRAINING,
+            T38_IND_V17_7200_SHORT_TRAINING,
+            T38_IND_V17_7200_LONG_TRAINING,
+            T38_IND_V17_9600_SHORT_TRAINING,
+            T38_IND_V17_9600_LONG_TRAINING,
+            T38_IND_V17_12000_SHORT_TRAINING,
+            T38_IND_V17_12000_LONG_TRAINING,
+            T38_IND_V17_14400_SHORT_TRAINING,
+            T38_IND_V17_14400_LONG_TRAINING,
+            T38_IND_V8_ANSAM,
+            T38_IND_V8_SIGNAL,
+            T38_IND_V34_CNTL_CHANNEL_1200,
+            T38_IND_V34_PRI_CHANNEL,
+            T38_IND_V34_CC_RETRAIN,
+            T38_IND_V33_12000_TRAINING,
+            T38_IND_V33_14400_TRAINING,
+        ];
+        usize::try_from(code)
+            .ok()
+            .and_then(|i| ALL.get(i))
+            .map(|&v| Self(v))
+    }
 }
 
 impl fmt::Display for T38Indicator {
@@ -91,6 +130,32 @@ impl T38DataType {
     pub const V17_12000: Self = Self(spandsp_sys::t38_data_types_e::T38_DATA_V17_12000);
     /// V.17 at 14400 bps.
     pub const V17_14400: Self = Self(spandsp_sys::t38_data_types_e::T38_DATA_V17_14400);
+
+    /// Look up a data type by its `t38_data_types_e` value.
+    pub fn from_code(code: i32) -> Option<Self> {
+        use spandsp_sys::t38_data_types_e::*;
+        const ALL: [spandsp_sys::t38_data_types_e; 15] = [
+            T38_DATA_V21,
+            T38_DATA_V27TER_2400,
+            T38_DATA_V27TER_4800,
+            T38_DATA_V29_7200,
+            T38_DATA_V29_9600,
+            T38_DATA_V17_7200,
+            T38_DATA_V17_9600,
+            T38_DATA_V17_12000,
+            T38_DATA_V17_14400,
+            T38_DATA_V8,
+            T38_DATA_V34_PRI_RATE,
+            T38_DATA_V34_CC_1200,
+            T38_DATA_V34_PRI_CH,
+            T38_DATA_V33_12000,
+            T38_DATA_V33_14400,
+        ];
+        usize::try_from(code)
+            .ok()
+            .and_then(|i| ALL.get(i))
+            .map(|&v| Self(v))
+    }
 }
 
 impl fmt::Display for T38DataType {
@@ -149,6 +214,29 @@ impl T38FieldType {
     /// End of T.4 non-ECM signal.
     pub const T4_NON_ECM_SIG_END: Self =
         Self(spandsp_sys::t38_field_types_e::T38_FIELD_T4_NON_ECM_SIG_END);
+
+    /// Look up a field type by its `t38_field_types_e` value.
+    pub fn from_code(code: i32) -> Option<Self> {
+        use spandsp_sys::t38_field_types_e::*;
+        const ALL: [spandsp_sys::t38_field_types_e; 12] = [
+            T38_FIELD_HDLC_DATA,
+            T38_FIELD_HDLC_SIG_END,
+            T38_FIELD_HDLC_FCS_OK,
+            T38_FIELD_HDLC_FCS_BAD,
+            T38_FIELD_HDLC_FCS_OK_SIG_END,
+            T38_FIELD_HDLC_FCS_BAD_SIG_END,
+            T38_FIELD_T4_NON_ECM_DATA,
+            T38_FIELD_T4_NON_ECM_SIG_END,
+            T38_FIELD_CM_MESSAGE,
+            T38_FIELD_JM_MESSAGE,
+            T38_FIELD_CI_MESSAGE,
+            T38_FIELD_V34RATE,
+        ];
+        usize::try_from(code)
+            .ok()
+            .and_then(|i| ALL.get(i))
+            .map(|&v| Self(v))
+    }
 }
 
 impl fmt::Display for T38FieldType {
@@ -227,6 +315,17 @@ pub enum T38PacketCategory {
     ImageDataEnd = spandsp_sys::t38_packet_categories_e_T38_PACKET_CATEGORY_IMAGE_DATA_END,
 }
 
+impl T38PacketCategory {
+    /// Every category, in `t38_packet_categories_e` order.
+    pub const ALL: [Self; 5] = [
+        Self::Indicator,
+        Self::ControlData,
+        Self::ControlDataEnd,
+        Self::ImageData,
+        Self::ImageDataEnd,
+    ];
+}
+
 /// T.38 protocol version.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(i32)]
@@ -251,17 +350,1240 @@ pub enum T38DataRateManagement {
     TransferredTcf = 2,
 }
 
+/// Most copies of one packet a [`RedundancyPolicy`] may ask for.
+pub const MAX_PACKET_REPEATS: u8 = 8;
+
+/// How many times each category of IFP packet is sent.
+///
+/// Unreliable transports lose packets; sending the ones that change the
+/// engine's state more than once (indicators and the end of each data
+/// stream) lets a call survive a lossy path. Apply with
+/// [`T38Core::set_redundancy_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedundancyPolicy {
+    /// Copies of each indicator packet.
+    pub indicator: u8,
+    /// Copies of each control (HDLC) data packet.
+    pub control_data: u8,
+    /// Copies of the packet ending a control data stream.
+    pub control_data_end: u8,
+    /// Copies of each image data packet.
+    pub image_data: u8,
+    /// Copies of the packet ending an image data stream.
+    pub image_data_end: u8,
+}
+
+impl Default for RedundancyPolicy {
+    /// spandsp's defaults: three copies of indicators and stream ends, one
+    /// of everything else.
+    fn default() -> Self {
+        Self {
+            indicator: 3,
+            control_data: 1,
+            control_data_end: 3,
+            image_data: 1,
+            image_data_end: 3,
+        }
+    }
+}
+
+impl RedundancyPolicy {
+    /// Send every packet `copies` times.
+    pub fn uniform(copies: u8) -> Self {
+        Self {
+            indicator: copies,
+            control_data: copies,
+            control_data_end: copies,
+            image_data: copies,
+            image_data_end: copies,
+        }
+    }
+
+    /// Copies sent of packets in `category`.
+    pub fn repeats(&self, category: T38PacketCategory) -> u8 {
+        match category {
+            T38PacketCategory::Indicator => self.indicator,
+            T38PacketCategory::ControlData => self.control_data,
+            T38PacketCategory::ControlDataEnd => self.control_data_end,
+            T38PacketCategory::ImageData => self.image_data,
+            T38PacketCategory::ImageDataEnd => self.image_data_end,
+        }
+    }
+
+    /// The policy for streaming transports (TCP/TPKT): no indicators, one
+    /// copy of everything else.
+    pub fn streaming() -> Self {
+        Self {
+            indicator: 0,
+            ..Self::uniform(1)
+        }
+    }
+
+    fn repeats_mut(&mut self, category: T38PacketCategory) -> &mut u8 {
+        match category {
+            T38PacketCategory::Indicator => &mut self.indicator,
+            T38PacketCategory::ControlData => &mut self.control_data,
+            T38PacketCategory::ControlDataEnd => &mut self.control_data_end,
+            T38PacketCategory::ImageData => &mut self.image_data,
+            T38PacketCategory::ImageDataEnd => &mut self.image_data_end,
+        }
+    }
+
+    /// Check every count is between 1 and [`MAX_PACKET_REPEATS`]. Indicators
+    /// may also be turned off with 0.
+    pub fn validate(&self) -> Result<()> {
+        for category in T38PacketCategory::ALL {
+            let repeats = self.repeats(category);
+            let min = u8::from(category != T38PacketCategory::Indicator);
+            if !(min..=MAX_PACKET_REPEATS).contains(&repeats) {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "{category:?} packets must be sent {min}-{MAX_PACKET_REPEATS} times, got {repeats}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Stream transports
+// ---------------------------------------------------------------------------
+
+/// How IFP packets travel between T.38 peers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum T38Transport {
+    /// One IFP packet per datagram, wrapped in UDPTL by the caller; see
+    /// [`IfpUdptlFramer`].
+    #[default]
+    Udptl,
+    /// One IFP packet per RTP packet (RFC 4612), with sequence numbers
+    /// taken from the RTP header; see [`IfpRtpFramer`].
+    Rtp,
+    /// IFP packets back to back on a TCP connection.
+    Tcp,
+    /// IFP packets on a TCP connection, each in a TPKT (RFC 1006) header.
+    TcpTpkt,
+}
+
+/// TPKT version number.
+const TPKT_VERSION: u8 = 3;
+/// Bytes in a TPKT header: version, reserved and a 16-bit length that
+/// counts the header itself.
+const TPKT_HEADER_LEN: usize = 4;
+
+impl T38Transport {
+    /// Whether the transport delivers every packet in order, so sequence
+    /// checks and repeated packets are not needed.
+    pub fn is_reliable(self) -> bool {
+        matches!(self, Self::Tcp | Self::TcpTpkt)
+    }
+
+    /// Frame an IFP packet from the transmit handler for this transport.
+    ///
+    /// RTP headers carry per-stream state, so [`Rtp`](Self::Rtp) packets
+    /// are framed with an [`IfpRtpFramer`] instead and this is an error.
+    pub fn frame(self, ifp: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Rtp => {
+                return Err(SpanDspError::InvalidInput(
+                    "RTP framing needs an IfpRtpFramer".into(),
+                ));
+            }
+            Self::Udptl | Self::Tcp => return Ok(ifp.to_vec()),
+            Self::TcpTpkt => {}
+        }
+        let len = u16::try_from(ifp.len() + TPKT_HEADER_LEN).map_err(|_| {
+            SpanDspError::InvalidInput(format!("IFP packet too long for TPKT: {}", ifp.len()))
+        })?;
+        let mut framed = Vec::with_capacity(usize::from(len));
+        framed.extend_from_slice(&[TPKT_VERSION, 0]);
+        framed.extend_from_slice(&len.to_be_bytes());
+        framed.extend_from_slice(ifp);
+        Ok(framed)
+    }
+}
+
+/// Bytes taken by the IFP packet at the start of `buf`, or `None` if more
+/// are needed to tell. Walks the same structure as [`decode_ifp`], without
+/// checking the codes.
+fn ifp_stream_length(buf: &[u8], t38_version: i32) -> Option<usize> {
+    let first = *buf.first()?;
+    let header = if first & 0x20 != 0 { 2 } else { 1 };
+    if first & 0x40 == 0 || first & 0x80 == 0 {
+        return (buf.len() >= header).then_some(header);
+    }
+    let count = *buf.get(header)?;
+    let mut ptr = header + 1;
+    for _ in 0..count {
+        let octet = *buf.get(ptr)?;
+        ptr += if t38_version != 0 && octet & 0x40 != 0 {
+            2
+        } else {
+            1
+        };
+        if octet & 0x80 != 0 {
+            let len = u16::from_be_bytes([*buf.get(ptr)?, *buf.get(ptr + 1)?]) as usize + 1;
+            ptr += 2 + len;
+        }
+    }
+    (buf.len() >= ptr).then_some(ptr)
+}
+
+/// Splits the byte stream of a TCP T.38 connection into IFP packets for
+/// [`T38Core::rx_ifp_stream`].
+///
+/// TCP hands over bytes in whatever chunks it likes; the reader buffers
+/// them until a whole packet is there.
+#[derive(Debug, Clone)]
+pub struct IfpStreamReader {
+    transport: T38Transport,
+    /// Needed to find the end of each packet, since field types are coded
+    /// differently in version 0.
+    t38_version: T38Version,
+    buf: Vec<u8>,
+    /// Count of packets handled, used as the sequence number in logs and
+    /// traces.
+    seq_no: u16,
+}
+
+impl IfpStreamReader {
+    /// Read a stream carried by `transport` in the T.38 version negotiated
+    /// for the call.
+    ///
+    /// Only the TCP transports carry a byte stream; any transport that is
+    /// not [`is_reliable`](T38Transport::is_reliable) is `InvalidInput`.
+    pub fn new(transport: T38Transport, t38_version: T38Version) -> Result<Self> {
+        if !transport.is_reliable() {
+            return Err(SpanDspError::InvalidInput(format!(
+                "{transport:?} carries packets, not a stream"
+            )));
+        }
+        Ok(Self {
+            transport,
+            t38_version,
+            buf: Vec::new(),
+            seq_no: 0,
+        })
+    }
+
+    /// Feed bytes received on the connection to `core`, returning the
+    /// number of IFP packets they completed.
+    ///
+    /// A malformed packet or TPKT header is an error, after which the
+    /// stream cannot be resynchronised; the connection should be dropped.
+    pub fn feed(&mut self, core: &mut T38Core<'_>, data: &[u8]) -> Result<usize> {
+        self.buf.extend_from_slice(data);
+        let mut start = 0;
+        let mut packets = 0;
+        let result = loop {
+            let pending = &self.buf[start..];
+            let (offset, len) = match self.transport {
+                T38Transport::TcpTpkt => {
+                    let &[version, _, hi, lo, ..] = pending else {
+                        break Ok(packets);
+                    };
+                    let total = usize::from(u16::from_be_bytes([hi, lo]));
+                    if version != TPKT_VERSION || total <= TPKT_HEADER_LEN {
+                        break Err(SpanDspError::InvalidInput(format!(
+                            "bad TPKT header: version {version}, length {total}"
+                        )));
+                    }
+                    if pending.len() < total {
+                        break Ok(packets);
+                    }
+                    (TPKT_HEADER_LEN, total - TPKT_HEADER_LEN)
+                }
+                // Tcp: `new` refuses the packet transports.
+                _ => match ifp_stream_length(pending, self.t38_version as i32) {
+                    Some(len) => (0, len),
+                    None => break Ok(packets),
+                },
+            };
+            let packet = &self.buf[start + offset..start + offset + len];
+            let used = core.rx_ifp_stream(packet, self.seq_no)?;
+            if used != len {
+                break Err(SpanDspError::InvalidInput(format!(
+                    "IFP packet of {len} bytes only {used} long"
+                )));
+            }
+            self.seq_no = self.seq_no.wrapping_add(1);
+            start += offset + len;
+            packets += 1;
+        };
+        self.buf.drain(..start);
+        result
+    }
+
+    /// Bytes received but not yet part of a whole packet.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// RTP transport
+// ---------------------------------------------------------------------------
+
+/// RTP version number.
+const RTP_VERSION: u8 = 2;
+/// Bytes in an RTP header without CSRCs or extension.
+const RTP_HEADER_LEN: usize = 12;
+
+/// Carries IFP packets in RTP (RFC 4612), in place of UDPTL.
+///
+/// Each IFP packet is the whole payload of one RTP packet, and the RTP
+/// sequence number is the one spandsp checks for loss and reordering.
+/// There is no UDPTL redundancy or FEC (see [`IfpUdptlFramer`]); losses
+/// are covered only by the engine's own repeats (see [`RedundancyPolicy`]). Set the engine up with
+/// [`T38Core::set_transport`] and [`T38Transport::Rtp`].
+#[derive(Debug, Clone)]
+pub struct IfpRtpFramer {
+    payload_type: u8,
+    ssrc: u32,
+    /// Sequence number of the next packet framed.
+    seq_no: u16,
+}
+
+impl IfpRtpFramer {
+    /// Frame packets with the dynamic `payload_type` negotiated for
+    /// `audio/t38`, from source `ssrc`.
+    pub fn new(payload_type: u8, ssrc: u32) -> Result<Self> {
+        if payload_type > 0x7F {
+            return Err(SpanDspError::InvalidInput(format!(
+                "RTP payload type {payload_type} is over 127"
+            )));
+        }
+        Ok(Self {
+            payload_type,
+            ssrc,
+            seq_no: 0,
+        })
+    }
+
+    /// Start sequence numbers from `seq_no`, which RFC 3550 asks to be
+    /// random.
+    pub fn with_initial_seq_no(mut self, seq_no: u16) -> Self {
+        self.seq_no = seq_no;
+        self
+    }
+
+    /// The payload type sent.
+    pub fn payload_type(&self) -> u8 {
+        self.payload_type
+    }
+
+    /// The sequence number the next framed packet will carry.
+    pub fn next_seq_no(&self) -> u16 {
+        self.seq_no
+    }
+
+    /// Wrap an IFP packet from the transmit handler in an RTP header, with
+    /// `timestamp` in the session's clock rate. Each call uses the next
+    /// sequence number, so repeats from the engine go out as separate
+    /// packets, as RFC 4612 expects.
+    pub fn frame(&mut self, ifp: &[u8], timestamp: u32) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(RTP_HEADER_LEN + ifp.len());
+        packet.extend_from_slice(&[RTP_VERSION << 6, self.payload_type]);
+        packet.extend_from_slice(&self.seq_no.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(ifp);
+        self.seq_no = self.seq_no.wrapping_add(1);
+        packet
+    }
+
+    /// Hand the IFP packet in a received RTP packet to `core`, returning
+    /// its sequence number.
+    ///
+    /// CSRCs, header extensions and padding are skipped. Packets of other
+    /// payload types are rejected, so RTP events or comfort noise sharing
+    /// the stream are not mistaken for IFP.
+    pub fn feed(&mut self, core: &mut T38Core<'_>, packet: &[u8]) -> Result<u16> {
+        let (seq_no, ifp) = self.payload(packet)?;
+        core.rx_ifp_packet(ifp, seq_no)?;
+        Ok(seq_no)
+    }
+
+    fn payload<'a>(&self, packet: &'a [u8]) -> Result<(u16, &'a [u8])> {
+        let bad = |what: &str| SpanDspError::InvalidInput(format!("bad RTP packet: {what}"));
+        if packet.len() < RTP_HEADER_LEN {
+            return Err(bad("too short"));
+        }
+        let (first, second) = (packet[0], packet[1]);
+        if first >> 6 != RTP_VERSION {
+            return Err(bad("not version 2"));
+        }
+        if second & 0x7F != self.payload_type {
+            return Err(bad("unexpected payload type"));
+        }
+        let mut start = RTP_HEADER_LEN + 4 * usize::from(first & 0x0F);
+        if first & 0x10 != 0 {
+            let ext = packet
+                .get(start + 2..start + 4)
+                .ok_or_else(|| bad("too short"))?;
+            start += 4 + 4 * usize::from(u16::from_be_bytes([ext[0], ext[1]]));
+        }
+        let mut end = packet.len();
+        if first & 0x20 != 0 {
+            end = end.saturating_sub(usize::from(packet[end - 1]));
+        }
+        let ifp = packet.get(start..end).ok_or_else(|| bad("too short"))?;
+        if ifp.is_empty() {
+            return Err(bad("no payload"));
+        }
+        Ok((u16::from_be_bytes([packet[2], packet[3]]), ifp))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// UDPTL transport
+// ---------------------------------------------------------------------------
+
+/// Most earlier packets one UDPTL packet can carry or protect: the deepest
+/// [`UdptlRedundancy`], or an FEC span times its entries.
+pub const MAX_UDPTL_WINDOW: u8 = 16;
+
+/// How many of the following UDPTL packets repeat a packet as secondary
+/// data, by its category.
+///
+/// Secondary packets run back from the primary without gaps, so each packet
+/// carries every packet back to the oldest one still within its category's
+/// depth. Apply with [`UdptlErrorRecovery::Redundancy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UdptlRedundancy {
+    /// Packets repeating each indicator packet.
+    pub indicator: u8,
+    /// Packets repeating each control (HDLC) data packet.
+    pub control_data: u8,
+    /// Packets repeating the packet ending a control data stream.
+    pub control_data_end: u8,
+    /// Packets repeating each image data packet.
+    pub image_data: u8,
+    /// Packets repeating the packet ending an image data stream.
+    pub image_data_end: u8,
+}
+
+impl Default for UdptlRedundancy {
+    /// Three packets for every category, the depth spandsp's own UDPTL
+    /// test harness sends.
+    fn default() -> Self {
+        Self::uniform(3)
+    }
+}
+
+impl UdptlRedundancy {
+    /// Repeat every packet in the `depth` packets after it.
+    pub fn uniform(depth: u8) -> Self {
+        Self {
+            indicator: depth,
+            control_data: depth,
+            control_data_end: depth,
+            image_data: depth,
+            image_data_end: depth,
+        }
+    }
+
+    /// Packets repeating a packet in `category`.
+    pub fn depth(&self, category: T38PacketCategory) -> u8 {
+        match category {
+            T38PacketCategory::Indicator => self.indicator,
+            T38PacketCategory::ControlData => self.control_data,
+            T38PacketCategory::ControlDataEnd => self.control_data_end,
+            T38PacketCategory::ImageData => self.image_data,
+            T38PacketCategory::ImageDataEnd => self.image_data_end,
+        }
+    }
+}
+
+/// The error recovery a UDPTL sender adds to each packet (T.38 clause 9).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UdptlErrorRecovery {
+    /// Copies of earlier packets, sent as secondary IFP packets.
+    Redundancy(UdptlRedundancy),
+    /// Parity of earlier packets. Each of `entries` FEC entries is the XOR
+    /// of `span` packets taken `entries` apart, so a run of up to `entries`
+    /// lost packets can be rebuilt from the next one that arrives.
+    Fec {
+        /// Packets XORed into each entry.
+        span: u8,
+        /// Entries in each packet.
+        entries: u8,
+    },
+}
+
+impl Default for UdptlErrorRecovery {
+    fn default() -> Self {
+        Self::Redundancy(UdptlRedundancy::default())
+    }
+}
+
+impl UdptlErrorRecovery {
+    /// Check the packets covered fit in [`MAX_UDPTL_WINDOW`], and that FEC
+    /// has a non-zero span and entry count.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Self::Redundancy(depths) => {
+                for category in T38PacketCategory::ALL {
+                    let depth = depths.depth(category);
+                    if depth > MAX_UDPTL_WINDOW {
+                        return Err(SpanDspError::InvalidInput(format!(
+                            "{category:?} packets can be repeated in at most {MAX_UDPTL_WINDOW} packets, got {depth}"
+                        )));
+                    }
+                }
+            }
+            Self::Fec { span, entries } => {
+                if span == 0
+                    || entries == 0
+                    || u16::from(span) * u16::from(entries) > u16::from(MAX_UDPTL_WINDOW)
+                {
+                    return Err(SpanDspError::InvalidInput(format!(
+                        "FEC must cover 1-{MAX_UDPTL_WINDOW} packets with a non-zero span and entry count, got span {span} with {entries} entries"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What an [`IfpUdptlFramer`] made of the packets fed to it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UdptlStats {
+    /// UDPTL packets accepted, not counting late ones.
+    pub received: u64,
+    /// Missing packets rebuilt from the secondary packets of a later one.
+    pub recovered_redundancy: u64,
+    /// Missing packets rebuilt from the FEC entries of a later one.
+    pub recovered_fec: u64,
+    /// Missing packets that could not be rebuilt.
+    pub lost: u64,
+    /// Packets that arrived after a later one, and were dropped.
+    pub late: u64,
+}
+
+impl UdptlStats {
+    /// Missing packets rebuilt, by either means.
+    pub fn recovered(&self) -> u64 {
+        self.recovered_redundancy + self.recovered_fec
+    }
+}
+
+/// Append an aligned PER length determinant. IFP packets never need the
+/// fragmented form used from 16K up.
+fn push_per_length(buf: &mut Vec<u8>, len: usize) -> Result<()> {
+    match u16::try_from(len) {
+        Ok(len @ 0..0x80) => buf.push(len as u8),
+        Ok(len @ 0x80..0x4000) => buf.extend_from_slice(&(0x8000 | len).to_be_bytes()),
+        _ => {
+            return Err(SpanDspError::InvalidInput(format!(
+                "UDPTL field of {len} bytes, limit is 16383"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Append a PER open type: a length determinant and the bytes.
+fn push_open_type(buf: &mut Vec<u8>, data: &[u8]) -> Result<()> {
+    push_per_length(buf, data.len())?;
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+/// XOR `data` into `fec`, as if the shorter of the two were padded with
+/// zeros.
+fn xor_into(fec: &mut Vec<u8>, data: &[u8]) {
+    if fec.len() < data.len() {
+        fec.resize(data.len(), 0);
+    }
+    for (f, d) in fec.iter_mut().zip(data) {
+        *f ^= d;
+    }
+}
+
+/// The category spandsp would send an IFP packet under, for choosing its
+/// redundancy depth. Packets that do not decode count as image data.
+fn ifp_category(ifp: &[u8], t38_version: T38Version) -> T38PacketCategory {
+    use spandsp_sys::t38_data_types_e::*;
+    use spandsp_sys::t38_field_types_e::*;
+
+    let Some(message) = decode_ifp(ifp, t38_version as i32) else {
+        return T38PacketCategory::ImageData;
+    };
+    let (data_type, fields) = match message {
+        IfpMessage::Indicator(_) => return T38PacketCategory::Indicator,
+        IfpMessage::Data { data_type, fields } => (data_type, fields),
+    };
+    let control = matches!(
+        data_type.0,
+        T38_DATA_V21 | T38_DATA_V8 | T38_DATA_V34_CC_1200
+    );
+    let end = fields.iter().any(|field| {
+        matches!(
+            field.field_type.0,
+            T38_FIELD_HDLC_SIG_END
+                | T38_FIELD_HDLC_FCS_OK_SIG_END
+                | T38_FIELD_HDLC_FCS_BAD_SIG_END
+                | T38_FIELD_T4_NON_ECM_SIG_END
+        )
+    });
+    match (control, end) {
+        (true, false) => T38PacketCategory::ControlData,
+        (true, true) => T38PacketCategory::ControlDataEnd,
+        (false, false) => T38PacketCategory::ImageData,
+        (false, true) => T38PacketCategory::ImageDataEnd,
+    }
+}
+
+/// Reads the fields of a received UDPTL packet.
+struct UdptlReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> UdptlReader<'a> {
+    fn bad(what: &str) -> SpanDspError {
+        SpanDspError::InvalidInput(format!("bad UDPTL packet: {what}"))
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .buf
+            .get(self.pos)
+            .ok_or_else(|| Self::bad("too short"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn length(&mut self) -> Result<usize> {
+        let first = self.byte()?;
+        match first {
+            0..0x80 => Ok(usize::from(first)),
+            0x80..0xC0 => Ok(usize::from(u16::from_be_bytes([
+                first & 0x3F,
+                self.byte()?,
+            ]))),
+            _ => Err(Self::bad("fragmented field")),
+        }
+    }
+
+    fn open_type(&mut self) -> Result<&'a [u8]> {
+        let len = self.length()?;
+        let data = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| Self::bad("too short"))?;
+        self.pos += len;
+        Ok(data)
+    }
+}
+
+/// Carries IFP packets in UDPTL (T.38 clause 9), with redundancy or FEC so
+/// lost packets can be rebuilt from later ones.
+///
+/// Frame each packet from the transmit handler with
+/// [`frame`](Self::frame), and pass each received datagram to
+/// [`feed`](Self::feed), which hands the engine any packets it can rebuild
+/// ahead of the new one. The error recovery chosen applies to the packets
+/// sent; received packets are read with whichever the peer uses. Set the
+/// engine up with [`T38Core::set_transport`] and [`T38Transport::Udptl`].
+#[derive(Debug, Clone)]
+pub struct IfpUdptlFramer {
+    recovery: UdptlErrorRecovery,
+    /// Needed to find where a rebuilt packet ends, and the category of each
+    /// packet sent.
+    t38_version: T38Version,
+    /// Sequence number of the next packet framed.
+    seq_no: u16,
+    /// Recently framed packets, newest first.
+    sent: VecDeque<(Vec<u8>, T38PacketCategory)>,
+    /// Sequence number expected next, once a packet has arrived.
+    rx_seq_no: Option<u16>,
+    /// Recently received or rebuilt packets, newest first.
+    received: VecDeque<(u16, Vec<u8>)>,
+    stats: UdptlStats,
+}
+
+impl IfpUdptlFramer {
+    /// Frame packets with `recovery`, for a call using `t38_version`.
+    pub fn new(recovery: UdptlErrorRecovery, t38_version: T38Version) -> Result<Self> {
+        recovery.validate()?;
+        Ok(Self {
+            recovery,
+            t38_version,
+            seq_no: 0,
+            sent: VecDeque::new(),
+            rx_seq_no: None,
+            received: VecDeque::new(),
+            stats: UdptlStats::default(),
+        })
+    }
+
+    /// The error recovery added to framed packets.
+    pub fn error_recovery(&self) -> UdptlErrorRecovery {
+        self.recovery
+    }
+
+    /// Change the error recovery added to packets framed from now on.
+    pub fn set_error_recovery(&mut self, recovery: UdptlErrorRecovery) -> Result<()> {
+        recovery.validate()?;
+        self.recovery = recovery;
+        Ok(())
+    }
+
+    /// The sequence number the next framed packet will carry.
+    pub fn next_seq_no(&self) -> u16 {
+        self.seq_no
+    }
+
+    /// Counts since the framer was created or the stats last taken.
+    pub fn stats(&self) -> UdptlStats {
+        self.stats
+    }
+
+    /// Return the counts and start them again from zero.
+    pub fn take_stats(&mut self) -> UdptlStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Wrap an IFP packet from the transmit handler in a UDPTL packet
+    /// carrying the error recovery for the packets before it.
+    ///
+    /// Each call uses the next sequence number. FEC winds up over the first
+    /// packets of a call, covering only packets already sent, as spandsp
+    /// does.
+    pub fn frame(&mut self, ifp: &[u8]) -> Result<Vec<u8>> {
+        if ifp.is_empty() {
+            return Err(SpanDspError::InvalidInput("empty IFP packet".into()));
+        }
+        let mut packet = Vec::with_capacity(4 + ifp.len());
+        packet.extend_from_slice(&self.seq_no.to_be_bytes());
+        push_open_type(&mut packet, ifp)?;
+        match self.recovery {
+            UdptlErrorRecovery::Redundancy(depths) => {
+                let count = self
+                    .sent
+                    .iter()
+                    .enumerate()
+                    .filter(|(age, (_, category))| *age < usize::from(depths.depth(*category)))
+                    .map(|(age, _)| age + 1)
+                    .max()
+                    .unwrap_or(0);
+                packet.push(0x00);
+                push_per_length(&mut packet, count)?;
+                for (secondary, _) in self.sent.iter().take(count) {
+                    push_open_type(&mut packet, secondary)?;
+                }
+            }
+            UdptlErrorRecovery::Fec { span, entries } => {
+                let span = usize::from(span);
+                let entries = usize::from(entries).min(self.sent.len() / span);
+                let span = if entries == 0 { 0 } else { span };
+                // The span is an unconstrained integer, sent as one octet.
+                packet.extend_from_slice(&[0x80, 1, span as u8]);
+                push_per_length(&mut packet, entries)?;
+                // Entry `m` covers the packets `entries - m`, then every
+                // `entries` further, back from this one.
+                for m in 0..entries {
+                    let mut fec = Vec::new();
+                    for j in 0..span {
+                        xor_into(&mut fec, &self.sent[entries - m + j * entries - 1].0);
+                    }
+                    push_open_type(&mut packet, &fec)?;
+                }
+            }
+        }
+        let category = ifp_category(ifp, self.t38_version);
+        self.sent.push_front((ifp.to_vec(), category));
+        self.sent.truncate(usize::from(MAX_UDPTL_WINDOW));
+        self.seq_no = self.seq_no.wrapping_add(1);
+        Ok(packet)
+    }
+
+    /// Hand the IFP packets in a received UDPTL packet to `core`: first any
+    /// missing packets its secondary data or FEC rebuilds, oldest first,
+    /// then its primary packet. Returns the number of IFP packets handed
+    /// over.
+    ///
+    /// A packet older than one already fed is dropped, and counted as late.
+    pub fn feed(&mut self, core: &mut T38Core<'_>, packet: &[u8]) -> Result<usize> {
+        let packets = self.receive(packet)?;
+        for (seq_no, ifp) in &packets {
+            core.rx_ifp_packet(ifp, *seq_no)?;
+        }
+        Ok(packets.len())
+    }
+
+    /// The IFP packets to hand over for a received UDPTL packet, with their
+    /// sequence numbers.
+    fn receive(&mut self, packet: &[u8]) -> Result<Vec<(u16, Vec<u8>)>> {
+        let mut reader = UdptlReader {
+            buf: packet,
+            pos: 0,
+        };
+        let seq_no = u16::from_be_bytes([reader.byte()?, reader.byte()?]);
+        let primary = reader.open_type()?;
+        if primary.is_empty() {
+            return Err(UdptlReader::bad("empty primary packet"));
+        }
+        let mut secondaries = Vec::new();
+        let mut fec = Vec::new();
+        let mut span = 0;
+        if reader.byte()? & 0x80 == 0 {
+            for _ in 0..reader.length()? {
+                secondaries.push(reader.open_type()?);
+            }
+        } else {
+            if reader.byte()? != 1 {
+                return Err(UdptlReader::bad("FEC span longer than one octet"));
+            }
+            span = usize::from(reader.byte()?);
+            for _ in 0..reader.length()? {
+                fec.push(reader.open_type()?);
+            }
+        }
+
+        let missing = match self.rx_seq_no {
+            Some(expected) => seq_no.wrapping_sub(expected),
+            None => 0,
+        };
+        if missing >= 0x8000 {
+            self.stats.late += 1;
+            return Ok(Vec::new());
+        }
+        let missing = usize::from(missing);
+        self.stats.received += 1;
+        self.rx_seq_no = Some(seq_no.wrapping_add(1));
+
+        // Rebuilt packets by age: `rebuilt[0]` is the one just before this.
+        let reach = missing.min(secondaries.len().max(span * fec.len()));
+        let mut rebuilt: Vec<Option<Vec<u8>>> = vec![None; reach];
+        for (slot, secondary) in rebuilt.iter_mut().zip(&secondaries) {
+            if !secondary.is_empty() {
+                *slot = Some(secondary.to_vec());
+                self.stats.recovered_redundancy += 1;
+            }
+        }
+        let entries = fec.len();
+        for (m, entry) in fec.iter().enumerate() {
+            let ages = (0..span).map(|j| entries - m + j * entries);
+            let mut gaps = ages
+                .clone()
+                .filter(|&age| age <= reach && rebuilt[age - 1].is_none());
+            let (Some(gap), None) = (gaps.next(), gaps.next()) else {
+                continue;
+            };
+            let mut data = entry.to_vec();
+            let mut complete = true;
+            for age in ages.filter(|&age| age != gap) {
+                // Past the reach of this packet lie only packets received
+                // before the gap.
+                let known = if age <= reach {
+                    rebuilt[age - 1].as_deref()
+                } else {
+                    let seq = seq_no.wrapping_sub(age as u16);
+                    self.received
+                        .iter()
+                        .find(|(s, _)| *s == seq)
+                        .map(|(_, p)| p.as_slice())
+                };
+                match known {
+                    Some(packet) => xor_into(&mut data, packet),
+                    None => complete = false,
+                }
+            }
+            // The entry is as long as the longest packet it covers.
+            if complete && let Some(len) = ifp_stream_length(&data, self.t38_version as i32) {
+                data.truncate(len);
+                rebuilt[gap - 1] = Some(data);
+                self.stats.recovered_fec += 1;
+            }
+        }
+
+        let mut packets: Vec<(u16, Vec<u8>)> = rebuilt
+            .into_iter()
+            .enumerate()
+            .rev()
+            .filter_map(|(i, p)| p.map(|p| (seq_no.wrapping_sub(i as u16 + 1), p)))
+            .collect();
+        self.stats.lost += (missing - packets.len()) as u64;
+        packets.push((seq_no, primary.to_vec()));
+        for (seq, ifp) in &packets {
+            self.received.push_front((*seq, ifp.clone()));
+        }
+        self.received.truncate(usize::from(MAX_UDPTL_WINDOW));
+        Ok(packets)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// IFP tracing
+// ---------------------------------------------------------------------------
+
+/// Which way a traced IFP packet was going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IfpDirection {
+    /// Handed to [`T38Core::rx_ifp_packet`] or
+    /// [`T38Core::rx_ifp_stream`].
+    Received,
+    /// Passed to the transmit handler.
+    Sent,
+}
+
+/// One field of an IFP data packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfpField {
+    /// Field type.
+    pub field_type: T38FieldType,
+    /// Field payload, empty if the packet carried none.
+    pub data: Vec<u8>,
+}
+
+/// The decoded content of an IFP packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IfpMessage {
+    /// A T.30 indicator.
+    Indicator(T38Indicator),
+    /// Modem data.
+    Data {
+        /// Modulation the data belongs to.
+        data_type: T38DataType,
+        /// Fields in packet order.
+        fields: Vec<IfpField>,
+    },
+}
+
+impl IfpMessage {
+    /// Decode a whole IFP packet as sent by a peer running `version`,
+    /// without a [`T38Core`], e.g. from a packet capture.
+    pub fn parse(buf: &[u8], version: T38Version) -> Result<Self> {
+        let message = decode_ifp(buf, version as i32)
+            .filter(|_| ifp_stream_length(buf, version as i32) == Some(buf.len()));
+        message.ok_or_else(|| SpanDspError::InvalidInput("malformed IFP packet".into()))
+    }
+
+    /// Encode the packet the way spandsp sends it to a peer running
+    /// `version`.
+    ///
+    /// A data field's payload is sent only if it is not empty, and a data
+    /// packet without fields has no field list, so parsing and encoding
+    /// again gives back the same bytes for any packet spandsp itself sends.
+    ///
+    /// Version 0 has no extension bits, so indicators, data types and field
+    /// types past its compact range are `InvalidInput` there.
+    pub fn to_bytes(&self, version: T38Version) -> Result<Vec<u8>> {
+        // Codes past the compact range go in an extension: `ext_flag`, then
+        // six bits split across the low nibble of this octet and the top of
+        // the next.
+        fn push_code(
+            buf: &mut Vec<u8>,
+            flags: u8,
+            code: i32,
+            first_ext: i32,
+            shift: u32,
+            ext_flag: u8,
+        ) {
+            if code < first_ext {
+                buf.push(flags | ((code as u8) << shift));
+            } else {
+                let ext = (code - first_ext) as u8;
+                buf.push(flags | ext_flag | ((ext >> 2) & 0x0F));
+                buf.push((ext & 0x03) << 6);
+            }
+        }
+        let compact_only = |code: i32, first_ext: i32, what: &dyn fmt::Debug| {
+            if version == T38Version::V0 && code >= first_ext {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "{what:?} needs T.38 version 1 or later"
+                )));
+            }
+            Ok(())
+        };
+        let mut buf = Vec::new();
+        match self {
+            Self::Indicator(indicator) => {
+                let first_ext = spandsp_sys::t30_indicator_types_e::T38_IND_V8_ANSAM as i32;
+                compact_only(indicator.0 as i32, first_ext, indicator)?;
+                push_code(&mut buf, 0x00, indicator.0 as i32, first_ext, 1, 0x20);
+            }
+            Self::Data { data_type, fields } => {
+                let first_ext = spandsp_sys::t38_data_types_e::T38_DATA_V8 as i32;
+                compact_only(data_type.0 as i32, first_ext, data_type)?;
+                let flags = if fields.is_empty() { 0x40 } else { 0xC0 };
+                push_code(&mut buf, flags, data_type.0 as i32, first_ext, 1, 0x20);
+                if fields.is_empty() {
+                    return Ok(buf);
+                }
+                let count = u8::try_from(fields.len()).map_err(|_| {
+                    SpanDspError::InvalidInput(format!("{} IFP fields, limit is 255", fields.len()))
+                })?;
+                buf.push(count);
+                let first_ext = spandsp_sys::t38_field_types_e::T38_FIELD_CM_MESSAGE as i32;
+                for field in fields {
+                    let code = field.field_type.0 as i32;
+                    let present = if field.data.is_empty() { 0x00 } else { 0x80 };
+                    compact_only(code, first_ext, &field.field_type)?;
+                    if version == T38Version::V0 {
+                        buf.push(present | ((code as u8) << 4));
+                    } else {
+                        push_code(&mut buf, present, code, first_ext, 3, 0x40);
+                    }
+                    if !field.data.is_empty() {
+                        let len = u16::try_from(field.data.len() - 1).map_err(|_| {
+                            SpanDspError::InvalidInput(format!(
+                                "IFP field of {} bytes, limit is 65536",
+                                field.data.len()
+                            ))
+                        })?;
+                        buf.extend_from_slice(&len.to_be_bytes());
+                        buf.extend_from_slice(&field.data);
+                    }
+                }
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// A traced IFP packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfpRecord {
+    /// Received or sent.
+    pub direction: IfpDirection,
+    /// Sequence number: as given to `rx_ifp_packet` (or `rx_ifp_stream`)
+    /// for received packets, and counted from the last restart for sent
+    /// ones.
+    pub seq_no: u16,
+    /// When the packet was seen.
+    pub timestamp: SystemTime,
+    /// Number of copies the transport was asked to send (1 when received).
+    pub copies: u32,
+    /// The decoded packet, or `None` if it is malformed.
+    pub message: Option<IfpMessage>,
+    /// The packet as it went on or came off the wire.
+    pub raw: Vec<u8>,
+}
+
+/// Decode an IFP packet the way `t38_core_rx_ifp_packet` does.
+///
+/// T.38 version 0 packs field types one bit further left than later
+/// versions, which added an extension bit.
+pub(crate) fn decode_ifp(buf: &[u8], t38_version: i32) -> Option<IfpMessage> {
+    let first = *buf.first()?;
+    let data_field_present = first & 0x80 != 0;
+    // Extended values continue into the top two bits of the next octet.
+    let extended = |offset: i32| offset + (((first << 2) & 0x3C) | (buf[1] >> 6)) as i32;
+    if first & 0x40 == 0 {
+        let code = if first & 0x20 != 0 {
+            if buf.len() != 2 {
+                return None;
+            }
+            extended(spandsp_sys::t30_indicator_types_e::T38_IND_V8_ANSAM as i32)
+        } else {
+            if buf.len() != 1 {
+                return None;
+            }
+            ((first >> 1) & 0x0F) as i32
+        };
+        if data_field_present {
+            return None;
+        }
+        return T38Indicator::from_code(code).map(IfpMessage::Indicator);
+    }
+
+    let (code, mut ptr) = if first & 0x20 != 0 {
+        if buf.len() < 2 {
+            return None;
+        }
+        (
+            extended(spandsp_sys::t38_data_types_e::T38_DATA_V8 as i32),
+            2,
+        )
+    } else {
+        (((first >> 1) & 0x0F) as i32, 1)
+    };
+    let data_type = T38DataType::from_code(code)?;
+    let mut fields = Vec::new();
+    if data_field_present {
+        let count = *buf.get(ptr)?;
+        ptr += 1;
+        for _ in 0..count {
+            let octet = *buf.get(ptr)?;
+            let field_data_present = octet & 0x80 != 0;
+            let code = if t38_version == 0 {
+                ptr += 1;
+                ((octet >> 4) & 0x07) as i32
+            } else if octet & 0x40 != 0 {
+                let next = *buf.get(ptr + 1)?;
+                ptr += 2;
+                spandsp_sys::t38_field_types_e::T38_FIELD_CM_MESSAGE as i32
+                    + (((octet << 2) & 0x3C) | (next >> 6)) as i32
+            } else {
+                ptr += 1;
+                ((octet >> 3) & 0x07) as i32
+            };
+            let field_type = T38FieldType::from_code(code)?;
+            let data = if field_data_present {
+                let len = u16::from_be_bytes([*buf.get(ptr)?, *buf.get(ptr + 1)?]) as usize + 1;
+                let data = buf.get(ptr + 2..ptr + 2 + len)?.to_vec();
+                ptr += 2 + len;
+                data
+            } else {
+                Vec::new()
+            };
+            fields.push(IfpField { field_type, data });
+        }
+    }
+    Some(IfpMessage::Data { data_type, fields })
+}
+
+pub(crate) type IfpTraceCallback = Box<dyn FnMut(&IfpRecord) + Send>;
+
+/// The caller's transmit handler, wrapped so sent packets can be counted
+/// and traced, along with engine settings spandsp cannot report back.
+pub(crate) struct IfpTap {
+    handler: spandsp_sys::t38_tx_packet_handler_t,
+    user_data: *mut c_void,
+    trace: Option<IfpTraceCallback>,
+    t38_version: i32,
+    redundancy: RedundancyPolicy,
+    tx_seq_no: u16,
+    packets: u32,
+}
+
+impl IfpTap {
+    /// Allocate a tap forwarding to `handler`. Register it with spandsp as
+    /// [`TRAMPOLINE`](Self::TRAMPOLINE) with the returned pointer as user
+    /// data, and release it with [`free`](Self::free).
+    pub(crate) fn new(
+        handler: spandsp_sys::t38_tx_packet_handler_t,
+        user_data: *mut c_void,
+    ) -> NonNull<Self> {
+        let tap = Box::new(Self {
+            handler,
+            user_data,
+            trace: None,
+            t38_version: 0,
+            redundancy: RedundancyPolicy::default(),
+            tx_seq_no: 0,
+            packets: 0,
+        });
+        NonNull::from(Box::leak(tap))
+    }
+
+    pub(crate) const TRAMPOLINE: spandsp_sys::t38_tx_packet_handler_t = Some(ifp_tap_trampoline);
+
+    /// Release a tap from [`new`](Self::new).
+    ///
+    /// # Safety
+    /// `tap` must come from `new`, and the engine using it must be freed.
+    pub(crate) unsafe fn free(tap: NonNull<Self>) {
+        drop(unsafe { Box::from_raw(tap.as_ptr()) });
+    }
+
+    /// Record a redundancy change spandsp made on its own.
+    pub(crate) fn set_redundancy(&mut self, policy: RedundancyPolicy) {
+        self.redundancy = policy;
+    }
+
+    /// Number the next sent packet from zero, as the engine does after a
+    /// restart.
+    pub(crate) fn restart(&mut self) {
+        self.tx_seq_no = 0;
+    }
+
+    /// Packets handed to the transmit handler so far.
+    pub(crate) fn packets(&self) -> u32 {
+        self.packets
+    }
+
+    fn trace(&mut self, direction: IfpDirection, seq_no: u16, buf: &[u8], copies: u32) {
+        if let Some(trace) = &mut self.trace {
+            trace(&IfpRecord {
+                direction,
+                seq_no,
+                timestamp: SystemTime::now(),
+                copies,
+                message: decode_ifp(buf, self.t38_version),
+                raw: buf.to_vec(),
+            });
+        }
+    }
+}
+
+/// Trampoline for `t38_tx_packet_handler_t` that counts, traces and
+/// forwards.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `IfpTap`.
+unsafe extern "C" fn ifp_tap_trampoline(
+    s: *mut spandsp_sys::t38_core_state_t,
+    user_data: *mut c_void,
+    buf: *const u8,
+    len: c_int,
+    count: c_int,
+) -> c_int {
+    unsafe {
+        if user_data.is_null() {
+            return -1;
+        }
+        let tap = &mut *(user_data as *mut IfpTap);
+        tap.packets = tap.packets.wrapping_add(1);
+        #[cfg(feature = "metrics")]
+        crate::metrics::t38_packet_sent();
+        if tap.trace.is_some() && !buf.is_null() && len > 0 {
+            let packet = std::slice::from_raw_parts(buf, len as usize);
+            tap.trace(
+                IfpDirection::Sent,
+                tap.tx_seq_no,
+                packet,
+                count.max(0) as u32,
+            );
+        }
+        tap.tx_seq_no = tap.tx_seq_no.wrapping_add(1);
+        match tap.handler {
+            Some(handler) => handler(s, tap.user_data, buf, len, count),
+            None => 0,
+        }
+    }
+}
+
 /// T.38 core protocol state wrapping `t38_core_state_t`.
 ///
 /// This is typically obtained via `T38Terminal::get_t38_core_state()` or
 /// `T38Gateway::get_t38_core_state()` rather than created directly, but
 /// can also be created standalone for custom T.38 implementations.
-pub struct T38Core {
+///
+/// `'a` is the borrow of the owner a handle came from. A handle from
+/// `get_t38_core_state` holds its owner mutably borrowed, so it cannot
+/// outlive the owner or be used alongside it:
+///
+/// ```compile_fail
+/// # use spandsp::t38_terminal::T38Terminal;
+/// let mut terminal = unsafe { T38Terminal::new_raw(true, None, std::ptr::null_mut()) }.unwrap();
+/// let core = terminal.get_t38_core_state().unwrap();
+/// drop(terminal);
+/// core.as_ptr();
+/// ```
+///
+/// A standalone core owns its engine and can have any lifetime.
+pub struct T38Core<'a> {
     inner: NonNull<spandsp_sys::t38_core_state_t>,
     owned: bool,
+    /// The tap on this engine's transmit path, if it is known. Owned
+    /// engines own their tap.
+    tap: Option<NonNull<IfpTap>>,
+    _owner: PhantomData<&'a mut ()>,
 }
 
-impl T38Core {
+impl<'a> T38Core<'a> {
     /// Create a new T.38 core context with raw callback pointers.
     ///
     /// # Safety
@@ -275,6 +1597,7 @@ impl T38Core {
         tx_packet_handler: spandsp_sys::t38_tx_packet_handler_t,
         tx_packet_user_data: *mut std::ffi::c_void,
     ) -> Result<Self> {
+        let tap = IfpTap::new(tx_packet_handler, tx_packet_user_data);
         unsafe {
             let ptr = spandsp_sys::t38_core_init(
                 std::ptr::null_mut(),
@@ -282,50 +1605,112 @@ impl T38Core {
                 rx_data_handler,
                 rx_missing_handler,
                 rx_user_data,
-                tx_packet_handler,
-                tx_packet_user_data,
+                IfpTap::TRAMPOLINE,
+                tap.as_ptr() as *mut c_void,
             );
-            let inner = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-            Ok(Self { inner, owned: true })
+            let Some(inner) = NonNull::new(ptr) else {
+                IfpTap::free(tap);
+                return Err(SpanDspError::InitFailed);
+            };
+            Ok(Self {
+                inner,
+                owned: true,
+                tap: Some(tap),
+                _owner: PhantomData,
+            })
         }
     }
 
     /// Wrap a non-owned pointer (e.g. from a T38Terminal or T38Gateway).
     ///
+    /// A core wrapped this way cannot be traced; use the owner's
+    /// `get_t38_core_state` for that.
+    ///
     /// # Safety
-    /// The pointer must be valid. The object will NOT be freed on drop.
+    /// The pointer must be valid for `'a`, and nothing else may drive the
+    /// engine while the result is in use. The object will NOT be freed on
+    /// drop.
     pub unsafe fn from_raw(ptr: *mut spandsp_sys::t38_core_state_t) -> Result<Self> {
         let inner = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
         Ok(Self {
             inner,
             owned: false,
+            tap: None,
+            _owner: PhantomData,
         })
     }
 
+    /// Wrap a non-owned pointer whose transmit handler is `tap`.
+    ///
+    /// # Safety
+    /// As [`from_raw`](Self::from_raw); `tap` must also be valid for `'a`
+    /// and not otherwise borrowed while the result is in use.
+    pub(crate) unsafe fn from_raw_tapped(
+        ptr: *mut spandsp_sys::t38_core_state_t,
+        tap: NonNull<IfpTap>,
+    ) -> Result<Self> {
+        let mut core = unsafe { Self::from_raw(ptr)? };
+        core.tap = Some(tap);
+        Ok(core)
+    }
+
+    /// Call `handler` with every IFP packet this engine receives or sends.
+    ///
+    /// Records carry the raw packet, its decoded fields, the sequence
+    /// number and a timestamp, so a protocol trace can be kept without a
+    /// packet capture. Returns `InvalidInput` for a core wrapped with
+    /// [`from_raw`](Self::from_raw), whose transmit path is not visible.
+    pub fn set_trace_handler<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: FnMut(&IfpRecord) + Send + 'static,
+    {
+        let tap = self.tap_mut().ok_or_else(|| {
+            SpanDspError::InvalidInput("IFP tracing needs a core with a known owner".into())
+        })?;
+        tap.trace = Some(Box::new(handler));
+        Ok(())
+    }
+
+    /// Stop tracing.
+    pub fn clear_trace_handler(&mut self) {
+        if let Some(tap) = self.tap_mut() {
+            tap.trace = None;
+        }
+    }
+
+    fn tap_mut(&mut self) -> Option<&mut IfpTap> {
+        // SAFETY: the tap is valid for 'a, and the owner that shares it is
+        // mutably borrowed for as long as this handle exists.
+        self.tap.map(|mut tap| unsafe { tap.as_mut() })
+    }
+
     /// Get the raw pointer.
     pub fn as_ptr(&self) -> *mut spandsp_sys::t38_core_state_t {
         self.inner.as_ptr()
     }
 
     /// Restart the T.38 core context.
-    pub fn restart(&self) -> Result<()> {
+    pub fn restart(&mut self) -> Result<()> {
         let rc = unsafe { spandsp_sys::t38_core_restart(self.inner.as_ptr()) };
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
         }
+        if let Some(tap) = self.tap_mut() {
+            tap.restart();
+        }
         Ok(())
     }
 
     /// Send an indicator packet.
     ///
     /// Returns the delay (in samples) to allow after sending.
-    pub fn send_indicator(&self, indicator: T38Indicator) -> i32 {
+    pub fn send_indicator(&mut self, indicator: T38Indicator) -> i32 {
         unsafe { spandsp_sys::t38_core_send_indicator(self.inner.as_ptr(), i32::from(indicator)) }
     }
 
     /// Send a data packet.
     pub fn send_data(
-        &self,
+        &mut self,
         data_type: T38DataType,
         field_type: T38FieldType,
         field: &[u8],
@@ -347,8 +1732,55 @@ impl T38Core {
         Ok(())
     }
 
+    /// Process an IFP packet taken from a reliable stream (TCP or TPKT),
+    /// where no sequence numbers are carried. `log_seq_no` only labels the
+    /// packet in logs and traces.
+    ///
+    /// Returns the number of bytes the packet took; most callers want an
+    /// [`IfpStreamReader`] to split the stream instead.
+    pub fn rx_ifp_stream(&mut self, buf: &[u8], log_seq_no: u16) -> Result<usize> {
+        if let Some(tap) = self.tap_mut() {
+            tap.trace(IfpDirection::Received, log_seq_no, buf, 1);
+        }
+        let rc = unsafe {
+            spandsp_sys::t38_core_rx_ifp_stream(
+                self.inner.as_ptr(),
+                buf.as_ptr(),
+                buf.len().min(c_int::MAX as usize) as c_int,
+                log_seq_no,
+            )
+        };
+        #[cfg(feature = "metrics")]
+        crate::metrics::t38_packet_received();
+        if rc < 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(rc as usize)
+    }
+
+    /// Set up the engine for `transport`: the reliable transports turn off
+    /// sequence number checking and use [`RedundancyPolicy::streaming`],
+    /// while UDPTL and RTP go back to checking and spandsp's default
+    /// redundancy.
+    pub fn set_transport(&mut self, transport: T38Transport) -> Result<()> {
+        unsafe {
+            spandsp_sys::t38_set_sequence_number_handling(
+                self.inner.as_ptr(),
+                c_int::from(!transport.is_reliable()),
+            );
+        }
+        let policy = match transport.is_reliable() {
+            true => RedundancyPolicy::streaming(),
+            false => RedundancyPolicy::default(),
+        };
+        self.set_redundancy_policy(&policy)
+    }
+
     /// Process a received IFP packet (unreliable transport like UDPTL/RTP).
-    pub fn rx_ifp_packet(&self, buf: &[u8], seq_no: u16) -> Result<()> {
+    pub fn rx_ifp_packet(&mut self, buf: &[u8], seq_no: u16) -> Result<()> {
+        if let Some(tap) = self.tap_mut() {
+            tap.trace(IfpDirection::Received, seq_no, buf, 1);
+        }
         let rc = unsafe {
             spandsp_sys::t38_core_rx_ifp_packet(
                 self.inner.as_ptr(),
@@ -357,6 +1789,8 @@ impl T38Core {
                 seq_no,
             )
         };
+        #[cfg(feature = "metrics")]
+        crate::metrics::t38_packet_received();
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
         }
@@ -364,21 +1798,55 @@ impl T38Core {
     }
 
     /// Set the T.38 version to emulate.
-    pub fn set_t38_version(&self, version: T38Version) {
+    pub fn set_t38_version(&mut self, version: T38Version) {
         unsafe {
             spandsp_sys::t38_set_t38_version(self.inner.as_ptr(), version as c_int);
         }
+        if let Some(tap) = self.tap_mut() {
+            tap.t38_version = version as i32;
+        }
     }
 
     /// Set the data rate management method.
-    pub fn set_data_rate_management_method(&self, method: T38DataRateManagement) {
+    pub fn set_data_rate_management_method(&mut self, method: T38DataRateManagement) {
         unsafe {
             spandsp_sys::t38_set_data_rate_management_method(self.inner.as_ptr(), method as c_int);
         }
     }
 
+    /// Set how many times each category of packet is sent.
+    ///
+    /// The whole policy is validated before any of it is applied.
+    pub fn set_redundancy_policy(&mut self, policy: &RedundancyPolicy) -> Result<()> {
+        policy.validate()?;
+        for category in T38PacketCategory::ALL {
+            unsafe {
+                spandsp_sys::t38_set_redundancy_control(
+                    self.inner.as_ptr(),
+                    category as c_int,
+                    policy.repeats(category) as c_int,
+                );
+            }
+        }
+        if let Some(tap) = self.tap_mut() {
+            tap.redundancy = *policy;
+        }
+        Ok(())
+    }
+
+    /// The policy last applied with
+    /// [`set_redundancy_policy`](Self::set_redundancy_policy), or spandsp's
+    /// default.
+    ///
+    /// Returns `None` for a core wrapped with [`from_raw`](Self::from_raw),
+    /// whose settings are not tracked.
+    pub fn redundancy_policy(&self) -> Option<RedundancyPolicy> {
+        self.tap.map(|tap| unsafe { tap.as_ref() }.redundancy)
+    }
+
     /// Set redundancy control for a packet category.
-    pub fn set_redundancy_control(&self, category: T38PacketCategory, setting: i32) {
+    #[deprecated(note = "use `set_redundancy_policy`")]
+    pub fn set_redundancy_control(&mut self, category: T38PacketCategory, setting: i32) {
         unsafe {
             spandsp_sys::t38_set_redundancy_control(
                 self.inner.as_ptr(),
@@ -386,19 +1854,34 @@ impl T38Core {
                 setting as c_int,
             );
         }
+        if let Some(tap) = self.tap_mut() {
+            *tap.redundancy.repeats_mut(category) = setting.clamp(0, u8::MAX as i32) as u8;
+        }
+    }
+}
+
+impl fmt::Debug for T38Core<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T38Core")
+            .field("owned", &self.owned)
+            .field("traceable", &self.tap.is_some())
+            .finish_non_exhaustive()
     }
 }
 
 // SAFETY: T38Core wraps a SpanDSP t38_core_state_t that is only accessed
 // through &self/&mut self methods. The underlying C library is not thread-safe,
 // but exclusive access can be guaranteed externally (e.g., via tokio::sync::Mutex).
-unsafe impl Send for T38Core {}
+unsafe impl Send for T38Core<'_> {}
 
-impl Drop for T38Core {
+impl Drop for T38Core<'_> {
     fn drop(&mut self) {
         if self.owned {
             unsafe {
                 spandsp_sys::t38_core_free(self.inner.as_ptr());
+                if let Some(tap) = self.tap {
+                    IfpTap::free(tap);
+                }
             }
         }
     }
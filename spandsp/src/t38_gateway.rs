@@ -4,15 +4,20 @@
 //! T.38 IP packets, allowing traditional PSTN FAX machines to
 //! communicate through an IP network.
 
+use std::ffi::c_void;
+use std::fmt;
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError};
+use crate::fax_tones::{FaxTone, FaxToneWatch};
 use crate::t30::T30ModemSupport;
-use crate::t38_core::T38Core;
+use crate::t38_core::{IfpTap, T38Core};
 
 /// T.38 gateway state wrapping `t38_gateway_state_t`.
 pub struct T38Gateway {
     inner: NonNull<spandsp_sys::t38_gateway_state_t>,
+    tap: NonNull<IfpTap>,
+    tone_watch: Option<FaxToneWatch>,
 }
 
 impl T38Gateway {
@@ -25,14 +30,22 @@ impl T38Gateway {
         tx_packet_handler: spandsp_sys::t38_tx_packet_handler_t,
         tx_packet_user_data: *mut std::ffi::c_void,
     ) -> Result<Self> {
+        let tap = IfpTap::new(tx_packet_handler, tx_packet_user_data);
         unsafe {
             let ptr = spandsp_sys::t38_gateway_init(
                 std::ptr::null_mut(),
-                tx_packet_handler,
-                tx_packet_user_data,
+                IfpTap::TRAMPOLINE,
+                tap.as_ptr() as *mut c_void,
             );
-            let inner = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-            Ok(Self { inner })
+            let Some(inner) = NonNull::new(ptr) else {
+                IfpTap::free(tap);
+                return Err(SpanDspError::InitFailed);
+            };
+            Ok(Self {
+                inner,
+                tap,
+                tone_watch: None,
+            })
         }
     }
 
@@ -42,15 +55,45 @@ impl T38Gateway {
     }
 
     /// Get a (non-owned) handle to the T.38 core IFP engine.
-    pub fn get_t38_core_state(&self) -> Result<T38Core> {
+    ///
+    /// The handle borrows the gateway mutably, since it drives the same
+    /// engine. It can trace the gateway's packets with
+    /// [`T38Core::set_trace_handler`].
+    pub fn get_t38_core_state(&mut self) -> Result<T38Core<'_>> {
         let ptr = unsafe { spandsp_sys::t38_gateway_get_t38_core_state(self.inner.as_ptr()) };
-        unsafe { T38Core::from_raw(ptr) }
+        unsafe { T38Core::from_raw_tapped(ptr, self.tap) }
+    }
+
+    /// Call `handler` the first time CNG, CED or a V.21 preamble is heard
+    /// on the audio leg, e.g. to send the re-INVITE to T.38.
+    ///
+    /// Runs a [`FaxToneDetector`](crate::fax_tones::FaxToneDetector) on the
+    /// audio passed to [`rx`](Self::rx); the handler fires once per
+    /// gateway, or once more after [`rearm_fax_tone_handler`](Self::rearm_fax_tone_handler).
+    pub fn set_fax_tone_handler<F>(&mut self, handler: F) -> Result<()>
+    where
+        F: FnMut(FaxTone) + Send + 'static,
+    {
+        self.tone_watch = Some(FaxToneWatch::new(Box::new(handler))?);
+        Ok(())
+    }
+
+    /// Listen for fax tones again, e.g. when the gateway is reused for
+    /// another call.
+    pub fn rearm_fax_tone_handler(&mut self) -> Result<()> {
+        match &mut self.tone_watch {
+            Some(watch) => watch.rearm(),
+            None => Ok(()),
+        }
     }
 
     /// Process received audio samples (PSTN side → T.38).
     ///
     /// Returns the number of unprocessed samples.
-    pub fn rx(&self, samples: &mut [i16]) -> usize {
+    pub fn rx(&mut self, samples: &mut [i16]) -> usize {
+        if let Some(watch) = &mut self.tone_watch {
+            watch.rx(samples);
+        }
         unsafe {
             spandsp_sys::t38_gateway_rx(
                 self.inner.as_ptr(),
@@ -63,7 +106,7 @@ impl T38Gateway {
     /// Generate transmit audio samples (T.38 → PSTN side).
     ///
     /// Returns the number of samples generated.
-    pub fn tx(&self, buf: &mut [i16]) -> usize {
+    pub fn tx(&mut self, buf: &mut [i16]) -> usize {
         unsafe {
             spandsp_sys::t38_gateway_tx(self.inner.as_ptr(), buf.as_mut_ptr(), buf.len() as i32)
                 as usize
@@ -71,28 +114,40 @@ impl T38Gateway {
     }
 
     /// Set whether ECM is allowed.
-    pub fn set_ecm_capability(&self, allowed: bool) {
+    pub fn set_ecm_capability(&mut self, allowed: bool) {
         unsafe {
             spandsp_sys::t38_gateway_set_ecm_capability(self.inner.as_ptr(), allowed);
         }
     }
 
     /// Set whether to send silent audio when idle.
-    pub fn set_transmit_on_idle(&self, on: bool) {
+    pub fn set_transmit_on_idle(&mut self, on: bool) {
         unsafe {
             spandsp_sys::t38_gateway_set_transmit_on_idle(self.inner.as_ptr(), on);
         }
     }
 
     /// Set supported modems.
-    pub fn set_supported_modems(&self, modems: T30ModemSupport) {
+    ///
+    /// Modems the linked spandsp lacks are passed on as they are; use
+    /// [`set_supported_modems_checked`](Self::set_supported_modems_checked)
+    /// to refuse them instead.
+    pub fn set_supported_modems(&mut self, modems: T30ModemSupport) {
         unsafe {
             spandsp_sys::t38_gateway_set_supported_modems(self.inner.as_ptr(), modems.bits());
         }
     }
 
+    /// Set supported modems, failing if any are not in
+    /// [`T30ModemSupport::available`].
+    pub fn set_supported_modems_checked(&mut self, modems: T30ModemSupport) -> Result<()> {
+        modems.check_available()?;
+        self.set_supported_modems(modems);
+        Ok(())
+    }
+
     /// Set TEP mode.
-    pub fn set_tep_mode(&self, use_tep: bool) {
+    pub fn set_tep_mode(&mut self, use_tep: bool) {
         unsafe {
             spandsp_sys::t38_gateway_set_tep_mode(self.inner.as_ptr(), use_tep);
         }
@@ -108,10 +163,22 @@ impl T38Gateway {
     }
 }
 
+impl fmt::Debug for T38Gateway {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.get_transfer_statistics();
+        f.debug_struct("T38Gateway")
+            .field("bit_rate", &stats.bit_rate)
+            .field("error_correcting_mode", &(stats.error_correcting_mode != 0))
+            .field("pages_transferred", &stats.pages_transferred)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for T38Gateway {
     fn drop(&mut self) {
         unsafe {
             spandsp_sys::t38_gateway_free(self.inner.as_ptr());
+            IfpTap::free(self.tap);
         }
     }
 }
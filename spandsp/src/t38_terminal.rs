@@ -3,15 +3,54 @@
 //! A T.38 terminal is an Internet-aware FAX device that connects directly
 //! to an IP network, sending and receiving T.38 IFP packets.
 
+use std::ffi::c_void;
+use std::fmt;
 use std::ptr::NonNull;
+use std::time::Duration;
 
 use crate::error::{Result, SpanDspError};
-use crate::t30::T30State;
-use crate::t38_core::{T38Core, T38TerminalOptions};
+use crate::t30::{
+    EcmEvent, FaxQualityReport, IdentDecision, IdentValidator, ReceivedPage, RemoteIdent,
+    RetrainPolicy, SessionHooks, T30InterruptSignal, T30ModemSupport, T30Snapshot, T30State,
+    TxDocument, TxDocumentQueue, install_ident_validator,
+};
+use crate::t38_core::{IfpTap, RedundancyPolicy, T38Core, T38TerminalOptions};
+
+/// Interval between paced data chunks: `US_PER_TX_CHUNK` in spandsp's
+/// `t38_terminal.c`, which `t38_terminal_set_config` sets as
+/// `us_per_tx_chunk` unless `T38_TERMINAL_OPTION_NO_PACING` is given.
+const PACED_CHUNK_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Tick interval while nothing is being sent, when only the T.30 timers
+/// need servicing. The shortest of those is T4 at 3 s with a tolerance of
+/// ±15% (T.30 §5.4.3), i.e. 450 ms; ticking every 100 ms keeps
+/// well inside it.
+const IDLE_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pacing information returned by [`T38Terminal::tick`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct T38Pacing {
+    /// The T.30 call has finished; no more ticks are needed.
+    pub call_ended: bool,
+    /// Audio-equivalent samples this tick accounted for.
+    pub samples: i32,
+    /// IFP packets handed to the transmit handler since the previous tick.
+    pub packets_sent: u32,
+    /// A transmission step was in progress, so more packets are expected.
+    pub sending: bool,
+    /// Suggested delay before the next tick (zero once the call has ended).
+    pub next_timeout: Duration,
+}
 
 /// T.38 terminal state wrapping `t38_terminal_state_t`.
 pub struct T38Terminal {
     inner: NonNull<spandsp_sys::t38_terminal_state_t>,
+    config: T38TerminalOptions,
+    tap: NonNull<IfpTap>,
+    packets_at_last_tick: u32,
+    documents: Option<Box<TxDocumentQueue>>,
+    hooks: Box<SessionHooks>,
+    ident_validator: Option<Box<IdentValidator>>,
 }
 
 impl T38Terminal {
@@ -25,15 +64,28 @@ impl T38Terminal {
         tx_packet_handler: spandsp_sys::t38_tx_packet_handler_t,
         tx_packet_user_data: *mut std::ffi::c_void,
     ) -> Result<Self> {
+        let tap = IfpTap::new(tx_packet_handler, tx_packet_user_data);
         unsafe {
             let ptr = spandsp_sys::t38_terminal_init(
                 std::ptr::null_mut(),
                 calling_party,
-                tx_packet_handler,
-                tx_packet_user_data,
+                IfpTap::TRAMPOLINE,
+                tap.as_ptr() as *mut c_void,
             );
-            let inner = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-            Ok(Self { inner })
+            let Some(inner) = NonNull::new(ptr) else {
+                IfpTap::free(tap);
+                return Err(SpanDspError::InitFailed);
+            };
+            let t30 = spandsp_sys::t38_terminal_get_t30_state(ptr);
+            Ok(Self {
+                inner,
+                config: T38TerminalOptions::default(),
+                tap,
+                packets_at_last_tick: 0,
+                documents: None,
+                hooks: SessionHooks::install(t30),
+                ident_validator: None,
+            })
         }
     }
 
@@ -43,50 +95,242 @@ impl T38Terminal {
     }
 
     /// Get a (non-owned) handle to the T.30 engine.
-    pub fn get_t30_state(&self) -> Result<T30State> {
-        let ptr = unsafe { spandsp_sys::t38_terminal_get_t30_state(self.inner.as_ptr()) };
-        unsafe { T30State::from_raw(ptr, false) }
+    ///
+    /// The handle borrows the terminal mutably, since it drives the same
+    /// engine.
+    pub fn get_t30_state(&mut self) -> Result<T30State<'_>> {
+        unsafe { T30State::from_raw(self.t30_ptr(), false) }
+    }
+
+    fn t30_ptr(&self) -> *mut spandsp_sys::t30_state_t {
+        unsafe { spandsp_sys::t38_terminal_get_t30_state(self.inner.as_ptr()) }
+    }
+
+    /// A T.30 handle for read-only queries from `&self`.
+    fn peek_t30(&self) -> Result<T30State<'_>> {
+        // SAFETY: the handle only lives for one query and is never handed
+        // out, so nothing can drive the engine through it.
+        unsafe { T30State::from_raw(self.t30_ptr(), false) }
     }
 
     /// Get a (non-owned) handle to the T.38 core IFP engine.
-    pub fn get_t38_core_state(&self) -> Result<T38Core> {
+    ///
+    /// The handle borrows the terminal mutably, since it drives the same
+    /// engine. It can trace the terminal's packets with
+    /// [`T38Core::set_trace_handler`].
+    pub fn get_t38_core_state(&mut self) -> Result<T38Core<'_>> {
         let ptr = unsafe { spandsp_sys::t38_terminal_get_t38_core_state(self.inner.as_ptr()) };
-        unsafe { T38Core::from_raw(ptr) }
+        unsafe { T38Core::from_raw_tapped(ptr, self.tap) }
+    }
+
+    /// Queue several documents to be sent back-to-back in one call.
+    ///
+    /// Behaves like [`FaxState::set_tx_documents`](crate::fax::FaxState::set_tx_documents):
+    /// documents are separated by EOM and the last one ends with EOP.
+    pub fn set_tx_documents(&mut self, docs: impl IntoIterator<Item = TxDocument>) -> Result<()> {
+        let t30 = self.t30_ptr();
+        self.documents = Some(unsafe { TxDocumentQueue::install(t30, docs)? });
+        Ok(())
+    }
+
+    /// Number of queued documents that have not been started yet.
+    pub fn pending_tx_documents(&self) -> usize {
+        self.documents.as_ref().map_or(0, |q| q.pending())
+    }
+
+    /// Capture the resumable state of this call.
+    ///
+    /// The calling side is not recorded by the terminal, so
+    /// `calling_party` is left as `None`.
+    pub fn snapshot(&self) -> Result<T30Snapshot> {
+        let mut snap = self.peek_t30()?.snapshot();
+        if let Some(queue) = &self.documents {
+            snap.remaining_documents = queue.remaining(snap.pages_tx);
+        }
+        Ok(snap)
+    }
+
+    /// Prepare this terminal to carry on from `snapshot`.
+    pub fn resume(&mut self, snapshot: &T30Snapshot) -> Result<()> {
+        if let Some(ident) = &snapshot.local_ident {
+            self.get_t30_state()?.set_tx_ident(ident)?;
+        }
+        if !snapshot.remaining_documents.is_empty() {
+            self.set_tx_documents(snapshot.remaining_documents.iter().cloned())?;
+        }
+        Ok(())
+    }
+
+    /// Call `handler` whenever a PIP/PIN or PRI-Q signal is exchanged.
+    ///
+    /// See [`FaxState::set_interrupt_handler`](crate::fax::FaxState::set_interrupt_handler);
+    /// this likewise takes over the T.30 phase D handler.
+    pub fn set_interrupt_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(T30InterruptSignal) + Send + 'static,
+    {
+        self.hooks.set_interrupt_handler(Box::new(handler));
+    }
+
+    /// Call `handler` for each ECM control frame exchanged.
+    ///
+    /// See [`FaxState::set_ecm_event_handler`](crate::fax::FaxState::set_ecm_event_handler).
+    pub fn set_ecm_event_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(EcmEvent) + Send + 'static,
+    {
+        self.hooks.set_ecm_handler(Box::new(handler));
+    }
+
+    /// Receive into `rx_file` and call `handler` with each page as soon as
+    /// it has been received.
+    ///
+    /// See [`FaxState::set_received_page_handler`](crate::fax::FaxState::set_received_page_handler).
+    pub fn set_received_page_handler<F>(
+        &mut self,
+        rx_file: &str,
+        stop_page: i32,
+        handler: F,
+    ) -> Result<()>
+    where
+        F: FnMut(Result<ReceivedPage>) + Send + 'static,
+    {
+        self.hooks
+            .set_received_page_handler(rx_file, stop_page, Box::new(handler))
+    }
+
+    /// Screen the far end before any page is exchanged.
+    ///
+    /// See [`FaxState::set_ident_validator`](crate::fax::FaxState::set_ident_validator).
+    pub fn set_ident_validator<F>(&mut self, validator: F)
+    where
+        F: FnMut(&RemoteIdent) -> IdentDecision + Send + 'static,
+    {
+        let t30 = self.t30_ptr();
+        self.ident_validator = Some(unsafe { install_ident_validator(t30, validator) });
+    }
+
+    /// Line quality of the pages exchanged so far.
+    ///
+    /// See [`FaxState::quality_report`](crate::fax::FaxState::quality_report).
+    pub fn quality_report(&self) -> FaxQualityReport {
+        self.hooks.quality_report()
+    }
+
+    /// Set the modems the session may offer.
+    ///
+    /// See [`FaxState::set_supported_modems`](crate::fax::FaxState::set_supported_modems).
+    pub fn set_supported_modems(&mut self, modems: T30ModemSupport) -> Result<T30ModemSupport> {
+        self.hooks.set_supported_modems(modems)
+    }
+
+    /// Cap the bit rate the session negotiates, or lift the cap with `None`.
+    ///
+    /// See [`FaxState::set_max_bit_rate`](crate::fax::FaxState::set_max_bit_rate).
+    pub fn set_max_bit_rate(&mut self, max_bit_rate: Option<u32>) -> Result<T30ModemSupport> {
+        self.hooks.set_max_bit_rate(max_bit_rate)
+    }
+
+    /// The current bit rate cap, if any.
+    pub fn max_bit_rate(&self) -> Option<u32> {
+        self.hooks.max_bit_rate()
+    }
+
+    /// Ask for a fallback when the line is worse than the engine thinks.
+    ///
+    /// See [`FaxState::request_fallback`](crate::fax::FaxState::request_fallback).
+    pub fn request_fallback(&mut self) -> Result<Option<u32>> {
+        self.hooks.request_fallback(0)
+    }
+
+    /// Fall back automatically after pages whose line quality crosses
+    /// `policy`.
+    ///
+    /// See [`FaxState::set_retrain_policy`](crate::fax::FaxState::set_retrain_policy).
+    pub fn set_retrain_policy(&mut self, policy: Option<RetrainPolicy>) {
+        self.hooks.set_retrain_policy(policy);
     }
 
     /// Drive the T.38 terminal's timer. Call periodically with the number of
     /// audio-equivalent samples elapsed.
-    pub fn send_timeout(&self, samples: i32) -> i32 {
+    pub fn send_timeout(&mut self, samples: i32) -> i32 {
         unsafe { spandsp_sys::t38_terminal_send_timeout(self.inner.as_ptr(), samples) }
     }
 
+    /// Drive the terminal's timer like [`send_timeout`](Self::send_timeout),
+    /// and report when it next needs attention.
+    ///
+    /// spandsp does not expose its transmit schedule, so `next_timeout` is
+    /// inferred: while packets are flowing it is the pacing interval (or zero
+    /// with [`T38TerminalOptions::NO_PACING`]), otherwise a relaxed idle
+    /// interval. Tick promptly after feeding received packets, since they may
+    /// start a new transmission step.
+    pub fn tick(&mut self, samples: i32) -> T38Pacing {
+        let call_ended = self.send_timeout(samples) != 0;
+        let packets = unsafe { self.tap.as_ref() }.packets();
+        let packets_sent = packets.wrapping_sub(self.packets_at_last_tick);
+        self.packets_at_last_tick = packets;
+        let sending = !call_ended && packets_sent > 0;
+        let next_timeout = if call_ended {
+            Duration::ZERO
+        } else if sending {
+            if self.config.contains(T38TerminalOptions::NO_PACING) {
+                Duration::ZERO
+            } else {
+                PACED_CHUNK_INTERVAL
+            }
+        } else {
+            IDLE_TICK_INTERVAL
+        };
+        T38Pacing {
+            call_ended,
+            samples,
+            packets_sent,
+            sending,
+            next_timeout,
+        }
+    }
+
     /// Set configuration options.
-    pub fn set_config(&self, config: T38TerminalOptions) {
+    ///
+    /// This resets the redundancy policy: spandsp switches to
+    /// [`RedundancyPolicy::streaming`] with `NO_PACING` and back to the
+    /// default without it.
+    pub fn set_config(&mut self, config: T38TerminalOptions) {
         unsafe {
             spandsp_sys::t38_terminal_set_config(self.inner.as_ptr(), config.bits());
         }
+        self.config = config;
+        let policy = if config.contains(T38TerminalOptions::NO_PACING) {
+            RedundancyPolicy::streaming()
+        } else {
+            RedundancyPolicy::default()
+        };
+        unsafe { self.tap.as_mut() }.set_redundancy(policy);
     }
 
     /// Set whether TEP (Talker Echo Protection) time is allowed for.
-    pub fn set_tep_mode(&self, use_tep: bool) {
+    pub fn set_tep_mode(&mut self, use_tep: bool) {
         unsafe {
             spandsp_sys::t38_terminal_set_tep_mode(self.inner.as_ptr(), use_tep);
         }
     }
 
     /// Set fill bit removal mode.
-    pub fn set_fill_bit_removal(&self, remove: bool) {
+    pub fn set_fill_bit_removal(&mut self, remove: bool) {
         unsafe {
             spandsp_sys::t38_terminal_set_fill_bit_removal(self.inner.as_ptr(), remove);
         }
     }
 
     /// Restart the terminal.
-    pub fn restart(&self, calling_party: bool) -> Result<()> {
+    pub fn restart(&mut self, calling_party: bool) -> Result<()> {
         let rc = unsafe { spandsp_sys::t38_terminal_restart(self.inner.as_ptr(), calling_party) };
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
         }
+        unsafe { self.tap.as_mut() }.restart();
+        self.hooks.clear_quality();
         Ok(())
     }
 }
@@ -96,10 +340,22 @@ impl T38Terminal {
 // but exclusive access can be guaranteed externally (e.g., via tokio::sync::Mutex).
 unsafe impl Send for T38Terminal {}
 
+impl fmt::Debug for T38Terminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T38Terminal")
+            .field(
+                "call_active",
+                &self.peek_t30().map(|t30| t30.call_active()).ok(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for T38Terminal {
     fn drop(&mut self) {
         unsafe {
             spandsp_sys::t38_terminal_free(self.inner.as_ptr());
+            IfpTap::free(self.tap);
         }
     }
 }
@@ -8,6 +8,7 @@ extern crate spandsp_sys;
 use std::fmt;
 
 use crate::error::SpanDspError;
+use crate::tz::Timezone;
 
 // ---------------------------------------------------------------------------
 // T4Compression
@@ -130,6 +131,42 @@ impl fmt::Display for T4DecodeStatus {
     }
 }
 
+// ---------------------------------------------------------------------------
+// T4ImageType
+// ---------------------------------------------------------------------------
+
+/// The pixel layout of raw image rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u32)]
+pub enum T4ImageType {
+    /// Bi-level, one bit per pixel, MSB first, with 1 for black.
+    #[default]
+    Bilevel = spandsp_sys::t4_image_types_t_T4_IMAGE_TYPE_BILEVEL,
+    /// 8-bit grey-scale, one byte per pixel, with 0 for black.
+    Gray8 = spandsp_sys::t4_image_types_t_T4_IMAGE_TYPE_GRAY_8BIT,
+    /// 12-bit grey-scale, two bytes per pixel.
+    Gray12 = spandsp_sys::t4_image_types_t_T4_IMAGE_TYPE_GRAY_12BIT,
+    /// 8-bit colour, three bytes (R, G, B) per pixel.
+    Colour8 = spandsp_sys::t4_image_types_t_T4_IMAGE_TYPE_COLOUR_8BIT,
+    /// 12-bit colour, three 16-bit samples per pixel.
+    Colour12 = spandsp_sys::t4_image_types_t_T4_IMAGE_TYPE_COLOUR_12BIT,
+}
+
+impl T4ImageType {
+    /// Bytes in a row `width` pixels wide.
+    pub fn row_bytes(self, width: u32) -> usize {
+        let width = width as usize;
+        match self {
+            Self::Bilevel => width.div_ceil(8),
+            Self::Gray8 => width,
+            Self::Gray12 => 2 * width,
+            Self::Colour8 => 3 * width,
+            Self::Colour12 => 6 * width,
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // T4Stats
 // ---------------------------------------------------------------------------
@@ -195,3 +232,108 @@ impl From<spandsp_sys::t4_stats_t> for T4Stats {
         }
     }
 }
+
+// ---------------------------------------------------------------------------
+// PageHeader
+// ---------------------------------------------------------------------------
+
+/// Longest local identity T.30 can carry in TSI/CSI.
+pub const MAX_IDENT_LEN: usize = 20;
+
+/// Longest free-text field spandsp puts in a page header line.
+pub const MAX_PAGE_HEADER_INFO_LEN: usize = 50;
+
+/// Everything that goes into the header line stamped on transmitted pages.
+///
+/// Build with the `with_*` methods, then apply in one call with
+/// `T4Tx::set_page_header` or the fax session types' `set_page_header`.
+/// Unset fields leave the header without that element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageHeader {
+    /// Local identity (as sent in TSI), printed in the header.
+    pub local_ident: Option<String>,
+    /// Free text printed in the header, typically the sender's name.
+    pub info: Option<String>,
+    /// Draw the header over the top of the image instead of adding lines.
+    pub overlays_image: bool,
+    /// POSIX `TZ` rule for the header timestamp; UTC if unset.
+    pub timezone: Option<String>,
+}
+
+impl PageHeader {
+    /// An empty header.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the local identity.
+    pub fn with_local_ident(mut self, ident: impl Into<String>) -> Self {
+        self.local_ident = Some(ident.into());
+        self
+    }
+
+    /// Set the header text.
+    pub fn with_info(mut self, info: impl Into<String>) -> Self {
+        self.info = Some(info.into());
+        self
+    }
+
+    /// Choose whether the header overlays the image.
+    pub fn with_overlay(mut self, overlays_image: bool) -> Self {
+        self.overlays_image = overlays_image;
+        self
+    }
+
+    /// Set the time zone rule, e.g. `"CET-1CEST,M3.5.0,M10.5.0/3"`.
+    pub fn with_timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.timezone = Some(timezone.into());
+        self
+    }
+
+    /// Stamp the header in the local time of an already parsed zone.
+    pub fn with_tz(self, tz: &Timezone) -> Self {
+        self.with_timezone(tz.spec())
+    }
+
+    /// Check the fields against the limits spandsp and T.30 impose.
+    ///
+    /// The identity must be printable ASCII of at most [`MAX_IDENT_LEN`]
+    /// characters and the info text at most [`MAX_PAGE_HEADER_INFO_LEN`]
+    /// bytes; no field may contain NUL.
+    pub fn validate(&self) -> Result<(), SpanDspError> {
+        if let Some(ident) = &self.local_ident {
+            if ident.len() > MAX_IDENT_LEN {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "local ident is {} characters, limit is {MAX_IDENT_LEN}",
+                    ident.len()
+                )));
+            }
+            if !ident.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+                return Err(SpanDspError::InvalidInput(
+                    "local ident must be printable ASCII".into(),
+                ));
+            }
+        }
+        if let Some(info) = &self.info {
+            if info.len() > MAX_PAGE_HEADER_INFO_LEN {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "header info is {} bytes, limit is {MAX_PAGE_HEADER_INFO_LEN}",
+                    info.len()
+                )));
+            }
+            if info.contains('\0') {
+                return Err(SpanDspError::InvalidInput(
+                    "header info contains NUL byte".into(),
+                ));
+            }
+        }
+        if let Some(tz) = &self.timezone
+            && (tz.is_empty() || tz.contains('\0'))
+        {
+            return Err(SpanDspError::InvalidInput(
+                "time zone must be a non-empty rule without NUL".into(),
+            ));
+        }
+        Ok(())
+    }
+}
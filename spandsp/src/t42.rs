@@ -0,0 +1,352 @@
+//! T.42 (JPEG) continuous-tone colour and grey-scale image coding.
+//!
+//! - [`T42Encoder`] wraps `t42_encode_state_t` (raw image rows via
+//!   callback → T.42 JPEG data).
+//! - [`T42Decoder`] wraps `t42_decode_state_t` (T.42 JPEG data → raw image
+//!   rows via callback).
+//!
+//! These are the codecs behind
+//! [`T4Compression::T42_T81`](crate::t4::T4Compression::T42_T81), and
+//! complement the bi-level [`T4T6Encoder`](crate::t4_tx::T4T6Encoder) and
+//! [`T85Encoder`](crate::t85::T85Encoder) with the same row callbacks.
+//! Rows hold one byte per pixel for grey-scale and three (R, G, B) for
+//! colour; the conversion to and from the ITULAB colour space T.42 sends
+//! on the line is done inside spandsp.
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::logging::LoggingState;
+use crate::t4::{T4DecodeStatus, T4ImageType};
+use crate::t4_rx::{RowWriteCallback, row_write_trampoline};
+use crate::t4_tx::{RowReadCallback, row_read_trampoline};
+
+/// Fail unless T.42 can carry `image_type`: its JPEG coding is 8-bit only.
+fn check_image_type(image_type: T4ImageType) -> Result<()> {
+    match image_type {
+        T4ImageType::Gray8 | T4ImageType::Colour8 => Ok(()),
+        other => Err(SpanDspError::InvalidInput(format!(
+            "T.42 codes 8-bit grey-scale or colour images, not {other:?}"
+        ))),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// T42Encoder
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `t42_encode_state_t`.
+///
+/// Compresses raw image rows (supplied via callback) into T.42 JPEG data.
+/// No file I/O is involved.
+///
+/// Created via [`T42Encoder::new()`]. Freed on drop via `t42_encode_free`.
+pub struct T42Encoder {
+    ptr: NonNull<spandsp_sys::t42_encode_state_t>,
+    image_type: T4ImageType,
+    _callback: Option<Box<RowReadCallback>>,
+}
+
+impl T42Encoder {
+    /// Create a new T.42 encoder.
+    ///
+    /// - `image_type`: [`T4ImageType::Gray8`] or [`T4ImageType::Colour8`],
+    ///   which sets the row layout.
+    /// - `image_width`: the image width in pixels.
+    /// - `image_length`: the image length in pixels.
+    /// - `handler`: closure called to read each image row. Receives a mutable
+    ///   buffer `&mut [u8]` to fill with row data. Return the number of bytes
+    ///   filled, or `0` to signal end of image.
+    pub fn new<F>(
+        image_type: T4ImageType,
+        image_width: u32,
+        image_length: u32,
+        handler: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(&mut [u8]) -> usize + 'static,
+    {
+        let boxed: Box<RowReadCallback> = Box::new(Box::new(handler));
+        let user_data = &*boxed as *const RowReadCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::t42_encode_init(
+                std::ptr::null_mut(),
+                image_width,
+                image_length,
+                Some(row_read_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        let mut encoder = Self {
+            ptr,
+            image_type,
+            _callback: Some(boxed),
+        };
+        encoder.set_image_type(image_type)?;
+        Ok(encoder)
+    }
+
+    /// Get the next chunk of compressed data.
+    ///
+    /// Returns the number of bytes written to `buf`. If this is less than
+    /// `buf.len()`, the end of the image has been reached.
+    pub fn get(&mut self, buf: &mut [u8]) -> usize {
+        let rc =
+            unsafe { spandsp_sys::t42_encode_get(self.ptr.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+        rc.max(0) as usize
+    }
+
+    /// Check whether the current image is complete.
+    pub fn image_complete(&self) -> bool {
+        unsafe { spandsp_sys::t42_encode_image_complete(self.ptr.as_ptr()) != 0 }
+    }
+
+    /// Restart the encoder with a new image width and length.
+    pub fn restart(&mut self, image_width: u32, image_length: u32) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::t42_encode_restart(self.ptr.as_ptr(), image_width, image_length)
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Set whether the rows are grey-scale or colour.
+    ///
+    /// Returns `InvalidInput` for anything but 8-bit grey-scale or colour.
+    pub fn set_image_type(&mut self, image_type: T4ImageType) -> Result<()> {
+        check_image_type(image_type)?;
+        let rc = unsafe {
+            spandsp_sys::t42_encode_set_image_type(self.ptr.as_ptr(), image_type as c_int)
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        self.image_type = image_type;
+        Ok(())
+    }
+
+    /// The kind of image being encoded.
+    pub fn image_type(&self) -> T4ImageType {
+        self.image_type
+    }
+
+    /// Set the image width in pixels.
+    pub fn set_image_width(&mut self, width: u32) -> Result<()> {
+        let rc = unsafe { spandsp_sys::t42_encode_set_image_width(self.ptr.as_ptr(), width) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Set the image length in pixels.
+    pub fn set_image_length(&mut self, length: u32) -> Result<()> {
+        let rc = unsafe { spandsp_sys::t42_encode_set_image_length(self.ptr.as_ptr(), length) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Add a JPEG comment marker to the image.
+    pub fn comment(&mut self, comment: &[u8]) {
+        unsafe {
+            spandsp_sys::t42_encode_comment(self.ptr.as_ptr(), comment.as_ptr(), comment.len());
+        }
+    }
+
+    /// Abandon the current image.
+    pub fn abort(&mut self) {
+        unsafe {
+            spandsp_sys::t42_encode_abort(self.ptr.as_ptr());
+        }
+    }
+
+    /// Get the width of the image in pixels.
+    pub fn image_width(&self) -> u32 {
+        unsafe { spandsp_sys::t42_encode_get_image_width(self.ptr.as_ptr()) }
+    }
+
+    /// Get the length of the image in pixels.
+    pub fn image_length(&self) -> u32 {
+        unsafe { spandsp_sys::t42_encode_get_image_length(self.ptr.as_ptr()) }
+    }
+
+    /// Get the size of the compressed image in bits.
+    pub fn compressed_image_size(&self) -> i32 {
+        unsafe { spandsp_sys::t42_encode_get_compressed_image_size(self.ptr.as_ptr()) }
+    }
+
+    /// Get the logging state associated with this encoder.
+    ///
+    /// # Safety
+    ///
+    /// The returned [`LoggingState`] borrows from this `T42Encoder` and must
+    /// not outlive it.
+    pub unsafe fn get_logging_state(&self) -> LoggingState {
+        let ptr = unsafe { spandsp_sys::t42_encode_get_logging_state(self.ptr.as_ptr()) };
+        let ptr = NonNull::new(ptr).expect("t42_encode_get_logging_state returned NULL");
+        unsafe { LoggingState::from_ptr_borrowed(ptr) }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::t42_encode_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for T42Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T42Encoder")
+            .field("image_type", &self.image_type)
+            .field("image_width", &self.image_width())
+            .field("image_length", &self.image_length())
+            .field("image_complete", &self.image_complete())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for T42Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::t42_encode_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// T42Decoder
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `t42_decode_state_t`.
+///
+/// Decompresses T.42 JPEG data, handing each decoded row to a callback.
+/// The image size and type come from the JPEG headers.
+///
+/// spandsp collects the compressed data and decodes it in one go, so no
+/// rows arrive until [`finish`](Self::finish) is called.
+///
+/// Created via [`T42Decoder::new()`]. Freed on drop via `t42_decode_free`.
+pub struct T42Decoder {
+    ptr: NonNull<spandsp_sys::t42_decode_state_t>,
+    _callback: Option<Box<RowWriteCallback>>,
+}
+
+impl T42Decoder {
+    /// Create a new T.42 decoder.
+    ///
+    /// - `handler`: closure called for each decoded row. Receives the row
+    ///   pixel data as `&[u8]`. Return `true` to continue, `false` to abort.
+    pub fn new<F>(handler: F) -> Result<Self>
+    where
+        F: FnMut(&[u8]) -> bool + 'static,
+    {
+        let boxed: Box<RowWriteCallback> = Box::new(Box::new(handler));
+        let user_data = &*boxed as *const RowWriteCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::t42_decode_init(
+                std::ptr::null_mut(),
+                Some(row_write_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            _callback: Some(boxed),
+        })
+    }
+
+    /// Feed a block of compressed data to the decoder.
+    pub fn put(&mut self, buf: &[u8]) -> T4DecodeStatus {
+        let rc = unsafe { spandsp_sys::t42_decode_put(self.ptr.as_ptr(), buf.as_ptr(), buf.len()) };
+        T4DecodeStatus::try_from(rc).unwrap_or(T4DecodeStatus::InvalidData)
+    }
+
+    /// Tell the decoder the compressed data has ended, and decode it.
+    pub fn finish(&mut self) -> T4DecodeStatus {
+        let rc = unsafe { spandsp_sys::t42_decode_put(self.ptr.as_ptr(), std::ptr::null(), 0) };
+        T4DecodeStatus::try_from(rc).unwrap_or(T4DecodeStatus::InvalidData)
+    }
+
+    /// Restart the decoder for a new image.
+    pub fn restart(&mut self) -> Result<()> {
+        let rc = unsafe { spandsp_sys::t42_decode_restart(self.ptr.as_ptr()) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Refuse images larger than `max_width` by `max_length` pixels, so a
+    /// corrupt or hostile header cannot demand a huge buffer.
+    pub fn set_image_size_constraints(&mut self, max_width: u32, max_length: u32) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::t42_decode_set_image_size_constraints(
+                self.ptr.as_ptr(),
+                max_width,
+                max_length,
+            )
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Get the width of the image in pixels.
+    pub fn image_width(&self) -> u32 {
+        unsafe { spandsp_sys::t42_decode_get_image_width(self.ptr.as_ptr()) }
+    }
+
+    /// Get the length of the image in pixels.
+    pub fn image_length(&self) -> u32 {
+        unsafe { spandsp_sys::t42_decode_get_image_length(self.ptr.as_ptr()) }
+    }
+
+    /// Get the size of the compressed image in bits.
+    pub fn compressed_image_size(&self) -> i32 {
+        unsafe { spandsp_sys::t42_decode_get_compressed_image_size(self.ptr.as_ptr()) }
+    }
+
+    /// Get the logging state associated with this decoder.
+    ///
+    /// # Safety
+    ///
+    /// The returned [`LoggingState`] borrows from this `T42Decoder` and must
+    /// not outlive it.
+    pub unsafe fn get_logging_state(&self) -> LoggingState {
+        let ptr = unsafe { spandsp_sys::t42_decode_get_logging_state(self.ptr.as_ptr()) };
+        let ptr = NonNull::new(ptr).expect("t42_decode_get_logging_state returned NULL");
+        unsafe { LoggingState::from_ptr_borrowed(ptr) }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::t42_decode_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for T42Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T42Decoder")
+            .field("image_width", &self.image_width())
+            .field("image_length", &self.image_length())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for T42Decoder {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::t42_decode_free(self.ptr.as_ptr());
+        }
+    }
+}
@@ -2,31 +2,36 @@
 //!
 //! - [`T4Rx`] wraps `t4_rx_state_t` for high-level file-based receive
 //!   (compressed fax data → TIFF file).
+//! - [`PagedT4Rx`] drives a fresh `T4Rx` per page, writing each page to a
+//!   file named by a user callback.
 //! - [`T4T6Decoder`] wraps `t4_t6_decode_state_t` for low-level
 //!   decompression (compressed bits → raw image rows via callback).
 
 extern crate spandsp_sys;
 
 use std::ffi::CString;
+use std::fmt;
 use std::os::raw::{c_int, c_void};
+use std::path::PathBuf;
 use std::ptr::NonNull;
+use std::time::SystemTime;
 
 use crate::error::{Result, SpanDspError};
 use crate::logging::LoggingState;
 use crate::t4::{T4Compression, T4DecodeStatus, T4Stats};
 
 // ---------------------------------------------------------------------------
-// Row-write callback trampoline (shared by T4Rx and T4T6Decoder)
+// Row-write callback trampoline (shared by T4Rx and the decoders)
 // ---------------------------------------------------------------------------
 
-type RowWriteCallback = Box<dyn FnMut(&[u8]) -> bool>;
+pub(crate) type RowWriteCallback = Box<dyn FnMut(&[u8]) -> bool>;
 
 /// Trampoline for `t4_row_write_handler_t`.
 ///
 /// # Safety
 ///
 /// `user_data` must point to a valid `RowWriteCallback`.
-unsafe extern "C" fn row_write_trampoline(
+pub(crate) unsafe extern "C" fn row_write_trampoline(
     user_data: *mut c_void,
     buf: *const u8,
     len: usize,
@@ -216,6 +221,16 @@ impl T4Rx {
     }
 }
 
+impl fmt::Debug for T4Rx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.get_transfer_statistics();
+        f.debug_struct("T4Rx")
+            .field("pages_transferred", &stats.pages_transferred)
+            .field("bad_rows", &stats.bad_rows)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for T4Rx {
     fn drop(&mut self) {
         unsafe {
@@ -224,6 +239,256 @@ impl Drop for T4Rx {
     }
 }
 
+// ---------------------------------------------------------------------------
+// PagedT4Rx — one TIFF file per received page
+// ---------------------------------------------------------------------------
+
+/// Details of a page about to be received, passed to the [`PagedT4Rx`]
+/// naming callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RxPageInfo {
+    /// 1-based number of the page within the session.
+    pub page: u32,
+    /// Column-to-column resolution in pixels per metre (0 if not yet set).
+    pub x_resolution: i32,
+    /// Row-to-row resolution in pixels per metre (0 if not yet set).
+    pub y_resolution: i32,
+    /// Wall-clock time at which the page was started.
+    pub started_at: SystemTime,
+}
+
+type PageNamer = Box<dyn FnMut(&RxPageInfo) -> PathBuf>;
+
+/// Receiver that writes each page to its own single-page TIFF file.
+///
+/// Offers the same page-level API as [`T4Rx`]. On every
+/// [`start_page()`](Self::start_page) the naming callback is asked for a
+/// path, a fresh `T4Rx` is opened on it with the settings made so far, and
+/// [`end_page()`](Self::end_page) closes the file again.
+pub struct PagedT4Rx {
+    compressions: T4Compression,
+    namer: PageNamer,
+    current: Option<T4Rx>,
+    pages_started: u32,
+    encoding: Option<T4Compression>,
+    image_width: Option<i32>,
+    x_resolution: i32,
+    y_resolution: i32,
+    dcs: Option<String>,
+    sub_address: Option<String>,
+    far_ident: Option<String>,
+    vendor: Option<String>,
+    model: Option<String>,
+    written: Vec<PathBuf>,
+}
+
+/// Reject strings that cannot be passed to spandsp.
+fn check_nul(s: &str, what: &str) -> Result<String> {
+    if s.contains('\0') {
+        return Err(SpanDspError::InvalidInput(format!(
+            "{what} contains NUL byte"
+        )));
+    }
+    Ok(s.to_owned())
+}
+
+impl PagedT4Rx {
+    /// Create a receiver whose page paths are chosen by `namer`.
+    ///
+    /// - `compressions`: supported output compression schemes for each file.
+    /// - `namer`: called at the start of every page with its [`RxPageInfo`].
+    pub fn new<F>(compressions: T4Compression, namer: F) -> Self
+    where
+        F: FnMut(&RxPageInfo) -> PathBuf + 'static,
+    {
+        Self {
+            compressions,
+            namer: Box::new(namer),
+            current: None,
+            pages_started: 0,
+            encoding: None,
+            image_width: None,
+            x_resolution: 0,
+            y_resolution: 0,
+            dcs: None,
+            sub_address: None,
+            far_ident: None,
+            vendor: None,
+            model: None,
+            written: Vec::new(),
+        }
+    }
+
+    /// Open the next page's file and prepare to receive into it.
+    ///
+    /// A page that was started but never ended is closed first.
+    pub fn start_page(&mut self) -> Result<()> {
+        self.current = None;
+        self.pages_started += 1;
+        let info = RxPageInfo {
+            page: self.pages_started,
+            x_resolution: self.x_resolution,
+            y_resolution: self.y_resolution,
+            started_at: SystemTime::now(),
+        };
+        let path = (self.namer)(&info);
+        let file = path
+            .to_str()
+            .ok_or_else(|| SpanDspError::InvalidInput("page path is not valid UTF-8".into()))?;
+
+        let mut rx = T4Rx::new(file, self.compressions)?;
+        if let Some(encoding) = self.encoding {
+            rx.set_rx_encoding(encoding)?;
+        }
+        if let Some(width) = self.image_width {
+            rx.set_image_width(width);
+        }
+        if self.x_resolution != 0 {
+            rx.set_x_resolution(self.x_resolution);
+        }
+        if self.y_resolution != 0 {
+            rx.set_y_resolution(self.y_resolution);
+        }
+        if let Some(dcs) = &self.dcs {
+            rx.set_dcs(dcs)?;
+        }
+        if let Some(sub_address) = &self.sub_address {
+            rx.set_sub_address(sub_address)?;
+        }
+        if let Some(ident) = &self.far_ident {
+            rx.set_far_ident(ident)?;
+        }
+        if let Some(vendor) = &self.vendor {
+            rx.set_vendor(vendor)?;
+        }
+        if let Some(model) = &self.model {
+            rx.set_model(model)?;
+        }
+        rx.start_page()?;
+        self.current = Some(rx);
+        self.written.push(path);
+        Ok(())
+    }
+
+    /// Feed a block of compressed data to the current page.
+    ///
+    /// Returns [`T4DecodeStatus::InvalidData`] if no page has been started.
+    pub fn put(&mut self, buf: &[u8]) -> T4DecodeStatus {
+        match &mut self.current {
+            Some(rx) => rx.put(buf),
+            None => T4DecodeStatus::InvalidData,
+        }
+    }
+
+    /// Feed a single bit of compressed data to the current page.
+    ///
+    /// Returns [`T4DecodeStatus::InvalidData`] if no page has been started.
+    pub fn put_bit(&mut self, bit: i32) -> T4DecodeStatus {
+        match &mut self.current {
+            Some(rx) => rx.put_bit(bit),
+            None => T4DecodeStatus::InvalidData,
+        }
+    }
+
+    /// Complete the current page and close its file.
+    pub fn end_page(&mut self) -> Result<()> {
+        let mut rx = self
+            .current
+            .take()
+            .ok_or_else(|| SpanDspError::InvalidInput("no page in progress".into()))?;
+        rx.end_page()
+    }
+
+    /// Set the encoding for received data (applies from the next page).
+    pub fn set_rx_encoding(&mut self, encoding: T4Compression) -> Result<()> {
+        if let Some(rx) = &mut self.current {
+            rx.set_rx_encoding(encoding)?;
+        }
+        self.encoding = Some(encoding);
+        Ok(())
+    }
+
+    /// Set the expected width of the received image in pixel columns.
+    pub fn set_image_width(&mut self, width: i32) {
+        if let Some(rx) = &mut self.current {
+            rx.set_image_width(width);
+        }
+        self.image_width = Some(width);
+    }
+
+    /// Set the column-to-column (x) resolution in pixels per metre.
+    pub fn set_x_resolution(&mut self, resolution: i32) {
+        if let Some(rx) = &mut self.current {
+            rx.set_x_resolution(resolution);
+        }
+        self.x_resolution = resolution;
+    }
+
+    /// Set the row-to-row (y) resolution in pixels per metre.
+    pub fn set_y_resolution(&mut self, resolution: i32) {
+        if let Some(rx) = &mut self.current {
+            rx.set_y_resolution(resolution);
+        }
+        self.y_resolution = resolution;
+    }
+
+    /// Set the DCS information string written into each file.
+    pub fn set_dcs(&mut self, dcs: &str) -> Result<()> {
+        self.dcs = Some(check_nul(dcs, "DCS")?);
+        Ok(())
+    }
+
+    /// Set the sub-address written into each file.
+    pub fn set_sub_address(&mut self, sub_address: &str) -> Result<()> {
+        self.sub_address = Some(check_nul(sub_address, "sub-address")?);
+        Ok(())
+    }
+
+    /// Set the remote identity written into each file.
+    pub fn set_far_ident(&mut self, ident: &str) -> Result<()> {
+        self.far_ident = Some(check_nul(ident, "far ident")?);
+        Ok(())
+    }
+
+    /// Set the remote vendor written into each file.
+    pub fn set_vendor(&mut self, vendor: &str) -> Result<()> {
+        self.vendor = Some(check_nul(vendor, "vendor")?);
+        Ok(())
+    }
+
+    /// Set the remote model written into each file.
+    pub fn set_model(&mut self, model: &str) -> Result<()> {
+        self.model = Some(check_nul(model, "model")?);
+        Ok(())
+    }
+
+    /// Number of pages started so far.
+    pub fn pages_started(&self) -> u32 {
+        self.pages_started
+    }
+
+    /// Paths of the files opened so far, in page order.
+    pub fn written_paths(&self) -> &[PathBuf] {
+        &self.written
+    }
+
+    /// Statistics for the page currently being received, if any.
+    pub fn get_transfer_statistics(&self) -> Option<T4Stats> {
+        self.current.as_ref().map(T4Rx::get_transfer_statistics)
+    }
+}
+
+impl fmt::Debug for PagedT4Rx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PagedT4Rx")
+            .field("pages_started", &self.pages_started)
+            .field("page_in_progress", &self.current.is_some())
+            .field("x_resolution", &self.x_resolution)
+            .field("y_resolution", &self.y_resolution)
+            .finish_non_exhaustive()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // T4T6Decoder — low-level decompressor
 // ---------------------------------------------------------------------------
@@ -335,6 +600,15 @@ impl T4T6Decoder {
     }
 }
 
+impl fmt::Debug for T4T6Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T4T6Decoder")
+            .field("image_width", &self.image_width())
+            .field("image_length", &self.image_length())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for T4T6Decoder {
     fn drop(&mut self) {
         unsafe {
@@ -7,26 +7,29 @@
 
 extern crate spandsp_sys;
 
+use std::collections::BTreeSet;
 use std::ffi::CString;
+use std::fmt;
 use std::os::raw::{c_int, c_void};
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError};
 use crate::logging::LoggingState;
-use crate::t4::{T4Compression, T4Stats};
+use crate::t4::{PageHeader, T4Compression, T4Stats};
+use crate::tz::Timezone;
 
 // ---------------------------------------------------------------------------
-// Row-read callback trampoline (shared by T4Tx and T4T6Encoder)
+// Row-read callback trampoline (shared by T4Tx, ImageTranslate and the encoders)
 // ---------------------------------------------------------------------------
 
-type RowReadCallback = Box<dyn FnMut(&mut [u8]) -> usize>;
+pub(crate) type RowReadCallback = Box<dyn FnMut(&mut [u8]) -> usize>;
 
 /// Trampoline for `t4_row_read_handler_t`.
 ///
 /// # Safety
 ///
 /// `user_data` must point to a valid `RowReadCallback`.
-unsafe extern "C" fn row_read_trampoline(
+pub(crate) unsafe extern "C" fn row_read_trampoline(
     user_data: *mut c_void,
     buf: *mut u8,
     len: usize,
@@ -54,8 +57,34 @@ unsafe extern "C" fn row_read_trampoline(
 ///
 /// Reads pages from a TIFF file and produces compressed fax data.
 /// Created via [`T4Tx::new()`]. Freed on drop via `t4_tx_free`.
+///
+/// The pages sent can be changed while the file is open, with
+/// [`select_pages`](Self::select_pages), [`skip_page`](Self::skip_page) and
+/// [`send_only`](Self::send_only), e.g. to resend just the pages a call
+/// failed on.
 pub struct T4Tx {
     ptr: NonNull<spandsp_sys::t4_tx_state_t>,
+    file: CString,
+    /// Pages selected, as given to `t4_tx_init`.
+    range: (i32, i32),
+    skipped: BTreeSet<i32>,
+    // spandsp keeps pointers to the header strings and time zone rather than
+    // copying them, so they live here for as long as the state does.
+    header_ident: Option<CString>,
+    header_info: Option<CString>,
+    header_tz: Option<Timezone>,
+    /// Settings to apply again when the state is reinitialised for a new
+    /// page selection.
+    settings: TxSettings,
+}
+
+/// Settings made through the [`T4Tx`] setters.
+#[derive(Debug, Clone, Copy, Default)]
+struct TxSettings {
+    image_format: Option<(T4Compression, i32, i32, i32)>,
+    min_bits_per_row: Option<i32>,
+    max_2d_rows_per_1d_row: Option<i32>,
+    header_overlays_image: Option<bool>,
 }
 
 impl T4Tx {
@@ -76,11 +105,136 @@ impl T4Tx {
             )
         };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            file: c_file,
+            range: (start_page, stop_page),
+            skipped: BTreeSet::new(),
+            header_ident: None,
+            header_info: None,
+            header_tz: None,
+            settings: TxSettings::default(),
+        })
+    }
+
+    /// Send pages `start_page..=stop_page` (`-1` for no restriction) from
+    /// the next [`start_page`](Self::start_page) on, clearing any skipped
+    /// pages.
+    ///
+    /// The file stays the same but the transmitter starts over, as if it
+    /// had just been created with this range; the settings made through its
+    /// setters carry over.
+    pub fn select_pages(&mut self, start_page: i32, stop_page: i32) -> Result<()> {
+        self.reselect(start_page, stop_page)?;
+        self.range = (start_page, stop_page);
+        self.skipped.clear();
+        Ok(())
+    }
+
+    /// Leave out page `page` of the selection when it comes up.
+    pub fn skip_page(&mut self, page: i32) {
+        self.skipped.insert(page);
+    }
+
+    /// Send only `pages`, in file order, e.g. the pages a previous attempt
+    /// failed on.
+    pub fn send_only(&mut self, pages: impl IntoIterator<Item = i32>) -> Result<()> {
+        let pages: BTreeSet<i32> = pages.into_iter().collect();
+        let (Some(&first), Some(&last)) = (pages.first(), pages.last()) else {
+            return Err(SpanDspError::InvalidInput("no pages to send".into()));
+        };
+        if first < 0 {
+            return Err(SpanDspError::InvalidInput(format!(
+                "page numbers cannot be negative, got {first}"
+            )));
+        }
+        self.select_pages(first, last)?;
+        self.skipped = (first..=last)
+            .filter(|page| !pages.contains(page))
+            .collect();
+        Ok(())
+    }
+
+    /// The selected range, as given to [`new`](Self::new) or
+    /// [`select_pages`](Self::select_pages).
+    pub fn page_range(&self) -> (i32, i32) {
+        self.range
+    }
+
+    /// Pages of the selection that will be left out.
+    pub fn skipped_pages(&self) -> impl Iterator<Item = i32> + '_ {
+        self.skipped.iter().copied()
     }
 
-    /// Prepare to send the next page.
+    /// Replace the state with a new one on the same file for
+    /// `start_page..=stop_page` and apply the settings again. The old state
+    /// is kept if the new one cannot be set up.
+    fn reselect(&mut self, start_page: i32, stop_page: i32) -> Result<()> {
+        let ptr = unsafe {
+            spandsp_sys::t4_tx_init(
+                std::ptr::null_mut(),
+                self.file.as_ptr(),
+                start_page,
+                stop_page,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        let old = std::mem::replace(&mut self.ptr, ptr);
+        unsafe {
+            spandsp_sys::t4_tx_free(old.as_ptr());
+        }
+        let p = ptr.as_ptr();
+        let settings = self.settings;
+        unsafe {
+            spandsp_sys::t4_tx_set_local_ident(
+                p,
+                self.header_ident
+                    .as_ref()
+                    .map_or(std::ptr::null(), |s| s.as_ptr()),
+            );
+            spandsp_sys::t4_tx_set_header_info(
+                p,
+                self.header_info
+                    .as_ref()
+                    .map_or(std::ptr::null(), |s| s.as_ptr()),
+            );
+            spandsp_sys::t4_tx_set_header_tz(
+                p,
+                self.header_tz
+                    .as_ref()
+                    .map_or(std::ptr::null_mut(), |tz| tz.as_ptr()),
+            );
+        }
+        if let Some(overlay) = settings.header_overlays_image {
+            unsafe { spandsp_sys::t4_tx_set_header_overlays_image(p, overlay) };
+        }
+        if let Some(bits) = settings.min_bits_per_row {
+            self.set_min_bits_per_row(bits);
+        }
+        if let Some(max) = settings.max_2d_rows_per_1d_row {
+            self.set_max_2d_rows_per_1d_row(max);
+        }
+        if let Some((compressions, sizes, bilevel, colour)) = settings.image_format {
+            self.set_tx_image_format(compressions, sizes, bilevel, colour)?;
+        }
+        Ok(())
+    }
+
+    /// Prepare to send the next page, passing over any skipped pages.
     pub fn start_page(&mut self) -> Result<()> {
+        let next = self.current_page_in_file();
+        if self.skipped.contains(&next) {
+            let stop = match self.range.1 {
+                stop if stop >= 0 => stop,
+                _ => i32::MAX,
+            };
+            let Some(page) = (next..=stop).find(|page| !self.skipped.contains(page)) else {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "no pages left to send: pages {next} to {stop} are all skipped"
+                )));
+            };
+            self.reselect(page, self.range.1)?;
+        }
         let rc = unsafe { spandsp_sys::t4_tx_start_page(self.ptr.as_ptr()) };
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
@@ -197,6 +351,12 @@ impl T4Tx {
         if rc != 0 {
             return Err(SpanDspError::ErrorCode(rc));
         }
+        self.settings.image_format = Some((
+            supported_compressions,
+            supported_image_sizes,
+            supported_bilevel_resolutions,
+            supported_colour_resolutions,
+        ));
         Ok(())
     }
 
@@ -205,6 +365,7 @@ impl T4Tx {
         unsafe {
             spandsp_sys::t4_tx_set_min_bits_per_row(self.ptr.as_ptr(), bits as c_int);
         }
+        self.settings.min_bits_per_row = Some(bits);
     }
 
     /// Set the maximum number of 2D encoded rows between 1D encoded rows.
@@ -212,33 +373,87 @@ impl T4Tx {
         unsafe {
             spandsp_sys::t4_tx_set_max_2d_rows_per_1d_row(self.ptr.as_ptr(), max as c_int);
         }
+        self.settings.max_2d_rows_per_1d_row = Some(max);
+    }
+
+    /// Configure the page header line in one step.
+    ///
+    /// The header is validated first (see [`PageHeader::validate`]); on error
+    /// the previous header settings are left in place.
+    pub fn set_page_header(&mut self, header: &PageHeader) -> Result<()> {
+        header.validate()?;
+        let ident = header.local_ident.as_deref().map(CString::new).transpose();
+        let info = header.info.as_deref().map(CString::new).transpose();
+        let (ident, info) = match (ident, info) {
+            (Ok(ident), Ok(info)) => (ident, info),
+            _ => {
+                return Err(SpanDspError::InvalidInput(
+                    "header contains NUL byte".into(),
+                ));
+            }
+        };
+        let tz = header.timezone.as_deref().map(Timezone::new).transpose()?;
+
+        let p = self.ptr.as_ptr();
+        unsafe {
+            spandsp_sys::t4_tx_set_local_ident(
+                p,
+                ident.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            );
+            spandsp_sys::t4_tx_set_header_info(
+                p,
+                info.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            );
+            spandsp_sys::t4_tx_set_header_overlays_image(p, header.overlays_image);
+            spandsp_sys::t4_tx_set_header_tz(
+                p,
+                tz.as_ref().map_or(std::ptr::null_mut(), |tz| tz.as_ptr()),
+            );
+        }
+        self.header_ident = ident;
+        self.header_info = info;
+        self.header_tz = tz;
+        self.settings.header_overlays_image = Some(header.overlays_image);
+        Ok(())
+    }
+
+    /// The time zone page header timestamps are stamped in, if one was set
+    /// with [`set_page_header`](Self::set_page_header); UTC otherwise.
+    pub fn header_timezone(&self) -> Option<&Timezone> {
+        self.header_tz.as_ref()
     }
 
     /// Set the identity of the local machine, for inclusion in page headers.
+    #[deprecated(note = "use `set_page_header`")]
     pub fn set_local_ident(&mut self, ident: &str) -> Result<()> {
         let c_ident = CString::new(ident)
             .map_err(|_| SpanDspError::InvalidInput("local ident contains NUL byte".into()))?;
         unsafe {
             spandsp_sys::t4_tx_set_local_ident(self.ptr.as_ptr(), c_ident.as_ptr());
         }
+        self.header_ident = Some(c_ident);
         Ok(())
     }
 
     /// Set the info field included in page header lines.
+    #[deprecated(note = "use `set_page_header`")]
     pub fn set_header_info(&mut self, info: &str) -> Result<()> {
         let c_info = CString::new(info)
             .map_err(|_| SpanDspError::InvalidInput("header info contains NUL byte".into()))?;
         unsafe {
             spandsp_sys::t4_tx_set_header_info(self.ptr.as_ptr(), c_info.as_ptr());
         }
+        self.header_info = Some(c_info);
         Ok(())
     }
 
     /// Set whether the page header overlays or extends the image.
+    #[deprecated(note = "use `set_page_header`")]
     pub fn set_header_overlays_image(&mut self, overlay: bool) {
         unsafe {
             spandsp_sys::t4_tx_set_header_overlays_image(self.ptr.as_ptr(), overlay);
         }
+        self.settings.header_overlays_image = Some(overlay);
     }
 
     /// Get the number of pages in the file.
@@ -279,6 +494,17 @@ impl T4Tx {
     }
 }
 
+impl fmt::Debug for T4Tx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T4Tx")
+            .field("pages_in_file", &self.pages_in_file())
+            .field("current_page_in_file", &self.current_page_in_file())
+            .field("page_range", &self.range)
+            .field("image_width", &self.get_tx_image_width())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for T4Tx {
     fn drop(&mut self) {
         unsafe {
@@ -457,6 +683,16 @@ impl T4T6Encoder {
     }
 }
 
+impl fmt::Debug for T4T6Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T4T6Encoder")
+            .field("image_width", &self.image_width())
+            .field("image_length", &self.image_length())
+            .field("image_complete", &self.image_complete())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for T4T6Encoder {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,328 @@
+//! T.85 (JBIG) monochrome image coding.
+//!
+//! - [`T85Encoder`] wraps `t85_encode_state_t` (raw image rows via
+//!   callback → JBIG BIE).
+//! - [`T85Decoder`] wraps `t85_decode_state_t` (JBIG BIE → raw image rows
+//!   via callback).
+//!
+//! These are the low-level codecs behind [`T4Compression::T85`] and
+//! [`T4Compression::T85_L0`](crate::t4::T4Compression::T85_L0), with the
+//! same row callbacks as [`T4T6Encoder`](crate::t4_tx::T4T6Encoder) and
+//! [`T4T6Decoder`](crate::t4_rx::T4T6Decoder). Rows are packed one bit per
+//! pixel, MSB first, with 1 for black.
+//!
+//! [`T4Compression::T85`]: crate::t4::T4Compression::T85
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+use crate::logging::LoggingState;
+use crate::t4::T4DecodeStatus;
+use crate::t4_rx::{RowWriteCallback, row_write_trampoline};
+use crate::t4_tx::{RowReadCallback, row_read_trampoline};
+
+// ---------------------------------------------------------------------------
+// T85Encoder
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `t85_encode_state_t`.
+///
+/// Compresses raw image rows (supplied via callback) into a T.85 bi-level
+/// image entity. No file I/O is involved.
+///
+/// Created via [`T85Encoder::new()`]. Freed on drop via `t85_encode_free`.
+pub struct T85Encoder {
+    ptr: NonNull<spandsp_sys::t85_encode_state_t>,
+    _callback: Option<Box<RowReadCallback>>,
+}
+
+impl T85Encoder {
+    /// Create a new T.85 encoder.
+    ///
+    /// - `image_width`: the image width in pixels.
+    /// - `image_length`: the image length in pixels. If it is not known
+    ///   up front, give an upper bound and correct it with
+    ///   [`set_image_length`](Self::set_image_length) once the rows run out.
+    /// - `handler`: closure called to read each image row. Receives a mutable
+    ///   buffer `&mut [u8]` to fill with row data. Return the number of bytes
+    ///   filled, or `0` to signal end of image.
+    pub fn new<F>(image_width: u32, image_length: u32, handler: F) -> Result<Self>
+    where
+        F: FnMut(&mut [u8]) -> usize + 'static,
+    {
+        let boxed: Box<RowReadCallback> = Box::new(Box::new(handler));
+        let user_data = &*boxed as *const RowReadCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::t85_encode_init(
+                std::ptr::null_mut(),
+                image_width,
+                image_length,
+                Some(row_read_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            _callback: Some(boxed),
+        })
+    }
+
+    /// Get the next chunk of compressed data.
+    ///
+    /// Returns the number of bytes written to `buf`. If this is less than
+    /// `buf.len()`, the end of the image has been reached.
+    pub fn get(&mut self, buf: &mut [u8]) -> usize {
+        let rc =
+            unsafe { spandsp_sys::t85_encode_get(self.ptr.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+        rc.max(0) as usize
+    }
+
+    /// Get the next bit of compressed data.
+    ///
+    /// Returns 0 or 1 for data bits, or `SIG_STATUS_END_OF_DATA` when
+    /// the image is complete.
+    pub fn get_bit(&mut self) -> i32 {
+        unsafe { spandsp_sys::t85_encode_get_bit(self.ptr.as_ptr()) }
+    }
+
+    /// Check whether the current image is complete.
+    pub fn image_complete(&self) -> bool {
+        unsafe { spandsp_sys::t85_encode_image_complete(self.ptr.as_ptr()) != 0 }
+    }
+
+    /// Restart the encoder with a new image width and length.
+    pub fn restart(&mut self, image_width: u32, image_length: u32) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::t85_encode_restart(self.ptr.as_ptr(), image_width, image_length)
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Set the image width in pixels.
+    pub fn set_image_width(&mut self, width: u32) -> Result<()> {
+        let rc = unsafe { spandsp_sys::t85_encode_set_image_width(self.ptr.as_ptr(), width) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Set the image length in pixels.
+    ///
+    /// Once encoding has started the length can only shrink; the encoder
+    /// then sends a NEWLEN marker so the decoder learns the real length.
+    pub fn set_image_length(&mut self, length: u32) -> Result<()> {
+        let rc = unsafe { spandsp_sys::t85_encode_set_image_length(self.ptr.as_ptr(), length) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Add a comment marker to the image, sent before the next stripe.
+    pub fn comment(&mut self, comment: &[u8]) {
+        unsafe {
+            spandsp_sys::t85_encode_comment(self.ptr.as_ptr(), comment.as_ptr(), comment.len());
+        }
+    }
+
+    /// Abort the image, sending an ABORT marker in place of the rest of it.
+    pub fn abort(&mut self) {
+        unsafe {
+            spandsp_sys::t85_encode_abort(self.ptr.as_ptr());
+        }
+    }
+
+    /// Get the width of the image in pixels.
+    pub fn image_width(&self) -> u32 {
+        unsafe { spandsp_sys::t85_encode_get_image_width(self.ptr.as_ptr()) }
+    }
+
+    /// Get the length of the image in pixels.
+    pub fn image_length(&self) -> u32 {
+        unsafe { spandsp_sys::t85_encode_get_image_length(self.ptr.as_ptr()) }
+    }
+
+    /// Get the size of the compressed image in bits.
+    pub fn compressed_image_size(&self) -> i32 {
+        unsafe { spandsp_sys::t85_encode_get_compressed_image_size(self.ptr.as_ptr()) }
+    }
+
+    /// Get the logging state associated with this encoder.
+    ///
+    /// # Safety
+    ///
+    /// The returned [`LoggingState`] borrows from this `T85Encoder` and must
+    /// not outlive it.
+    pub unsafe fn get_logging_state(&self) -> LoggingState {
+        let ptr = unsafe { spandsp_sys::t85_encode_get_logging_state(self.ptr.as_ptr()) };
+        let ptr = NonNull::new(ptr).expect("t85_encode_get_logging_state returned NULL");
+        unsafe { LoggingState::from_ptr_borrowed(ptr) }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::t85_encode_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for T85Encoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T85Encoder")
+            .field("image_width", &self.image_width())
+            .field("image_length", &self.image_length())
+            .field("image_complete", &self.image_complete())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for T85Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::t85_encode_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// T85Decoder
+// ---------------------------------------------------------------------------
+
+/// RAII wrapper around `t85_decode_state_t`.
+///
+/// Decompresses a T.85 bi-level image entity, handing each decoded row to
+/// a callback. The image size comes from the BIE header, so unlike
+/// [`T4T6Decoder`](crate::t4_rx::T4T6Decoder) no width is needed up front.
+///
+/// Created via [`T85Decoder::new()`]. Freed on drop via `t85_decode_free`.
+pub struct T85Decoder {
+    ptr: NonNull<spandsp_sys::t85_decode_state_t>,
+    _callback: Option<Box<RowWriteCallback>>,
+}
+
+impl T85Decoder {
+    /// Create a new T.85 decoder.
+    ///
+    /// - `handler`: closure called for each decoded row. Receives the row
+    ///   pixel data as `&[u8]`. Return `true` to continue, `false` to abort.
+    pub fn new<F>(handler: F) -> Result<Self>
+    where
+        F: FnMut(&[u8]) -> bool + 'static,
+    {
+        let boxed: Box<RowWriteCallback> = Box::new(Box::new(handler));
+        let user_data = &*boxed as *const RowWriteCallback as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::t85_decode_init(
+                std::ptr::null_mut(),
+                Some(row_write_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            _callback: Some(boxed),
+        })
+    }
+
+    /// Feed a block of compressed data to the decoder.
+    pub fn put(&mut self, buf: &[u8]) -> T4DecodeStatus {
+        let rc = unsafe { spandsp_sys::t85_decode_put(self.ptr.as_ptr(), buf.as_ptr(), buf.len()) };
+        T4DecodeStatus::try_from(rc).unwrap_or(T4DecodeStatus::InvalidData)
+    }
+
+    /// Tell the decoder the compressed data has ended, flushing any rows
+    /// still held back. Needed when the image length was left open.
+    pub fn finish(&mut self) -> T4DecodeStatus {
+        let rc = unsafe { spandsp_sys::t85_decode_put(self.ptr.as_ptr(), std::ptr::null(), 0) };
+        T4DecodeStatus::try_from(rc).unwrap_or(T4DecodeStatus::InvalidData)
+    }
+
+    /// Feed a single bit of compressed data to the decoder.
+    pub fn put_bit(&mut self, bit: i32) -> T4DecodeStatus {
+        let rc = unsafe { spandsp_sys::t85_decode_put_bit(self.ptr.as_ptr(), bit as c_int) };
+        T4DecodeStatus::try_from(rc).unwrap_or(T4DecodeStatus::InvalidData)
+    }
+
+    /// Restart the decoder for a new image.
+    pub fn restart(&mut self) -> Result<()> {
+        let rc = unsafe { spandsp_sys::t85_decode_restart(self.ptr.as_ptr()) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Refuse images larger than `max_width` by `max_length` pixels, so a
+    /// corrupt or hostile header cannot demand a huge buffer.
+    pub fn set_image_size_constraints(&mut self, max_width: u32, max_length: u32) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::t85_decode_set_image_size_constraints(
+                self.ptr.as_ptr(),
+                max_width,
+                max_length,
+            )
+        };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(())
+    }
+
+    /// Get the width of the image in pixels.
+    pub fn image_width(&self) -> u32 {
+        unsafe { spandsp_sys::t85_decode_get_image_width(self.ptr.as_ptr()) }
+    }
+
+    /// Get the length of the image in pixels.
+    pub fn image_length(&self) -> u32 {
+        unsafe { spandsp_sys::t85_decode_get_image_length(self.ptr.as_ptr()) }
+    }
+
+    /// Get the size of the compressed image in bits.
+    pub fn compressed_image_size(&self) -> i32 {
+        unsafe { spandsp_sys::t85_decode_get_compressed_image_size(self.ptr.as_ptr()) }
+    }
+
+    /// Get the logging state associated with this decoder.
+    ///
+    /// # Safety
+    ///
+    /// The returned [`LoggingState`] borrows from this `T85Decoder` and must
+    /// not outlive it.
+    pub unsafe fn get_logging_state(&self) -> LoggingState {
+        let ptr = unsafe { spandsp_sys::t85_decode_get_logging_state(self.ptr.as_ptr()) };
+        let ptr = NonNull::new(ptr).expect("t85_decode_get_logging_state returned NULL");
+        unsafe { LoggingState::from_ptr_borrowed(ptr) }
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::t85_decode_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for T85Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("T85Decoder")
+            .field("image_width", &self.image_width())
+            .field("image_length", &self.image_length())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for T85Decoder {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::t85_decode_free(self.ptr.as_ptr());
+        }
+    }
+}
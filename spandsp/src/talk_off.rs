@@ -0,0 +1,328 @@
+//! DTMF talk-off and digit simulation benchmarks.
+//!
+//! Enabled by the `talk-off` feature. A DTMF receiver is judged on two
+//! numbers: how often speech and music make it report a digit nobody
+//! dialled (talk-off), and how often it misses digits that were. The
+//! industry benchmarks are recordings: the Mitel CM7291 talk-off tape and
+//! the six Bellcore TR-TSY-000763 tapes of speech, on which every digit
+//! reported is a false detection, and digit tapes whose dialled digits are
+//! known. [`run`] plays a set of [`Tape`]s through a fresh detector each and
+//! counts both, so a [`DtmfRxConfig`](crate::dtmf::DtmfRxConfig) preset can
+//! be checked before it goes into service. The tapes are not bundled: point
+//! [`talk_off_tapes`] at a directory holding them as 8 kHz WAV files.
+//!
+//! ```no_run
+//! use spandsp::dtmf::{DtmfRx, DtmfRxConfig};
+//! use spandsp::talk_off::{Tape, run, talk_off_tapes};
+//!
+//! let mut tapes = talk_off_tapes("test-data").unwrap();
+//! tapes.push(Tape::read_wav("digits", "digits.wav", "0123456789").unwrap());
+//! let report = run(&tapes, || {
+//!     let mut rx = DtmfRx::new()?;
+//!     rx.configure(&DtmfRxConfig {
+//!         twist_db: Some(6.0),
+//!         ..Default::default()
+//!     })?;
+//!     Ok(rx)
+//! })
+//! .unwrap();
+//! println!("{report}");
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+use crate::analyze::wav_samples;
+use crate::error::{Result, SpanDspError};
+
+/// Samples per second on the tapes.
+const SAMPLE_RATE: usize = 8000;
+
+/// Samples fed to the detector at a time.
+const CHUNK: usize = 160;
+
+/// The speech tapes, as named in spandsp's test data: the Mitel talk-off
+/// tape, then the six Bellcore tapes.
+pub const TALK_OFF_FILES: [&str; 7] = [
+    "mitel-cm7291-talkoff.wav",
+    "tr-tsy-00763-1.wav",
+    "tr-tsy-00763-2.wav",
+    "tr-tsy-00763-3.wav",
+    "tr-tsy-00763-4.wav",
+    "tr-tsy-00763-5.wav",
+    "tr-tsy-00763-6.wav",
+];
+
+// ---------------------------------------------------------------------------
+// Detectors
+// ---------------------------------------------------------------------------
+
+/// A DTMF receiver [`run`] can benchmark.
+pub trait DigitDetector {
+    /// Feed 8 kHz audio.
+    fn rx(&mut self, amp: &[i16]);
+
+    /// Take the digits detected so far.
+    fn take_digits(&mut self) -> String;
+}
+
+impl DigitDetector for crate::dtmf::DtmfRx {
+    fn rx(&mut self, amp: &[i16]) {
+        crate::dtmf::DtmfRx::rx(self, amp);
+    }
+
+    fn take_digits(&mut self) -> String {
+        self.get(usize::MAX)
+    }
+}
+
+#[cfg(feature = "pure-dtmf")]
+impl DigitDetector for crate::dtmf_pure::DtmfRx {
+    fn rx(&mut self, amp: &[i16]) {
+        crate::dtmf_pure::DtmfRx::rx(self, amp);
+    }
+
+    fn take_digits(&mut self) -> String {
+        self.get(usize::MAX)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tapes
+// ---------------------------------------------------------------------------
+
+/// A recording and the digits dialled on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tape {
+    /// Name reported for the tape, e.g. its file name.
+    pub name: String,
+    /// 8 kHz audio.
+    pub samples: Vec<i16>,
+    /// The digits dialled, in order; empty for a talk-off tape.
+    pub expected: String,
+}
+
+impl Tape {
+    /// A tape dialling `expected`.
+    pub fn new(name: impl Into<String>, samples: Vec<i16>, expected: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            samples,
+            expected: expected.into(),
+        }
+    }
+
+    /// A tape on which no digits are dialled.
+    pub fn talk_off(name: impl Into<String>, samples: Vec<i16>) -> Self {
+        Self::new(name, samples, "")
+    }
+
+    /// Read an 8 kHz mono WAV file: 16-bit PCM, A-law or u-law.
+    pub fn read_wav(
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+        expected: impl Into<String>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let wav = std::fs::read(path).map_err(|e| {
+            SpanDspError::InvalidInput(format!("cannot read tape {}: {e}", path.display()))
+        })?;
+        Ok(Self::new(name, wav_samples(&wav)?, expected))
+    }
+
+    /// Length of the recording, in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.samples.len() as f64 / SAMPLE_RATE as f64
+    }
+}
+
+/// Read the speech tapes in [`TALK_OFF_FILES`] from `dir`.
+///
+/// Every file must be present; a missing or unreadable one is an
+/// `InvalidInput` error.
+pub fn talk_off_tapes(dir: impl AsRef<Path>) -> Result<Vec<Tape>> {
+    let dir = dir.as_ref();
+    TALK_OFF_FILES
+        .iter()
+        .map(|&name| Tape::read_wav(name, dir.join(name), ""))
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+// Report
+// ---------------------------------------------------------------------------
+
+/// Outcome of one tape.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TapeResult {
+    /// The tape's name.
+    pub name: String,
+    /// Samples played.
+    pub samples: usize,
+    /// Digits dialled on the tape.
+    pub expected: String,
+    /// Digits the detector reported.
+    pub detected: String,
+    /// Reported digits that were not dialled.
+    pub false_detects: usize,
+    /// Dialled digits that were not reported.
+    pub misses: usize,
+}
+
+impl TapeResult {
+    /// Line `detected` up against `expected`, matching as many digits in
+    /// order as possible.
+    fn compare(tape: &Tape, detected: String) -> Self {
+        let expected: Vec<char> = tape.expected.chars().collect();
+        let found: Vec<char> = detected.chars().collect();
+        let matched = longest_common_subsequence(&expected, &found);
+        Self {
+            name: tape.name.clone(),
+            samples: tape.samples.len(),
+            expected: tape.expected.clone(),
+            false_detects: found.len() - matched,
+            misses: expected.len() - matched,
+            detected,
+        }
+    }
+
+    /// `true` if exactly the dialled digits were reported.
+    pub fn passed(&self) -> bool {
+        self.false_detects == 0 && self.misses == 0
+    }
+}
+
+impl fmt::Display for TapeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({:.1} s): {} false, {} of {} missed",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.name,
+            self.samples as f64 / SAMPLE_RATE as f64,
+            self.false_detects,
+            self.misses,
+            self.expected.chars().count()
+        )
+    }
+}
+
+fn longest_common_subsequence(a: &[char], b: &[char]) -> usize {
+    let mut row = vec![0usize; b.len() + 1];
+    for &x in a {
+        let mut diagonal = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Results of running a detector over a set of tapes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TalkOffReport {
+    tapes: Vec<TapeResult>,
+}
+
+impl TalkOffReport {
+    /// Every tape run, in order.
+    pub fn tapes(&self) -> &[TapeResult] {
+        &self.tapes
+    }
+
+    /// Digits reported that were not dialled, over all tapes.
+    pub fn false_detects(&self) -> usize {
+        self.tapes.iter().map(|t| t.false_detects).sum()
+    }
+
+    /// Dialled digits that were not reported, over all tapes.
+    pub fn misses(&self) -> usize {
+        self.tapes.iter().map(|t| t.misses).sum()
+    }
+
+    /// Digits dialled, over all tapes.
+    pub fn expected_digits(&self) -> usize {
+        self.tapes.iter().map(|t| t.expected.chars().count()).sum()
+    }
+
+    /// Hours of audio played.
+    pub fn hours(&self) -> f64 {
+        let samples: usize = self.tapes.iter().map(|t| t.samples).sum();
+        samples as f64 / (SAMPLE_RATE * 3600) as f64
+    }
+
+    /// False detections per hour of audio, or 0 if nothing was played.
+    pub fn false_detects_per_hour(&self) -> f64 {
+        let hours = self.hours();
+        if hours > 0.0 {
+            self.false_detects() as f64 / hours
+        } else {
+            0.0
+        }
+    }
+
+    /// Share of dialled digits missed, or 0 if none were dialled.
+    pub fn miss_rate(&self) -> f64 {
+        match self.expected_digits() {
+            0 => 0.0,
+            n => self.misses() as f64 / n as f64,
+        }
+    }
+
+    /// `true` if the detector stayed within both limits.
+    pub fn within(&self, max_false_detects: usize, max_miss_rate: f64) -> bool {
+        self.false_detects() <= max_false_detects && self.miss_rate() <= max_miss_rate
+    }
+}
+
+impl fmt::Display for TalkOffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} false detects ({:.1}/h), {} of {} digits missed ({:.2}%)",
+            self.false_detects(),
+            self.false_detects_per_hour(),
+            self.misses(),
+            self.expected_digits(),
+            100.0 * self.miss_rate()
+        )?;
+        for tape in &self.tapes {
+            writeln!(f, "  {tape}")?;
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Entry point
+// ---------------------------------------------------------------------------
+
+/// Play each tape through a detector from `new_detector` and count false
+/// detections and misses.
+///
+/// Each tape gets a fresh detector, so configure it in `new_detector` to
+/// benchmark a preset. An error from `new_detector` stops the run.
+pub fn run<D: DigitDetector>(
+    tapes: &[Tape],
+    mut new_detector: impl FnMut() -> Result<D>,
+) -> Result<TalkOffReport> {
+    let mut results = Vec::with_capacity(tapes.len());
+    for tape in tapes {
+        let mut detector = new_detector()?;
+        let mut detected = String::new();
+        for chunk in tape.samples.chunks(CHUNK) {
+            detector.rx(chunk);
+            detected.push_str(&detector.take_digits());
+        }
+        results.push(TapeResult::compare(tape, detected));
+    }
+    Ok(TalkOffReport { tapes: results })
+}
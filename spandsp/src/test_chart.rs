@@ -0,0 +1,293 @@
+//! Reproducible test images for T.4/T.6 compression.
+//!
+//! The CCITT test charts are scanned documents and cannot be shipped here,
+//! so [`TestChart`] draws parametric stand-ins that stress the coder in the
+//! same ways: lines of text, halftones, stripes and checkerboards. Rows are
+//! packed bilevel, MSB first with 1 for black, as [`T4T6Encoder`] expects.
+//!
+//! ```no_run
+//! use spandsp::t4::T4Compression;
+//! use spandsp::t4_tx::T4T6Encoder;
+//! use spandsp::test_chart::{TestChart, TestPattern};
+//!
+//! let chart = TestChart::a4(TestPattern::TextLines { scale: 2 });
+//! let (width, length) = (chart.width() as i32, chart.length() as i32);
+//! let mut encoder =
+//!     T4T6Encoder::new(T4Compression::T6, width, length, chart.into_row_reader()).unwrap();
+//! let mut buf = vec![0u8; 1 << 16];
+//! let compressed = encoder.get(&mut buf);
+//! # let _ = compressed;
+//! ```
+//!
+//! [`T4T6Encoder`]: crate::t4_tx::T4T6Encoder
+
+use crate::error::{Result, SpanDspError};
+
+/// Width of an A4 page at the standard 8 pixels/mm.
+pub const A4_WIDTH: u32 = 1728;
+/// Length of an A4 page at standard resolution (3.85 lines/mm).
+pub const A4_LENGTH_STANDARD: u32 = 1143;
+/// Length of an A4 page at fine resolution (7.7 lines/mm).
+pub const A4_LENGTH_FINE: u32 = 2287;
+
+/// Digits 0-9 in a 5x7 font, one byte per row, bit 4 leftmost.
+const DIGITS_5X7: [[u8; 7]; 10] = [
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+];
+
+/// 4x4 Bayer dither thresholds, scaled to 0-255.
+const BAYER_4X4: [[u8; 4]; 4] = [
+    [8, 136, 40, 168],
+    [200, 72, 232, 104],
+    [56, 184, 24, 152],
+    [248, 120, 216, 88],
+];
+
+/// What a [`TestChart`] draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TestPattern {
+    /// All white.
+    White,
+    /// All black.
+    Black,
+    /// Lines of 5x7 digit glyphs grouped into words, magnified by `scale`,
+    /// inside a white margin.
+    TextLines {
+        /// Pixels per glyph dot.
+        scale: u32,
+    },
+    /// Ordered-dither halftone of a flat gray.
+    Halftone {
+        /// Gray level, from 0 (white) to 255 (black).
+        level: u8,
+    },
+    /// Ordered-dither halftone running from white at the left edge to black
+    /// at the right.
+    HalftoneRamp,
+    /// Alternating black and white vertical stripes.
+    VerticalStripes {
+        /// Stripe width in pixels.
+        width: u32,
+    },
+    /// Alternating black and white horizontal bands.
+    HorizontalStripes {
+        /// Band height in rows.
+        height: u32,
+    },
+    /// Black and white squares.
+    Checkerboard {
+        /// Square side in pixels.
+        size: u32,
+    },
+}
+
+impl TestPattern {
+    /// The size parameter, for patterns that have one.
+    fn size(self) -> Option<u32> {
+        match self {
+            TestPattern::TextLines { scale } => Some(scale),
+            TestPattern::VerticalStripes { width } => Some(width),
+            TestPattern::HorizontalStripes { height } => Some(height),
+            TestPattern::Checkerboard { size } => Some(size),
+            _ => None,
+        }
+    }
+}
+
+/// A bilevel test image of a given pattern and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TestChart {
+    pattern: TestPattern,
+    width: u32,
+    length: u32,
+}
+
+impl TestChart {
+    /// Create a chart `width` pixels wide and `length` rows long.
+    ///
+    /// Returns `InvalidInput` for a zero dimension or pattern size.
+    pub fn new(pattern: TestPattern, width: u32, length: u32) -> Result<Self> {
+        if width == 0 || length == 0 {
+            return Err(SpanDspError::InvalidInput(format!(
+                "test chart must not be empty, got {width}x{length}"
+            )));
+        }
+        if pattern.size() == Some(0) {
+            return Err(SpanDspError::InvalidInput(format!(
+                "{pattern:?} needs a non-zero size"
+            )));
+        }
+        Ok(Self {
+            pattern,
+            width,
+            length,
+        })
+    }
+
+    /// An A4 page at standard resolution.
+    ///
+    /// # Panics
+    ///
+    /// If the pattern has a zero size.
+    pub fn a4(pattern: TestPattern) -> Self {
+        Self::new(pattern, A4_WIDTH, A4_LENGTH_STANDARD).expect("zero-sized test pattern")
+    }
+
+    /// The pattern drawn.
+    pub fn pattern(&self) -> TestPattern {
+        self.pattern
+    }
+
+    /// Width in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Length in rows.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Bytes in one packed row.
+    pub fn row_bytes(&self) -> usize {
+        self.width.div_ceil(8) as usize
+    }
+
+    /// Draw row `y` into `row`, packed MSB first with 1 for black. Only the
+    /// first `row.len()` bytes of the row are drawn; pad bits beyond the
+    /// width are white.
+    pub fn fill_row(&self, y: u32, row: &mut [u8]) {
+        for (i, byte) in row.iter_mut().take(self.row_bytes()).enumerate() {
+            *byte = 0;
+            for bit in 0..8 {
+                let x = i as u32 * 8 + bit;
+                if x < self.width && self.is_black(x, y) {
+                    *byte |= 0x80 >> bit;
+                }
+            }
+        }
+    }
+
+    /// Row `y` as a new buffer.
+    pub fn row(&self, y: u32) -> Vec<u8> {
+        let mut row = vec![0u8; self.row_bytes()];
+        self.fill_row(y, &mut row);
+        row
+    }
+
+    /// Iterate over every row, top to bottom.
+    pub fn rows(&self) -> Rows<'_> {
+        Rows {
+            chart: self,
+            next: 0,
+        }
+    }
+
+    /// A row reader for [`T4T6Encoder::new`](crate::t4_tx::T4T6Encoder::new)
+    /// that yields each row once and then signals the end of the image.
+    pub fn into_row_reader(self) -> impl FnMut(&mut [u8]) -> usize + 'static {
+        let mut y = 0;
+        move |buf: &mut [u8]| {
+            if y >= self.length {
+                return 0;
+            }
+            let n = buf.len().min(self.row_bytes());
+            self.fill_row(y, &mut buf[..n]);
+            y += 1;
+            n
+        }
+    }
+
+    fn is_black(&self, x: u32, y: u32) -> bool {
+        match self.pattern {
+            TestPattern::White => false,
+            TestPattern::Black => true,
+            TestPattern::TextLines { scale } => self.text_pixel(scale, x, y),
+            TestPattern::Halftone { level } => dither(level, x, y),
+            TestPattern::HalftoneRamp => {
+                let level = (x as u64 * 256 / self.width as u64).min(255) as u8;
+                dither(level, x, y)
+            }
+            TestPattern::VerticalStripes { width } => (x / width) % 2 == 0,
+            TestPattern::HorizontalStripes { height } => (y / height) % 2 == 0,
+            TestPattern::Checkerboard { size } => (x / size + y / size) % 2 == 0,
+        }
+    }
+
+    /// Glyphs sit in 6x10 dot cells (5x7 plus spacing and leading), with
+    /// roughly one cell in six left blank as a word gap. Only glyphs that fit
+    /// wholly inside the margins are drawn. Positions are worked out in u64,
+    /// so any scale is safe; one too large for the page leaves it blank.
+    fn text_pixel(&self, scale: u32, x: u32, y: u32) -> bool {
+        let (scale, x, y) = (u64::from(scale), u64::from(x), u64::from(y));
+        let margin = 8 * scale;
+        if x < margin || y < margin {
+            return false;
+        }
+        let (dx, dy) = ((x - margin) / scale, (y - margin) / scale);
+        let (line, gy) = (dy / 10, dy % 10);
+        let (cell, gx) = (dx / 6, dx % 6);
+        if gx >= 5 || gy >= 7 {
+            return false;
+        }
+        let right = margin + (cell * 6 + 5) * scale;
+        let bottom = margin + (line * 10 + 7) * scale;
+        if right + margin > u64::from(self.width) || bottom + margin > u64::from(self.length) {
+            return false;
+        }
+        let h = mix(line as u32, cell as u32);
+        if h % 6 == 0 {
+            return false;
+        }
+        let glyph = DIGITS_5X7[(h / 6 % 10) as usize][gy as usize];
+        glyph & (0x10 >> gx) != 0
+    }
+}
+
+fn dither(level: u8, x: u32, y: u32) -> bool {
+    level > BAYER_4X4[(y % 4) as usize][(x % 4) as usize]
+}
+
+/// A cheap, well-mixed hash of a glyph position.
+fn mix(a: u32, b: u32) -> u32 {
+    let mut h = a.wrapping_mul(0x9E37_79B1) ^ b.wrapping_mul(0x85EB_CA77);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0xC2B2_AE3D);
+    h ^ (h >> 13)
+}
+
+/// Iterator over the rows of a [`TestChart`].
+#[derive(Debug, Clone)]
+pub struct Rows<'a> {
+    chart: &'a TestChart,
+    next: u32,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.next >= self.chart.length {
+            return None;
+        }
+        let row = self.chart.row(self.next);
+        self.next += 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.chart.length - self.next) as usize;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
@@ -0,0 +1,203 @@
+//! TIFF-FX (RFC 3949) writing and validation.
+//!
+//! A fax TIFF that breaks a profile rule is usually only found out once a
+//! call is up, when the far end rejects the page format and the call fails
+//! mid-document. [`validate`] checks every page of a file against a
+//! [`TiffProfile`] before dialling and lists everything wrong with it, tag
+//! by tag, rather than stopping at the first problem. [`to_tiff_fx`] and
+//! [`rewrite_file`] turn what `T4Rx` wrote into a compliant file.
+//!
+//! ```no_run
+//! use spandsp::t37::TiffProfile;
+//! use spandsp::tiff_fx;
+//!
+//! let report = tiff_fx::validate_file("outgoing.tif", TiffProfile::F).unwrap();
+//! for violation in report.violations() {
+//!     eprintln!("{violation}");
+//! }
+//! report.into_result().unwrap();
+//! ```
+
+use std::fmt;
+use std::path::Path;
+
+use crate::error::{Result, SpanDspError};
+use crate::fax_tiff::{self, Tags};
+use crate::t37::{self, FaxPage, TiffProfile};
+
+/// T4Options bit: uncompressed mode, which TIFF-FX does not allow.
+const T4_OPTION_UNCOMPRESSED: u32 = 0x02;
+
+/// One broken profile rule.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Violation {
+    /// Index of the offending page, from 0.
+    pub page: usize,
+    /// What is wrong.
+    pub problem: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page {}: {}", self.page + 1, self.problem)
+    }
+}
+
+/// The result of checking a file against a profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    profile: TiffProfile,
+    pages: usize,
+    violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    /// The profile checked against.
+    pub fn profile(&self) -> TiffProfile {
+        self.profile
+    }
+
+    /// Pages in the file.
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Every rule the file breaks, in page order.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Whether the file meets the profile.
+    pub fn is_compliant(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// `Ok` for a compliant file, otherwise `InvalidInput` naming the first
+    /// violation and how many more there are.
+    pub fn into_result(self) -> Result<()> {
+        match self.violations.split_first() {
+            None => Ok(()),
+            Some((first, [])) => Err(SpanDspError::InvalidInput(format!(
+                "not TIFF Profile {}: {first}",
+                self.profile
+            ))),
+            Some((first, rest)) => Err(SpanDspError::InvalidInput(format!(
+                "not TIFF Profile {}: {first} (and {} more)",
+                self.profile,
+                rest.len()
+            ))),
+        }
+    }
+}
+
+/// Check every page of a TIFF against `profile`.
+///
+/// Returns `Err` only when the file is not a readable TIFF at all.
+pub fn validate(file: &[u8], profile: TiffProfile) -> Result<ValidationReport> {
+    let ifds = fax_tiff::read_ifds(file)?;
+    let mut violations = Vec::new();
+    for (index, tags) in ifds.iter().enumerate() {
+        let mut report = |problem: String| {
+            violations.push(Violation {
+                page: index,
+                problem,
+            })
+        };
+        for problem in tag_problems(tags, index) {
+            report(problem);
+        }
+        match fax_tiff::page(file, tags) {
+            Ok(image) => {
+                if let Some(problem) = profile.problem(&FaxPage { image }) {
+                    report(problem);
+                }
+            }
+            Err(SpanDspError::InvalidInput(problem)) => report(problem),
+            Err(other) => report(other.to_string()),
+        }
+    }
+    Ok(ValidationReport {
+        profile,
+        pages: ifds.len(),
+        violations,
+    })
+}
+
+/// Read a file and [`validate`] it.
+pub fn validate_file(path: impl AsRef<Path>, profile: TiffProfile) -> Result<ValidationReport> {
+    validate(&read(path.as_ref())?, profile)
+}
+
+/// Rewrite a fax TIFF in `profile`, recoding pages where their coding is
+/// all that stands in the way. See [`TiffProfile::conform`].
+pub fn to_tiff_fx(file: &[u8], profile: TiffProfile) -> Result<Vec<u8>> {
+    let pages = FaxPage::from_tiff(file)?
+        .iter()
+        .map(|page| profile.conform(page))
+        .collect::<Result<Vec<_>>>()?;
+    t37::write_tiff(&pages, profile)
+}
+
+/// Rewrite a file in place in `profile`, e.g. once `T4Rx` has closed it.
+pub fn rewrite_file(path: impl AsRef<Path>, profile: TiffProfile) -> Result<()> {
+    let path = path.as_ref();
+    let tiff = to_tiff_fx(&read(path)?, profile)?;
+    std::fs::write(path, tiff).map_err(|err| {
+        SpanDspError::InvalidInput(format!("cannot write {}: {err}", path.display()))
+    })
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|err| SpanDspError::InvalidInput(format!("cannot read {}: {err}", path.display())))
+}
+
+/// The tag-level rules. Coding, photometric, image size and resolution are
+/// left to [`TiffProfile::check`].
+fn tag_problems(tags: &Tags, index: usize) -> Vec<String> {
+    let mut problems = Vec::new();
+    let value = |tag| fax_tiff::first(tags, tag);
+    let mut require =
+        |tag, name: &str, allowed: &[u32], default: Option<u32>| match value(tag).or(default) {
+            None => problems.push(format!("{name} is missing")),
+            Some(v) if !allowed.contains(&v) => {
+                problems.push(format!("{name} is {v}, must be one of {allowed:?}"));
+            }
+            Some(_) => {}
+        };
+    require(fax_tiff::TAG_NEW_SUBFILE_TYPE, "NewSubfileType", &[2], None);
+    require(
+        fax_tiff::TAG_BITS_PER_SAMPLE,
+        "BitsPerSample",
+        &[1],
+        Some(1),
+    );
+    require(
+        fax_tiff::TAG_SAMPLES_PER_PIXEL,
+        "SamplesPerPixel",
+        &[1],
+        Some(1),
+    );
+    require(fax_tiff::TAG_FILL_ORDER, "FillOrder", &[1, 2], Some(1));
+    require(
+        fax_tiff::TAG_RESOLUTION_UNIT,
+        "ResolutionUnit",
+        &[2, 3],
+        Some(2),
+    );
+    if value(fax_tiff::TAG_PHOTOMETRIC).is_none() {
+        problems.push("PhotometricInterpretation is missing".into());
+    }
+    if value(fax_tiff::TAG_X_RESOLUTION).is_none() || value(fax_tiff::TAG_Y_RESOLUTION).is_none() {
+        problems.push("XResolution and YResolution are required".into());
+    }
+    if value(fax_tiff::TAG_T4_OPTIONS).unwrap_or(0) & T4_OPTION_UNCOMPRESSED != 0 {
+        problems.push("T4Options selects uncompressed mode".into());
+    }
+    match tags.get(&fax_tiff::TAG_PAGE_NUMBER).map(Vec::as_slice) {
+        Some(&[number, ..]) if number as usize == index => {}
+        Some(&[number, ..]) => problems.push(format!("PageNumber is {number}, expected {index}")),
+        _ => problems.push("PageNumber is missing".into()),
+    }
+    problems
+}
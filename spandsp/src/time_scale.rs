@@ -0,0 +1,181 @@
+//! Safe wrapper around spandsp's time-scale modification.
+//!
+//! [`TimeScale`] plays audio faster or slower without changing its pitch,
+//! e.g. voicemail at 1.5x. It works pitch period by pitch period, so the
+//! output of a call is not a fixed multiple of the input; whatever does not
+//! fit the caller's buffer is held and handed out by the next call.
+//!
+//! ```no_run
+//! use spandsp::time_scale::TimeScale;
+//!
+//! let mut fast = TimeScale::new(8000, 1.5).unwrap();
+//! let message = vec![0i16; 8000];
+//! let mut out = [0i16; 160];
+//! for frame in message.chunks(160) {
+//!     let n = fast.process(&mut out, frame);
+//!     // play out[..n]
+//! }
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// Most samples `time_scale_flush` can return: spandsp's internal buffer,
+/// two pitch periods of 60 Hz at 48 kHz.
+const FLUSH_MAX_SAMPLES: usize = 1600;
+
+/// Highest sample rate spandsp's buffers are sized for.
+const MAX_SAMPLE_RATE: u32 = 48000;
+
+/// RAII wrapper around `time_scale_state_t`.
+///
+/// Created via `TimeScale::new()`. Freed on drop via `time_scale_free`.
+pub struct TimeScale {
+    ptr: NonNull<spandsp_sys::time_scale_state_t>,
+    sample_rate: u32,
+    rate: f32,
+    scratch: Vec<i16>,
+    pending: Vec<i16>,
+}
+
+/// Check a playout rate is one spandsp can follow.
+fn check_rate(rate: f32) -> Result<()> {
+    if !(rate.is_finite() && rate > 0.0) {
+        return Err(SpanDspError::InvalidInput(format!(
+            "playout rate {rate} is not a positive speed"
+        )));
+    }
+    Ok(())
+}
+
+impl TimeScale {
+    /// Create a time scaler for audio at `sample_rate`, playing out at
+    /// `rate` times normal speed (above 1 is faster, below 1 slower).
+    pub fn new(sample_rate: u32, rate: f32) -> Result<Self> {
+        check_rate(rate)?;
+        if sample_rate == 0 || sample_rate > MAX_SAMPLE_RATE {
+            return Err(SpanDspError::InvalidInput(format!(
+                "time scaling at {sample_rate} Hz; spandsp handles up to {MAX_SAMPLE_RATE} Hz"
+            )));
+        }
+        let ptr = unsafe {
+            spandsp_sys::time_scale_init(std::ptr::null_mut(), sample_rate as c_int, rate)
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            sample_rate,
+            rate,
+            scratch: Vec::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Returns the sample rate this scaler was initialized with.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the playout rate.
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Change the playout rate. Takes effect from the next call to
+    /// [`process`](Self::process).
+    pub fn set_rate(&mut self, rate: f32) -> Result<()> {
+        check_rate(rate)?;
+        let rc = unsafe { spandsp_sys::time_scale_rate(self.ptr.as_ptr(), rate) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        self.rate = rate;
+        Ok(())
+    }
+
+    /// Most samples processing `input_len` samples can produce at the
+    /// current rate.
+    pub fn max_output_len(&self, input_len: usize) -> usize {
+        let len = input_len.min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::time_scale_max_output_len(self.ptr.as_ptr(), len).max(0) as usize }
+    }
+
+    /// Samples produced but not yet handed out, because an earlier call's
+    /// buffer was full.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Time-scale `amp` into `out`.
+    ///
+    /// All of `amp` is taken. Returns the number of samples written, which
+    /// varies from call to call; samples that do not fit `out` are written
+    /// by later calls.
+    pub fn process(&mut self, out: &mut [i16], amp: &[i16]) -> usize {
+        for chunk in amp.chunks(c_int::MAX as usize / 2) {
+            self.scratch.clear();
+            self.scratch.resize(self.max_output_len(chunk.len()), 0);
+            let n = unsafe {
+                spandsp_sys::time_scale(
+                    self.ptr.as_ptr(),
+                    self.scratch.as_mut_ptr(),
+                    chunk.as_ptr().cast_mut(),
+                    chunk.len() as c_int,
+                )
+            };
+            let n = (n.max(0) as usize).min(self.scratch.len());
+            self.pending.extend_from_slice(&self.scratch[..n]);
+        }
+        self.drain(out)
+    }
+
+    /// Take the audio still held inside spandsp at the end of a stream.
+    ///
+    /// Returns the number of samples written; call again while it fills
+    /// `out`.
+    pub fn flush(&mut self, out: &mut [i16]) -> usize {
+        self.scratch.clear();
+        self.scratch.resize(FLUSH_MAX_SAMPLES, 0);
+        let n =
+            unsafe { spandsp_sys::time_scale_flush(self.ptr.as_ptr(), self.scratch.as_mut_ptr()) };
+        let n = (n.max(0) as usize).min(FLUSH_MAX_SAMPLES);
+        self.pending.extend_from_slice(&self.scratch[..n]);
+        self.drain(out)
+    }
+
+    /// Move as much pending audio into `out` as fits.
+    fn drain(&mut self, out: &mut [i16]) -> usize {
+        let n = out.len().min(self.pending.len());
+        out[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        n
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::time_scale_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for TimeScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeScale")
+            .field("sample_rate", &self.sample_rate)
+            .field("rate", &self.rate)
+            .field("pending", &self.pending.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for TimeScale {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::time_scale_free(self.ptr.as_ptr());
+        }
+    }
+}
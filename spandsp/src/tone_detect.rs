@@ -5,6 +5,7 @@
 
 extern crate spandsp_sys;
 
+use std::fmt;
 use std::os::raw::c_int;
 use std::ptr::NonNull;
 
@@ -14,8 +15,27 @@ use crate::error::{Result, SpanDspError};
 /// block size.
 ///
 /// This is a stack-allocated value type (not heap-allocated by spandsp).
+/// It is never changed after construction, so one descriptor, or a
+/// shared table of them, can initialize any number of detectors on any
+/// number of threads:
+///
+/// ```no_run
+/// use std::sync::LazyLock;
+///
+/// use spandsp::tone_detect::{GoertzelDescriptor, GoertzelDetector};
+///
+/// static BANK: LazyLock<[GoertzelDescriptor; 2]> = LazyLock::new(|| {
+///     [350.0, 440.0].map(|freq| GoertzelDescriptor::new(freq, 205))
+/// });
+///
+/// let channels: Vec<Vec<GoertzelDetector>> = (0..100)
+///     .map(|_| BANK.iter().map(|d| GoertzelDetector::new(d).unwrap()).collect())
+///     .collect();
+/// ```
+#[derive(Clone, Copy)]
 pub struct GoertzelDescriptor {
     inner: spandsp_sys::goertzel_descriptor_t,
+    freq: f32,
 }
 
 impl GoertzelDescriptor {
@@ -28,7 +48,22 @@ impl GoertzelDescriptor {
         unsafe {
             spandsp_sys::make_goertzel_descriptor(&mut desc, freq, samples as c_int);
         }
-        Self { inner: desc }
+        Self { inner: desc, freq }
+    }
+
+    /// Returns the target frequency in Hz.
+    pub fn freq(&self) -> f32 {
+        self.freq
+    }
+
+    /// Returns the number of samples per Goertzel block.
+    pub fn samples(&self) -> usize {
+        self.inner.samples as usize
+    }
+
+    /// Return a pointer to the inner descriptor (for passing to FFI).
+    pub fn as_ptr(&self) -> *const spandsp_sys::goertzel_descriptor_t {
+        &self.inner
     }
 
     /// Return a mutable pointer to the inner descriptor (for passing to FFI).
@@ -37,20 +72,41 @@ impl GoertzelDescriptor {
     }
 }
 
+impl fmt::Debug for GoertzelDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GoertzelDescriptor")
+            .field("freq", &self.freq)
+            .field("samples", &self.inner.samples)
+            .finish()
+    }
+}
+
 /// RAII wrapper around `goertzel_state_t`.
 ///
 /// Created via `GoertzelDetector::new()`, which calls
 /// `goertzel_init(NULL, ...)`. Freed on drop via `goertzel_free`.
 pub struct GoertzelDetector {
     ptr: NonNull<spandsp_sys::goertzel_state_t>,
+    freq: f32,
+    samples: usize,
 }
 
 impl GoertzelDetector {
     /// Create a new Goertzel detector from a descriptor.
-    pub fn new(desc: &mut GoertzelDescriptor) -> Result<Self> {
-        let ptr = unsafe { spandsp_sys::goertzel_init(std::ptr::null_mut(), desc.as_mut_ptr()) };
+    ///
+    /// The descriptor is only read, and can go on to initialize other
+    /// detectors.
+    pub fn new(desc: &GoertzelDescriptor) -> Result<Self> {
+        // goertzel_init() takes a non-const pointer but only copies the
+        // coefficients out of the descriptor.
+        let ptr =
+            unsafe { spandsp_sys::goertzel_init(std::ptr::null_mut(), desc.as_ptr().cast_mut()) };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            freq: desc.freq(),
+            samples: desc.samples(),
+        })
     }
 
     /// Reset the detector state so it can be reused for a new block.
@@ -77,12 +133,35 @@ impl GoertzelDetector {
         unsafe { spandsp_sys::goertzel_result(self.ptr.as_ptr()) }
     }
 
+    /// Mean square of the tone over the block just fed, comparable with
+    /// the mean square of the block's samples, then reset for the next
+    /// block.
+    ///
+    /// [`result`](Self::result) is the unscaled DFT power, `(n * a / 2)^2`
+    /// for a sine of amplitude `a` over `n` samples; this scales it to
+    /// `a^2 / 2`. The block must be the descriptor's full length.
+    pub(crate) fn tone_power(&mut self) -> f32 {
+        let n = self.samples as f32;
+        let power = 2.0 * self.result() / (n * n);
+        self.reset();
+        power
+    }
+
     /// Return the raw pointer.
     pub fn as_ptr(&self) -> *mut spandsp_sys::goertzel_state_t {
         self.ptr.as_ptr()
     }
 }
 
+impl fmt::Debug for GoertzelDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GoertzelDetector")
+            .field("freq", &self.freq)
+            .field("samples", &self.samples)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for GoertzelDetector {
     fn drop(&mut self) {
         unsafe {
@@ -10,12 +10,14 @@ use std::os::raw::c_int;
 use std::ptr::NonNull;
 
 use crate::error::{Result, SpanDspError};
+use crate::gain_ramp::GainRamp;
 
 /// A frequency + level pair for tone generation.
 ///
 /// - `frequency`: tone frequency in Hz. Use 0 for none, negative for AM modulation.
 /// - `level`: signal level in dBm0 (or modulation depth % for AM).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ToneFreq {
     /// Tone frequency in Hz. Use 0 for none, negative for AM modulation.
     pub frequency: i32,
@@ -55,6 +57,7 @@ impl fmt::Display for ToneFreq {
 /// A typical pattern is `on1` / `off1` for a simple repeating cadence,
 /// with `on2` / `off2` for more complex patterns (e.g. distinctive ring).
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ToneCadence {
     /// First on-period duration in milliseconds.
     pub on1: i32,
@@ -119,6 +122,10 @@ impl fmt::Display for ToneCadence {
 /// `tone_gen_descriptor_free` on drop.
 pub struct ToneGenDescriptor {
     ptr: NonNull<spandsp_sys::tone_gen_descriptor_t>,
+    tone1: ToneFreq,
+    tone2: ToneFreq,
+    cadence: ToneCadence,
+    repeat: bool,
 }
 
 impl ToneGenDescriptor {
@@ -151,7 +158,33 @@ impl ToneGenDescriptor {
             )
         };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            tone1,
+            tone2,
+            cadence,
+            repeat,
+        })
+    }
+
+    /// Returns the first tone component.
+    pub fn tone1(&self) -> ToneFreq {
+        self.tone1
+    }
+
+    /// Returns the second tone component.
+    pub fn tone2(&self) -> ToneFreq {
+        self.tone2
+    }
+
+    /// Returns the on/off timing pattern.
+    pub fn cadence(&self) -> ToneCadence {
+        self.cadence
+    }
+
+    /// Returns `true` if the cadence repeats.
+    pub fn repeat(&self) -> bool {
+        self.repeat
     }
 
     /// Return the raw pointer.
@@ -160,6 +193,17 @@ impl ToneGenDescriptor {
     }
 }
 
+impl fmt::Debug for ToneGenDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToneGenDescriptor")
+            .field("tone1", &self.tone1)
+            .field("tone2", &self.tone2)
+            .field("cadence", &self.cadence)
+            .field("repeat", &self.repeat)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for ToneGenDescriptor {
     fn drop(&mut self) {
         unsafe {
@@ -170,9 +214,24 @@ impl Drop for ToneGenDescriptor {
 
 /// Cadenced multi-tone generator state.
 ///
-/// Created from a `ToneGenDescriptor`. Freed via `tone_gen_free` on drop.
+/// Created from a `ToneGenDescriptor`. The descriptor's settings are copied
+/// into the generator, so the descriptor need not outlive it. Freed via
+/// `tone_gen_free` on drop.
+///
+/// The output level can be changed while the tone plays with
+/// [`set_gain`](Self::set_gain), and the tone itself with
+/// [`retune`](Self::retune); both ramp rather than step, so neither clicks.
 pub struct ToneGenerator {
     ptr: NonNull<spandsp_sys::tone_gen_state_t>,
+    gain: GainRamp,
+    pending: Option<PendingRetune>,
+}
+
+/// A tone switch waiting for the current tone to fade out.
+struct PendingRetune {
+    descriptor: ToneGenDescriptor,
+    ramp_ms: u32,
+    gain_db: f32,
 }
 
 impl ToneGenerator {
@@ -180,7 +239,71 @@ impl ToneGenerator {
     pub fn new(descriptor: &ToneGenDescriptor) -> Result<Self> {
         let ptr = unsafe { spandsp_sys::tone_gen_init(std::ptr::null_mut(), descriptor.as_ptr()) };
         let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
-        Ok(Self { ptr })
+        Ok(Self {
+            ptr,
+            gain: GainRamp::unity(),
+            pending: None,
+        })
+    }
+
+    /// Switch to a different tone, e.g. from dial tone to ringback, reusing
+    /// this generator's state. The cadence restarts from the beginning.
+    ///
+    /// A [`retune`](Self::retune) still in progress is abandoned.
+    pub fn reinit(&mut self, descriptor: &ToneGenDescriptor) -> Result<()> {
+        if let Some(pending) = self.pending.take() {
+            self.gain.set_target_db(pending.gain_db, 0);
+        }
+        self.init(descriptor)
+    }
+
+    fn init(&mut self, descriptor: &ToneGenDescriptor) -> Result<()> {
+        let ptr = unsafe { spandsp_sys::tone_gen_init(self.ptr.as_ptr(), descriptor.as_ptr()) };
+        if ptr.is_null() {
+            return Err(SpanDspError::InitFailed);
+        }
+        Ok(())
+    }
+
+    /// Change the output level by `gain_db` relative to the descriptor's
+    /// levels, ramping linearly over `ramp_ms` milliseconds.
+    ///
+    /// A ramp to `f32::NEG_INFINITY` fades the tone out. Boosts that push
+    /// the signal past full scale are clipped.
+    pub fn set_gain(&mut self, gain_db: f32, ramp_ms: u32) {
+        match self.pending.as_mut() {
+            Some(pending) => pending.gain_db = gain_db,
+            None => self.gain.set_target_db(gain_db, ramp_ms),
+        }
+    }
+
+    /// The output gain in dB, or the gain being ramped to.
+    pub fn gain(&self) -> f32 {
+        match &self.pending {
+            Some(pending) => pending.gain_db,
+            None => self.gain.target_db(),
+        }
+    }
+
+    /// Switch to a different tone without a click: the current tone fades
+    /// out over `ramp_ms`, then the new one starts from the beginning of its
+    /// cadence and fades in over the same time to the current gain.
+    ///
+    /// With a `ramp_ms` of 0 this is the same as [`reinit`](Self::reinit).
+    pub fn retune(&mut self, descriptor: ToneGenDescriptor, ramp_ms: u32) -> Result<()> {
+        let gain_db = self.gain();
+        if ramp_ms == 0 {
+            self.pending = None;
+            self.gain.set_target_db(gain_db, 0);
+            return self.init(&descriptor);
+        }
+        self.gain.set_target_db(f32::NEG_INFINITY, ramp_ms);
+        self.pending = Some(PendingRetune {
+            descriptor,
+            ramp_ms,
+            gain_db,
+        });
+        Ok(())
     }
 
     /// Generate tone samples.
@@ -188,6 +311,29 @@ impl ToneGenerator {
     /// Returns the number of samples actually generated. A return value of 0
     /// indicates the tone cadence has completed.
     pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let mut len = 0;
+        if self.pending.is_some() {
+            let fade = self.gain.remaining().min(amp.len());
+            len = self.generate_raw(&mut amp[..fade]);
+            self.gain.apply(&mut amp[..len]);
+            if len == amp.len() {
+                return len;
+            }
+            // The old tone has faded out (or ended early), so switch now.
+            if let Some(pending) = self.pending.take() {
+                let _ = self.init(&pending.descriptor);
+                self.gain.set_target_db(pending.gain_db, pending.ramp_ms);
+            }
+        }
+        let n = self.generate_raw(&mut amp[len..]);
+        self.gain.apply(&mut amp[len..len + n]);
+        len + n
+    }
+
+    fn generate_raw(&mut self, amp: &mut [i16]) -> usize {
+        if amp.is_empty() {
+            return 0;
+        }
         let max_samples = amp.len().min(c_int::MAX as usize) as c_int;
         unsafe { spandsp_sys::tone_gen(self.ptr.as_ptr(), amp.as_mut_ptr(), max_samples) as usize }
     }
@@ -198,6 +344,15 @@ impl ToneGenerator {
     }
 }
 
+impl fmt::Debug for ToneGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToneGenerator")
+            .field("gain", &self.gain())
+            .field("retuning", &self.pending.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
 impl Drop for ToneGenerator {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,159 @@
+//! Overlaying generated tones on existing audio.
+//!
+//! Beeps, call-waiting tones and DTMF often have to be added to audio that
+//! is already flowing, e.g. a recording warning tone over a live call.
+//! [`ToneMixer`] runs a [`ToneGenerator`] or [`DtmfTx`] alongside the call
+//! audio and adds its output in, saturating rather than wrapping where the
+//! sum leaves the 16-bit range.
+//!
+//! ```no_run
+//! use spandsp::tone_generate::ToneCadence;
+//! use spandsp::tone_mixer::ToneMixer;
+//!
+//! // A 1400 Hz beep every 15 s at -20 dBm0.
+//! let mut beep = ToneMixer::tone(1400, 0, -20.0, ToneCadence::simple(200, 14800), true).unwrap();
+//! let mut frame = [0i16; 160];
+//! beep.mix(&mut frame);
+//! ```
+
+use std::fmt;
+
+use crate::dtmf::DtmfTx;
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::DBM0_MAX_SINE_POWER;
+use crate::super_tone::SuperToneTx;
+use crate::tone_generate::{ToneCadence, ToneFreq, ToneGenDescriptor, ToneGenerator};
+
+/// Add `overlay` into `amp` sample by sample, saturating at full scale.
+///
+/// Only the overlapping part of the two buffers is mixed.
+pub fn mix_saturating(amp: &mut [i16], overlay: &[i16]) {
+    for (sample, &add) in amp.iter_mut().zip(overlay) {
+        *sample = sample.saturating_add(add);
+    }
+}
+
+/// A generator a [`ToneMixer`] can overlay.
+pub trait ToneSource {
+    /// Generate into `amp`, returning the number of samples generated.
+    /// Fewer than `amp.len()` means the source has run out for now.
+    fn generate(&mut self, amp: &mut [i16]) -> usize;
+}
+
+impl ToneSource for ToneGenerator {
+    fn generate(&mut self, amp: &mut [i16]) -> usize {
+        ToneGenerator::generate(self, amp)
+    }
+}
+
+impl ToneSource for DtmfTx {
+    fn generate(&mut self, amp: &mut [i16]) -> usize {
+        DtmfTx::generate(self, amp)
+    }
+}
+
+impl ToneSource for SuperToneTx {
+    fn generate(&mut self, amp: &mut [i16]) -> usize {
+        SuperToneTx::generate(self, amp)
+    }
+}
+
+/// Split a level into the whole dBm0 spandsp takes and the remaining gain.
+fn split_level(level_dbm0: f32) -> Result<(i32, f32)> {
+    if !level_dbm0.is_finite() || level_dbm0 > DBM0_MAX_SINE_POWER {
+        return Err(SpanDspError::InvalidInput(format!(
+            "tone level {level_dbm0} dBm0 is not a level at or below {DBM0_MAX_SINE_POWER} dBm0"
+        )));
+    }
+    let whole = level_dbm0.floor();
+    Ok((whole as i32, level_dbm0 - whole))
+}
+
+/// Overlays a tone source on audio frames.
+pub struct ToneMixer<S> {
+    source: S,
+    scratch: Vec<i16>,
+}
+
+impl ToneMixer<ToneGenerator> {
+    /// A tone of `tone1_hz` and, unless 0, `tone2_hz`, each at
+    /// `level_dbm0`, following `cadence`.
+    pub fn tone(
+        tone1_hz: i32,
+        tone2_hz: i32,
+        level_dbm0: f32,
+        cadence: ToneCadence,
+        repeat: bool,
+    ) -> Result<Self> {
+        let (level, gain_db) = split_level(level_dbm0)?;
+        let tone2 = match tone2_hz {
+            0 => ToneFreq::NONE,
+            hz => ToneFreq::new(hz, level),
+        };
+        let descriptor =
+            ToneGenDescriptor::new(ToneFreq::new(tone1_hz, level), tone2, cadence, repeat)?;
+        let mut generator = ToneGenerator::new(&descriptor)?;
+        generator.set_gain(gain_db, 0);
+        Ok(Self::new(generator))
+    }
+}
+
+impl ToneMixer<DtmfTx> {
+    /// DTMF `digits` with both tones at `level_dbm0`. Queue more with
+    /// [`put`](Self::put).
+    pub fn dtmf(digits: &str, level_dbm0: f32) -> Result<Self> {
+        let (level, gain_db) = split_level(level_dbm0)?;
+        let mut tx = DtmfTx::new()?;
+        tx.set_level(level, 0);
+        tx.set_gain(gain_db, 0);
+        let mut mixer = Self::new(tx);
+        mixer.put(digits)?;
+        Ok(mixer)
+    }
+
+    /// Queue more digits. See [`DtmfTx::put`].
+    pub fn put(&mut self, digits: &str) -> Result<usize> {
+        self.source.put(digits)
+    }
+}
+
+impl<S: ToneSource> ToneMixer<S> {
+    /// Overlay `source` at whatever level it generates.
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Add the next `amp.len()` samples of the source into `amp`.
+    ///
+    /// Returns the number of samples overlaid. Fewer than `amp.len()` means
+    /// the source has run out, e.g. the tone's cadence has finished or the
+    /// DTMF digits have all been sent; the rest of `amp` is left alone.
+    pub fn mix(&mut self, amp: &mut [i16]) -> usize {
+        self.scratch.clear();
+        self.scratch.resize(amp.len(), 0);
+        let n = self.source.generate(&mut self.scratch);
+        mix_saturating(&mut amp[..n], &self.scratch[..n]);
+        n
+    }
+
+    /// The source, e.g. to change its gain mid-tone.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Unwrap the source.
+    pub fn into_source(self) -> S {
+        self.source
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for ToneMixer<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToneMixer")
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,591 @@
+//! Tone plans described as data.
+//!
+//! Dial, ringback, busy, congestion and call waiting tones differ from
+//! country to country, and carriers tweak them. A [`TonePlan`] describes a
+//! set of tones as plain data (with the `serde` feature it loads from any
+//! serde format), and compiles each one into a generator and all of them
+//! into one detector, so a new country is a config file rather than code.
+//!
+//! ```no_run
+//! use spandsp::tone_generate::{ToneCadence, ToneFreq};
+//! use spandsp::tone_plan::{TonePattern, TonePlan, ToneSpec};
+//!
+//! let plan = TonePlan {
+//!     name: "us".into(),
+//!     tones: vec![
+//!         ToneSpec::new(
+//!             "dial",
+//!             TonePattern::cadenced(
+//!                 ToneFreq::new(350, -13),
+//!                 ToneFreq::new(440, -13),
+//!                 ToneCadence::continuous(1000),
+//!             ),
+//!         ),
+//!         ToneSpec::new(
+//!             "busy",
+//!             TonePattern::cadenced(
+//!                 ToneFreq::new(480, -24),
+//!                 ToneFreq::new(620, -24),
+//!                 ToneCadence::simple(500, 500),
+//!             ),
+//!         ),
+//!     ],
+//! };
+//! let mut busy = plan.generator("busy").unwrap();
+//! let mut detector = plan.detector().unwrap();
+//! let mut amp = [0i16; 160];
+//! busy.generate(&mut amp);
+//! detector.rx(&amp);
+//! if let Some(tone) = detector.current() {
+//!     println!("hearing {}", plan.tones[tone].name);
+//! }
+//! ```
+//!
+//! Plans can also be built in code, or taken from the bundled ones for
+//! common countries ([`TonePlan::COUNTRIES`]):
+//!
+//! ```no_run
+//! use spandsp::tone_generate::ToneCadence;
+//! use spandsp::tone_plan::TonePlan;
+//!
+//! let waiting = TonePlan::call_waiting()
+//!     .freq(425)
+//!     .cadence(ToneCadence::new(200, 200, 200, 5000));
+//! let plan = TonePlan::country("DE").unwrap().with_tone(waiting);
+//! let detector = plan.detector_with_callback(|name, event| println!("{name}: {event:?}"));
+//! ```
+
+use crate::error::{Result, SpanDspError};
+use crate::super_tone::{
+    SuperToneEvent, SuperToneRx, SuperToneRxDescriptor, SuperToneStep, SuperToneTx, ToneElement,
+};
+use crate::tone_generate::{ToneCadence, ToneFreq, ToneGenDescriptor};
+
+/// Share of a segment's duration a detected segment may differ by, when
+/// the elements are derived from the pattern.
+const DETECT_TOLERANCE: f32 = 0.1;
+
+/// Shortest time a steady tone must last to be detected, in milliseconds,
+/// when the elements are derived from the pattern.
+const STEADY_MIN_MS: u32 = 1000;
+
+/// Level a [`ToneBuilder`] starts with, in dBm0.
+const DEFAULT_LEVEL_DBM0: i32 = -13;
+
+/// How a tone sounds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TonePattern {
+    /// Up to two tones switched on and off by a [`ToneCadence`]; compiles
+    /// to a [`ToneGenDescriptor`].
+    Cadenced {
+        /// First tone component.
+        tone1: ToneFreq,
+        /// Second tone component, or [`ToneFreq::NONE`].
+        tone2: ToneFreq,
+        /// On/off timing.
+        cadence: ToneCadence,
+        /// Whether the cadence repeats.
+        repeat: bool,
+    },
+    /// A tree of steps, for cadences a [`ToneCadence`] cannot describe.
+    Steps(Vec<SuperToneStep>),
+}
+
+impl TonePattern {
+    /// A repeating cadenced tone.
+    pub fn cadenced(tone1: ToneFreq, tone2: ToneFreq, cadence: ToneCadence) -> Self {
+        Self::Cadenced {
+            tone1,
+            tone2,
+            cadence,
+            repeat: true,
+        }
+    }
+
+    /// The pattern as super tone steps.
+    fn steps(&self) -> Vec<SuperToneStep> {
+        let (tone1, tone2, cadence, repeat) = match self {
+            Self::Steps(steps) => return steps.clone(),
+            Self::Cadenced {
+                tone1,
+                tone2,
+                cadence,
+                repeat,
+            } => (*tone1, *tone2, *cadence, *repeat),
+        };
+        if repeat && cadence.off1 <= 0 && cadence.off2 <= 0 {
+            // Steady: one step that never ends.
+            return vec![SuperToneStep::tone(tone1, tone2, 0)];
+        }
+        let segments = [cadence.on1, cadence.off1, cadence.on2, cadence.off2];
+        let cycle: Vec<SuperToneStep> = segments
+            .iter()
+            .enumerate()
+            .filter(|&(_, &ms)| ms > 0)
+            .map(|(i, &ms)| match i % 2 {
+                0 => SuperToneStep::tone(tone1, tone2, ms as u32),
+                _ => SuperToneStep::silence(ms as u32),
+            })
+            .collect();
+        if repeat {
+            vec![SuperToneStep::repeat(0, cycle)]
+        } else {
+            cycle
+        }
+    }
+}
+
+/// One named tone in a [`TonePlan`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ToneSpec {
+    /// Name the plan looks the tone up by, e.g. `"busy"`.
+    pub name: String,
+    /// How the tone sounds.
+    pub pattern: TonePattern,
+    /// What the detector listens for. When empty, the segments of one
+    /// cycle of `pattern`, each allowed 10% either way; a steady tone must
+    /// last a second.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub detect: Vec<ToneElement>,
+}
+
+impl ToneSpec {
+    /// A tone detected by its pattern.
+    pub fn new(name: impl Into<String>, pattern: TonePattern) -> Self {
+        Self {
+            name: name.into(),
+            pattern,
+            detect: Vec::new(),
+        }
+    }
+
+    /// The tone as a [`ToneGenDescriptor`]. Only cadenced tones have one.
+    pub fn descriptor(&self) -> Result<ToneGenDescriptor> {
+        match &self.pattern {
+            TonePattern::Cadenced {
+                tone1,
+                tone2,
+                cadence,
+                repeat,
+            } => ToneGenDescriptor::new(*tone1, *tone2, *cadence, *repeat),
+            TonePattern::Steps(_) => Err(SpanDspError::InvalidInput(format!(
+                "tone {:?} is a step tree, not a cadenced tone",
+                self.name
+            ))),
+        }
+    }
+
+    /// A generator playing the tone.
+    pub fn generator(&self) -> Result<SuperToneTx> {
+        SuperToneTx::new(self.pattern.steps())
+    }
+
+    /// The elements the detector listens for: [`detect`](Self::detect),
+    /// or ones derived from the pattern.
+    pub fn detect_elements(&self) -> Vec<ToneElement> {
+        if !self.detect.is_empty() {
+            return self.detect.clone();
+        }
+        let mut elements = Vec::new();
+        cycle_elements(&self.pattern.steps(), &mut elements);
+        elements
+    }
+}
+
+/// Builds a [`ToneSpec`] a setting at a time, starting from one of
+/// [`TonePlan::dial`], [`TonePlan::busy`] and the like.
+///
+/// The tone is steady and at -13 dBm0 until given a cadence or level; give
+/// it its frequencies with [`freq`](Self::freq) or [`freqs`](Self::freqs).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct ToneBuilder {
+    name: String,
+    tone1: ToneFreq,
+    tone2: ToneFreq,
+    cadence: ToneCadence,
+    repeat: bool,
+    steps: Option<Vec<SuperToneStep>>,
+    detect: Vec<ToneElement>,
+}
+
+impl ToneBuilder {
+    /// A tone called `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tone1: ToneFreq::new(0, DEFAULT_LEVEL_DBM0),
+            tone2: ToneFreq::NONE,
+            cadence: ToneCadence::continuous(STEADY_MIN_MS as i32),
+            repeat: true,
+            steps: None,
+            detect: Vec::new(),
+        }
+    }
+
+    /// A single frequency, in Hz.
+    pub fn freq(mut self, hz: i32) -> Self {
+        self.tone1.frequency = hz;
+        self.tone2 = ToneFreq::NONE;
+        self
+    }
+
+    /// Two frequencies mixed, in Hz, at the same level.
+    pub fn freqs(mut self, hz1: i32, hz2: i32) -> Self {
+        self.tone1.frequency = hz1;
+        self.tone2 = ToneFreq::new(hz2, self.tone1.level);
+        self
+    }
+
+    /// The level of each frequency, in dBm0.
+    pub fn level(mut self, dbm0: i32) -> Self {
+        self.tone1.level = dbm0;
+        if self.tone2.frequency != 0 {
+            self.tone2.level = dbm0;
+        }
+        self
+    }
+
+    /// Switch the tone on and off by `cadence`, repeating.
+    pub fn cadence(mut self, cadence: ToneCadence) -> Self {
+        self.cadence = cadence;
+        self.steps = None;
+        self
+    }
+
+    /// Play the cadence once rather than repeating it.
+    pub fn once(mut self) -> Self {
+        self.repeat = false;
+        self
+    }
+
+    /// Play a tree of steps instead, for cadences a [`ToneCadence`] cannot
+    /// describe. Frequencies and levels set on the builder are not used.
+    pub fn steps(mut self, steps: Vec<SuperToneStep>) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    /// Listen for `elements` rather than ones derived from the pattern.
+    pub fn detect(mut self, elements: Vec<ToneElement>) -> Self {
+        self.detect = elements;
+        self
+    }
+
+    /// The finished tone.
+    pub fn build(self) -> ToneSpec {
+        let pattern = match self.steps {
+            Some(steps) => TonePattern::Steps(steps),
+            None => TonePattern::Cadenced {
+                tone1: self.tone1,
+                tone2: self.tone2,
+                cadence: self.cadence,
+                repeat: self.repeat,
+            },
+        };
+        ToneSpec {
+            name: self.name,
+            pattern,
+            detect: self.detect,
+        }
+    }
+}
+
+impl From<ToneBuilder> for ToneSpec {
+    fn from(builder: ToneBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Append the segments of one pass through `steps`, merging neighbours
+/// that sound the same.
+fn cycle_elements(steps: &[SuperToneStep], elements: &mut Vec<ToneElement>) {
+    for step in steps {
+        match step {
+            SuperToneStep::Tone {
+                tone1,
+                tone2,
+                duration_ms,
+            } => {
+                let (f1, f2) = (tone1.frequency, tone2.frequency);
+                match elements.last_mut() {
+                    Some(last) if (last.tone1_hz, last.tone2_hz) == (f1, f2) => {
+                        if last.max_ms != 0 && *duration_ms != 0 {
+                            let total = nominal_ms(last) + duration_ms;
+                            *last = toleranced(f1, f2, total);
+                        } else {
+                            last.max_ms = 0;
+                        }
+                    }
+                    _ => elements.push(toleranced(f1, f2, *duration_ms)),
+                }
+            }
+            SuperToneStep::Repeat { cycles, steps } => {
+                for _ in 0..(*cycles).max(1) {
+                    cycle_elements(steps, elements);
+                }
+            }
+        }
+    }
+}
+
+/// An element for a segment of `ms` within the tolerance, or for a steady
+/// tone if `ms` is 0.
+fn toleranced(f1: i32, f2: i32, ms: u32) -> ToneElement {
+    if ms == 0 {
+        return ToneElement::new(f1, f2, STEADY_MIN_MS, 0);
+    }
+    let slack = (ms as f32 * DETECT_TOLERANCE).round() as u32;
+    ToneElement::new(f1, f2, ms - slack, ms + slack)
+}
+
+/// The duration a toleranced element was made from.
+fn nominal_ms(element: &ToneElement) -> u32 {
+    (element.min_ms + element.max_ms) / 2
+}
+
+/// A named set of tones, e.g. one country's call progress tones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TonePlan {
+    /// Name of the plan, e.g. a country code.
+    pub name: String,
+    /// The tones; a detector reports them by index into this list.
+    pub tones: Vec<ToneSpec>,
+}
+
+impl TonePlan {
+    /// An empty plan called `name`, to add tones to with
+    /// [`with_tone`](Self::with_tone).
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tones: Vec::new(),
+        }
+    }
+
+    /// The plan with `tone` added.
+    #[must_use]
+    pub fn with_tone(mut self, tone: impl Into<ToneSpec>) -> Self {
+        self.tones.push(tone.into());
+        self
+    }
+
+    /// Start building a tone called `"dial"`.
+    pub fn dial() -> ToneBuilder {
+        ToneBuilder::new("dial")
+    }
+
+    /// Start building a tone called `"ringback"`.
+    pub fn ringback() -> ToneBuilder {
+        ToneBuilder::new("ringback")
+    }
+
+    /// Start building a tone called `"busy"`.
+    pub fn busy() -> ToneBuilder {
+        ToneBuilder::new("busy")
+    }
+
+    /// Start building a tone called `"congestion"` (reorder).
+    pub fn congestion() -> ToneBuilder {
+        ToneBuilder::new("congestion")
+    }
+
+    /// Start building a tone called `"call_waiting"`.
+    pub fn call_waiting() -> ToneBuilder {
+        ToneBuilder::new("call_waiting")
+    }
+
+    /// The tone called `name`.
+    pub fn tone(&self, name: &str) -> Option<&ToneSpec> {
+        self.tones.iter().find(|tone| tone.name == name)
+    }
+
+    /// Check every tone compiles: names are unique and patterns playable.
+    pub fn validate(&self) -> Result<()> {
+        for (i, tone) in self.tones.iter().enumerate() {
+            if self.tones[..i].iter().any(|other| other.name == tone.name) {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "tone plan {:?} names {:?} twice",
+                    self.name, tone.name
+                )));
+            }
+            tone.generator()?;
+        }
+        Ok(())
+    }
+
+    /// A generator playing the tone called `name`.
+    pub fn generator(&self, name: &str) -> Result<SuperToneTx> {
+        self.tone(name)
+            .ok_or_else(|| {
+                SpanDspError::InvalidInput(format!(
+                    "tone plan {:?} has no tone {name:?}",
+                    self.name
+                ))
+            })?
+            .generator()
+    }
+
+    /// A detector for every tone in the plan. It reports tones by their
+    /// index in [`tones`](Self::tones).
+    pub fn detector(&self) -> Result<SuperToneRx> {
+        SuperToneRx::new(self.rx_descriptor()?)
+    }
+
+    /// A detector for every tone in the plan, calling `callback` with the
+    /// tone's name as each one starts and ends.
+    pub fn detector_with_callback<F>(&self, mut callback: F) -> Result<SuperToneRx>
+    where
+        F: FnMut(&str, SuperToneEvent) + 'static,
+    {
+        let names: Vec<String> = self.tones.iter().map(|tone| tone.name.clone()).collect();
+        SuperToneRx::with_callback(self.rx_descriptor()?, move |event| {
+            callback(&names[event.tone()], event)
+        })
+    }
+
+    /// Every tone's detection cadence, in plan order.
+    fn rx_descriptor(&self) -> Result<SuperToneRxDescriptor> {
+        let mut descriptor = SuperToneRxDescriptor::new()?;
+        for tone in &self.tones {
+            descriptor.add_tone(&tone.detect_elements())?;
+        }
+        Ok(descriptor)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Bundled plans
+// ---------------------------------------------------------------------------
+
+/// How long dial tone must be heard before it is reported, in
+/// milliseconds: longer than any ringback burst, which in single-frequency
+/// countries is the same frequency.
+const DIAL_DETECT_MS: u32 = 3000;
+
+impl TonePlan {
+    /// Country codes (ISO 3166-1 alpha-2) with a bundled plan.
+    pub const COUNTRIES: &'static [&'static str] = &["DE", "ES", "FR", "GB", "IT", "NL", "US"];
+
+    /// The bundled dial, ringback, busy and congestion tones of `country`,
+    /// an ISO 3166-1 alpha-2 code in either case, from ITU-T E.180
+    /// Supplement 2.
+    pub fn country(country: &str) -> Option<Self> {
+        let code = country.to_ascii_uppercase();
+        let plan = match code.as_str() {
+            "US" => Self::new(code)
+                .with_tone(steady_dial(Self::dial().freqs(350, 440)))
+                .with_tone(
+                    Self::ringback()
+                        .freqs(440, 480)
+                        .level(-19)
+                        .cadence(ToneCadence::simple(2000, 4000)),
+                )
+                .with_tone(
+                    Self::busy()
+                        .freqs(480, 620)
+                        .level(-24)
+                        .cadence(ToneCadence::simple(500, 500)),
+                )
+                .with_tone(
+                    Self::congestion()
+                        .freqs(480, 620)
+                        .level(-24)
+                        .cadence(ToneCadence::simple(250, 250)),
+                ),
+            "GB" => Self::new(code)
+                .with_tone(steady_dial(Self::dial().freqs(350, 440)))
+                .with_tone(
+                    Self::ringback()
+                        .freqs(400, 450)
+                        .level(-19)
+                        .cadence(ToneCadence::new(400, 200, 400, 2000)),
+                )
+                .with_tone(
+                    Self::busy()
+                        .freq(400)
+                        .cadence(ToneCadence::simple(375, 375)),
+                )
+                .with_tone(
+                    Self::congestion()
+                        .freq(400)
+                        .cadence(ToneCadence::new(400, 350, 225, 525)),
+                ),
+            "DE" => single_frequency(code, 425, (1000, 4000), (480, 480), (240, 240)),
+            "ES" => Self::new(code)
+                .with_tone(steady_dial(Self::dial().freq(425)))
+                .with_tone(
+                    Self::ringback()
+                        .freq(425)
+                        .cadence(ToneCadence::simple(1500, 3000)),
+                )
+                .with_tone(
+                    Self::busy()
+                        .freq(425)
+                        .cadence(ToneCadence::simple(200, 200)),
+                )
+                .with_tone(
+                    Self::congestion()
+                        .freq(425)
+                        .cadence(ToneCadence::new(200, 200, 200, 600)),
+                ),
+            "FR" => single_frequency(code, 440, (1500, 3500), (500, 500), (250, 250)),
+            "IT" => Self::new(code)
+                .with_tone(
+                    Self::dial()
+                        .freq(425)
+                        .cadence(ToneCadence::new(200, 200, 600, 1000)),
+                )
+                .with_tone(
+                    Self::ringback()
+                        .freq(425)
+                        .cadence(ToneCadence::simple(1000, 4000)),
+                )
+                .with_tone(
+                    Self::busy()
+                        .freq(425)
+                        .cadence(ToneCadence::simple(500, 500)),
+                )
+                .with_tone(
+                    Self::congestion()
+                        .freq(425)
+                        .cadence(ToneCadence::simple(200, 200)),
+                ),
+            "NL" => single_frequency(code, 425, (1000, 4000), (500, 500), (250, 250)),
+            _ => return None,
+        };
+        Some(plan)
+    }
+}
+
+/// A steady dial tone, reported once it has lasted [`DIAL_DETECT_MS`].
+fn steady_dial(dial: ToneBuilder) -> ToneSpec {
+    let spec = dial.build();
+    let mut detect = spec.detect_elements();
+    for element in &mut detect {
+        element.min_ms = DIAL_DETECT_MS;
+    }
+    ToneSpec { detect, ..spec }
+}
+
+/// A plan whose tones are all `hz`: steady dial tone, and ringback, busy
+/// and congestion with the given on/off times in milliseconds.
+fn single_frequency(
+    code: String,
+    hz: i32,
+    ringback: (i32, i32),
+    busy: (i32, i32),
+    congestion: (i32, i32),
+) -> TonePlan {
+    let cadenced = |tone: ToneBuilder, (on, off): (i32, i32)| {
+        tone.freq(hz).cadence(ToneCadence::simple(on, off))
+    };
+    TonePlan::new(code)
+        .with_tone(steady_dial(TonePlan::dial().freq(hz)))
+        .with_tone(cadenced(TonePlan::ringback(), ringback))
+        .with_tone(cadenced(TonePlan::busy(), busy))
+        .with_tone(cadenced(TonePlan::congestion(), congestion))
+}
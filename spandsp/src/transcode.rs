@@ -0,0 +1,438 @@
+//! Transcoding between the wrapped voice codecs.
+//!
+//! A gateway bridging two legs usually decodes one codec, converts the
+//! sample rate, and encodes another, holding back odd samples so the
+//! encoder only ever sees whole units. [`Transcoder`] does all of that
+//! between any two [`CodecSpec`]s, e.g. narrowband G.711 to wideband G.722:
+//!
+//! ```no_run
+//! use spandsp::g711::G711Mode;
+//! use spandsp::transcode::{CodecSpec, Transcoder};
+//!
+//! let mut transcoder = Transcoder::new(CodecSpec::G711(G711Mode::ULaw), CodecSpec::g722()).unwrap();
+//! let mut g722 = Vec::new();
+//! transcoder.transcode(&[0xFF; 160], &mut g722);
+//! assert_eq!(g722.len(), 160);
+//! ```
+
+use std::f64::consts::PI;
+use std::fmt;
+
+use crate::error::{Result, SpanDspError};
+use crate::g711::{G711Mode, G711State};
+use crate::g722::{G722Decoder, G722Encoder, G722Options, G722Rate};
+use crate::g726::{G726Encoding, G726Packing, G726Rate, G726State};
+
+/// Taps in the 8 kHz <-> 16 kHz interpolation filter. Odd, so the filter
+/// has a whole sample of delay.
+const RESAMPLE_TAPS: usize = 63;
+
+/// Most samples any codec decodes from one byte (G.726 at 16 kbit/s,
+/// packed).
+const MAX_SAMPLES_PER_BYTE: usize = 4;
+
+/// A codec and the settings that affect its bitstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CodecSpec {
+    /// G.711 at 8 kHz, one code per byte.
+    G711(G711Mode),
+    /// G.722, at 16 kHz unless `options` has
+    /// [`SAMPLE_RATE_8000`](G722Options::SAMPLE_RATE_8000).
+    G722 {
+        /// Bit rate.
+        rate: G722Rate,
+        /// Sample rate and packing options.
+        options: G722Options,
+    },
+    /// G.726 at 8 kHz.
+    G726 {
+        /// Bit rate.
+        rate: G726Rate,
+        /// How codes are packed into bytes.
+        packing: G726Packing,
+    },
+    /// 16-bit linear PCM in network (big-endian) byte order, at 8000 or
+    /// 16000 samples per second.
+    L16 {
+        /// Samples per second.
+        sample_rate: u32,
+    },
+}
+
+impl CodecSpec {
+    /// G.722 at 64 kbit/s and 16 kHz, as used for wideband RTP.
+    pub fn g722() -> Self {
+        Self::G722 {
+            rate: G722Rate::Rate64000,
+            options: G722Options::empty(),
+        }
+    }
+
+    /// Samples per second of the audio this codec carries.
+    pub fn sample_rate(&self) -> u32 {
+        match *self {
+            Self::G722 { options, .. } if !options.contains(G722Options::SAMPLE_RATE_8000) => 16000,
+            Self::L16 { sample_rate } => sample_rate,
+            _ => 8000,
+        }
+    }
+
+    /// The smallest run of samples the encoder turns into whole bytes.
+    /// [`Transcoder`] encodes in multiples of this.
+    pub fn samples_per_unit(&self) -> usize {
+        match *self {
+            Self::G711(_) | Self::L16 { .. } => 1,
+            Self::G722 { rate, options } => {
+                let pair = if options.contains(G722Options::SAMPLE_RATE_8000) {
+                    1
+                } else {
+                    2
+                };
+                // Packed 56 and 48 kbit/s codes are 7 and 6 bits per pair.
+                if options.contains(G722Options::PACKED) && rate != G722Rate::Rate64000 {
+                    8 * pair
+                } else {
+                    pair
+                }
+            }
+            Self::G726 { packing, .. } => match packing {
+                G726Packing::None => 1,
+                G726Packing::Left | G726Packing::Right => 8,
+            },
+        }
+    }
+
+    /// Bytes the encoder produces for `samples` samples, a multiple of
+    /// [`samples_per_unit`](Self::samples_per_unit).
+    pub fn bytes_for(&self, samples: usize) -> usize {
+        match *self {
+            Self::G711(_) => samples,
+            Self::L16 { .. } => 2 * samples,
+            Self::G722 { rate, options } => {
+                let pairs = if options.contains(G722Options::SAMPLE_RATE_8000) {
+                    samples
+                } else {
+                    samples / 2
+                };
+                if options.contains(G722Options::PACKED) {
+                    pairs * rate.bps() as usize / 64000
+                } else {
+                    pairs
+                }
+            }
+            Self::G726 { rate, packing } => match packing {
+                G726Packing::None => samples,
+                G726Packing::Left | G726Packing::Right => {
+                    samples * usize::from(rate.bits_per_sample()) / 8
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for CodecSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::G711(mode) => write!(f, "G.711 {mode}"),
+            Self::G722 { rate, .. } => write!(f, "G.722 {rate}, {} Hz", self.sample_rate()),
+            Self::G726 { rate, .. } => write!(f, "G.726 {rate}"),
+            Self::L16 { sample_rate } => write!(f, "L16 {sample_rate} Hz"),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Codec ends
+// ---------------------------------------------------------------------------
+
+/// The decoding half of a [`CodecSpec`].
+pub(crate) enum Decoder {
+    G711(G711State),
+    G722(G722Decoder),
+    G726(G726State),
+    /// An odd byte waiting for its partner.
+    L16(Option<u8>),
+}
+
+impl Decoder {
+    pub(crate) fn new(spec: CodecSpec) -> Result<Self> {
+        Ok(match spec {
+            CodecSpec::G711(mode) => Self::G711(G711State::new(mode)?),
+            CodecSpec::G722 { rate, options } => Self::G722(G722Decoder::new(rate, options)?),
+            CodecSpec::G726 { rate, packing } => {
+                Self::G726(G726State::new(rate, G726Encoding::Linear, packing)?)
+            }
+            CodecSpec::L16 { .. } => Self::L16(None),
+        })
+    }
+
+    /// Decode `data`, appending the samples to `amp`.
+    pub(crate) fn decode(&mut self, data: &[u8], amp: &mut Vec<i16>) {
+        let start = amp.len();
+        amp.resize(start + data.len() * MAX_SAMPLES_PER_BYTE, 0);
+        let out = &mut amp[start..];
+        let samples = match self {
+            Self::G711(state) => state.decode(out, data),
+            Self::G722(decoder) => decoder.decode(out, data),
+            Self::G726(state) => state.decode(out, data),
+            Self::L16(pending) => {
+                let mut bytes = pending.take().into_iter().chain(data.iter().copied());
+                let mut samples = 0;
+                while let Some(hi) = bytes.next() {
+                    match bytes.next() {
+                        Some(lo) => {
+                            out[samples] = i16::from_be_bytes([hi, lo]);
+                            samples += 1;
+                        }
+                        None => *pending = Some(hi),
+                    }
+                }
+                samples
+            }
+        };
+        amp.truncate(start + samples);
+    }
+}
+
+/// The encoding half of a [`CodecSpec`].
+pub(crate) enum Encoder {
+    G711(G711State),
+    G722(G722Encoder),
+    G726(G726State),
+    L16,
+}
+
+impl Encoder {
+    pub(crate) fn new(spec: CodecSpec) -> Result<Self> {
+        Ok(match spec {
+            CodecSpec::G711(mode) => Self::G711(G711State::new(mode)?),
+            CodecSpec::G722 { rate, options } => Self::G722(G722Encoder::new(rate, options)?),
+            CodecSpec::G726 { rate, packing } => {
+                Self::G726(G726State::new(rate, G726Encoding::Linear, packing)?)
+            }
+            CodecSpec::L16 { .. } => Self::L16,
+        })
+    }
+
+    /// Encode `amp`, appending `bytes` bytes to `data`.
+    pub(crate) fn encode(&mut self, amp: &[i16], bytes: usize, data: &mut Vec<u8>) {
+        let start = data.len();
+        data.resize(start + bytes, 0);
+        let out = &mut data[start..];
+        let written = match self {
+            Self::G711(state) => state.encode(out, amp),
+            Self::G722(encoder) => encoder.encode(out, amp),
+            Self::G726(state) => state.encode(out, amp),
+            Self::L16 => {
+                for (pair, sample) in out.chunks_exact_mut(2).zip(amp) {
+                    pair.copy_from_slice(&sample.to_be_bytes());
+                }
+                bytes
+            }
+        };
+        data.truncate(start + written);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Sample rate conversion
+// ---------------------------------------------------------------------------
+
+/// Converts between 8 kHz and 16 kHz with a windowed-sinc low-pass filter
+/// cutting off at 4 kHz.
+struct Resampler {
+    /// Filter taps at the 16 kHz rate.
+    taps: Vec<f32>,
+    /// Most recent input samples, newest last.
+    history: Vec<f32>,
+    up: bool,
+    /// Downsampling: whether the next input sample is the odd one of a pair.
+    odd: bool,
+}
+
+impl Resampler {
+    fn new(up: bool) -> Self {
+        let centre = (RESAMPLE_TAPS / 2) as f64;
+        let taps: Vec<f64> = (0..RESAMPLE_TAPS)
+            .map(|i| {
+                let x = i as f64 - centre;
+                let sinc = if x == 0.0 {
+                    0.5
+                } else {
+                    (0.5 * PI * x).sin() / (PI * x)
+                };
+                let window = 0.54 - 0.46 * (2.0 * PI * i as f64 / (RESAMPLE_TAPS - 1) as f64).cos();
+                sinc * window
+            })
+            .collect();
+        let dc: f64 = taps.iter().sum();
+        let history = if up {
+            RESAMPLE_TAPS.div_ceil(2)
+        } else {
+            RESAMPLE_TAPS
+        };
+        Self {
+            taps: taps.iter().map(|t| (t / dc) as f32).collect(),
+            history: vec![0.0; history],
+            up,
+            odd: false,
+        }
+    }
+
+    fn push(&mut self, sample: i16) {
+        self.history.rotate_left(1);
+        let newest = self.history.len() - 1;
+        self.history[newest] = f32::from(sample);
+    }
+
+    /// Resample `amp`, appending the result to `out`.
+    fn process(&mut self, amp: &[i16], out: &mut Vec<i16>) {
+        for &sample in amp {
+            self.push(sample);
+            if self.up {
+                // Zero-stuffing halves the level, so each phase is doubled.
+                for phase in 0..2 {
+                    let acc: f32 = self
+                        .taps
+                        .iter()
+                        .skip(phase)
+                        .step_by(2)
+                        .zip(self.history.iter().rev())
+                        .map(|(t, x)| t * x)
+                        .sum();
+                    out.push(to_sample(2.0 * acc));
+                }
+            } else {
+                self.odd = !self.odd;
+                if !self.odd {
+                    let acc: f32 = self
+                        .taps
+                        .iter()
+                        .zip(self.history.iter().rev())
+                        .map(|(t, x)| t * x)
+                        .sum();
+                    out.push(to_sample(acc));
+                }
+            }
+        }
+    }
+}
+
+fn to_sample(v: f32) -> i16 {
+    v.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+}
+
+// ---------------------------------------------------------------------------
+// Transcoder
+// ---------------------------------------------------------------------------
+
+/// Decodes one codec, converts the sample rate if need be, and encodes
+/// another.
+///
+/// Input can arrive in any sized pieces. The encoder is fed whole
+/// [`samples_per_unit`](CodecSpec::samples_per_unit) runs only; samples
+/// short of one wait for the next call.
+pub struct Transcoder {
+    from: CodecSpec,
+    to: CodecSpec,
+    decoder: Decoder,
+    resampler: Option<Resampler>,
+    encoder: Encoder,
+    /// Decoded, resampled audio not yet encoded.
+    pending: Vec<i16>,
+    /// Scratch for decoded audio before resampling.
+    decoded: Vec<i16>,
+}
+
+impl Transcoder {
+    /// Create a transcoder from `from` to `to`.
+    pub fn new(from: CodecSpec, to: CodecSpec) -> Result<Self> {
+        for spec in [from, to] {
+            if !matches!(spec.sample_rate(), 8000 | 16000) {
+                return Err(SpanDspError::InvalidInput(format!(
+                    "{spec}: only 8000 and 16000 Hz are supported"
+                )));
+            }
+        }
+        let resampler = match (from.sample_rate(), to.sample_rate()) {
+            (8000, 16000) => Some(Resampler::new(true)),
+            (16000, 8000) => Some(Resampler::new(false)),
+            _ => None,
+        };
+        Ok(Self {
+            from,
+            to,
+            decoder: Decoder::new(from)?,
+            resampler,
+            encoder: Encoder::new(to)?,
+            pending: Vec::new(),
+            decoded: Vec::new(),
+        })
+    }
+
+    /// The input codec.
+    pub fn from(&self) -> CodecSpec {
+        self.from
+    }
+
+    /// The output codec.
+    pub fn to(&self) -> CodecSpec {
+        self.to
+    }
+
+    /// Transcode `input`, appending the encoded result to `output`.
+    ///
+    /// Returns the number of bytes appended.
+    pub fn transcode(&mut self, input: &[u8], output: &mut Vec<u8>) -> usize {
+        match &mut self.resampler {
+            Some(resampler) => {
+                self.decoded.clear();
+                self.decoder.decode(input, &mut self.decoded);
+                resampler.process(&self.decoded, &mut self.pending);
+            }
+            None => self.decoder.decode(input, &mut self.pending),
+        }
+        let unit = self.to.samples_per_unit();
+        let samples = self.pending.len() - self.pending.len() % unit;
+        let start = output.len();
+        self.encoder
+            .encode(&self.pending[..samples], self.to.bytes_for(samples), output);
+        self.pending.drain(..samples);
+        output.len() - start
+    }
+
+    /// Samples decoded but waiting for a whole encoder unit.
+    pub fn pending_samples(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Encode the samples still waiting for a whole unit at the end of a
+    /// stream, padded out with silence, so the tail is not lost.
+    ///
+    /// Returns the number of bytes appended to `output`.
+    pub fn finish(&mut self, output: &mut Vec<u8>) -> usize {
+        if self.pending.is_empty() {
+            return 0;
+        }
+        let samples = self
+            .pending
+            .len()
+            .next_multiple_of(self.to.samples_per_unit());
+        self.pending.resize(samples, 0);
+        let start = output.len();
+        self.encoder
+            .encode(&self.pending, self.to.bytes_for(samples), output);
+        self.pending.clear();
+        output.len() - start
+    }
+}
+
+impl fmt::Debug for Transcoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transcoder")
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .field("pending", &self.pending.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,574 @@
+//! Type II caller ID: caller ID delivered to a line that is already on a
+//! call, e.g. with call waiting.
+//!
+//! The switch interrupts the call with the subscriber alerting signal (SAS,
+//! a 440 Hz beep for the subscriber) and the CPE alerting signal (CAS,
+//! 2130 Hz plus 2750 Hz for 80 ms). A phone that hears the CAS mutes its
+//! handset and acknowledges with DTMF `D` (`A` from an ADSI phone). The
+//! switch then sends the caller ID as FSK, with a short run of marks in
+//! place of the channel seizure, and the phone unmutes once it is in.
+//!
+//! - [`CasTx`] generates the SAS and CAS, and [`CasDetector`] detects the
+//!   CAS.
+//! - [`Type2Sender`] runs the switch's side of the exchange.
+//! - [`Type2Receiver`] runs the phone's side.
+//!
+//! Each side sits in an existing 8 kHz audio path: every block passes
+//! through `process`, which leaves the audio alone except while an exchange
+//! is under way. Timings follow GR-30; ETSI's "during a call" caller ID
+//! (EN 300 659-2) uses the same alerting tone and acknowledgement.
+//!
+//! ```no_run
+//! use spandsp::adsi::{AdsiStandard, CallerIdBuilder};
+//! use spandsp::type2_cid::Type2Sender;
+//!
+//! let mut sender = Type2Sender::new(AdsiStandard::Class).unwrap();
+//! sender
+//!     .offer(&CallerIdBuilder::new().number("5551234567"))
+//!     .unwrap();
+//! let (from_phone, mut to_phone) = ([0i16; 160], [0i16; 160]);
+//! // ... for each 20 ms block of the call:
+//! if let Some(outcome) = sender.process(&from_phone, &mut to_phone) {
+//!     println!("caller ID {outcome}");
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::adsi::{
+    AdsiStandard, AdsiTx, CallerId, CallerIdBuilder, CallerIdDetector, ChannelSeizure,
+};
+use crate::dtmf::{DtmfRx, DtmfTx};
+use crate::error::{Result, SpanDspError};
+use crate::power_meter::dbm0_to_sine_power;
+use crate::tone_detect::{GoertzelDescriptor, GoertzelDetector};
+use crate::tone_generate::{ToneCadence, ToneFreq, ToneGenDescriptor, ToneGenerator};
+
+const SAMPLE_RATE: usize = 8000;
+
+/// The CAS: two tones for 80 ms.
+const CAS_FREQS: [f32; 2] = [2130.0, 2750.0];
+const CAS_MS: usize = 80;
+/// The SAS: one 300 ms burst of 440 Hz.
+const SAS_FREQ: i32 = 440;
+const SAS_MS: usize = 300;
+const SAS_LEVEL_DBM0: i32 = -13;
+
+/// Detection block, 10 ms.
+const CAS_BLOCK: usize = 80;
+/// Blocks a CAS must fill; an 80 ms tone fills at least seven whole ones.
+const CAS_MIN_BLOCKS: u32 = 5;
+/// Blocks past which the tone is too long to be a CAS.
+const CAS_MAX_BLOCKS: u32 = 12;
+/// Quietest each tone may be, in dBm0.
+const CAS_MIN_LEVEL_DBM0: f32 = -32.0;
+/// Largest difference in level between the tones, in dB.
+const CAS_MAX_TWIST_DB: f32 = 6.0;
+/// Share of a block's power the two tones must account for.
+const CAS_MIN_SHARE: f32 = 0.7;
+
+/// How long the switch waits after the CAS for the acknowledgement.
+const ACK_WAIT_MS: usize = 160;
+/// How long the phone's acknowledgement lasts.
+const ACK_MS: i32 = 60;
+/// What the phone acknowledges with: not an ADSI phone, so `D`.
+const ACK_DIGIT: &str = "D";
+/// Pause between the acknowledgement and the FSK; GR-30 allows 50-500 ms.
+const FSK_DELAY_MS: usize = 100;
+/// How long the phone stays muted waiting for the FSK.
+const RECEIVE_TIMEOUT_MS: usize = 2000;
+
+/// Type II FSK has no channel seizure, just 80 marks.
+const TYPE2_LEAD_IN: ChannelSeizure = ChannelSeizure {
+    seizure_bits: 0,
+    mark_bits: 80,
+    postamble_bits: 5,
+    stop_bits: 1,
+};
+
+fn ms(ms: usize) -> usize {
+    ms * SAMPLE_RATE / 1000
+}
+
+/// Check a standard has a Type II form.
+fn check_standard(standard: AdsiStandard) -> Result<()> {
+    match standard {
+        AdsiStandard::Class | AdsiStandard::Clip => Ok(()),
+        _ => Err(SpanDspError::InvalidInput(format!(
+            "{standard:?} has no off-hook caller ID"
+        ))),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// CasTx
+// ---------------------------------------------------------------------------
+
+/// Generates the SAS and CAS that open a Type II exchange.
+#[derive(Debug, Clone)]
+pub struct CasTx {
+    alert: Vec<i16>,
+    pos: usize,
+}
+
+impl CasTx {
+    /// Just the CAS, each tone at `level_dbm0`.
+    pub fn new(level_dbm0: i32) -> Result<Self> {
+        let alert = render(
+            ToneFreq::new(CAS_FREQS[0] as i32, level_dbm0),
+            ToneFreq::new(CAS_FREQS[1] as i32, level_dbm0),
+            CAS_MS,
+        )?;
+        Ok(Self { alert, pos: 0 })
+    }
+
+    /// The SAS, so the subscriber hears that a call is waiting, then the
+    /// CAS.
+    pub fn with_sas(level_dbm0: i32) -> Result<Self> {
+        let mut alert = render(
+            ToneFreq::new(SAS_FREQ, SAS_LEVEL_DBM0),
+            ToneFreq::NONE,
+            SAS_MS,
+        )?;
+        alert.extend(Self::new(level_dbm0)?.alert);
+        Ok(Self { alert, pos: 0 })
+    }
+
+    /// Generate audio.
+    ///
+    /// Returns the number of samples generated; fewer than `amp.len()` once
+    /// the alert is over.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let n = amp.len().min(self.alert.len() - self.pos);
+        amp[..n].copy_from_slice(&self.alert[self.pos..self.pos + n]);
+        self.pos += n;
+        n
+    }
+
+    /// Whether the whole alert has been generated.
+    pub fn is_finished(&self) -> bool {
+        self.pos == self.alert.len()
+    }
+}
+
+/// Play a one-off tone to the end.
+fn render(tone1: ToneFreq, tone2: ToneFreq, duration_ms: usize) -> Result<Vec<i16>> {
+    let cadence = ToneCadence::new(duration_ms as i32, 0, 0, 0);
+    let mut tone = ToneGenerator::new(&ToneGenDescriptor::new(tone1, tone2, cadence, false)?)?;
+    let mut out = vec![0i16; ms(duration_ms)];
+    let n = tone.generate(&mut out);
+    out.truncate(n);
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// CasDetector
+// ---------------------------------------------------------------------------
+
+/// Detects the CAS.
+///
+/// Every 10 ms block is checked for both tones, each above -32 dBm0,
+/// within 6 dB of each other and carrying most of the block's power. A run
+/// of such blocks as long as a CAS counts once it ends; a longer run, such
+/// as a held tone, does not.
+#[derive(Debug)]
+pub struct CasDetector {
+    goertzels: [GoertzelDetector; 2],
+    energy: f32,
+    filled: usize,
+    run: u32,
+    detected: u64,
+}
+
+impl CasDetector {
+    /// Create a detector.
+    pub fn new() -> Result<Self> {
+        let [low, high] = CAS_FREQS.map(|freq| GoertzelDescriptor::new(freq, CAS_BLOCK));
+        Ok(Self {
+            goertzels: [GoertzelDetector::new(&low)?, GoertzelDetector::new(&high)?],
+            energy: 0.0,
+            filled: 0,
+            run: 0,
+            detected: 0,
+        })
+    }
+
+    /// Feed audio. Returns whether a CAS ended in it.
+    pub fn rx(&mut self, amp: &[i16]) -> bool {
+        let mut ended = false;
+        let mut rest = amp;
+        while !rest.is_empty() {
+            let (block, tail) = rest.split_at((CAS_BLOCK - self.filled).min(rest.len()));
+            for goertzel in &mut self.goertzels {
+                goertzel.update(block);
+            }
+            self.energy += block
+                .iter()
+                .map(|&s| f32::from(s) * f32::from(s))
+                .sum::<f32>();
+            self.filled += block.len();
+            if self.filled == CAS_BLOCK {
+                ended |= self.block();
+            }
+            rest = tail;
+        }
+        ended
+    }
+
+    /// How many CASs have been detected.
+    pub fn detected(&self) -> u64 {
+        self.detected
+    }
+
+    fn block(&mut self) -> bool {
+        let [low, high] = self.goertzels.each_mut().map(|g| g.tone_power());
+        let mean_sq = self.energy / CAS_BLOCK as f32;
+        self.energy = 0.0;
+        self.filled = 0;
+
+        let min_power = dbm0_to_sine_power(CAS_MIN_LEVEL_DBM0);
+        let twist = 10.0f32.powf(CAS_MAX_TWIST_DB / 10.0);
+        let present = low >= min_power
+            && high >= min_power
+            && low.max(high) <= twist * low.min(high)
+            && low + high >= CAS_MIN_SHARE * mean_sq;
+        if present {
+            self.run += 1;
+            return false;
+        }
+        let cas = (CAS_MIN_BLOCKS..=CAS_MAX_BLOCKS).contains(&self.run);
+        self.run = 0;
+        if cas {
+            self.detected += 1;
+        }
+        cas
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Type2Sender
+// ---------------------------------------------------------------------------
+
+/// Where the switch's side of the exchange is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SenderState {
+    /// No caller ID to send; the call's audio passes untouched.
+    Idle,
+    /// Sending the SAS and CAS.
+    Alerting,
+    /// Waiting for the phone to acknowledge.
+    AwaitingAck,
+    /// Acknowledged; pausing before the FSK.
+    Pausing,
+    /// Sending the caller ID.
+    Sending,
+}
+
+/// How a Type II exchange ended, for the switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Type2Outcome {
+    /// The phone acknowledged with `digit` and the caller ID was sent.
+    Delivered {
+        /// `A` from an ADSI phone, `D` from any other.
+        digit: char,
+    },
+    /// The phone did not acknowledge; it cannot take Type II caller ID, so
+    /// nothing was sent.
+    NotAcknowledged,
+}
+
+impl fmt::Display for Type2Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type2Outcome::Delivered { digit } => write!(f, "delivered (ACK {digit})"),
+            Type2Outcome::NotAcknowledged => f.write_str("not acknowledged"),
+        }
+    }
+}
+
+/// The switch's side of a Type II exchange.
+///
+/// [`offer`](Self::offer) a caller ID, then pass each block of the call
+/// through [`process`](Self::process): the audio heard from the phone, and
+/// the audio going to it, which is replaced while the exchange runs.
+pub struct Type2Sender {
+    standard: AdsiStandard,
+    state: SenderState,
+    sas: bool,
+    cas_level_dbm0: i32,
+    alert: Option<CasTx>,
+    ack_rx: DtmfRx,
+    fsk: Option<AdsiTx>,
+    countdown: usize,
+    digit: char,
+}
+
+impl Type2Sender {
+    /// Create a sender for CLASS or ETSI CLIP, sending the SAS ahead of a
+    /// CAS at -15 dBm0.
+    pub fn new(standard: AdsiStandard) -> Result<Self> {
+        check_standard(standard)?;
+        Ok(Self {
+            standard,
+            state: SenderState::Idle,
+            sas: true,
+            cas_level_dbm0: -15,
+            alert: None,
+            ack_rx: DtmfRx::new()?,
+            fsk: None,
+            countdown: 0,
+            digit: 'D',
+        })
+    }
+
+    /// Whether to send the SAS ahead of the CAS.
+    pub fn set_sas(&mut self, sas: bool) {
+        self.sas = sas;
+    }
+
+    /// Set the level of each CAS tone, in dBm0.
+    pub fn set_cas_level(&mut self, level_dbm0: i32) {
+        self.cas_level_dbm0 = level_dbm0;
+    }
+
+    /// Start an exchange delivering `caller`.
+    pub fn offer(&mut self, caller: &CallerIdBuilder) -> Result<()> {
+        if self.state != SenderState::Idle {
+            return Err(SpanDspError::InvalidInput(
+                "a Type II exchange is already under way".into(),
+            ));
+        }
+        // The FSK is only generated once the phone has acknowledged.
+        let mut fsk = AdsiTx::new(self.standard)?;
+        fsk.set_channel_seizure(TYPE2_LEAD_IN);
+        fsk.put_raw(&caller.build(self.standard)?)?;
+        self.fsk = Some(fsk);
+        self.alert = Some(if self.sas {
+            CasTx::with_sas(self.cas_level_dbm0)?
+        } else {
+            CasTx::new(self.cas_level_dbm0)?
+        });
+        self.state = SenderState::Alerting;
+        Ok(())
+    }
+
+    /// Run one block of the call.
+    ///
+    /// `rx` is the audio from the phone, listened to for the
+    /// acknowledgement. `tx` is the audio to the phone; while an exchange
+    /// runs it is replaced with the alert, silence and then the FSK.
+    /// Returns the outcome when an exchange ends.
+    pub fn process(&mut self, rx: &[i16], tx: &mut [i16]) -> Option<Type2Outcome> {
+        match self.state {
+            SenderState::Idle => None,
+            SenderState::Alerting => {
+                let alert = self.alert.as_mut()?;
+                let n = alert.generate(tx);
+                tx[n..].fill(0);
+                if alert.is_finished() {
+                    self.alert = None;
+                    self.ack_rx.get(usize::MAX);
+                    self.countdown = ms(ACK_WAIT_MS);
+                    self.state = SenderState::AwaitingAck;
+                }
+                None
+            }
+            SenderState::AwaitingAck => {
+                tx.fill(0);
+                self.ack_rx.rx(rx);
+                if let Some(digit) = self
+                    .ack_rx
+                    .get(usize::MAX)
+                    .chars()
+                    .find(|d| "AD".contains(*d))
+                {
+                    self.digit = digit;
+                    self.countdown = ms(FSK_DELAY_MS);
+                    self.state = SenderState::Pausing;
+                    return None;
+                }
+                self.countdown = self.countdown.saturating_sub(rx.len());
+                if self.countdown == 0 {
+                    self.fsk = None;
+                    self.state = SenderState::Idle;
+                    return Some(Type2Outcome::NotAcknowledged);
+                }
+                None
+            }
+            SenderState::Pausing => {
+                tx.fill(0);
+                self.countdown = self.countdown.saturating_sub(tx.len());
+                if self.countdown == 0 {
+                    self.state = SenderState::Sending;
+                }
+                None
+            }
+            SenderState::Sending => {
+                let n = self.fsk.as_mut().map_or(0, |fsk| fsk.tx(tx));
+                tx[n..].fill(0);
+                if n < tx.len() {
+                    self.fsk = None;
+                    self.state = SenderState::Idle;
+                    return Some(Type2Outcome::Delivered { digit: self.digit });
+                }
+                None
+            }
+        }
+    }
+
+    /// Where the exchange is.
+    pub fn state(&self) -> SenderState {
+        self.state
+    }
+
+    /// The standard in use.
+    pub fn standard(&self) -> AdsiStandard {
+        self.standard
+    }
+}
+
+impl fmt::Debug for Type2Sender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Type2Sender")
+            .field("standard", &self.standard)
+            .field("state", &self.state)
+            .field("sas", &self.sas)
+            .finish_non_exhaustive()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Type2Receiver
+// ---------------------------------------------------------------------------
+
+/// Where the phone's side of the exchange is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReceiverState {
+    /// Listening for the CAS; the call's audio passes untouched.
+    Listening,
+    /// Muted, sending the acknowledgement.
+    Acknowledging,
+    /// Muted, receiving the caller ID.
+    Receiving,
+}
+
+/// How a Type II exchange ended, for the phone.
+#[derive(Debug, Clone)]
+pub enum Type2Reception {
+    /// A caller ID message arrived: the caller ID, or why it could not be
+    /// read.
+    Received(Result<CallerId>),
+    /// No message arrived within two seconds of the CAS, and the line was
+    /// handed back to the subscriber.
+    TimedOut,
+}
+
+/// The phone's side of a Type II exchange.
+///
+/// Pass each block of the call through [`process`](Self::process): the
+/// audio from the line, which is muted for the subscriber during the
+/// exchange, and the audio from the handset, which is replaced by the
+/// acknowledgement and silence.
+pub struct Type2Receiver {
+    state: ReceiverState,
+    cas: CasDetector,
+    ack: DtmfTx,
+    detector: CallerIdDetector,
+    countdown: usize,
+}
+
+impl Type2Receiver {
+    /// Create a receiver for CLASS or ETSI CLIP.
+    pub fn new(standard: AdsiStandard) -> Result<Self> {
+        check_standard(standard)?;
+        let mut ack = DtmfTx::new()?;
+        ack.set_timing(ACK_MS, 0);
+        Ok(Self {
+            state: ReceiverState::Listening,
+            cas: CasDetector::new()?,
+            ack,
+            detector: CallerIdDetector::new(standard)?,
+            countdown: 0,
+        })
+    }
+
+    /// Run one block of the call.
+    ///
+    /// `rx` is the audio from the line and `tx` the audio from the handset;
+    /// both are muted from the CAS until the caller ID is in, or two
+    /// seconds have passed without it. Returns how the exchange ended, in
+    /// the block it ends in; fails, still listening, if the acknowledgement
+    /// cannot be queued.
+    pub fn process(&mut self, rx: &mut [i16], tx: &mut [i16]) -> Result<Option<Type2Reception>> {
+        match self.state {
+            ReceiverState::Listening => {
+                if self.cas.rx(rx) {
+                    self.ack.put(ACK_DIGIT)?;
+                    self.countdown = ms(RECEIVE_TIMEOUT_MS);
+                    self.state = ReceiverState::Acknowledging;
+                }
+                Ok(None)
+            }
+            ReceiverState::Acknowledging => {
+                let n = self.ack.generate(tx);
+                tx[n..].fill(0);
+                if n < tx.len() {
+                    self.state = ReceiverState::Receiving;
+                }
+                Ok(self.receive(rx))
+            }
+            ReceiverState::Receiving => {
+                tx.fill(0);
+                Ok(self.receive(rx))
+            }
+        }
+    }
+
+    fn receive(&mut self, rx: &mut [i16]) -> Option<Type2Reception> {
+        // A Type II exchange carries one message, so the first is the
+        // caller ID; the line is handed back to the subscriber after it.
+        let result = self.detector.rx(rx).into_iter().next();
+        rx.fill(0);
+        self.countdown = self.countdown.saturating_sub(rx.len());
+        let reception = match result {
+            Some(result) => Type2Reception::Received(result),
+            None if self.countdown == 0 => Type2Reception::TimedOut,
+            None => return None,
+        };
+        self.state = ReceiverState::Listening;
+        Some(reception)
+    }
+
+    /// Where the exchange is.
+    pub fn state(&self) -> ReceiverState {
+        self.state
+    }
+
+    /// Whether the subscriber is cut off from the line.
+    pub fn is_muted(&self) -> bool {
+        self.state != ReceiverState::Listening
+    }
+
+    /// The most recent caller ID received.
+    pub fn caller_id(&self) -> Option<&CallerId> {
+        self.detector.caller_id()
+    }
+
+    /// The standard in use.
+    pub fn standard(&self) -> AdsiStandard {
+        self.detector.standard()
+    }
+}
+
+impl fmt::Debug for Type2Receiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Type2Receiver")
+            .field("standard", &self.standard())
+            .field("state", &self.state)
+            .field("caller_id", &self.caller_id())
+            .finish_non_exhaustive()
+    }
+}
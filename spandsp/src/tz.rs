@@ -0,0 +1,146 @@
+//! Time zone handling for fax page header timestamps.
+//!
+//! Wraps spandsp's `tz_t`, which converts UTC to local time using a POSIX
+//! `TZ` style rule string. Each [`Timezone`] carries its own rule, so a
+//! server sending for many customers can stamp each page in its sender's
+//! local time without touching the process-wide `TZ`.
+//!
+//! ```no_run
+//! use spandsp::t4::PageHeader;
+//! use spandsp::tz::Timezone;
+//!
+//! let paris = Timezone::new("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+//! println!("{}", paris.local_time(std::time::SystemTime::now()).unwrap());
+//! let header = PageHeader::new().with_info("ACME Paris").with_tz(&paris);
+//! ```
+
+extern crate spandsp_sys;
+
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::raw::c_int;
+use std::ptr::NonNull;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Result, SpanDspError};
+
+/// A broken-down local time, as stamped in a page header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Whether daylight saving time is in force.
+    pub is_dst: bool,
+}
+
+/// ISO 8601 style, e.g. `2024-07-01 14:00:00`.
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// RAII wrapper around `tz_t`.
+///
+/// Created via `tz_init(NULL, ...)`, freed on drop via `tz_free`.
+pub struct Timezone {
+    ptr: NonNull<spandsp_sys::tz_t>,
+    spec: String,
+}
+
+impl Timezone {
+    /// Parse a POSIX time zone rule such as `"CET-1CEST,M3.5.0,M10.5.0/3"`.
+    pub fn new(spec: &str) -> Result<Self> {
+        let c_spec = CString::new(spec)
+            .map_err(|_| SpanDspError::InvalidInput("time zone contains NUL byte".into()))?;
+        let ptr = unsafe { spandsp_sys::tz_init(std::ptr::null_mut(), c_spec.as_ptr()) };
+        let ptr = NonNull::new(ptr)
+            .ok_or_else(|| SpanDspError::InvalidInput(format!("invalid time zone: {spec}")))?;
+        Ok(Self {
+            ptr,
+            spec: spec.to_owned(),
+        })
+    }
+
+    /// The rule this zone was built from.
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// The zone's abbreviation for standard or daylight saving time, e.g.
+    /// `CET` or `CEST`.
+    pub fn abbreviation(&self, is_dst: bool) -> Option<String> {
+        let name = unsafe { spandsp_sys::tz_tzname(self.ptr.as_ptr(), is_dst as c_int) };
+        if name.is_null() {
+            return None;
+        }
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+        (!name.is_empty()).then(|| name.into_owned())
+    }
+
+    /// Convert `time` to local time in this zone.
+    pub fn local_time(&self, time: SystemTime) -> Result<LocalTime> {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| SpanDspError::InvalidInput("time is before 1970".into()))?
+            .as_secs();
+        let t = spandsp_sys::time_t::try_from(secs)
+            .map_err(|_| SpanDspError::InvalidInput("time is out of range".into()))?;
+        let mut tm: spandsp_sys::tm = unsafe { std::mem::zeroed() };
+        let rc = unsafe { spandsp_sys::tz_localtime(self.ptr.as_ptr(), &mut tm, t) };
+        if rc != 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(LocalTime {
+            year: tm.tm_year + 1900,
+            month: (tm.tm_mon + 1) as u8,
+            day: tm.tm_mday as u8,
+            hour: tm.tm_hour as u8,
+            minute: tm.tm_min as u8,
+            second: tm.tm_sec as u8,
+            is_dst: tm.tm_isdst > 0,
+        })
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::tz_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Clone for Timezone {
+    fn clone(&self) -> Self {
+        Self::new(&self.spec).expect("time zone rule parsed before")
+    }
+}
+
+impl fmt::Debug for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timezone")
+            .field("spec", &self.spec)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Timezone {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::tz_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: tz_t holds only the parsed rule and is never written after
+// tz_init; states that keep a pointer to it also keep the Timezone.
+unsafe impl Send for Timezone {}
@@ -0,0 +1,411 @@
+//! V.150.1 State Signalling Events.
+//!
+//! A modem-over-IP gateway tells its peer which media state it is in
+//! (plain audio, voice band data, modem relay, FAX relay, ...) with State
+//! Signalling Event (SSE) packets, carried in RTP next to the audio. This
+//! is how a call that started as voice moves to VBD or modem relay, the way
+//! a re-INVITE moves a FAX call to T.38.
+//!
+//! [`SseEvent`] is the packet payload; [`V150Sse`] wraps spandsp's SSE
+//! engine, which repeats the packets it sends for loss resilience and
+//! keeps track of what the far end last signalled.
+//!
+//! ```no_run
+//! use spandsp::v150_1_sse::{MediaState, ReasonCode, V150Sse};
+//!
+//! let mut sse = V150Sse::new(|pkt: &[u8], repeat: bool| {
+//!     // send `pkt` as an RTP SSE packet
+//! #   let _ = (pkt, repeat);
+//! })
+//! .unwrap();
+//! sse.send(MediaState::ModemRelay, ReasonCode::V8_CM, 0).unwrap();
+//! sse.poll(20_000); // from a microsecond clock, so repeats go out
+//! ```
+
+extern crate spandsp_sys;
+
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// A media state signalled by an SSE (V.150.1 Table 32).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MediaState {
+    /// The audio the call started with.
+    InitialAudio,
+    /// Voice band data: modem audio carried by a clear-channel codec.
+    VoiceBandData,
+    /// Modem relay: the modems are terminated and their data sent over
+    /// [SPRT](crate::sprt).
+    ModemRelay,
+    /// FAX relay, i.e. T.38.
+    FaxRelay,
+    /// Text telephone relay.
+    TextRelay,
+    /// Probing for a text telephone.
+    TextProbe,
+    /// A code this crate does not know.
+    Reserved(u8),
+}
+
+impl MediaState {
+    /// The 7-bit event code.
+    pub fn code(self) -> u8 {
+        match self {
+            Self::InitialAudio => 0,
+            Self::VoiceBandData => 1,
+            Self::ModemRelay => 2,
+            Self::FaxRelay => 3,
+            Self::TextRelay => 4,
+            Self::TextProbe => 5,
+            Self::Reserved(code) => code & 0x7F,
+        }
+    }
+
+    /// The state for a 7-bit event code.
+    pub fn from_code(code: u8) -> Self {
+        match code & 0x7F {
+            0 => Self::InitialAudio,
+            1 => Self::VoiceBandData,
+            2 => Self::ModemRelay,
+            3 => Self::FaxRelay,
+            4 => Self::TextRelay,
+            5 => Self::TextProbe,
+            code => Self::Reserved(code),
+        }
+    }
+}
+
+impl fmt::Display for MediaState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InitialAudio => f.write_str("initial audio"),
+            Self::VoiceBandData => f.write_str("VBD"),
+            Self::ModemRelay => f.write_str("modem relay"),
+            Self::FaxRelay => f.write_str("FAX relay"),
+            Self::TextRelay => f.write_str("text relay"),
+            Self::TextProbe => f.write_str("text probe"),
+            Self::Reserved(code) => write!(f, "reserved ({code})"),
+        }
+    }
+}
+
+/// Why a state change was signalled: the reason identifier code (RIC) of
+/// V.150.1 Table 33.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReasonCode(pub u8);
+
+impl ReasonCode {
+    /// No particular reason.
+    pub const NULL: Self = Self(0);
+    /// A V.8 CM signal was detected.
+    pub const V8_CM: Self = Self(1);
+    /// A V.8 JM signal was detected.
+    pub const V8_JM: Self = Self(2);
+    /// V.32bis AA.
+    pub const V32BIS_AA: Self = Self(3);
+    /// V.32bis AC.
+    pub const V32BIS_AC: Self = Self(4);
+    /// V.22bis unscrambled binary ones.
+    pub const V22BIS_USB1: Self = Self(5);
+    /// V.22bis scrambled binary ones.
+    pub const V22BIS_SB1: Self = Self(6);
+    /// V.22bis S1.
+    pub const V22BIS_S1: Self = Self(7);
+    /// V.21 channel 2 carrier.
+    pub const V21_CH2: Self = Self(8);
+    /// V.21 channel 1 carrier.
+    pub const V21_CH1: Self = Self(9);
+    /// V.23 forward channel.
+    pub const V23_HIGH_CHANNEL: Self = Self(10);
+    /// V.23 backward channel.
+    pub const V23_LOW_CHANNEL: Self = Self(11);
+    /// A 2225 Hz answer tone (Bell 103).
+    pub const TONE_2225HZ: Self = Self(12);
+    /// V.21 channel 2 HDLC flags, i.e. a FAX preamble.
+    pub const V21_CH2_HDLC_FLAGS: Self = Self(13);
+    /// An indeterminate signal.
+    pub const INDETERMINATE_SIGNAL: Self = Self(14);
+    /// Silence.
+    pub const SILENCE: Self = Self(15);
+    /// FAX CNG.
+    pub const CNG: Self = Self(16);
+    /// Voice.
+    pub const VOICE: Self = Self(17);
+    /// A timer ran out.
+    pub const TIMEOUT: Self = Self(18);
+    /// A physical layer state transition.
+    pub const P_STATE_TRANSITION: Self = Self(19);
+    /// Call cleardown.
+    pub const CLEARDOWN: Self = Self(20);
+    /// ANS or FAX CED.
+    pub const ANS_CED: Self = Self(21);
+    /// ANSam.
+    pub const ANSAM: Self = Self(22);
+    /// ANS with phase reversals.
+    pub const ANS_PR: Self = Self(23);
+    /// ANSam with phase reversals.
+    pub const ANSAM_PR: Self = Self(24);
+}
+
+/// An SSE payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SseEvent {
+    /// The state the sender has moved to.
+    pub state: MediaState,
+    /// The F bit: the receiver must answer with its own state.
+    pub force_response: bool,
+    /// Why the sender moved.
+    pub reason: ReasonCode,
+    /// Reason-specific information.
+    pub reason_info: u16,
+}
+
+impl SseEvent {
+    /// The 4-byte wire form.
+    pub fn to_payload(self) -> [u8; 4] {
+        let [hi, lo] = self.reason_info.to_be_bytes();
+        [
+            (self.state.code() << 1) | u8::from(self.force_response),
+            self.reason.0,
+            hi,
+            lo,
+        ]
+    }
+
+    /// Parse a payload, ignoring any extension that follows the first four
+    /// bytes.
+    pub fn from_payload(payload: &[u8]) -> Option<Self> {
+        let &[event, ric, hi, lo, ..] = payload else {
+            return None;
+        };
+        Some(Self {
+            state: MediaState::from_code(event >> 1),
+            force_response: event & 1 != 0,
+            reason: ReasonCode(ric),
+            reason_info: u16::from_be_bytes([hi, lo]),
+        })
+    }
+}
+
+type SsePacketCallback = Box<dyn FnMut(&[u8], bool) + Send>;
+
+/// What the C callbacks reach through their `user_data`.
+struct SseShared {
+    tx: SsePacketCallback,
+    /// The caller's clock, as of the last [`V150Sse::poll`].
+    now: u64,
+    /// When spandsp next wants [`v150_1_sse_timer_expired`] called.
+    ///
+    /// [`v150_1_sse_timer_expired`]: spandsp_sys::v150_1_sse_timer_expired
+    deadline: Option<u64>,
+}
+
+/// Trampoline for `v150_1_sse_packet_handler_t`.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SseShared`.
+unsafe extern "C" fn sse_packet_trampoline(
+    user_data: *mut c_void,
+    repeat: bool,
+    pkt: *const u8,
+    len: c_int,
+) -> c_int {
+    unsafe {
+        if user_data.is_null() || pkt.is_null() || len <= 0 {
+            return -1;
+        }
+        let shared = &mut *(user_data as *mut SseShared);
+        (shared.tx)(std::slice::from_raw_parts(pkt, len as usize), repeat);
+        0
+    }
+}
+
+/// Trampoline for `v150_1_sse_status_handler_t`. State changes are taken
+/// from the parsed packets instead, so this only acknowledges.
+unsafe extern "C" fn sse_status_trampoline(_user_data: *mut c_void, _status: c_int) -> c_int {
+    0
+}
+
+/// Trampoline for `v150_1_sse_timer_handler_t`: records the requested
+/// deadline (zero cancels it) and answers with the current time.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `SseShared`.
+unsafe extern "C" fn sse_timer_trampoline(
+    user_data: *mut c_void,
+    timeout: spandsp_sys::span_timestamp_t,
+) -> spandsp_sys::span_timestamp_t {
+    unsafe {
+        if user_data.is_null() {
+            return 0;
+        }
+        let shared = &mut *(user_data as *mut SseShared);
+        shared.deadline = (timeout > 0).then_some(timeout as u64);
+        shared.now as spandsp_sys::span_timestamp_t
+    }
+}
+
+/// RAII wrapper around `v150_1_sse_state_t`.
+///
+/// Created via `V150Sse::new()`, which calls `v150_1_sse_init(NULL, ...)`.
+/// Freed on drop via `v150_1_sse_free`.
+///
+/// Times are microseconds on the caller's monotonic clock, handed in through
+/// [`poll`](Self::poll).
+pub struct V150Sse {
+    ptr: NonNull<spandsp_sys::v150_1_sse_state_t>,
+    shared: Box<SseShared>,
+    local: MediaState,
+    remote: MediaState,
+}
+
+impl V150Sse {
+    /// Create an SSE engine in the initial audio state. `tx_packet` is
+    /// called with each SSE payload to send, and whether it repeats an
+    /// earlier one.
+    pub fn new<F>(tx_packet: F) -> Result<Self>
+    where
+        F: FnMut(&[u8], bool) + Send + 'static,
+    {
+        let mut shared = Box::new(SseShared {
+            tx: Box::new(tx_packet),
+            now: 0,
+            deadline: None,
+        });
+        let user_data = &mut *shared as *mut SseShared as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::v150_1_sse_init(
+                std::ptr::null_mut(),
+                Some(sse_packet_trampoline),
+                user_data,
+                Some(sse_status_trampoline),
+                user_data,
+                Some(sse_timer_trampoline),
+                user_data,
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            shared,
+            local: MediaState::InitialAudio,
+            remote: MediaState::InitialAudio,
+        })
+    }
+
+    /// Move to `state` and tell the far end, with `reason` and its
+    /// `reason_info`.
+    pub fn send(&mut self, state: MediaState, reason: ReasonCode, reason_info: u16) -> Result<()> {
+        let rc = unsafe {
+            spandsp_sys::v150_1_sse_tx_packet(
+                self.ptr.as_ptr(),
+                c_int::from(state.code()),
+                c_int::from(reason.0),
+                c_int::from(reason_info),
+            )
+        };
+        if rc < 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        self.local = state;
+        Ok(())
+    }
+
+    /// Handle a received SSE packet with its RTP sequence number and
+    /// timestamp.
+    ///
+    /// Returns the event if it moves the far end to a new state; repeats
+    /// and restatements of the current state give `None`.
+    pub fn rx_packet(
+        &mut self,
+        seq_no: u16,
+        timestamp: u32,
+        pkt: &[u8],
+    ) -> Result<Option<SseEvent>> {
+        let event = SseEvent::from_payload(pkt).ok_or_else(|| {
+            SpanDspError::InvalidInput(format!("SSE payload too short: {} bytes", pkt.len()))
+        })?;
+        let len = pkt.len().min(c_int::MAX as usize) as c_int;
+        let rc = unsafe {
+            spandsp_sys::v150_1_sse_rx_packet(
+                self.ptr.as_ptr(),
+                seq_no,
+                timestamp,
+                pkt.as_ptr(),
+                len,
+            )
+        };
+        if rc < 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        if event.state == self.remote {
+            return Ok(None);
+        }
+        self.remote = event.state;
+        Ok(Some(event))
+    }
+
+    /// Advance the clock to `now`, sending any repeats that have come due.
+    pub fn poll(&mut self, now: u64) {
+        self.shared.now = now;
+        if self.shared.deadline.is_some_and(|deadline| deadline <= now) {
+            self.shared.deadline = None;
+            unsafe {
+                spandsp_sys::v150_1_sse_timer_expired(
+                    self.ptr.as_ptr(),
+                    now as spandsp_sys::span_timestamp_t,
+                );
+            }
+        }
+    }
+
+    /// When [`poll`](Self::poll) next has work to do, if ever.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.shared.deadline
+    }
+
+    /// The state last sent.
+    pub fn local_state(&self) -> MediaState {
+        self.local
+    }
+
+    /// The state the far end last signalled.
+    pub fn remote_state(&self) -> MediaState {
+        self.remote
+    }
+
+    /// Return the raw pointer to the underlying state.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::v150_1_sse_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for V150Sse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V150Sse")
+            .field("local", &self.local)
+            .field("remote", &self.remote)
+            .field("deadline", &self.shared.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for V150Sse {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::v150_1_sse_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: V150Sse wraps a SpanDSP v150_1_sse_state_t that is only accessed
+// through &self/&mut self methods, and its packet callback is `Send`.
+unsafe impl Send for V150Sse {}
@@ -0,0 +1,331 @@
+//! Safe wrapper around spandsp's V.18 textphone.
+//!
+//! `V18` wraps `v18_state_t`: one end of a text telephone (TTY/TDD) call in
+//! any of the modes V.18 brings together, from US Baudot at 45.45 baud to
+//! the European EDT and DTMF textphones. Given a nation, it starts in V.18
+//! and probes for whichever of that nation's modes the far end speaks, so a
+//! gateway can answer textphones without knowing their kind in advance.
+//!
+//! ```no_run
+//! use spandsp::v18::{V18, V18Nation};
+//!
+//! let mut tty = V18::automoding(V18Nation::Usa, false).unwrap();
+//! tty.put("HELLO GA").unwrap();
+//! let (rx, mut tx) = ([0i16; 160], [0i16; 160]);
+//! tty.rx(&rx);
+//! tty.generate(&mut tx);
+//! print!("{}", tty.get_text());
+//! ```
+
+extern crate spandsp_sys;
+
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr::NonNull;
+
+use crate::error::{Result, SpanDspError};
+
+/// A textphone mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum V18Mode {
+    /// Annex A: Weitbrecht Baudot at 45.45 baud, the US TTY.
+    Weitbrecht4545,
+    /// Annex A: Weitbrecht Baudot at 50 baud, the UK and Australian TTY.
+    Weitbrecht50,
+    /// Annex A: Weitbrecht Baudot at 47.6 baud, used while probing.
+    Weitbrecht476,
+    /// Annex B: ASCII as DTMF, as in Denmark and the Netherlands.
+    Dtmf,
+    /// Annex C: European Deaf Telephone, 110 bit/s V.21, as in Germany,
+    /// Austria and Switzerland.
+    Edt,
+    /// Annex D: 300 bit/s Bell 103 ASCII, in the US.
+    Bell103,
+    /// Annex E: 1200 bit/s V.23 Videotex, in France.
+    V23Videotex,
+    /// Annex F: 300 bit/s V.21 ASCII, in Sweden, Norway and Finland.
+    V21Textphone,
+    /// Annex G: V.18 proper.
+    V18Textphone,
+}
+
+impl V18Mode {
+    const ALL: [V18Mode; 9] = [
+        V18Mode::Weitbrecht4545,
+        V18Mode::Weitbrecht50,
+        V18Mode::Weitbrecht476,
+        V18Mode::Dtmf,
+        V18Mode::Edt,
+        V18Mode::Bell103,
+        V18Mode::V23Videotex,
+        V18Mode::V21Textphone,
+        V18Mode::V18Textphone,
+    ];
+
+    fn as_raw(self) -> c_int {
+        (match self {
+            V18Mode::Weitbrecht4545 => spandsp_sys::V18_MODE_WEITBRECHT_5BIT_4545,
+            V18Mode::Weitbrecht50 => spandsp_sys::V18_MODE_WEITBRECHT_5BIT_50,
+            V18Mode::Weitbrecht476 => spandsp_sys::V18_MODE_WEITBRECHT_5BIT_476,
+            V18Mode::Dtmf => spandsp_sys::V18_MODE_DTMF,
+            V18Mode::Edt => spandsp_sys::V18_MODE_EDT,
+            V18Mode::Bell103 => spandsp_sys::V18_MODE_BELL103,
+            V18Mode::V23Videotex => spandsp_sys::V18_MODE_V23VIDEOTEX,
+            V18Mode::V21Textphone => spandsp_sys::V18_MODE_V21TEXTPHONE,
+            V18Mode::V18Textphone => spandsp_sys::V18_MODE_V18TEXTPHONE,
+        }) as c_int
+    }
+
+    fn from_raw(mode: c_int) -> Option<Self> {
+        // Drop option flags such as repetitive shifts.
+        let mode = mode & !(spandsp_sys::V18_MODE_REPETITIVE_SHIFTS_OPTION as c_int);
+        Self::ALL.into_iter().find(|m| m.as_raw() == mode)
+    }
+}
+
+impl fmt::Display for V18Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = unsafe { spandsp_sys::v18_mode_to_str(self.as_raw()) };
+        if name.is_null() {
+            return write!(f, "{self:?}");
+        }
+        f.write_str(&unsafe { CStr::from_ptr(name) }.to_string_lossy())
+    }
+}
+
+/// Which nation's textphones to probe for when automoding.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum V18Nation {
+    /// Any, in V.18's global probing order.
+    #[default]
+    Global,
+    Usa,
+    Australia,
+    Ireland,
+    Germany,
+    Switzerland,
+    Italy,
+    Spain,
+    Austria,
+    Netherlands,
+    Iceland,
+    Norway,
+    Sweden,
+    Finland,
+    Denmark,
+    Uk,
+    France,
+}
+
+impl V18Nation {
+    fn as_raw(self) -> c_int {
+        (match self {
+            V18Nation::Global => spandsp_sys::V18_AUTOMODING_GLOBAL,
+            V18Nation::Usa => spandsp_sys::V18_AUTOMODING_USA,
+            V18Nation::Australia => spandsp_sys::V18_AUTOMODING_AUSTRALIA,
+            V18Nation::Ireland => spandsp_sys::V18_AUTOMODING_IRELAND,
+            V18Nation::Germany => spandsp_sys::V18_AUTOMODING_GERMANY,
+            V18Nation::Switzerland => spandsp_sys::V18_AUTOMODING_SWITZERLAND,
+            V18Nation::Italy => spandsp_sys::V18_AUTOMODING_ITALY,
+            V18Nation::Spain => spandsp_sys::V18_AUTOMODING_SPAIN,
+            V18Nation::Austria => spandsp_sys::V18_AUTOMODING_AUSTRIA,
+            V18Nation::Netherlands => spandsp_sys::V18_AUTOMODING_NETHERLANDS,
+            V18Nation::Iceland => spandsp_sys::V18_AUTOMODING_ICELAND,
+            V18Nation::Norway => spandsp_sys::V18_AUTOMODING_NORWAY,
+            V18Nation::Sweden => spandsp_sys::V18_AUTOMODING_SWEDEN,
+            // (sic) spandsp spells it this way.
+            V18Nation::Finland => spandsp_sys::V18_AUTOMODING_FINALND,
+            V18Nation::Denmark => spandsp_sys::V18_AUTOMODING_DENMARK,
+            V18Nation::Uk => spandsp_sys::V18_AUTOMODING_UK,
+            V18Nation::France => spandsp_sys::V18_AUTOMODING_FRANCE,
+        }) as c_int
+    }
+}
+
+type TextCallback = Box<dyn FnMut(&str) + Send>;
+
+/// Where received text goes.
+struct Received {
+    callback: Option<TextCallback>,
+    text: String,
+}
+
+/// Trampoline for received text.
+///
+/// # Safety
+///
+/// `user_data` must point to a valid `Received`.
+unsafe extern "C" fn v18_put_msg_trampoline(user_data: *mut c_void, msg: *const u8, len: c_int) {
+    unsafe {
+        // Negative lengths report status changes, not text.
+        if user_data.is_null() || msg.is_null() || len <= 0 {
+            return;
+        }
+        let received = &mut *(user_data as *mut Received);
+        let text = String::from_utf8_lossy(std::slice::from_raw_parts(msg, len as usize));
+        match &mut received.callback {
+            Some(callback) => callback(&text),
+            None => received.text.push_str(&text),
+        }
+    }
+}
+
+/// RAII wrapper around `v18_state_t`.
+///
+/// Created via `V18::new()`, `V18::automoding()` or `V18::with_callback()`.
+/// Freed on drop via `v18_free`.
+pub struct V18 {
+    ptr: NonNull<spandsp_sys::v18_state_t>,
+    calling_party: bool,
+    nation: V18Nation,
+    received: Box<Received>,
+}
+
+impl V18 {
+    /// Create a textphone fixed in `mode`. Received text is held for
+    /// [`get_text`](Self::get_text).
+    pub fn new(mode: V18Mode, calling_party: bool) -> Result<Self> {
+        Self::init(mode, V18Nation::Global, calling_party, None)
+    }
+
+    /// Create a textphone that starts in V.18 and falls back to whichever
+    /// of `nation`'s modes the far end answers in. Received text is held
+    /// for [`get_text`](Self::get_text).
+    pub fn automoding(nation: V18Nation, calling_party: bool) -> Result<Self> {
+        Self::init(V18Mode::V18Textphone, nation, calling_party, None)
+    }
+
+    /// Create a textphone handing received text to `callback` as it
+    /// arrives. [`V18Mode::V18Textphone`] automodes over `nation`'s modes.
+    pub fn with_callback<F>(
+        mode: V18Mode,
+        nation: V18Nation,
+        calling_party: bool,
+        callback: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        Self::init(mode, nation, calling_party, Some(Box::new(callback)))
+    }
+
+    fn init(
+        mode: V18Mode,
+        nation: V18Nation,
+        calling_party: bool,
+        callback: Option<TextCallback>,
+    ) -> Result<Self> {
+        let mut received = Box::new(Received {
+            callback,
+            text: String::new(),
+        });
+        let user_data = &mut *received as *mut Received as *mut c_void;
+        let ptr = unsafe {
+            spandsp_sys::v18_init(
+                std::ptr::null_mut(),
+                calling_party,
+                mode.as_raw(),
+                nation.as_raw(),
+                Some(v18_put_msg_trampoline),
+                user_data,
+                None,
+                std::ptr::null_mut(),
+            )
+        };
+        let ptr = NonNull::new(ptr).ok_or(SpanDspError::InitFailed)?;
+        Ok(Self {
+            ptr,
+            calling_party,
+            nation,
+            received,
+        })
+    }
+
+    /// Queue text to send.
+    ///
+    /// Baudot modes carry upper case letters, digits and some punctuation;
+    /// spandsp folds or drops the rest. Returns the number of characters
+    /// queued, which may be fewer than given if the queue is full.
+    pub fn put(&mut self, text: &str) -> Result<usize> {
+        if !text.is_ascii() {
+            return Err(SpanDspError::InvalidInput(
+                "textphones only carry ASCII".into(),
+            ));
+        }
+        let len = text.len().min(c_int::MAX as usize) as c_int;
+        let rc =
+            unsafe { spandsp_sys::v18_put(self.ptr.as_ptr(), text.as_ptr() as *const c_char, len) };
+        if rc < 0 {
+            return Err(SpanDspError::ErrorCode(rc));
+        }
+        Ok(rc as usize)
+    }
+
+    /// Take the text received since the last call. Always empty for a
+    /// textphone created with a callback.
+    pub fn get_text(&mut self) -> String {
+        std::mem::take(&mut self.received.text)
+    }
+
+    /// Generate audio.
+    ///
+    /// Returns the number of samples generated.
+    pub fn generate(&mut self, amp: &mut [i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::v18_tx(self.ptr.as_ptr(), amp.as_mut_ptr(), len).max(0) as usize }
+    }
+
+    /// Feed received audio.
+    ///
+    /// Returns the number of unprocessed samples (normally 0).
+    pub fn rx(&mut self, amp: &[i16]) -> usize {
+        let len = amp.len().min(c_int::MAX as usize) as c_int;
+        unsafe { spandsp_sys::v18_rx(self.ptr.as_ptr(), amp.as_ptr(), len).max(0) as usize }
+    }
+
+    /// The mode in use, once one has been settled on.
+    pub fn mode(&self) -> Option<V18Mode> {
+        V18Mode::from_raw(unsafe { spandsp_sys::v18_get_current_mode(self.ptr.as_ptr()) })
+    }
+
+    /// Whether this end placed the call.
+    pub fn is_calling_party(&self) -> bool {
+        self.calling_party
+    }
+
+    /// Returns the nation whose modes are probed for.
+    pub fn nation(&self) -> V18Nation {
+        self.nation
+    }
+
+    /// Return the raw pointer.
+    pub fn as_ptr(&self) -> *mut spandsp_sys::v18_state_t {
+        self.ptr.as_ptr()
+    }
+}
+
+impl fmt::Debug for V18 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V18")
+            .field("mode", &self.mode())
+            .field("nation", &self.nation)
+            .field("calling_party", &self.calling_party)
+            .field("has_callback", &self.received.callback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for V18 {
+    fn drop(&mut self) {
+        unsafe {
+            spandsp_sys::v18_free(self.ptr.as_ptr());
+        }
+    }
+}
+
+// SAFETY: V18 wraps a SpanDSP v18_state_t that is only accessed through
+// &self/&mut self methods, and its text callback is Send.
+unsafe impl Send for V18 {}
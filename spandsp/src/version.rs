@@ -0,0 +1,124 @@
+//! The linked spandsp's version and optional parts.
+//!
+//! `spandsp-sys` builds spandsp from source, and which optional modems and
+//! modules go in depends on its cargo features, which any crate in the
+//! dependency graph can switch on. [`capabilities`] reports what the
+//! library actually contains, so an application can offer V.34 only where
+//! it exists. The wrappers check the same thing: asking for a modem that
+//! is not compiled in is an error rather than a failed negotiation.
+//!
+//! ```
+//! let version = spandsp::version::version();
+//! assert!(version >= spandsp::version::Version::new(3, 0, 0));
+//! println!("spandsp {version}: {}", spandsp::version::capabilities());
+//! ```
+
+use std::fmt;
+
+use crate::error::{Result, SpanDspError};
+
+/// A spandsp release number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Version {
+    /// Major version.
+    pub major: u16,
+    /// Minor version.
+    pub minor: u16,
+    /// Patch version.
+    pub patch: u16,
+}
+
+impl Version {
+    /// The version `major.minor.patch`.
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse `major.minor.patch`; a missing patch or minor is 0.
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || SpanDspError::InvalidInput(format!("invalid version {s:?}"));
+        let mut parts = s.trim().split('.');
+        let mut next = |required: bool| match parts.next() {
+            Some(part) => part.parse::<u16>().map_err(|_| invalid()),
+            None if required => Err(invalid()),
+            None => Ok(0),
+        };
+        let version = Self::new(next(true)?, next(false)?, next(false)?);
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(version),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The version of the linked spandsp.
+pub fn version() -> Version {
+    Version::parse(spandsp_sys::SPANDSP_VERSION).expect("spandsp-sys records a valid version")
+}
+
+/// When the linked spandsp was built, as `YYYYMMDD HHMMSS` in UTC.
+///
+/// This is the build time `spandsp-sys` stamps into `version.h`, not the
+/// release date of the spandsp source; use [`version`] to tell releases
+/// apart.
+pub fn build_datetime() -> &'static str {
+    spandsp_sys::SPANDSP_BUILD_DATETIME
+}
+
+/// Optional parts of the linked spandsp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// T.30, T.38 and T.4 fax.
+    pub fax: bool,
+    /// The V.32bis modem.
+    pub v32bis: bool,
+    /// The V.34 modem, and with it V.34 half-duplex fax.
+    pub v34: bool,
+    /// SSL fax.
+    pub ssl_fax: bool,
+    /// The hooks the ITU test sequences need.
+    pub conformance: bool,
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let present: Vec<&str> = [
+            (self.fax, "fax"),
+            (self.v32bis, "V.32bis"),
+            (self.v34, "V.34"),
+            (self.ssl_fax, "SSL fax"),
+            (self.conformance, "conformance"),
+        ]
+        .into_iter()
+        .filter_map(|(present, name)| present.then_some(name))
+        .collect();
+        if present.is_empty() {
+            f.write_str("core only")
+        } else {
+            f.write_str(&present.join(", "))
+        }
+    }
+}
+
+/// What the linked spandsp was built with.
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        fax: spandsp_sys::HAVE_FAX,
+        v32bis: spandsp_sys::HAVE_V32BIS,
+        v34: spandsp_sys::HAVE_V34,
+        ssl_fax: spandsp_sys::HAVE_SSL_FAX,
+        conformance: spandsp_sys::HAVE_CONFORMANCE,
+    }
+}
@@ -1,3 +1,5 @@
+use spandsp::quality::{best_correlation, correlation, rms};
+
 /// Generate a sine wave at the given frequency and sample rate.
 fn sine_wave(freq_hz: f32, sample_rate: f32, num_samples: usize, amplitude: f32) -> Vec<i16> {
     (0..num_samples)
@@ -8,34 +10,6 @@ fn sine_wave(freq_hz: f32, sample_rate: f32, num_samples: usize, amplitude: f32)
         .collect()
 }
 
-/// Compute the Pearson correlation coefficient between two i16 slices.
-fn correlation(a: &[i16], b: &[i16]) -> f64 {
-    assert_eq!(a.len(), b.len());
-    let n = a.len() as f64;
-    let mean_a = a.iter().map(|&x| x as f64).sum::<f64>() / n;
-    let mean_b = b.iter().map(|&x| x as f64).sum::<f64>() / n;
-    let mut cov = 0.0;
-    let mut var_a = 0.0;
-    let mut var_b = 0.0;
-    for i in 0..a.len() {
-        let da = a[i] as f64 - mean_a;
-        let db = b[i] as f64 - mean_b;
-        cov += da * db;
-        var_a += da * da;
-        var_b += db * db;
-    }
-    if var_a == 0.0 || var_b == 0.0 {
-        return 0.0;
-    }
-    cov / (var_a.sqrt() * var_b.sqrt())
-}
-
-/// Compute the RMS power of a signal in linear units.
-fn rms_power(samples: &[i16]) -> f64 {
-    let sum_sq: f64 = samples.iter().map(|&s| (s as f64) * (s as f64)).sum();
-    (sum_sq / samples.len() as f64).sqrt()
-}
-
 // =========================================================================
 // G.711
 // =========================================================================
@@ -185,6 +159,59 @@ mod g711 {
             "A-law sine should be symmetric: sample[1]={lin1}, sample[5]={lin5}"
         );
     }
+
+    #[test]
+    fn frame_api_matches_slice_api() {
+        let amp: [i16; 160] = sine_wave(1000.0, 8000.0, 160, 20000.0).try_into().unwrap();
+        for mode in [G711Mode::ALaw, G711Mode::ULaw] {
+            let mut state = G711State::new(mode).unwrap();
+            let frame = state.encode_frame(&amp);
+            let mut encoded = [0u8; 160];
+            assert_eq!(state.encode(&mut encoded, &amp), 160);
+            assert_eq!(frame, encoded, "{mode}");
+
+            let mut decoded = [0i16; 160];
+            state.decode(&mut decoded, &encoded);
+            assert_eq!(state.decode_frame(&frame), decoded, "{mode}");
+        }
+    }
+
+    #[test]
+    fn known_answers_and_ffi_agree() {
+        assert_eq!(ULAW_TO_LINEAR[0x00], -32124);
+        assert_eq!(ULAW_TO_LINEAR[0xFF], 0);
+        assert_eq!(ALAW_TO_LINEAR[0xD5], 8);
+        assert_eq!(ALAW_TO_LINEAR[0x2A], -32256);
+        verify_against_ffi().unwrap();
+    }
+
+    #[test]
+    fn lost_frames_are_concealed() {
+        let mut decoder = G711State::new(G711Mode::ULaw)
+            .unwrap()
+            .with_plc(160)
+            .unwrap();
+        let tone = sine_wave(400.0, 8000.0, 160 * 5, 8000.0);
+        let mut amp = [0i16; 160];
+        for frame in tone.chunks(160) {
+            let encoded: Vec<u8> = frame.iter().map(|&s| linear_to_ulaw(s)).collect();
+            assert_eq!(decoder.decode_frame(Some(&encoded), &mut amp), 160);
+        }
+
+        // A lost frame carries the tone on rather than dropping to silence.
+        assert_eq!(decoder.decode_frame(None, &mut amp), 160);
+        assert!(rms(&amp) > 1000.0);
+        assert_eq!(decoder.decode_frame(None, &mut amp[..80]), 80);
+        assert_eq!(decoder.lost_frames(), 2);
+        assert_eq!(decoder.consecutive_lost_frames(), 2);
+
+        assert_eq!(decoder.decode_frame(Some(&[0xFF; 160]), &mut amp), 160);
+        assert_eq!(decoder.consecutive_lost_frames(), 0);
+        assert!(matches!(
+            G711State::new(G711Mode::ALaw).unwrap().with_plc(0),
+            Err(spandsp::error::SpanDspError::InvalidInput(_))
+        ));
+    }
 }
 
 // =========================================================================
@@ -268,6 +295,27 @@ mod g722 {
         }
     }
 
+    #[test]
+    fn flush_completes_packed_bytes() {
+        let amp = sine_wave(1000.0, 16000.0, 6, 8000.0);
+        // Three 6-bit codes leave 2 bits pending; one more code fills the byte.
+        let mut encoder = G722Encoder::new(G722Rate::Rate48000, G722Options::PACKED).unwrap();
+        let mut out = [0u8; 8];
+        assert_eq!(encoder.encode(&mut out, &amp), 2);
+        assert_eq!(encoder.pending_bits(), 2);
+        assert!(encoder.flush(&mut []).is_err());
+        assert_eq!(encoder.flush(&mut out).unwrap(), 1);
+        assert_eq!(encoder.pending_bits(), 0);
+        assert_eq!(encoder.flush(&mut out).unwrap(), 0);
+
+        // Unpacked, every code is a byte of its own.
+        let mut encoder = G722Encoder::new(G722Rate::Rate48000, G722Options::empty()).unwrap();
+        assert_eq!(encoder.encode(&mut out, &amp), 3);
+        assert_eq!(encoder.pending_bits(), 0);
+        assert_eq!(encoder.flush(&mut out).unwrap(), 0);
+        assert_eq!(G722Rate::Rate56000.bits_per_code(), 7);
+    }
+
     #[test]
     fn rate_enum() {
         assert!(G722Rate::try_from(64000u32).is_ok());
@@ -277,6 +325,67 @@ mod g722 {
     }
 }
 
+// =========================================================================
+// ITU conformance sequences
+// =========================================================================
+#[cfg(feature = "conformance")]
+mod conformance {
+    use spandsp::conformance::*;
+    use spandsp::error::SpanDspError;
+
+    #[test]
+    fn missing_sequences_are_an_error() {
+        let dir = std::env::temp_dir().join(format!("spandsp-itu-empty-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let g722_err = g722(&dir).unwrap_err();
+        let g726_err = g726(&dir).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        for (err, file) in [(g722_err, "T1C1.XMT"), (g726_err, "RN16FM.I")] {
+            match err {
+                SpanDspError::InvalidInput(msg) => assert!(msg.contains(file), "{msg}"),
+                other => panic!("unexpected error {other:?}"),
+            }
+        }
+    }
+
+    /// Runs only when `SPANDSP_ITU_G722_DIR` names a directory holding the
+    /// G.722 test sequences.
+    #[test]
+    fn g722_itu_sequences() {
+        let Ok(dir) = std::env::var("SPANDSP_ITU_G722_DIR") else {
+            return;
+        };
+        let report = g722(dir).unwrap();
+        assert_eq!(report.cases().len(), 11);
+        assert!(report.passed(), "{report}");
+    }
+
+    /// Runs only when `SPANDSP_ITU_G726_DIR` names a directory holding the
+    /// G.726 test sequences.
+    #[test]
+    fn g726_itu_sequences() {
+        let Ok(dir) = std::env::var("SPANDSP_ITU_G726_DIR") else {
+            return;
+        };
+        let report = g726(dir).unwrap();
+        assert_eq!(report.cases().len(), 56);
+        assert!(report.passed(), "{report}");
+    }
+
+    /// Runs only when `SPANDSP_ITU_G726_DIR` names a directory holding the
+    /// G.726 test sequences.
+    #[cfg(feature = "pure-g726")]
+    #[test]
+    fn g726_pure_itu_sequences() {
+        let Ok(dir) = std::env::var("SPANDSP_ITU_G726_DIR") else {
+            return;
+        };
+        let report = g726_pure(dir).unwrap();
+        assert_eq!(report.cases().len(), 56);
+        assert!(report.passed(), "{report}");
+    }
+}
+
 // =========================================================================
 // G.726
 // =========================================================================
@@ -285,6 +394,17 @@ mod g726 {
 
     use super::*;
 
+    #[test]
+    fn debug_shows_configuration() {
+        let state =
+            G726State::new(G726Rate::Rate24000, G726Encoding::ALaw, G726Packing::Left).unwrap();
+        assert_eq!(state.rate(), G726Rate::Rate24000);
+        let s = format!("{state:?}");
+        assert!(s.contains("Rate24000"), "{s}");
+        assert!(s.contains("ALaw"), "{s}");
+        assert!(s.contains("Left"), "{s}");
+    }
+
     #[test]
     fn roundtrip_silence_all_rates() {
         let rates = [
@@ -342,6 +462,618 @@ mod g726 {
             "G.726 32kbit/s roundtrip correlation too low: {corr}"
         );
     }
+
+    #[test]
+    fn flush_completes_packed_bytes() {
+        let amp = sine_wave(1000.0, 8000.0, 7, 8000.0);
+        for packing in [G726Packing::Left, G726Packing::Right] {
+            // Seven 3-bit codes leave 5 bits pending; one more code fills the
+            // byte.
+            let mut encoder =
+                G726State::new(G726Rate::Rate24000, G726Encoding::Linear, packing).unwrap();
+            let mut encoded = [0u8; 3];
+            assert_eq!(encoder.encode(&mut encoded, &amp), 2);
+            assert_eq!(encoder.pending_bits(), 5);
+            assert!(encoder.flush(&mut []).is_err());
+            assert_eq!(encoder.flush(&mut encoded[2..]).unwrap(), 1);
+            assert_eq!(encoder.pending_bits(), 0);
+
+            // The decoder gets 8 samples back, the last one silence.
+            let mut decoder =
+                G726State::new(G726Rate::Rate24000, G726Encoding::Linear, packing).unwrap();
+            let mut decoded = [0i16; 16];
+            assert_eq!(decoder.decode(&mut decoded, &encoded), 8, "{packing}");
+        }
+
+        let mut unpacked =
+            G726State::new(G726Rate::Rate24000, G726Encoding::ULaw, G726Packing::None).unwrap();
+        let mut out = [0u8; 8];
+        unpacked.encode(&mut out, &[0x7F7F; 3]);
+        assert_eq!(unpacked.pending_bits(), 0);
+        assert_eq!(unpacked.flush(&mut out).unwrap(), 0);
+    }
+}
+
+// =========================================================================
+// Pure-Rust G.726
+// =========================================================================
+#[cfg(feature = "pure-g726")]
+mod g726_pure {
+    use spandsp::g726::G726State as FfiG726State;
+    use spandsp::g726_pure::*;
+
+    use super::*;
+
+    const RATES: [G726Rate; 4] = [
+        G726Rate::Rate16000,
+        G726Rate::Rate24000,
+        G726Rate::Rate32000,
+        G726Rate::Rate40000,
+    ];
+
+    fn test_signal() -> Vec<i16> {
+        let mut signal = sine_wave(440.0, 8000.0, 400, 12000.0);
+        signal.extend(sine_wave(1800.0, 8000.0, 400, 3000.0));
+        signal.extend(std::iter::repeat_n(0, 80));
+        signal.extend(sine_wave(300.0, 8000.0, 400, 30000.0));
+        signal
+    }
+
+    #[test]
+    fn matches_ffi_linear_all_rates_and_packings() {
+        let signal = test_signal();
+        for rate in RATES {
+            for packing in [G726Packing::None, G726Packing::Left, G726Packing::Right] {
+                let mut ffi = FfiG726State::new(rate, G726Encoding::Linear, packing).unwrap();
+                let mut pure = G726State::new(rate, G726Encoding::Linear, packing).unwrap();
+
+                let mut ffi_code = vec![0u8; signal.len()];
+                let mut pure_code = vec![0u8; signal.len()];
+                let n = ffi.encode(&mut ffi_code, &signal);
+                assert_eq!(pure.encode(&mut pure_code, &signal), n, "{rate} {packing}");
+                assert_eq!(pure_code[..n], ffi_code[..n], "{rate} {packing}");
+
+                let mut ffi_out = vec![0i16; signal.len()];
+                let mut pure_out = vec![0i16; signal.len()];
+                let m = ffi.decode(&mut ffi_out, &ffi_code[..n]);
+                assert_eq!(
+                    pure.decode(&mut pure_out, &ffi_code[..n]),
+                    m,
+                    "{rate} {packing}"
+                );
+                assert_eq!(pure_out[..m], ffi_out[..m], "{rate} {packing}");
+            }
+        }
+    }
+
+    #[test]
+    fn matches_ffi_g711_interworking() {
+        let signal = test_signal();
+        for encoding in [G726Encoding::ALaw, G726Encoding::ULaw] {
+            // G.711 samples travel one per byte in the i16 buffer.
+            let bytes: Vec<u8> = signal
+                .iter()
+                .map(|&s| match encoding {
+                    G726Encoding::ALaw => spandsp::g711::linear_to_alaw(s),
+                    _ => spandsp::g711::linear_to_ulaw(s),
+                })
+                .collect();
+            let input: Vec<i16> = bytes
+                .chunks(2)
+                .map(|c| i16::from_ne_bytes([c[0], *c.get(1).unwrap_or(&0)]))
+                .collect();
+            for rate in RATES {
+                let mut ffi = FfiG726State::new(rate, encoding, G726Packing::None).unwrap();
+                let mut pure = G726State::new(rate, encoding, G726Packing::None).unwrap();
+
+                let mut ffi_code = vec![0u8; bytes.len()];
+                let mut pure_code = vec![0u8; bytes.len()];
+                let n = ffi.encode(&mut ffi_code, &input);
+                assert_eq!(pure.encode(&mut pure_code, &input), n, "{rate} {encoding}");
+                assert_eq!(pure_code[..n], ffi_code[..n], "{rate} {encoding}");
+
+                let mut ffi_out = vec![0i16; bytes.len()];
+                let mut pure_out = vec![0i16; bytes.len()];
+                let m = ffi.decode(&mut ffi_out, &ffi_code[..n]);
+                assert_eq!(
+                    pure.decode(&mut pure_out, &ffi_code[..n]),
+                    m,
+                    "{rate} {encoding}"
+                );
+                assert_eq!(pure_out, ffi_out, "{rate} {encoding}");
+            }
+        }
+    }
+
+    #[test]
+    fn flush_matches_ffi() {
+        let signal = test_signal();
+        for rate in RATES {
+            for packing in [G726Packing::Left, G726Packing::Right] {
+                let mut ffi = FfiG726State::new(rate, G726Encoding::ALaw, packing).unwrap();
+                let mut pure = G726State::new(rate, G726Encoding::ALaw, packing).unwrap();
+                let mut ffi_code = vec![0u8; signal.len()];
+                let mut pure_code = vec![0u8; signal.len()];
+                let n = ffi.encode(&mut ffi_code, &signal[..101]);
+                assert_eq!(pure.encode(&mut pure_code, &signal[..101]), n);
+                assert_eq!(pure.pending_bits(), ffi.pending_bits(), "{rate} {packing}");
+                let m = ffi.flush(&mut ffi_code[n..]).unwrap();
+                assert_eq!(
+                    pure.flush(&mut pure_code[n..]).unwrap(),
+                    m,
+                    "{rate} {packing}"
+                );
+                assert_eq!(pure_code[..n + m], ffi_code[..n + m], "{rate} {packing}");
+                assert_eq!(pure.pending_bits(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn output_is_bounded_by_buffers() {
+        let mut state =
+            G726State::new(G726Rate::Rate32000, G726Encoding::Linear, G726Packing::None).unwrap();
+        let signal = sine_wave(1000.0, 8000.0, 160, 8000.0);
+        let mut code = [0u8; 40];
+        assert_eq!(state.encode(&mut code, &signal), 40);
+        let mut out = [0i16; 10];
+        assert_eq!(state.decode(&mut out, &code), 10);
+    }
+}
+
+// =========================================================================
+// GSM 06.10
+// =========================================================================
+mod gsm0610 {
+    use spandsp::gsm0610::*;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip_sine_voip() {
+        let mut encoder = Gsm0610Encoder::new(Gsm0610Packing::Voip).unwrap();
+        let mut decoder = Gsm0610Decoder::new(Gsm0610Packing::Voip).unwrap();
+        let original = sine_wave(600.0, 8000.0, 1600, 8000.0);
+
+        let mut encoded = vec![0u8; 330];
+        assert_eq!(encoder.encode(&mut encoded, &original), 330);
+        assert!(encoded.chunks(33).all(|frame| frame[0] >> 4 == 0xD));
+
+        let mut decoded = vec![0i16; 1600];
+        assert_eq!(decoder.decode(&mut decoded, &encoded), 1600);
+        let found = best_correlation(&original[400..1200], &decoded[400..], 40).unwrap();
+        assert!(found.correlation > 0.6, "{found:?}");
+    }
+
+    #[test]
+    fn only_whole_frames_are_coded() {
+        let amp = sine_wave(1000.0, 8000.0, 3 * 160 + 50, 8000.0);
+        let mut encoder = Gsm0610Encoder::new(Gsm0610Packing::Voip).unwrap();
+        let mut out = [0u8; 200];
+        assert_eq!(encoder.encode(&mut out, &amp), 99);
+        // Output room limits it too.
+        assert_eq!(encoder.encode(&mut out[..70], &amp), 66);
+
+        let mut wav49 = Gsm0610Encoder::new(Gsm0610Packing::Wav49).unwrap();
+        assert_eq!(wav49.packing().samples_per_unit(), 320);
+        assert_eq!(wav49.encode(&mut out, &amp), 65);
+        assert_eq!(wav49.encode(&mut out, &amp[..300]), 0);
+
+        let mut unpacked = Gsm0610Encoder::new(Gsm0610Packing::None).unwrap();
+        assert_eq!(unpacked.encode(&mut out, &amp), 152);
+
+        let mut decoder = Gsm0610Decoder::new(Gsm0610Packing::Voip).unwrap();
+        let mut decoded = [0i16; 480];
+        assert_eq!(decoder.decode(&mut decoded, &out[..40]), 0);
+        assert_eq!(decoder.decode(&mut decoded[..200], &out[..99]), 160);
+    }
+
+    #[test]
+    fn packing_can_be_changed() {
+        let amp = sine_wave(1000.0, 8000.0, 320, 8000.0);
+        let mut encoder = Gsm0610Encoder::new(Gsm0610Packing::Wav49).unwrap();
+        let mut decoder = Gsm0610Decoder::new(Gsm0610Packing::Wav49).unwrap();
+        let mut code = [0u8; 152];
+        let mut out = [0i16; 320];
+        assert_eq!(encoder.encode(&mut code, &amp), 65);
+        assert_eq!(decoder.decode(&mut out, &code[..65]), 320);
+
+        for (packing, bytes) in [(Gsm0610Packing::Voip, 66), (Gsm0610Packing::None, 152)] {
+            encoder.set_packing(packing).unwrap();
+            decoder.set_packing(packing).unwrap();
+            assert_eq!(encoder.packing(), packing);
+            assert_eq!(encoder.encode(&mut code, &amp), bytes, "{packing}");
+            assert_eq!(decoder.decode(&mut out, &code[..bytes]), 320, "{packing}");
+        }
+    }
+
+    #[test]
+    fn voip_signature_is_checked() {
+        let mut decoder = Gsm0610Decoder::new(Gsm0610Packing::Voip).unwrap();
+        let mut amp = [0i16; 160];
+        assert_eq!(decoder.decode(&mut amp, &[0u8; 33]), 0);
+        let mut frame = [0u8; 33];
+        frame[0] = 0xD0;
+        assert_eq!(decoder.decode(&mut amp, &frame), 160);
+    }
+
+    #[test]
+    fn plc_conceals_lost_frames() {
+        let mut encoder = Gsm0610Encoder::new(Gsm0610Packing::Voip).unwrap();
+        let mut decoder = Gsm0610Decoder::new(Gsm0610Packing::Voip)
+            .unwrap()
+            .with_plc()
+            .unwrap();
+        let amp = sine_wave(500.0, 8000.0, 160, 8000.0);
+        let mut frame = [0u8; 33];
+        let mut out = [0i16; 160];
+        for _ in 0..5 {
+            assert_eq!(encoder.encode(&mut frame, &amp), 33);
+            assert_eq!(decoder.decode_frame(Some(&frame), &mut out), 160);
+        }
+        assert_eq!(decoder.decode_frame(None, &mut out), 160);
+        assert!(rms(&out) > 100.0);
+    }
+
+    #[test]
+    fn debug_shows_packing() {
+        let encoder = Gsm0610Encoder::new(Gsm0610Packing::Wav49).unwrap();
+        assert!(format!("{encoder:?}").contains("Wav49"));
+        assert_eq!(Gsm0610Packing::default(), Gsm0610Packing::Voip);
+        assert_eq!(Gsm0610Packing::None.bytes_per_unit(), 76);
+        assert_eq!(Gsm0610Packing::Wav49.to_string(), "WAV49");
+    }
+}
+
+// =========================================================================
+// IMA ADPCM
+// =========================================================================
+mod ima_adpcm {
+    use spandsp::ima_adpcm::*;
+
+    use super::*;
+
+    fn roundtrip(variant: ImaAdpcmVariant) -> (Vec<i16>, Vec<i16>, usize) {
+        let original = sine_wave(1000.0, 8000.0, 160, 10000.0);
+        let mut encoder = ImaAdpcmState::new(variant, 0).unwrap();
+        let mut decoder = ImaAdpcmState::new(variant, 0).unwrap();
+        let mut encoded = vec![0u8; encoder.max_encoded_len(original.len())];
+        let bytes = encoder.encode(&mut encoded, &original);
+        let mut decoded = vec![0i16; decoder.max_decoded_len(bytes)];
+        let n = decoder.decode(&mut decoded, &encoded[..bytes]);
+        decoded.truncate(n);
+        (original, decoded, bytes)
+    }
+
+    #[test]
+    fn roundtrip_sine_each_variant() {
+        for variant in [ImaAdpcmVariant::Ima4, ImaAdpcmVariant::Dvi4] {
+            let (original, decoded, bytes) = roundtrip(variant);
+            // A 4-byte header, then a nibble per sample.
+            assert_eq!(bytes, 84, "{variant}");
+            assert_eq!(decoded.len(), 160, "{variant}");
+            let corr = correlation(&original[40..], &decoded[40..]);
+            assert!(corr > 0.9, "{variant}: {corr}");
+        }
+        let (original, decoded, bytes) = roundtrip(ImaAdpcmVariant::Vdvi);
+        assert!(bytes <= 164, "{bytes}");
+        assert!(decoded.len() >= 160, "{}", decoded.len());
+        let corr = correlation(&original[40..], &decoded[40..160]);
+        assert!(corr > 0.9, "VDVI: {corr}");
+    }
+
+    #[test]
+    fn buffers_bound_the_work() {
+        let state = ImaAdpcmState::new(ImaAdpcmVariant::Dvi4, 0).unwrap();
+        assert_eq!(state.max_encoded_len(160), 84);
+        assert_eq!(state.max_decoded_len(84), 168);
+        let vdvi = ImaAdpcmState::new(ImaAdpcmVariant::Vdvi, 0).unwrap();
+        assert_eq!(vdvi.max_encoded_len(160), 164);
+        assert_eq!(vdvi.max_decoded_len(10), 40);
+        // A WAV block of 505 samples, with room for a header either side.
+        let wav = ImaAdpcmState::new(ImaAdpcmVariant::Ima4, 505).unwrap();
+        assert_eq!(wav.chunk_size(), 505);
+        assert_eq!(wav.max_encoded_len(505), 265);
+
+        let amp = sine_wave(1000.0, 8000.0, 160, 10000.0);
+        let mut encoder = ImaAdpcmState::new(ImaAdpcmVariant::Dvi4, 0).unwrap();
+        let mut short = [0u8; 24];
+        assert!(encoder.encode(&mut short, &amp) <= 24);
+        assert_eq!(encoder.encode(&mut [0u8; 3], &amp), 0);
+
+        let mut decoder = ImaAdpcmState::new(ImaAdpcmVariant::Dvi4, 0).unwrap();
+        let mut few = [0i16; 10];
+        assert!(decoder.decode(&mut few, &[0x11; 84]) <= 10);
+    }
+
+    #[test]
+    fn plc_conceals_lost_packets() {
+        let amp = sine_wave(500.0, 8000.0, 160, 8000.0);
+        let mut encoder = ImaAdpcmState::new(ImaAdpcmVariant::Dvi4, 0).unwrap();
+        let mut decoder = ImaAdpcmState::new(ImaAdpcmVariant::Dvi4, 0)
+            .unwrap()
+            .with_plc(160)
+            .unwrap();
+        let mut packet = [0u8; 84];
+        let mut out = [0i16; 160];
+        for _ in 0..5 {
+            assert_eq!(encoder.encode(&mut packet, &amp), 84);
+            assert_eq!(decoder.decode_frame(Some(&packet), &mut out), 160);
+        }
+        assert_eq!(decoder.decode_frame(None, &mut out), 160);
+        assert!(rms(&out) > 100.0);
+        let state = decoder.into_inner();
+        assert_eq!(state.variant(), ImaAdpcmVariant::Dvi4);
+        assert!(format!("{state:?}").contains("Dvi4"));
+    }
+}
+
+// =========================================================================
+// Time scaling
+// =========================================================================
+mod time_scale {
+    use spandsp::time_scale::*;
+
+    use super::*;
+
+    fn scale(rate: f32, input: &[i16]) -> Vec<i16> {
+        let mut ts = TimeScale::new(8000, rate).unwrap();
+        let mut out = vec![0i16; 160];
+        let mut scaled = Vec::new();
+        for frame in input.chunks(160) {
+            let n = ts.process(&mut out, frame);
+            scaled.extend_from_slice(&out[..n]);
+        }
+        loop {
+            let n = ts.flush(&mut out);
+            scaled.extend_from_slice(&out[..n]);
+            if n < out.len() {
+                break;
+            }
+        }
+        scaled
+    }
+
+    #[test]
+    fn speeds_up_and_slows_down() {
+        let input = sine_wave(300.0, 8000.0, 8000, 10000.0);
+        let fast = scale(1.5, &input);
+        let slow = scale(0.5, &input);
+        // Pitch periods are dropped or repeated whole, so lengths are close
+        // to, not exactly, the ratio.
+        assert!((4800..=5900).contains(&fast.len()), "{}", fast.len());
+        assert!((15000..=16800).contains(&slow.len()), "{}", slow.len());
+        assert!(rms(&fast) > 5000.0);
+        assert!(rms(&slow) > 5000.0);
+    }
+
+    #[test]
+    fn held_output_is_not_lost() {
+        let input = sine_wave(300.0, 8000.0, 1600, 10000.0);
+        let mut ts = TimeScale::new(8000, 0.5).unwrap();
+        assert!(ts.max_output_len(160) >= 320);
+        let mut small = [0i16; 40];
+        let mut total = 0;
+        for frame in input.chunks(160) {
+            total += ts.process(&mut small, frame);
+        }
+        assert!(ts.pending() > 0);
+        while ts.pending() > 0 {
+            total += ts.process(&mut small, &[]);
+        }
+        assert!(total >= 2800, "{total}");
+    }
+
+    #[test]
+    fn rate_is_validated() {
+        let mut ts = TimeScale::new(8000, 1.0).unwrap();
+        assert_eq!(ts.sample_rate(), 8000);
+        ts.set_rate(1.25).unwrap();
+        assert_eq!(ts.rate(), 1.25);
+        assert!(ts.set_rate(0.0).is_err());
+        assert!(ts.set_rate(f32::NAN).is_err());
+        assert_eq!(ts.rate(), 1.25);
+        assert!(TimeScale::new(8000, -1.0).is_err());
+        assert!(TimeScale::new(96000, 1.0).is_err());
+        assert!(format!("{ts:?}").contains("1.25"));
+    }
+}
+
+// =========================================================================
+// Noise
+// =========================================================================
+mod noise {
+    use spandsp::noise::*;
+
+    use super::*;
+
+    fn generate(class: NoiseClass, level_dbm0: f32, seed: i32) -> Vec<i16> {
+        let mut noise = NoiseGenerator::new(class, level_dbm0, seed).unwrap();
+        let mut amp = vec![0i16; 8000];
+        noise.generate(&mut amp);
+        amp
+    }
+
+    fn zero_crossings(amp: &[i16]) -> usize {
+        amp.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn level_matches_each_class() {
+        // -20 dBm0 is 3.14 dB below a full-scale sine's 23170 RMS, and 20 more.
+        let expected = 23170.0 * 10f64.powf(-23.14 / 20.0);
+        for class in [NoiseClass::White, NoiseClass::Hoth, NoiseClass::Awgn] {
+            let level = rms(&generate(class, -20.0, 1234));
+            let db = 20.0 * (level / expected).log10();
+            assert!(db.abs() < 2.0, "{class}: {level} RMS, {db:.1} dB off");
+        }
+    }
+
+    #[test]
+    fn hoth_is_duller_than_white() {
+        let white = zero_crossings(&generate(NoiseClass::White, -20.0, 1));
+        let hoth = zero_crossings(&generate(NoiseClass::Hoth, -20.0, 1));
+        assert!(hoth < white, "Hoth {hoth}, white {white}");
+    }
+
+    #[test]
+    fn seed_repeats_the_noise() {
+        assert_eq!(
+            generate(NoiseClass::Awgn, -30.0, 42),
+            generate(NoiseClass::Awgn, -30.0, 42)
+        );
+        assert_ne!(
+            generate(NoiseClass::White, -30.0, 42),
+            generate(NoiseClass::White, -30.0, 43)
+        );
+    }
+
+    #[test]
+    fn level_is_validated() {
+        assert!(NoiseGenerator::new(NoiseClass::White, 10.0, 1).is_err());
+        assert!(NoiseGenerator::new(NoiseClass::Awgn, f32::NAN, 1).is_err());
+        let noise = NoiseGenerator::new(NoiseClass::Hoth, -40.0, 1).unwrap();
+        assert_eq!(noise.class(), NoiseClass::Hoth);
+        assert_eq!(noise.level_dbm0(), -40.0);
+        assert!(format!("{noise:?}").contains("Hoth"));
+    }
+}
+
+// =========================================================================
+// DDS oscillators
+// =========================================================================
+mod dds {
+    use spandsp::dds::Dds;
+
+    use super::*;
+
+    #[test]
+    fn tone_has_requested_frequency_and_level() {
+        let mut dds = Dds::new(1004.0, -13.0).unwrap();
+        assert!((dds.frequency() - 1004.0).abs() < 0.01);
+        let mut amp = vec![0i16; 8000];
+        dds.generate(&mut amp);
+
+        let crossings = amp.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+        assert!(
+            (2006..=2010).contains(&crossings),
+            "{crossings} zero crossings"
+        );
+        let expected = 23170.0 * 10f64.powf(-16.14 / 20.0);
+        let db = 20.0 * (rms(&amp) / expected).log10();
+        assert!(db.abs() < 0.5, "level {db:.2} dB off");
+    }
+
+    #[test]
+    fn complex_output_has_constant_magnitude() {
+        let mut lo = Dds::new(-1800.0, 0.0).unwrap();
+        let mut out = vec![(0i16, 0i16); 400];
+        lo.generate_complex(&mut out);
+        let mags: Vec<f64> = out
+            .iter()
+            .map(|&(re, im)| (re as f64).hypot(im as f64))
+            .collect();
+        let (min, max) = mags
+            .iter()
+            .fold((f64::MAX, 0f64), |(lo, hi), &m| (lo.min(m), hi.max(m)));
+        assert!(max > 10_000.0 && max - min < max * 0.01, "{min}..{max}");
+    }
+
+    #[test]
+    fn phase_carries_over_a_frequency_change() {
+        let mut dds = Dds::new(1000.0, -10.0).unwrap();
+        let mut amp = [0i16; 3];
+        dds.generate(&mut amp);
+        let phase = dds.phase();
+        dds.set_frequency(2000.0).unwrap();
+        assert_eq!(dds.phase(), phase);
+        dds.set_phase(0);
+        let first = dds.next_sample();
+        assert!(first.abs() < 100, "sine at zero phase gave {first}");
+    }
+
+    #[test]
+    fn settings_are_validated() {
+        assert!(Dds::new(5000.0, -10.0).is_err());
+        assert!(Dds::new(4000.0, -10.0).is_err());
+        assert!(Dds::new(-4000.0, -10.0).is_err());
+        assert!(Dds::new(f32::NAN, -10.0).is_err());
+        assert!(Dds::new(1000.0, 10.0).is_err());
+        let mut dds = Dds::new(1000.0, -10.0).unwrap();
+        assert!(dds.set_level_dbov(1.0).is_err());
+        dds.set_level_dbov(-6.0).unwrap();
+        assert!(format!("{dds:?}").contains("Dds"));
+    }
+}
+
+// =========================================================================
+// DTX / VAD
+// =========================================================================
+mod dtx {
+    use spandsp::dtx::*;
+    use spandsp::g711::*;
+
+    use super::*;
+
+    #[test]
+    fn speech_is_encoded_and_silence_suppressed() {
+        let mut codec = G711State::new(G711Mode::ULaw).unwrap();
+        let mut dtx = Dtx::new(DtxConfig {
+            hangover_frames: 2,
+            ..DtxConfig::default()
+        });
+        let mut out = [0u8; 160];
+
+        let speech = sine_wave(440.0, 8000.0, 160, 8000.0);
+        assert_eq!(
+            codec.encode_dtx(&mut dtx, &mut out, &speech),
+            DtxFrame::Voice(160)
+        );
+
+        // Hangover keeps the first silent frames flowing.
+        let silence = [0i16; 160];
+        for _ in 0..2 {
+            assert_eq!(
+                codec.encode_dtx(&mut dtx, &mut out, &silence),
+                DtxFrame::Voice(160)
+            );
+        }
+        // Then one SID on entry to silence, followed by nothing.
+        match codec.encode_dtx(&mut dtx, &mut out, &silence) {
+            DtxFrame::Sid(cn) => assert_eq!(cn.level, 127),
+            other => panic!("expected SID, got {other:?}"),
+        }
+        assert!(dtx.in_silence());
+        assert_eq!(
+            codec.encode_dtx(&mut dtx, &mut out, &silence),
+            DtxFrame::NoFrame
+        );
+
+        // Speech resumes immediately.
+        assert_eq!(
+            codec.encode_dtx(&mut dtx, &mut out, &speech),
+            DtxFrame::Voice(160)
+        );
+    }
+
+    #[test]
+    fn sid_level_tracks_noise() {
+        let mut dtx = Dtx::new(DtxConfig {
+            hangover_frames: 0,
+            ..DtxConfig::default()
+        });
+        // A full-scale sine is -3 dBov; 60 dB down should report ~63 -dBov.
+        let quiet = sine_wave(300.0, 8000.0, 160, 32767.0 / 1000.0);
+        match dtx.process(&quiet, |_| unreachable!()) {
+            DtxFrame::Sid(cn) => assert!((62..=64).contains(&cn.level), "level {}", cn.level),
+            other => panic!("expected SID, got {other:?}"),
+        }
+        assert_eq!(
+            ComfortNoise::from_payload(&[40, 1, 2]),
+            Some(ComfortNoise { level: 40 })
+        );
+        assert_eq!(ComfortNoise { level: 40 }.to_payload(), [40]);
+    }
 }
 
 // =========================================================================
@@ -511,22 +1243,207 @@ mod hdlc {
 }
 
 // =========================================================================
-// DTMF
+// Pure-Rust HDLC
 // =========================================================================
-mod dtmf {
-    use spandsp::dtmf::*;
+#[cfg(feature = "pure-hdlc")]
+mod hdlc_pure {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use spandsp::hdlc;
+    use spandsp::hdlc_pure::*;
+
+    type Frames = Rc<RefCell<Vec<(Vec<u8>, bool)>>>;
+
+    const FRAMES: [&[u8]; 4] = [
+        b"Hello HDLC!",
+        &[0xFF; 24],
+        &[0x7E, 0x7D, 0x1F, 0xF8, 0x00, 0x3F],
+        b"x",
+    ];
+
+    /// Drive a transmitter through preamble and each frame in turn, collecting
+    /// its output bits.
+    fn collect_bits(
+        mut frame: impl FnMut(&[u8]),
+        mut get_bit: impl FnMut() -> i32,
+        frames: &[&[u8]],
+    ) -> Vec<u8> {
+        let mut bits: Vec<u8> = (0..64).map(|_| get_bit() as u8).collect();
+        for data in frames {
+            frame(data);
+            bits.extend((0..(data.len() + 8) * 10).map(|_| get_bit() as u8));
+        }
+        bits
+    }
 
     #[test]
-    fn tx_rx_roundtrip_all_digits() {
-        let mut tx = DtmfTx::new().unwrap();
-        let mut rx = DtmfRx::new().unwrap();
+    fn tx_matches_ffi_bitstream() {
+        for crc32 in [false, true] {
+            let mut ffi = hdlc::HdlcTx::new(crc32, 2, false, None::<fn()>).unwrap();
+            let mut pure = HdlcTx::new(crc32, 2, false, None::<fn()>).unwrap();
+            let ffi_bits = {
+                let ffi = RefCell::new(&mut ffi);
+                collect_bits(
+                    |d| ffi.borrow_mut().frame(d).unwrap(),
+                    || ffi.borrow_mut().get_bit(),
+                    &FRAMES,
+                )
+            };
+            let pure_bits = {
+                let pure = RefCell::new(&mut pure);
+                collect_bits(
+                    |d| pure.borrow_mut().frame(d).unwrap(),
+                    || pure.borrow_mut().get_bit(),
+                    &FRAMES,
+                )
+            };
+            assert_eq!(pure_bits, ffi_bits, "crc32={crc32}");
+        }
+    }
 
-        let digits = "123456789*#0ABCD";
-        tx.put(digits).unwrap();
+    #[test]
+    fn rx_matches_ffi() {
+        for crc32 in [false, true] {
+            let mut tx = hdlc::HdlcTx::new(crc32, 2, false, None::<fn()>).unwrap();
+            let mut stream = vec![0u8; 16];
+            tx.get(&mut stream);
+            for data in FRAMES {
+                tx.frame(data).unwrap();
+                let mut chunk = vec![0u8; data.len() + 16];
+                tx.get(&mut chunk);
+                stream.extend(chunk);
+            }
+            // Corrupt one frame so bad-frame reporting is exercised too.
+            stream[30] ^= 0x10;
+
+            let ffi_frames: Frames = Rc::default();
+            let pure_frames: Frames = Rc::default();
+            let sink = ffi_frames.clone();
+            let mut ffi_rx = hdlc::HdlcRx::new(crc32, true, 2, move |d: &[u8], ok: bool| {
+                sink.borrow_mut().push((d.to_vec(), ok))
+            })
+            .unwrap();
+            let sink = pure_frames.clone();
+            let mut pure_rx = HdlcRx::new(crc32, true, 2, move |d: &[u8], ok: bool| {
+                sink.borrow_mut().push((d.to_vec(), ok))
+            })
+            .unwrap();
+            ffi_rx.put(&stream);
+            pure_rx.put(&stream);
 
-        // Generate enough audio: ~100ms on + ~100ms off per digit = ~1600 samples/digit
-        // 16 digits * 1600 = 25600 samples, add some margin
-        let mut audio = vec![0i16; 64000];
+            assert_eq!(*pure_frames.borrow(), *ffi_frames.borrow(), "crc32={crc32}");
+            assert!(
+                pure_frames
+                    .borrow()
+                    .iter()
+                    .any(|(d, ok)| *ok && d.as_slice() == FRAMES[3])
+            );
+        }
+    }
+
+    #[test]
+    fn bit_level_roundtrip() {
+        let received: Frames = Rc::default();
+        let sink = received.clone();
+        let mut rx = HdlcRx::new(false, false, 1, move |d: &[u8], ok: bool| {
+            if !d.is_empty() {
+                sink.borrow_mut().push((d.to_vec(), ok));
+            }
+        })
+        .unwrap();
+        let mut tx = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        for _ in 0..128 {
+            rx.put_bit(tx.get_bit() != 0);
+        }
+        tx.frame(b"Bit level").unwrap();
+        for _ in 0..8192 {
+            rx.put_bit(tx.get_bit() != 0);
+        }
+        assert_eq!(*received.borrow(), vec![(b"Bit level".to_vec(), true)]);
+    }
+
+    /// Run `frames` through `tx` into `rx`, returning the frames with data.
+    fn pipe(tx: &mut HdlcTx, rx_mode: FcsMode, frames: &[&[u8]]) -> Vec<(Vec<u8>, bool)> {
+        let received: Frames = Rc::default();
+        let sink = received.clone();
+        let mut rx = HdlcRx::new(false, true, 2, move |d: &[u8], ok: bool| {
+            if !d.is_empty() {
+                sink.borrow_mut().push((d.to_vec(), ok));
+            }
+        })
+        .unwrap();
+        rx.set_fcs_mode(rx_mode).unwrap();
+        let mut chunk = [0u8; 16];
+        tx.get(&mut chunk);
+        rx.put(&chunk);
+        for data in frames {
+            tx.frame(data).unwrap();
+            let mut chunk = vec![0u8; data.len() + 16];
+            tx.get(&mut chunk);
+            rx.put(&chunk);
+        }
+        received.take()
+    }
+
+    #[test]
+    fn raw_mode_relays_frames_with_their_fcs() {
+        // Capture frames with their FCS, as a relay would receive them.
+        let mut tx = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        let raw = pipe(&mut tx, FcsMode::Raw, &FRAMES);
+        assert_eq!(raw.len(), FRAMES.len());
+        for ((data, ok), sent) in raw.iter().zip(FRAMES) {
+            assert!(ok);
+            assert_eq!(data.len(), sent.len() + 2);
+            assert_eq!(&data[..sent.len()], sent);
+        }
+
+        // Sent on untouched, they pass an ordinary receiver's CRC check.
+        let mut relay = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        relay.set_fcs_mode(FcsMode::Raw).unwrap();
+        assert_eq!(relay.fcs_mode(), FcsMode::Raw);
+        let relayed: Vec<&[u8]> = raw.iter().map(|(d, _)| d.as_slice()).collect();
+        let checked = pipe(&mut relay, FcsMode::Crc, &relayed);
+        let expected: Vec<(Vec<u8>, bool)> = FRAMES.iter().map(|d| (d.to_vec(), true)).collect();
+        assert_eq!(checked, expected);
+
+        // With no FCS at all, raw to raw is transparent.
+        let mut bare = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        bare.set_fcs_mode(FcsMode::Raw).unwrap();
+        let received = pipe(&mut bare, FcsMode::Raw, &FRAMES);
+        assert_eq!(received, expected);
+        // An ordinary receiver sees a bad CRC.
+        let mut bare = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        bare.set_fcs_mode(FcsMode::Raw).unwrap();
+        assert!(!pipe(&mut bare, FcsMode::Crc, &[b"no fcs here"])[0].1);
+    }
+
+    #[test]
+    fn non_progressive_rejects_second_frame() {
+        let mut tx = HdlcTx::new(false, 1, false, None::<fn()>).unwrap();
+        tx.frame(b"first").unwrap();
+        assert!(tx.frame(b"second").is_err());
+        assert!(tx.frame(&[0u8; 401]).is_err());
+    }
+}
+
+// =========================================================================
+// DTMF
+// =========================================================================
+mod dtmf {
+    use spandsp::dtmf::*;
+
+    #[test]
+    fn tx_rx_roundtrip_all_digits() {
+        let mut tx = DtmfTx::new().unwrap();
+        let mut rx = DtmfRx::new().unwrap();
+
+        let digits = "123456789*#0ABCD";
+        tx.put(digits).unwrap();
+
+        // Generate enough audio: ~100ms on + ~100ms off per digit = ~1600 samples/digit
+        // 16 digits * 1600 = 25600 samples, add some margin
+        let mut audio = vec![0i16; 64000];
         let mut total_generated = 0;
 
         loop {
@@ -561,6 +1478,533 @@ mod dtmf {
         let n = tx.generate(&mut buf);
         assert_eq!(n, 0, "expected 0 samples from empty DTMF TX, got {n}");
     }
+
+    #[test]
+    fn configure_updates_getters() {
+        let mut rx = DtmfRx::new().unwrap();
+        assert!(!rx.filter_dialtone());
+        assert_eq!(rx.twist_db(), 8.0);
+        assert_eq!(rx.reverse_twist_db(), 4.0);
+        assert_eq!(rx.threshold_dbm0(), -42.0);
+
+        rx.configure(&DtmfRxConfig {
+            filter_dialtone: Some(true),
+            threshold_dbm0: Some(-30.0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(rx.filter_dialtone());
+        assert_eq!(rx.twist_db(), 8.0);
+        assert_eq!(rx.threshold_dbm0(), -30.0);
+
+        let bad = DtmfRxConfig {
+            twist_db: Some(6.0),
+            reverse_twist_db: Some(-1.0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            rx.configure(&bad),
+            Err(spandsp::error::SpanDspError::InvalidInput(_))
+        ));
+        assert_eq!(rx.twist_db(), 8.0);
+
+        // spandsp's detector has fixed timing.
+        let timed = DtmfRxConfig {
+            threshold_dbm0: Some(-20.0),
+            min_on_ms: Some(40),
+            ..Default::default()
+        };
+        assert!(matches!(
+            rx.configure(&timed),
+            Err(spandsp::error::SpanDspError::InvalidInput(_))
+        ));
+        assert_eq!(rx.threshold_dbm0(), -30.0);
+
+        // A tone 10 dB below the new threshold is no longer a digit.
+        let mut tx = DtmfTx::new().unwrap();
+        tx.set_level(-40, 0);
+        tx.put("3").unwrap();
+        let mut audio = vec![0i16; 1600];
+        tx.generate(&mut audio);
+        rx.rx(&audio);
+        assert_eq!(rx.get(8), "");
+    }
+
+    #[test]
+    fn set_gain_changes_sounding_digit() {
+        let mut tx = DtmfTx::new().unwrap();
+        tx.set_timing(100, 100);
+        tx.put("5").unwrap();
+        let mut before = vec![0i16; 160];
+        assert_eq!(tx.generate(&mut before), 160);
+
+        tx.set_gain(6.0, 10);
+        assert_eq!(tx.gain(), 6.0);
+        let mut after = vec![0i16; 240];
+        assert_eq!(tx.generate(&mut after), 240);
+        let ratio = super::rms(&after[80..]) / super::rms(&before);
+        assert!((ratio - 2.0).abs() < 0.1, "ratio {ratio}");
+    }
+
+    #[test]
+    fn squelch_blanks_tones_and_keeps_digits() {
+        use spandsp::dtmf_squelch::*;
+
+        let mut tx = DtmfTx::new().unwrap();
+        tx.put("47").unwrap();
+        let mut audio = vec![0i16; 8000];
+        let mut generated = 0;
+        while generated < audio.len() {
+            let n = tx.generate(&mut audio[generated..]);
+            if n == 0 {
+                break;
+            }
+            generated += n;
+        }
+        // Speech-like audio either side of the tones passes untouched.
+        let tone_end = generated;
+        let speech = super::sine_wave(300.0, 8000.0, 2000, 4000.0);
+        audio[tone_end..tone_end + 2000].copy_from_slice(&speech);
+
+        let config = DtmfSquelchConfig::default();
+        let mut squelch = DtmfSquelch::new(config).unwrap();
+        let mut out = audio.clone();
+        for frame in out.chunks_mut(160) {
+            squelch.process(frame);
+        }
+        assert_eq!(squelch.take_digits(), "47");
+        assert!(squelch.squelched_samples() > 0);
+
+        // Output lags input by the lookahead.
+        let delayed = |i: usize| i + config.lookahead;
+        let tones = &out[delayed(0)..delayed(tone_end)];
+        assert!(super::rms(tones) < 50.0, "{}", super::rms(tones));
+        let after = &out[delayed(tone_end + 800)..delayed(tone_end + 2000)];
+        assert_eq!(after, &speech[800..]);
+
+        let noisy = DtmfSquelchConfig {
+            fill: SquelchFill::ComfortNoise { level_dbm0: 10.0 },
+            ..config
+        };
+        assert!(DtmfSquelch::new(noisy).is_err());
+    }
+
+    #[test]
+    fn relay_sends_rfc4733_events_over_the_squelched_gap() {
+        use spandsp::dtmf_relay::*;
+
+        let mut tx = DtmfTx::new().unwrap();
+        tx.put("9").unwrap();
+        let mut audio = vec![0i16; 4800];
+        tx.generate(&mut audio);
+
+        let mut relay = DtmfRelay::new(DtmfRelayConfig::default()).unwrap();
+        let mut packets = Vec::new();
+        for frame in audio.chunks_mut(160) {
+            packets.extend(relay.process(frame));
+        }
+        assert_eq!(relay.take_digits(), "9");
+
+        let (first, rest) = packets.split_first().unwrap();
+        assert!(first.marker);
+        assert_eq!(first.event.digit(), Some('9'));
+        assert!(
+            rest.iter()
+                .all(|p| !p.marker && p.timestamp == first.timestamp)
+        );
+        // Three identical end packets close the event.
+        let ends: Vec<_> = packets.iter().filter(|p| p.event.end).collect();
+        assert_eq!(ends.len(), 3);
+        assert!(ends.windows(2).all(|w| w[0] == w[1]));
+        assert!(ends[0].event.duration >= 400, "{:?}", ends[0]);
+        assert!(ends[0].event.volume < 20);
+        // Durations never go backwards.
+        assert!(
+            packets
+                .windows(2)
+                .all(|w| w[0].event.duration <= w[1].event.duration)
+        );
+
+        let payload = ends[0].event.to_payload();
+        assert_eq!(payload[0], 9);
+        assert_eq!(payload[1] & 0x80, 0x80);
+        assert_eq!(TelephoneEvent::from_payload(&payload), Some(ends[0].event));
+        assert_eq!(TelephoneEvent::code_for_digit('#'), Some(11));
+        assert_eq!(TelephoneEvent::code_for_digit('e'), None);
+    }
+}
+
+// =========================================================================
+// Pure-Rust DTMF detector
+// =========================================================================
+#[cfg(feature = "pure-dtmf")]
+mod dtmf_pure {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use spandsp::dtmf::{self, DtmfTx};
+    use spandsp::dtmf_pure::*;
+
+    use super::*;
+
+    fn generate(digits: &str, level: i32, twist: i32) -> Vec<i16> {
+        let mut tx = DtmfTx::new().unwrap();
+        tx.set_level(level, twist);
+        tx.put(digits).unwrap();
+        let mut audio = vec![0i16; 2000 * digits.len() + 800];
+        let mut total = 0;
+        loop {
+            let n = tx.generate(&mut audio[total..]);
+            if n == 0 {
+                break;
+            }
+            total += n;
+        }
+        audio.truncate(total + 800);
+        audio
+    }
+
+    fn detect(audio: &[i16]) -> String {
+        let mut rx = DtmfRx::new().unwrap();
+        for chunk in audio.chunks(160) {
+            rx.rx(chunk);
+        }
+        rx.get(128)
+    }
+
+    #[test]
+    fn detects_all_digits_like_ffi() {
+        let digits = "123456789*#0ABCD";
+        let audio = generate(digits, -10, 0);
+
+        let mut ffi = dtmf::DtmfRx::new().unwrap();
+        for chunk in audio.chunks(160) {
+            ffi.rx(chunk);
+        }
+        assert_eq!(ffi.get(128), digits);
+        assert_eq!(detect(&audio), digits);
+    }
+
+    #[test]
+    fn digit_changes_follow_ffi() {
+        // One 102-sample detection block of a digit's two tones.
+        let block = |row: f32, col: f32| -> Vec<i16> {
+            let row = sine_wave(row, 8000.0, 102, 7000.0);
+            let col = sine_wave(col, 8000.0, 102, 7000.0);
+            row.iter().zip(&col).map(|(&r, &c)| r + c).collect()
+        };
+        let one = block(697.0, 1209.0);
+        let two = block(697.0, 1336.0);
+        // A one-block "1" then "2": the disagreeing pair cancels the change,
+        // so "2" needs two more blocks of its own to start.
+        let blocks = [vec![0i16; 102], one, two.clone(), two.clone(), two];
+        let expected = [None, Some('x'), None, Some('x'), Some('2')];
+
+        let mut rx = DtmfRx::new().unwrap();
+        let mut ffi = dtmf::DtmfRx::new().unwrap();
+        for (block, expected) in blocks.iter().zip(expected) {
+            rx.rx(block);
+            ffi.rx(block);
+            assert_eq!(rx.status(), expected);
+            assert_eq!(ffi.status(), expected);
+        }
+        assert_eq!(rx.get(8), "2");
+    }
+
+    #[test]
+    fn callback_receives_digits() {
+        let seen = Rc::new(RefCell::new(String::new()));
+        let sink = seen.clone();
+        let mut rx = DtmfRx::with_callback(move |d: &str| sink.borrow_mut().push_str(d)).unwrap();
+        rx.rx(&generate("159#", -20, 0));
+        assert_eq!(*seen.borrow(), "159#");
+        assert_eq!(rx.get(10), "");
+    }
+
+    #[test]
+    fn rejects_weak_single_and_twisted_tones() {
+        assert_eq!(detect(&generate("5", -50, 0)), "");
+        assert_eq!(detect(&sine_wave(770.0, 8000.0, 1600, 8000.0)), "");
+        assert_eq!(detect(&generate("5", -10, 12)), "");
+    }
+
+    #[test]
+    fn thresholds_are_adjustable() {
+        let audio = generate("7", -10, -6);
+        assert_eq!(detect(&audio), "7");
+        let mut rx = DtmfRx::new().unwrap();
+        rx.configure(&DtmfRxConfig {
+            twist_db: Some(3.0),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(rx.twist_db(), 3.0);
+        rx.rx(&audio);
+        assert_eq!(rx.get(8), "");
+        assert_eq!(rx.status(), None);
+    }
+
+    fn stats_for(audio: &[i16], config: &DtmfRxConfig) -> (String, DtmfRxStats) {
+        let mut rx = DtmfRx::new().unwrap();
+        rx.configure(config).unwrap();
+        for chunk in audio.chunks(160) {
+            rx.rx(chunk);
+        }
+        (rx.get(128), rx.take_stats())
+    }
+
+    #[test]
+    fn stats_count_accepted_and_rejected() {
+        let (digits, stats) = stats_for(&generate("123", -10, 0), &DtmfRxConfig::default());
+        assert_eq!(digits, "123");
+        assert_eq!(stats.accepted, 3);
+        assert_eq!(stats.rejected_duration, 0);
+        assert!(stats.blocks > 0);
+
+        let (_, stats) = stats_for(&generate("5", -10, -12), &DtmfRxConfig::default());
+        assert_eq!(stats.accepted, 0);
+        assert!(stats.rejected_twist > 0);
+        let (_, stats) = stats_for(&generate("5", -10, 12), &DtmfRxConfig::default());
+        assert!(stats.rejected_reverse_twist > 0);
+        // A lone row tone: its column is below the threshold.
+        let (_, stats) = stats_for(
+            &sine_wave(770.0, 8000.0, 1600, 1000.0),
+            &DtmfRxConfig::default(),
+        );
+        assert!(stats.rejected_level > 0);
+        assert_eq!(stats.rejected(), stats.rejected_level);
+
+        // Silence is not a candidate.
+        let (_, stats) = stats_for(&[0i16; 1600], &DtmfRxConfig::default());
+        assert_eq!(stats.rejected(), 0);
+    }
+
+    #[test]
+    fn stats_count_talk_off() {
+        // A digit with a third tone as loud as its own, as speech can have.
+        let tones: Vec<Vec<i16>> = [770.0, 1336.0, 852.0]
+            .iter()
+            .map(|&freq| sine_wave(freq, 8000.0, 1600, 4000.0))
+            .collect();
+        let audio: Vec<i16> = (0..1600)
+            .map(|i| tones.iter().map(|tone| tone[i]).sum())
+            .collect();
+        let (digits, stats) = stats_for(&audio, &DtmfRxConfig::default());
+        assert_eq!(digits, "");
+        assert!(stats.talk_off > 0);
+    }
+
+    #[test]
+    fn minimum_durations_are_adjustable() {
+        let audio = generate("42", -10, 0);
+        let mut rx = DtmfRx::new().unwrap();
+        assert_eq!(rx.min_on_ms(), 25.5);
+        assert_eq!(rx.min_off_ms(), 25.5);
+        rx.configure(&DtmfRxConfig {
+            min_on_ms: Some(80),
+            min_off_ms: Some(30),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(rx.min_on_ms(), 89.25);
+        assert_eq!(rx.min_off_ms(), 38.25);
+        rx.rx(&audio);
+        assert_eq!(rx.get(8), "");
+        let stats = rx.stats();
+        assert_eq!(stats.accepted, 0);
+        assert_eq!(stats.rejected_duration, 2);
+
+        let (digits, _) = stats_for(
+            &audio,
+            &DtmfRxConfig {
+                min_on_ms: Some(30),
+                ..Default::default()
+            },
+        );
+        assert_eq!(digits, "42");
+
+        let err = rx.configure(&DtmfRxConfig {
+            min_off_ms: Some(0),
+            ..Default::default()
+        });
+        assert!(matches!(
+            err,
+            Err(spandsp::error::SpanDspError::InvalidInput(_))
+        ));
+        assert_eq!(rx.min_off_ms(), 38.25);
+
+        rx.configure(&DtmfRxConfig {
+            min_on_ms: Some(u32::MAX),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(rx.min_on_ms() > 4.0e9);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn set_parms_leaves_unusable_values_unchanged() {
+        let mut rx = DtmfRx::new().unwrap();
+        rx.set_parms(-1, f32::INFINITY, 6.0, f32::NAN);
+        assert_eq!(rx.twist_db(), DtmfRx::new().unwrap().twist_db());
+        assert_eq!(rx.reverse_twist_db(), 6.0);
+        assert_eq!(rx.threshold_dbm0(), DtmfRx::new().unwrap().threshold_dbm0());
+    }
+}
+
+// =========================================================================
+// Media clock
+// =========================================================================
+mod media_clock {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use spandsp::dtmf::{DtmfRx, DtmfTx};
+    use spandsp::media_clock::*;
+    use spandsp::tone_generate::ToneGenerator;
+
+    #[test]
+    fn rejects_fractional_frames() {
+        assert!(MediaClock::new(Duration::ZERO).is_err());
+        assert!(MediaClock::new(Duration::from_micros(100)).is_err());
+        let clock = MediaClock::new(Duration::from_millis(10)).unwrap();
+        assert_eq!(clock.frame_samples(), 80);
+    }
+
+    #[test]
+    fn mixes_components_and_passes_input() {
+        let mut clock = MediaClock::new(Duration::from_millis(20)).unwrap();
+        clock.add(ClockFn(|_: &mut [i16], out: &mut [i16]| out.fill(30000)));
+        clock.add(ClockFn(|input: &mut [i16], out: &mut [i16]| {
+            out.copy_from_slice(input)
+        }));
+        let out = clock.tick(&[5; 100]);
+        assert_eq!(out.len(), 160);
+        assert_eq!(out[0], i16::MAX);
+        assert_eq!(out[120], 30000);
+        assert_eq!(clock.elapsed(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn timers_fire_in_order_and_can_be_cancelled() {
+        let mut clock = MediaClock::new(Duration::from_millis(20)).unwrap();
+        let fired = Rc::new(Cell::new(0u32));
+        let f = fired.clone();
+        clock.schedule(Duration::from_millis(40), move |_| f.set(f.get() + 1));
+        let f = fired.clone();
+        let cancelled = clock.schedule(Duration::from_millis(40), move |_| f.set(f.get() + 10));
+        assert!(clock.cancel(cancelled));
+
+        clock.tick(&[]);
+        clock.tick(&[]);
+        assert_eq!(fired.get(), 0);
+        clock.tick(&[]);
+        assert_eq!(fired.get(), 1);
+        assert!(!clock.cancel(cancelled));
+    }
+
+    #[test]
+    fn drives_dtmf_from_a_timer() {
+        let mut clock = MediaClock::new(Duration::from_millis(20)).unwrap();
+        let id = clock.add(DtmfTx::new().unwrap());
+        clock.schedule(Duration::from_millis(100), move |clock| {
+            clock
+                .component_mut::<DtmfTx>(id)
+                .unwrap()
+                .put("42")
+                .unwrap();
+        });
+        let mut rx = DtmfRx::new().unwrap();
+        for _ in 0..50 {
+            rx.rx(clock.tick(&[]));
+        }
+        assert_eq!(rx.get(8), "42");
+        assert!(clock.component_mut::<ToneGenerator>(id).is_none());
+        assert!(clock.remove::<DtmfTx>(id).is_some());
+        assert_eq!(clock.component_count(), 0);
+    }
+}
+
+// =========================================================================
+// Audio rings
+// =========================================================================
+mod audio_ring {
+    use spandsp::audio_ring::*;
+    use spandsp::media_clock::ClockFn;
+
+    #[test]
+    fn ring_wraps_and_bounds() {
+        let (mut tx, mut rx) = sample_ring(5);
+        assert_eq!(tx.push(&[1, 2, 3, 4, 5, 6]), 5);
+        assert_eq!(tx.free(), 0);
+        let mut out = [0i16; 3];
+        assert_eq!(rx.pop(&mut out), 3);
+        assert_eq!(out, [1, 2, 3]);
+        assert_eq!(tx.push(&[7, 8, 9]), 3);
+        let mut out = [0i16; 8];
+        assert_eq!(rx.pop(&mut out), 5);
+        assert_eq!(out[..5], [4, 5, 7, 8, 9]);
+        assert_eq!(rx.available(), 0);
+    }
+
+    #[test]
+    fn ring_crosses_threads() {
+        let (mut tx, mut rx) = sample_ring(64);
+        let writer = std::thread::spawn(move || {
+            let mut next = 0i16;
+            while next < 10_000 {
+                let block: Vec<i16> = (next..next.saturating_add(17).min(10_000)).collect();
+                next += tx.push(&block) as i16;
+            }
+        });
+        let mut expected = 0i16;
+        let mut buf = [0i16; 32];
+        while expected < 10_000 {
+            let n = rx.pop(&mut buf);
+            for &sample in &buf[..n] {
+                assert_eq!(sample, expected);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn stream_processes_whole_frames() {
+        let engine = ClockFn(|input: &mut [i16], out: &mut [i16]| {
+            for (o, i) in out.iter_mut().zip(input.iter()) {
+                *o = i.saturating_mul(2);
+            }
+        });
+        let (mut stream, mut port) = AudioStream::new(engine, 160, 2).unwrap();
+        assert!(AudioStream::new(ClockFn(|_: &mut [i16], _: &mut [i16]| ()), 0, 2).is_err());
+
+        assert_eq!(port.write(&[3; 250]), 250);
+        assert_eq!(stream.process(), 1);
+        assert_eq!(port.available(), 160);
+        assert_eq!(port.write(&[3; 200]), 200);
+        assert_eq!(stream.process(), 1);
+        // Output ring is full until the port drains it.
+        assert_eq!(port.write(&[3; 160]), 160);
+        assert_eq!(stream.process(), 0);
+        let mut out = [0i16; 320];
+        assert_eq!(port.read(&mut out), 320);
+        assert!(out.iter().all(|&s| s == 6));
+        assert_eq!(stream.process(), 1);
+    }
+
+    #[cfg(feature = "fax")]
+    #[test]
+    fn fax_stream_generates_audio() {
+        let fax = spandsp::fax::FaxState::new(true).unwrap();
+        let (mut stream, mut port): (FaxStream, _) = AudioStream::new(fax, 160, 4).unwrap();
+        stream.engine_mut().set_transmit_on_idle(true);
+        port.write(&[0; 640]);
+        assert_eq!(stream.process(), 4);
+        let mut out = [0i16; 640];
+        assert_eq!(port.read(&mut out), 640);
+    }
 }
 
 // =========================================================================
@@ -585,8 +2029,8 @@ mod tone {
         let n = tone_gen.generate(&mut samples);
         assert_eq!(n, 256);
 
-        let mut goertzel_desc = GoertzelDescriptor::new(440.0, 256);
-        let mut detector = GoertzelDetector::new(&mut goertzel_desc).unwrap();
+        let goertzel_desc = GoertzelDescriptor::new(440.0, 256);
+        let mut detector = GoertzelDetector::new(&goertzel_desc).unwrap();
 
         detector.update(&samples);
         let result = detector.result();
@@ -612,14 +2056,14 @@ mod tone {
         tone_gen.generate(&mut samples);
 
         // Detect at 440Hz (on-frequency)
-        let mut desc_on = GoertzelDescriptor::new(440.0, 256);
-        let mut det_on = GoertzelDetector::new(&mut desc_on).unwrap();
+        let desc_on = GoertzelDescriptor::new(440.0, 256);
+        let mut det_on = GoertzelDetector::new(&desc_on).unwrap();
         det_on.update(&samples);
         let on_freq = det_on.result();
 
         // Detect at 1000Hz (off-frequency)
-        let mut desc_off = GoertzelDescriptor::new(1000.0, 256);
-        let mut det_off = GoertzelDetector::new(&mut desc_off).unwrap();
+        let desc_off = GoertzelDescriptor::new(1000.0, 256);
+        let mut det_off = GoertzelDetector::new(&desc_off).unwrap();
         det_off.update(&samples);
         let off_freq = det_off.result();
 
@@ -660,6 +2104,144 @@ mod tone {
             "expected non-zero samples in cadenced tone, found only {nonzero_count}"
         );
     }
+
+    #[test]
+    fn reinit_switches_tone() {
+        let dial = ToneGenDescriptor::new(
+            ToneFreq::new(440, -10),
+            ToneFreq::NONE,
+            ToneCadence::continuous(1000),
+            false,
+        )
+        .unwrap();
+        let mut tone_gen = ToneGenerator::new(&dial).unwrap();
+        drop(dial);
+        let mut samples = vec![0i16; 256];
+        tone_gen.generate(&mut samples);
+
+        let busy = ToneGenDescriptor::new(
+            ToneFreq::new(1000, -10),
+            ToneFreq::NONE,
+            ToneCadence::continuous(1000),
+            false,
+        )
+        .unwrap();
+        tone_gen.reinit(&busy).unwrap();
+        assert_eq!(tone_gen.generate(&mut samples), 256);
+
+        let desc_new = GoertzelDescriptor::new(1000.0, 256);
+        let mut det_new = GoertzelDetector::new(&desc_new).unwrap();
+        det_new.update(&samples);
+        let desc_old = GoertzelDescriptor::new(440.0, 256);
+        let mut det_old = GoertzelDetector::new(&desc_old).unwrap();
+        det_old.update(&samples);
+        assert!(det_old.result() < det_new.result() * 0.01);
+    }
+
+    fn tone_power(samples: &[i16], freq: f32) -> f32 {
+        let desc = GoertzelDescriptor::new(freq, samples.len());
+        let mut det = GoertzelDetector::new(&desc).unwrap();
+        det.update(samples);
+        det.result()
+    }
+
+    #[test]
+    fn descriptor_is_shared_across_threads() {
+        static BANK: std::sync::LazyLock<[GoertzelDescriptor; 2]> =
+            std::sync::LazyLock::new(|| [440.0, 1000.0].map(|f| GoertzelDescriptor::new(f, 256)));
+        let desc = ToneGenDescriptor::new(
+            ToneFreq::new(440, -10),
+            ToneFreq::NONE,
+            ToneCadence::continuous(1000),
+            false,
+        )
+        .unwrap();
+        let mut samples = vec![0i16; 256];
+        ToneGenerator::new(&desc).unwrap().generate(&mut samples);
+
+        let powers: Vec<[f32; 2]> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        BANK.each_ref().map(|d| {
+                            let mut det = GoertzelDetector::new(d).unwrap();
+                            det.update(&samples);
+                            det.result()
+                        })
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        for [on, off] in &powers {
+            assert_eq!([*on, *off], powers[0]);
+            assert!(*off < *on * 0.01);
+        }
+        let copy = BANK[0];
+        assert_eq!(copy.freq(), 440.0);
+        assert_eq!(copy.samples(), 256);
+    }
+
+    #[test]
+    fn gain_ramps_to_silence() {
+        let desc = ToneGenDescriptor::new(
+            ToneFreq::new(440, -10),
+            ToneFreq::NONE,
+            ToneCadence::continuous(1000),
+            false,
+        )
+        .unwrap();
+        let mut tone_gen = ToneGenerator::new(&desc).unwrap();
+        let mut before = vec![0i16; 160];
+        tone_gen.generate(&mut before);
+
+        tone_gen.set_gain(-6.0, 0);
+        let mut quieter = vec![0i16; 160];
+        tone_gen.generate(&mut quieter);
+        let ratio = super::rms(&quieter) / super::rms(&before);
+        assert!((ratio - 0.5).abs() < 0.02, "ratio {ratio}");
+
+        tone_gen.set_gain(f32::NEG_INFINITY, 20);
+        let mut fade = vec![0i16; 160];
+        assert_eq!(tone_gen.generate(&mut fade), 160);
+        let first = super::rms(&fade[..40]);
+        let last = super::rms(&fade[120..]);
+        assert!(last < first * 0.5, "fade {first} -> {last}");
+        let mut silent = vec![1i16; 160];
+        assert_eq!(tone_gen.generate(&mut silent), 160);
+        assert!(silent.iter().all(|&s| s == 0));
+        assert_eq!(tone_gen.gain(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn retune_fades_between_tones() {
+        let dial = ToneGenDescriptor::new(
+            ToneFreq::new(440, -10),
+            ToneFreq::NONE,
+            ToneCadence::continuous(1000),
+            false,
+        )
+        .unwrap();
+        let mut tone_gen = ToneGenerator::new(&dial).unwrap();
+        tone_gen.set_gain(3.0, 0);
+        let busy = ToneGenDescriptor::new(
+            ToneFreq::new(1000, -10),
+            ToneFreq::NONE,
+            ToneCadence::continuous(1000),
+            false,
+        )
+        .unwrap();
+        tone_gen.retune(busy, 10).unwrap();
+        assert_eq!(tone_gen.gain(), 3.0);
+
+        // 80 samples fading out, 80 fading in, then the new tone.
+        let mut samples = vec![0i16; 400];
+        assert_eq!(tone_gen.generate(&mut samples), 400);
+        assert!(samples[70..90].iter().all(|s| s.abs() < 2000));
+        assert!(tone_power(&samples[..80], 440.0) > tone_power(&samples[..80], 1000.0));
+        let tail = &samples[160..];
+        assert!(tone_power(tail, 440.0) < tone_power(tail, 1000.0) * 0.01);
+    }
 }
 
 // =========================================================================
@@ -711,16 +2293,62 @@ mod power_meter {
             "level_dbov(0.0) should return a positive integer, got {dbov_val}"
         );
     }
-}
 
-// =========================================================================
-// Echo canceller
-// =========================================================================
-mod echo {
-    use spandsp::echo::*;
+    #[test]
+    fn dc_restore_tracks_offset() {
+        let mut dc = DcRestore::new();
+        let mut last = 0;
+        for _ in 0..200_000 {
+            last = dc.restore(1000);
+        }
+        assert!((dc.estimate() - 1000).abs() <= 1, "{}", dc.estimate());
+        assert!(last.abs() <= 1, "{last}");
+    }
+
+    #[test]
+    fn level_meter_reports_offset_peak_and_clipping() {
+        let mut meter = LevelMeter::new().unwrap();
+        let tone: Vec<i16> = sine_wave(1000.0, 8000.0, 80000, 10000.0)
+            .into_iter()
+            .map(|s| s + 2000)
+            .collect();
+        meter.update(&tone);
+        meter.update(&[i16::MAX, i16::MIN, 0]);
+        let d = meter.diagnostics();
+        assert_eq!(d.samples, 80003);
+        assert_eq!(d.clipped, 2);
+        assert_eq!(d.peak, 32768);
+        assert!((d.dc_offset - 2000).abs() < 100, "{d}");
+        // A 10000 peak sine is about -7 dBm0; the offset must not add to it.
+        assert!((-10.0..-4.0).contains(&d.long_term_dbm0), "{d}");
+
+        let taken = meter.take_diagnostics();
+        assert_eq!(taken, d);
+        let after = meter.diagnostics();
+        assert_eq!((after.peak, after.clipped, after.samples), (0, 0, 0));
+        assert_eq!(after.long_term_dbm0, d.long_term_dbm0);
+    }
+}
+
+// =========================================================================
+// Echo canceller
+// =========================================================================
+mod echo {
+    use spandsp::double_talk::*;
+    use spandsp::echo::*;
 
     use super::*;
 
+    #[test]
+    fn debug_reflects_adaption_mode() {
+        let mut canceller = EchoCanceller::new(128, EchoCanFlags::ADAPTION).unwrap();
+        canceller.set_adaption_mode(EchoCanFlags::ADAPTION | EchoCanFlags::CLIP);
+        let s = format!("{canceller:?}");
+        assert!(s.starts_with("EchoCanceller"), "{s}");
+        assert!(s.contains("len: 128"), "{s}");
+        assert!(s.contains("CLIP"), "{s}");
+    }
+
     #[test]
     fn cancels_simple_echo() {
         let mut canceller = EchoCanceller::new(256, EchoCanFlags::default()).unwrap();
@@ -744,8 +2372,8 @@ mod echo {
         // After convergence, output power should be lower than input RX power
         // Only compare the second half (after convergence)
         let half = tx_signal.len() / 2;
-        let rx_power = rms_power(&rx_signal[half..]);
-        let out_power = rms_power(&output[half..]);
+        let rx_power = rms(&rx_signal[half..]);
+        let out_power = rms(&output[half..]);
 
         assert!(
             out_power < rx_power,
@@ -761,6 +2389,500 @@ mod echo {
             assert_eq!(out, 0, "silence through echo canceller should be 0");
         }
     }
+
+    #[test]
+    fn geigel_detects_near_end_speech() {
+        let mut detector = DoubleTalkDetector::new(64).unwrap();
+        detector.set_hangover(16);
+        let far = sine_wave(500.0, 8000.0, 400, 10000.0);
+        let near = sine_wave(1200.0, 8000.0, 400, 8000.0);
+
+        // Echo 12 dB down is not double talk.
+        for (i, &tx) in far.iter().enumerate() {
+            let echo = if i >= 8 { far[i - 8] / 4 } else { 0 };
+            assert!(!detector.update(tx, echo), "false detection at {i}");
+        }
+        // Near-end speech on top of the echo is.
+        let talking = far
+            .iter()
+            .zip(&near)
+            .filter(|&(&tx, &rx)| detector.update(tx, tx / 4 + rx))
+            .count();
+        assert!(talking > 300, "only {talking} samples flagged");
+        // Held for the hangover once the far end is alone again.
+        detector.update(far[0], 30000);
+        for _ in 0..16 {
+            assert!(detector.update(0, 0));
+        }
+        assert!(!detector.update(0, 0));
+        assert!(DoubleTalkDetector::new(0).is_err());
+    }
+
+    #[test]
+    fn processor_freezes_adaption_during_double_talk() {
+        let mut processor = EchoProcessor::new(128, EchoCanFlags::default()).unwrap();
+        processor.detector_mut().set_hangover(0);
+        processor.update(8000, 1000);
+        assert!(!processor.is_frozen());
+        assert!(
+            processor
+                .canceller()
+                .flags()
+                .contains(EchoCanFlags::ADAPTION)
+        );
+
+        processor.update(1000, 8000);
+        assert!(processor.is_frozen());
+        assert_eq!(processor.canceller().flags(), EchoCanFlags::NLP);
+        assert_eq!(processor.flags(), EchoCanFlags::default());
+
+        processor.update(0, 0);
+        assert!(!processor.is_frozen());
+        assert_eq!(processor.canceller().flags(), EchoCanFlags::default());
+
+        processor.update(1000, 8000);
+        processor.flush();
+        assert!(!processor.is_frozen());
+        assert_eq!(processor.canceller().flags(), EchoCanFlags::default());
+    }
+}
+
+// =========================================================================
+// Modem echo cancellation
+// =========================================================================
+mod modem_echo {
+    use spandsp::modem_echo::*;
+    use spandsp::noise::{NoiseClass, NoiseGenerator};
+
+    use super::*;
+
+    /// A far-end line signal and its echo, 6 dB down and 20 samples late.
+    fn line_and_echo(n: usize) -> (Vec<i16>, Vec<i16>) {
+        let mut tx = vec![0i16; n];
+        NoiseGenerator::new(NoiseClass::White, -15.0, 7)
+            .unwrap()
+            .generate(&mut tx);
+        let mut rx = vec![0i16; n];
+        for i in 20..n {
+            rx[i] = tx[i - 20] / 2;
+        }
+        (tx, rx)
+    }
+
+    #[test]
+    fn cancels_echo_while_adapting() {
+        let (tx, rx) = line_and_echo(16000);
+        let mut canceller = ModemEchoCanceller::new(64).unwrap();
+        assert!(canceller.is_adapting());
+        let out: Vec<i16> = tx
+            .iter()
+            .zip(&rx)
+            .map(|(&t, &r)| canceller.update(t, r))
+            .collect();
+        let half = tx.len() / 2;
+        let (rx_rms, out_rms) = (rms(&rx[half..]), rms(&out[half..]));
+        assert!(out_rms < rx_rms / 2.0, "rx {rx_rms:.1}, out {out_rms:.1}");
+    }
+
+    #[test]
+    fn frozen_filter_holds() {
+        let (tx, rx) = line_and_echo(800);
+        let mut canceller = ModemEchoCanceller::new(64).unwrap();
+        canceller.set_adaption(false);
+        assert!(!canceller.is_adapting());
+        // A filter that never adapted leaves the echo alone.
+        for (&t, &r) in tx.iter().zip(&rx) {
+            assert_eq!(canceller.update(t, r), r);
+        }
+        canceller.flush();
+        assert!(format!("{canceller:?}").contains("adapting: false"));
+    }
+
+    #[test]
+    fn tail_must_be_a_power_of_two() {
+        assert!(ModemEchoCanceller::new(100).is_err());
+        assert!(ModemEchoCanceller::new(0).is_err());
+        assert_eq!(ModemEchoCanceller::new(256).unwrap().tail_len(), 256);
+    }
+}
+
+// =========================================================================
+// FSK modems
+// =========================================================================
+mod fsk {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::fsk::*;
+
+    fn pattern() -> Vec<bool> {
+        (0..64)
+            .map(|i| (0x5A3C_96E1_0F0F_33CCu64 >> i) & 1 == 1)
+            .collect()
+    }
+
+    /// Send `pattern()` between runs of marks, returning the bits received
+    /// and whether the receiver saw carrier while the signal was on.
+    fn roundtrip(spec: FskSpec, framing: FskFraming) -> (Vec<bool>, bool) {
+        let mut line = vec![true; 100];
+        line.extend(pattern());
+        line.extend([true; 100]);
+        let mut bits = line.into_iter();
+        let mut tx = FskTx::new(spec, move || bits.next()).unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let mut rx = FskRx::new(spec, framing, move |bit| sink.lock().unwrap().push(bit)).unwrap();
+        let mut amp = [0i16; 160];
+        let mut carrier = false;
+        loop {
+            let n = tx.generate(&mut amp);
+            rx.rx(&amp[..n]);
+            carrier |= rx.carrier();
+            if n < amp.len() {
+                break;
+            }
+        }
+        rx.rx(&[0i16; 800]);
+        let bits = received.lock().unwrap().clone();
+        (bits, carrier)
+    }
+
+    fn contains(haystack: &[bool], needle: &[bool]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn bell202_async_roundtrip() {
+        let (bits, carrier) = roundtrip(FskSpec::Bell202, FskFraming::Async);
+        assert!(carrier);
+        assert!(contains(&bits, &pattern()), "{} bits", bits.len());
+    }
+
+    #[test]
+    fn v21_sync_roundtrip() {
+        let (bits, carrier) = roundtrip(FskSpec::V21Ch2, FskFraming::Sync);
+        assert!(carrier);
+        assert!(contains(&bits, &pattern()), "{} bits", bits.len());
+    }
+
+    #[test]
+    fn specs_describe_their_modems() {
+        assert_eq!(FskSpec::Bell202.freq_one(), 1200);
+        assert_eq!(FskSpec::Bell202.freq_zero(), 2200);
+        assert_eq!(FskSpec::Bell202.baud_rate(), 1200.0);
+        assert_eq!(FskSpec::V21Ch1.baud_rate(), 300.0);
+        assert!((FskSpec::Weitbrecht4545.baud_rate() - 45.45).abs() < 0.01);
+        assert_eq!(FskSpec::V23Ch1.freq_one(), 1300);
+        assert!(FskSpec::V21Ch2.min_level() < FskSpec::V21Ch2.tx_level());
+        assert!(!FskSpec::V23Ch2.to_string().is_empty());
+    }
+
+    #[test]
+    fn tx_ends_with_its_bits_and_restarts() {
+        let mut sent = 0;
+        let mut tx = FskTx::new(FskSpec::V21Ch1, move || {
+            sent += 1;
+            (sent <= 30).then_some(true)
+        })
+        .unwrap();
+        // 30 bits at 300 baud is 800 samples.
+        let mut amp = vec![0i16; 8000];
+        let n = tx.generate(&mut amp);
+        assert!((700..=900).contains(&n), "{n} samples");
+        tx.restart(FskSpec::Bell103Ch1).unwrap();
+        assert_eq!(tx.spec(), FskSpec::Bell103Ch1);
+        assert!(format!("{tx:?}").contains("Bell103Ch1"));
+    }
+
+    #[test]
+    fn v21_hdlc_receiver_recovers_t30_frames() {
+        use spandsp::hdlc::HdlcTx;
+
+        // A DIS-like frame: address, final control, FCF and a little FIF.
+        let frame = [0xFFu8, 0x13, 0x80, 0x00, 0xEE, 0xF8];
+        let mut hdlc = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        let mut bits: Vec<bool> = (0..300).map(|_| hdlc.get_bit() != 0).collect();
+        hdlc.frame(&frame).unwrap();
+        for _ in 0..400 {
+            let bit = hdlc.get_bit();
+            if bit < 0 {
+                break;
+            }
+            bits.push(bit != 0);
+        }
+        let mut bits = bits.into_iter();
+        let mut tx = FskTx::new(FskSpec::V21Ch2, move || bits.next()).unwrap();
+
+        let mut rx = V21HdlcReceiver::new(false).unwrap();
+        let mut frames = Vec::new();
+        let mut carrier = false;
+        let mut amp = [0i16; 160];
+        loop {
+            let n = tx.generate(&mut amp);
+            frames.extend(rx.rx(&amp[..n]));
+            carrier |= rx.carrier();
+            if n < amp.len() {
+                break;
+            }
+        }
+        frames.extend(rx.rx(&[0i16; 800]));
+        assert!(carrier);
+        assert!(!rx.carrier());
+        assert_eq!(frames.len(), 1, "{frames:?}");
+        assert!(frames[0].crc_ok);
+        assert_eq!(frames[0].data, frame);
+        assert_eq!(frames[0].fcf(), Some(0x80));
+        assert!(frames[0].is_final());
+    }
+}
+
+// =========================================================================
+// Async serial framing
+// =========================================================================
+mod async_serial {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::async_serial::*;
+    use spandsp::bit_source::BitSource;
+    use spandsp::fsk::{FskFraming, FskRx, FskSpec, FskTx};
+
+    fn sender(format: AsyncFormat, data: &'static [u8]) -> AsyncTx {
+        let mut bytes = data.iter().copied();
+        AsyncTx::new(format, move || bytes.next()).unwrap()
+    }
+
+    fn receiver(format: AsyncFormat) -> (AsyncRx, Arc<Mutex<Vec<u8>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let rx = AsyncRx::new(format, move |byte| sink.lock().unwrap().push(byte)).unwrap();
+        (rx, received)
+    }
+
+    #[test]
+    fn bytes_roundtrip_as_8n1() {
+        let format = AsyncFormat::default();
+        let mut tx = sender(format, b"Hi!");
+        let (mut rx, received) = receiver(format);
+        let bits: Vec<bool> = tx.bits().collect();
+        assert_eq!(bits.len(), 3 * 10);
+        // Start bit low, then the data LSB first, then the stop bit high.
+        assert!(!bits[0]);
+        assert_eq!(
+            bits[1..9],
+            [false, false, false, true, false, false, true, false]
+        );
+        assert!(bits[9]);
+        rx.put_bits(bits);
+        assert_eq!(*received.lock().unwrap(), b"Hi!");
+    }
+
+    #[test]
+    fn parity_and_stop_bits_are_framed() {
+        let format = AsyncFormat::new(7, Parity::Even, 2);
+        assert_eq!(format.to_string(), "7E2");
+        assert_eq!(format.bits_per_char(), 11);
+        let mut tx = sender(format, b"OK");
+        let (mut rx, received) = receiver(format);
+        let bits: Vec<bool> = tx.bits().collect();
+        assert_eq!(bits.len(), 2 * 11);
+        rx.put_bits(bits);
+        assert_eq!(*received.lock().unwrap(), b"OK");
+    }
+
+    #[test]
+    fn rides_on_an_fsk_modem() {
+        let format = AsyncFormat::default();
+        let mut marks = std::iter::repeat_n(true, 60);
+        let mut tx = sender(format, b"ATZ\r");
+        let source = move || marks.next().or_else(|| tx.next_bit());
+        let mut modem = FskTx::new(FskSpec::V21Ch1, source).unwrap();
+        let (mut rx, received) = receiver(format);
+        let mut demod = FskRx::new(FskSpec::V21Ch1, FskFraming::Async, move |bit| {
+            rx.put_bit(bit)
+        })
+        .unwrap();
+        let mut amp = [0i16; 160];
+        loop {
+            let n = modem.generate(&mut amp);
+            demod.rx(&amp[..n]);
+            if n < amp.len() {
+                break;
+            }
+        }
+        demod.rx(&[0i16; 800]);
+        assert_eq!(*received.lock().unwrap(), b"ATZ\r");
+    }
+
+    #[test]
+    fn rejects_bad_formats() {
+        assert!(AsyncTx::new(AsyncFormat::new(9, Parity::None, 1), || None).is_err());
+        assert!(AsyncRx::new(AsyncFormat::new(8, Parity::Odd, 3), |_| {}).is_err());
+        assert_eq!(AsyncFormat::default().to_string(), "8N1");
+    }
+}
+
+// =========================================================================
+// AT command interpreter
+// =========================================================================
+mod at_interpreter {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::at_interpreter::*;
+
+    struct Dte {
+        at: AtInterpreter,
+        output: Arc<Mutex<Vec<u8>>>,
+        controls: Arc<Mutex<Vec<ModemControl>>>,
+    }
+
+    impl Dte {
+        fn new() -> Self {
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let controls = Arc::new(Mutex::new(Vec::new()));
+            let (out, ctl) = (Arc::clone(&output), Arc::clone(&controls));
+            let at = AtInterpreter::new(
+                move |bytes| out.lock().unwrap().extend_from_slice(bytes),
+                move |op| {
+                    ctl.lock().unwrap().push(op);
+                    true
+                },
+            )
+            .unwrap();
+            Self {
+                at,
+                output,
+                controls,
+            }
+        }
+
+        fn take_output(&self) -> String {
+            String::from_utf8_lossy(&std::mem::take(&mut *self.output.lock().unwrap())).into_owned()
+        }
+    }
+
+    #[test]
+    fn answers_at_with_ok() {
+        let mut dte = Dte::new();
+        dte.at.interpret(b"AT\r");
+        assert!(dte.take_output().contains("OK"));
+        dte.at.put_response_code(AtResponseCode::Error);
+        assert!(dte.take_output().contains("ERROR"));
+    }
+
+    #[test]
+    fn dialling_asks_for_a_call() {
+        let mut dte = Dte::new();
+        dte.at.interpret(b"ATDT5551234\r");
+        let controls = dte.controls.lock().unwrap();
+        assert!(
+            controls
+                .iter()
+                .any(|c| matches!(c, ModemControl::Call(n) if n.contains("5551234"))),
+            "{controls:?}"
+        );
+    }
+
+    #[test]
+    fn class1_commands_reach_the_handler() {
+        let mut dte = Dte::new();
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&commands);
+        dte.at.set_class1_handler(move |command| {
+            sink.lock().unwrap().push(command);
+            match command.kind {
+                Class1Kind::Silence => Class1Reply::Later,
+                _ => Class1Reply::Ok,
+            }
+        });
+        // Class 1 commands are refused while on hook.
+        dte.at.interpret(b"AT+FCLASS=1\r");
+        dte.at.interpret(b"ATH1\r");
+        dte.take_output();
+        dte.at.interpret(b"AT+FRH=3\r");
+        assert_eq!(
+            *commands.lock().unwrap(),
+            vec![Class1Command {
+                transmit: false,
+                kind: Class1Kind::Hdlc,
+                value: 3,
+            }]
+        );
+        assert!(dte.take_output().contains("OK"));
+
+        dte.at.interpret(b"AT+FTS=8\r");
+        assert_eq!(commands.lock().unwrap().len(), 2);
+        assert!(!dte.take_output().contains("OK"));
+    }
+
+    #[test]
+    fn call_info_rejects_nul() {
+        let mut dte = Dte::new();
+        assert!(dte.at.set_call_info("NMBR", "555\0").is_err());
+        assert!(dte.at.set_call_info("NMBR", "5551234").is_ok());
+        assert!(dte.at.put_response("HELLO\0").is_err());
+    }
+}
+
+// =========================================================================
+// Modem connect tones
+// =========================================================================
+mod modem_connect_tones {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::modem_connect_tones::*;
+
+    /// Generate `secs` seconds of `tone`, and what a detector for it heard.
+    fn heard(tone: ConnectTone, secs: usize) -> Option<ConnectTone> {
+        let mut tx = ConnectToneTx::new(tone).unwrap();
+        let mut rx = ConnectToneRx::new(tone).unwrap();
+        let mut buf = [0i16; 160];
+        for _ in 0..secs * 50 {
+            let n = tx.generate(&mut buf);
+            buf[n..].fill(0);
+            rx.rx(&buf);
+        }
+        rx.take_detected()
+    }
+
+    #[test]
+    fn cng_roundtrip() {
+        assert_eq!(heard(ConnectTone::Cng, 4), Some(ConnectTone::Cng));
+    }
+
+    #[test]
+    fn ansam_roundtrip() {
+        assert_eq!(heard(ConnectTone::AnsAm, 2), Some(ConnectTone::AnsAm));
+    }
+
+    #[test]
+    fn callback_reports_the_tone() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let mut rx = ConnectToneRx::with_callback(ConnectTone::Ans, move |tone, _level| {
+            log.lock().unwrap().push(tone);
+        })
+        .unwrap();
+        let mut tx = ConnectToneTx::new(ConnectTone::Ans).unwrap();
+        let mut buf = [0i16; 160];
+        for _ in 0..100 {
+            tx.generate(&mut buf);
+            rx.rx(&buf);
+        }
+        assert!(seen.lock().unwrap().contains(&Some(ConnectTone::Ans)));
+        assert!(format!("{rx:?}").contains("has_callback: true"));
+    }
+
+    #[test]
+    fn silence_is_no_tone() {
+        let mut rx = ConnectToneRx::fax_answer().unwrap();
+        rx.rx(&[0i16; 8000]);
+        assert_eq!(rx.take_detected(), None);
+    }
+
+    #[test]
+    fn v21_preamble_cannot_be_generated() {
+        assert!(ConnectToneTx::new(ConnectTone::V21Preamble).is_err());
+        assert_eq!(ConnectTone::AnsAmPr.to_string(), "ANSam/");
+    }
 }
 
 // =========================================================================
@@ -818,19 +2940,113 @@ mod t4 {
         assert_eq!(stats.image_length, 100);
         assert_eq!(stats.compression, 2);
     }
+
+    #[test]
+    fn page_header_validation() {
+        let ok = PageHeader::new()
+            .with_local_ident("+44 20 7946 0000")
+            .with_info("ACME Corp")
+            .with_overlay(true)
+            .with_timezone("GMT0BST,M3.5.0/1,M10.5.0");
+        assert!(ok.validate().is_ok());
+        assert!(ok.overlays_image);
+
+        let long_ident = PageHeader::new().with_local_ident("1".repeat(MAX_IDENT_LEN + 1));
+        assert!(long_ident.validate().is_err());
+        let bad_ident = PageHeader::new().with_local_ident("caf\u{e9}");
+        assert!(bad_ident.validate().is_err());
+        let long_info = PageHeader::new().with_info("x".repeat(MAX_PAGE_HEADER_INFO_LEN + 1));
+        assert!(long_info.validate().is_err());
+        assert!(PageHeader::new().with_timezone("").validate().is_err());
+    }
+
+    #[test]
+    fn paged_rx_names_each_page() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use spandsp::t4_rx::{PagedT4Rx, RxPageInfo};
+
+        let dir = std::env::temp_dir().join(format!("spandsp-paged-rx-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let seen = Rc::new(RefCell::new(Vec::<RxPageInfo>::new()));
+        let seen_clone = seen.clone();
+        let base = dir.clone();
+        let mut rx = PagedT4Rx::new(T4Compression::T4_1D | T4Compression::T6, move |info| {
+            seen_clone.borrow_mut().push(*info);
+            base.join(format!("page-{:03}.tif", info.page))
+        });
+        rx.set_x_resolution(8040);
+        rx.set_y_resolution(7700);
+
+        rx.start_page().unwrap();
+        rx.start_page().unwrap();
+
+        let seen = seen.borrow();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].page, 1);
+        assert_eq!(seen[1].page, 2);
+        assert_eq!(seen[1].x_resolution, 8040);
+        assert_eq!(seen[1].y_resolution, 7700);
+        assert_eq!(
+            rx.written_paths(),
+            [dir.join("page-001.tif"), dir.join("page-002.tif")]
+        );
+
+        drop(rx);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn tx_page_selection_changes_on_an_open_file() {
+        use spandsp::t4_tx::T4Tx;
+        use spandsp::t37::{self, FaxPage, TiffProfile};
+
+        let rows = vec![vec![0u8; 1728 / 8]; 40];
+        let page = FaxPage::encode(T4Compression::T4_1D, 1728, &rows, 204.0, 98.0).unwrap();
+        let tiff = t37::write_tiff(&[page.clone(), page.clone(), page], TiffProfile::S).unwrap();
+        let path = std::env::temp_dir().join("spandsp_t4_tx_pages.tif");
+        std::fs::write(&path, tiff).unwrap();
+
+        let mut tx = T4Tx::new(path.to_str().unwrap(), -1, -1).unwrap();
+        assert_eq!(tx.pages_in_file(), 3);
+        tx.send_only([2, 0]).unwrap();
+        assert_eq!(tx.page_range(), (0, 2));
+        assert_eq!(tx.skipped_pages().collect::<Vec<_>>(), vec![1]);
+
+        tx.start_page().unwrap();
+        assert_eq!(tx.current_page_in_file(), 0);
+        tx.end_page().unwrap();
+        // Page 1 is passed over.
+        tx.start_page().unwrap();
+        assert_eq!(tx.current_page_in_file(), 2);
+        tx.end_page().unwrap();
+        assert!(tx.start_page().is_err());
+
+        tx.select_pages(1, 1).unwrap();
+        assert_eq!(tx.skipped_pages().count(), 0);
+        tx.start_page().unwrap();
+        assert_eq!(tx.current_page_in_file(), 1);
+        assert!(tx.send_only([]).is_err());
+    }
 }
 
 // =========================================================================
-// T.4/T.6 encode/decode roundtrip (requires fax feature)
+// T.4/T.6, T.85, T.42 and T.43 encode/decode (requires fax feature)
 // =========================================================================
 #[cfg(feature = "fax")]
 mod t4_codec {
     use std::cell::RefCell;
     use std::rc::Rc;
 
+    use spandsp::image_translate::ImageTranslate;
     use spandsp::t4::*;
     use spandsp::t4_rx::T4T6Decoder;
     use spandsp::t4_tx::T4T6Encoder;
+    use spandsp::t42::{T42Decoder, T42Encoder};
+    use spandsp::t43::{T43Decoder, T43Encoder};
+    use spandsp::t85::{T85Decoder, T85Encoder};
 
     /// Standard fax width in pixels.
     const IMAGE_WIDTH: i32 = 1728;
@@ -1033,4 +3249,4170 @@ mod t4_codec {
             );
         }
     }
+
+    #[test]
+    fn t85_encode_decode_roundtrip() {
+        let num_rows = 10u32;
+        let row_index = Rc::new(RefCell::new(0u32));
+        let row_index_enc = row_index.clone();
+
+        let mut encoder = T85Encoder::new(IMAGE_WIDTH as u32, num_rows, move |buf: &mut [u8]| {
+            let mut idx = row_index_enc.borrow_mut();
+            if *idx >= num_rows {
+                return 0;
+            }
+            let len = buf.len().min(ROW_BYTES);
+            buf[..len].fill(if *idx % 2 == 0 { 0x00 } else { 0xFF });
+            *idx += 1;
+            len
+        })
+        .unwrap();
+        assert_eq!(encoder.image_width(), IMAGE_WIDTH as u32);
+
+        let mut encoded = vec![0u8; 16384];
+        let mut total_encoded = 0;
+        loop {
+            let n = encoder.get(&mut encoded[total_encoded..]);
+            if n == 0 {
+                break;
+            }
+            total_encoded += n;
+        }
+        assert!(total_encoded > 0, "T.85 encoder produced no data");
+        assert!(encoder.image_complete());
+
+        let decoded_rows = Rc::new(RefCell::new(Vec::<Vec<u8>>::new()));
+        let decoded_rows_clone = decoded_rows.clone();
+
+        let mut decoder = T85Decoder::new(move |row_data: &[u8]| {
+            decoded_rows_clone.borrow_mut().push(row_data.to_vec());
+            true
+        })
+        .unwrap();
+
+        decoder.put(&encoded[..total_encoded]);
+        decoder.finish();
+        assert_eq!(decoder.image_width(), IMAGE_WIDTH as u32);
+        assert_eq!(decoder.image_length(), num_rows);
+
+        let rows = decoded_rows.borrow();
+        assert!(
+            rows.len() >= 2,
+            "T.85: expected at least 2 decoded rows, got {}",
+            rows.len()
+        );
+        for (i, row) in rows.iter().filter(|row| !row.is_empty()).enumerate() {
+            let expected = if i % 2 == 0 { 0x00u8 } else { 0xFFu8 };
+            assert!(
+                row.iter().all(|&b| b == expected),
+                "T.85: row {i} doesn't match expected pattern"
+            );
+        }
+    }
+
+    #[test]
+    fn t42_grey_encode_decode_roundtrip() {
+        const WIDTH: u32 = 64;
+        const LENGTH: u32 = 32;
+        const GREY: u8 = 0x80;
+        let row_index = Rc::new(RefCell::new(0u32));
+        let row_index_enc = row_index.clone();
+
+        let mut encoder = T42Encoder::new(
+            T4ImageType::Gray8,
+            WIDTH,
+            LENGTH,
+            move |buf: &mut [u8]| {
+                let mut idx = row_index_enc.borrow_mut();
+                if *idx >= LENGTH {
+                    return 0;
+                }
+                let len = buf.len().min(T4ImageType::Gray8.row_bytes(WIDTH));
+                buf[..len].fill(GREY);
+                *idx += 1;
+                len
+            },
+        )
+        .unwrap();
+        assert_eq!(encoder.image_type(), T4ImageType::Gray8);
+
+        let mut encoded = Vec::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            let n = encoder.get(&mut chunk);
+            encoded.extend_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                break;
+            }
+        }
+        assert!(!encoded.is_empty(), "T.42 encoder produced no data");
+
+        let decoded_rows = Rc::new(RefCell::new(Vec::<Vec<u8>>::new()));
+        let decoded_rows_clone = decoded_rows.clone();
+        let mut decoder = T42Decoder::new(move |row_data: &[u8]| {
+            if !row_data.is_empty() {
+                decoded_rows_clone.borrow_mut().push(row_data.to_vec());
+            }
+            true
+        })
+        .unwrap();
+        decoder.put(&encoded);
+        decoder.finish();
+        assert_eq!(decoder.image_width(), WIDTH);
+        assert_eq!(decoder.image_length(), LENGTH);
+
+        // JPEG is lossy, but a flat grey page should come back close.
+        let rows = decoded_rows.borrow();
+        assert_eq!(rows.len(), LENGTH as usize);
+        for (i, row) in rows.iter().enumerate() {
+            assert!(
+                row.iter().all(|&b| b.abs_diff(GREY) <= 8),
+                "T.42: row {i} drifted from grey"
+            );
+        }
+    }
+
+    #[test]
+    fn t42_image_types() {
+        assert_eq!(T4ImageType::Gray8.row_bytes(1728), 1728);
+        assert_eq!(T4ImageType::Colour8.row_bytes(1728), 5184);
+
+        let mut encoder =
+            T42Encoder::new(T4ImageType::Colour8, 64, 32, |_buf: &mut [u8]| 0).unwrap();
+        assert!(encoder.set_image_type(T4ImageType::Gray12).is_err());
+        assert!(encoder.set_image_type(T4ImageType::Bilevel).is_err());
+        assert_eq!(encoder.image_type(), T4ImageType::Colour8);
+        assert!(T42Encoder::new(T4ImageType::Colour12, 64, 32, |_buf: &mut [u8]| 0).is_err());
+    }
+
+    #[test]
+    fn t43_encoder_and_decoder_setup() {
+        let mut encoder = T43Encoder::new(T4ImageType::Gray8, 64, 32, |buf: &mut [u8]| {
+            buf.fill(0x40);
+            buf.len()
+        })
+        .unwrap();
+        assert_eq!(encoder.image_width(), 64);
+        assert_eq!(encoder.image_length(), 32);
+        assert_eq!(encoder.image_type(), T4ImageType::Gray8);
+        encoder.set_image_type(T4ImageType::Colour8).unwrap();
+        assert_eq!(encoder.image_type(), T4ImageType::Colour8);
+        assert_eq!(encoder.image_type().row_bytes(64), 192);
+        encoder.set_image_type(T4ImageType::Gray12).unwrap();
+        assert_eq!(encoder.image_type().row_bytes(64), 128);
+        assert!(encoder.set_image_type(T4ImageType::Bilevel).is_err());
+        assert_eq!(encoder.image_type(), T4ImageType::Gray12);
+
+        let mut decoder = T43Decoder::new(|_row: &[u8]| true).unwrap();
+        decoder.set_image_size_constraints(1728, 2400).unwrap();
+        decoder.restart().unwrap();
+    }
+
+    #[test]
+    fn t4_image_type_row_bytes() {
+        assert_eq!(T4ImageType::Bilevel.row_bytes(1728), 216);
+        assert_eq!(T4ImageType::Bilevel.row_bytes(1730), 217);
+        assert_eq!(T4ImageType::Gray8.row_bytes(100), 100);
+        assert_eq!(T4ImageType::Colour8.row_bytes(100), 300);
+        assert_eq!(T4ImageType::Colour12.row_bytes(100), 600);
+    }
+
+    #[test]
+    fn image_translate_grey_to_bilevel() {
+        const WIDTH: u32 = 64;
+        const LENGTH: u32 = 16;
+        // Top half black, bottom half white.
+        let row_index = Rc::new(RefCell::new(0u32));
+        let row_index_in = row_index.clone();
+        let mut translate = ImageTranslate::new(
+            T4ImageType::Gray8,
+            WIDTH,
+            LENGTH,
+            T4ImageType::Bilevel,
+            None,
+            move |buf: &mut [u8]| {
+                let mut idx = row_index_in.borrow_mut();
+                if *idx >= LENGTH {
+                    return 0;
+                }
+                let len = buf.len().min(WIDTH as usize);
+                buf[..len].fill(if *idx < LENGTH / 2 { 0x00 } else { 0xFF });
+                *idx += 1;
+                len
+            },
+        )
+        .unwrap();
+        assert_eq!(translate.output_width(), WIDTH as i32);
+        assert_eq!(translate.output_length(), LENGTH as i32);
+        assert_eq!(translate.output_row_bytes(), 8);
+
+        let mut rows = Vec::new();
+        let mut buf = vec![0u8; translate.output_row_bytes()];
+        loop {
+            let n = translate.row(&mut buf);
+            if n == 0 {
+                break;
+            }
+            rows.push(buf[..n].to_vec());
+        }
+        assert_eq!(rows.len(), LENGTH as usize);
+        assert!(
+            rows[0].iter().all(|&b| b == 0xFF),
+            "black row came out white"
+        );
+        assert!(
+            rows[LENGTH as usize - 1].iter().all(|&b| b == 0x00),
+            "white row came out black"
+        );
+    }
+}
+
+// =========================================================================
+// Time zones (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod tz {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use spandsp::t4::PageHeader;
+    use spandsp::tz::Timezone;
+
+    const PARIS: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+    #[test]
+    fn local_time_follows_dst_rules() {
+        let tz = Timezone::new(PARIS).unwrap();
+        assert_eq!(tz.spec(), PARIS);
+
+        // 2024-01-15 12:00:00 UTC
+        let winter = tz
+            .local_time(UNIX_EPOCH + Duration::from_secs(1_705_320_000))
+            .unwrap();
+        assert_eq!((winter.year, winter.month, winter.day), (2024, 1, 15));
+        assert_eq!(winter.hour, 13);
+        assert!(!winter.is_dst);
+
+        // 2024-07-01 12:00:00 UTC
+        let summer = tz
+            .local_time(UNIX_EPOCH + Duration::from_secs(1_719_835_200))
+            .unwrap();
+        assert_eq!(summer.hour, 14);
+        assert!(summer.is_dst);
+        assert_eq!(summer.to_string(), "2024-07-01 14:00:00");
+
+        assert_eq!(tz.abbreviation(false).as_deref(), Some("CET"));
+        assert_eq!(tz.abbreviation(true).as_deref(), Some("CEST"));
+    }
+
+    #[test]
+    fn rejects_nul_and_feeds_page_header() {
+        assert!(Timezone::new("CET\0").is_err());
+
+        let tz = Timezone::new(PARIS).unwrap();
+        let header = PageHeader::new().with_tz(&tz);
+        assert_eq!(header.timezone.as_deref(), Some(PARIS));
+        assert!(header.validate().is_ok());
+        assert_eq!(tz.clone().spec(), PARIS);
+    }
+}
+
+// =========================================================================
+// Test charts (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod test_chart {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use spandsp::t4::T4Compression;
+    use spandsp::t4_rx::T4T6Decoder;
+    use spandsp::t4_tx::T4T6Encoder;
+    use spandsp::test_chart::*;
+
+    fn encode(chart: TestChart, compression: T4Compression) -> Vec<u8> {
+        let (width, length) = (chart.width() as i32, chart.length() as i32);
+        let mut encoder =
+            T4T6Encoder::new(compression, width, length, chart.into_row_reader()).unwrap();
+        let mut encoded = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = encoder.get(&mut buf);
+            if n == 0 {
+                break;
+            }
+            encoded.extend_from_slice(&buf[..n]);
+        }
+        encoded
+    }
+
+    #[test]
+    fn rows_have_chart_dimensions() {
+        let chart = TestChart::a4(TestPattern::HalftoneRamp);
+        let rows = chart.rows();
+        assert_eq!(rows.len(), A4_LENGTH_STANDARD as usize);
+        assert!(rows.into_iter().all(|row| row.len() == 216));
+
+        assert!(TestChart::new(TestPattern::White, 0, 10).is_err());
+        assert!(TestChart::new(TestPattern::Checkerboard { size: 0 }, 8, 8).is_err());
+
+        // A glyph dot bigger than the page leaves it blank.
+        let huge = TestChart::new(TestPattern::TextLines { scale: u32::MAX }, 64, 4).unwrap();
+        assert!(huge.rows().all(|row| row.iter().all(|&b| b == 0)));
+    }
+
+    #[test]
+    fn stripes_are_drawn_msb_first() {
+        let chart = TestChart::new(TestPattern::VerticalStripes { width: 4 }, 20, 2).unwrap();
+        assert_eq!(chart.row(0), vec![0xF0, 0xF0, 0xF0]);
+
+        let chart = TestChart::new(TestPattern::HorizontalStripes { height: 2 }, 8, 4).unwrap();
+        let rows: Vec<_> = chart.rows().collect();
+        assert_eq!(rows, vec![vec![0xFF], vec![0xFF], vec![0x00], vec![0x00]]);
+    }
+
+    #[test]
+    fn text_lines_survive_t4_roundtrip() {
+        let chart = TestChart::new(TestPattern::TextLines { scale: 2 }, 1728, 200).unwrap();
+        let encoded = encode(chart, T4Compression::T4_2D);
+
+        let decoded = Rc::new(RefCell::new(Vec::<Vec<u8>>::new()));
+        let sink = decoded.clone();
+        let mut decoder = T4T6Decoder::new(T4Compression::T4_2D, 1728, move |row: &[u8]| {
+            // An empty row marks the end of the image.
+            if !row.is_empty() {
+                sink.borrow_mut().push(row.to_vec());
+            }
+            true
+        })
+        .unwrap();
+        decoder.put(&encoded);
+
+        let decoded = decoded.borrow();
+        let expected: Vec<_> = chart.rows().collect();
+        assert!(expected.iter().any(|row| row.iter().any(|&b| b != 0)));
+        assert_eq!(decoded.len(), expected.len());
+        assert!(decoded.iter().zip(&expected).all(|(got, want)| got == want));
+    }
+
+    #[test]
+    fn halftone_compresses_worse_than_white() {
+        let white = encode(TestChart::a4(TestPattern::White), T4Compression::T6);
+        let halftone = encode(
+            TestChart::a4(TestPattern::Halftone { level: 128 }),
+            T4Compression::T6,
+        );
+        assert!(
+            halftone.len() > white.len() * 10,
+            "white {} bytes, halftone {} bytes",
+            white.len(),
+            halftone.len()
+        );
+    }
+}
+
+// =========================================================================
+// FAX session (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod fax {
+    use spandsp::error::SpanDspError;
+    use spandsp::fax::*;
+    use spandsp::t30::TxDocument;
+
+    #[test]
+    fn tx_documents_queue() {
+        let mut fax = FaxState::new(true).unwrap();
+        assert_eq!(fax.pending_tx_documents(), 0);
+
+        let docs = [
+            TxDocument::new("cover.tif"),
+            TxDocument::new("body.tif").with_pages(2, 5),
+            TxDocument::new("appendix.tif"),
+        ];
+        fax.set_tx_documents(docs).unwrap();
+        // The first document is loaded straight away; the rest wait for EOM.
+        assert_eq!(fax.pending_tx_documents(), 2);
+    }
+
+    #[test]
+    fn tx_documents_rejects_empty_and_nul() {
+        let mut fax = FaxState::new(true).unwrap();
+        assert!(matches!(
+            fax.set_tx_documents([]),
+            Err(SpanDspError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            fax.set_tx_documents([TxDocument::new("a.tif"), TxDocument::new("b\0.tif")]),
+            Err(SpanDspError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn page_header_on_t30() {
+        use spandsp::t4::PageHeader;
+
+        let mut fax = FaxState::new(true).unwrap();
+        let mut t30 = fax.get_t30_state().unwrap();
+        let header = PageHeader::new()
+            .with_local_ident("+1 555 0100")
+            .with_info("Front desk")
+            .with_timezone("EST5EDT,M3.2.0,M11.1.0");
+        t30.set_page_header(&header).unwrap();
+        assert_eq!(t30.tx_ident().as_deref(), Some("+1 555 0100"));
+
+        let too_long = PageHeader::new().with_info("x".repeat(80));
+        assert!(matches!(
+            t30.set_page_header(&too_long),
+            Err(SpanDspError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn snapshot_and_resume() {
+        let mut fax = FaxState::new(true).unwrap();
+        fax.get_t30_state()
+            .unwrap()
+            .set_tx_ident("+1 555 0100")
+            .unwrap();
+        let docs = vec![
+            TxDocument::new("cover.tif"),
+            TxDocument::new("body.tif").with_pages(2, 5),
+        ];
+        fax.set_tx_documents(docs).unwrap();
+
+        let snap = fax.snapshot().unwrap();
+        assert_eq!(snap.calling_party, Some(true));
+        assert_eq!(snap.local_ident.as_deref(), Some("+1 555 0100"));
+        assert_eq!(snap.pages_tx, 0);
+        // Nothing sent yet: the first document restarts from its first page.
+        assert_eq!(
+            snap.remaining_documents,
+            [
+                TxDocument::new("cover.tif").with_pages(0, -1),
+                TxDocument::new("body.tif").with_pages(2, 5),
+            ]
+        );
+
+        let mut resumed = FaxState::new(true).unwrap();
+        resumed.resume(&snap).unwrap();
+        assert_eq!(resumed.pending_tx_documents(), 1);
+        assert_eq!(
+            resumed.get_t30_state().unwrap().tx_ident().as_deref(),
+            Some("+1 555 0100")
+        );
+    }
+
+    #[test]
+    fn interrupt_signals_from_fcf() {
+        use spandsp::t30::T30InterruptSignal;
+
+        assert_eq!(
+            T30InterruptSignal::from_fcf(0xAC),
+            Some(T30InterruptSignal::Pip)
+        );
+        // The X bit is ignored.
+        assert_eq!(
+            T30InterruptSignal::from_fcf(0x2D),
+            Some(T30InterruptSignal::Pin)
+        );
+        assert_eq!(
+            T30InterruptSignal::from_fcf(0x3E),
+            Some(T30InterruptSignal::PriEop)
+        );
+        // MCF is an ordinary confirmation.
+        assert_eq!(T30InterruptSignal::from_fcf(0x8C), None);
+
+        let mut fax = FaxState::new(false).unwrap();
+        fax.set_interrupt_handler(|_| {});
+        let mut t30 = fax.get_t30_state().unwrap();
+        t30.set_remote_interrupts_allowed(true);
+        t30.request_local_interrupt(true);
+    }
+
+    #[test]
+    fn ident_validator_decisions() {
+        use spandsp::spandsp_sys::t30_err_e::*;
+        use spandsp::t30::{IdentDecision, RemoteIdent};
+
+        assert!(IdentDecision::Accept.completion().is_ok());
+        assert_eq!(
+            IdentDecision::RejectIdent.completion().raw(),
+            T30_ERR_IDENT_UNACCEPTABLE
+        );
+        assert_eq!(
+            IdentDecision::RejectPassword.completion().raw(),
+            T30_ERR_PWD_UNACCEPTABLE
+        );
+
+        let allowed = ["+1 555 0100".to_string()];
+        let screen = move |remote: &RemoteIdent| match &remote.ident {
+            Some(ident) if allowed.contains(ident) => IdentDecision::Accept,
+            _ => IdentDecision::RejectIdent,
+        };
+        let mut fax = FaxState::new(false).unwrap();
+        fax.set_ident_validator(screen.clone());
+        assert_eq!(fax.get_t30_state().unwrap().rx_password(), None);
+
+        let mut term = unsafe {
+            spandsp::t38_terminal::T38Terminal::new_raw(false, None, std::ptr::null_mut())
+        }
+        .unwrap();
+        term.set_ident_validator(screen);
+    }
+
+    #[test]
+    fn quality_report_summarises_pages() {
+        use spandsp::t30::{FaxQualityReport, PageQuality};
+
+        let mut fax = FaxState::new(true).unwrap();
+        let report = fax.quality_report();
+        assert!(report.pages.is_empty());
+        assert!(!report.has_line_problems());
+        fax.restart(false).unwrap();
+        assert!(fax.quality_report().pages.is_empty());
+
+        let clean = PageQuality {
+            page: 1,
+            bit_rate: 14400,
+            ecm: true,
+            ..Default::default()
+        };
+        let report = FaxQualityReport {
+            pages: vec![
+                clean.clone(),
+                PageQuality {
+                    page: 2,
+                    bit_rate: 9600,
+                    retrains: 1,
+                    rate_fallback: true,
+                    ecm_retransmissions: 2,
+                    ..clean.clone()
+                },
+                PageQuality {
+                    page: 3,
+                    bit_rate: 9600,
+                    bad_rows: 12,
+                    longest_bad_row_run: 5,
+                    ..clean
+                },
+            ],
+            status: 0,
+        };
+        assert!(report.completion().is_some_and(|e| e.is_ok()));
+        assert!(report.has_line_problems());
+        assert_eq!(report.bad_rows(), 12);
+        assert_eq!(report.retrains(), 1);
+        assert_eq!(report.rate_fallbacks(), 1);
+        assert_eq!(report.ecm_retransmissions(), 2);
+    }
+
+    #[test]
+    fn ecm_frames_are_decoded_and_counted() {
+        use spandsp::t30::{EcmEvent, EcmEventKind, FaxQualityReport, PageQuality};
+
+        // PPS-NULL for page 2, block 0, 256 frames.
+        let pps = [0xFF, 0x13, 0xBF, 0x00, 0x02, 0x00, 0xFF];
+        assert_eq!(
+            EcmEventKind::from_frame(&pps),
+            Some(EcmEventKind::PartialPage {
+                page: 2,
+                block: 0,
+                frames: 256
+            })
+        );
+        let mut ppr = vec![0xFF, 0x13, 0xBC];
+        ppr.extend_from_slice(&[0u8; 32]);
+        ppr[3] = 0b1010_0000;
+        ppr[34] = 0x01;
+        assert_eq!(
+            EcmEventKind::from_frame(&ppr),
+            Some(EcmEventKind::PartialPageRequest { frames: 3 })
+        );
+        assert_eq!(
+            EcmEventKind::from_frame(&[0xFF, 0x13, 0xEC]),
+            Some(EcmEventKind::ReceiverNotReady)
+        );
+        // MCF and a truncated PPR are not ECM events.
+        assert_eq!(EcmEventKind::from_frame(&[0xFF, 0x13, 0x8C]), None);
+        assert_eq!(EcmEventKind::from_frame(&ppr[..20]), None);
+
+        let mut fax = FaxState::new(true).unwrap();
+        let (tx, _rx) = std::sync::mpsc::channel::<EcmEvent>();
+        fax.set_ecm_event_handler(move |event| {
+            let _ = tx.send(event);
+        });
+        assert_eq!(fax.quality_report().partial_page_requests(), 0);
+
+        let report = FaxQualityReport {
+            pages: vec![PageQuality {
+                page: 1,
+                ecm: true,
+                ecm_blocks: 2,
+                partial_page_requests: 1,
+                ecm_frames_resent: 3,
+                ..Default::default()
+            }],
+            status: 0,
+        };
+        assert!(report.has_line_problems());
+        assert_eq!(report.ecm_frames_resent(), 3);
+    }
+
+    #[test]
+    fn bit_rate_cap_and_fallback() {
+        use spandsp::t30::{PageQuality, RetrainPolicy, T30ModemSupport};
+
+        let all = T30ModemSupport::default();
+        assert_eq!(all.max_bit_rate(), 14_400);
+        // 12000 falls between V.29 and V.17, so V.17 goes.
+        assert_eq!(
+            all.capped_at(12_000),
+            T30ModemSupport::V27TER | T30ModemSupport::V29
+        );
+        assert_eq!(all.capped_at(4_800).max_bit_rate(), 4_800);
+        assert!(all.capped_at(2_400).is_empty());
+
+        let mut fax = FaxState::new(true).unwrap();
+        assert_eq!(fax.max_bit_rate(), None);
+        assert!(matches!(
+            fax.set_max_bit_rate(Some(2_400)),
+            Err(SpanDspError::InvalidInput(_))
+        ));
+        assert_eq!(
+            fax.set_max_bit_rate(Some(9_600)).unwrap().max_bit_rate(),
+            9_600
+        );
+        // No page has been sent, so the fallback steps down from the cap.
+        assert_eq!(fax.request_fallback().unwrap(), Some(4_800));
+        assert_eq!(fax.max_bit_rate(), Some(4_800));
+        assert_eq!(fax.request_fallback().unwrap(), None);
+        assert_eq!(fax.set_max_bit_rate(None).unwrap(), all);
+
+        // The cap applies to the modems set, rather than replacing them.
+        let v29 = T30ModemSupport::V27TER | T30ModemSupport::V29;
+        assert_eq!(fax.set_supported_modems(v29).unwrap(), v29);
+        assert_eq!(
+            fax.set_max_bit_rate(Some(14_400)).unwrap(),
+            v29,
+            "the cap must not bring V.17 back"
+        );
+        assert_eq!(
+            fax.set_max_bit_rate(Some(4_800)).unwrap(),
+            T30ModemSupport::V27TER
+        );
+        assert_eq!(
+            fax.set_supported_modems(all).unwrap(),
+            T30ModemSupport::V27TER
+        );
+        assert_eq!(fax.set_max_bit_rate(None).unwrap(), all);
+
+        let policy = RetrainPolicy::default();
+        let mut page = PageQuality {
+            page: 1,
+            bad_rows: policy.max_bad_rows,
+            ..Default::default()
+        };
+        assert!(!policy.is_degraded(&page));
+        page.partial_page_requests = policy.max_partial_page_requests + 1;
+        assert!(policy.is_degraded(&page));
+        fax.set_retrain_policy(Some(policy));
+    }
+
+    #[test]
+    fn received_page_handler_takes_the_rx_file() {
+        let path = std::env::temp_dir().join("spandsp_received_pages.tif");
+        let mut fax = FaxState::new(false).unwrap();
+        fax.set_received_page_handler(path.to_str().unwrap(), -1, |_page| {})
+            .unwrap();
+        assert!(matches!(
+            fax.set_received_page_handler("rx\0.tif", -1, |_page| {}),
+            Err(SpanDspError::InvalidInput(_))
+        ));
+        // No page has been received, so restarting delivers nothing.
+        fax.restart(false).unwrap();
+    }
+
+    #[test]
+    fn t38_terminal_tick_reports_pacing() {
+        use std::time::Duration;
+
+        use spandsp::t38_core::T38TerminalOptions;
+        use spandsp::t38_terminal::*;
+
+        let mut term = unsafe { T38Terminal::new_raw(true, None, std::ptr::null_mut()) }.unwrap();
+        // Two seconds of a calling terminal: the CNG indicator goes out
+        // after its initial delay, then the call waits for DIS.
+        let ticks: Vec<T38Pacing> = (0..100).map(|_| term.tick(160)).collect();
+        for pacing in &ticks {
+            assert_eq!(pacing.samples, 160);
+            assert!(!pacing.call_ended);
+            assert_eq!(pacing.sending, pacing.packets_sent > 0);
+            let expected = if pacing.sending { 30 } else { 100 };
+            assert_eq!(pacing.next_timeout, Duration::from_millis(expected));
+        }
+        assert!(ticks.iter().any(|pacing| pacing.sending));
+        assert!(ticks.iter().any(|pacing| !pacing.sending));
+
+        term.set_config(T38TerminalOptions::NO_PACING);
+        let pacing = term.tick(160);
+        let expected = if pacing.sending { 0 } else { 100 };
+        assert_eq!(pacing.next_timeout, Duration::from_millis(expected));
+    }
+
+    #[test]
+    fn t38_terminal_restart_renumbers_traced_packets() {
+        use std::sync::{Arc, Mutex};
+
+        use spandsp::t38_core::IfpDirection;
+        use spandsp::t38_terminal::*;
+
+        let mut term = unsafe { T38Terminal::new_raw(true, None, std::ptr::null_mut()) }.unwrap();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sink = sent.clone();
+        term.get_t38_core_state()
+            .unwrap()
+            .set_trace_handler(move |record| {
+                if record.direction == IfpDirection::Sent {
+                    sink.lock().unwrap().push(record.seq_no);
+                }
+            })
+            .unwrap();
+
+        // Two seconds of a calling terminal send at least the CNG indicator.
+        for _ in 0..100 {
+            term.tick(160);
+        }
+        let first_call = std::mem::take(&mut *sent.lock().unwrap());
+        assert!(!first_call.is_empty());
+        assert_eq!(first_call[0], 0);
+
+        term.restart(true).unwrap();
+        for _ in 0..100 {
+            term.tick(160);
+        }
+        let second_call = sent.lock().unwrap();
+        assert!(!second_call.is_empty());
+        assert_eq!(
+            second_call[0], 0,
+            "sequence numbers carried over the restart"
+        );
+        assert!(
+            second_call
+                .windows(2)
+                .all(|pair| pair[1] == pair[0].wrapping_add(1))
+        );
+    }
+}
+
+// =========================================================================
+// T.38 core (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod t38 {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::t38_core::*;
+
+    #[test]
+    fn trace_records_received_and_sent_packets() {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        let records = Arc::new(Mutex::new(Vec::<IfpRecord>::new()));
+        let sink = records.clone();
+        core.set_trace_handler(move |record| sink.lock().unwrap().push(record.clone()))
+            .unwrap();
+
+        // CNG indicator, then a V.21 HDLC data field carrying two octets.
+        let _ = core.rx_ifp_packet(&[0x02], 7);
+        let _ = core.rx_ifp_packet(&[0xC0, 0x01, 0x80, 0x00, 0x01, 0xFF, 0x13], 8);
+        let _ = core.rx_ifp_packet(&[0x02, 0x00], 9);
+        core.send_indicator(T38Indicator::CED);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].direction, IfpDirection::Received);
+        assert_eq!(records[0].seq_no, 7);
+        assert_eq!(
+            records[0].message,
+            Some(IfpMessage::Indicator(T38Indicator::CNG))
+        );
+        assert_eq!(
+            records[1].message,
+            Some(IfpMessage::Data {
+                data_type: T38DataType::V21,
+                fields: vec![IfpField {
+                    field_type: T38FieldType::HDLC_DATA,
+                    data: vec![0xFF, 0x13],
+                }],
+            })
+        );
+        assert_eq!(records[2].message, None, "malformed packet decoded");
+        assert_eq!(records[2].raw, vec![0x02, 0x00]);
+        assert_eq!(records[3].direction, IfpDirection::Sent);
+        assert_eq!(records[3].seq_no, 0);
+        assert_eq!(
+            records[3].message,
+            Some(IfpMessage::Indicator(T38Indicator::CED))
+        );
+    }
+
+    #[test]
+    fn trace_needs_a_known_owner() {
+        let owner = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        let mut alias = unsafe { T38Core::from_raw(owner.as_ptr()) }.unwrap();
+        assert!(alias.set_trace_handler(|_| {}).is_err());
+
+        let mut gateway =
+            unsafe { spandsp::t38_gateway::T38Gateway::new_raw(None, std::ptr::null_mut()) }
+                .unwrap();
+        gateway
+            .get_t38_core_state()
+            .unwrap()
+            .set_trace_handler(|_| {})
+            .unwrap();
+    }
+
+    #[test]
+    fn redundancy_policy_applies_and_reads_back() {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        assert_eq!(core.redundancy_policy(), Some(RedundancyPolicy::default()));
+
+        assert!(RedundancyPolicy::uniform(0).validate().is_err());
+        assert!(
+            RedundancyPolicy::uniform(MAX_PACKET_REPEATS + 1)
+                .validate()
+                .is_err()
+        );
+        assert!(RedundancyPolicy::streaming().validate().is_ok());
+
+        let policy = RedundancyPolicy {
+            indicator: 5,
+            ..RedundancyPolicy::uniform(2)
+        };
+        core.set_redundancy_policy(&policy).unwrap();
+        assert_eq!(core.redundancy_policy(), Some(policy));
+        assert!(
+            core.set_redundancy_policy(&RedundancyPolicy::uniform(0))
+                .is_err()
+        );
+        assert_eq!(core.redundancy_policy(), Some(policy));
+
+        let copies = Arc::new(Mutex::new(Vec::new()));
+        let sink = copies.clone();
+        core.set_trace_handler(move |record| sink.lock().unwrap().push(record.copies))
+            .unwrap();
+        core.send_indicator(T38Indicator::CNG);
+        assert_eq!(*copies.lock().unwrap(), vec![5]);
+
+        let alias = unsafe { T38Core::from_raw(core.as_ptr()) }.unwrap();
+        assert_eq!(alias.redundancy_policy(), None);
+    }
+
+    #[test]
+    fn tpkt_stream_is_split_into_packets() {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        core.set_transport(T38Transport::TcpTpkt).unwrap();
+        assert_eq!(
+            core.redundancy_policy(),
+            Some(RedundancyPolicy::streaming())
+        );
+        let records = Arc::new(Mutex::new(Vec::<IfpRecord>::new()));
+        let sink = records.clone();
+        core.set_trace_handler(move |record| sink.lock().unwrap().push(record.clone()))
+            .unwrap();
+
+        // CNG, then a V.21 HDLC data field, split mid-packet.
+        let data = [0xC0, 0x01, 0x80, 0x00, 0x01, 0xFF, 0x13];
+        let mut stream = T38Transport::TcpTpkt.frame(&[0x02]).unwrap();
+        stream.extend(T38Transport::TcpTpkt.frame(&data).unwrap());
+        assert_eq!(&stream[..4], &[3, 0, 0, 5]);
+
+        let mut reader = IfpStreamReader::new(T38Transport::TcpTpkt, T38Version::V0).unwrap();
+        assert_eq!(reader.feed(&mut core, &stream[..8]).unwrap(), 1);
+        assert_eq!(reader.buffered(), 3);
+        assert_eq!(reader.feed(&mut core, &stream[8..]).unwrap(), 1);
+        assert_eq!(reader.buffered(), 0);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].message,
+            Some(IfpMessage::Indicator(T38Indicator::CNG))
+        );
+        assert_eq!(records[1].seq_no, 1);
+        assert_eq!(records[1].raw, data);
+
+        let mut bad = IfpStreamReader::new(T38Transport::TcpTpkt, T38Version::V0).unwrap();
+        assert!(bad.feed(&mut core, &[4, 0, 0, 5, 0x02]).is_err());
+    }
+
+    #[test]
+    fn raw_tcp_stream_finds_packet_boundaries() {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        core.set_transport(T38Transport::Tcp).unwrap();
+        let stream = [0x02, 0xC0, 0x01, 0x80, 0x00, 0x01, 0xFF, 0x13, 0x02];
+        let mut reader = IfpStreamReader::new(T38Transport::Tcp, T38Version::V0).unwrap();
+        assert_eq!(reader.feed(&mut core, &stream[..5]).unwrap(), 1);
+        assert_eq!(reader.feed(&mut core, &stream[5..]).unwrap(), 2);
+        assert_eq!(reader.buffered(), 0);
+
+        // From version 1, an extended field type takes a second octet.
+        core.set_t38_version(T38Version::V3);
+        let cm = IfpMessage::Data {
+            data_type: T38DataType::V21,
+            fields: vec![IfpField {
+                field_type: T38FieldType::from_code(
+                    spandsp::spandsp_sys::t38_field_types_e::T38_FIELD_CM_MESSAGE as i32,
+                )
+                .unwrap(),
+                data: vec![0x01],
+            }],
+        };
+        let mut stream = cm.to_bytes(T38Version::V3).unwrap();
+        stream.push(0x02);
+        let mut reader = IfpStreamReader::new(T38Transport::Tcp, T38Version::V3).unwrap();
+        assert_eq!(reader.feed(&mut core, &stream).unwrap(), 2);
+        assert_eq!(reader.buffered(), 0);
+        assert_eq!(T38Transport::Tcp.frame(&[0x02]).unwrap(), vec![0x02]);
+        assert!(!T38Transport::Udptl.is_reliable());
+        assert!(IfpStreamReader::new(T38Transport::Udptl, T38Version::V0).is_err());
+        assert!(IfpStreamReader::new(T38Transport::Rtp, T38Version::V3).is_err());
+    }
+
+    #[test]
+    fn ifp_packets_parse_and_serialize_standalone() {
+        let cng = IfpMessage::parse(&[0x02], T38Version::V2).unwrap();
+        assert_eq!(cng, IfpMessage::Indicator(T38Indicator::CNG));
+        assert_eq!(cng.to_bytes(T38Version::V2).unwrap(), [0x02]);
+
+        // V.8 ANSam is the first indicator past the compact range.
+        let ansam = IfpMessage::Indicator(
+            T38Indicator::from_code(
+                spandsp::spandsp_sys::t30_indicator_types_e::T38_IND_V8_ANSAM as i32,
+            )
+            .unwrap(),
+        );
+        let bytes = ansam.to_bytes(T38Version::V3).unwrap();
+        assert_eq!(bytes, [0x20, 0x00]);
+        assert!(matches!(
+            ansam.to_bytes(T38Version::V0),
+            Err(spandsp::error::SpanDspError::InvalidInput(_))
+        ));
+        let v8 = IfpMessage::Data {
+            data_type: T38DataType::from_code(
+                spandsp::spandsp_sys::t38_data_types_e::T38_DATA_V8 as i32,
+            )
+            .unwrap(),
+            fields: Vec::new(),
+        };
+        assert!(v8.to_bytes(T38Version::V0).is_err());
+        assert!(v8.to_bytes(T38Version::V1).is_ok());
+        assert_eq!(IfpMessage::parse(&bytes, T38Version::V3).unwrap(), ansam);
+
+        // V.21 HDLC data, two bytes in one field.
+        let raw = [0xC0, 0x01, 0x80, 0x00, 0x01, 0xFF, 0x13];
+        let data = IfpMessage::parse(&raw, T38Version::V2).unwrap();
+        assert_eq!(
+            data,
+            IfpMessage::Data {
+                data_type: T38DataType::V21,
+                fields: vec![IfpField {
+                    field_type: T38FieldType::HDLC_DATA,
+                    data: vec![0xFF, 0x13],
+                }],
+            }
+        );
+        assert_eq!(data.to_bytes(T38Version::V2).unwrap(), raw);
+
+        // Field types sit one bit further left in version 0.
+        let end = IfpMessage::Data {
+            data_type: T38DataType::V21,
+            fields: vec![IfpField {
+                field_type: T38FieldType::HDLC_FCS_OK,
+                data: Vec::new(),
+            }],
+        };
+        let v0 = end.to_bytes(T38Version::V0).unwrap();
+        let v2 = end.to_bytes(T38Version::V2).unwrap();
+        assert_ne!(v0, v2);
+        assert_eq!(IfpMessage::parse(&v0, T38Version::V0).unwrap(), end);
+        assert_eq!(IfpMessage::parse(&v2, T38Version::V2).unwrap(), end);
+
+        let cm = IfpMessage::Data {
+            data_type: T38DataType::V21,
+            fields: vec![IfpField {
+                field_type: T38FieldType::from_code(
+                    spandsp::spandsp_sys::t38_field_types_e::T38_FIELD_CM_MESSAGE as i32,
+                )
+                .unwrap(),
+                data: vec![0x01],
+            }],
+        };
+        assert!(cm.to_bytes(T38Version::V0).is_err());
+        let bytes = cm.to_bytes(T38Version::V3).unwrap();
+        assert_eq!(IfpMessage::parse(&bytes, T38Version::V3).unwrap(), cm);
+
+        assert!(IfpMessage::parse(&[], T38Version::V2).is_err());
+        assert!(IfpMessage::parse(&raw[..5], T38Version::V2).is_err());
+        assert!(
+            IfpMessage::parse(
+                &[0xC0, 0x01, 0x80, 0x00, 0x01, 0xFF, 0x13, 0x00],
+                T38Version::V2
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn rtp_carries_ifp_with_its_sequence_numbers() {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        core.set_transport(T38Transport::Rtp).unwrap();
+        assert!(!T38Transport::Rtp.is_reliable());
+        assert!(T38Transport::Rtp.frame(&[0x02]).is_err());
+        assert_eq!(core.redundancy_policy(), Some(RedundancyPolicy::default()));
+        let records = Arc::new(Mutex::new(Vec::<IfpRecord>::new()));
+        let sink = records.clone();
+        core.set_trace_handler(move |record| sink.lock().unwrap().push(record.clone()))
+            .unwrap();
+
+        let mut tx = IfpRtpFramer::new(96, 0x1234_5678)
+            .unwrap()
+            .with_initial_seq_no(40_000);
+        let cng = tx.frame(&[0x02], 160);
+        assert_eq!(
+            &cng[..12],
+            &[0x80, 96, 0x9C, 0x40, 0, 0, 0, 160, 0x12, 0x34, 0x56, 0x78]
+        );
+        assert_eq!(tx.next_seq_no(), 40_001);
+
+        let mut rx = IfpRtpFramer::new(96, 0).unwrap();
+        assert_eq!(rx.feed(&mut core, &cng).unwrap(), 40_000);
+
+        // One CSRC, a one-word extension and two bytes of padding.
+        let mut padded = vec![0xB1, 96, 0x9C, 0x41, 0, 0, 1, 64, 0, 0, 0, 1];
+        padded.extend_from_slice(&[0xAA; 4]);
+        padded.extend_from_slice(&[0xBE, 0xDE, 0, 1, 0, 0, 0, 0]);
+        padded.extend_from_slice(&[0x02, 0, 2]);
+        assert_eq!(rx.feed(&mut core, &padded).unwrap(), 40_001);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].seq_no, 40_001);
+        assert_eq!(records[1].raw, [0x02]);
+
+        // Wrong payload type, wrong version, truncated.
+        let mut other = IfpRtpFramer::new(101, 0).unwrap();
+        assert!(other.feed(&mut core, &cng).is_err());
+        assert!(
+            rx.feed(&mut core, &[0x40, 96, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2])
+                .is_err()
+        );
+        assert!(rx.feed(&mut core, &cng[..8]).is_err());
+        assert!(IfpRtpFramer::new(128, 0).is_err());
+    }
+
+    /// IFP packets for the UDPTL tests: indicators and V.21 HDLC data of
+    /// varying lengths.
+    fn udptl_test_packets() -> Vec<Vec<u8>> {
+        (0..12u8)
+            .map(|i| match i % 4 {
+                0 => vec![0x02],
+                _ => {
+                    let len = usize::from(i % 5) + 1;
+                    let mut packet = vec![0xC0, 0x01, 0x80, 0x00, len as u8 - 1];
+                    packet.extend((0..len as u8).map(|k| i ^ k));
+                    packet
+                }
+            })
+            .collect()
+    }
+
+    /// Frame `packets` with `recovery`, lose the ones in `lost` and feed
+    /// the rest to an engine, returning what it received and the
+    /// receiver's stats.
+    fn udptl_loss(
+        recovery: UdptlErrorRecovery,
+        packets: &[Vec<u8>],
+        lost: &[usize],
+    ) -> (Vec<(u16, Vec<u8>)>, UdptlStats) {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        core.set_transport(T38Transport::Udptl).unwrap();
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = records.clone();
+        core.set_trace_handler(move |record| {
+            sink.lock()
+                .unwrap()
+                .push((record.seq_no, record.raw.clone()))
+        })
+        .unwrap();
+
+        let mut tx = IfpUdptlFramer::new(recovery, T38Version::V0).unwrap();
+        let mut rx = IfpUdptlFramer::new(recovery, T38Version::V0).unwrap();
+        for (i, packet) in packets.iter().enumerate() {
+            let datagram = tx.frame(packet).unwrap();
+            if !lost.contains(&i) {
+                rx.feed(&mut core, &datagram).unwrap();
+            }
+        }
+        let records = records.lock().unwrap().clone();
+        (records, rx.stats())
+    }
+
+    #[test]
+    fn udptl_redundancy_rebuilds_lost_packets() {
+        let packets = udptl_test_packets();
+        let recovery = UdptlErrorRecovery::Redundancy(UdptlRedundancy::uniform(2));
+        let (received, stats) = udptl_loss(recovery, &packets, &[2, 3, 6, 7, 8]);
+        // Two secondaries cover the pairs lost; the third of a run is gone.
+        let seqs: Vec<u16> = received.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, [0, 1, 2, 3, 4, 5, 7, 8, 9, 10, 11]);
+        for (seq, raw) in &received {
+            assert_eq!(raw, &packets[usize::from(*seq)]);
+        }
+        assert_eq!(stats.received, 7);
+        assert_eq!(stats.recovered_redundancy, 4);
+        assert_eq!(stats.recovered(), 4);
+        assert_eq!(stats.lost, 1);
+
+        // Indicators only: each is repeated in the three packets after it,
+        // so a packet carries secondaries back to the last indicator.
+        let depths = UdptlRedundancy {
+            indicator: 3,
+            ..UdptlRedundancy::uniform(0)
+        };
+        let mut tx =
+            IfpUdptlFramer::new(UdptlErrorRecovery::Redundancy(depths), T38Version::V0).unwrap();
+        let datagrams: Vec<Vec<u8>> = packets[..5]
+            .iter()
+            .map(|packet| tx.frame(packet).unwrap())
+            .collect();
+        assert_eq!(datagrams[0], [0, 0, 1, 0x02, 0x00, 0]);
+        let secondaries: usize = packets[..3].iter().map(|packet| 1 + packet.len()).sum();
+        assert_eq!(
+            datagrams[3].len(),
+            2 + 1 + packets[3].len() + 2 + secondaries
+        );
+        // The first indicator is now out of reach.
+        assert_eq!(datagrams[4], [0, 4, 1, 0x02, 0x00, 0]);
+        assert_eq!(tx.next_seq_no(), 5);
+
+        assert!(
+            UdptlErrorRecovery::Redundancy(UdptlRedundancy::uniform(17))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn udptl_fec_rebuilds_lost_packets() {
+        let packets = udptl_test_packets();
+        let recovery = UdptlErrorRecovery::Fec {
+            span: 2,
+            entries: 3,
+        };
+        // Runs of up to three packets, once the first six are sent.
+        let (received, stats) = udptl_loss(recovery, &packets, &[6, 7, 8]);
+        let seqs: Vec<u16> = received.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, (0..12).collect::<Vec<u16>>());
+        for (seq, raw) in &received {
+            assert_eq!(raw, &packets[usize::from(*seq)]);
+        }
+        assert_eq!(stats.recovered_fec, 3);
+        assert_eq!(stats.lost, 0);
+
+        // Before the FEC has wound up, one entry covers both packets lost.
+        let (_, stats) = udptl_loss(recovery, &packets, &[1, 2]);
+        assert_eq!(stats.recovered(), 0);
+        assert_eq!(stats.lost, 2);
+
+        for (span, entries) in [(0, 1), (1, 0), (4, 5)] {
+            assert!(
+                IfpUdptlFramer::new(UdptlErrorRecovery::Fec { span, entries }, T38Version::V0)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn udptl_drops_late_and_malformed_packets() {
+        let mut core = unsafe {
+            T38Core::new_raw(
+                None,
+                None,
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+            )
+        }
+        .unwrap();
+        let mut tx = IfpUdptlFramer::new(UdptlErrorRecovery::default(), T38Version::V0).unwrap();
+        let mut rx = IfpUdptlFramer::new(UdptlErrorRecovery::default(), T38Version::V0).unwrap();
+        let first = tx.frame(&[0x02]).unwrap();
+        let second = tx.frame(&[0x04]).unwrap();
+        assert_eq!(rx.feed(&mut core, &second).unwrap(), 1);
+        assert_eq!(rx.feed(&mut core, &first).unwrap(), 0);
+        assert_eq!(rx.stats().late, 1);
+        assert_eq!(rx.take_stats().received, 1);
+        assert_eq!(rx.stats(), UdptlStats::default());
+
+        assert!(tx.frame(&[]).is_err());
+        assert!(rx.feed(&mut core, &second[..3]).is_err());
+        // An empty primary packet, and an FEC span of two octets.
+        assert!(rx.feed(&mut core, &[0, 5, 0, 0, 0]).is_err());
+        assert!(
+            rx.feed(&mut core, &[0, 5, 1, 0x02, 0x80, 2, 0, 1, 0])
+                .is_err()
+        );
+    }
+}
+
+// =========================================================================
+// T.31 class 1 fax modem (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod t31 {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::at_interpreter::ModemControl;
+    use spandsp::t31::T31;
+
+    type Shared<T> = Arc<Mutex<Vec<T>>>;
+
+    fn modem() -> (T31, Shared<u8>, Shared<ModemControl>) {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let controls = Arc::new(Mutex::new(Vec::new()));
+        let (out, ctl) = (Arc::clone(&output), Arc::clone(&controls));
+        let t31 = T31::new(
+            move |bytes| out.lock().unwrap().extend_from_slice(bytes),
+            move |op| {
+                ctl.lock().unwrap().push(op);
+                true
+            },
+        )
+        .unwrap();
+        (t31, output, controls)
+    }
+
+    #[test]
+    fn takes_class1_commands() {
+        let (mut t31, output, _) = modem();
+        t31.at_rx(b"AT+FCLASS=1\r");
+        let text = String::from_utf8_lossy(&output.lock().unwrap()).into_owned();
+        assert!(text.contains("OK"), "{text:?}");
+    }
+
+    #[test]
+    fn dialling_reaches_modem_control() {
+        let (mut t31, _, controls) = modem();
+        t31.at_rx(b"AT+FCLASS=1\r");
+        t31.at_rx(b"ATD5551234\r");
+        let controls = controls.lock().unwrap();
+        assert!(
+            controls
+                .iter()
+                .any(|c| matches!(c, ModemControl::Call(n) if n.contains("5551234"))),
+            "{controls:?}"
+        );
+    }
+
+    #[test]
+    fn idles_and_switches_to_t38() {
+        let (mut t31, _, _) = modem();
+        t31.set_transmit_on_idle(true);
+        let mut amp = [0i16; 160];
+        assert_eq!(t31.tx(&mut amp), amp.len());
+        assert_eq!(t31.rx(&mut [0i16; 160]), 0);
+        assert!(!t31.t38_mode());
+        t31.set_t38_mode(true);
+        assert!(t31.t38_mode());
+        assert!(t31.get_t38_core_state().is_ok());
+        assert!(format!("{t31:?}").contains("t38_mode: true"));
+    }
+
+    #[test]
+    fn class1_modem_queues_events() {
+        use spandsp::t31::{Class1Event, Class1Modem};
+
+        let mut modem = Class1Modem::new().unwrap();
+        modem.process_at_bytes(b"AT+FCLASS=1\r");
+        let Some(Class1Event::ToDte(bytes)) = modem.poll_event() else {
+            panic!("no response");
+        };
+        assert!(String::from_utf8_lossy(&bytes).contains("OK"));
+        assert_eq!(modem.poll_event(), None);
+
+        assert!(!modem.is_off_hook());
+        modem.process_at_bytes(b"ATD5551234\r");
+        let events = modem.drain_events();
+        assert!(
+            events.iter().any(
+                |e| matches!(e, Class1Event::Control(ModemControl::Call(n)) if n.contains("5551234"))
+            ),
+            "{events:?}"
+        );
+        assert!(modem.is_off_hook());
+        let mut audio = [0i16; 160];
+        modem.tx_audio(&mut audio);
+        assert_eq!(modem.rx_audio(&mut audio), 0);
+    }
+}
+
+// =========================================================================
+// T.35 country and vendor codes (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod t35 {
+    use spandsp::t35::*;
+
+    #[test]
+    fn splits_nsf_bodies() {
+        let body = [0xB5, 0x00, 0x22, 0x41, 0x42];
+        assert_eq!(split(&body), Some((0xB5, 0, [0x00, 0x22], &body[3..])));
+        let extended = [0xFF, 0x01, 0x00, 0x22];
+        assert_eq!(split(&extended), Some((0xFF, 0x01, [0x00, 0x22], &[][..])));
+        assert_eq!(split(&[0xB5, 0x00]), None);
+    }
+
+    #[test]
+    fn names_countries() {
+        // The United States.
+        assert!(country_name(0xB5, 0).is_some());
+        assert_eq!(real_country_code(0xB5, 0), Some(0xB5));
+    }
+
+    #[test]
+    fn decodes_only_nsf_style_frames() {
+        let dis = [0xFF, 0x13, 0x80, 0x00, 0xEE, 0xF8];
+        assert_eq!(T35Info::from_frame(&dis), None);
+        let nsf = [0xFF, 0x13, 0x20, 0xB5, 0x00, 0x22];
+        let info = T35Info::from_frame(&nsf).unwrap();
+        assert!(info.country.is_some());
+        assert!(!info.to_string().is_empty());
+        let nsc = [0xFF, 0x13, 0xA0, 0xB5, 0x00, 0x22];
+        assert_eq!(T35Info::from_frame(&nsc), Some(info));
+        let nss = [0xFF, 0x13, 0x23, 0xB5, 0x00, 0x22];
+        assert!(T35Info::from_frame(&nss).is_some());
+        assert_eq!(T35Info::default().to_string(), "unknown");
+        assert!(!T35Info::default().is_known());
+    }
+}
+
+// =========================================================================
+// PDF output (requires pdf feature)
+// =========================================================================
+#[cfg(feature = "pdf")]
+mod pdf {
+    use spandsp::pdf::*;
+    use spandsp::test_chart::{TestChart, TestPattern};
+
+    /// A little-endian single-strip TIFF holding `data` with the given
+    /// compression and fill order, at 204x196 dpi.
+    fn fax_tiff(
+        width: u32,
+        length: u32,
+        compression: u16,
+        fill_order: u16,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let entries: [(u16, u16, u32, u32); 10] = [
+            (256, 4, 1, width),
+            (257, 4, 1, length),
+            (259, 3, 1, compression as u32),
+            (262, 3, 1, 0),
+            (266, 3, 1, fill_order as u32),
+            (273, 4, 1, 0), // strip offset, patched below
+            (279, 4, 1, data.len() as u32),
+            (282, 5, 1, 0), // x resolution, patched below
+            (283, 5, 1, 0), // y resolution, patched below
+            (296, 3, 1, 2),
+        ];
+        let ifd_len = 2 + entries.len() * 12 + 4;
+        let rationals = 8 + ifd_len as u32;
+        let strip = rationals + 16;
+        let mut out = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in entries {
+            let value = match tag {
+                273 => strip,
+                282 => rationals,
+                283 => rationals + 8,
+                _ => value,
+            };
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&kind.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&0u32.to_le_bytes());
+        for (num, den) in [(204u32, 1u32), (196, 1)] {
+            out.extend_from_slice(&num.to_le_bytes());
+            out.extend_from_slice(&den.to_le_bytes());
+        }
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn count(haystack: &[u8], needle: &str) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle.as_bytes())
+            .count()
+    }
+
+    #[test]
+    fn bitmap_pages_become_g4_pdf() {
+        let chart = TestChart::new(TestPattern::TextLines { scale: 2 }, 1728, 300).unwrap();
+        let page = FaxPage::from_rows(1728, chart.rows(), 204.0, 196.0).unwrap();
+        assert_eq!((page.width(), page.length()), (1728, 300));
+        assert!(page.coded_size() > 0);
+        assert!(page.coded_size() < 1728 / 8 * 300 / 4);
+
+        let pdf = to_pdf(&[page.clone(), page]);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert_eq!(count(&pdf, "/Type /Page "), 2);
+        assert_eq!(count(&pdf, "/Filter /CCITTFaxDecode"), 2);
+        assert_eq!(count(&pdf, "/K -1 /Columns 1728 /Rows 300"), 2);
+        // 1728 pixels at 204 dpi is 609.88 points; 300 rows at 196 dpi 110.20.
+        assert!(count(&pdf, "/MediaBox [0 0 609.88 110.20]") == 2);
+
+        assert!(FaxPage::from_rows(1728, Vec::<Vec<u8>>::new(), 204.0, 98.0).is_err());
+        assert!(FaxPage::from_rows(8, [[0u8]], 0.0, 98.0).is_err());
+    }
+
+    #[test]
+    fn tiff_pages_are_embedded_without_decoding() {
+        let data = [0x12, 0x34, 0x80];
+        let tiff = fax_tiff(1728, 40, 4, 2, &data);
+        let pages = FaxPage::from_tiff(&tiff).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!((pages[0].width(), pages[0].length()), (1728, 40));
+        assert_eq!((pages[0].x_dpi(), pages[0].y_dpi()), (204.0, 196.0));
+        assert_eq!(pages[0].coded_size(), 3);
+
+        let pdf = tiff_to_pdf(&tiff).unwrap();
+        // Fill order 2 is bit-reversed to the MSB-first order PDF expects.
+        assert_eq!(count(&pdf, "stream\n\x48\x2c\x01\nendstream"), 1);
+
+        let g3 = fax_tiff(1728, 40, 3, 1, &data);
+        assert_eq!(count(&tiff_to_pdf(&g3).unwrap(), "/K 0 "), 1);
+
+        assert!(FaxPage::from_tiff(b"not a tiff").is_err());
+        assert!(FaxPage::from_tiff(&fax_tiff(1728, 40, 5, 1, &data)).is_err());
+        assert!(FaxPage::from_tiff(&tiff[..tiff.len() - 2]).is_err());
+    }
+}
+
+// =========================================================================
+// T.37 attachments (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod t37 {
+    use spandsp::t4::T4Compression;
+    use spandsp::t37::{self, FaxPage, TiffProfile};
+    use spandsp::test_chart::{TestChart, TestPattern};
+
+    fn chart() -> TestChart {
+        TestChart::new(TestPattern::TextLines { scale: 2 }, 1728, 120).unwrap()
+    }
+
+    #[test]
+    fn profile_s_attachment_roundtrips() {
+        let chart = chart();
+        let page = FaxPage::encode(T4Compression::T4_1D, 1728, chart.rows(), 204.0, 98.0).unwrap();
+        let tiff = t37::write_tiff(&[page.clone(), page], TiffProfile::S).unwrap();
+        assert_eq!(&tiff[..4], b"II*\0");
+
+        let pages = t37::read_tiff(&tiff, TiffProfile::S).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!((pages[1].width(), pages[1].length()), (1728, 120));
+        let expected: Vec<_> = chart.rows().collect();
+        assert_eq!(pages[1].rows().unwrap(), expected);
+    }
+
+    #[test]
+    fn pages_are_conformed_to_profile() {
+        let chart = chart();
+        let mut g4 = FaxPage::from_rows(1728, chart.rows(), 204.0, 196.0).unwrap();
+        g4.set_bad_rows(4, 2);
+        assert!(TiffProfile::S.check(&g4).is_err());
+        TiffProfile::F.check(&g4).unwrap();
+        assert!(t37::write_tiff(std::slice::from_ref(&g4), TiffProfile::S).is_err());
+
+        let mh = TiffProfile::S.conform(&g4).unwrap();
+        TiffProfile::S.check(&mh).unwrap();
+        assert_eq!(mh.rows().unwrap(), g4.rows().unwrap());
+        assert_eq!(mh.bad_rows(), 4);
+
+        // Profile F files carry the line quality tags.
+        let tiff = t37::write_tiff(&[g4], TiffProfile::F).unwrap();
+        let pages = t37::read_tiff(&tiff, TiffProfile::F).unwrap();
+        assert_eq!(
+            (pages[0].bad_rows(), pages[0].longest_bad_row_run()),
+            (4, 2)
+        );
+
+        let narrow = FaxPage::from_rows(1000, chart.rows(), 204.0, 98.0).unwrap();
+        assert!(TiffProfile::F.conform(&narrow).is_err());
+        assert!(t37::write_tiff(&[], TiffProfile::F).is_err());
+    }
+}
+
+// =========================================================================
+// TIFF-FX validation (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod tiff_fx {
+    use spandsp::t37::{self, FaxPage, TiffProfile};
+    use spandsp::test_chart::{TestChart, TestPattern};
+    use spandsp::tiff_fx;
+
+    /// Overwrite the value of `tag` in the first IFD of a little-endian TIFF.
+    fn patch_tag(tiff: &mut [u8], tag: u16, value: u32) {
+        let ifd = u32::from_le_bytes(tiff[4..8].try_into().unwrap()) as usize;
+        let entries = u16::from_le_bytes([tiff[ifd], tiff[ifd + 1]]) as usize;
+        let entry = (0..entries)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|&entry| tiff[entry..entry + 2] == tag.to_le_bytes())
+            .unwrap();
+        tiff[entry + 8..entry + 12].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn validation_lists_every_violation() {
+        let chart = TestChart::new(TestPattern::TextLines { scale: 2 }, 1728, 80).unwrap();
+        let page = FaxPage::from_rows(1728, chart.rows(), 204.0, 196.0).unwrap();
+        let mut tiff = t37::write_tiff(&[page], TiffProfile::F).unwrap();
+        assert!(
+            tiff_fx::validate(&tiff, TiffProfile::F)
+                .unwrap()
+                .is_compliant()
+        );
+
+        patch_tag(&mut tiff, 254, 0); // NewSubfileType
+        patch_tag(&mut tiff, 297, 3); // PageNumber
+        let report = tiff_fx::validate(&tiff, TiffProfile::F).unwrap();
+        assert_eq!(report.pages(), 1);
+        assert_eq!(report.violations().len(), 2);
+        assert!(report.violations()[0].problem.contains("NewSubfileType"));
+
+        // Profile S also rejects the T.6 coding.
+        let report = tiff_fx::validate(&tiff, TiffProfile::S).unwrap();
+        assert_eq!(report.violations().len(), 3);
+        let err = report.into_result().unwrap_err().to_string();
+        assert!(err.contains("(and 2 more)"), "{err}");
+
+        let fixed = tiff_fx::to_tiff_fx(&tiff, TiffProfile::S).unwrap();
+        tiff_fx::validate(&fixed, TiffProfile::S)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert!(tiff_fx::validate(b"not a tiff", TiffProfile::F).is_err());
+    }
+}
+
+// =========================================================================
+// FAX job queue (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod fax_queue {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    use spandsp::fax_queue::*;
+    use spandsp::spandsp_sys::t30_err_e::*;
+    use spandsp::t30::{T30Snapshot, TxDocument};
+
+    fn session(status: spandsp::spandsp_sys::t30_err_e, remaining: Vec<TxDocument>) -> CallOutcome {
+        CallOutcome::Session(T30Snapshot {
+            status: status as i32,
+            remaining_documents: remaining,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn failed_calls_are_retried_with_backoff() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_secs(60),
+            backoff: 2,
+            max_delay: Duration::from_secs(90),
+        };
+        assert_eq!(policy.delay_after(1), Duration::from_secs(60));
+        assert_eq!(policy.delay_after(2), Duration::from_secs(90));
+
+        let mut queue = FaxJobQueue::new();
+        let transitions = Arc::new(Mutex::new(Vec::new()));
+        let log = transitions.clone();
+        queue.set_transition_handler(move |job, previous| {
+            log.lock().unwrap().push((previous, job.state()));
+        });
+        let docs = vec![TxDocument::new("a.tif"), TxDocument::new("b.tif")];
+        let id = queue
+            .submit(FaxJob::new("5551234", docs).with_retry(policy))
+            .unwrap();
+        assert_eq!(queue.next_due(), Some(SystemTime::UNIX_EPOCH));
+
+        // First call fails after sending a.tif; the retry resumes with b.tif.
+        let mut outcomes = vec![
+            session(T30_ERR_OK, Vec::new()),
+            session(T30_ERR_T1_EXPIRED, vec![TxDocument::new("b.tif")]),
+        ];
+        let mut carrier = |job: &FaxJob| {
+            assert_eq!(job.destination(), "5551234");
+            outcomes.pop().unwrap()
+        };
+        assert_eq!(queue.run_due(start, &mut carrier), 1);
+        let job = queue.job(id).unwrap();
+        assert_eq!(job.state(), JobState::Scheduled);
+        assert_eq!(job.documents(), [TxDocument::new("b.tif")]);
+        assert_eq!(job.next_attempt(), Some(start + Duration::from_secs(60)));
+
+        assert_eq!(
+            queue.run_due(start + Duration::from_secs(59), &mut carrier),
+            0
+        );
+        assert_eq!(
+            queue.run_due(start + Duration::from_secs(60), &mut carrier),
+            1
+        );
+        let job = queue.job(id).unwrap();
+        assert_eq!(job.state(), JobState::Completed);
+        assert_eq!(job.attempts().len(), 2);
+        assert!(job.attempts()[1].outcome.is_success());
+        assert_eq!(queue.pending(), 0);
+        assert_eq!(queue.next_due(), None);
+
+        assert_eq!(
+            *transitions.lock().unwrap(),
+            [
+                (JobState::Scheduled, JobState::InProgress { attempt: 1 }),
+                (JobState::InProgress { attempt: 1 }, JobState::Scheduled),
+                (JobState::Scheduled, JobState::InProgress { attempt: 2 }),
+                (JobState::InProgress { attempt: 2 }, JobState::Completed),
+            ]
+        );
+        assert_eq!(queue.take_finished().len(), 1);
+        assert!(queue.job(id).is_none());
+    }
+
+    #[test]
+    fn permanent_failures_and_cancellation() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut queue = FaxJobQueue::new();
+        let doc = || vec![TxDocument::new("a.tif")];
+        let bad = queue.submit(FaxJob::new("1", doc())).unwrap();
+        let busy = queue
+            .submit(FaxJob::new("2", doc()).with_retry(RetryPolicy::once()))
+            .unwrap();
+        let later = queue
+            .submit(FaxJob::new("3", doc()).not_before(now + Duration::from_secs(3600)))
+            .unwrap();
+        assert!(queue.submit(FaxJob::new("", doc())).is_err());
+        assert!(queue.submit(FaxJob::new("4", Vec::new())).is_err());
+
+        let calls = queue.run_due(now, &mut |job: &FaxJob| match job.destination() {
+            "1" => session(T30_ERR_BADTIFF, Vec::new()),
+            _ => CallOutcome::Busy,
+        });
+        assert_eq!(calls, 2);
+        assert_eq!(queue.job(bad).unwrap().state(), JobState::Failed);
+        assert_eq!(queue.job(busy).unwrap().state(), JobState::Failed);
+        assert_eq!(queue.job(later).unwrap().state(), JobState::Scheduled);
+
+        queue.cancel(later).unwrap();
+        assert_eq!(queue.job(later).unwrap().state(), JobState::Cancelled);
+        assert!(queue.cancel(later).is_err());
+
+        // A queue restored from saved jobs keeps their IDs.
+        let saved: Vec<FaxJob> = queue.jobs().cloned().collect();
+        let mut restored = FaxJobQueue::from_jobs(saved);
+        assert_eq!(restored.jobs().count(), 3);
+        let next = restored.submit(FaxJob::new("5", doc())).unwrap();
+        assert!(next > later);
+    }
+
+    #[test]
+    fn unreachable_retry_time_fails_the_job() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::MAX,
+            backoff: 2,
+            max_delay: Duration::MAX,
+        };
+        let mut queue = FaxJobQueue::new();
+        let id = queue
+            .submit(FaxJob::new("1", vec![TxDocument::new("a.tif")]).with_retry(policy))
+            .unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(queue.run_due(now, &mut |_: &FaxJob| CallOutcome::Busy), 1);
+        let job = queue.job(id).unwrap();
+        assert_eq!(job.state(), JobState::Failed);
+        assert_eq!(job.next_attempt(), None);
+    }
+}
+
+// =========================================================================
+// Fax tone detection
+// =========================================================================
+
+#[cfg(feature = "fax")]
+mod fax_tones {
+    use std::sync::mpsc;
+
+    use spandsp::fax::FaxState;
+    use spandsp::fax_tones::*;
+    use spandsp::t38_gateway::T38Gateway;
+
+    use super::sine_wave;
+
+    #[test]
+    fn ced_is_reported_once() {
+        let mut detector = FaxToneDetector::new().unwrap();
+        assert_eq!(detector.rx(&[0; 8000]), None);
+
+        let ced = sine_wave(2100.0, 8000.0, 8000 * 4, 8000.0);
+        let first = ced
+            .chunks(160)
+            .find_map(|block| detector.rx(block))
+            .unwrap();
+        assert_eq!(first, FaxTone::Ced);
+        assert_eq!(detector.rx(&[0; 160]), Some(FaxTone::Ced));
+
+        detector.reset().unwrap();
+        assert_eq!(detector.detected(), None);
+    }
+
+    #[test]
+    fn engines_call_the_handler_on_first_tone() {
+        let ced = sine_wave(2100.0, 8000.0, 8000 * 4, 8000.0);
+
+        let (tx, rx) = mpsc::channel();
+        let mut fax = FaxState::new(false).unwrap();
+        fax.set_fax_tone_handler(move |tone| tx.send(tone).unwrap())
+            .unwrap();
+        for block in ced.chunks(160) {
+            fax.rx(&mut block.to_vec());
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [FaxTone::Ced]);
+        fax.restart(false).unwrap();
+        fax.rx(&mut [0; 160]);
+        assert_eq!(rx.try_recv().ok(), None);
+
+        let (tx, rx) = mpsc::channel();
+        let mut gateway = unsafe { T38Gateway::new_raw(None, std::ptr::null_mut()) }.unwrap();
+        gateway
+            .set_fax_tone_handler(move |tone| tx.send(tone).unwrap())
+            .unwrap();
+        for block in ced.chunks(160) {
+            gateway.rx(&mut block.to_vec());
+        }
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [FaxTone::Ced]);
+    }
+}
+
+// =========================================================================
+// Playout
+// =========================================================================
+
+mod playout {
+    use spandsp::g711::{G711Mode, G711State, linear_to_alaw};
+    use spandsp::playout::*;
+
+    use super::{rms, sine_wave};
+
+    fn pipeline() -> PlayoutPipeline<G711State> {
+        let decoder = G711State::new(G711Mode::ALaw)
+            .unwrap()
+            .with_plc(160)
+            .unwrap();
+        PlayoutPipeline::new(decoder, PlayoutConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn reorders_conceals_and_drops_late_frames() {
+        let tone: Vec<u8> = sine_wave(400.0, 8000.0, 160 * 8, 8000.0)
+            .into_iter()
+            .map(linear_to_alaw)
+            .collect();
+        let frame = |n: usize| &tone[n * 160..(n + 1) * 160];
+        // Timestamps start just short of the wrap.
+        let ts = |n: usize| (u32::MAX - 159).wrapping_add(n as u32 * 160);
+        let mut playout = pipeline();
+
+        // Silence until the minimum delay is buffered.
+        assert!(playout.put(ts(1), frame(1)));
+        assert_eq!(playout.tick(), &[0; 160][..]);
+        assert!(playout.put(ts(0), frame(0)));
+        assert!(!playout.put(ts(0), frame(0)));
+        assert!(rms(playout.tick()) > 1000.0);
+        assert!(rms(playout.tick()) > 1000.0);
+
+        // Frame 2 is lost; frame 3 is queued, so 2 is concealed in turn.
+        assert!(playout.put(ts(3), frame(3)));
+        assert!(rms(playout.tick()) > 1000.0);
+        assert!(!playout.put(ts(2), frame(2)));
+        playout.tick();
+
+        let stats = playout.stats();
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.played, 3);
+        assert_eq!(stats.concealed, 1);
+        assert_eq!((stats.late, stats.duplicates), (1, 1));
+    }
+
+    #[test]
+    fn underruns_add_delay_and_jumps_resync() {
+        let mut playout = pipeline();
+        for n in 0..2u32 {
+            playout.put(n * 160, &[0xD5; 160]);
+        }
+        playout.tick();
+        playout.tick();
+        // Nothing queued: the next tick conceals and holds the playout point.
+        playout.tick();
+        assert_eq!(playout.target_delay(), 3);
+        assert!(playout.put(2 * 160, &[0xD5; 160]));
+        playout.tick();
+        assert_eq!(playout.stats().played, 3);
+
+        playout.put(1_000_000, &[0xD5; 160]);
+        assert_eq!(playout.stats().resyncs, 1);
+        assert_eq!(playout.buffered(), 1);
+        assert!(
+            PlayoutPipeline::new(
+                G711State::new(G711Mode::ULaw)
+                    .unwrap()
+                    .with_plc(160)
+                    .unwrap(),
+                PlayoutConfig {
+                    min_delay: 4,
+                    max_delay: 2,
+                    ..Default::default()
+                },
+            )
+            .is_err()
+        );
+    }
+}
+
+// =========================================================================
+// V.150.1 SSE
+// =========================================================================
+
+mod v150_1_sse {
+    use spandsp::v150_1_sse::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn payload_round_trip() {
+        let event = SseEvent {
+            state: MediaState::ModemRelay,
+            force_response: true,
+            reason: ReasonCode::V8_CM,
+            reason_info: 0x1234,
+        };
+        let payload = event.to_payload();
+        assert_eq!(payload, [0x05, 0x01, 0x12, 0x34]);
+        assert_eq!(SseEvent::from_payload(&payload), Some(event));
+        assert_eq!(SseEvent::from_payload(&payload[..3]), None);
+        assert_eq!(MediaState::from_code(0x42), MediaState::Reserved(0x42));
+        assert_eq!(MediaState::from_code(3), MediaState::FaxRelay);
+    }
+
+    #[test]
+    fn state_change_reaches_the_far_end() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&sent);
+        let mut near = V150Sse::new(move |pkt: &[u8], _repeat: bool| {
+            log.lock().unwrap().push(pkt.to_vec());
+        })
+        .unwrap();
+        let mut far = V150Sse::new(|_: &[u8], _: bool| {}).unwrap();
+
+        near.send(MediaState::ModemRelay, ReasonCode::V8_JM, 0)
+            .unwrap();
+        assert_eq!(near.local_state(), MediaState::ModemRelay);
+        let packets = sent.lock().unwrap().clone();
+        let first = packets.first().expect("no SSE packet sent");
+        assert_eq!(
+            SseEvent::from_payload(first).map(|event| event.state),
+            Some(MediaState::ModemRelay)
+        );
+
+        let event = far.rx_packet(1, 160, first).unwrap();
+        assert_eq!(event.map(|event| event.reason), Some(ReasonCode::V8_JM));
+        assert_eq!(far.remote_state(), MediaState::ModemRelay);
+        // A repeat of the same state is not a new event.
+        assert_eq!(far.rx_packet(2, 320, first).unwrap(), None);
+        assert!(far.rx_packet(3, 480, &[0x04]).is_err());
+    }
+}
+
+// =========================================================================
+// SPRT
+// =========================================================================
+
+mod sprt {
+    use spandsp::sprt::*;
+    use std::sync::{Arc, Mutex};
+
+    type Wire = Arc<Mutex<Vec<Vec<u8>>>>;
+    type Delivered = Arc<Mutex<Vec<(SprtChannel, Vec<u8>)>>>;
+
+    fn endpoint() -> (Sprt, Wire, Delivered) {
+        let wire = Wire::default();
+        let delivered = Delivered::default();
+        let (tx, rx) = (Arc::clone(&wire), Arc::clone(&delivered));
+        let sprt = Sprt::new(
+            0,
+            120,
+            120,
+            move |pkt: &[u8]| tx.lock().unwrap().push(pkt.to_vec()),
+            move |channel: SprtChannel, _seq: u16, msg: &[u8]| {
+                rx.lock().unwrap().push((channel, msg.to_vec()))
+            },
+        )
+        .unwrap();
+        (sprt, wire, delivered)
+    }
+
+    #[test]
+    fn channels() {
+        assert_eq!(
+            SprtChannel::from_id(2),
+            Some(SprtChannel::ExpeditedReliableSequenced)
+        );
+        assert_eq!(SprtChannel::from_id(4), None);
+        assert!(SprtChannel::ReliableSequenced.is_reliable());
+        assert!(!SprtChannel::UnreliableSequenced.is_reliable());
+        assert_eq!(
+            SprtChannelConfig::default_for(SprtChannel::ReliableSequenced).window_size,
+            32
+        );
+        assert!(Sprt::new(0, 128, 120, |_: &[u8]| {}, |_, _, _: &[u8]| {}).is_err());
+
+        let (mut sprt, _, _) = endpoint();
+        let config = SprtChannelConfig {
+            payload_bytes: 100,
+            window_size: 16,
+            max_tries: 5,
+        };
+        sprt.configure_channel(SprtChannel::ReliableSequenced, config)
+            .unwrap();
+        assert_eq!(sprt.channel_config(SprtChannel::ReliableSequenced), config);
+    }
+
+    #[test]
+    fn messages_cross_between_endpoints() {
+        let (mut a, a_wire, _) = endpoint();
+        let (mut b, _, b_delivered) = endpoint();
+
+        a.send(SprtChannel::UnreliableUnsequenced, b"hello")
+            .unwrap();
+        a.send(SprtChannel::ReliableSequenced, b"modem data")
+            .unwrap();
+        a.poll(1_000);
+        for pkt in a_wire.lock().unwrap().drain(..) {
+            b.rx_packet(&pkt).unwrap();
+        }
+        let delivered = b_delivered.lock().unwrap();
+        assert!(delivered.contains(&(SprtChannel::UnreliableUnsequenced, b"hello".to_vec())));
+        assert!(delivered.contains(&(SprtChannel::ReliableSequenced, b"modem data".to_vec())));
+        assert!(!b.far_busy(SprtChannel::ReliableSequenced));
+    }
+}
+
+// ============================================================================
+// ADSI / caller ID
+// ============================================================================
+
+mod adsi {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::adsi::{
+        Absence, AdsiRx, AdsiStandard, AdsiTx, CallDateTime, CallerId, CallerIdBuilder,
+        CallerIdDetector, CallerIdMessage, DtmfCliDecoder, Presentation,
+    };
+
+    #[test]
+    fn mdmf_lays_out_parameters_with_checksum() {
+        let msg = CallerIdBuilder::new()
+            .datetime(3, 14, 15, 9)
+            .number("5551234")
+            .name("DOE JOHN")
+            .build_mdmf()
+            .unwrap();
+        let bytes = msg.to_bytes();
+        assert_eq!(bytes[0], 0x80);
+        assert_eq!(usize::from(bytes[1]), bytes.len() - 3);
+        assert_eq!(&bytes[2..12], b"\x01\x0803141509");
+        assert_eq!(&bytes[12..21], b"\x02\x075551234");
+        assert_eq!(&bytes[21..31], b"\x07\x08DOE JOHN");
+        let sum = bytes.iter().fold(0u8, |s, &b| s.wrapping_add(b));
+        assert_eq!(sum, 0);
+    }
+
+    #[test]
+    fn sdmf_carries_absence_reason() {
+        let msg = CallerIdBuilder::new()
+            .datetime(12, 1, 0, 0)
+            .number_absent(Absence::Private)
+            .build_sdmf()
+            .unwrap();
+        assert_eq!(msg.as_bytes(), b"\x04\x0912010000P");
+        assert!(
+            CallerIdBuilder::new()
+                .datetime(1, 1, 0, 0)
+                .number("1")
+                .name("X")
+                .build_sdmf()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        assert!(CallerIdBuilder::new().build_mdmf().is_err());
+        assert!(
+            CallerIdBuilder::new()
+                .datetime(13, 1, 0, 0)
+                .build_mdmf()
+                .is_err()
+        );
+        assert!(
+            CallerIdBuilder::new()
+                .number("555-1234")
+                .build_mdmf()
+                .is_err()
+        );
+        assert!(
+            CallerIdBuilder::new()
+                .name("A NAME FAR TOO LONG")
+                .build_mdmf()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn message_waiting_forms() {
+        assert_eq!(
+            CallerIdMessage::message_waiting(true, false).as_bytes(),
+            b"\x06\x03\x42\x42\x42"
+        );
+        assert_eq!(
+            CallerIdMessage::message_waiting(false, true).as_bytes(),
+            b"\x82\x03\x0b\x01\x00"
+        );
+    }
+
+    #[test]
+    fn tx_sends_fsk_for_message() {
+        let msg = CallerIdBuilder::new()
+            .datetime(3, 14, 15, 9)
+            .number("5551234567")
+            .build_mdmf()
+            .unwrap();
+        let mut tx = AdsiTx::new(AdsiStandard::Class).unwrap();
+        tx.put_message(&msg).unwrap();
+        let mut amp = [0i16; 160];
+        let mut total = 0;
+        let mut loud = false;
+        loop {
+            let n = tx.tx(&mut amp);
+            if n == 0 || total > 8000 * 5 {
+                break;
+            }
+            loud |= amp[..n].iter().any(|&s| s.unsigned_abs() > 1000);
+            total += n;
+        }
+        assert!(loud);
+        // Seizure, marks and ~30 bytes at 1200 baud: well over 0.5 s.
+        assert!(total > 4000, "only {total} samples");
+    }
+
+    #[test]
+    fn parse_reads_back_built_messages() {
+        let msg = CallerIdBuilder::new()
+            .datetime(3, 14, 15, 9)
+            .number("5551234")
+            .name("DOE JOHN")
+            .build_mdmf()
+            .unwrap();
+        let expected = CallerId {
+            number: Some("5551234".into()),
+            name: Some("DOE JOHN".into()),
+            datetime: Some(CallDateTime {
+                month: 3,
+                day: 14,
+                hour: 15,
+                minute: 9,
+            }),
+            presentation: Presentation::Allowed,
+        };
+        for bytes in [msg.as_bytes().to_vec(), msg.to_bytes()] {
+            assert_eq!(
+                CallerId::parse(AdsiStandard::Class, &bytes).unwrap(),
+                expected
+            );
+        }
+        let mut corrupt = msg.to_bytes();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(CallerId::parse(AdsiStandard::Class, &corrupt).is_err());
+
+        let sdmf = CallerIdBuilder::new()
+            .datetime(12, 1, 0, 0)
+            .number_absent(Absence::Unavailable)
+            .build_sdmf()
+            .unwrap();
+        let id = CallerId::parse(AdsiStandard::Clip, sdmf.as_bytes()).unwrap();
+        assert_eq!(id.number, None);
+        assert_eq!(id.presentation, Presentation::Unavailable);
+    }
+
+    #[test]
+    fn parse_handles_standard_quirks() {
+        // CLASS dialable number stands in for a missing calling number; ETSI
+        // uses the same code for the called number.
+        let msg = b"\x80\x05\x03\x03555";
+        let class = CallerId::parse(AdsiStandard::Class, msg).unwrap();
+        assert_eq!(class.number.as_deref(), Some("555"));
+        let clip = CallerId::parse(AdsiStandard::Clip, msg).unwrap();
+        assert_eq!(clip.number, None);
+
+        let dtmf = CallerId::parse(AdsiStandard::ClipDtmf, b"A0123456789C").unwrap();
+        assert_eq!(dtmf.number.as_deref(), Some("0123456789"));
+        let withheld = CallerId::parse(AdsiStandard::ClipDtmf, b"B10C").unwrap();
+        assert_eq!(withheld.presentation, Presentation::Private);
+
+        // DLE SOH header DLE STX, type, length, number, DLE ETX, CRC; with
+        // a DLE in the number doubled and the parity bit set on some bytes.
+        let jclip = [
+            0x10, 0x81, 0x07, 0x90, 0x02, 0x40, 0x05, 0x02, 0x03, 0xB0, 0x33, 0x10, 0x10, 0x90,
+            0x03, 0x00, 0x00,
+        ];
+        let id = CallerId::parse(AdsiStandard::Jclip, &jclip).unwrap();
+        assert_eq!(id.number.as_deref(), Some("03\u{10}"));
+        assert!(CallerId::parse(AdsiStandard::Tdd, b"HELLO").is_err());
+    }
+
+    #[test]
+    fn rx_decodes_tx_caller_id() {
+        let msg = CallerIdBuilder::new()
+            .datetime(3, 14, 15, 9)
+            .number("5551234567")
+            .name("DOE JOHN")
+            .build_mdmf()
+            .unwrap();
+        let mut tx = AdsiTx::new(AdsiStandard::Class).unwrap();
+        tx.put_message(&msg).unwrap();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let mut rx = AdsiRx::caller_id(AdsiStandard::Class, move |id| {
+            sink.lock().unwrap().push(id);
+        })
+        .unwrap();
+        let mut amp = [0i16; 160];
+        for _ in 0..100 {
+            let n = tx.tx(&mut amp);
+            amp[n..].fill(0);
+            rx.rx(&amp);
+        }
+        let received = received.lock().unwrap();
+        let id = received[0].as_ref().unwrap();
+        assert_eq!(id.number.as_deref(), Some("5551234567"));
+        assert_eq!(id.name.as_deref(), Some("DOE JOHN"));
+    }
+
+    #[test]
+    fn build_lays_out_each_standard() {
+        let caller = CallerIdBuilder::new()
+            .datetime(3, 14, 15, 9)
+            .number("0312345678")
+            .name("DOE JOHN");
+        for standard in [
+            AdsiStandard::Class,
+            AdsiStandard::Clip,
+            AdsiStandard::Aclip,
+            AdsiStandard::Jclip,
+            AdsiStandard::ClipDtmf,
+        ] {
+            let msg = caller.build(standard).unwrap();
+            let id = CallerId::parse(standard, &msg).unwrap();
+            assert_eq!(id.number.as_deref(), Some("0312345678"), "{standard:?}");
+            assert_eq!(id.presentation, Presentation::Allowed);
+        }
+        assert_eq!(
+            caller.build(AdsiStandard::ClipDtmf).unwrap(),
+            b"A0312345678C"
+        );
+        let withheld = CallerIdBuilder::new().number_absent(Absence::Private);
+        assert_eq!(withheld.build(AdsiStandard::ClipDtmf).unwrap(), b"B10C");
+        let jclip = withheld.build(AdsiStandard::Jclip).unwrap();
+        assert_eq!(
+            CallerId::parse(AdsiStandard::Jclip, &jclip)
+                .unwrap()
+                .presentation,
+            Presentation::Private
+        );
+        assert!(caller.build(AdsiStandard::Tdd).is_err());
+        assert!(CallerIdBuilder::new().build(AdsiStandard::Jclip).is_err());
+    }
+
+    #[test]
+    fn put_caller_id_sends_clip() {
+        let mut tx = AdsiTx::new(AdsiStandard::Clip).unwrap();
+        tx.put_caller_id(&CallerIdBuilder::new().number("1632960123"))
+            .unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let mut rx = AdsiRx::caller_id(AdsiStandard::Clip, move |id| {
+            sink.lock().unwrap().push(id);
+        })
+        .unwrap();
+        let mut amp = [0i16; 160];
+        for _ in 0..150 {
+            let n = tx.tx(&mut amp);
+            amp[n..].fill(0);
+            rx.rx(&amp);
+        }
+        let received = received.lock().unwrap();
+        let id = received[0].as_ref().unwrap();
+        assert_eq!(id.number.as_deref(), Some("1632960123"));
+    }
+
+    #[test]
+    fn detector_decodes_between_rings() {
+        let mut tx = AdsiTx::new(AdsiStandard::Class).unwrap();
+        tx.put_caller_id(
+            &CallerIdBuilder::new()
+                .datetime(12, 25, 7, 30)
+                .number_absent(Absence::Private)
+                .name("SANTA"),
+        )
+        .unwrap();
+        let mut detector = CallerIdDetector::new(AdsiStandard::Class).unwrap();
+        let mut amp = [0i16; 160];
+        let mut results = Vec::new();
+        for _ in 0..100 {
+            let n = tx.tx(&mut amp);
+            amp[n..].fill(0);
+            results.extend(detector.rx(&amp));
+        }
+        assert_eq!(results.len(), 1);
+        let caller = detector.caller_id().unwrap();
+        assert_eq!(caller, results[0].as_ref().unwrap());
+        assert_eq!(caller.number, None);
+        assert_eq!(caller.name.as_deref(), Some("SANTA"));
+        assert_eq!(caller.presentation, Presentation::Private);
+        assert_eq!(
+            caller.datetime,
+            Some(CallDateTime {
+                month: 12,
+                day: 25,
+                hour: 7,
+                minute: 30
+            })
+        );
+        assert!(CallerIdDetector::new(AdsiStandard::Tdd).is_err());
+    }
+
+    #[test]
+    fn detector_returns_every_message_in_a_block() {
+        let mut audio = Vec::new();
+        for number in ["5551234", "5556789"] {
+            let mut tx = AdsiTx::new(AdsiStandard::Class).unwrap();
+            tx.put_caller_id(&CallerIdBuilder::new().number(number))
+                .unwrap();
+            let mut amp = [0i16; 160];
+            for _ in 0..100 {
+                let n = tx.tx(&mut amp);
+                amp[n..].fill(0);
+                audio.extend_from_slice(&amp);
+            }
+        }
+        let mut detector = CallerIdDetector::new(AdsiStandard::Class).unwrap();
+        let results = detector.rx(&audio);
+        let numbers: Vec<_> = results
+            .iter()
+            .map(|r| r.as_ref().unwrap().number.as_deref().unwrap())
+            .collect();
+        assert_eq!(numbers, ["5551234", "5556789"]);
+        assert_eq!(
+            detector.caller_id().unwrap().number.as_deref(),
+            Some("5556789")
+        );
+    }
+
+    #[test]
+    fn message_waiting_lights_the_lamp() {
+        for (standard, active) in [(AdsiStandard::Class, true), (AdsiStandard::Clip, false)] {
+            let mut tx = AdsiTx::message_waiting(standard, active).unwrap();
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&received);
+            let mut rx = AdsiRx::new(standard, move |msg| {
+                sink.lock().unwrap().push(msg.to_vec());
+            })
+            .unwrap();
+            let mut amp = [0i16; 160];
+            for _ in 0..100 {
+                let n = tx.tx(&mut amp);
+                amp[n..].fill(0);
+                rx.rx(&amp);
+            }
+            let expected = CallerIdMessage::message_waiting(active, true);
+            let received = received.lock().unwrap();
+            assert!(
+                received[0].starts_with(expected.as_bytes()),
+                "{standard:?}: {:02x?}",
+                received[0]
+            );
+        }
+        assert!(AdsiTx::message_waiting(AdsiStandard::ClipDtmf, true).is_err());
+    }
+
+    #[test]
+    fn dtmf_cli_decoder_frames_digits() {
+        let mut cli = DtmfCliDecoder::new();
+        // Stray digits before the start digit are ignored; digits may
+        // arrive a few at a time.
+        assert!(cli.push("95").is_empty());
+        assert!(cli.push("D0123").is_empty());
+        assert!(cli.is_receiving());
+        let callers = cli.push("456789#");
+        assert_eq!(callers.len(), 1);
+        let caller = callers[0].as_ref().unwrap();
+        assert_eq!(caller.number.as_deref(), Some("0123456789"));
+        assert!(!cli.is_receiving());
+
+        let withheld = cli.push("b10c");
+        assert_eq!(
+            withheld[0].as_ref().unwrap().presentation,
+            Presentation::Private
+        );
+
+        // The first ring can cut the terminator off.
+        assert!(cli.push("A5551234").is_empty());
+        let caller = cli.finish().unwrap().unwrap();
+        assert_eq!(caller.number.as_deref(), Some("5551234"));
+        assert!(cli.finish().is_none());
+
+        let overrun = cli.push(&format!("A{}", "1".repeat(100)));
+        assert_eq!(overrun.len(), 1);
+        assert!(overrun[0].is_err());
+    }
+
+    #[test]
+    fn dtmf_cli_decoder_returns_every_message_in_a_push() {
+        let mut cli = DtmfCliDecoder::new();
+        // A detector polled rarely can hand over a withheld CLI and the
+        // number that follows it in one string.
+        let callers = cli.push("B10CD0123456789#");
+        assert_eq!(callers.len(), 2);
+        assert_eq!(
+            callers[0].as_ref().unwrap().presentation,
+            Presentation::Private
+        );
+        assert_eq!(
+            callers[1].as_ref().unwrap().number.as_deref(),
+            Some("0123456789")
+        );
+        assert!(!cli.is_receiving());
+    }
+
+    #[test]
+    fn dtmf_cli_decoder_reads_dtmf_rx() {
+        let mut tx = spandsp::dtmf::DtmfTx::new().unwrap();
+        tx.put("A4930123456C").unwrap();
+        let mut rx = spandsp::dtmf::DtmfRx::new().unwrap();
+        let mut cli = DtmfCliDecoder::new();
+        let mut amp = [0i16; 160];
+        let mut callers = Vec::new();
+        for _ in 0..150 {
+            let n = tx.generate(&mut amp);
+            amp[n..].fill(0);
+            rx.rx(&amp);
+            callers.extend(cli.push(&rx.get(128)));
+        }
+        assert_eq!(callers.len(), 1);
+        let caller = callers[0].as_ref().unwrap();
+        assert_eq!(caller.number.as_deref(), Some("4930123456"));
+    }
+}
+
+// =========================================================================
+// Type II caller ID
+// =========================================================================
+mod type2_cid {
+    use spandsp::adsi::{AdsiStandard, CallerIdBuilder};
+    use spandsp::type2_cid::*;
+
+    use super::*;
+
+    #[test]
+    fn cas_is_detected_once() {
+        let mut tx = CasTx::with_sas(-15).unwrap();
+        let mut detector = CasDetector::new().unwrap();
+        let mut amp = [0i16; 160];
+        let mut hits = 0;
+        for _ in 0..40 {
+            let n = tx.generate(&mut amp);
+            amp[n..].fill(0);
+            hits += detector.rx(&amp) as u32;
+        }
+        assert!(tx.is_finished());
+        assert_eq!(hits, 1);
+        assert_eq!(detector.detected(), 1);
+    }
+
+    #[test]
+    fn held_tones_and_speech_are_not_cas() {
+        let mut detector = CasDetector::new().unwrap();
+        let held: Vec<i16> = sine_wave(2130.0, 8000.0, 4000, 3000.0)
+            .iter()
+            .zip(sine_wave(2750.0, 8000.0, 4000, 3000.0))
+            .map(|(&a, b)| a / 2 + b / 2)
+            .collect();
+        assert!(!detector.rx(&held));
+        assert!(!detector.rx(&[0i16; 800]));
+        assert!(!detector.rx(&sine_wave(1000.0, 8000.0, 8000, 8000.0)));
+        assert_eq!(detector.detected(), 0);
+    }
+
+    #[test]
+    fn sender_and_receiver_deliver_caller_id() {
+        let mut sender = Type2Sender::new(AdsiStandard::Class).unwrap();
+        let mut phone = Type2Receiver::new(AdsiStandard::Class).unwrap();
+        sender
+            .offer(
+                &CallerIdBuilder::new()
+                    .datetime(6, 1, 9, 45)
+                    .number("5551234567")
+                    .name("DOE JANE"),
+            )
+            .unwrap();
+        assert!(sender.offer(&CallerIdBuilder::new().number("1")).is_err());
+
+        let mut from_phone = [0i16; 160];
+        let mut outcome = None;
+        let mut received = None;
+        let mut muted = false;
+        for _ in 0..200 {
+            let mut to_phone = [0i16; 160];
+            outcome = outcome.or(sender.process(&from_phone, &mut to_phone));
+            let mut mic = [0i16; 160];
+            received = received.or(phone.process(&mut to_phone, &mut mic).unwrap());
+            muted |= phone.is_muted();
+            from_phone = mic;
+        }
+        assert_eq!(outcome, Some(Type2Outcome::Delivered { digit: 'D' }));
+        assert!(muted);
+        assert!(!phone.is_muted());
+        let Some(Type2Reception::Received(Ok(caller))) = received else {
+            panic!("no caller ID: {received:?}");
+        };
+        assert_eq!(caller.number.as_deref(), Some("5551234567"));
+        assert_eq!(caller.name.as_deref(), Some("DOE JANE"));
+        assert_eq!(phone.caller_id(), Some(&caller));
+        assert_eq!(sender.state(), SenderState::Idle);
+    }
+
+    #[test]
+    fn unacknowledged_offer_is_dropped() {
+        let mut sender = Type2Sender::new(AdsiStandard::Clip).unwrap();
+        sender.set_sas(false);
+        sender
+            .offer(&CallerIdBuilder::new().number("1632960123"))
+            .unwrap();
+        let silence = [0i16; 160];
+        let mut outcomes = Vec::new();
+        for _ in 0..50 {
+            let mut to_phone = [0i16; 160];
+            outcomes.extend(sender.process(&silence, &mut to_phone));
+        }
+        assert_eq!(outcomes, [Type2Outcome::NotAcknowledged]);
+
+        // Idle, the call's audio goes through untouched.
+        let voice = sine_wave(500.0, 8000.0, 160, 5000.0);
+        let mut to_phone = [0i16; 160];
+        to_phone.copy_from_slice(&voice);
+        assert_eq!(sender.process(&silence, &mut to_phone), None);
+        assert_eq!(&to_phone[..], &voice[..]);
+    }
+
+    #[test]
+    fn receiver_times_out_without_caller_id() {
+        let mut phone = Type2Receiver::new(AdsiStandard::Class).unwrap();
+        let mut cas = CasTx::new(-15).unwrap();
+        let mut receptions = Vec::new();
+        for _ in 0..150 {
+            let mut line = [0i16; 160];
+            let n = cas.generate(&mut line);
+            line[n..].fill(0);
+            let mut mic = [0i16; 160];
+            receptions.extend(phone.process(&mut line, &mut mic).unwrap());
+        }
+        assert!(matches!(receptions[..], [Type2Reception::TimedOut]));
+        assert!(!phone.is_muted());
+        assert_eq!(phone.caller_id(), None);
+    }
+
+    #[test]
+    fn only_fsk_standards_have_type2() {
+        assert!(Type2Sender::new(AdsiStandard::ClipDtmf).is_err());
+        assert!(Type2Receiver::new(AdsiStandard::Jclip).is_err());
+        assert!(Type2Receiver::new(AdsiStandard::Clip).is_ok());
+    }
+}
+
+// ============================================================================
+// V.18 textphone
+// ============================================================================
+
+mod v18 {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::v18::*;
+
+    /// Send `text` from one fixed-mode textphone to another, returning what
+    /// the far end received.
+    fn roundtrip(mode: V18Mode, text: &str) -> String {
+        let mut tx = V18::new(mode, true).unwrap();
+        let received = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&received);
+        let mut rx = V18::with_callback(mode, V18Nation::Global, false, move |s| {
+            sink.lock().unwrap().push_str(s)
+        })
+        .unwrap();
+        assert_eq!(tx.put(text).unwrap(), text.len());
+        let mut amp = [0i16; 160];
+        // Five seconds is ample for a few characters at 45.45 baud.
+        for _ in 0..250 {
+            let n = tx.generate(&mut amp);
+            rx.rx(&amp[..n]);
+        }
+        received.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn baudot_roundtrip() {
+        let text = roundtrip(V18Mode::Weitbrecht4545, "HELLO");
+        assert!(text.contains("HELLO"), "received {text:?}");
+    }
+
+    #[test]
+    fn fixed_mode_is_reported() {
+        let tty = V18::new(V18Mode::Weitbrecht50, false).unwrap();
+        assert_eq!(tty.mode(), Some(V18Mode::Weitbrecht50));
+        assert!(!tty.is_calling_party());
+        assert_eq!(tty.nation(), V18Nation::Global);
+    }
+
+    #[test]
+    fn received_text_is_buffered_without_a_callback() {
+        let mut tx = V18::new(V18Mode::Weitbrecht4545, true).unwrap();
+        let mut rx = V18::new(V18Mode::Weitbrecht4545, false).unwrap();
+        tx.put("GA").unwrap();
+        let mut amp = [0i16; 160];
+        for _ in 0..250 {
+            let n = tx.generate(&mut amp);
+            rx.rx(&amp[..n]);
+        }
+        assert!(rx.get_text().contains("GA"));
+        assert!(rx.get_text().is_empty());
+    }
+
+    #[test]
+    fn put_rejects_non_ascii() {
+        let mut tty = V18::automoding(V18Nation::Usa, false).unwrap();
+        assert!(tty.put("caf\u{e9}").is_err());
+        assert_eq!(tty.nation(), V18Nation::Usa);
+    }
+
+    #[test]
+    fn modes_have_names() {
+        assert!(!V18Mode::Bell103.to_string().is_empty());
+        assert!(!V18Mode::Edt.to_string().is_empty());
+    }
+}
+
+// ============================================================================
+// R2 MF / MFC
+// ============================================================================
+
+mod r2_mf {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::r2_mf::{R2MfRx, R2MfTx, R2Signal};
+
+    #[test]
+    fn callback_reports_signal_changes() {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&changes);
+        let mut rx =
+            R2MfRx::with_callback(false, move |signal| sink.lock().unwrap().push(signal)).unwrap();
+        let mut tx = R2MfTx::backward().unwrap();
+        assert!(!tx.is_forward());
+        let one = R2Signal::new(1).unwrap();
+        let mut amp = [0i16; 160];
+        for signal in [Some(one), None] {
+            tx.put(signal).unwrap();
+            for _ in 0..10 {
+                tx.generate(&mut amp);
+                rx.rx(&amp);
+            }
+        }
+        assert_eq!(*changes.lock().unwrap(), [Some(one), None]);
+        assert!(format!("{rx:?}").contains("has_callback: true"));
+    }
+
+    #[test]
+    fn directions_do_not_hear_each_other() {
+        let mut tx = R2MfTx::forward().unwrap();
+        let mut rx = R2MfRx::backward().unwrap();
+        tx.put(R2Signal::new(5)).unwrap();
+        let mut amp = [0i16; 160];
+        for _ in 0..10 {
+            tx.generate(&mut amp);
+            rx.rx(&amp);
+        }
+        assert_eq!(rx.signal(), None);
+        assert!(R2MfRx::forward().unwrap().is_forward());
+    }
+}
+
+mod r2_mfc {
+    use spandsp::r2_mf::{R2MfRx, R2MfTx, R2Signal};
+    use spandsp::r2_mfc::{R2Callee, R2Caller, R2Event, R2Outcome, R2Reject, R2Variant};
+
+    #[test]
+    fn tx_signal_is_detected() {
+        let mut tx = R2MfTx::new(true).unwrap();
+        let mut rx = R2MfRx::new(true).unwrap();
+        let seven = R2Signal::new(7).unwrap();
+        tx.put(Some(seven)).unwrap();
+        let mut amp = [0i16; 160];
+        for _ in 0..10 {
+            tx.generate(&mut amp);
+            rx.rx(&amp);
+        }
+        assert_eq!(rx.signal(), Some(seven));
+        tx.put(None).unwrap();
+        for _ in 0..10 {
+            tx.generate(&mut amp);
+            rx.rx(&amp);
+        }
+        assert_eq!(rx.signal(), None);
+    }
+
+    #[test]
+    fn signals_map_to_digits() {
+        assert_eq!(R2Signal::from_digit('0').map(R2Signal::number), Some(10));
+        assert_eq!(R2Signal::new(3).and_then(R2Signal::to_digit), Some('3'));
+        assert_eq!(R2Signal::new(15).and_then(R2Signal::to_digit), None);
+        assert!(R2Signal::new(16).is_none());
+    }
+
+    /// Run a caller and callee back to back until both are done.
+    fn exchange(
+        caller: &mut R2Caller,
+        callee: &mut R2Callee,
+        mut decide: impl FnMut(&mut R2Callee),
+    ) -> (R2Outcome, R2Outcome) {
+        let mut forward = [0i16; 160];
+        let mut backward = [0i16; 160];
+        for _ in 0..3000 {
+            let fwd_in = forward;
+            caller.process(&backward, &mut forward).unwrap();
+            if callee.process(&fwd_in, &mut backward).unwrap().is_some() {
+                decide(callee);
+            }
+            if let (Some(a), Some(b)) = (caller.outcome(), callee.outcome()) {
+                return (a, b);
+            }
+        }
+        panic!("exchange did not finish: {caller:?} {callee:?}");
+    }
+
+    #[test]
+    fn itu_call_is_accepted() {
+        let mut caller = R2Caller::dial(R2Variant::itu(), "4321", None).unwrap();
+        let mut callee = R2Callee::answer(R2Variant::itu(), None).unwrap();
+        let mut offered = None;
+        let outcomes = exchange(&mut caller, &mut callee, |callee| {
+            offered = callee.offer();
+            callee.accept(true).unwrap();
+        });
+        let accepted = R2Outcome::Accepted { charge: true };
+        assert_eq!(outcomes, (accepted, accepted));
+        let offer = offered.unwrap();
+        assert_eq!(offer.dnis, "4321");
+        assert_eq!(offer.ani, None);
+        assert_eq!(offer.category, R2Variant::itu().category);
+    }
+
+    #[test]
+    fn brazil_call_carries_ani_and_is_rejected() {
+        let mut caller = R2Caller::dial(R2Variant::brazil(), "123", Some("5550")).unwrap();
+        let mut callee = R2Callee::answer(R2Variant::brazil(), Some(3)).unwrap();
+        let mut offered = None;
+        let outcomes = exchange(&mut caller, &mut callee, |callee| {
+            offered = callee.offer();
+            callee.reject(R2Reject::Busy).unwrap();
+        });
+        let busy = R2Outcome::Rejected(R2Reject::Busy);
+        assert_eq!(outcomes, (busy, busy));
+        let offer = offered.unwrap();
+        assert_eq!(offer.dnis, "123");
+        assert_eq!(offer.ani.as_deref(), Some("5550"));
+    }
+
+    #[test]
+    fn events_trace_the_exchange() {
+        let mut caller = R2Caller::dial(R2Variant::itu(), "42", None).unwrap();
+        let mut callee = R2Callee::answer(R2Variant::itu(), None).unwrap();
+        exchange(&mut caller, &mut callee, |callee| {
+            callee.accept(false).unwrap()
+        });
+        let accepted = R2Event::Finished(R2Outcome::Accepted { charge: false });
+        assert_eq!(
+            callee.take_events(),
+            [
+                R2Event::DnisDigit('4'),
+                R2Event::DnisDigit('2'),
+                R2Event::AddressComplete,
+                R2Event::Category(R2Variant::itu().category),
+                accepted,
+            ]
+        );
+        assert_eq!(caller.take_events(), [R2Event::AddressComplete, accepted]);
+        assert!(callee.take_events().is_empty());
+
+        let mut caller = R2Caller::dial(R2Variant::brazil(), "7", Some("55")).unwrap();
+        let mut callee = R2Callee::answer(R2Variant::brazil(), Some(1)).unwrap();
+        exchange(&mut caller, &mut callee, |callee| {
+            callee.reject(R2Reject::Congestion).unwrap()
+        });
+        let events = callee.take_events();
+        assert_eq!(
+            events[..3],
+            [
+                R2Event::DnisDigit('7'),
+                R2Event::AniRequested,
+                R2Event::Category(R2Variant::brazil().category)
+            ]
+        );
+        let ani: String = events
+            .iter()
+            .filter_map(|event| match event {
+                R2Event::AniDigit(digit) => Some(*digit),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ani, "55");
+        assert_eq!(caller.take_events()[0], R2Event::AniRequested);
+    }
+
+    #[test]
+    fn invalid_numbers_and_early_decisions_are_rejected() {
+        assert!(R2Caller::dial(R2Variant::itu(), "12a", None).is_err());
+        assert!(R2Caller::dial(R2Variant::itu(), "", None).is_err());
+        let mut callee = R2Callee::answer(R2Variant::itu(), None).unwrap();
+        assert!(callee.accept(true).is_err());
+    }
+}
+
+// ============================================================================
+// Bell MF / R1
+// ============================================================================
+
+mod bell_mf {
+    use std::sync::{Arc, Mutex};
+
+    use spandsp::bell_mf::{BellMfRx, BellMfTx};
+
+    fn generated(digits: &str) -> Vec<i16> {
+        let mut tx = BellMfTx::new().unwrap();
+        assert_eq!(tx.put(digits).unwrap(), 0);
+        let mut audio = vec![0i16; 8000 * 2];
+        let n = tx.generate(&mut audio);
+        assert!(n > 0 && n < audio.len(), "{n}");
+        audio.truncate(n + 800);
+        audio
+    }
+
+    #[test]
+    fn roundtrip_through_get() {
+        let mut rx = BellMfRx::new().unwrap();
+        for chunk in generated("*5551234#").chunks(160) {
+            rx.rx(chunk);
+        }
+        assert_eq!(rx.get(), "*5551234#");
+        assert_eq!(rx.get(), "");
+    }
+
+    #[test]
+    fn callback_receives_digits() {
+        let heard = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&heard);
+        let mut rx =
+            BellMfRx::with_callback(move |digits| sink.lock().unwrap().push_str(digits)).unwrap();
+        for chunk in generated("*0A").chunks(160) {
+            rx.rx(chunk);
+        }
+        assert_eq!(*heard.lock().unwrap(), "*0A");
+        assert!(format!("{rx:?}").contains("has_callback: true"));
+    }
+}
+
+mod mf_r1 {
+    use spandsp::mf_r1::{R1Address, R1Decoder, R1Receiver, R1Sender, StCode};
+
+    #[test]
+    fn address_frames_and_parses() {
+        let address = R1Address::new("5551234", StCode::Stp).unwrap();
+        assert_eq!(address.framed(), "*5551234A");
+        assert_eq!(R1Address::parse("*5551234A").unwrap(), address);
+        assert!(R1Address::new("55*1", StCode::St).is_err());
+        assert!(R1Address::new("", StCode::St).is_err());
+        assert!(R1Address::parse("5551234#").is_err());
+        assert!(R1Address::parse("*5551234").is_err());
+    }
+
+    #[test]
+    fn decoder_finds_bursts_across_calls() {
+        let mut decoder = R1Decoder::new();
+        assert!(decoder.feed("9*12").is_empty());
+        assert!(decoder.in_burst());
+        let addresses = decoder.feed("34#*0C");
+        assert_eq!(
+            addresses,
+            vec![
+                R1Address::new("1234", StCode::St).unwrap(),
+                R1Address::new("0", StCode::St3p).unwrap(),
+            ]
+        );
+        assert!(!decoder.in_burst());
+        // The stray 9, then a burst cut short by KP.
+        assert!(decoder.feed("*77*8B").len() == 1);
+        assert_eq!(decoder.discarded(), 4);
+    }
+
+    #[test]
+    fn addresses_cross_over_audio() {
+        let mut sender = R1Sender::new().unwrap();
+        let mut receiver = R1Receiver::new().unwrap();
+        let sent = R1Address::new("2125551234", StCode::St).unwrap();
+        sender.send(&sent).unwrap();
+        let mut received = Vec::new();
+        let mut amp = [0i16; 160];
+        for _ in 0..200 {
+            let n = sender.tx(&mut amp);
+            amp[n..].fill(0);
+            received.extend(receiver.rx(&amp));
+        }
+        assert_eq!(received, vec![sent]);
+        assert_eq!(receiver.decoder().discarded(), 0);
+    }
+}
+
+// ===========================================================================
+// Recording analysis
+// ===========================================================================
+
+mod analyze {
+    use spandsp::analyze::*;
+    use spandsp::dtmf::DtmfTx;
+
+    fn tone(out: &mut Vec<i16>, freqs: &[f64], ms: usize, amp: f64) {
+        let start = out.len();
+        for i in 0..ms * 8 {
+            let t = (start + i) as f64 / 8000.0;
+            let v: f64 = freqs
+                .iter()
+                .map(|f| (2.0 * std::f64::consts::PI * f * t).sin() * amp)
+                .sum();
+            out.push(v as i16);
+        }
+    }
+
+    fn kinds(analysis: &Analysis) -> Vec<EventKind> {
+        analysis.events.iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn finds_call_progress_tones() {
+        let mut audio = Vec::new();
+        tone(&mut audio, &[350.0, 440.0], 1500, 3000.0);
+        tone(&mut audio, &[], 500, 0.0);
+        for _ in 0..2 {
+            tone(&mut audio, &[440.0, 480.0], 2000, 3000.0);
+            tone(&mut audio, &[], 4000, 0.0);
+        }
+        for _ in 0..3 {
+            tone(&mut audio, &[480.0, 620.0], 500, 3000.0);
+            tone(&mut audio, &[], 500, 0.0);
+        }
+        let analysis = analyze(&audio).unwrap();
+        assert_eq!(
+            kinds(&analysis),
+            vec![
+                EventKind::CallProgress(CallProgressTone::DialTone),
+                EventKind::CallProgress(CallProgressTone::Ringback),
+                EventKind::CallProgress(CallProgressTone::Busy),
+            ]
+        );
+        assert!(analysis.events[0].duration_secs() > 1.0);
+    }
+
+    #[test]
+    fn decodes_sit() {
+        let mut audio = Vec::new();
+        tone(&mut audio, &[], 200, 0.0);
+        tone(&mut audio, &[913.8], 274, 5000.0);
+        tone(&mut audio, &[1370.6], 274, 5000.0);
+        tone(&mut audio, &[1776.7], 380, 5000.0);
+        tone(&mut audio, &[], 500, 0.0);
+        let analysis = analyze(&audio).unwrap();
+        assert_eq!(kinds(&analysis), vec![EventKind::Sit(SitCode::Intercept)]);
+    }
+
+    #[test]
+    fn finds_dtmf_and_levels() {
+        let mut tx = DtmfTx::new().unwrap();
+        tx.put("159#").unwrap();
+        let mut audio = vec![0i16; 8000];
+        let n = tx.generate(&mut audio);
+        assert!(n > 0);
+        let analysis = analyze(&audio).unwrap();
+        assert_eq!(analysis.digits(), "159#");
+        assert_eq!(analysis.samples, 8000);
+        assert_eq!(analysis.levels.len(), 10);
+        assert!(analysis.peak > 0);
+        assert_eq!(analysis.clipped, 0);
+    }
+
+    #[test]
+    fn reads_wav() {
+        let mut audio = Vec::new();
+        tone(&mut audio, &[350.0, 440.0], 1500, 3000.0);
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + 2 * audio.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&[1, 0, 1, 0]);
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&[2, 0, 16, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(2 * audio.len() as u32).to_le_bytes());
+        for s in &audio {
+            wav.extend_from_slice(&s.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(format!("spandsp-analyze-{}.wav", std::process::id()));
+        std::fs::write(&path, &wav).unwrap();
+        let analysis = analyze_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(analysis.samples, audio.len() as u64);
+        assert_eq!(
+            kinds(&analysis),
+            vec![EventKind::CallProgress(CallProgressTone::DialTone)]
+        );
+        assert!(analyze_wav("/nonexistent/recording.wav").is_err());
+    }
+}
+
+// ===========================================================================
+// G.168 harness
+// ===========================================================================
+
+mod g168 {
+    use spandsp::echo::EchoCanFlags;
+    use spandsp::g168::*;
+
+    /// Level relative to a full-scale sine, in dB.
+    fn level_db(amp: &[i16]) -> f64 {
+        let mean_sq = amp.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>() / amp.len() as f64;
+        10.0 * (mean_sq / (32767.0f64 * 32767.0 / 2.0)).log10()
+    }
+
+    fn css_period(level_dbm0: f32) -> Vec<i16> {
+        let mut css = CompositeSource::new(level_dbm0, 7).unwrap();
+        let mut amp = vec![0i16; 5600];
+        css.fill(&mut amp);
+        amp
+    }
+
+    #[test]
+    fn css_bursts_are_at_the_requested_level() {
+        let quiet = css_period(-20.0);
+        let loud = css_period(-10.0);
+        let voiced = level_db(&quiet[..389]);
+        assert!((level_db(&quiet[389..1989]) - voiced).abs() < 0.1);
+        assert!((level_db(&loud[..389]) - voiced - 10.0).abs() < 0.1);
+        assert!(quiet[1989..2800].iter().all(|&s| s == 0));
+        // The second period is the first inverted.
+        assert_eq!(quiet[2800 + 100], -quiet[100]);
+        assert!(CompositeSource::new(0.0, 7).is_err());
+    }
+
+    #[test]
+    fn echo_path_delays_and_attenuates() {
+        let mut path = EchoPath::new(10, 6.0).unwrap();
+        assert_eq!(path.span(), 18);
+        let response: Vec<i16> = (0..20)
+            .map(|i| path.echo(if i == 0 { 10000 } else { 0 }))
+            .collect();
+        assert!(response[..10].iter().all(|&s| s == 0));
+        assert!(response[10..18].iter().any(|&s| s != 0));
+        assert!(response[18..].iter().all(|&s| s == 0));
+        assert!(EchoPath::new(10, -1.0).is_err());
+    }
+
+    #[test]
+    fn adaption_beats_a_frozen_canceller() {
+        let frozen = G168Harness::new(256, EchoCanFlags::empty())
+            .run(G168Test::Test2B)
+            .unwrap();
+        assert!(!frozen.passed(), "{frozen}");
+        let adapting = G168Harness::new(256, EchoCanFlags::ADAPTION)
+            .run(G168Test::Test2B)
+            .unwrap();
+        assert!(adapting.residual_dbm0 < frozen.residual_dbm0 - 6.0);
+    }
+
+    #[test]
+    fn report_covers_every_test() {
+        let report = G168Harness::new(256, EchoCanFlags::default())
+            .run_all()
+            .unwrap();
+        let tests: Vec<_> = report.results().iter().map(|r| r.test).collect();
+        assert_eq!(tests, G168Test::ALL);
+        assert_eq!(report.failures().count() == 0, report.passed());
+        assert!(
+            report
+                .to_string()
+                .contains("3B (double talk after convergence)")
+        );
+    }
+
+    #[test]
+    fn rejects_a_tail_shorter_than_the_echo_path() {
+        let mut harness = G168Harness::new(64, EchoCanFlags::default());
+        assert!(harness.run(G168Test::Test2A).is_err());
+        harness.set_echo_path(16, 6.0).unwrap();
+        assert!(harness.run(G168Test::Test2A).is_ok());
+        assert!(harness.set_rin_level(f32::NAN).is_err());
+    }
+}
+
+// ===========================================================================
+// Audio files
+// ===========================================================================
+
+mod audio_file {
+    use spandsp::audio_file::*;
+    use spandsp::g711::{G711Mode, G711State};
+
+    use super::*;
+
+    #[test]
+    fn au_header_layout() {
+        let mut au = AuFile::from_linear(AuEncoding::Linear16, &[1, -2]);
+        au.set_annotation("call");
+        let bytes = au.to_bytes();
+        assert_eq!(&bytes[..4], b".snd");
+        // Offset 32: 24 header bytes and "call" plus NUL padding.
+        assert_eq!(
+            &bytes[4..24],
+            &[
+                0, 0, 0, 32, 0, 0, 0, 4, 0, 0, 0, 3, 0, 0, 0x1F, 0x40, 0, 0, 0, 1
+            ]
+        );
+        assert_eq!(&bytes[24..32], b"call\0\0\0\0");
+        assert_eq!(&bytes[32..], &[0, 1, 0xFF, 0xFE]);
+        let parsed = AuFile::parse(&bytes).unwrap();
+        assert_eq!(parsed, au);
+        assert_eq!(parsed.samples(), vec![1, -2]);
+    }
+
+    #[test]
+    fn g711_codes_pass_straight_through() {
+        let amp = sine_wave(1000.0, 8000.0, 160, 8000.0);
+        let mut encoder = G711State::new(G711Mode::ALaw).unwrap();
+        let mut codes = vec![0u8; amp.len()];
+        encoder.encode(&mut codes, &amp);
+
+        let au =
+            AuFile::parse(&AuFile::from_g711(G711Mode::ALaw, codes.clone()).to_bytes()).unwrap();
+        assert_eq!(au.encoding(), AuEncoding::ALaw);
+        assert_eq!(au.data(), &codes[..]);
+        assert_eq!(au.len_samples(), 160);
+
+        let mut decoder = G711State::new(au.g711_mode().unwrap()).unwrap();
+        let mut decoded = vec![0i16; au.data().len()];
+        decoder.decode(&mut decoded, au.data());
+        assert_eq!(decoded, au.samples());
+    }
+
+    #[test]
+    fn au_reads_unknown_size_and_rejects_other_encodings() {
+        let mut bytes = AuFile::from_linear(AuEncoding::ULaw, &[0, 100, -100]).to_bytes();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        bytes.push(0xFF);
+        assert_eq!(AuFile::parse(&bytes).unwrap().len_samples(), 4);
+
+        bytes[12..16].copy_from_slice(&6u32.to_be_bytes());
+        assert!(AuFile::parse(&bytes).is_err());
+        assert!(AuFile::parse(b"RIFF").is_err());
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let amp = sine_wave(440.0, 8000.0, 800, 12000.0);
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+
+        let au_path = dir.join(format!("spandsp-audio-file-{id}.au"));
+        AuFile::from_linear(AuEncoding::ULaw, &amp)
+            .write(&au_path)
+            .unwrap();
+        let au = AuFile::read(&au_path).unwrap();
+        std::fs::remove_file(&au_path).unwrap();
+        assert_eq!(au.g711_mode(), Some(G711Mode::ULaw));
+        assert_eq!(au.sample_rate(), 8000);
+
+        let raw_path = dir.join(format!("spandsp-audio-file-{id}.ul"));
+        write_raw(&raw_path, G711Mode::ULaw, &amp).unwrap();
+        assert_eq!(std::fs::read(&raw_path).unwrap(), au.data());
+        assert_eq!(read_raw(&raw_path, G711Mode::ULaw).unwrap(), au.samples());
+        std::fs::remove_file(&raw_path).unwrap();
+        assert!(read_raw(&raw_path, G711Mode::ULaw).is_err());
+    }
+}
+
+// ===========================================================================
+// Transcoding
+// ===========================================================================
+
+mod transcode {
+    use spandsp::g711::*;
+    use spandsp::g726::{G726Packing, G726Rate};
+    use spandsp::transcode::*;
+
+    use super::*;
+
+    fn l16(amp: &[i16]) -> Vec<u8> {
+        amp.iter().flat_map(|s| s.to_be_bytes()).collect()
+    }
+
+    fn from_l16(bytes: &[u8]) -> Vec<i16> {
+        bytes
+            .chunks_exact(2)
+            .map(|b| i16::from_be_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn spec_alignment() {
+        let g722 = CodecSpec::g722();
+        assert_eq!(g722.sample_rate(), 16000);
+        assert_eq!(g722.samples_per_unit(), 2);
+        assert_eq!(g722.bytes_for(320), 160);
+        let g726 = CodecSpec::G726 {
+            rate: G726Rate::Rate24000,
+            packing: G726Packing::Left,
+        };
+        assert_eq!(g726.samples_per_unit(), 8);
+        assert_eq!(g726.bytes_for(160), 60);
+        assert!(Transcoder::new(CodecSpec::L16 { sample_rate: 44100 }, g722).is_err());
+    }
+
+    #[test]
+    fn ulaw_to_alaw_matches_the_tables() {
+        let mut transcoder = Transcoder::new(
+            CodecSpec::G711(G711Mode::ULaw),
+            CodecSpec::G711(G711Mode::ALaw),
+        )
+        .unwrap();
+        let ulaw: Vec<u8> = (0..=255).collect();
+        let mut alaw = Vec::new();
+        assert_eq!(transcoder.transcode(&ulaw, &mut alaw), 256);
+        for (&u, &a) in ulaw.iter().zip(&alaw) {
+            assert_eq!(a, linear_to_alaw(ulaw_to_linear(u)), "u-law {u:#04X}");
+        }
+    }
+
+    #[test]
+    fn l16_survives_odd_splits_and_rate_changes() {
+        let amp = sine_wave(1000.0, 8000.0, 800, 10000.0);
+        let bytes = l16(&amp);
+        let narrow = CodecSpec::L16 { sample_rate: 8000 };
+        let wide = CodecSpec::L16 { sample_rate: 16000 };
+
+        let mut copy = Transcoder::new(narrow, narrow).unwrap();
+        let mut out = Vec::new();
+        for piece in bytes.chunks(33) {
+            copy.transcode(piece, &mut out);
+        }
+        assert_eq!(out, bytes);
+
+        let mut up = Transcoder::new(narrow, wide).unwrap();
+        let mut down = Transcoder::new(wide, narrow).unwrap();
+        let mut wideband = Vec::new();
+        up.transcode(&bytes, &mut wideband);
+        assert_eq!(wideband.len(), 2 * bytes.len());
+        let mut back = Vec::new();
+        down.transcode(&wideband, &mut back);
+        let back = from_l16(&back);
+        assert_eq!(back.len(), amp.len());
+        let ratio = rms(&back[400..]) / rms(&amp[400..]);
+        assert!((0.9..1.1).contains(&ratio), "{ratio}");
+    }
+
+    #[test]
+    fn encoder_sees_whole_units_only() {
+        let mut transcoder = Transcoder::new(
+            CodecSpec::L16 { sample_rate: 16000 },
+            CodecSpec::G726 {
+                rate: G726Rate::Rate24000,
+                packing: G726Packing::Left,
+            },
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        assert_eq!(transcoder.transcode(&l16(&[100; 10]), &mut out), 0);
+        assert_eq!(transcoder.pending_samples(), 5);
+        assert_eq!(transcoder.transcode(&l16(&[100; 6]), &mut out), 3);
+        assert_eq!(transcoder.pending_samples(), 0);
+        assert_eq!(transcoder.finish(&mut out), 0);
+
+        // The tail is padded out to a whole unit rather than dropped.
+        assert_eq!(transcoder.transcode(&l16(&[100; 6]), &mut out), 0);
+        assert_eq!(transcoder.finish(&mut out), 3);
+        assert_eq!(out.len(), 6);
+        assert_eq!(transcoder.pending_samples(), 0);
+    }
+
+    #[test]
+    fn g711_to_g722_and_back() {
+        let amp = sine_wave(1000.0, 8000.0, 1600, 8000.0);
+        let ulaw: Vec<u8> = amp.iter().map(|&s| linear_to_ulaw(s)).collect();
+        let mut to_wide =
+            Transcoder::new(CodecSpec::G711(G711Mode::ULaw), CodecSpec::g722()).unwrap();
+        let mut to_narrow =
+            Transcoder::new(CodecSpec::g722(), CodecSpec::G711(G711Mode::ULaw)).unwrap();
+        let mut g722 = Vec::new();
+        for frame in ulaw.chunks(160) {
+            assert_eq!(to_wide.transcode(frame, &mut g722), 160);
+        }
+        let mut back = Vec::new();
+        to_narrow.transcode(&g722, &mut back);
+        assert_eq!(back.len(), ulaw.len());
+        let back: Vec<i16> = back.iter().map(|&c| ulaw_to_linear(c)).collect();
+        let ratio = rms(&back[800..]) / rms(&amp[800..]);
+        assert!((0.8..1.2).contains(&ratio), "{ratio}");
+    }
+}
+
+// ===========================================================================
+// Channel engine
+// ===========================================================================
+
+mod channel {
+    use spandsp::channel::*;
+    use spandsp::dtmf::DtmfTx;
+    use spandsp::g168::{CompositeSource, EchoPath};
+    use spandsp::g711::{linear_to_ulaw, ulaw_to_linear};
+    use spandsp::transcode::CodecSpec;
+
+    fn energy(payload: &[u8]) -> f64 {
+        payload
+            .iter()
+            .map(|&c| f64::from(ulaw_to_linear(c)).powi(2))
+            .sum()
+    }
+
+    #[test]
+    fn rejects_wideband_codecs() {
+        let config = ChannelConfig {
+            codec: CodecSpec::g722(),
+            ..Default::default()
+        };
+        assert!(ChannelEngine::new(config).is_err());
+    }
+
+    #[test]
+    fn reports_dtmf_from_the_line() {
+        let mut engine = ChannelEngine::new(ChannelConfig::default()).unwrap();
+        let mut tx = DtmfTx::new().unwrap();
+        tx.put("42").unwrap();
+        let mut payload = Vec::new();
+        let mut frame = [0i16; 160];
+        for _ in 0..25 {
+            let n = tx.generate(&mut frame);
+            frame[n..].fill(0);
+            assert_eq!(engine.process_rx(&frame, &mut payload), 160);
+        }
+        let digits: String = engine
+            .take_events()
+            .into_iter()
+            .map(|ChannelEvent::Dtmf { digit, .. }| digit)
+            .collect();
+        assert_eq!(digits, "42");
+        assert!(engine.take_events().is_empty());
+        let stats = engine.stats();
+        assert_eq!(stats.dtmf_digits, 2);
+        assert_eq!(stats.rx.samples, 4000);
+    }
+
+    #[test]
+    fn cancels_echo_of_what_it_played() {
+        let mut engine = ChannelEngine::new(ChannelConfig::default()).unwrap();
+        let mut far = CompositeSource::new(-10.0, 1).unwrap();
+        let mut hybrid = EchoPath::new(40, 6.0).unwrap();
+        let mut echo_energy = 0.0;
+        let mut residual_energy = 0.0;
+        for i in 0..200 {
+            let mut far_frame = [0i16; 160];
+            far.fill(&mut far_frame);
+            let payload: Vec<u8> = far_frame.iter().map(|&s| linear_to_ulaw(s)).collect();
+            let mut to_line = Vec::new();
+            assert_eq!(engine.process_tx(&payload, &mut to_line), 160);
+            let from_line: Vec<i16> = to_line.iter().map(|&s| hybrid.echo(s)).collect();
+            let mut to_network = Vec::new();
+            engine.process_rx(&from_line, &mut to_network);
+            if i >= 150 {
+                echo_energy += from_line.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>();
+                residual_energy += energy(&to_network);
+            }
+        }
+        assert!(
+            residual_energy < echo_energy / 10.0,
+            "{residual_energy} vs {echo_energy}"
+        );
+        assert_eq!(engine.stats().reference_dropped, 0);
+    }
+
+    #[test]
+    fn drops_reference_when_the_line_falls_behind() {
+        let mut engine = ChannelEngine::new(ChannelConfig::default()).unwrap();
+        let mut to_line = Vec::new();
+        engine.process_tx(&[0xFF; 16000], &mut to_line);
+        assert_eq!(to_line.len(), 16000);
+        let stats = engine.stats();
+        assert_eq!(stats.reference_dropped, 8000);
+        assert_eq!(stats.tx.samples, 16000);
+
+        let mut bare = ChannelEngine::new(ChannelConfig {
+            echo_tail: None,
+            dtmf: false,
+            ..Default::default()
+        })
+        .unwrap();
+        bare.process_tx(&[0xFF; 16000], &mut to_line);
+        assert_eq!(bare.stats().reference_dropped, 0);
+        assert!(bare.echo_canceller_mut().is_none());
+        assert!(bare.dtmf_rx_mut().is_none());
+    }
+}
+
+// ===========================================================================
+// Version and capabilities
+// ===========================================================================
+
+mod version {
+    use spandsp::version::*;
+
+    #[test]
+    fn reports_the_linked_version() {
+        assert_eq!(version(), Version::new(3, 0, 0));
+        assert_eq!(version().to_string(), "3.0.0");
+        assert_eq!(build_datetime().len(), 15);
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(Version::parse("0.0.6").unwrap(), Version::new(0, 0, 6));
+        assert_eq!(Version::parse("3").unwrap(), Version::new(3, 0, 0));
+        assert!(Version::parse("0.0.6").unwrap() < Version::parse("3.0").unwrap());
+        assert!(Version::parse("3.x").is_err());
+        assert!(Version::parse("1.2.3.4").is_err());
+        assert!(Version::parse("").is_err());
+    }
+
+    #[test]
+    fn capabilities_follow_features() {
+        let caps = capabilities();
+        assert_eq!(caps.fax, cfg!(feature = "fax"));
+        assert_eq!(caps.v34, cfg!(feature = "v34"));
+        assert!(!caps.to_string().is_empty());
+    }
+
+    #[cfg(feature = "fax")]
+    #[test]
+    fn modems_are_gated_on_capabilities() {
+        use spandsp::fax::FaxState;
+        use spandsp::t30::T30ModemSupport;
+
+        let available = T30ModemSupport::available();
+        assert!(available.contains(T30ModemSupport::default()));
+        assert_eq!(
+            available.contains(T30ModemSupport::V34HDX),
+            capabilities().v34
+        );
+
+        let mut fax = FaxState::new(true).unwrap();
+        let mut t30 = fax.get_t30_state().unwrap();
+        t30.set_supported_modems(T30ModemSupport::default())
+            .unwrap();
+        let v34 = t30.set_supported_modems(T30ModemSupport::default() | T30ModemSupport::V34HDX);
+        assert_eq!(v34.is_ok(), capabilities().v34);
+
+        let mut gateway =
+            unsafe { spandsp::t38_gateway::T38Gateway::new_raw(None, std::ptr::null_mut()) }
+                .unwrap();
+        gateway.set_supported_modems(T30ModemSupport::default() | T30ModemSupport::V34HDX);
+        gateway
+            .set_supported_modems_checked(T30ModemSupport::default())
+            .unwrap();
+        let v34 = gateway
+            .set_supported_modems_checked(T30ModemSupport::default() | T30ModemSupport::V34HDX);
+        assert_eq!(v34.is_ok(), capabilities().v34);
+    }
+}
+
+// =========================================================================
+// Thread-safe sessions (requires fax feature)
+// =========================================================================
+#[cfg(feature = "fax")]
+mod sync_session {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use spandsp::fax::FaxState;
+    use spandsp::sync_session::*;
+    use spandsp::t38_terminal::T38Terminal;
+
+    fn assert_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn sessions_are_sync() {
+        assert_sync::<SyncFaxSession>();
+        assert_sync::<SyncT38Session>();
+    }
+
+    #[test]
+    fn fax_audio_and_control_from_different_threads() {
+        let mut fax = FaxState::new(true).unwrap();
+        fax.set_transmit_on_idle(true);
+        let session = Arc::new(SyncFaxSession::new(fax));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let control = {
+            let session = Arc::clone(&session);
+            let done = Arc::clone(&done);
+            std::thread::spawn(move || {
+                let mut calls = 0;
+                while !done.load(Ordering::Relaxed) {
+                    session
+                        .with_t30(|t30| t30.set_tx_ident("+1 555 0100"))
+                        .unwrap()
+                        .unwrap();
+                    session.quality_report();
+                    calls += 1;
+                }
+                calls
+            })
+        };
+
+        for _ in 0..200 {
+            let mut rx = [0i16; 160];
+            let mut tx = [0i16; 160];
+            assert_eq!(session.process_audio(&mut rx, &mut tx), 160);
+        }
+        done.store(true, Ordering::Relaxed);
+        assert!(control.join().unwrap() > 0);
+
+        let snapshot = session.snapshot().unwrap();
+        assert_eq!(snapshot.local_ident.as_deref(), Some("+1 555 0100"));
+        let fax = Arc::into_inner(session).unwrap().into_inner();
+        assert!(fax.calling_party());
+    }
+
+    #[test]
+    fn try_calls_skip_while_locked() {
+        let session = SyncFaxSession::new(FaxState::new(false).unwrap());
+        let mut rx = [0i16; 160];
+        let mut tx = [0i16; 160];
+        session.with(|_fax| {
+            assert_eq!(session.try_process_audio(&mut rx, &mut tx), None);
+            assert!(format!("{session:?}").contains("<locked>"));
+        });
+        assert!(session.try_process_audio(&mut rx, &mut tx).is_some());
+
+        let terminal = unsafe { T38Terminal::new_raw(true, None, std::ptr::null_mut()) }.unwrap();
+        let session = SyncT38Session::from(terminal);
+        session.with(|_terminal| assert!(session.try_tick(160).is_none()));
+        assert_eq!(session.try_tick(160).unwrap().samples, 160);
+    }
+
+    #[test]
+    fn t38_session_survives_a_panicking_control_call() {
+        let terminal = unsafe { T38Terminal::new_raw(false, None, std::ptr::null_mut()) }.unwrap();
+        let session = Arc::new(SyncT38Session::new(terminal));
+        let panicking = Arc::clone(&session);
+        let result = std::thread::spawn(move || {
+            panicking
+                .with_t30(|_t30| panic!("control call failed"))
+                .ok();
+        })
+        .join();
+        assert!(result.is_err());
+
+        assert_eq!(session.tick(160).samples, 160);
+        assert!(
+            session
+                .with_t38_core(|core| core.redundancy_policy())
+                .is_ok()
+        );
+        // A T.30 indicator packet for "no signal".
+        session.rx_packet(&[0x00], 0).unwrap();
+    }
+}
+
+// =========================================================================
+// Tone mixing
+// =========================================================================
+mod tone_mixer {
+    use super::*;
+    use spandsp::dtmf::DtmfRx;
+    use spandsp::tone_generate::ToneCadence;
+    use spandsp::tone_mixer::*;
+
+    fn rms_db(amp: &[i16]) -> f64 {
+        20.0 * rms(amp).log10()
+    }
+
+    fn continuous(level_dbm0: f32) -> Vec<i16> {
+        let mut mixer =
+            ToneMixer::tone(1000, 0, level_dbm0, ToneCadence::continuous(1000), false).unwrap();
+        let mut amp = vec![0i16; 4000];
+        assert_eq!(mixer.mix(&mut amp), 4000);
+        amp
+    }
+
+    #[test]
+    fn mix_saturates() {
+        let mut amp = [30_000i16, -30_000, 100, 5];
+        mix_saturating(&mut amp, &[10_000, -10_000, -200]);
+        assert_eq!(amp, [i16::MAX, i16::MIN, -100, 5]);
+    }
+
+    #[test]
+    fn tone_lands_at_the_requested_level() {
+        let reference = rms_db(&continuous(-10.0));
+        assert!((reference - rms_db(&continuous(-20.0)) - 10.0).abs() < 0.2);
+        // Fractional levels are made up with gain.
+        assert!((reference - rms_db(&continuous(-13.5)) - 3.5).abs() < 0.2);
+        assert!(ToneMixer::tone(1000, 0, 10.0, ToneCadence::continuous(100), false).is_err());
+        assert!(ToneMixer::tone(1000, 0, f32::NAN, ToneCadence::continuous(100), false).is_err());
+    }
+
+    #[test]
+    fn tone_adds_to_existing_audio() {
+        let speech = sine_wave(300.0, 8000.0, 800, 2000.0);
+        let mut amp = speech.clone();
+        let mut mixer =
+            ToneMixer::tone(1400, 0, -20.0, ToneCadence::continuous(50), false).unwrap();
+        // The 50 ms beep covers 400 samples, then the rest is untouched.
+        assert_eq!(mixer.mix(&mut amp), 400);
+        assert_ne!(amp[..400], speech[..400]);
+        assert_eq!(amp[400..], speech[400..]);
+        assert_eq!(mixer.mix(&mut amp), 0);
+    }
+
+    #[test]
+    fn dtmf_overlay_is_detected_over_audio() {
+        let mut amp = sine_wave(440.0, 8000.0, 8000, 500.0);
+        let mut mixer = ToneMixer::dtmf("159", -10.0).unwrap();
+        let n = mixer.mix(&mut amp[..4000]);
+        assert!(n > 0 && n < 4000);
+        mixer.put("#").unwrap();
+        assert!(mixer.mix(&mut amp[4000..]) > 0);
+
+        let mut rx = DtmfRx::new().unwrap();
+        rx.rx(&amp);
+        assert_eq!(rx.get(8), "159#");
+    }
+}
+
+// =========================================================================
+// Super tones and tone plans
+// =========================================================================
+mod tone_plan {
+    use spandsp::super_tone::*;
+    use spandsp::tone_generate::{ToneCadence, ToneFreq};
+    use spandsp::tone_plan::*;
+
+    fn beep(ms: u32) -> SuperToneStep {
+        SuperToneStep::tone(ToneFreq::new(400, -13), ToneFreq::NONE, ms)
+    }
+
+    fn plan() -> TonePlan {
+        TonePlan {
+            name: "test".into(),
+            tones: vec![
+                ToneSpec::new(
+                    "dial",
+                    TonePattern::cadenced(
+                        ToneFreq::new(350, -13),
+                        ToneFreq::new(440, -13),
+                        ToneCadence::continuous(1000),
+                    ),
+                ),
+                ToneSpec::new(
+                    "busy",
+                    TonePattern::cadenced(
+                        ToneFreq::new(480, -24),
+                        ToneFreq::new(620, -24),
+                        ToneCadence::simple(500, 500),
+                    ),
+                ),
+                ToneSpec::new(
+                    "waiting",
+                    TonePattern::Steps(vec![SuperToneStep::repeat(
+                        0,
+                        vec![
+                            beep(200),
+                            SuperToneStep::silence(200),
+                            beep(200),
+                            SuperToneStep::silence(1000),
+                        ],
+                    )]),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn steps_play_in_order() {
+        let mut tx = SuperToneTx::new(vec![
+            beep(10),
+            SuperToneStep::silence(5),
+            SuperToneStep::repeat(3, vec![beep(2), SuperToneStep::silence(3)]),
+        ])
+        .unwrap();
+        let mut amp = vec![1i16; 400];
+        assert_eq!(tx.generate(&mut amp), 200);
+        assert!(tx.is_finished());
+        assert!(amp[..80].iter().any(|&s| s != 0));
+        assert!(amp[80..120].iter().all(|&s| s == 0));
+        for cycle in 0..3 {
+            let start = 120 + cycle * 40;
+            assert!(amp[start..start + 16].iter().any(|&s| s != 0));
+            assert!(amp[start + 16..start + 40].iter().all(|&s| s == 0));
+        }
+        assert!(amp[200..].iter().all(|&s| s == 1));
+
+        tx.restart();
+        assert_eq!(tx.generate(&mut amp), 200);
+    }
+
+    #[test]
+    fn rejects_steps_that_never_play() {
+        assert!(SuperToneTx::new(vec![]).is_err());
+        assert!(SuperToneTx::new(vec![beep(10), SuperToneStep::repeat(2, vec![])]).is_err());
+        let mut descriptor = SuperToneRxDescriptor::new().unwrap();
+        assert!(descriptor.add_tone(&[]).is_err());
+        assert!(
+            descriptor
+                .add_tone(&[ToneElement::new(400, 0, 500, 100)])
+                .is_err()
+        );
+        assert_eq!(
+            descriptor
+                .add_tone(&[ToneElement::new(400, 0, 500, 0)])
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn derives_detection_from_the_pattern() {
+        let plan = plan();
+        assert_eq!(
+            plan.tone("dial").unwrap().detect_elements(),
+            [ToneElement::new(350, 440, 1000, 0)]
+        );
+        assert_eq!(
+            plan.tone("busy").unwrap().detect_elements(),
+            [
+                ToneElement::new(480, 620, 450, 550),
+                ToneElement::silence(450, 550)
+            ]
+        );
+        assert_eq!(plan.tone("waiting").unwrap().detect_elements().len(), 4);
+
+        let mut custom = plan.tone("busy").unwrap().clone();
+        custom.detect = vec![ToneElement::new(480, 620, 400, 600)];
+        assert_eq!(custom.detect_elements(), custom.detect);
+    }
+
+    #[test]
+    fn compiles_to_descriptors() {
+        let plan = plan();
+        plan.validate().unwrap();
+        let busy = plan.tone("busy").unwrap().descriptor().unwrap();
+        assert_eq!(busy.cadence(), ToneCadence::simple(500, 500));
+        assert!(plan.tone("waiting").unwrap().descriptor().is_err());
+        assert!(plan.generator("congestion").is_err());
+
+        let mut twice = plan.clone();
+        twice.tones.push(plan.tones[0].clone());
+        assert!(twice.validate().is_err());
+    }
+
+    #[test]
+    fn detects_its_own_tones() {
+        let plan = plan();
+        for (index, tone) in plan.tones.iter().enumerate() {
+            let mut tx = plan.generator(&tone.name).unwrap();
+            let mut rx = plan.detector().unwrap();
+            let mut amp = vec![0i16; 8000 * 4];
+            assert_eq!(tx.generate(&mut amp), amp.len());
+            for chunk in amp.chunks(160) {
+                rx.rx(chunk);
+            }
+            assert_eq!(rx.current(), Some(index), "{}", tone.name);
+            assert_eq!(rx.take_changes().first(), Some(&Some(index)));
+        }
+    }
+
+    #[test]
+    fn callback_reports_start_and_end() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let plan = plan();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&events);
+        let mut rx = plan
+            .detector_with_callback(move |name, event| {
+                sink.borrow_mut().push((name.to_owned(), event))
+            })
+            .unwrap();
+        let mut amp = vec![0i16; 8000 * 5];
+        plan.generator("busy")
+            .unwrap()
+            .generate(&mut amp[..8000 * 3]);
+        for chunk in amp.chunks(160) {
+            rx.rx(chunk);
+        }
+        assert_eq!(rx.position(), amp.len() as u64);
+        assert!(rx.take_changes().is_empty());
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 2, "{events:?}");
+        let (ref name, start) = events[0];
+        assert_eq!(name, "busy");
+        assert!(matches!(start, SuperToneEvent::Started { tone: 1, .. }));
+        let (ref name, end) = events[1];
+        assert_eq!(name, "busy");
+        assert!(matches!(end, SuperToneEvent::Ended { tone: 1, .. }));
+        // Recognised within the first two cycles, lost soon after it stops.
+        assert!(start.at() < 8000 * 2, "{start:?}");
+        assert!((8000 * 3..8000 * 4).contains(&end.at()), "{end:?}");
+    }
+
+    #[test]
+    fn builder_matches_hand_written_specs() {
+        let built = TonePlan::new("test")
+            .with_tone(
+                TonePlan::busy()
+                    .freqs(480, 620)
+                    .level(-24)
+                    .cadence(ToneCadence::simple(500, 500)),
+            )
+            .with_tone(
+                TonePlan::dial()
+                    .freqs(350, 440)
+                    .detect(vec![ToneElement::new(350, 440, 2000, 0)]),
+            );
+        let busy = &plan().tones[1];
+        assert_eq!(built.tones[0], *busy);
+        assert_eq!(
+            built.tones[1].pattern,
+            TonePattern::cadenced(
+                ToneFreq::new(350, -13),
+                ToneFreq::new(440, -13),
+                ToneCadence::continuous(1000),
+            )
+        );
+        assert_eq!(built.tones[1].detect_elements().len(), 1);
+
+        let once = TonePlan::call_waiting()
+            .freq(440)
+            .cadence(ToneCadence::simple(300, 0))
+            .once()
+            .build();
+        assert_eq!(once.name, "call_waiting");
+        assert_eq!(once.generator().unwrap().steps().len(), 1);
+        let steps = ToneSpec::from(TonePlan::ringback().freq(425).steps(vec![beep(100)]));
+        assert_eq!(steps.pattern, TonePattern::Steps(vec![beep(100)]));
+    }
+
+    #[test]
+    fn bundled_plans_detect_their_own_tones() {
+        for &country in TonePlan::COUNTRIES {
+            let plan = TonePlan::country(&country.to_lowercase()).unwrap();
+            assert_eq!(plan.name, country);
+            plan.validate().unwrap();
+            for (index, tone) in plan.tones.iter().enumerate() {
+                let mut tx = plan.generator(&tone.name).unwrap();
+                let mut rx = plan.detector().unwrap();
+                let mut amp = vec![0i16; 8000 * 12];
+                assert_eq!(tx.generate(&mut amp), amp.len());
+                for chunk in amp.chunks(160) {
+                    rx.rx(chunk);
+                }
+                assert_eq!(rx.current(), Some(index), "{country} {}", tone.name);
+            }
+        }
+        assert!(TonePlan::country("XX").is_none());
+    }
+}
+
+// =========================================================================
+// DTMF talk-off benchmarks
+// =========================================================================
+#[cfg(feature = "talk-off")]
+mod talk_off {
+    use spandsp::dtmf::{DtmfRx, DtmfTx};
+    use spandsp::error::SpanDspError;
+    use spandsp::talk_off::*;
+
+    fn dialled(digits: &str) -> Vec<i16> {
+        let mut tx = DtmfTx::new().unwrap();
+        tx.put(digits).unwrap();
+        let mut audio = vec![0i16; 1000 * digits.len() + 800];
+        let n = tx.generate(&mut audio);
+        audio.truncate(n + 800);
+        audio
+    }
+
+    #[test]
+    fn missing_tapes_are_an_error() {
+        let dir = std::env::temp_dir().join(format!("spandsp-talk-off-{}", std::process::id()));
+        match talk_off_tapes(&dir).unwrap_err() {
+            SpanDspError::InvalidInput(msg) => assert!(msg.contains(TALK_OFF_FILES[0]), "{msg}"),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn counts_false_detects_and_misses() {
+        let tapes = [
+            Tape::new("clean", dialled("123"), "123"),
+            Tape::new("short", dialled("1#3"), "1234"),
+            Tape::talk_off("stray digit", dialled("9")),
+            Tape::talk_off("silence", vec![0; 8000]),
+        ];
+        let report = run(&tapes, DtmfRx::new).unwrap();
+        let results = report.tapes();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].passed(), "{}", results[0]);
+        assert_eq!((results[1].false_detects, results[1].misses), (1, 2));
+        assert_eq!(results[2].detected, "9");
+        assert_eq!(results[2].false_detects, 1);
+        assert!(results[3].passed());
+
+        assert_eq!(report.false_detects(), 2);
+        assert_eq!(report.misses(), 2);
+        assert_eq!(report.expected_digits(), 7);
+        assert!((report.miss_rate() - 2.0 / 7.0).abs() < 1e-9);
+        assert!(report.false_detects_per_hour() > 0.0);
+        assert!(report.within(2, 0.3));
+        assert!(!report.within(1, 0.3));
+        assert!(report.to_string().contains("FAIL short"), "{report}");
+    }
+
+    #[test]
+    fn detector_errors_stop_the_run() {
+        let tapes = [Tape::talk_off("silence", vec![0; 160])];
+        let result = run(&tapes, || -> spandsp::error::Result<DtmfRx> {
+            Err(SpanDspError::InitFailed)
+        });
+        assert!(result.is_err());
+    }
+
+    /// Needs `SPANDSP_TALK_OFF_DIR` to name a directory holding the Mitel
+    /// and Bellcore talk-off tapes; run with `--ignored`.
+    #[test]
+    #[ignore = "needs the talk-off tapes in SPANDSP_TALK_OFF_DIR"]
+    fn talk_off_tapes_with_default_config() {
+        let dir = std::env::var("SPANDSP_TALK_OFF_DIR")
+            .expect("set SPANDSP_TALK_OFF_DIR to the talk-off tape directory");
+        let report = run(&talk_off_tapes(dir).unwrap(), DtmfRx::new).unwrap();
+        assert_eq!(report.tapes().len(), TALK_OFF_FILES.len());
+        assert_eq!(report.misses(), 0);
+    }
+}
+
+// =========================================================================
+// Signal quality measurements
+// =========================================================================
+mod quality {
+    use spandsp::quality::*;
+
+    use super::*;
+
+    #[test]
+    fn correlation_and_lag() {
+        let tone = sine_wave(440.0, 8000.0, 800, 8000.0);
+        assert!((correlation(&tone, &tone) - 1.0).abs() < 1e-9);
+        let inverted: Vec<i16> = tone.iter().map(|&s| -s).collect();
+        assert!((correlation(&tone, &inverted) + 1.0).abs() < 1e-9);
+        assert_eq!(correlation(&tone, &[0; 800]), 0.0);
+
+        let mut late = vec![0i16; 7];
+        late.extend(&tone);
+        let found = best_correlation(&tone[..200], &late, 12).unwrap();
+        assert_eq!(found.lag, 7);
+        assert!(found.correlation > 0.999);
+        assert_eq!(best_correlation(&tone, &tone[..100], 5), None);
+        assert!((rms(&tone) - 8000.0 / 2f64.sqrt()).abs() < 20.0);
+        assert_eq!(rms(&[]), 0.0);
+    }
+
+    #[test]
+    fn snr_counts_quiet_segments() {
+        let mut reference = sine_wave(1000.0, 8000.0, 800, 16000.0);
+        reference.extend(sine_wave(1000.0, 8000.0, 800, 160.0));
+        // The same absolute error throughout swamps the quiet half.
+        let test: Vec<i16> = reference
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| s + if i % 2 == 0 { 100 } else { -100 })
+            .collect();
+        assert_eq!(snr_db(&reference, &reference), f64::INFINITY);
+        let snr = snr_db(&reference, &test);
+        assert!((snr - 38.1).abs() < 0.5, "{snr}");
+        let segmental = segmental_snr_db(&reference, &test, 160);
+        assert!((segmental - 18.5).abs() < 1.0, "{segmental}");
+        assert_eq!(segmental_snr_db(&[0; 320], &test, 160), 0.0);
+    }
+
+    #[test]
+    fn thd_of_tones() {
+        let pure = sine_wave(1000.0, 8000.0, 8000, 16000.0);
+        assert!(thd(&pure, 8000, 1000.0).unwrap() < 0.001);
+
+        let second = sine_wave(2000.0, 8000.0, 8000, 1600.0);
+        let distorted: Vec<i16> = pure.iter().zip(&second).map(|(a, b)| a + b).collect();
+        let measured = thd(&distorted, 8000, 1000.0).unwrap();
+        assert!((measured - 0.1).abs() < 0.005, "{measured}");
+
+        let clipped: Vec<i16> = pure.iter().map(|&s| s.clamp(-8000, 8000)).collect();
+        let measured = thd(&clipped, 8000, 1000.0).unwrap();
+        assert!(measured > 0.1, "{measured}");
+
+        assert!(thd(&pure, 8000, 4000.0).is_err());
+        assert!(thd(&[], 8000, 1000.0).is_err());
+    }
+}
+
+// =========================================================================
+// Bit sources
+// =========================================================================
+mod bit_source {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use spandsp::bit_source::BitSource;
+    use spandsp::hdlc::{HdlcRx, HdlcTx};
+
+    #[test]
+    fn hdlc_bits_end_with_the_data() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let sink = received.clone();
+        let mut rx = HdlcRx::new(false, false, 1, move |d: &[u8], ok: bool| {
+            if !d.is_empty() {
+                sink.borrow_mut().push((d.to_vec(), ok));
+            }
+        })
+        .unwrap();
+        let mut tx = HdlcTx::new(false, 2, false, None::<fn()>).unwrap();
+        tx.bits().take(128).for_each(|bit| rx.put_bit(bit));
+        tx.frame(b"Bit source").unwrap();
+        tx.frame(&[]).unwrap();
+        let sent = tx.bits().inspect(|&bit| rx.put_bit(bit)).count();
+        assert!(sent > 8 * 12 && sent < 8 * 20, "{sent}");
+        assert_eq!(*received.borrow(), vec![(b"Bit source".to_vec(), true)]);
+
+        // Idle again, on flags until the next end.
+        assert_eq!(tx.bits().take(64).count(), 64);
+    }
+
+    #[cfg(feature = "pure-hdlc")]
+    #[test]
+    fn pure_hdlc_matches_ffi() {
+        let mut ffi = HdlcTx::new(true, 2, false, None::<fn()>).unwrap();
+        let mut pure = spandsp::hdlc_pure::HdlcTx::new(true, 2, false, None::<fn()>).unwrap();
+        for data in [&[0x7E, 0x7D, 0x1F, 0xF8][..], &[]] {
+            ffi.frame(data).unwrap();
+            pure.frame(data).unwrap();
+        }
+        let bits: Vec<bool> = pure.bits().collect();
+        assert_eq!(ffi.bits().collect::<Vec<_>>(), bits);
+        assert!(pure.bits().nth(10_000).is_some());
+    }
+
+    #[cfg(feature = "fax")]
+    #[test]
+    fn t6_bits_decode() {
+        use spandsp::t4::T4Compression;
+        use spandsp::t4_rx::T4T6Decoder;
+        use spandsp::t4_tx::T4T6Encoder;
+
+        const WIDTH: i32 = 1728;
+        let mut rows_left = 8;
+        let mut encoder = T4T6Encoder::new(T4Compression::T6, WIDTH, 8, move |buf: &mut [u8]| {
+            if rows_left == 0 {
+                return 0;
+            }
+            rows_left -= 1;
+            let len = buf.len().min(WIDTH as usize / 8);
+            buf[..len].fill(if rows_left % 2 == 0 { 0xF0 } else { 0x00 });
+            len
+        })
+        .unwrap();
+        let rows = Rc::new(RefCell::new(0));
+        let counter = rows.clone();
+        let mut decoder = T4T6Decoder::new(T4Compression::T6, WIDTH, move |_: &[u8]| {
+            *counter.borrow_mut() += 1;
+            true
+        })
+        .unwrap();
+        let sent = encoder
+            .bits()
+            .inspect(|&bit| {
+                decoder.put_bit(bit as i32);
+            })
+            .count();
+        assert!(sent > 0);
+        assert!(encoder.image_complete());
+        assert!(*rows.borrow() >= 8);
+    }
+}
+
+// =========================================================================
+// Self test
+// =========================================================================
+mod self_test {
+    use spandsp::self_test::*;
+
+    #[test]
+    fn every_subsystem_passes() {
+        let report = all();
+        assert!(report.passed(), "{report}");
+        assert_eq!(report.failures().count(), 0);
+        for subsystem in [
+            Subsystem::Codecs,
+            Subsystem::Dtmf,
+            Subsystem::Hdlc,
+            Subsystem::Echo,
+        ] {
+            assert!(
+                report.checks().iter().any(|c| c.subsystem == subsystem),
+                "no {subsystem} checks"
+            );
+        }
+        let text = report.to_string();
+        assert!(text.contains("checks passed"), "{text}");
+        assert!(text.contains("PASS HDLC CRC-32 loopback"), "{text}");
+    }
+
+    #[test]
+    fn subsystems_run_alone() {
+        assert_eq!(hdlc().checks().len(), 2);
+        let dtmf = dtmf();
+        assert!(dtmf.passed(), "{dtmf}");
+        assert!(dtmf.checks()[0].detail.contains("0123456789*#ABCD"));
+        assert!(!SelfTestReport::default().passed());
+    }
 }